@@ -5,6 +5,12 @@ pub mod process;
 #[derive(Default, Clone, Copy, Debug, PartialEq)]
 pub struct IndexedTriangle([usize; 3]);
 
+impl From<[usize; 3]> for IndexedTriangle {
+    fn from(indices: [usize; 3]) -> Self {
+        Self(indices)
+    }
+}
+
 impl std::ops::Index<usize> for IndexedTriangle {
     type Output = usize;
 
@@ -35,7 +41,8 @@ impl std::ops::IndexMut<usize> for IndexedLine {
     }
 }
 
-pub struct SliceInput<M> {
+pub struct SliceInput<M, D> {
     pub objects: Vec<object::ObjectMesh>,
     pub masks: Vec<M>,
+    pub modifiers: Vec<D>,
 }
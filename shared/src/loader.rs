@@ -1,9 +1,19 @@
 use std::{
-    io::{BufReader, Cursor},
+    collections::HashMap,
+    io::{BufReader, Cursor, Read, Seek},
     path::Path,
+    str::FromStr,
 };
 
-use crate::object::ObjectMesh;
+use glam::{vec3, Mat4, Vec3};
+
+use crate::{
+    object::{ObjectMesh, ObjectVertex},
+    IndexedTriangle,
+};
+
+//Components nested deeper than this are treated as a reference cycle
+const MAX_COMPONENT_DEPTH: usize = 16;
 
 #[derive(thiserror::Error, Debug)]
 pub enum LoadError {
@@ -11,6 +21,11 @@ pub enum LoadError {
     FileNotFound,
     #[error("Broken File")]
     BrokenFile,
+    #[error("Repaired mesh, flipped {flipped_faces} faces and capped {capped_holes} holes")]
+    NonManifold {
+        flipped_faces: usize,
+        capped_holes: usize,
+    },
 }
 
 pub trait FileLoader {
@@ -47,3 +62,279 @@ impl BytesLoader for STLLoader {
             .into())
     }
 }
+
+///An object placed on the build plate of a 3MF package
+#[derive(Debug, Clone)]
+pub struct BuildItem {
+    ///Name of the object in the package, if it has one
+    pub name: Option<String>,
+
+    ///The mesh in millimeters, moved to where the item is placed on the build plate
+    pub mesh: ObjectMesh,
+}
+
+pub struct ThreeMFLoader;
+
+impl ThreeMFLoader {
+    ///Every item on the build plate of the package, with the transform of the item applied to its
+    ///mesh
+    pub fn load_items(&self, bytes: &[u8]) -> Result<Vec<BuildItem>, LoadError> {
+        let mut archive =
+            zip::ZipArchive::new(Cursor::new(bytes)).map_err(|_| LoadError::BrokenFile)?;
+
+        let model_path = read_entry(&mut archive, "_rels/.rels")
+            .ok()
+            .and_then(|rels| start_part(&rels))
+            .unwrap_or_else(|| "3D/3dmodel.model".to_string());
+
+        parse_model(&read_entry(
+            &mut archive,
+            model_path.trim_start_matches('/'),
+        )?)
+    }
+
+    ///Whether the bytes are a zip archive like a 3MF package, and not an STL
+    pub fn is_package(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"PK\x03\x04")
+    }
+}
+
+fn read_entry<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<String, LoadError> {
+    let mut content = String::new();
+
+    archive
+        .by_name(name)
+        .map_err(|_| LoadError::BrokenFile)?
+        .read_to_string(&mut content)
+        .map_err(|_| LoadError::BrokenFile)?;
+
+    Ok(content)
+}
+
+///The model part the package relationships point to
+fn start_part(rels: &str) -> Option<String> {
+    let document = roxmltree::Document::parse(rels).ok()?;
+
+    document
+        .descendants()
+        .filter(|node| node.has_tag_name("Relationship"))
+        .find(|node| {
+            node.attribute("Type")
+                .is_some_and(|kind| kind.ends_with("/3dmodel"))
+        })
+        .and_then(|node| node.attribute("Target"))
+        .map(str::to_string)
+}
+
+fn parse_model(xml: &str) -> Result<Vec<BuildItem>, LoadError> {
+    let document = roxmltree::Document::parse(xml).map_err(|_| LoadError::BrokenFile)?;
+    let model = document.root_element();
+
+    let unit = match model.attribute("unit").unwrap_or("millimeter") {
+        "micron" => 0.001,
+        "centimeter" => 10.0,
+        "inch" => 25.4,
+        "foot" => 304.8,
+        "meter" => 1000.0,
+        _ => 1.0,
+    };
+
+    let objects: HashMap<&str, roxmltree::Node> = model
+        .descendants()
+        .filter(|node| node.has_tag_name("object"))
+        .filter_map(|node| Some((node.attribute("id")?, node)))
+        .collect();
+
+    let build = child(model, "build").ok_or(LoadError::BrokenFile)?;
+
+    build
+        .children()
+        .filter(|node| node.has_tag_name("item"))
+        .map(|item| -> Result<BuildItem, LoadError> {
+            let object = objects
+                .get(attribute::<String>(item, "objectid")?.as_str())
+                .ok_or(LoadError::BrokenFile)?;
+            let transform =
+                Mat4::from_scale(Vec3::splat(unit)) * parse_transform(item.attribute("transform"))?;
+
+            let mut vertices = vec![];
+            let mut triangles = vec![];
+            collect_mesh(
+                *object,
+                &objects,
+                transform,
+                &mut vertices,
+                &mut triangles,
+                0,
+            )?;
+
+            Ok(BuildItem {
+                name: object.attribute("name").map(str::to_string),
+                mesh: ObjectMesh::new(vertices, triangles),
+            })
+        })
+        .collect()
+}
+
+///Add the mesh of an object and of all its components to the vertices and triangles
+fn collect_mesh(
+    object: roxmltree::Node,
+    objects: &HashMap<&str, roxmltree::Node>,
+    transform: Mat4,
+    vertices: &mut Vec<ObjectVertex>,
+    triangles: &mut Vec<IndexedTriangle>,
+    depth: usize,
+) -> Result<(), LoadError> {
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(LoadError::BrokenFile);
+    }
+
+    if let Some(mesh) = child(object, "mesh") {
+        let offset = vertices.len();
+
+        for vertex in child(mesh, "vertices")
+            .into_iter()
+            .flat_map(|node| node.children())
+            .filter(|node| node.has_tag_name("vertex"))
+        {
+            let point = transform.transform_point3(vec3(
+                attribute(vertex, "x")?,
+                attribute(vertex, "y")?,
+                attribute(vertex, "z")?,
+            ));
+            vertices.push(ObjectVertex::new(point.x, point.y, point.z));
+        }
+
+        for triangle in child(mesh, "triangles")
+            .into_iter()
+            .flat_map(|node| node.children())
+            .filter(|node| node.has_tag_name("triangle"))
+        {
+            let mut indices = [0; 3];
+            for (index, name) in indices.iter_mut().zip(["v1", "v2", "v3"]) {
+                *index = offset + attribute::<usize>(triangle, name)?;
+
+                if *index >= vertices.len() {
+                    return Err(LoadError::BrokenFile);
+                }
+            }
+
+            triangles.push(IndexedTriangle::from(indices));
+        }
+    }
+
+    for component in child(object, "components")
+        .into_iter()
+        .flat_map(|node| node.children())
+        .filter(|node| node.has_tag_name("component"))
+    {
+        let object = objects
+            .get(attribute::<String>(component, "objectid")?.as_str())
+            .ok_or(LoadError::BrokenFile)?;
+
+        collect_mesh(
+            *object,
+            objects,
+            transform * parse_transform(component.attribute("transform"))?,
+            vertices,
+            triangles,
+            depth + 1,
+        )?;
+    }
+
+    Ok(())
+}
+
+///The twelve numbers of a 3MF transform are the rows of a matrix applied to row vectors, so
+///they are the columns of the matrix glam applies to column vectors
+fn parse_transform(transform: Option<&str>) -> Result<Mat4, LoadError> {
+    let Some(transform) = transform else {
+        return Ok(Mat4::IDENTITY);
+    };
+
+    let values = transform
+        .split_whitespace()
+        .map(|value| value.parse::<f32>().map_err(|_| LoadError::BrokenFile))
+        .collect::<Result<Vec<f32>, LoadError>>()?;
+
+    let [m00, m01, m02, m10, m11, m12, m20, m21, m22, m30, m31, m32] = values[..] else {
+        return Err(LoadError::BrokenFile);
+    };
+
+    Ok(Mat4::from_cols_array(&[
+        m00, m01, m02, 0.0, m10, m11, m12, 0.0, m20, m21, m22, 0.0, m30, m31, m32, 1.0,
+    ]))
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn attribute<T: FromStr>(node: roxmltree::Node, name: &str) -> Result<T, LoadError> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or(LoadError::BrokenFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_items_are_placed_and_named() {
+        let model = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="centimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <object id="1" name="Wedge" type="model">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0"/>
+          <vertex x="1" y="0" z="0"/>
+          <vertex x="0" y="1" z="0"/>
+          <vertex x="0" y="0" z="1"/>
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="2" v3="1"/>
+          <triangle v1="0" v2="1" v3="3"/>
+          <triangle v1="0" v2="3" v3="2"/>
+          <triangle v1="1" v2="2" v3="3"/>
+        </triangles>
+      </mesh>
+    </object>
+    <object id="2" type="model">
+      <components>
+        <component objectid="1" transform="1 0 0 0 1 0 0 0 1 0 0 1"/>
+      </components>
+    </object>
+  </resources>
+  <build>
+    <item objectid="1" transform="0 1 0 -1 0 0 0 0 1 5 0 0"/>
+    <item objectid="2"/>
+  </build>
+</model>"#;
+
+        let items = parse_model(model).expect("Model is valid");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name.as_deref(), Some("Wedge"));
+        assert_eq!(items[1].name, None);
+
+        //Turned a quarter around z and moved 5 cm along x
+        let (min, max) = items[0].mesh.min_max();
+        assert!(min.abs_diff_eq(vec3(40.0, 0.0, 0.0), 0.001));
+        assert!(max.abs_diff_eq(vec3(50.0, 10.0, 10.0), 0.001));
+
+        //The component is lifted by 1 cm
+        let (min, max) = items[1].mesh.min_max();
+        assert!(min.abs_diff_eq(vec3(0.0, 0.0, 10.0), 0.001));
+        assert!(max.abs_diff_eq(vec3(10.0, 10.0, 20.0), 0.001));
+        assert_eq!(items[1].mesh.triangles().len(), 4);
+
+        assert!(parse_model(&model.replace("v3=\"3\"", "v3=\"4\"")).is_err());
+    }
+}
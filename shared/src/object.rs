@@ -1,11 +1,13 @@
 use std::{
     cmp::Ordering,
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
     ops::{Deref, DerefMut},
 };
 
 use glam::{vec3, Mat4, Vec3};
 
-use crate::IndexedTriangle;
+use crate::{loader::LoadError, IndexedTriangle};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ObjectVertex(Vec3);
@@ -54,9 +56,41 @@ impl std::ops::Mul<ObjectVertex> for Mat4 {
 pub struct ObjectMesh {
     vertices: Vec<ObjectVertex>,
     triangles: Vec<IndexedTriangle>,
+    extruder_index: usize,
+}
+
+//Vertices are hashed by their bits, so only identical meshes hash the same
+impl Hash for ObjectMesh {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.vertices.len().hash(state);
+        for vertex in &self.vertices {
+            vertex.0.to_array().map(f32::to_bits).hash(state);
+        }
+        for triangle in &self.triangles {
+            [triangle[0], triangle[1], triangle[2]].hash(state);
+        }
+        self.extruder_index.hash(state);
+    }
 }
 
 impl ObjectMesh {
+    pub fn new(vertices: Vec<ObjectVertex>, triangles: Vec<IndexedTriangle>) -> Self {
+        Self {
+            vertices,
+            triangles,
+            extruder_index: 0,
+        }
+    }
+
+    ///The extruder that prints this object
+    pub fn extruder_index(&self) -> usize {
+        self.extruder_index
+    }
+
+    pub fn set_extruder_index(&mut self, extruder_index: usize) {
+        self.extruder_index = extruder_index;
+    }
+
     pub fn vertices(&self) -> &[ObjectVertex] {
         &self.vertices
     }
@@ -98,6 +132,240 @@ impl ObjectMesh {
     }
 }
 
+impl ObjectMesh {
+    ///Repair the mesh so it slices cleanly. Faces wound against their neighbours are flipped,
+    ///holes bounded by a single loop of edges are capped and closed shells that face the wrong
+    ///way are turned round. Returns the warning to show if anything had to be repaired.
+    pub fn repair(&mut self) -> Option<LoadError> {
+        let original = self.triangles.clone();
+
+        let edges = self.edge_faces();
+        self.orient_faces(&edges);
+        let capped_holes = self.cap_holes();
+
+        let edges = self.edge_faces();
+        let shells: Vec<Vec<usize>> = self
+            .orient_faces(&edges)
+            .into_iter()
+            .filter(|component| {
+                component.iter().all(|face| {
+                    directed_edges(self.triangles[*face])
+                        .iter()
+                        .all(|(a, b)| edges[&edge_key(*a, *b)].len() == 2)
+                })
+            })
+            .collect();
+
+        //Shells inside an odd number of other shells bound a cavity and face inwards, all
+        //others face outwards. The volume comes out negative when the faces point inwards.
+        let inside_out: Vec<bool> = shells
+            .iter()
+            .enumerate()
+            .map(|(index, shell)| {
+                let point = *self.vertices[self.triangles[shell[0]][0]];
+                let depth = shells
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, enclosing)| {
+                        *other != index && self.winding_number(enclosing, point).abs() > 0.5
+                    })
+                    .count();
+
+                (self.volume(shell) < 0.0) != (depth % 2 == 1)
+            })
+            .collect();
+
+        for (shell, inside_out) in shells.iter().zip(inside_out) {
+            if inside_out {
+                shell
+                    .iter()
+                    .for_each(|face| flip(&mut self.triangles[*face]));
+            }
+        }
+
+        let flipped_faces = original
+            .iter()
+            .zip(&self.triangles)
+            .filter(|(before, after)| before != after)
+            .count();
+
+        (flipped_faces > 0 || capped_holes > 0).then_some(LoadError::NonManifold {
+            flipped_faces,
+            capped_holes,
+        })
+    }
+
+    ///Six times the signed volume enclosed by the faces
+    fn volume(&self, faces: &[usize]) -> f32 {
+        faces
+            .iter()
+            .map(|face| {
+                let triangle = self.triangles[*face];
+                self.vertices[triangle[0]]
+                    .dot(self.vertices[triangle[1]].cross(*self.vertices[triangle[2]]))
+            })
+            .sum()
+    }
+
+    ///How often the faces wind around the point, from the solid angle they cover as seen from it
+    fn winding_number(&self, faces: &[usize], point: Vec3) -> f32 {
+        let solid_angle: f32 = faces
+            .iter()
+            .map(|face| {
+                let triangle = self.triangles[*face];
+                let [a, b, c] = [0, 1, 2].map(|index| *self.vertices[triangle[index]] - point);
+                let (la, lb, lc) = (a.length(), b.length(), c.length());
+
+                let numerator = a.dot(b.cross(c));
+                let denominator = la * lb * lc + a.dot(b) * lc + a.dot(c) * lb + b.dot(c) * la;
+                2.0 * numerator.atan2(denominator)
+            })
+            .sum();
+
+        solid_angle / (4.0 * std::f32::consts::PI)
+    }
+
+    ///The faces along each edge
+    fn edge_faces(&self) -> HashMap<(usize, usize), Vec<usize>> {
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+        for (face, triangle) in self.triangles.iter().enumerate() {
+            for (a, b) in directed_edges(*triangle) {
+                edges.entry(edge_key(a, b)).or_default().push(face);
+            }
+        }
+
+        edges
+    }
+
+    ///Wind every face like the faces it shares edges with, spreading out from the first face of
+    ///each connected part. Returns the faces of each part.
+    fn orient_faces(&mut self, edges: &HashMap<(usize, usize), Vec<usize>>) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.triangles.len()];
+        let mut components = vec![];
+
+        for start in 0..self.triangles.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+
+            let mut component = vec![start];
+            let mut queue = vec![start];
+
+            while let Some(face) = queue.pop() {
+                for (a, b) in directed_edges(self.triangles[face]) {
+                    let neighbours = &edges[&edge_key(a, b)];
+
+                    //An edge with more than two faces doesn't tell which side is which
+                    if neighbours.len() != 2 {
+                        continue;
+                    }
+
+                    for neighbour in neighbours.iter().copied() {
+                        if visited[neighbour] {
+                            continue;
+                        }
+                        visited[neighbour] = true;
+
+                        //Faces wound the same way run along their shared edge in opposite directions
+                        if directed_edges(self.triangles[neighbour]).contains(&(a, b)) {
+                            flip(&mut self.triangles[neighbour]);
+                        }
+
+                        component.push(neighbour);
+                        queue.push(neighbour);
+                    }
+                }
+            }
+
+            components.push(component);
+        }
+
+        components
+    }
+
+    ///Cap the holes bounded by a single loop of edges with a fan of faces. Returns the number of
+    ///capped holes.
+    fn cap_holes(&mut self) -> usize {
+        let edges = self.edge_faces();
+
+        //The cap runs along the boundary the other way round, so it is wound like its neighbours
+        let mut next: HashMap<usize, Vec<usize>> = HashMap::new();
+        for triangle in self.triangles.iter() {
+            for (a, b) in directed_edges(*triangle) {
+                if edges[&edge_key(a, b)].len() == 1 {
+                    next.entry(b).or_default().push(a);
+                }
+            }
+        }
+
+        let mut starts: Vec<usize> = next.keys().copied().collect();
+        starts.sort_unstable();
+
+        let mut used = HashSet::new();
+        let mut capped_holes = 0;
+
+        for start in starts {
+            if !used.insert(start) {
+                continue;
+            }
+
+            //Boundaries meeting at a vertex don't form a simple hole, they are left open
+            let hole = (|| {
+                let mut hole = vec![start];
+                loop {
+                    let [following] = next.get(hole.last()?)?.as_slice() else {
+                        return None;
+                    };
+
+                    if *following == start {
+                        return Some(hole);
+                    }
+                    if hole.contains(following) {
+                        return None;
+                    }
+                    hole.push(*following);
+                }
+            })();
+
+            let Some(hole) = hole else {
+                continue;
+            };
+            used.extend(hole.iter().copied());
+
+            if hole.len() < 3 {
+                continue;
+            }
+
+            self.triangles.extend(
+                hole[1..]
+                    .windows(2)
+                    .map(|pair| IndexedTriangle([hole[0], pair[0], pair[1]])),
+            );
+            capped_holes += 1;
+        }
+
+        capped_holes
+    }
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn directed_edges(triangle: IndexedTriangle) -> [(usize, usize); 3] {
+    [
+        (triangle[0], triangle[1]),
+        (triangle[1], triangle[2]),
+        (triangle[2], triangle[0]),
+    ]
+}
+
+fn flip(triangle: &mut IndexedTriangle) {
+    triangle.0.swap(1, 2);
+}
+
 impl std::ops::Mul<ObjectMesh> for Mat4 {
     type Output = ObjectMesh;
 
@@ -111,6 +379,7 @@ impl std::ops::Mul<ObjectMesh> for Mat4 {
         ObjectMesh {
             vertices,
             triangles: mesh.triangles,
+            extruder_index: mesh.extruder_index,
         }
     }
 }
@@ -137,9 +406,136 @@ impl From<nom_stl::Mesh> for ObjectMesh {
             })
             .collect();
 
-        Self {
-            vertices,
-            triangles,
-        }
+        Self::new(vertices, triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> ObjectMesh {
+        let vertices = (0..8)
+            .map(|index| {
+                ObjectVertex::new(
+                    (index & 1) as f32,
+                    ((index >> 1) & 1) as f32,
+                    ((index >> 2) & 1) as f32,
+                )
+            })
+            .collect();
+
+        let triangles = [
+            [0, 2, 3],
+            [0, 3, 1],
+            [4, 5, 7],
+            [4, 7, 6],
+            [0, 1, 5],
+            [0, 5, 4],
+            [2, 6, 7],
+            [2, 7, 3],
+            [0, 4, 6],
+            [0, 6, 2],
+            [1, 3, 7],
+            [1, 7, 5],
+        ]
+        .into_iter()
+        .map(IndexedTriangle)
+        .collect();
+
+        ObjectMesh::new(vertices, triangles)
+    }
+
+    fn volume(mesh: &ObjectMesh) -> f32 {
+        mesh.triangles()
+            .iter()
+            .map(|triangle| {
+                mesh.vertices()[triangle[0]]
+                    .dot(mesh.vertices()[triangle[1]].cross(*mesh.vertices()[triangle[2]]))
+            })
+            .sum::<f32>()
+            / 6.0
+    }
+
+    #[test]
+    fn repair_flips_faces_and_caps_holes() {
+        let mut clean = cube();
+        assert!(clean.repair().is_none());
+        assert_eq!(clean.triangles(), cube().triangles());
+
+        //Top face missing and a bottom face wound the wrong way
+        let mut broken = cube();
+        broken.triangles.drain(2..4);
+        flip(&mut broken.triangles[0]);
+
+        assert!(matches!(
+            broken.repair(),
+            Some(LoadError::NonManifold {
+                flipped_faces: 1,
+                capped_holes: 1
+            })
+        ));
+        assert_eq!(broken.triangles().len(), 12);
+        assert!(broken.edge_faces().values().all(|faces| faces.len() == 2));
+        assert!((volume(&broken) - 1.0).abs() < 0.0001);
+
+        let mut inside_out = cube();
+        inside_out.triangles.iter_mut().for_each(flip);
+
+        assert!(matches!(
+            inside_out.repair(),
+            Some(LoadError::NonManifold {
+                flipped_faces: 12,
+                capped_holes: 0
+            })
+        ));
+        assert!((volume(&inside_out) - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn repair_keeps_cavities_facing_inwards() {
+        //A 3mm cube with a 1mm cavity in the middle, the cavity faces point into it
+        let outer = Mat4::from_scale(Vec3::splat(3.0)) * cube();
+        let mut inner = Mat4::from_translation(Vec3::ONE) * cube();
+        inner.triangles.iter_mut().for_each(flip);
+
+        let offset = outer.vertices.len();
+        let hollow = ObjectMesh::new(
+            outer
+                .vertices
+                .iter()
+                .chain(&inner.vertices)
+                .copied()
+                .collect(),
+            outer
+                .triangles
+                .iter()
+                .copied()
+                .chain(
+                    inner
+                        .triangles
+                        .iter()
+                        .map(|triangle| IndexedTriangle(triangle.0.map(|index| index + offset))),
+                )
+                .collect(),
+        );
+
+        let mut clean = hollow.clone();
+        assert!(clean.repair().is_none());
+        assert_eq!(clean.triangles(), hollow.triangles());
+        assert!((volume(&clean) - 26.0).abs() < 0.0001);
+
+        //A cavity facing outwards is turned round, the outer shell is left alone
+        let mut wrong_cavity = hollow.clone();
+        wrong_cavity.triangles[12..].iter_mut().for_each(flip);
+
+        assert!(matches!(
+            wrong_cavity.repair(),
+            Some(LoadError::NonManifold {
+                flipped_faces: 12,
+                capped_holes: 0
+            })
+        ));
+        assert_eq!(wrong_cavity.triangles(), hollow.triangles());
     }
 }
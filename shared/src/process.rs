@@ -7,8 +7,17 @@ use parking_lot::RwLock;
 pub struct Process {
     task: RwLock<String>,
     progress: AtomicF32,
+    //Seconds left, NaN while unknown
+    eta: AtomicF32,
     finished: AtomicBool,
     closed: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl Default for Process {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Process {
@@ -16,14 +25,27 @@ impl Process {
         Self {
             task: RwLock::new(String::new()),
             progress: AtomicF32::new(0.0),
+            eta: AtomicF32::new(f32::NAN),
             finished: AtomicBool::new(false),
             closed: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
         }
     }
 
+    ///Set the progress, the time left is unknown until the next `set_progress_with_eta`
     pub fn set_progress(&self, progress: f32) {
         self.progress
             .store(progress, std::sync::atomic::Ordering::Relaxed);
+        self.eta
+            .store(f32::NAN, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    ///Set the progress along with an estimate of the seconds left
+    pub fn set_progress_with_eta(&self, progress: f32, eta_secs: f32) {
+        self.progress
+            .store(progress, std::sync::atomic::Ordering::Relaxed);
+        self.eta
+            .store(eta_secs, std::sync::atomic::Ordering::Relaxed);
     }
 
     pub fn set_task(&self, task: String) {
@@ -34,6 +56,12 @@ impl Process {
         self.progress.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    ///Estimated seconds left, if known
+    pub fn eta(&self) -> Option<f32> {
+        let eta = self.eta.load(std::sync::atomic::Ordering::Relaxed);
+        eta.is_finite().then_some(eta)
+    }
+
     pub fn finish(&self) {
         self.finished
             .store(true, std::sync::atomic::Ordering::Relaxed);
@@ -44,6 +72,12 @@ impl Process {
             .store(true, std::sync::atomic::Ordering::Relaxed);
     }
 
+    ///Ask the work tracked by this process to stop early
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
     pub fn task(&self) -> String {
         self.task.read().clone()
     }
@@ -55,4 +89,8 @@ impl Process {
     pub fn is_closed(&self) -> bool {
         self.closed.load(std::sync::atomic::Ordering::Relaxed)
     }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::Relaxed)
+    }
 }
@@ -0,0 +1,73 @@
+//! Cuts a tall model into layers on a single thread and on all threads, to compare how the
+//! cutting scales with the number of cores.
+//!
+//! Run with `cargo bench -p slicer --bench slice_layers`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use shared::object::{ObjectMesh, ObjectVertex};
+use slicer::{slice_layers, Settings};
+
+///A 200 mm tall prism with many sides that twists as it rises, so most layers cut new triangles
+fn tall_model() -> ObjectMesh {
+    let (sides, rings, height) = (96, 800, 200.0);
+
+    let mut vertices: Vec<ObjectVertex> = (0..=rings)
+        .flat_map(|ring| {
+            (0..sides).map(move |side| {
+                let angle = std::f32::consts::TAU * side as f32 / sides as f32
+                    + ring as f32 * 0.5f32.to_radians();
+                let radius = 20.0 + 5.0 * (ring as f32 / 40.0).sin();
+                ObjectVertex::new(
+                    100.0 + radius * angle.cos(),
+                    100.0 + radius * angle.sin(),
+                    height * ring as f32 / rings as f32,
+                )
+            })
+        })
+        .collect();
+    let bottom = vertices.len();
+    vertices.push(ObjectVertex::new(100.0, 100.0, 0.0));
+    vertices.push(ObjectVertex::new(100.0, 100.0, height));
+    let index = |ring: usize, side: usize| ring * sides + side % sides;
+
+    let mut triangles: Vec<[usize; 3]> = vec![];
+    for side in 0..sides {
+        for ring in 0..rings {
+            let (a, b) = (index(ring, side), index(ring, side + 1));
+            let (c, d) = (index(ring + 1, side + 1), index(ring + 1, side));
+            triangles.extend([[a, b, c], [a, c, d]]);
+        }
+
+        triangles.push([bottom, index(0, side + 1), index(0, side)]);
+        triangles.push([bottom + 1, index(rings, side), index(rings, side + 1)]);
+    }
+
+    let mut mesh = ObjectMesh::new(
+        vertices,
+        triangles.into_iter().map(|triangle| triangle.into()).collect(),
+    );
+    mesh.sort_indices();
+    mesh
+}
+
+fn slice_tall_model(c: &mut Criterion) {
+    let objects = vec![tall_model()];
+    let settings = Settings::default();
+    let single_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("slice tall model");
+    group.sample_size(10);
+    group.bench_function("one thread", |b| {
+        b.iter(|| single_thread.install(|| slice_layers(&objects, &settings).unwrap()))
+    });
+    group.bench_function("all threads", |b| {
+        b.iter(|| slice_layers(&objects, &settings).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, slice_tall_model);
+criterion_main!(benches);
@@ -1,17 +1,47 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
 use crate::*;
+use log::warn;
+
+///Only the worst layers are reported so a print full of small islands doesn't flood the warnings
+const MAX_RETRACTION_WARNINGS: usize = 5;
 
 pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedValues {
     let mut values = CalculatedValues {
         plastic_volume: 0.0,
         plastic_weight: 0.0,
+        extruder_weights: vec![],
         total_time: 0.0,
         plastic_length: 0.0,
         fiber_length: 0.0,
+        center_of_mass: Vec3::ZERO,
+        trace_breakdown: HashMap::new(),
+        travel_time: 0.0,
     };
 
     let mut current_speed = 0.0;
     let mut current_pos = Coord { x: 0.0, y: 0.0 };
 
+    //Volume extruded by each extruder
+    let mut current_extruder = 0;
+    let mut extruder_volumes = vec![0.0];
+    let mut start_volume = 0.0;
+
+    let mut nozzle_temp = settings.filament.extruder_temp;
+
+    //Sum of the mass of every segment times its midpoint
+    let mut current_z = 0.0;
+    let mut total_mass = 0.0;
+    let mut mass_moment = Vec3::ZERO;
+    let fiber_area = std::f32::consts::PI * (settings.fiber.diameter / 2.0).powi(2);
+
+    //Plastic laid down around the fiber isn't counted as plastic length
+    let filament_area =
+        std::f32::consts::PI * (settings.nozzle_diameter / 2.0) * (settings.nozzle_diameter / 2.0);
+    let mut fiber_volume = 0.0;
+    let mut current_type = TraceType::Infill;
+
     for cmd in moves {
         match cmd {
             Command::MoveTo { end } => {
@@ -21,6 +51,7 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
                 current_pos = *end;
                 if current_speed != 0.0 {
                     values.total_time += d / current_speed;
+                    values.travel_time += d / current_speed;
                 }
             }
             Command::MoveAndExtrude {
@@ -37,6 +68,16 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
                 values.total_time += d / current_speed;
 
                 values.plastic_volume += width * thickness * d;
+                add_trace(
+                    &mut values,
+                    current_type,
+                    d / current_speed,
+                    width * thickness * d / filament_area,
+                );
+
+                let mass = plastic_mass(width * thickness * d, current_extruder, settings);
+                total_mass += mass;
+                mass_moment += midpoint(*start, *end, current_z - thickness / 2.0) * mass;
             }
             Command::MoveAndExtrudeFiberAndCut {
                 start,
@@ -60,18 +101,38 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
 
                 values.plastic_volume += width * thickness * d;
                 values.fiber_length += d;
+                fiber_volume += width * thickness * d;
+                add_trace(&mut values, current_type, d / current_speed, 0.0);
+
+                //The fiber takes the place of plastic in the bead and weighs in with its own density
+                let plastic = (width * thickness - fiber_area).max(0.0) * d;
+                let mass = plastic_mass(plastic, current_extruder, settings)
+                    + fiber_area * d * settings.fiber.density / 1000.0;
+                total_mass += mass;
+                mass_moment += midpoint(*start, *end, current_z - thickness / 2.0) * mass;
             }
             Command::SetState { new_state } => {
                 if let Some(speed) = new_state.movement_speed {
                     current_speed = speed
                 }
+                if let Some(temp) = new_state.extruder_temp {
+                    nozzle_temp = temp;
+                }
                 if new_state.retract != RetractionType::NoRetract {
-                    values.total_time += settings.retract_length / settings.retract_speed;
-                    values.total_time += settings.retract_lift_z / settings.speed.travel;
+                    let retract_time = settings.retract_length / settings.retract_speed
+                        + settings.retract_lift_z / settings.speed.travel;
+                    values.total_time += retract_time;
+                    values.travel_time += retract_time;
                 }
             }
             Command::Delay { msec } => {
                 values.total_time += *msec as f32 / 1000.0;
+
+                //The nozzle cools during the pause but printing waits for it to reheat
+                if let Some(standby) = settings.standby_temperature(*msec, nozzle_temp) {
+                    values.total_time +=
+                        (nozzle_temp - standby) / settings.standby_temp.reheat_rate;
+                }
             }
             Command::Arc {
                 start,
@@ -100,19 +161,127 @@ pub fn calculate_values(moves: &[Command], settings: &Settings) -> CalculatedVal
                 values.total_time += extrusion_length / current_speed;
 
                 values.plastic_volume += width * thickness * extrusion_length;
+                add_trace(
+                    &mut values,
+                    current_type,
+                    extrusion_length / current_speed,
+                    width * thickness * extrusion_length / filament_area,
+                );
+
+                //Halfway along the arc, which is less than half a circle
+                let chord_middle = midpoint(*start, *end, current_z - thickness / 2.0);
+                let outward = (chord_middle.truncate() - glam::Vec2::new(center.x, center.y))
+                    .try_normalize()
+                    .map_or(chord_middle, |dir| {
+                        (glam::Vec2::new(center.x, center.y) + dir * radius).extend(chord_middle.z)
+                    });
+
+                let mass = plastic_mass(
+                    width * thickness * extrusion_length,
+                    current_extruder,
+                    settings,
+                );
+                total_mass += mass;
+                mass_moment += outward * mass;
+            }
+            Command::ChangeObject { extruder, .. } => {
+                extruder_volumes[current_extruder] += values.plastic_volume - start_volume;
+                start_volume = values.plastic_volume;
+
+                //The temperature of the new extruder on the layer is set right after the change
+                current_extruder = *extruder;
+                if extruder_volumes.len() <= current_extruder {
+                    extruder_volumes.resize(current_extruder + 1, 0.0);
+                }
             }
-            Command::NoAction
-            | Command::LayerChange { .. }
-            | Command::ChangeObject { .. }
-            | Command::ChangeType { .. } => {}
+            Command::LayerChange { z, .. } => current_z = *z,
+            Command::ChangeType { print_type } => current_type = *print_type,
+            Command::NoAction => {}
         }
     }
 
-    values.plastic_weight = (values.plastic_volume / 1000.0) * settings.filament.density;
-    values.plastic_length = values.plastic_volume
-        / (std::f32::consts::PI
-            * (settings.nozzle_diameter / 2.0)
-            * (settings.nozzle_diameter / 2.0));
+    extruder_volumes[current_extruder] += values.plastic_volume - start_volume;
+
+    values.extruder_weights = extruder_volumes
+        .iter()
+        .enumerate()
+        .map(|(extruder, volume)| (volume / 1000.0) * settings.extruder_filament(extruder).density)
+        .collect();
+    values.plastic_weight = values.extruder_weights.iter().sum();
+    values.center_of_mass = if total_mass > 0.0 {
+        mass_moment / total_mass
+    } else {
+        Vec3::new(settings.print_x / 2.0, settings.print_y / 2.0, 0.0)
+    };
+    values.plastic_length = (values.plastic_volume - fiber_volume) / filament_area;
 
     values
 }
+
+///Add the time and plastic length of an extrusion to the breakdown of its trace type
+fn add_trace(values: &mut CalculatedValues, trace_type: TraceType, time: f32, length: f32) {
+    let (total_time, total_length) = values
+        .trace_breakdown
+        .entry(trace_type)
+        .or_insert((0.0, 0.0));
+    *total_time += time;
+    *total_length += length;
+}
+
+///Mass in grams of plastic from the extruder
+fn plastic_mass(volume: f32, extruder: usize, settings: &Settings) -> f32 {
+    volume / 1000.0 * settings.extruder_filament(extruder).density
+}
+
+fn midpoint(start: Coord<f32>, end: Coord<f32>, z: f32) -> Vec3 {
+    Vec3::new((start.x + end.x) / 2.0, (start.y + end.y) / 2.0, z)
+}
+
+///The number of retractions on every layer by layer index. Objects share the index of the layers
+///they are printed on together.
+pub fn retractions_per_layer(moves: &[Command]) -> BTreeMap<usize, usize> {
+    let mut layers = BTreeMap::new();
+    let mut current_layer = 0;
+
+    for cmd in moves {
+        match cmd {
+            Command::LayerChange { index, .. } => {
+                current_layer = *index;
+                layers.entry(current_layer).or_insert(0);
+            }
+            Command::SetState { new_state } => {
+                if matches!(
+                    new_state.retract,
+                    RetractionType::Retract | RetractionType::MoveRetract(_)
+                ) {
+                    *layers.entry(current_layer).or_insert(0) += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    layers
+}
+
+///Warn about the layers with more retractions than the configured maximum, worst layers first
+pub fn excessive_retraction_warnings(
+    moves: &[Command],
+    settings: &Settings,
+) -> Vec<SlicerWarnings> {
+    if !settings.max_retractions_per_layer.is_enabled() {
+        return vec![];
+    }
+
+    retractions_per_layer(moves)
+        .into_iter()
+        .filter(|(_, count)| *count > *settings.max_retractions_per_layer)
+        .sorted_by_key(|(layer, count)| (Reverse(*count), *layer))
+        .take(MAX_RETRACTION_WARNINGS)
+        .map(|(layer, count)| {
+            let warning = SlicerWarnings::ExcessiveRetraction { layer, count };
+            warn!("{}", warning.get_code_and_message().1);
+            warning
+        })
+        .collect()
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use geo::EuclideanDistance;
 use ordered_float::OrderedFloat;
 
 use crate::optimizer::*;
@@ -13,12 +14,19 @@ pub struct OptimizePass {}
 
 impl CommandPass for OptimizePass {
     fn pass(cmds: &mut Vec<Command>, settings: &Settings) {
+        if settings.minimize_retractions {
+            island_optimizer(cmds, settings);
+        }
+
         let mut size = cmds.len();
 
         while {
             //arc_optomizer(cmds);
             state_optomizer(cmds);
             unary_optimizer(cmds);
+            if settings.remove_zero_length_moves {
+                zero_length_optimizer(cmds);
+            }
             binary_optimizer(cmds, settings);
 
             cmds.len() != size
@@ -28,6 +36,99 @@ impl CommandPass for OptimizePass {
     }
 }
 
+pub struct ArcFitPass {}
+
+impl CommandPass for ArcFitPass {
+    fn pass(cmds: &mut Vec<Command>, settings: &Settings) {
+        if settings.arc_tolerance.is_enabled() {
+            arc_fit_optimizer(cmds, *settings.arc_tolerance);
+            unary_optimizer(cmds);
+        }
+    }
+}
+
+pub struct StandbyTravelPass {}
+
+impl CommandPass for StandbyTravelPass {
+    fn pass(cmds: &mut Vec<Command>, settings: &Settings) {
+        if !settings.standby_travel_threshold.is_enabled() || !settings.standby_temp.is_enabled() {
+            return;
+        }
+
+        let temperature_change = |temp: f32| Command::SetState {
+            new_state: StateChange {
+                extruder_temp: Some(temp),
+                ..Default::default()
+            },
+        };
+
+        let mut position = Coord { x: 0.0, y: 0.0 };
+        let mut speed = settings.speed.travel;
+        let mut nozzle_temp = settings.filament.extruder_temp;
+
+        //The travel to the start of the print is left alone, the nozzle only just heated up
+        let mut printing = false;
+
+        let mut new_cmds = Vec::with_capacity(cmds.len());
+        for cmd in cmds.drain(..) {
+            match &cmd {
+                Command::MoveTo { end } => {
+                    let reheat_point =
+                        standby_reheat_point(position, *end, speed, nozzle_temp, settings);
+                    if let (true, Some(reheat_point)) = (printing, reheat_point) {
+                        new_cmds.push(temperature_change(settings.standby_temp.temperature));
+                        new_cmds.push(Command::MoveTo { end: reheat_point });
+                        new_cmds.push(temperature_change(nozzle_temp));
+                    }
+                    position = *end;
+                }
+                Command::MoveAndExtrude { end, .. }
+                | Command::MoveAndExtrudeFiber { end, .. }
+                | Command::MoveAndExtrudeFiberAndCut { end, .. }
+                | Command::Arc { end, .. } => {
+                    position = *end;
+                    printing = true;
+                }
+                //Object changes are followed by the temperature of their extruder on the layer
+                Command::SetState { new_state } => {
+                    speed = new_state.movement_speed.unwrap_or(speed);
+                    nozzle_temp = new_state.extruder_temp.unwrap_or(nozzle_temp);
+                }
+                _ => {}
+            }
+
+            new_cmds.push(cmd);
+        }
+
+        *cmds = new_cmds;
+    }
+}
+
+///The point of a travel from which the nozzle reheats from the standby temperature, so it is back
+///at `nozzle_temp` when the travel ends. None if the travel is too short to cool down on.
+fn standby_reheat_point(
+    start: Coord<f32>,
+    end: Coord<f32>,
+    speed: f32,
+    nozzle_temp: f32,
+    settings: &Settings,
+) -> Option<Coord<f32>> {
+    let standby_temp = &*settings.standby_temp;
+    let length = start.euclidean_distance(&end);
+
+    if length < *settings.standby_travel_threshold
+        || standby_temp.temperature >= nozzle_temp
+        || speed <= 0.0
+    {
+        return None;
+    }
+
+    let reheat_distance =
+        (nozzle_temp - standby_temp.temperature) / standby_temp.reheat_rate * speed;
+
+    (reheat_distance < length).then(|| end + (start - end) * (reheat_distance / length))
+}
+
 pub struct SlowDownLayerPass {}
 
 impl CommandPass for SlowDownLayerPass {
@@ -219,3 +320,95 @@ impl CommandPass for SlowDownLayerPass {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extrude(start: (f32, f32), end: (f32, f32)) -> Command {
+        Command::MoveAndExtrude {
+            start: start.into(),
+            end: end.into(),
+            thickness: 0.2,
+            width: 0.4,
+            id: None,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        }
+    }
+
+    fn temperatures(cmds: &[Command]) -> Vec<f32> {
+        cmds.iter()
+            .filter_map(|cmd| match cmd {
+                Command::SetState { new_state } => new_state.extruder_temp,
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_travels_reheat_before_the_end() {
+        let mut settings = Settings::default();
+        settings.filament.extruder_temp = 210.0;
+        *settings.standby_temp.enabled_mut() = true;
+        settings.standby_temp.temperature = 150.0;
+        settings.standby_temp.reheat_rate = 100.0;
+        *settings.standby_travel_threshold.enabled_mut() = true;
+        *settings.standby_travel_threshold = 100.0;
+
+        let travel_speed = Command::SetState {
+            new_state: StateChange {
+                movement_speed: Some(50.0),
+                ..Default::default()
+            },
+        };
+        let mut cmds = vec![
+            travel_speed.clone(),
+            Command::MoveTo {
+                end: Coord { x: 0.0, y: 150.0 },
+            },
+            extrude((0.0, 150.0), (10.0, 150.0)),
+            Command::MoveTo {
+                end: Coord { x: 20.0, y: 150.0 },
+            },
+            extrude((20.0, 150.0), (30.0, 150.0)),
+            travel_speed,
+            Command::MoveTo {
+                end: Coord { x: 230.0, y: 150.0 },
+            },
+            extrude((230.0, 150.0), (240.0, 150.0)),
+        ];
+
+        StandbyTravelPass::pass(&mut cmds, &settings);
+
+        //Only the long travel after printing started cools down
+        assert_eq!(temperatures(&cmds), vec![150.0, 210.0]);
+
+        //Reheating 60°C at 100°C/s takes 0.6s, 30mm at 50mm/s
+        let restore = cmds
+            .iter()
+            .position(|cmd| matches!(cmd, Command::SetState { new_state } if new_state.extruder_temp == Some(210.0)))
+            .unwrap();
+        assert!(matches!(
+            cmds[restore - 1],
+            Command::MoveTo { end } if end.euclidean_distance(&Coord { x: 200.0, y: 150.0 }) < 0.001
+        ));
+        assert!(matches!(
+            cmds[restore + 1],
+            Command::MoveTo { end } if end == Coord { x: 230.0, y: 150.0 }
+        ));
+        assert!(matches!(cmds[restore + 2], Command::MoveAndExtrude { .. }));
+
+        //Without a standby temperature to go to nothing changes
+        let mut cmds = vec![
+            extrude((0.0, 0.0), (10.0, 0.0)),
+            Command::MoveTo {
+                end: Coord { x: 300.0, y: 0.0 },
+            },
+        ];
+        *settings.standby_temp.enabled_mut() = false;
+        StandbyTravelPass::pass(&mut cmds, &settings);
+        assert_eq!(cmds.len(), 2);
+    }
+}
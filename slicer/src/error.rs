@@ -96,12 +96,96 @@ pub enum SlicerErrors {
         value: f32,
     },
 
+    ///Error because a setting is not a percentage between 0 and 1
+    SettingNotAPercentage {
+        ///The setting name
+        setting: String,
+
+        ///The current value
+        value: f32,
+    },
+
+    ///Two objects overlap each other
+    ObjectsOverlap {
+        ///Index of the first object
+        first: usize,
+
+        ///Index of the second object
+        second: usize,
+    },
+
     ///The file format is not supported
     FileFormatNotSupported {
         /// File with invalid Format
         filepath: String,
     },
 
+    ///There are no objects to slice, masks alone can't be printed
+    NoObjects,
+
+    ///The commands passed to the gcode writer break its invariants
+    InvalidCommands {
+        ///Every broken invariant found
+        violations: Vec<CommandViolation>,
+    },
+
+    ///A placeholder in the custom gcode doesn't name a setting
+    UnknownPlaceholder {
+        ///The placeholder without the brackets
+        placeholder: String,
+    },
+
+    ///The layer height would slice more layers than the configured cap
+    TooManyLayers {
+        ///Number of layers the model would be sliced into
+        computed: usize,
+
+        ///The max_layers setting
+        cap: usize,
+    },
+
+    ///A spiralized layer doesn't have exactly one contour to follow
+    SpiralizeContourCount {
+        ///Index of the layer
+        layer: usize,
+
+        ///Number of outlines and holes of the layer
+        contours: usize,
+    },
+
+    ///Objects printed one after another are too close for the print head to pass the finished
+    ///ones
+    SequentialClearance {
+        ///Indices of the objects that are too close to another object
+        objects: Vec<usize>,
+
+        ///The sequential_clearance setting
+        clearance: f32,
+    },
+
+    ///The slice was cancelled before it finished
+    Cancelled,
+
+    ///Only a z range of the model was sliced, which can be previewed but not exported
+    PartialSlice {
+        ///Bottom of the sliced range in mm
+        min: f32,
+
+        ///Top of the sliced range in mm
+        max: f32,
+    },
+
+    ///A layer of the fiber orientation field has a different number of rows or columns than
+    ///the first layer
+    RaggedOrientationField {
+        ///Index of the layer
+        layer: usize,
+    },
+
+    ///Resuming from a layer skips the lower layers of every object, which only matches what was
+    ///printed when the objects are printed layer by layer
+    ResumeByObject,
+
     ///Another error, here for plugins to use
     UnspecifiedError(String),
 }
@@ -176,6 +260,126 @@ impl SlicerErrors {
             SlicerErrors::MovesOutsideBuildArea => {
                 (0x1014,"Slicer generated move outside build area.".to_string())
             }
+            SlicerErrors::SettingNotAPercentage { setting, value } => {
+                (0x1015,format!("The setting {} must be between 0 and 1. It is currently {}.",setting, value))
+            }
+            SlicerErrors::ObjectsOverlap { first, second } => {
+                (0x1016,format!("Object {} overlaps object {}. Move the objects apart or merge them.",first, second))
+            }
+            SlicerErrors::NoObjects => {
+                (0x1017,"There are no objects to slice. Masks only change the objects they overlap and can't be printed on their own.".to_string())
+            }
+            SlicerErrors::InvalidCommands { violations } => {
+                (0x1018,format!("The generated commands are invalid: {}.",violations.iter().map(|violation| violation.to_string()).collect::<Vec<_>>().join(", ")))
+            }
+            SlicerErrors::UnknownPlaceholder { placeholder } => {
+                (0x1019,format!("The custom gcode placeholder [{}] doesn't name a setting. Fix the name or set unknown placeholders to be kept as written.",placeholder))
+            }
+            SlicerErrors::TooManyLayers { computed, cap } => {
+                (0x101A,format!("Slicing would create {} layers, more than the maximum of {}. Check that layer_height and the layer heights of the layer settings are not too small, or raise max_layers.",computed, cap))
+            }
+            SlicerErrors::SpiralizeContourCount { layer, contours } => {
+                (0x101B,format!("Layer {} has {} contours. Spiralized prints need a single outline without holes on every layer.",layer, contours))
+            }
+            SlicerErrors::Cancelled => {
+                (0x101D,"Slicing was cancelled.".to_string())
+            }
+            SlicerErrors::RaggedOrientationField { layer } => {
+                (0x101E,format!("Layer {} of the fiber orientation field doesn't have the same number of rows and columns as the first layer. Every layer of the field needs the same grid.",layer))
+            }
+            SlicerErrors::PartialSlice { min, max } => {
+                (0x101F,format!("Only the layers from {}mm to {}mm were sliced for a preview. Slice the whole model to export its gcode.",min, max))
+            }
+            SlicerErrors::ResumeByObject => {
+                (0x1020,"Resuming from a layer needs the objects to be printed layer by layer. Print the objects layer by layer or slice the unfinished objects on their own.".to_string())
+            }
+            SlicerErrors::SequentialClearance { objects, clearance } => {
+                (0x101C,format!("Objects {} are closer than the clearance of {}mm. Move them apart or print them layer by layer.",objects.iter().map(|object| object.to_string()).collect::<Vec<_>>().join(", "), clearance))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SlicerErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (code, message) = self.get_code_and_message();
+        write!(f, "{:#X}: {}", code, message)
+    }
+}
+
+impl std::error::Error for SlicerErrors {}
+
+///A broken invariant of the commands passed to the gcode writer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CommandViolation {
+    ///A `NoAction` command was not optimized out
+    NoAction {
+        ///Index of the command
+        index: usize,
+    },
+
+    ///An extrusion has no id, so the ids were never evaluated
+    MissingId {
+        ///Index of the command
+        index: usize,
+    },
+
+    ///A layer change moves below the previous layer of the same print sequence
+    LayerBelowPrevious {
+        ///Index of the command
+        index: usize,
+
+        ///Height of the layer
+        z: f32,
+
+        ///Height of the previous layer
+        previous: f32,
+    },
+
+    ///A move ends outside the bed
+    OutsideBed {
+        ///Index of the command
+        index: usize,
+
+        ///X coordinate on the bed
+        x: f32,
+
+        ///Y coordinate on the bed
+        y: f32,
+    },
+
+    ///An object is printed by an extruder that isn't configured
+    UnknownExtruder {
+        ///Index of the command
+        index: usize,
+
+        ///Index of the extruder
+        extruder: usize,
+    },
+}
+
+impl std::fmt::Display for CommandViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandViolation::NoAction { index } => {
+                write!(f, "command {} was not optimized out", index)
+            }
+            CommandViolation::MissingId { index } => write!(f, "extrusion {} has no id", index),
+            CommandViolation::LayerBelowPrevious { index, z, previous } => write!(
+                f,
+                "layer change {} to {} is below the previous layer at {}",
+                index, z, previous
+            ),
+            CommandViolation::OutsideBed { index, x, y } => {
+                write!(f, "move {} ends outside the bed at ({}, {})", index, x, y)
+            }
+            CommandViolation::UnknownExtruder { index, extruder } => {
+                write!(
+                    f,
+                    "object change {} uses unknown extruder {}",
+                    index, extruder
+                )
+            }
         }
     }
 }
@@ -0,0 +1,550 @@
+use std::io::{self, Write};
+
+use crate::settings::Settings;
+
+use super::WriteGCode;
+
+const MAGIC: &[u8; 4] = b"GCDE";
+const VERSION: u32 = 1;
+const CHECKSUM_CRC32: u16 = 1;
+
+const BLOCK_GCODE: u16 = 1;
+const BLOCK_SLICER_METADATA: u16 = 2;
+const BLOCK_PRINTER_METADATA: u16 = 3;
+const BLOCK_PRINT_METADATA: u16 = 4;
+
+const COMPRESSION_NONE: u16 = 0;
+const COMPRESSION_HEATSHRINK_12_4: u16 = 3;
+
+const ENCODING_INI: u16 = 0;
+const ENCODING_MEATPACK_COMMENTS: u16 = 2;
+
+///Amount of gcode text that is packed and compressed into one block
+const BLOCK_SIZE: usize = 65536;
+
+const MEATPACK_SIGNAL: u8 = 0xFF;
+const MEATPACK_ENABLE_PACKING: u8 = 251;
+const MEATPACK_DISABLE_PACKING: u8 = 250;
+
+///Nibble marking a character that didn't fit and follows as a full byte
+const MEATPACK_UNPACKABLE: u8 = 0b1111;
+const MEATPACK_LINE_BREAK: u8 = 12;
+
+const HEATSHRINK_WINDOW_BITS: u32 = 12;
+const HEATSHRINK_LOOKAHEAD_BITS: u32 = 4;
+
+///Backreferences have to be longer than this to be smaller than the literals they replace
+const HEATSHRINK_BREAKEVEN: usize =
+    (1 + HEATSHRINK_WINDOW_BITS + HEATSHRINK_LOOKAHEAD_BITS) as usize / 8;
+
+///Candidates checked per position when searching for a backreference
+const HEATSHRINK_MAX_CANDIDATES: usize = 64;
+
+///Writes the gcode as a Prusa binary gcode file. The gcode is meatpacked and compressed with
+///heatshrink in blocks that end on a line break. Lines are counted on the text before it's packed,
+///so the navigator works the same as for text gcode.
+pub struct GCodeBinaryWriter<T: Write> {
+    writer: T,
+    buffer: Vec<u8>,
+    line_count: usize,
+}
+
+impl<T: Write> GCodeBinaryWriter<T> {
+    ///Start the file with its header and metadata, the gcode blocks follow as they are written
+    pub fn new(mut writer: T, settings: &Settings) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&CHECKSUM_CRC32.to_le_bytes())?;
+
+        let printer_metadata = format!(
+            "nozzle_diameter={}\ntemperature={}\nbed_temperature={}\n",
+            settings.nozzle_diameter, settings.filament.extruder_temp, settings.filament.bed_temp
+        );
+        write_block(
+            &mut writer,
+            BLOCK_PRINTER_METADATA,
+            ENCODING_INI,
+            printer_metadata.as_bytes(),
+            None,
+        )?;
+
+        let print_metadata = format!(
+            "layer_height={}\nfilament_diameter={}\n",
+            settings.layer_height, settings.filament.diameter
+        );
+        write_block(
+            &mut writer,
+            BLOCK_PRINT_METADATA,
+            ENCODING_INI,
+            print_metadata.as_bytes(),
+            None,
+        )?;
+
+        write_block(
+            &mut writer,
+            BLOCK_SLICER_METADATA,
+            ENCODING_INI,
+            b"generator=fiberslice\n",
+            None,
+        )?;
+
+        Ok(Self {
+            writer,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            line_count: 0,
+        })
+    }
+
+    ///Write the remaining gcode and return the underlying writer
+    pub fn finish(mut self) -> io::Result<T> {
+        self.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_gcode_block(&mut self, text: &[u8]) -> io::Result<()> {
+        let packed = meatpack(text);
+        let compressed = heatshrink(&packed);
+
+        //Blocks that don't shrink are stored as they are
+        let compressed = (compressed.len() < packed.len()).then_some(compressed.as_slice());
+
+        write_block(
+            &mut self.writer,
+            BLOCK_GCODE,
+            ENCODING_MEATPACK_COMMENTS,
+            &packed,
+            compressed,
+        )
+    }
+}
+
+impl<T: Write> WriteGCode for GCodeBinaryWriter<T> {
+    fn line_count(&self) -> usize {
+        self.line_count
+    }
+}
+
+impl<T: Write> Write for GCodeBinaryWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line_count += buf.iter().filter(|c| **c == b'\n').count();
+
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= BLOCK_SIZE {
+            //Lines are never split between blocks
+            if let Some(line_end) = self.buffer.iter().rposition(|c| *c == b'\n') {
+                let rest = self.buffer.split_off(line_end + 1);
+                let text = std::mem::replace(&mut self.buffer, rest);
+
+                self.write_gcode_block(&text)?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let text = std::mem::take(&mut self.buffer);
+
+            self.write_gcode_block(&text)?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+///Write a block with its header, the encoding parameter and the crc32 of all of it. The size of
+///the data before compression is stored along with compressed data.
+fn write_block<W: Write>(
+    writer: &mut W,
+    block_type: u16,
+    encoding: u16,
+    data: &[u8],
+    compressed: Option<&[u8]>,
+) -> io::Result<()> {
+    let mut block = Vec::with_capacity(data.len() + 16);
+
+    block.extend_from_slice(&block_type.to_le_bytes());
+    match compressed {
+        Some(compressed) => {
+            block.extend_from_slice(&COMPRESSION_HEATSHRINK_12_4.to_le_bytes());
+            block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            block.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        }
+        None => {
+            block.extend_from_slice(&COMPRESSION_NONE.to_le_bytes());
+            block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+    }
+    block.extend_from_slice(&encoding.to_le_bytes());
+    block.extend_from_slice(compressed.unwrap_or(data));
+
+    writer.write_all(&block)?;
+    writer.write_all(&crc32(&block).to_le_bytes())
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn meatpack_nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'.' => Some(10),
+        b' ' => Some(11),
+        b'\n' => Some(MEATPACK_LINE_BREAK),
+        b'G' => Some(13),
+        b'X' => Some(14),
+        _ => None,
+    }
+}
+
+///Pack pairs of common gcode characters into a byte each, characters that don't have a nibble
+///follow the pair as full bytes. Every line starts a new pair as the reader drops the second half
+///of a pair that starts with a line break.
+fn meatpack(text: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(text.len());
+    packed.extend_from_slice(&[MEATPACK_SIGNAL, MEATPACK_SIGNAL, MEATPACK_ENABLE_PACKING]);
+
+    for line in text.split_inclusive(|c| *c == b'\n') {
+        for pair in line.chunks(2) {
+            match *pair {
+                [first, second] => {
+                    let low = meatpack_nibble(first);
+                    let high = meatpack_nibble(second);
+
+                    packed.push(
+                        low.unwrap_or(MEATPACK_UNPACKABLE)
+                            | (high.unwrap_or(MEATPACK_UNPACKABLE) << 4),
+                    );
+                    if low.is_none() {
+                        packed.push(first);
+                    }
+                    if high.is_none() {
+                        packed.push(second);
+                    }
+                }
+                [b'\n'] => packed.push(MEATPACK_LINE_BREAK),
+                //The last character of a line without a line break can't be paired
+                [last] => packed.extend_from_slice(&[
+                    MEATPACK_SIGNAL,
+                    MEATPACK_SIGNAL,
+                    MEATPACK_DISABLE_PACKING,
+                    last,
+                    MEATPACK_SIGNAL,
+                    MEATPACK_SIGNAL,
+                    MEATPACK_ENABLE_PACKING,
+                ]),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    packed
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    ///Push the lowest bits of the value, most significant bit first
+    fn push(&mut self, value: usize, bits: u32) {
+        for bit in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> bit) & 1) as u8;
+            self.used += 1;
+
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.bytes.push(self.current << (8 - self.used));
+        }
+
+        self.bytes
+    }
+}
+
+///Compress with heatshrink, an LZSS variant. Every byte is either a literal tagged with a 1 bit
+///or part of a backreference tagged with a 0 bit followed by the distance and length minus one.
+fn heatshrink(data: &[u8]) -> Vec<u8> {
+    let window = 1 << HEATSHRINK_WINDOW_BITS;
+    let max_length = 1 << HEATSHRINK_LOOKAHEAD_BITS;
+
+    //Most recent position of each 3 byte prefix and the position before it with the same prefix
+    let mut head = vec![usize::MAX; 1 << 16];
+    let mut previous = vec![usize::MAX; data.len()];
+    let hash = |pos: usize| {
+        data.get(pos..pos + 3).map(|prefix| {
+            (((prefix[0] as usize) << 8) ^ ((prefix[1] as usize) << 4) ^ prefix[2] as usize)
+                & 0xFFFF
+        })
+    };
+
+    let mut bits = BitWriter::default();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let longest = max_length.min(data.len() - pos);
+
+        let mut best = (0, 0);
+        let mut candidate = hash(pos).map_or(usize::MAX, |key| head[key]);
+        for _ in 0..HEATSHRINK_MAX_CANDIDATES {
+            if candidate == usize::MAX || pos - candidate > window {
+                break;
+            }
+
+            let length = (0..longest)
+                .take_while(|offset| data[candidate + offset] == data[pos + offset])
+                .count();
+            if length > best.1 {
+                best = (pos - candidate, length);
+            }
+
+            candidate = previous[candidate];
+        }
+
+        let (distance, length) = best;
+        if length > HEATSHRINK_BREAKEVEN {
+            bits.push(0, 1);
+            bits.push(distance - 1, HEATSHRINK_WINDOW_BITS);
+            bits.push(length - 1, HEATSHRINK_LOOKAHEAD_BITS);
+        } else {
+            bits.push(1, 1);
+            bits.push(data[pos] as usize, 8);
+        }
+
+        for advanced in pos..pos + length.max(1) {
+            if let Some(key) = hash(advanced) {
+                previous[advanced] = head[key];
+                head[key] = advanced;
+            }
+        }
+        pos += length.max(1);
+    }
+
+    bits.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Coord;
+
+    use super::*;
+    use crate::{
+        gcode::{mem::GCodeMemoryWriter, write_gcode},
+        Command,
+    };
+
+    fn unheatshrink(compressed: &[u8]) -> Vec<u8> {
+        let mut bits = compressed
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+        let mut read = |count: u32| -> Option<usize> {
+            (0..count).try_fold(0, |value, _| Some((value << 1) | bits.next()? as usize))
+        };
+
+        let mut data = vec![];
+        while let Some(tag) = read(1) {
+            if tag == 1 {
+                let Some(byte) = read(8) else { break };
+                data.push(byte as u8);
+            } else {
+                let (Some(distance), Some(length)) = (
+                    read(HEATSHRINK_WINDOW_BITS),
+                    read(HEATSHRINK_LOOKAHEAD_BITS),
+                ) else {
+                    break;
+                };
+                for _ in 0..=length {
+                    data.push(data[data.len() - distance - 1]);
+                }
+            }
+        }
+        data
+    }
+
+    fn unmeatpack(packed: &[u8]) -> Vec<u8> {
+        const CHARACTERS: &[u8; 15] = b"0123456789. \nGX";
+
+        let mut text = vec![];
+        let mut bytes = packed.iter().copied().peekable();
+        let mut packing = false;
+
+        while let Some(byte) = bytes.next() {
+            //A single signal byte is a packed pair of two full characters
+            if byte == MEATPACK_SIGNAL && bytes.next_if_eq(&MEATPACK_SIGNAL).is_some() {
+                packing = bytes.next() == Some(MEATPACK_ENABLE_PACKING);
+            } else if !packing {
+                text.push(byte);
+            } else {
+                let low = CHARACTERS.get((byte & 0xF) as usize).copied();
+                let first = low.or_else(|| bytes.next()).unwrap();
+                text.push(first);
+
+                if first != b'\n' {
+                    let high = CHARACTERS.get((byte >> 4) as usize).copied();
+                    text.push(high.or_else(|| bytes.next()).unwrap());
+                }
+            }
+        }
+        text
+    }
+
+    fn read_u16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn packing_round_trips() {
+        let text = b"G1 X10.5 Y20.25 E0.12345 ; extrude\nG1 X1\n\n;LAYER:1\nM104 S200\nG";
+        assert_eq!(unmeatpack(&meatpack(text)), text);
+
+        let repeated = text.repeat(100);
+        let compressed = heatshrink(&repeated);
+        assert!(compressed.len() < repeated.len() / 4);
+        assert_eq!(unheatshrink(&compressed), repeated);
+    }
+
+    #[test]
+    fn gcode_is_written_in_checked_blocks() {
+        let settings = Settings::default();
+        let lines: String = (0..5000)
+            .map(|i| format!("G1 X{}.{} Y{} E0.01234 ; {}\n", i % 200, i % 7, i % 123, i))
+            .collect();
+
+        let mut writer = GCodeBinaryWriter::new(vec![], &settings).unwrap();
+        writer.write_all(lines.as_bytes()).unwrap();
+        assert_eq!(writer.line_count(), 5000);
+        let bytes = writer.finish().unwrap();
+
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(read_u32(&bytes, 4), VERSION);
+        assert_eq!(read_u16(&bytes, 8), CHECKSUM_CRC32);
+
+        let mut at = 10;
+        let mut block_types = vec![];
+        let mut gcode = vec![];
+        while at < bytes.len() {
+            let block_type = read_u16(&bytes, at);
+            let compression = read_u16(&bytes, at + 2);
+            let size = read_u32(&bytes, at + 4) as usize;
+            let (header, stored) = if compression == COMPRESSION_NONE {
+                (8, size)
+            } else {
+                (12, read_u32(&bytes, at + 8) as usize)
+            };
+
+            let data_start = at + header + 2;
+            let block_end = data_start + stored;
+            assert_eq!(
+                crc32(&bytes[at..block_end]),
+                read_u32(&bytes, block_end),
+                "Checksum of block {} doesn't match",
+                block_types.len()
+            );
+
+            if block_type == BLOCK_GCODE {
+                let data = &bytes[data_start..block_end];
+                let packed = if compression == COMPRESSION_HEATSHRINK_12_4 {
+                    unheatshrink(data)
+                } else {
+                    data.to_vec()
+                };
+                assert_eq!(packed.len(), size);
+
+                let text = unmeatpack(&packed);
+                assert_eq!(text.last(), Some(&b'\n'));
+                gcode.extend(text);
+            }
+
+            block_types.push(block_type);
+            at = block_end + 4;
+        }
+
+        assert_eq!(
+            block_types[..3],
+            [
+                BLOCK_PRINTER_METADATA,
+                BLOCK_PRINT_METADATA,
+                BLOCK_SLICER_METADATA
+            ]
+        );
+        assert!(block_types[3..].len() > 1);
+        assert!(block_types[3..].iter().all(|block| *block == BLOCK_GCODE));
+        assert_eq!(gcode, lines.as_bytes());
+        assert!(bytes.len() < lines.len());
+    }
+
+    #[test]
+    fn lines_are_counted_like_text() {
+        let settings = Settings::default();
+        let cmds: Vec<Command> = (0..3)
+            .flat_map(|layer| {
+                [
+                    Command::LayerChange {
+                        z: 0.2 * (layer + 1) as f32,
+                        index: layer,
+                    },
+                    Command::MoveTo {
+                        end: Coord { x: 10.0, y: 10.0 },
+                    },
+                ]
+            })
+            .collect();
+
+        let mut text = GCodeMemoryWriter::new();
+        let text_navigator = write_gcode(&cmds, &settings, &mut text).unwrap();
+
+        let mut binary = GCodeBinaryWriter::new(vec![], &settings).unwrap();
+        let binary_navigator = write_gcode(&cmds, &settings, &mut binary).unwrap();
+
+        assert_eq!(binary.line_count(), text.line_count());
+        assert_eq!(
+            format!("{:?}", binary_navigator),
+            format!("{:?}", text_navigator)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::io::Write;
+
+use geo::Coord;
+use glam::vec2;
+use serde::Serialize;
+
+use super::{bead_area, filament_length};
+use crate::{r#move::split_arc, Command, Settings, TraceType};
+
+///A move of the toolpath in the coordinates of the printer, for analysis outside of the slicer
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExportedMove {
+    ///Index of the command the move was made from. Arcs are split into several moves with the
+    ///same index.
+    pub command: usize,
+
+    ///Index of the layer the move is printed on
+    pub layer: usize,
+
+    ///Start point of the move, x, y and the height of the layer
+    pub start: [f32; 3],
+
+    ///End point of the move
+    pub end: [f32; 3],
+
+    ///The trace type of the bead, None for travel moves
+    pub trace_type: Option<TraceType>,
+
+    ///The extrusion width, 0 for travel moves
+    pub width: f32,
+
+    ///The height thickness of the bead, 0 for travel moves
+    pub thickness: f32,
+
+    ///Whether the bead contains fiber
+    pub fiber: bool,
+
+    ///Length of filament in mm extruded for the move, the E value of the gcode
+    pub extrusion_length: f32,
+}
+
+///Every travel and extrusion of the commands with the bed transform applied. Arcs are split into
+///straight moves like `extrusion_segments` does.
+pub fn export_moves(commands: &[Command], settings: &Settings) -> Vec<ExportedMove> {
+    let bed_center = vec2(settings.print_x / 2.0, settings.print_y / 2.0);
+    let transform = super::bed_transform(commands, settings);
+    let to_bed = |point: Coord<f32>, z: f32| {
+        let point = transform(point.x, point.y);
+        [point.x, point.y, z]
+    };
+
+    let mut moves = vec![];
+    let mut position = Coord {
+        x: bed_center.x,
+        y: bed_center.y,
+    };
+    let mut layer = 0;
+    let mut z = 0.0;
+    let mut trace_type = TraceType::Infill;
+
+    for (index, command) in commands.iter().enumerate() {
+        let (current_layer, current_z, current_type) = (layer, z, trace_type);
+        let mut push = |start: Coord<f32>, end: Coord<f32>, bead: Option<(f32, f32, bool, f32)>| {
+            let (width, thickness, fiber, area) = bead.unwrap_or((0.0, 0.0, false, 0.0));
+            let length = ((end.x - start.x).powi(2) + (end.y - start.y).powi(2)).sqrt();
+
+            moves.push(ExportedMove {
+                command: index,
+                layer: current_layer,
+                start: to_bed(start, current_z),
+                end: to_bed(end, current_z),
+                trace_type: bead.map(|_| current_type),
+                width,
+                thickness,
+                fiber,
+                extrusion_length: filament_length(area, length, settings),
+            });
+        };
+
+        match command {
+            Command::MoveTo { end } => {
+                push(position, *end, None);
+                position = *end;
+            }
+            Command::MoveAndExtrude {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } => {
+                let area = bead_area(*width, *thickness);
+                push(*start, *end, Some((*width, *thickness, false, area)));
+                position = *end;
+            }
+            Command::MoveAndExtrudeFiber {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            }
+            | Command::MoveAndExtrudeFiberAndCut {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } => {
+                let area = bead_area(*width, *thickness);
+                push(*start, *end, Some((*width, *thickness, true, area)));
+                position = *end;
+            }
+            //The gcode extrudes arcs with a rectangular cross section
+            Command::Arc {
+                start,
+                end,
+                center,
+                clockwise,
+                thickness,
+                width,
+                ..
+            } => {
+                for (piece_start, piece_end) in split_arc(*start, *end, *center, *clockwise) {
+                    push(
+                        piece_start,
+                        piece_end,
+                        Some((*width, *thickness, false, width * thickness)),
+                    );
+                }
+                position = *end;
+            }
+            Command::LayerChange { z: height, index } => {
+                z = *height;
+                layer = *index;
+            }
+            Command::ChangeType { print_type } => trace_type = *print_type,
+            _ => {}
+        }
+    }
+
+    moves
+}
+
+///Write the moves of `export_moves` as a json array
+pub fn write_moves_json<W: Write>(
+    commands: &[Command],
+    settings: &Settings,
+    writer: W,
+) -> serde_json::Result<()> {
+    serde_json::to_writer(writer, &export_moves(commands, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_exported_on_the_bed() {
+        let mut settings = Settings::default();
+        settings.bed_transform.offset = (5.0, 0.0);
+
+        let commands = vec![
+            Command::LayerChange { z: 0.4, index: 1 },
+            Command::ChangeType {
+                print_type: TraceType::WallOuter,
+            },
+            Command::MoveTo {
+                end: Coord { x: 10.0, y: 10.0 },
+            },
+            Command::MoveAndExtrudeFiber {
+                id: None,
+                start: Coord { x: 10.0, y: 10.0 },
+                end: Coord { x: 20.0, y: 10.0 },
+                thickness: 0.2,
+                width: 0.4,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+            Command::Arc {
+                id: None,
+                start: Coord { x: 20.0, y: 10.0 },
+                end: Coord { x: 10.0, y: 10.0 },
+                center: Coord { x: 15.0, y: 10.0 },
+                clockwise: false,
+                thickness: 0.2,
+                width: 0.4,
+            },
+        ];
+
+        let moves = export_moves(&commands, &settings);
+        assert_eq!(moves[0].trace_type, None);
+        assert_eq!(moves[0].extrusion_length, 0.0);
+        assert_eq!(moves[0].end, [15.0, 10.0, 0.4]);
+
+        let fiber = &moves[1];
+        assert_eq!(
+            (fiber.command, fiber.layer, fiber.trace_type, fiber.fiber),
+            (3, 1, Some(TraceType::WallOuter), true)
+        );
+        assert_eq!(fiber.start, [15.0, 10.0, 0.4]);
+        assert_eq!(fiber.end, [25.0, 10.0, 0.4]);
+        assert!(
+            (fiber.extrusion_length - filament_length(bead_area(0.4, 0.2), 10.0, &settings)).abs()
+                < 0.00001
+        );
+
+        //Half a circle of radius 5 split into pieces
+        let arc: Vec<&ExportedMove> = moves.iter().filter(|m| m.command == 4).collect();
+        assert!(arc.len() > 1);
+        let extruded: f32 = arc.iter().map(|m| m.extrusion_length).sum();
+        let expected = filament_length(0.4 * 0.2, std::f32::consts::PI * 5.0, &settings);
+        assert!((extruded - expected).abs() / expected < 0.01);
+
+        let mut json = vec![];
+        write_moves_json(&commands, &settings, &mut json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed.as_array().map(Vec::len), Some(moves.len()));
+        assert_eq!(parsed[1]["trace_type"], "WallOuter");
+    }
+}
@@ -1,11 +1,26 @@
+use std::collections::HashMap;
 use std::io::Write;
 
-use glam::vec2;
+use geo::Coord;
+use glam::{vec2, Vec2};
 use navigator::Navigator;
 
-use super::{settings::Settings, Command, RetractionType};
+use ordered_float::OrderedFloat;
 
+use super::{
+    error::SlicerErrors,
+    settings::{HeightMap, Settings, WitnessPoint},
+    Command, GcodeFlavor, RetractionType, StateChange,
+};
+
+pub mod binary;
+pub mod export;
 pub mod navigator;
+pub mod placeholder;
+pub mod validate;
+
+///Height above the resumed layer the nozzle travels at before lowering onto the part
+const RESUME_APPROACH_HEIGHT: f32 = 2.0;
 
 #[derive(Debug)]
 pub struct SlicedGCode {
@@ -27,6 +42,34 @@ impl SlicedGCode {
             navigator,
         }
     }
+
+    ///Re-parse the gcode and check that every move has valid, finite arguments and stays on the
+    ///bed once the first layer starts. Returns the indices of the offending lines along with what
+    ///is wrong with them.
+    pub fn verify(&self, settings: &Settings) -> Result<(), Vec<(usize, String)>> {
+        let bounds_from = self.navigator.get_layer_change_index(0).unwrap_or(0);
+        validate::verify_gcode(&self.gcode, &self.line_breaks, bounds_from, settings)
+    }
+}
+
+///The file format exported gcode is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GCodeFormat {
+    ///Plain text gcode
+    #[default]
+    Text,
+
+    ///Prusa binary gcode, meatpacked and compressed with heatshrink
+    Binary,
+}
+
+impl GCodeFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GCodeFormat::Text => "gcode",
+            GCodeFormat::Binary => "bgcode",
+        }
+    }
 }
 
 pub trait WriteGCode: Write {
@@ -113,17 +156,138 @@ impl<T: Write> Write for GCodeFileWriter<T> {
     }
 }
 
+///Corners of the area covered by the moves of the commands, including travels and the moves of
+///retractions
+fn print_bounds(cmds: &[Command]) -> Option<(Vec2, Vec2)> {
+    cmds.iter()
+        .flat_map(|cmd| match cmd {
+            Command::MoveAndExtrude { start, end, .. }
+            | Command::MoveAndExtrudeFiber { start, end, .. }
+            | Command::MoveAndExtrudeFiberAndCut { start, end, .. }
+            | Command::Arc { start, end, .. } => vec![*start, *end],
+            Command::MoveTo { end } => vec![*end],
+            Command::SetState { new_state } => match &new_state.retract {
+                RetractionType::MoveRetract(moves) => moves.iter().map(|(_, end)| *end).collect(),
+                _ => vec![],
+            },
+            _ => vec![],
+        })
+        .map(|point| vec2(point.x, point.y))
+        .fold(None, |bounds, point| match bounds {
+            Some((min, max)) => Some((point.min(min), point.max(max))),
+            None => Some((point, point)),
+        })
+}
+
+///Moves a point of the commands from slicer space into the printers frame
+fn bed_transform<'a>(cmds: &[Command], settings: &'a Settings) -> impl Fn(f32, f32) -> Vec2 + 'a {
+    let bed = vec2(settings.print_x, settings.print_y);
+    let (pivot, shift) = print_bounds(cmds)
+        .map(|(min, max)| settings.bed_transform.placement(min, max, bed))
+        .unwrap_or((bed / 2.0, Vec2::ZERO));
+
+    move |x, y| settings.bed_transform.apply(vec2(x, y), pivot) + shift
+}
+
 pub fn write_gcode(
     cmds: &[Command],
     settings: &Settings,
     writer: &mut dyn WriteGCode,
 ) -> Result<Navigator, Box<dyn std::error::Error>> {
+    //Coordinates are only moved into the printers frame when written out
+    let to_bed = bed_transform(cmds, settings);
+    validate::validate_commands(cmds, settings, &to_bed)?;
+
     let mut current_z = 0.0;
     let mut layer_count = 0;
     let mut current_object = None;
 
+    //Printers start on the first extruder, other extruders are expected to be parked with their
+    //filament retracted by the toolchange length
+    let mut current_extruder = 0;
+    let mut retracted = false;
+
+    //Temperature printing resumes at after a pause on standby
+    let mut nozzle_temp = settings.filament.extruder_temp;
+
     let mut navigator = Navigator::new(cmds.len());
 
+    let spiral_heights = if settings.spiralize {
+        spiral_heights(cmds)
+    } else {
+        vec![None; cmds.len()]
+    };
+
+    let bed_center = vec2(settings.print_x / 2.0, settings.print_y / 2.0);
+
+    //Height the bed mesh adds at the last written position, so moves that only change z keep
+    //the nozzle on the mesh
+    let mut mesh_offset = 0.0;
+    let mut last_position = bed_center;
+    let mut lifted = false;
+
+    //Printing by layer changes to every object's part of a layer, the hooks only run on the first
+    let mut last_hooked_layer = None;
+
+    //The retraction after the last extrusion ends the print and can skip its z lift
+    let final_retract = if settings.final_retract_no_lift {
+        cmds.iter()
+            .rposition(|cmd| {
+                matches!(
+                    cmd,
+                    Command::MoveAndExtrude { .. }
+                        | Command::MoveAndExtrudeFiber { .. }
+                        | Command::MoveAndExtrudeFiberAndCut { .. }
+                        | Command::Arc { .. }
+                )
+            })
+            .and_then(|last_extrusion| {
+                cmds.iter()
+                    .skip(last_extrusion)
+                    .position(|cmd| match cmd {
+                        Command::SetState { new_state } => matches!(
+                            new_state.retract,
+                            RetractionType::Retract
+                                | RetractionType::Lift
+                                | RetractionType::MoveRetract(_)
+                        ),
+                        _ => false,
+                    })
+                    .map(|offset| last_extrusion + offset)
+            })
+    } else {
+        None
+    };
+
+    //Each witness point is emitted once, at the layer change closest to its height
+    let layer_changes: Vec<(usize, f32)> = cmds
+        .iter()
+        .enumerate()
+        .filter_map(|(index, cmd)| match cmd {
+            Command::LayerChange { z, .. } => Some((index, *z)),
+            _ => None,
+        })
+        .collect();
+    let witnesses: Vec<(usize, &WitnessPoint)> = settings
+        .witness_points
+        .iter()
+        .filter_map(|point| {
+            layer_changes
+                .iter()
+                .min_by_key(|(_, z)| OrderedFloat((z - point.z).abs()))
+                .map(|(index, _)| (*index, point))
+        })
+        .collect();
+
+    //Layers before the resume layer are not printed, only the state they leave behind is kept
+    let resume_layer = settings
+        .resume_from_layer
+        .is_enabled()
+        .then_some(*settings.resume_from_layer);
+    let mut skipping = resume_layer.is_some();
+    let mut skipped_state = StateChange::default();
+    let mut approaching = false;
+
     let start = convert_instructions(
         settings.starting_instructions.clone(),
         current_z,
@@ -131,7 +295,7 @@ pub fn write_gcode(
         None,
         current_object,
         settings,
-    );
+    )?;
 
     writeln!(
         writer,
@@ -141,33 +305,210 @@ pub fn write_gcode(
         settings.max_acceleration_z,
         settings.max_acceleration_e
     )?;
-    writeln!(
-        writer,
-        "M203 X{:.1} Y{:.1} Z{:.1} E{:.1}; ; sets maximum feedrates, mm/sec",
-        settings.maximum_feedrate_x,
-        settings.maximum_feedrate_y,
-        settings.maximum_feedrate_z,
-        settings.maximum_feedrate_e
-    )?;
-    writeln!(writer, "M204 P{:.1} R{:.1} T{:.1}; sets acceleration (P, T) and retract acceleration (R), mm/sec^2", settings.max_acceleration_extruding, settings.max_acceleration_retracting, settings.max_acceleration_travel)?;
-    writeln!(
-        writer,
-        "M205 X{:.1} Y{:.1} Z{:.1} E{:.1}; sets the jerk limits, mm/sec",
-        settings.max_jerk_x, settings.max_jerk_y, settings.max_jerk_z, settings.max_jerk_e
-    )?;
-    writeln!(
-        writer,
-        "M205 S{:.1} T{:.1} ; sets the minimum extruding and travel feed rate, mm/sec",
-        settings.minimum_feedrate_print, settings.minimum_feedrate_travel
-    )?;
-    writeln!(writer, "{}", start)?;
+    match settings.gcode_flavor {
+        GcodeFlavor::Marlin => {
+            writeln!(
+                writer,
+                "M203 X{:.1} Y{:.1} Z{:.1} E{:.1}; ; sets maximum feedrates, mm/sec",
+                settings.maximum_feedrate_x,
+                settings.maximum_feedrate_y,
+                settings.maximum_feedrate_z,
+                settings.maximum_feedrate_e
+            )?;
+            writeln!(writer, "M204 P{:.1} R{:.1} T{:.1}; sets acceleration (P, T) and retract acceleration (R), mm/sec^2", settings.max_acceleration_extruding, settings.max_acceleration_retracting, settings.max_acceleration_travel)?;
+            writeln!(
+                writer,
+                "M205 X{:.1} Y{:.1} Z{:.1} E{:.1}; sets the jerk limits, mm/sec",
+                settings.max_jerk_x, settings.max_jerk_y, settings.max_jerk_z, settings.max_jerk_e
+            )?;
+            writeln!(
+                writer,
+                "M205 S{:.1} T{:.1} ; sets the minimum extruding and travel feed rate, mm/sec",
+                settings.minimum_feedrate_print, settings.minimum_feedrate_travel
+            )?;
+        }
+        //RepRapFirmware takes feedrates and jerk in mm/min and has no retract acceleration
+        GcodeFlavor::RepRapFirmware => {
+            writeln!(
+                writer,
+                "M203 X{:.1} Y{:.1} Z{:.1} E{:.1} ; sets maximum feedrates, mm/min",
+                settings.maximum_feedrate_x * 60.0,
+                settings.maximum_feedrate_y * 60.0,
+                settings.maximum_feedrate_z * 60.0,
+                settings.maximum_feedrate_e * 60.0
+            )?;
+            writeln!(
+                writer,
+                "M204 P{:.1} T{:.1} ; sets printing and travel acceleration, mm/sec^2",
+                settings.max_acceleration_extruding, settings.max_acceleration_travel
+            )?;
+            writeln!(
+                writer,
+                "M566 X{:.1} Y{:.1} Z{:.1} E{:.1} ; sets the jerk limits, mm/min",
+                settings.max_jerk_x * 60.0,
+                settings.max_jerk_y * 60.0,
+                settings.max_jerk_z * 60.0,
+                settings.max_jerk_e * 60.0
+            )?;
+        }
+    }
+    if settings.pressure_advance.is_enabled() {
+        match settings.gcode_flavor {
+            GcodeFlavor::Marlin => writeln!(
+                writer,
+                "M900 K{:.4} ; set linear advance",
+                *settings.pressure_advance
+            )?,
+            GcodeFlavor::RepRapFirmware => writeln!(
+                writer,
+                "M572 D0 S{:.4} ; set pressure advance",
+                *settings.pressure_advance
+            )?,
+        }
+    }
+    //The resume instructions replace the starting instructions at the resumed layer
+    if resume_layer.is_none() {
+        writeln!(writer, "{}", start)?;
+    }
     writeln!(writer, "G21 ; set units to millimeters")?;
     writeln!(writer, "G90 ; use absolute Coords")?;
     writeln!(writer, "M83 ; use relative distances for extrusion")?;
 
-    for cmd in cmds {
+    for (index, cmd) in cmds.iter().enumerate() {
+        let lift_z = final_retract != Some(index);
+        let spiral_height = spiral_heights[index];
+
+        if skipping {
+            match cmd {
+                Command::LayerChange { z, index } if Some(*index) >= resume_layer => {
+                    skipping = false;
+                    approaching = true;
+
+                    writeln!(writer, ";RESUME FROM LAYER:{}", index)?;
+                    writeln!(
+                        writer,
+                        "{}",
+                        convert_instructions(
+                            settings.resume_instructions.clone(),
+                            *z,
+                            *index,
+                            None,
+                            current_object,
+                            settings
+                        )?
+                    )?;
+
+                    //Restore the state the skipped layers left behind
+                    if current_extruder != 0 {
+                        writeln!(writer, "T{} ; change extruder", current_extruder)?;
+                    }
+                    if let Some(ext_temp) = skipped_state.extruder_temp {
+                        nozzle_temp = ext_temp;
+                        writeln!(
+                            writer,
+                            "{}",
+                            extruder_temp_command(ext_temp, current_extruder, settings)
+                        )?;
+                    }
+                    if let Some(bed_temp) = skipped_state.bed_temp {
+                        writeln!(writer, "M140 S{:.1} ; set bed temp", bed_temp)?;
+                    }
+                    if let Some(fan_speed) = skipped_state.fan_speed {
+                        writeln!(
+                            writer,
+                            "M106 S{} ; set fan speed",
+                            (2.550 * fan_speed).round() as usize
+                        )?;
+                    }
+                    if let Some(accel) = skipped_state.acceleration {
+                        writeln!(writer, "{}", acceleration_command(accel, settings))?;
+                    }
+                    if let Some(speed) = skipped_state.movement_speed {
+                        writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
+                    }
+
+                    //The next unretract expects the filament to be retracted
+                    retracted = matches!(
+                        skipped_state.retract,
+                        RetractionType::Retract
+                            | RetractionType::MoveRetract(_)
+                            | RetractionType::LayerLift { retract: true }
+                            | RetractionType::ClearanceLift { .. }
+                    );
+                    if retracted {
+                        writeln!(
+                            writer,
+                            "G1 E{:.5} F{:.5}; Retract",
+                            -settings.retract_length,
+                            60.0 * settings.retract_speed,
+                        )?;
+                    }
+                }
+                Command::LayerChange { z, index } => {
+                    navigator.record_layer_change(writer.line_count());
+                    current_z = *z;
+                    layer_count = *index;
+                    continue;
+                }
+                Command::SetState { new_state } => {
+                    skipped_state = skipped_state.combine(new_state);
+                    continue;
+                }
+                Command::ChangeObject { object, extruder } => {
+                    current_object = Some(*object);
+                    if *extruder != current_extruder {
+                        nozzle_temp = settings.extruder_filament(*extruder).extruder_temp;
+                    }
+                    current_extruder = *extruder;
+                    continue;
+                }
+                _ => continue,
+            }
+        }
+
+        //Lower onto the resumed layer from above once the nozzle is over the part
+        if approaching
+            && matches!(
+                cmd,
+                Command::MoveAndExtrude { .. }
+                    | Command::MoveAndExtrudeFiber { .. }
+                    | Command::MoveAndExtrudeFiberAndCut { .. }
+                    | Command::Arc { .. }
+            )
+        {
+            writeln!(writer, "G1 Z{:.5}; lower onto resumed layer", current_z)?;
+            approaching = false;
+        }
+
         match cmd {
-            Command::MoveTo { end, .. } => writeln!(writer, "G1 X{:.5} Y{:.5}", end.x, end.y)?,
+            Command::MoveTo { end, .. } => {
+                let end = to_bed(end.x, end.y);
+                last_position = end;
+
+                match MeshLayer::new(settings, layer_count) {
+                    //A lifted nozzle stays lifted until the unretract lowers it onto the mesh
+                    Some(mesh) => {
+                        mesh_offset = mesh.offset_at(end);
+                        if lifted {
+                            writeln!(writer, "G1 X{:.5} Y{:.5}", end.x, end.y)?;
+                        } else {
+                            writeln!(
+                                writer,
+                                "G1 X{:.5} Y{:.5} Z{:.5}",
+                                end.x,
+                                end.y,
+                                current_z + mesh_offset
+                            )?;
+                        }
+                    }
+                    None => writeln!(writer, "G1 X{:.5} Y{:.5}", end.x, end.y)?,
+                }
+
+                if approaching {
+                    writeln!(writer, "G1 Z{:.5}; lower onto resumed layer", current_z)?;
+                    approaching = false;
+                }
+            }
             Command::MoveAndExtrude {
                 id,
                 start,
@@ -185,28 +526,45 @@ pub fn write_gcode(
                 let y_diff = end.y - start.y;
                 let length = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
 
-                //let extrusion_width = width + (thickness * (1.0 - std::f32::consts::FRAC_PI_4));
-
-                let extrusion_volume = (((width - thickness) * thickness)
-                    + (std::f32::consts::PI * (thickness / 2.0) * (thickness / 2.0)))
-                    * length;
-                /*let extrusion_volume = width*thickness*length;*/
-
-                let filament_area = (std::f32::consts::PI
-                    * settings.filament.diameter
-                    * settings.filament.diameter)
-                    / 4.0;
-                let extrude = extrusion_volume / filament_area;
-
-                #[cfg(debug_assertions)]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} ;{}",
-                    end.x, end.y, extrude, debug
-                )?;
+                let extrude = filament_length(bead_area(*width, *thickness), length, settings);
+                let start = to_bed(start.x, start.y);
+                let end = to_bed(end.x, end.y);
+                last_position = end;
+                let start_z = current_z;
+
+                //A spiralized wall rises with every move instead of at the layer change
+                let spiral_z = spiral_height
+                    .map(|z| {
+                        current_z = z;
+                        format!(" Z{:.5}", z)
+                    })
+                    .unwrap_or_default();
+
+                if let Some(mesh) = MeshLayer::new(settings, layer_count) {
+                    mesh_offset = write_meshed_extrusion(
+                        writer,
+                        start,
+                        end,
+                        (start_z, current_z),
+                        extrude,
+                        false,
+                        &mesh,
+                    )?;
+                } else {
+                    #[cfg(debug_assertions)]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5}{} E{:.5} ;{}",
+                        end.x, end.y, spiral_z, extrude, debug
+                    )?;
 
-                #[cfg(not(debug_assertions))]
-                writeln!(writer, "G1 X{:.5} Y{:.5} E{:.5}", end.x, end.y, extrude)?;
+                    #[cfg(not(debug_assertions))]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5}{} E{:.5}",
+                        end.x, end.y, spiral_z, extrude
+                    )?;
+                }
             }
             Command::MoveAndExtrudeFiber {
                 id,
@@ -225,32 +583,36 @@ pub fn write_gcode(
                 let y_diff = end.y - start.y;
                 let length = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
 
-                //let extrusion_width = width + (thickness * (1.0 - std::f32::consts::FRAC_PI_4));
+                let extrude = filament_length(bead_area(*width, *thickness), length, settings);
+                let start = to_bed(start.x, start.y);
+                let end = to_bed(end.x, end.y);
+                last_position = end;
 
-                let extrusion_volume = (((width - thickness) * thickness)
-                    + (std::f32::consts::PI * (thickness / 2.0) * (thickness / 2.0)))
-                    * length;
-                /*let extrusion_volume = width*thickness*length;*/
-
-                let filament_area = (std::f32::consts::PI
-                    * settings.filament.diameter
-                    * settings.filament.diameter)
-                    / 4.0;
-                let extrude = extrusion_volume / filament_area;
-
-                #[cfg(debug_assertions)]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
-                    end.x, end.y, extrude, extrude, debug
-                )?;
+                if let Some(mesh) = MeshLayer::new(settings, layer_count) {
+                    mesh_offset = write_meshed_extrusion(
+                        writer,
+                        start,
+                        end,
+                        (current_z, current_z),
+                        extrude,
+                        true,
+                        &mesh,
+                    )?;
+                } else {
+                    #[cfg(debug_assertions)]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
+                        end.x, end.y, extrude, extrude, debug
+                    )?;
 
-                #[cfg(not(debug_assertions))]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
-                    end.x, end.y, extrude, extrude
-                )?;
+                    #[cfg(not(debug_assertions))]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
+                        end.x, end.y, extrude, extrude
+                    )?;
+                }
             }
             Command::MoveAndExtrudeFiberAndCut {
                 id,
@@ -278,54 +640,72 @@ pub fn write_gcode(
                 let lenght_before_cut = (cut_pos - start).length();
                 let length_after_cut = length - lenght_before_cut;
 
-                //let extrusion_width = width + (thickness * (1.0 - std::f32::consts::FRAC_PI_4));
-                let extrude_fn = |length: f32| {
-                    let extrusion_volume = (((width - thickness) * thickness)
-                        + (std::f32::consts::PI * (thickness / 2.0) * (thickness / 2.0)))
-                        * length;
-                    /*let extrusion_volume = width*thickness*length;*/
-
-                    let filament_area = (std::f32::consts::PI
-                        * settings.filament.diameter
-                        * settings.filament.diameter)
-                        / 4.0;
-                    extrusion_volume / filament_area
-                };
+                let extrude_fn =
+                    |length: f32| filament_length(bead_area(*width, *thickness), length, settings);
 
                 let extrude_before_cut = extrude_fn(lenght_before_cut);
+                let start = to_bed(start.x, start.y);
+                let cut_pos = to_bed(cut_pos.x, cut_pos.y);
+                let mesh = MeshLayer::new(settings, layer_count);
 
-                #[cfg(debug_assertions)]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
-                    cut_pos.x, cut_pos.y, extrude_before_cut, extrude_before_cut, debug
-                )?;
+                if let Some(mesh) = &mesh {
+                    write_meshed_extrusion(
+                        writer,
+                        start,
+                        cut_pos,
+                        (current_z, current_z),
+                        extrude_before_cut,
+                        true,
+                        mesh,
+                    )?;
+                } else {
+                    #[cfg(debug_assertions)]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
+                        cut_pos.x, cut_pos.y, extrude_before_cut, extrude_before_cut, debug
+                    )?;
 
-                #[cfg(not(debug_assertions))]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
-                    cut_pos.x, cut_pos.y, extrude_before_cut, extrude_before_cut
-                )?;
+                    #[cfg(not(debug_assertions))]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
+                        cut_pos.x, cut_pos.y, extrude_before_cut, extrude_before_cut
+                    )?;
+                }
 
                 // cut
                 writeln!(writer, "M300; cut fiber")?;
 
                 let extrude_after_cut = extrude_fn(length_after_cut);
+                let end = to_bed(end.x, end.y);
+                last_position = end;
 
-                #[cfg(debug_assertions)]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
-                    end.x, end.y, extrude_after_cut, extrude_after_cut, debug
-                )?;
+                if let Some(mesh) = &mesh {
+                    mesh_offset = write_meshed_extrusion(
+                        writer,
+                        cut_pos,
+                        end,
+                        (current_z, current_z),
+                        extrude_after_cut,
+                        true,
+                        mesh,
+                    )?;
+                } else {
+                    #[cfg(debug_assertions)]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5} ;{}",
+                        end.x, end.y, extrude_after_cut, extrude_after_cut, debug
+                    )?;
 
-                #[cfg(not(debug_assertions))]
-                writeln!(
-                    writer,
-                    "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
-                    end.x, end.y, extrude_after_cut, extrude_after_cut
-                )?;
+                    #[cfg(not(debug_assertions))]
+                    writeln!(
+                        writer,
+                        "G1 X{:.5} Y{:.5} E{:.5} D{:.5}",
+                        end.x, end.y, extrude_after_cut, extrude_after_cut
+                    )?;
+                }
             }
             Command::SetState { new_state } => {
                 match &new_state.retract {
@@ -334,7 +714,7 @@ pub fn write_gcode(
                             writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
                         }
                         if let Some(accel) = new_state.acceleration {
-                            writeln!(writer, "M204 S{:.1}", accel)?;
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
                         }
                     }
                     RetractionType::Retract => {
@@ -343,7 +723,7 @@ pub fn write_gcode(
                             writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
                         }
                         if let Some(accel) = new_state.acceleration {
-                            writeln!(writer, "M204 S{:.1}", accel)?;
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
                         }
 
                         writeln!(
@@ -352,29 +732,57 @@ pub fn write_gcode(
                             -settings.retract_length,
                             60.0 * settings.retract_speed,
                         )?;
+                        retracted = true;
 
-                        writeln!(
-                            writer,
-                            "G1 Z{:.5} F{:.5}; z Lift",
-                            current_z + settings.retract_lift_z,
-                            60.0 * settings.speed.travel,
-                        )?;
+                        if lift_z {
+                            writeln!(
+                                writer,
+                                "G1 Z{:.5} F{:.5}; z Lift",
+                                current_z + mesh_offset + settings.retract_lift_z,
+                                60.0 * settings.speed.travel,
+                            )?;
+                            lifted = true;
+                        }
+                    }
+                    RetractionType::Lift => {
+                        if let Some(speed) = new_state.movement_speed {
+                            writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
+                        }
+                        if let Some(accel) = new_state.acceleration {
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
+                        }
+
+                        if lift_z {
+                            writeln!(
+                                writer,
+                                "G1 Z{:.5} F{:.5}; z Lift",
+                                current_z + mesh_offset + settings.retract_lift_z,
+                                60.0 * settings.speed.travel,
+                            )?;
+                            lifted = true;
+                        }
                     }
                     RetractionType::Unretract => {
                         //unretract
-                        writeln!(writer, "G1 Z{:.5}; z unlift", current_z,)?;
-                        writeln!(
-                            writer,
-                            "G1 E{:.5} F{:.5}; Unretract",
-                            settings.retract_length,
-                            60.0 * settings.retract_speed,
-                        )?;
+                        writeln!(writer, "G1 Z{:.5}; z unlift", current_z + mesh_offset)?;
+                        lifted = false;
+
+                        //Travels that only lifted left the filament in place
+                        if retracted {
+                            writeln!(
+                                writer,
+                                "G1 E{:.5} F{:.5}; Unretract",
+                                settings.retract_length,
+                                60.0 * settings.retract_speed,
+                            )?;
+                            retracted = false;
+                        }
 
                         if let Some(speed) = new_state.movement_speed {
                             writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
                         }
                         if let Some(accel) = new_state.acceleration {
-                            writeln!(writer, "M204 S{:.1}", accel)?;
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
                         }
                     }
                     RetractionType::MoveRetract(moves) => {
@@ -382,28 +790,91 @@ pub fn write_gcode(
                             writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
                         }
                         if let Some(accel) = new_state.acceleration {
-                            writeln!(writer, "M204 S{:.1}", accel)?;
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
                         }
 
                         for (retract_amount, end) in moves {
+                            let end = to_bed(end.x, end.y);
                             writeln!(
                                 writer,
                                 "G1 X{:.5} Y{:.5} E{:.5}; Retract with move",
                                 end.x, end.y, -retract_amount
                             )?;
                         }
+                        retracted = true;
+
+                        if lift_z {
+                            writeln!(
+                                writer,
+                                "G1 Z{:.5} F{:.5}; z Lift",
+                                current_z + mesh_offset + settings.retract_lift_z,
+                                60.0 * settings.speed.travel,
+                            )?;
+                            lifted = true;
+                        }
+                    }
+                    RetractionType::ClearanceLift { z } => {
+                        if let Some(speed) = new_state.movement_speed {
+                            writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
+                        }
+                        if let Some(accel) = new_state.acceleration {
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
+                        }
+
+                        if !retracted {
+                            writeln!(
+                                writer,
+                                "G1 E{:.5} F{:.5}; Retract",
+                                -settings.retract_length,
+                                60.0 * settings.retract_speed,
+                            )?;
+                            retracted = true;
+                        }
 
+                        writeln!(
+                            writer,
+                            "G1 Z{:.5} F{:.5}; clearance lift",
+                            z.max(current_z + mesh_offset + settings.retract_lift_z),
+                            60.0 * settings.speed.travel,
+                        )?;
+                        lifted = true;
+                    }
+                    RetractionType::LayerLift { retract } => {
+                        if let Some(speed) = new_state.movement_speed {
+                            writeln!(writer, "G1 F{:.5}", speed * 60.0)?;
+                        }
+                        if let Some(accel) = new_state.acceleration {
+                            writeln!(writer, "{}", acceleration_command(accel, settings))?;
+                        }
+
+                        if *retract && !retracted {
+                            writeln!(
+                                writer,
+                                "G1 E{:.5} F{:.5}; Retract",
+                                -settings.retract_length,
+                                60.0 * settings.retract_speed,
+                            )?;
+                            retracted = true;
+                        }
+
+                        //The z is already that of the new layer
                         writeln!(
                             writer,
                             "G1 Z{:.5} F{:.5}; z Lift",
-                            current_z + settings.retract_lift_z,
+                            current_z + mesh_offset + settings.retract_lift_z,
                             60.0 * settings.speed.travel,
                         )?;
+                        lifted = true;
                     }
                 }
 
                 if let Some(ext_temp) = new_state.extruder_temp {
-                    writeln!(writer, "M104 S{:.1} ; set extruder temp", ext_temp)?;
+                    nozzle_temp = ext_temp;
+                    writeln!(
+                        writer,
+                        "{}",
+                        extruder_temp_command(ext_temp, current_extruder, settings)
+                    )?;
                 }
                 if let Some(bed_temp) = new_state.bed_temp {
                     writeln!(writer, "M140 S{:.1} ; set bed temp", bed_temp)?;
@@ -431,11 +902,23 @@ pub fn write_gcode(
                         None,
                         current_object,
                         settings
-                    )
+                    )?
                 )?;
-                current_z = *z;
+                //A spiralized layer starts where the layer below ended and rises from there
+                current_z = spiral_height.unwrap_or(*z);
                 layer_count = *index;
-                writeln!(writer, "G1 Z{:.5}", z)?;
+                if approaching {
+                    writeln!(
+                        writer,
+                        "G1 Z{:.5}; approach resumed layer from above",
+                        z + RESUME_APPROACH_HEIGHT
+                    )?;
+                } else {
+                    mesh_offset = MeshLayer::new(settings, layer_count)
+                        .map_or(0.0, |mesh| mesh.offset_at(last_position));
+                    writeln!(writer, "G1 Z{:.5}", current_z + mesh_offset)?;
+                    lifted = false;
+                }
 
                 writeln!(
                     writer,
@@ -447,13 +930,46 @@ pub fn write_gcode(
                         None,
                         current_object,
                         settings
-                    )
+                    )?
                 )?;
+
+                //Hooks run once the nozzle is at the new layer, so a pause resumes right there
+                let first_of_layer = last_hooked_layer != Some(layer_count);
+                last_hooked_layer = Some(layer_count);
+                for (_, hook) in settings
+                    .layer_gcode_hooks
+                    .iter()
+                    .filter(|(range, _)| first_of_layer && range.contains(layer_count, *z))
+                {
+                    writeln!(
+                        writer,
+                        "{}",
+                        convert_instructions(
+                            hook.clone(),
+                            current_z,
+                            layer_count,
+                            None,
+                            current_object,
+                            settings
+                        )?
+                    )?;
+                }
+
+                for (_, point) in witnesses.iter().filter(|(at, _)| *at == index) {
+                    writeln!(
+                        writer,
+                        ";WITNESS X{:.3} Y{:.3} Z{:.3}",
+                        point.x, point.y, point.z
+                    )?;
+                    writeln!(writer, "M117 {}", sanitize_message(&point.message))?;
+                    write_dwell(writer, point.dwell, nozzle_temp, current_extruder, settings)?;
+                }
             }
             Command::Delay { msec } => {
-                writeln!(writer, "G4 P{:.5}", msec)?;
+                write_dwell(writer, *msec, nozzle_temp, current_extruder, settings)?;
             }
             Command::Arc {
+                id,
                 start,
                 end,
                 center,
@@ -461,39 +977,83 @@ pub fn write_gcode(
                 width,
                 thickness,
             } => {
-                let x_diff = end.x - start.x;
-                let y_diff = end.y - start.y;
-                let cord_length = ((x_diff * x_diff) + (y_diff * y_diff)).sqrt();
-                let x_diff_r = end.x - center.x;
-                let y_diff_r = end.y - center.y;
-                let radius = ((x_diff_r * x_diff_r) + (y_diff_r * y_diff_r)).sqrt();
-
-                //Divide the chord length by double the radius.
-                let t = cord_length / (2.0 * radius);
-                //println!("{}",t);
-                //Find the inverse sine of the result (in radians).
-                //Double the result of the inverse sine to get the central angle in radians.
-                let central = t.asin() * 2.0;
-                //Once you have the central angle in radians, multiply it by the radius to get the arc length.
-                let extrusion_length = central * radius;
-
-                //println!("{}",extrusion_length);
-                let extrude = (4.0 * thickness * width * extrusion_length)
-                    / (std::f32::consts::PI
-                        * settings.filament.diameter
-                        * settings.filament.diameter);
+                navigator.record_trace(id.expect("Id's not eval yet!"), writer.line_count());
+
+                //The same bead as the lines the arc replaces
+                let extrude = filament_length(
+                    bead_area(*width, *thickness),
+                    arc_length(*start, *end, *center),
+                    settings,
+                );
+
+                let end = to_bed(end.x, end.y);
+                last_position = end;
+                let center_offset = settings
+                    .bed_transform
+                    .apply_vector(vec2(center.x - start.x, center.y - start.y));
+                //Mirroring the bed reverses the direction of the arc
+                let clockwise = *clockwise != settings.bed_transform.is_mirrored();
+
+                //A spiralized arc rises like the other extrusions of the wall
+                let spiral = spiral_height.map(|z| current_z = z).is_some();
+
+                //On the bed mesh the arc becomes a helix ending at the height of the mesh
+                let mesh_z = match MeshLayer::new(settings, layer_count) {
+                    Some(mesh) => {
+                        mesh_offset = mesh.offset_at(end);
+                        format!(" Z{:.5}", current_z + mesh_offset)
+                    }
+                    None if spiral => format!(" Z{:.5}", current_z),
+                    None => String::new(),
+                };
+
                 writeln!(
                     writer,
-                    "{} X{:.5} Y{:.5} I{:.5} J{:.5} E{:.5}",
-                    if *clockwise { "G2" } else { "G3" },
+                    "{} X{:.5} Y{:.5}{} I{:.5} J{:.5} E{:.5}",
+                    if clockwise { "G2" } else { "G3" },
                     end.x,
                     end.y,
-                    center.x - start.x,
-                    center.y - start.y,
+                    mesh_z,
+                    center_offset.x,
+                    center_offset.y,
                     extrude
                 )?;
             }
-            Command::ChangeObject { object } => {
+            Command::ChangeObject { object, extruder } => {
+                //Adjacent objects on the same extruder don't swap the filament
+                if *extruder != current_extruder {
+                    //The filament is pulled back by the toolchange length, counting a retraction
+                    //that was already done, and the new extruder is left in the same state
+                    let swap_length = settings.toolchange_retract_length
+                        - if retracted {
+                            settings.retract_length
+                        } else {
+                            0.0
+                        };
+
+                    writeln!(
+                        writer,
+                        "G1 E{:.5} F{:.5}; Retract for toolchange",
+                        -swap_length,
+                        60.0 * settings.retract_speed,
+                    )?;
+                    writeln!(writer, "T{} ; change extruder", extruder)?;
+                    nozzle_temp = toolchange_temp(&cmds[index..], *extruder, settings);
+                    writeln!(
+                        writer,
+                        "{}",
+                        extruder_wait_command(nozzle_temp, *extruder, settings)
+                    )?;
+                    writeln!(
+                        writer,
+                        "G1 E{:.5} F{:.5}; Prime after toolchange",
+                        swap_length,
+                        60.0 * settings.retract_speed,
+                    )?;
+
+                    current_extruder = *extruder;
+                }
+
                 let previous_object = std::mem::replace(&mut current_object, Some(*object));
                 writeln!(
                     writer,
@@ -505,11 +1065,11 @@ pub fn write_gcode(
                         previous_object,
                         current_object,
                         settings
-                    )
+                    )?
                 )?;
             }
             Command::NoAction => {
-                panic!("Converter reached a No Action Command, Optimization Failure")
+                unreachable!("No action commands are rejected by the validation")
             }
             Command::ChangeType { print_type } => {
                 writeln!(writer, ";TYPE:{}", print_type)?;
@@ -524,7 +1084,7 @@ pub fn write_gcode(
         None,
         current_object,
         settings,
-    );
+    )?;
 
     writeln!(writer, "{}", end)?;
 
@@ -535,6 +1095,227 @@ pub fn write_gcode(
     Ok(navigator)
 }
 
+///Heights of a spiralized print, indexed like the commands. Each layer of an object rises from the
+///height of the layer below to its own height over the length of its extrusions, so a layer change
+///holds the height the layer starts at and each extrusion the height it ends at. The first layer
+///of each object is printed flat.
+fn spiral_heights(cmds: &[Command]) -> Vec<Option<f32>> {
+    let mut heights = vec![None; cmds.len()];
+    let mut previous_heights: HashMap<usize, f32> = HashMap::new();
+    let mut object = 0;
+
+    for (index, cmd) in cmds.iter().enumerate() {
+        match cmd {
+            Command::ChangeObject {
+                object: new_object, ..
+            } => object = *new_object,
+            Command::LayerChange { z, .. } => {
+                let Some(previous) = previous_heights.insert(object, *z) else {
+                    continue;
+                };
+
+                //The layer lasts until the next layer or object starts
+                let extrusions: Vec<(usize, f32)> = cmds
+                    .iter()
+                    .enumerate()
+                    .skip(index + 1)
+                    .take_while(|(_, cmd)| {
+                        !matches!(
+                            cmd,
+                            Command::LayerChange { .. } | Command::ChangeObject { .. }
+                        )
+                    })
+                    .filter_map(|(at, cmd)| match cmd {
+                        Command::MoveAndExtrude { start, end, .. } => {
+                            Some((at, (end.x - start.x).hypot(end.y - start.y)))
+                        }
+                        Command::Arc {
+                            start, end, center, ..
+                        } => Some((at, arc_length(*start, *end, *center))),
+                        _ => None,
+                    })
+                    .collect();
+
+                let total: f32 = extrusions.iter().map(|(_, length)| length).sum();
+                if total <= 0.0 {
+                    continue;
+                }
+
+                heights[index] = Some(previous);
+
+                let mut printed = 0.0;
+                for (at, length) in extrusions {
+                    printed += length;
+                    heights[at] = Some(previous + (z - previous) * printed / total);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    heights
+}
+
+///Length of an arc from its chord and radius
+fn arc_length(start: Coord<f32>, end: Coord<f32>, center: Coord<f32>) -> f32 {
+    let chord = (end.x - start.x).hypot(end.y - start.y);
+    let radius = (end.x - center.x).hypot(end.y - center.y);
+
+    //The central angle is double the inverse sine of the chord over the diameter
+    let central = (chord / (2.0 * radius)).asin() * 2.0;
+    central * radius
+}
+
+///The bed mesh on a layer it still applies to, faded out by the layers distance from the bed
+struct MeshLayer<'a> {
+    mesh: &'a HeightMap,
+    fade: f32,
+}
+
+impl<'a> MeshLayer<'a> {
+    fn new(settings: &'a Settings, layer: usize) -> Option<Self> {
+        if !settings.bed_mesh.is_enabled() || layer >= settings.bed_mesh_fade_layers {
+            return None;
+        }
+
+        Some(MeshLayer {
+            mesh: &settings.bed_mesh,
+            fade: 1.0 - layer as f32 / settings.bed_mesh_fade_layers as f32,
+        })
+    }
+
+    fn offset_at(&self, point: Vec2) -> f32 {
+        self.mesh.height_at(point.x, point.y) * self.fade
+    }
+}
+
+///Write an extrusion in pieces short enough to follow the bed mesh, sharing the extrusion evenly
+///between them. `z` holds the heights at the start and the end, which differ for a spiralized
+///wall. Returns the offset of the mesh at the end of the extrusion.
+fn write_meshed_extrusion(
+    writer: &mut dyn WriteGCode,
+    start: Vec2,
+    end: Vec2,
+    z: (f32, f32),
+    extrude: f32,
+    fiber: bool,
+    mesh: &MeshLayer,
+) -> std::io::Result<f32> {
+    let pieces = ((start.distance(end) / mesh.mesh.max_segment_length()).ceil() as usize).max(1);
+    let extrude = extrude / pieces as f32;
+
+    let mut offset = 0.0;
+    for piece in 1..=pieces {
+        let t = piece as f32 / pieces as f32;
+        let point = start.lerp(end, t);
+        let z = z.0 + (z.1 - z.0) * t;
+        offset = mesh.offset_at(point);
+
+        if fiber {
+            writeln!(
+                writer,
+                "G1 X{:.5} Y{:.5} Z{:.5} E{:.5} D{:.5}",
+                point.x,
+                point.y,
+                z + offset,
+                extrude,
+                extrude
+            )?;
+        } else {
+            writeln!(
+                writer,
+                "G1 X{:.5} Y{:.5} Z{:.5} E{:.5}",
+                point.x,
+                point.y,
+                z + offset,
+                extrude
+            )?;
+        }
+    }
+
+    Ok(offset)
+}
+
+///The temperature an extruder changed to at the start of `cmds` prints at. That is the first
+///temperature set before it extrudes, which holds the temperature changes of the layer, or else
+///the temperature of its filament.
+fn toolchange_temp(cmds: &[Command], extruder: usize, settings: &Settings) -> f32 {
+    cmds.iter()
+        .take_while(|cmd| {
+            !matches!(
+                cmd,
+                Command::MoveAndExtrude { .. }
+                    | Command::MoveAndExtrudeFiber { .. }
+                    | Command::MoveAndExtrudeFiberAndCut { .. }
+                    | Command::Arc { .. }
+            )
+        })
+        .find_map(|cmd| match cmd {
+            Command::SetState { new_state } => new_state.extruder_temp,
+            _ => None,
+        })
+        .unwrap_or(settings.extruder_filament(extruder).extruder_temp)
+}
+
+fn extruder_temp_command(temp: f32, extruder: usize, settings: &Settings) -> String {
+    match settings.gcode_flavor {
+        GcodeFlavor::Marlin => format!("M104 S{:.1} ; set extruder temp", temp),
+        GcodeFlavor::RepRapFirmware => {
+            format!("G10 P{} S{:.1} ; set extruder temp", extruder, temp)
+        }
+    }
+}
+
+fn extruder_wait_command(temp: f32, extruder: usize, settings: &Settings) -> String {
+    match settings.gcode_flavor {
+        GcodeFlavor::Marlin => format!("M109 S{:.1} T{} ; wait for extruder temp", temp, extruder),
+        GcodeFlavor::RepRapFirmware => format!(
+            "G10 P{} S{:.1} ; set extruder temp\nM116 P{} ; wait for extruder temp",
+            extruder, temp, extruder
+        ),
+    }
+}
+
+///Pause for the time, on standby if the pause is long enough. Printing only continues once the
+///nozzle is back at the printing temperature.
+fn write_dwell(
+    writer: &mut dyn WriteGCode,
+    msec: u64,
+    nozzle_temp: f32,
+    extruder: usize,
+    settings: &Settings,
+) -> std::io::Result<()> {
+    let standby = settings.standby_temperature(msec, nozzle_temp);
+
+    if let Some(standby) = standby {
+        writeln!(
+            writer,
+            "{}",
+            extruder_temp_command(standby, extruder, settings)
+        )?;
+    }
+
+    writeln!(writer, "G4 P{}", msec)?;
+
+    if standby.is_some() {
+        writeln!(
+            writer,
+            "{}",
+            extruder_wait_command(nozzle_temp, extruder, settings)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn acceleration_command(accel: f32, settings: &Settings) -> String {
+    match settings.gcode_flavor {
+        GcodeFlavor::Marlin => format!("M204 S{:.1}", accel),
+        //RepRapFirmware ignores S and sets printing and travel acceleration separately
+        GcodeFlavor::RepRapFirmware => format!("M204 P{:.1} T{:.1}", accel, accel),
+    }
+}
+
 fn convert_instructions(
     mut instructions: String,
     current_z_height: f32,
@@ -542,7 +1323,7 @@ fn convert_instructions(
     previous_object: Option<usize>,
     current_object: Option<usize>,
     settings: &Settings,
-) -> String {
+) -> Result<String, SlicerErrors> {
     let layer_settings = settings.get_layer_settings(layer_count, current_z_height);
 
     instructions = instructions.replace(
@@ -573,5 +1354,896 @@ fn convert_instructions(
             .unwrap_or_default(),
     );
 
-    instructions
+    placeholder::substitute_setting_placeholders(&instructions, settings)
+}
+
+///Strip characters that would end the message early or be read as a comment or checksum
+fn sanitize_message(message: &str) -> String {
+    message
+        .chars()
+        .map(|c| {
+            if c == ';' || c == '*' || !(c.is_ascii_graphic() || c == ' ') {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+///Cross section in mm² of a bead, a rectangle with rounded sides
+pub(crate) fn bead_area(width: f32, thickness: f32) -> f32 {
+    ((width - thickness) * thickness)
+        + (std::f32::consts::PI * (thickness / 2.0) * (thickness / 2.0))
+}
+
+///Length of filament in mm extruded for `length` mm of a bead with the cross section `area`
+pub(crate) fn filament_length(area: f32, length: f32, settings: &Settings) -> f32 {
+    let filament_area =
+        (std::f32::consts::PI * settings.filament.diameter * settings.filament.diameter) / 4.0;
+    (area * length) / filament_area
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::FilamentSettings, MoveId, StateChange, TraceType, UnknownPlaceholderPolicy,
+    };
+
+    fn extrude(id: usize, x: f32) -> Command {
+        Command::MoveAndExtrude {
+            id: Some(MoveId(id)),
+            start: Coord { x: 0.0, y: 0.0 },
+            end: Coord { x, y: 0.0 },
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        }
+    }
+
+    fn retract() -> Command {
+        Command::SetState {
+            new_state: StateChange {
+                retract: RetractionType::Retract,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn gcode(settings: &Settings) -> String {
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            extrude(0, 10.0),
+            retract(),
+            Command::MoveTo {
+                end: Coord { x: 0.0, y: 0.0 },
+            },
+            extrude(1, 10.0),
+            retract(),
+        ];
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, settings, &mut writer).unwrap();
+
+        writer.finish(navigator).gcode
+    }
+
+    fn word(line: &str, prefix: char) -> Option<f32> {
+        line.split([' ', ';'])
+            .find_map(|word| word.strip_prefix(prefix)?.parse().ok())
+    }
+
+    #[test]
+    fn bed_transform_flip_y() {
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::MoveTo {
+                end: Coord { x: 20.0, y: 30.0 },
+            },
+            extrude(0, 50.0),
+            Command::SetState {
+                new_state: StateChange {
+                    retract: RetractionType::MoveRetract(vec![(0.5, Coord { x: 40.0, y: 5.0 })]),
+                    ..Default::default()
+                },
+            },
+            Command::Arc {
+                id: None,
+                start: Coord { x: 40.0, y: 5.0 },
+                end: Coord { x: 50.0, y: 15.0 },
+                center: Coord { x: 50.0, y: 5.0 },
+                clockwise: true,
+                width: 0.4,
+                thickness: 0.2,
+            },
+        ];
+
+        let write = |settings: &Settings| {
+            let mut writer = mem::GCodeMemoryWriter::new();
+            let navigator = write_gcode(&cmds, settings, &mut writer).unwrap();
+            writer.finish(navigator).gcode
+        };
+
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            ..Default::default()
+        };
+        let plain = write(&settings);
+        settings.bed_transform.flip_y = true;
+        let flipped = write(&settings);
+
+        let mut moves = 0;
+        for (plain, flipped) in plain
+            .lines()
+            .zip(flipped.lines())
+            .filter(|(plain, _)| plain.starts_with('G'))
+        {
+            if let (Some(x), Some(y)) = (word(plain, 'X'), word(plain, 'Y')) {
+                assert_eq!(word(flipped, 'X'), Some(x));
+                let flipped_y = word(flipped, 'Y').unwrap();
+                assert!((flipped_y - (settings.print_y - y)).abs() < 0.001);
+                moves += 1;
+            }
+
+            if let Some(j) = word(plain, 'J') {
+                assert!(plain.starts_with("G2") && flipped.starts_with("G3"));
+                assert_eq!(word(flipped, 'I'), word(plain, 'I'));
+                assert!((word(flipped, 'J').unwrap() + j).abs() < 0.001);
+            }
+        }
+
+        assert_eq!(moves, 4);
+
+        let transform = &settings.bed_transform;
+        let center = vec2(settings.print_x / 2.0, settings.print_y / 2.0);
+        let point = vec2(12.0, 34.0);
+        assert!(
+            (transform.invert(transform.apply(point, center), center) - point).length() < 0.001
+        );
+    }
+
+    #[test]
+    fn quarter_turn_keeps_the_print_on_a_long_bed() {
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::MoveTo {
+                end: Coord { x: 260.0, y: 2.0 },
+            },
+            Command::MoveAndExtrude {
+                id: Some(MoveId(0)),
+                start: Coord { x: 260.0, y: 2.0 },
+                end: Coord { x: 300.0, y: 6.0 },
+                thickness: 0.2,
+                width: 0.4,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            print_x: 300.0,
+            print_y: 200.0,
+            ..Default::default()
+        };
+        settings.bed_transform.rotate_90_steps = 1;
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let gcode = writer.finish(navigator).gcode;
+
+        //Turned around its center at (280, 4) the print would span -16 to 24 in Y, so it moves
+        //16 mm up onto the bed
+        let points: Vec<(f32, f32)> = gcode
+            .lines()
+            .filter(|line| line.starts_with('G'))
+            .filter_map(|line| Some((word(line, 'X')?, word(line, 'Y')?)))
+            .collect();
+        assert_eq!(points.len(), 2);
+        for ((x, y), (expected_x, expected_y)) in
+            points.into_iter().zip([(282.0, 0.0), (278.0, 40.0)])
+        {
+            assert!((x - expected_x).abs() < 0.001, "{}", x);
+            assert!((y - expected_y).abs() < 0.001, "{}", y);
+        }
+    }
+
+    #[test]
+    fn final_retract_no_lift() {
+        let mut settings = Settings {
+            ending_instructions: "M84 ; end".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(gcode(&settings).matches("; z Lift").count(), 2);
+
+        settings.final_retract_no_lift = true;
+        let output = gcode(&settings);
+
+        assert_eq!(output.matches("; Retract").count(), 2);
+        assert_eq!(output.matches("; z Lift").count(), 1);
+        assert!(output.rfind("; z Lift").unwrap() < output.rfind("; Retract").unwrap());
+        assert!(output.trim_end().ends_with("M84 ; end"));
+    }
+
+    #[test]
+    fn witness_point_at_closest_layer() {
+        let settings = Settings {
+            witness_points: vec![WitnessPoint {
+                x: 10.0,
+                y: 20.0,
+                z: 0.42,
+                message: "Check; infill*\nnow".to_string(),
+                dwell: 500,
+            }],
+            ..Default::default()
+        };
+
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            extrude(0, 10.0),
+            Command::LayerChange { z: 0.4, index: 1 },
+            extrude(1, 10.0),
+            Command::LayerChange { z: 0.6, index: 2 },
+            extrude(2, 10.0),
+        ];
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        let message = output.find("M117 Check  infill  now\n").unwrap();
+        assert_eq!(output.matches("M117").count(), 1);
+        assert!(output.find(";LAYER:1").unwrap() < message);
+        assert!(message < output.find(";LAYER:2").unwrap());
+        assert!(output[message..].lines().nth(1) == Some("G4 P500"));
+    }
+
+    #[test]
+    fn layer_hooks_fire_after_the_layer_change() {
+        let settings = Settings {
+            after_layer_change_instructions: "; after change".to_string(),
+            layer_gcode_hooks: vec![
+                (
+                    crate::LayerRange::HeightRange {
+                        start: 0.5,
+                        end: 0.9,
+                    },
+                    "M600 ; at [Z Position]".to_string(),
+                ),
+                (crate::LayerRange::SingleLayer(0), "M0 ; first".to_string()),
+            ],
+            ..Default::default()
+        };
+
+        let cmds: Vec<Command> = (0..6)
+            .flat_map(|layer| {
+                vec![
+                    Command::LayerChange {
+                        z: 0.2 * (layer + 1) as f32,
+                        index: layer,
+                    },
+                    extrude(layer, 10.0),
+                ]
+            })
+            .collect();
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        //Every layer from 0.6 to 0.8 mm
+        let layers: Vec<&str> = output.split(";LAYER:").skip(1).collect();
+        let hooked: Vec<usize> = layers
+            .iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.contains("M600"))
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(hooked, vec![2, 3]);
+        assert!(layers[2].contains("M600 ; at 0.60000"));
+
+        for index in [0, 2, 3] {
+            let hook = layers[index].find("M600").or(layers[index].find("M0 ;"));
+            assert!(layers[index].find("; after change") < hook);
+        }
+        assert_eq!(output.matches("M0 ; first").count(), 1);
+    }
+
+    #[test]
+    fn layer_hooks_fire_once_per_layer_of_several_objects() {
+        let settings = Settings {
+            object_change_instructions: String::new(),
+            layer_gcode_hooks: vec![(crate::LayerRange::SingleLayer(1), "M600".to_string())],
+            ..Default::default()
+        };
+
+        //Printing by layer, both objects change to every layer
+        let cmds: Vec<Command> = (0..3)
+            .flat_map(|layer| {
+                (0..2).flat_map(move |object| {
+                    vec![
+                        Command::ChangeObject {
+                            object,
+                            extruder: 0,
+                        },
+                        Command::LayerChange {
+                            z: 0.2 * (layer + 1) as f32,
+                            index: layer,
+                        },
+                        extrude(layer, 10.0),
+                    ]
+                })
+            })
+            .collect();
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        assert_eq!(output.matches("M600").count(), 1);
+    }
+
+    #[test]
+    fn resume_from_layer() {
+        let mut settings = Settings {
+            starting_instructions: "; start".to_string(),
+            resume_instructions: "; resume".to_string(),
+            ending_instructions: String::new(),
+            ..Default::default()
+        };
+        *settings.resume_from_layer.enabled_mut() = true;
+        *settings.resume_from_layer = 5;
+
+        let cmds: Vec<Command> = (0..8)
+            .flat_map(|layer| {
+                vec![
+                    Command::LayerChange {
+                        z: 0.2 * (layer + 1) as f32,
+                        index: layer,
+                    },
+                    Command::MoveTo {
+                        end: Coord { x: 5.0, y: 5.0 },
+                    },
+                    Command::SetState {
+                        new_state: StateChange {
+                            retract: RetractionType::Unretract,
+                            ..Default::default()
+                        },
+                    },
+                    extrude(layer, 10.0),
+                    retract(),
+                ]
+            })
+            .collect();
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        assert!(!output.contains("; start"));
+        assert!((0..5).all(|layer| !output.contains(&format!(";LAYER:{}\n", layer))));
+
+        let resume = output.find(";RESUME FROM LAYER:5").unwrap();
+        assert!(output[resume..].starts_with(";RESUME FROM LAYER:5\n; resume"));
+
+        //Nothing is extruded or moved before resuming
+        assert!(output[..resume]
+            .lines()
+            .all(|line| !line.starts_with("G1 ")));
+
+        let resumed: Vec<&str> = output[resume..].lines().collect();
+
+        //Approach from above, then lower onto the layer before extruding
+        let z_moves: Vec<(usize, f32)> = resumed
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with("G1 Z"))
+            .map(|(index, line)| (index, word(line, 'Z').unwrap()))
+            .collect();
+        assert!((z_moves[0].1 - 3.2).abs() < 0.0001);
+        assert!((z_moves[1].1 - 1.2).abs() < 0.0001);
+        let first_extrusion = resumed
+            .iter()
+            .position(|line| line.starts_with("G1 X") && line.contains(" E"))
+            .unwrap();
+        let first_travel = resumed
+            .iter()
+            .position(|line| line.starts_with("G1 X"))
+            .unwrap();
+        assert!(z_moves[0].0 < first_travel);
+        assert!(first_travel < z_moves[1].0 && z_moves[1].0 < first_extrusion);
+        assert!(resumed.iter().any(|line| line.starts_with("G1 Z1.40000")));
+
+        //Retracted before the first unretract, so the filament ends retracted as usual
+        let retraction: f32 = resumed
+            .iter()
+            .filter(|line| line.contains("Retract") || line.contains("Unretract"))
+            .filter_map(|line| word(line, 'E'))
+            .sum();
+        assert!((retraction + settings.retract_length).abs() < 0.0001);
+        assert_eq!(
+            resumed
+                .iter()
+                .filter(|line| line.starts_with("G1 X") && line.contains(" E"))
+                .count(),
+            3
+        );
+
+        //Objects printed one after another restart their layers, so the skip wouldn't match
+        settings.print_sequence = crate::PrintSequence::ByObject;
+        assert!(matches!(
+            settings.validate_settings(),
+            crate::SettingsValidationResult::Error(SlicerErrors::ResumeByObject)
+        ));
+    }
+
+    #[test]
+    fn reprap_firmware_flavor() {
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            gcode_flavor: GcodeFlavor::RepRapFirmware,
+            ..Default::default()
+        };
+        *settings.pressure_advance.enabled_mut() = true;
+        *settings.pressure_advance = 0.06;
+
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::SetState {
+                new_state: StateChange {
+                    extruder_temp: Some(215.0),
+                    acceleration: Some(800.0),
+                    ..Default::default()
+                },
+            },
+            Command::MoveAndExtrudeFiberAndCut {
+                id: Some(MoveId(0)),
+                start: Coord { x: 0.0, y: 0.0 },
+                end: Coord { x: 20.0, y: 0.0 },
+                thickness: 0.2,
+                width: 0.4,
+                cut_pos: 5.0,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+
+        let write = |settings: &Settings| {
+            let mut writer = mem::GCodeMemoryWriter::new();
+            let navigator = write_gcode(&cmds, settings, &mut writer).unwrap();
+            writer.finish(navigator).gcode
+        };
+
+        let output = write(&settings);
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(lines[0].starts_with("M201 "));
+        let feedrate = lines.iter().find(|line| line.starts_with("M203 ")).unwrap();
+        assert_eq!(
+            word(feedrate, 'X'),
+            Some(settings.maximum_feedrate_x * 60.0)
+        );
+        let limits = lines.iter().find(|line| line.starts_with("M204 ")).unwrap();
+        assert_eq!(word(limits, 'P'), Some(settings.max_acceleration_extruding));
+        assert_eq!(word(limits, 'R'), None);
+        assert!(lines.iter().any(|line| line.starts_with("M566 ")));
+        assert!(!output.contains("M205"));
+
+        assert!(output.contains("M572 D0 S0.0600"));
+        assert!(!output.contains("M900"));
+        assert!(output.contains("G10 P0 S215.0"));
+        assert!(!output.contains("M104"));
+        assert!(output.contains("M204 P800.0 T800.0"));
+
+        //Fiber moves keep their D axis and the cut
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("G1 X") && word(line, 'D').is_some()));
+        assert!(output.contains("M300; cut fiber"));
+
+        settings.gcode_flavor = GcodeFlavor::Marlin;
+        let output = write(&settings);
+
+        assert!(output.contains("M900 K0.0600"));
+        assert!(output.contains("M104 S215.0"));
+        assert!(output.contains("M204 S800.0"));
+        assert!(!output.contains("M572") && !output.contains("G10") && !output.contains("M566"));
+    }
+
+    #[test]
+    fn setting_placeholders_in_instructions() {
+        let mut settings = Settings {
+            ending_instructions: "M117 [Layer Count] [nozzle_diameter] [gcode_flavor]".to_string(),
+            ..Default::default()
+        };
+
+        let output = gcode(&settings);
+        assert!(output.contains("M117 0 0.8 Marlin"), "{}", output);
+
+        //Unknown placeholders are kept as written by default
+        settings.ending_instructions = "M117 [nozzle_size]".to_string();
+        let output = gcode(&settings);
+        assert!(output.contains("M117 [nozzle_size]"), "{}", output);
+
+        settings.unknown_placeholder_policy = UnknownPlaceholderPolicy::Error;
+        let err = write_gcode(&[], &settings, &mut mem::GCodeMemoryWriter::new())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("[nozzle_size]"), "{}", err);
+    }
+
+    #[test]
+    fn bed_mesh_raises_moves_over_raised_cells() {
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            ..Default::default()
+        };
+        *settings.bed_mesh.enabled_mut() = true;
+        settings.bed_mesh.heights = vec![
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+        ];
+
+        let line = |start: (f32, f32), end: (f32, f32)| Command::MoveAndExtrude {
+            id: Some(MoveId(0)),
+            start: start.into(),
+            end: end.into(),
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        };
+        let layer = |index: usize| {
+            vec![
+                Command::LayerChange {
+                    z: 0.2 * (index + 1) as f32,
+                    index,
+                },
+                Command::MoveTo {
+                    end: Coord { x: 0.0, y: 0.0 },
+                },
+                line((0.0, 0.0), (5.0, 0.0)),
+                Command::MoveTo {
+                    end: Coord { x: 5.0, y: 10.0 },
+                },
+                line((5.0, 10.0), (15.0, 10.0)),
+            ]
+        };
+        let cmds = [layer(0), layer(3)].concat();
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        let z_at = |layer: &str, x: f32, y: f32| {
+            output
+                .split(";LAYER:")
+                .find(|section| section.starts_with(layer))
+                .unwrap()
+                .lines()
+                .find(|line| word(line, 'X') == Some(x) && word(line, 'Y') == Some(y))
+                .and_then(|line| word(line, 'Z'))
+        };
+
+        //The long move is split at the raised grid point and follows it up
+        assert_eq!(z_at("0", 5.0, 0.0), Some(0.2));
+        assert!(
+            (z_at("0", 10.0, 10.0).unwrap() - 1.2).abs() < 1e-4,
+            "{}",
+            output
+        );
+        assert!(z_at("0", 10.0, 10.0) > z_at("0", 15.0, 10.0));
+
+        //The mesh has faded out by the layer at the fade height
+        assert_eq!(z_at("3", 10.0, 10.0), None);
+        assert_eq!(z_at("3", 15.0, 10.0), None);
+    }
+
+    #[test]
+    fn spiralized_arcs_and_meshed_moves_rise() {
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            spiralize: true,
+            ..Default::default()
+        };
+
+        let line = |start: (f32, f32), end: (f32, f32)| Command::MoveAndExtrude {
+            id: Some(MoveId(0)),
+            start: start.into(),
+            end: end.into(),
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        };
+        let layer = |index: usize| {
+            vec![
+                Command::LayerChange {
+                    z: 0.2 * (index + 1) as f32,
+                    index,
+                },
+                Command::MoveTo {
+                    end: Coord { x: 10.0, y: 0.0 },
+                },
+                Command::Arc {
+                    id: None,
+                    start: Coord { x: 10.0, y: 0.0 },
+                    end: Coord { x: -10.0, y: 0.0 },
+                    center: Coord { x: 0.0, y: 0.0 },
+                    clockwise: false,
+                    thickness: 0.2,
+                    width: 0.4,
+                },
+                line((-10.0, 0.0), (10.0, 0.0)),
+            ]
+        };
+        let cmds = [layer(0), layer(1)].concat();
+
+        let heights = |settings: &Settings| {
+            let mut writer = mem::GCodeMemoryWriter::new();
+            let navigator = write_gcode(&cmds, settings, &mut writer).unwrap();
+            let output = writer.finish(navigator).gcode;
+
+            output
+                .split(";LAYER:")
+                .find(|section| section.starts_with('1'))
+                .unwrap()
+                .lines()
+                .filter(|line| line.starts_with('G') && word(line, 'E').is_some())
+                .map(|line| word(line, 'Z'))
+                .collect::<Vec<_>>()
+        };
+
+        //Half the second layer is the arc, so it ends halfway up
+        let arc_length = std::f32::consts::PI * 10.0;
+        let halfway = 0.2 + 0.2 * arc_length / (arc_length + 20.0);
+        let plain = heights(&settings);
+        assert_eq!(plain.len(), 2);
+        assert!((plain[0].unwrap() - halfway).abs() < 1e-4, "{:?}", plain);
+        assert!((plain[1].unwrap() - 0.4).abs() < 1e-4, "{:?}", plain);
+
+        //On a flat mesh the pieces of the line keep rising between the two
+        *settings.bed_mesh.enabled_mut() = true;
+        settings.bed_mesh.heights = vec![vec![0.0; 5]; 5];
+        let meshed = heights(&settings)
+            .into_iter()
+            .map(|z| z.expect("Meshed moves carry their height"))
+            .collect::<Vec<_>>();
+        assert!(meshed.len() > 2, "{:?}", meshed);
+        assert!((meshed[0] - halfway).abs() < 1e-4, "{:?}", meshed);
+        assert!(
+            meshed.windows(2).all(|pair| pair[1] > pair[0]),
+            "{:?}",
+            meshed
+        );
+        assert!((meshed.last().unwrap() - 0.4).abs() < 1e-4, "{:?}", meshed);
+    }
+
+    #[test]
+    fn fitted_arcs_extrude_like_the_lines_they_replace() {
+        let point = |step: usize| {
+            let angle = 2.0 * step as f32 / 40.0;
+            Coord {
+                x: 100.0 + 20.0 * angle.cos(),
+                y: 100.0 + 20.0 * angle.sin(),
+            }
+        };
+        let lines: Vec<Command> = std::iter::once(Command::LayerChange { z: 0.2, index: 0 })
+            .chain((0..40).map(|step| Command::MoveAndExtrude {
+                id: Some(MoveId(step)),
+                start: point(step),
+                end: point(step + 1),
+                thickness: 0.2,
+                width: 0.4,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            }))
+            .collect();
+        let mut arcs = lines.clone();
+        crate::optimizer::arc_fit_optimizer(&mut arcs, 0.02);
+        crate::optimizer::unary_optimizer(&mut arcs);
+        assert!(matches!(arcs.as_slice(), [_, Command::Arc { .. }]));
+
+        let settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            ..Default::default()
+        };
+        let extruded = |cmds: &[Command]| -> f32 {
+            let mut writer = mem::GCodeMemoryWriter::new();
+            let navigator = write_gcode(cmds, &settings, &mut writer).unwrap();
+            writer
+                .finish(navigator)
+                .gcode
+                .lines()
+                .filter(|line| line.starts_with("G1 X") || line.starts_with("G3 "))
+                .filter_map(|line| word(line, 'E'))
+                .sum()
+        };
+
+        //The chords are a hair shorter than the arc, the bead is the same
+        let (lines, arcs) = (extruded(&lines), extruded(&arcs));
+        assert!((arcs - lines).abs() < 0.001 * lines, "{} {}", arcs, lines);
+    }
+
+    #[test]
+    fn toolchanges_only_between_different_extruders() {
+        let settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            object_change_instructions: String::new(),
+            extruders: vec![
+                FilamentSettings::default(),
+                FilamentSettings {
+                    extruder_temp: 240.0,
+                    density: 2.0,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let cmds: Vec<Command> = [(0, 0), (1, 1), (2, 1), (3, 0)]
+            .into_iter()
+            .flat_map(|(object, extruder)| {
+                vec![
+                    Command::ChangeObject { object, extruder },
+                    Command::LayerChange { z: 0.2, index: 0 },
+                    extrude(object, 10.0),
+                    retract(),
+                ]
+            })
+            .collect();
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        //Objects 1 and 2 share an extruder, so there is no change between them
+        let toolchanges: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with('T'))
+            .collect();
+        assert_eq!(
+            toolchanges,
+            ["T1 ; change extruder", "T0 ; change extruder"]
+        );
+        assert!(output.contains("M109 S240.0 T1"));
+
+        //The swap retraction tops up the retraction done before the change
+        let swap_length = settings.toolchange_retract_length - settings.retract_length;
+        let swaps: Vec<f32> = output
+            .lines()
+            .filter(|line| line.ends_with("Retract for toolchange"))
+            .filter_map(|line| word(line, 'E'))
+            .collect();
+        assert_eq!(swaps.len(), 2);
+        assert!(swaps.iter().all(|e| (e + swap_length).abs() < 1e-4));
+
+        //The plastic is weighed with the density of the extruder that used it
+        let values = crate::calculation::calculate_values(&cmds, &settings);
+        let volume = 2.0 * 10.0 * 0.4 * 0.2 / 1000.0;
+        assert_eq!(values.extruder_weights.len(), 2);
+        assert!((values.extruder_weights[0] - volume * 1.24).abs() < 1e-6);
+        assert!((values.extruder_weights[1] - volume * 2.0).abs() < 1e-6);
+        assert!((values.plastic_weight - volume * 3.24).abs() < 1e-6);
+    }
+
+    #[test]
+    fn long_pauses_cool_down_to_standby() {
+        let mut settings = Settings::default();
+        settings.filament.extruder_temp = 210.0;
+        *settings.standby_temp.enabled_mut() = true;
+        settings.standby_temp.temperature = 150.0;
+        settings.standby_temp.min_idle_time = 30.0;
+
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::SetState {
+                new_state: StateChange {
+                    movement_speed: Some(50.0),
+                    ..Default::default()
+                },
+            },
+            extrude(0, 10.0),
+            Command::Delay { msec: 5000 },
+            extrude(1, 20.0),
+            Command::Delay { msec: 60000 },
+            extrude(2, 30.0),
+        ];
+
+        let mut writer = mem::GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+        let lines: Vec<&str> = output.lines().collect();
+
+        //The short pause is left alone
+        let short = lines.iter().position(|line| *line == "G4 P5000").unwrap();
+        assert!(!lines[short - 1].starts_with("M104"));
+        assert!(!lines[short + 1].starts_with("M109"));
+
+        //The long pause cools down and waits for the nozzle to reheat before printing
+        let long = lines.iter().position(|line| *line == "G4 P60000").unwrap();
+        assert!(lines[long - 1].starts_with("M104 S150.0"));
+        assert!(lines[long + 1].starts_with("M109 S210.0"));
+        assert!(lines[long + 2..]
+            .iter()
+            .any(|line| line.starts_with("G1 X") && line.contains(" E")));
+
+        //Reheating is part of the time estimate
+        let with_standby = crate::calculation::calculate_values(&cmds, &settings).total_time;
+        *settings.standby_temp.enabled_mut() = false;
+        let without_standby = crate::calculation::calculate_values(&cmds, &settings).total_time;
+        assert!((with_standby - without_standby - 60.0 / 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn time_and_plastic_are_broken_down_per_trace_type() {
+        let settings = Settings::default();
+
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::SetState {
+                new_state: StateChange {
+                    movement_speed: Some(10.0),
+                    ..Default::default()
+                },
+            },
+            Command::ChangeType {
+                print_type: TraceType::WallOuter,
+            },
+            extrude(0, 20.0),
+            Command::MoveTo {
+                end: Coord { x: 0.0, y: 0.0 },
+            },
+            Command::ChangeType {
+                print_type: TraceType::Infill,
+            },
+            extrude(1, 10.0),
+            Command::MoveAndExtrudeFiber {
+                id: Some(MoveId(2)),
+                start: Coord { x: 10.0, y: 0.0 },
+                end: Coord { x: 30.0, y: 0.0 },
+                thickness: 0.2,
+                width: 0.4,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+
+        let values = crate::calculation::calculate_values(&cmds, &settings);
+        let filament_area = std::f32::consts::PI * (settings.nozzle_diameter / 2.0).powi(2);
+
+        let (wall_time, wall_length) = values.trace_breakdown[&TraceType::WallOuter];
+        assert!((wall_time - 2.0).abs() < 1e-4);
+        assert!((wall_length - 20.0 * 0.4 * 0.2 / filament_area).abs() < 1e-4);
+
+        //The fiber move takes time but its plastic isn't counted as plastic length
+        let (infill_time, infill_length) = values.trace_breakdown[&TraceType::Infill];
+        assert!((infill_time - 3.0).abs() < 1e-4);
+        assert!((infill_length - 10.0 * 0.4 * 0.2 / filament_area).abs() < 1e-4);
+        assert!((values.fiber_length - 20.0).abs() < 1e-4);
+        assert!((values.plastic_length - (wall_length + infill_length)).abs() < 1e-4);
+
+        assert!((values.travel_time - 2.0).abs() < 1e-4);
+        assert!((values.total_time - 7.0).abs() < 1e-4);
+    }
 }
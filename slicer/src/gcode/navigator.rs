@@ -12,6 +12,9 @@ struct MoveEntry {
 pub struct Navigator {
     layer_indices: Vec<usize>,
     move_mapping: HashMap<MoveId, MoveEntry>,
+
+    //The first line of every trace, recorded in the order they are written so sorted by line
+    trace_lines: Vec<(usize, MoveId)>,
 }
 
 impl Navigator {
@@ -19,6 +22,7 @@ impl Navigator {
         Self {
             layer_indices: Vec::new(),
             move_mapping: HashMap::with_capacity(move_capacity),
+            trace_lines: Vec::with_capacity(move_capacity),
         }
     }
 
@@ -34,6 +38,26 @@ impl Navigator {
         self.move_mapping.get(id).map(|o| o.layer)
     }
 
+    ///The trace a line of the gcode belongs to. A trace owns the lines from its first one up to
+    ///the next trace or layer change.
+    pub fn trace_at_line(&self, line: usize) -> Option<MoveId> {
+        let index = self
+            .trace_lines
+            .partition_point(|(trace_line, _)| *trace_line <= line);
+        let (trace_line, id) = *self.trace_lines.get(index.checked_sub(1)?)?;
+
+        let layer_change = self
+            .layer_indices
+            .partition_point(|layer_line| *layer_line <= line)
+            .checked_sub(1)
+            .map(|layer| self.layer_indices[layer]);
+
+        match layer_change {
+            Some(layer_line) if layer_line > trace_line => None,
+            _ => Some(id),
+        }
+    }
+
     pub(crate) fn record_layer_change(&mut self, line: usize) {
         self.layer_indices.push(line);
     }
@@ -46,5 +70,41 @@ impl Navigator {
                 layer: self.layer_indices.len() as u32,
             },
         );
+        self.trace_lines.push((line, id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_map_to_the_trace_written_there() {
+        let mut navigator = Navigator::new(3);
+        navigator.record_layer_change(0);
+        navigator.record_trace(MoveId(0), 2);
+        navigator.record_trace(MoveId(1), 5);
+        navigator.record_layer_change(8);
+        navigator.record_trace(MoveId(2), 10);
+
+        //Lines of the layer change before the first trace
+        assert_eq!(navigator.trace_at_line(0), None);
+        assert_eq!(navigator.trace_at_line(1), None);
+
+        assert_eq!(navigator.trace_at_line(2), Some(MoveId(0)));
+        assert_eq!(navigator.trace_at_line(4), Some(MoveId(0)));
+        assert_eq!(navigator.trace_at_line(5), Some(MoveId(1)));
+        assert_eq!(navigator.trace_at_line(7), Some(MoveId(1)));
+
+        //The next layer change ends the trace
+        assert_eq!(navigator.trace_at_line(8), None);
+        assert_eq!(navigator.trace_at_line(9), None);
+        assert_eq!(navigator.trace_at_line(10), Some(MoveId(2)));
+        assert_eq!(navigator.trace_at_line(1_000_000), Some(MoveId(2)));
+
+        for id in 0..3 {
+            let line = navigator.get_trace_index(&MoveId(id)).unwrap();
+            assert_eq!(navigator.trace_at_line(line), Some(MoveId(id)));
+        }
     }
 }
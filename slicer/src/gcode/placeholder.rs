@@ -0,0 +1,133 @@
+use log::warn;
+use serde_json::Value;
+
+use crate::{error::SlicerErrors, settings::Settings, UnknownPlaceholderPolicy};
+
+///Replace every placeholder that names a setting, like `[nozzle_diameter]`, with the value of the
+///setting. Nested settings are named with dots, like `[support.interface_spacing]`. Placeholders
+///that aren't setting names, like `[Z Position]`, are left as they are.
+pub fn substitute_setting_placeholders(
+    instructions: &str,
+    settings: &Settings,
+) -> Result<String, SlicerErrors> {
+    let mut values = None;
+    let mut substituted = String::with_capacity(instructions.len());
+    let mut rest = instructions;
+
+    while let Some(open) = rest.find('[') {
+        substituted.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+
+        let name = after
+            .find(']')
+            .map(|close| &after[..close])
+            .filter(|name| is_setting_name(name));
+
+        let Some(name) = name else {
+            substituted.push('[');
+            rest = after;
+            continue;
+        };
+
+        //Only serialize the settings when there is something to look up
+        let values =
+            values.get_or_insert_with(|| serde_json::to_value(settings).unwrap_or(Value::Null));
+
+        match lookup(values, name) {
+            Some(value) => substituted.push_str(&value),
+            None => match settings.unknown_placeholder_policy {
+                UnknownPlaceholderPolicy::Error => {
+                    return Err(SlicerErrors::UnknownPlaceholder {
+                        placeholder: name.to_string(),
+                    })
+                }
+                UnknownPlaceholderPolicy::KeepLiteral => {
+                    warn!(
+                        "Placeholder [{}] doesn't name a setting, kept as written",
+                        name
+                    );
+                    substituted.push('[');
+                    substituted.push_str(name);
+                    substituted.push(']');
+                }
+            },
+        }
+
+        rest = &after[name.len() + 1..];
+    }
+
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+fn is_setting_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+}
+
+///Format the setting at the dotted path, or None if the path doesn't lead to a single value
+fn lookup(values: &Value, path: &str) -> Option<String> {
+    let value = path
+        .split('.')
+        .try_fold(values, |value, key| optional_value(value).get(key))?;
+
+    match optional_value(value) {
+        //Settings are f32, print them without the noise of widening them to f64
+        Value::Number(number) if number.is_f64() => number.as_f64().map(|n| (n as f32).to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::String(string) => Some(string.clone()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+///Optional settings are stored with whether they are enabled, placeholders use the setting itself
+fn optional_value(value: &Value) -> &Value {
+    match value {
+        Value::Object(map) if map.len() == 2 && map.contains_key("enabled") => {
+            map.get("setting").unwrap_or(value)
+        }
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_substituted_by_name() {
+        let mut settings = Settings::default();
+        settings.nozzle_diameter = 0.6;
+        settings.print_x = 250.0;
+        settings.support.interface_spacing = 1.5;
+
+        let substituted = substitute_setting_placeholders(
+            "M117 [nozzle_diameter] mm\nG1 X[print_x] ; [Z Position] [support.interface_spacing]",
+            &settings,
+        )
+        .unwrap();
+
+        assert_eq!(substituted, "M117 0.6 mm\nG1 X250 ; [Z Position] 1.5");
+    }
+
+    #[test]
+    fn unknown_placeholders_follow_the_policy() {
+        let mut settings = Settings::default();
+
+        //Custom gcode can use brackets for anything, so they are kept unless asked otherwise
+        assert_eq!(
+            substitute_setting_placeholders("M117 [no_such_setting] [print_y]", &settings).unwrap(),
+            "M117 [no_such_setting] 210"
+        );
+
+        settings.unknown_placeholder_policy = UnknownPlaceholderPolicy::Error;
+        let err = substitute_setting_placeholders("M117 [no_such_setting]", &settings).unwrap_err();
+        assert!(matches!(
+            err,
+            SlicerErrors::UnknownPlaceholder { placeholder } if placeholder == "no_such_setting"
+        ));
+    }
+}
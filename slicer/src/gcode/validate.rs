@@ -0,0 +1,374 @@
+use geo::Coord;
+use glam::Vec2;
+
+use crate::{
+    error::{CommandViolation, SlicerErrors},
+    settings::Settings,
+    Command, PrintSequence, RetractionType,
+};
+
+///How far a move may end past the edge of the bed before it counts as outside
+const BED_TOLERANCE: f32 = 0.001;
+
+///Check the commands for the invariants the gcode writer relies on, so broken commands are
+///reported as an error instead of panicking or producing unprintable gcode. Every violation is
+///listed, not just the first one. Points are checked after `to_bed` moves them into the printers
+///frame, which has to be the transform the gcode is written with.
+pub fn validate_commands(
+    cmds: &[Command],
+    settings: &Settings,
+    to_bed: &impl Fn(f32, f32) -> Vec2,
+) -> Result<(), SlicerErrors> {
+    let mut violations = vec![];
+
+    let check_on_bed = |index: usize, point: &Coord<f32>| {
+        let point = to_bed(point.x, point.y);
+
+        let on_bed = (-BED_TOLERANCE..=settings.print_x + BED_TOLERANCE).contains(&point.x)
+            && (-BED_TOLERANCE..=settings.print_y + BED_TOLERANCE).contains(&point.y);
+
+        if !on_bed {
+            Some(CommandViolation::OutsideBed {
+                index,
+                x: point.x,
+                y: point.y,
+            })
+        } else {
+            None
+        }
+    };
+
+    let mut current_object = None;
+    let mut previous_z: Option<f32> = None;
+
+    for (index, cmd) in cmds.iter().enumerate() {
+        match cmd {
+            Command::NoAction => violations.push(CommandViolation::NoAction { index }),
+            Command::MoveAndExtrude { id, end, .. }
+            | Command::MoveAndExtrudeFiber { id, end, .. }
+            | Command::MoveAndExtrudeFiberAndCut { id, end, .. } => {
+                if id.is_none() {
+                    violations.push(CommandViolation::MissingId { index });
+                }
+                violations.extend(check_on_bed(index, end));
+            }
+            Command::MoveTo { end } => violations.extend(check_on_bed(index, end)),
+            Command::Arc { id, start, end, .. } => {
+                if id.is_none() {
+                    violations.push(CommandViolation::MissingId { index });
+                }
+                violations.extend(check_on_bed(index, start));
+                violations.extend(check_on_bed(index, end));
+            }
+            Command::SetState { new_state } => {
+                if let RetractionType::MoveRetract(moves) = &new_state.retract {
+                    for (_, end) in moves {
+                        violations.extend(check_on_bed(index, end));
+                    }
+                }
+            }
+            Command::ChangeObject { object, extruder } => {
+                if *extruder >= settings.extruders.len().max(1) {
+                    violations.push(CommandViolation::UnknownExtruder {
+                        index,
+                        extruder: *extruder,
+                    });
+                }
+
+                //Printing by object starts from the bed again for every object
+                if settings.print_sequence == PrintSequence::ByObject
+                    && current_object != Some(*object)
+                {
+                    previous_z = None;
+                }
+                current_object = Some(*object);
+            }
+            Command::LayerChange { z, .. } => {
+                if let Some(previous) = previous_z.filter(|previous| z < previous) {
+                    violations.push(CommandViolation::LayerBelowPrevious {
+                        index,
+                        z: *z,
+                        previous,
+                    });
+                }
+                previous_z = Some(*z);
+            }
+            Command::Delay { .. } | Command::ChangeType { .. } => {}
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(SlicerErrors::InvalidCommands { violations })
+    }
+}
+
+///Re-read written gcode and report every move with an argument that isn't a finite number or that
+///ends outside of the bed, as the index of the offending line and what is wrong with it. Only
+///lines from `bounds_from` on are checked against the bed, so the start instructions can prime
+///outside of the print area.
+pub fn verify_gcode(
+    gcode: &str,
+    line_breaks: &[usize],
+    bounds_from: usize,
+    settings: &Settings,
+) -> Result<(), Vec<(usize, String)>> {
+    let mut errors = vec![];
+
+    let starts = std::iter::once(0).chain(line_breaks.iter().map(|line_break| line_break + 1));
+    let ends = line_breaks
+        .iter()
+        .copied()
+        .chain(std::iter::once(gcode.len()));
+
+    for (line, (start, end)) in starts.zip(ends).enumerate() {
+        let code = gcode[start..end].split(';').next().unwrap_or_default();
+        let mut words = code.split_whitespace();
+
+        if !matches!(words.next(), Some("G0" | "G1" | "G2" | "G3")) {
+            continue;
+        }
+
+        for word in words {
+            let mut chars = word.chars();
+            let axis = chars.next().filter(|axis| axis.is_ascii_uppercase());
+            let value = chars.as_str().parse::<f32>().ok();
+
+            let (axis, value) = match (axis, value) {
+                (Some(axis), Some(value)) => (axis, value),
+                _ => {
+                    errors.push((line, format!("invalid argument `{}`", word)));
+                    continue;
+                }
+            };
+
+            if !value.is_finite() {
+                errors.push((line, format!("non-finite {} value {}", axis, value)));
+                continue;
+            }
+
+            let limit = match axis {
+                'X' => settings.print_x,
+                'Y' => settings.print_y,
+                _ => continue,
+            };
+
+            if line >= bounds_from && !(-BED_TOLERANCE..=limit + BED_TOLERANCE).contains(&value) {
+                errors.push((line, format!("{} {} is outside of the bed", axis, value)));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gcode::{bed_transform, mem::GCodeMemoryWriter, write_gcode, SlicedGCode},
+        MoveId, StateChange,
+    };
+
+    fn extrude(id: Option<usize>, x: f32, y: f32) -> Command {
+        Command::MoveAndExtrude {
+            id: id.map(MoveId),
+            start: Coord { x: 0.0, y: 0.0 },
+            end: Coord { x, y },
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        }
+    }
+
+    fn violations(cmds: &[Command], settings: &Settings) -> Vec<CommandViolation> {
+        match validate_commands(cmds, settings, &bed_transform(cmds, settings)) {
+            Ok(()) => vec![],
+            Err(SlicerErrors::InvalidCommands { violations }) => violations,
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    fn layer(object: usize, z: f32) -> Vec<Command> {
+        vec![
+            Command::ChangeObject {
+                object,
+                extruder: 0,
+            },
+            Command::LayerChange { z, index: 0 },
+            extrude(Some(0), 10.0, 10.0),
+        ]
+    }
+
+    #[test]
+    fn valid_commands_pass() {
+        let cmds = [layer(0, 0.2), layer(1, 0.2), layer(0, 0.4), layer(1, 0.4)].concat();
+
+        assert_eq!(violations(&cmds, &Settings::default()), vec![]);
+    }
+
+    #[test]
+    fn no_action_is_reported() {
+        let mut cmds = layer(0, 0.2);
+        cmds.push(Command::NoAction);
+
+        assert_eq!(
+            violations(&cmds, &Settings::default()),
+            vec![CommandViolation::NoAction { index: 3 }]
+        );
+
+        //The writer returns the violation instead of panicking
+        let err = write_gcode(&cmds, &Settings::default(), &mut GCodeMemoryWriter::new())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("command 3 was not optimized out"), "{}", err);
+    }
+
+    #[test]
+    fn missing_id_is_reported() {
+        let mut cmds = layer(0, 0.2);
+        cmds.push(extrude(None, 20.0, 10.0));
+        cmds.push(Command::Arc {
+            id: None,
+            start: Coord { x: 20.0, y: 10.0 },
+            end: Coord { x: 30.0, y: 10.0 },
+            center: Coord { x: 25.0, y: 10.0 },
+            clockwise: true,
+            thickness: 0.2,
+            width: 0.4,
+        });
+
+        assert_eq!(
+            violations(&cmds, &Settings::default()),
+            vec![
+                CommandViolation::MissingId { index: 3 },
+                CommandViolation::MissingId { index: 4 }
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_below_previous_is_reported() {
+        let cmds = [layer(0, 0.2), layer(0, 0.4), layer(1, 0.2)].concat();
+
+        let mut settings = Settings::default();
+        assert_eq!(
+            violations(&cmds, &settings),
+            vec![CommandViolation::LayerBelowPrevious {
+                index: 7,
+                z: 0.2,
+                previous: 0.4
+            }]
+        );
+
+        //Each object starts from the bed when printing by object
+        settings.print_sequence = PrintSequence::ByObject;
+        assert_eq!(violations(&cmds, &settings), vec![]);
+    }
+
+    #[test]
+    fn moves_outside_bed_are_reported() {
+        let settings = Settings::default();
+        let mut cmds = layer(0, 0.2);
+        cmds.push(Command::MoveTo {
+            end: Coord {
+                x: settings.print_x + 5.0,
+                y: 10.0,
+            },
+        });
+        cmds.push(Command::SetState {
+            new_state: StateChange {
+                retract: RetractionType::MoveRetract(vec![(0.5, Coord { x: 10.0, y: -1.0 })]),
+                ..Default::default()
+            },
+        });
+
+        assert_eq!(
+            violations(&cmds, &settings),
+            vec![
+                CommandViolation::OutsideBed {
+                    index: 3,
+                    x: settings.print_x + 5.0,
+                    y: 10.0
+                },
+                CommandViolation::OutsideBed {
+                    index: 4,
+                    x: 10.0,
+                    y: -1.0
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn travels_stay_on_the_bed_after_a_quarter_turn() {
+        let mut settings = Settings {
+            starting_instructions: String::new(),
+            ending_instructions: String::new(),
+            print_x: 300.0,
+            print_y: 200.0,
+            ..Default::default()
+        };
+        settings.bed_transform.rotate_90_steps = 1;
+
+        //Turned around the center of the extrusion alone the travel would end 10 mm off the bed
+        let cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::MoveTo {
+                end: Coord { x: 250.0, y: 4.0 },
+            },
+            Command::MoveAndExtrude {
+                id: Some(MoveId(0)),
+                start: Coord { x: 260.0, y: 2.0 },
+                end: Coord { x: 300.0, y: 6.0 },
+                thickness: 0.2,
+                width: 0.4,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+
+        assert_eq!(violations(&cmds, &settings), vec![]);
+
+        let mut writer = GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        assert_eq!(writer.finish(navigator).verify(&settings), Ok(()));
+    }
+
+    #[test]
+    fn corrupted_gcode_is_reported_by_line() {
+        let settings = Settings::default();
+        let cmds = layer(0, 0.2);
+
+        let mut writer = GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let sliced = writer.finish(navigator);
+        assert_eq!(sliced.verify(&settings), Ok(()));
+
+        let mut lines: Vec<String> = sliced.gcode.lines().map(String::from).collect();
+        let extrusion = lines
+            .iter()
+            .position(|line| line.starts_with("G1 X") && line.contains(" E"))
+            .unwrap();
+        lines[extrusion] = "G1 XNaN Y10.00000 E0.1".to_string();
+        lines.push(format!("G1 X{} Y10.0", settings.print_x + 5.0));
+        lines.push("G1 X10.0 Y".to_string());
+
+        let corrupted = SlicedGCode::new(lines.join("\n"), sliced.navigator);
+        let errors = corrupted.verify(&settings).unwrap_err();
+        let lines_with_errors: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(
+            lines_with_errors,
+            vec![extrusion, lines.len() - 2, lines.len() - 1]
+        );
+        assert!(errors[0].1.contains("non-finite X"), "{}", errors[0].1);
+    }
+}
@@ -1,21 +1,26 @@
 mod settings;
 
-use command_pass::{CommandPass, OptimizePass, SlowDownLayerPass};
+use command_pass::{ArcFitPass, CommandPass, OptimizePass, SlowDownLayerPass, StandbyTravelPass};
 use glam::Vec3;
 use mask::ObjectMask;
-use plotter::{convert_objects_into_moves, polygon_operations::PolygonOperations};
+use plotter::{
+    convert_object_into_moves, flatten_object_moves, polygon_operations::PolygonOperations,
+    ObjectMoves,
+};
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 pub use settings::*;
-use shared::{process::Process, SliceInput};
+use shared::loader::{FileLoader, LoadError, STLLoader};
+use shared::{object::ObjectMesh, SliceInput};
 use slice_pass::*;
 use strum_macros::{EnumIter, EnumString};
-use tower::create_towers;
+use tower::{create_towers, TriangleTower};
 
 mod calculation;
 mod command_pass;
 mod error;
 pub mod gcode;
 mod mask;
+mod modifier;
 mod r#move;
 mod optimizer;
 mod plotter;
@@ -27,56 +32,305 @@ mod warning;
 
 pub use gcode::SlicedGCode;
 pub use mask::Mask;
+pub use modifier::ModifierMesh;
+pub use plotter::clip_lines_to_polygon;
 
 pub use r#move::*;
+pub use warning::SlicerWarnings;
 
-use error::SlicerErrors;
+pub use error::SlicerErrors;
 use geo::{
     Contains, Coord, LineString, MultiLineString, MultiPolygon, Polygon, SimplifyVw,
     SimplifyVwPreserve,
 };
+///Progress of a slice, `Process::default()` keeps track of it without reporting it anywhere
+pub use shared::process::Process;
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
-#[derive(Debug)]
+///Version of the `SliceReport` schema, increased whenever its layout changes
+pub const SLICE_REPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
 pub struct SliceResult {
     pub moves: Vec<Command>,
     pub calculated_values: CalculatedValues,
     pub settings: Settings,
+    pub warnings: Vec<SlicerWarnings>,
+
+    object_moves: Vec<ObjectMoves>,
+    object_outlines: Vec<slicing::ObjectOutline>,
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+}
+
+impl SliceResult {
+    ///Iterate over every deposited bead of the slice
+    pub fn extrusion_segments(&self) -> impl Iterator<Item = ExtrusionSegment> + '_ {
+        r#move::extrusion_segments(&self.moves)
+    }
+
+    ///A snapshot of the settings, calculated values and warnings of this slice for record keeping
+    pub fn report(&self) -> SliceReport {
+        SliceReport {
+            version: SLICE_REPORT_VERSION,
+            settings: self.settings.clone(),
+            calculated_values: self.calculated_values.clone(),
+            warnings: self.warnings.clone(),
+        }
+    }
+
+    ///The slice with settings that only differ from its own in `GCODE_ONLY_SETTINGS`, so the moves
+    ///stay the same and only the written gcode changes
+    pub fn with_settings(mut self, settings: &Settings) -> Self {
+        self.calculated_values = calculation::calculate_values(&self.moves, settings);
+        self.settings = settings.clone();
+        self
+    }
+
+    ///Write every move of the slice as a json array, with the coordinates on the bed, the trace
+    ///type, the bead size and the extruded filament length of each move
+    pub fn export_moves_json<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        gcode::export::write_moves_json(&self.moves, &self.settings, writer)
+    }
+
+    ///The z range of a partial slice, None if the whole model was sliced
+    pub fn z_range(&self) -> Option<(f32, f32)> {
+        self.z_range
+    }
+
+    ///Write the gcode of the slice to the file at `path`, replacing it if it exists. Partial
+    ///slices are only previews and can't be written.
+    pub fn write_gcode_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SlicerErrors> {
+        if let Some((min, max)) = self.z_range {
+            return Err(SlicerErrors::PartialSlice { min, max });
+        }
+
+        let filepath = path.as_ref().display().to_string();
+        let write_error = || SlicerErrors::FileWriteError {
+            filepath: filepath.clone(),
+        };
+
+        let file = File::create(&path).map_err(|_| SlicerErrors::FileCreateError {
+            filepath: filepath.clone(),
+        })?;
+        let mut writer = BufWriter::new(file);
+
+        gcode::write_gcode(
+            &self.moves,
+            &self.settings,
+            &mut gcode::GCodeFileWriter::new(&mut writer),
+        )
+        .map_err(|err| {
+            err.downcast::<SlicerErrors>()
+                .map_or_else(|_| write_error(), |err| *err)
+        })?;
+
+        writer.flush().map_err(|_| write_error())
+    }
+}
+
+///Structured summary of a slice that can be saved or shared as json/hjson
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SliceReport {
+    ///The `SLICE_REPORT_VERSION` the report was created with
+    pub version: u32,
+    pub settings: Settings,
+    pub calculated_values: CalculatedValues,
+    pub warnings: Vec<SlicerWarnings>,
+}
+
+///Key of the slice of the input, equal for inputs that slice into the same moves. Meshes have to
+///match exactly, and of the settings only `GCODE_ONLY_SETTINGS` may differ.
+pub fn slice_key(input: &SliceInput<Mask, ModifierMesh>, settings: &Settings) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    settings.geometry_hash().hash(&mut hasher);
+
+    input.objects.hash(&mut hasher);
+    for mask in &input.masks {
+        (**mask).hash(&mut hasher);
+        serde_json::to_string(mask.settings())
+            .expect("Mask settings serialize to json")
+            .hash(&mut hasher);
+    }
+    for modifier in &input.modifiers {
+        (**modifier).hash(&mut hasher);
+        serde_json::to_string(modifier.settings())
+            .expect("Partial settings serialize to json")
+            .hash(&mut hasher);
+        modifier.priority().hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+fn max_point(objects: &[ObjectMesh]) -> Vec3 {
+    objects
+        .iter()
+        .fold(Vec3::NEG_INFINITY, |max, obj| max.max(obj.min_max().1))
 }
 
 pub fn slice(
-    input: SliceInput<Mask>,
+    input: SliceInput<Mask, ModifierMesh>,
     settings: &Settings,
     process: &Process,
 ) -> Result<SliceResult, SlicerErrors> {
-    let max = input
-        .objects
-        .iter()
-        .fold(Vec3::NEG_INFINITY, |max, obj| max.max(obj.min_max().1));
+    slice_z_range(input, None, settings, process)
+}
+
+///Cut the objects into their layers without generating any moves, the first stage of `slice`
+pub fn slice_layers(
+    objects: &[ObjectMesh],
+    settings: &Settings,
+) -> Result<Vec<Object>, SlicerErrors> {
+    let max = max_point(objects);
+    slicing::slice_meshes(
+        objects,
+        max.z,
+        None,
+        None,
+        settings,
+        &Process::default(),
+        0.0..1.0,
+    )
+}
+
+///Load a single STL and slice it without the viewer, for batch jobs or servers without a GPU. The
+///model is centered on the bed and dropped onto it, progress isn't reported.
+pub fn slice_from_stl<P: AsRef<Path>>(
+    path: P,
+    settings: &Settings,
+) -> Result<SliceResult, SlicerErrors> {
+    let mut object = STLLoader.load(&path).map_err(|err| match err {
+        LoadError::FileNotFound => SlicerErrors::ObjectFileNotFound {
+            filepath: path.as_ref().display().to_string(),
+        },
+        LoadError::BrokenFile | LoadError::NonManifold { .. } => SlicerErrors::StlLoadError,
+    })?;
+    //There is nobody to warn, the repaired mesh is sliced as is
+    object.repair();
+
+    let (min, max) = object.min_max();
+    let center = (min + max) / 2.0;
+    object.transform(glam::Mat4::from_translation(Vec3::new(
+        settings.print_x / 2.0 - center.x,
+        settings.print_y / 2.0 - center.y,
+        -min.z,
+    )));
+
+    slice(
+        SliceInput {
+            objects: vec![object],
+            masks: vec![],
+            modifiers: vec![],
+        },
+        settings,
+        &Process::default(),
+    )
+}
+
+///Slice only the layers whose center lies within `z_range`, for quickly previewing part of a tall
+///model. Layers keep the index they have in a full slice. The passes only see the sliced layers,
+///so top and bottom layers are detected at the ends of the range and are only approximate.
+pub fn slice_z_range(
+    input: SliceInput<Mask, ModifierMesh>,
+    z_range: Option<(f32, f32)>,
+    settings: &Settings,
+    process: &Process,
+) -> Result<SliceResult, SlicerErrors> {
+    //Masks are cropped to the objects, so without objects there is nothing to print
+    if input.objects.is_empty() {
+        return Err(SlicerErrors::NoObjects);
+    }
+
+    let max = max_point(&input.objects);
+
+    let mut warnings = vec![];
+    match settings.validate_settings() {
+        SettingsValidationResult::NoIssue => {}
+        SettingsValidationResult::Warning(warning) => warnings.push(warning),
+        SettingsValidationResult::Error(error) => return Err(error),
+    }
 
     process.set_task("Creating Towers".to_string());
     process.set_progress(0.1);
 
+    //Adaptive layer heights look at all towers at once, otherwise each tower is only built while
+    //its object is sliced
+    let (towers, layer_heights) = if settings.adaptive_layer_height.is_enabled() {
+        let towers = slicing::create_towers_with_progress(&input.objects, process, 0.1..0.2)?;
+        let layer_heights = slicing::adaptive_layer_heights(&towers, max.z, settings)?;
+        (Some(towers), layer_heights)
+    } else {
+        (None, None)
+    };
+    let layer_heights = layer_heights.as_deref();
+
     let mut masks: Vec<mask::ObjectMask> = input
         .masks
         .into_iter()
-        .map(|mask| mask.into_object(max, settings))
+        .map(|mask| mask.into_object(max, z_range, layer_heights, settings))
         .try_collect()?;
 
-    let towers = create_towers(&input.objects)?;
+    let modifiers: Vec<modifier::ObjectModifier> = input
+        .modifiers
+        .into_iter()
+        .map(|modifier| modifier.into_object(max, z_range, layer_heights, settings))
+        .try_collect()?;
 
     process.set_task("Slicing".to_string());
     process.set_progress(0.2);
     // println!("Max: {:?}", max);
 
-    let mut objects = slicing::slice(&towers, max.z, settings)?;
-
+    let mut objects = match towers {
+        Some(towers) => slicing::slice_towers_with_progress(
+            &towers,
+            max.z,
+            z_range,
+            layer_heights,
+            settings,
+            process,
+            0.2..0.5,
+        )?,
+        None => slicing::slice_meshes(
+            &input.objects,
+            max.z,
+            z_range,
+            layer_heights,
+            settings,
+            process,
+            0.2..0.5,
+        )?,
+    };
+    for (object, mesh) in objects.iter_mut().zip(&input.objects) {
+        object.extruder = mesh.extruder_index();
+    }
+    warnings.extend(clamped_width_warning(&objects, settings));
+    let object_outlines: Vec<slicing::ObjectOutline> =
+        objects.iter().map(slicing::ObjectOutline::new).collect();
+    let mut objects = slicing::resolve_overlapping_objects(
+        objects,
+        &object_outlines,
+        settings.overlapping_objects,
+        &mut warnings,
+    )?;
+    slicing::check_sequential_clearance(&objects, settings)?;
+
+    modifier::apply_modifiers(&mut objects, modifiers);
+
+    slicing::check_cancelled(process)?;
     process.set_task("Cropping Masks".to_string());
     process.set_progress(0.5);
+    let bounds = mask::LayerBounds::new(&objects);
     masks.iter_mut().for_each(|mask| {
-        mask.crop(&objects, max);
+        mask.crop(&objects, &bounds, max);
 
         if mask.mask_settings().epsilon.abs() > f32::EPSILON {
             mask.randomize_mask_underlaps(mask.mask_settings().epsilon);
@@ -98,30 +352,240 @@ pub fn slice(
     });
 
     combine_mask_moves(&mut objects, masks);
+    slicing::check_cancelled(process)?;
+
+    let object_moves = generate_moves(objects, settings, process)?;
+
+    finish_slice(
+        object_moves,
+        object_outlines,
+        max.z,
+        z_range,
+        warnings,
+        settings,
+        process,
+    )
+}
+
+///Reslices only the object at `index` and splices its commands into the previous result.
+///Objects that can influence each other (masks, skirt, brim, ooze shield, adaptive layer heights,
+///the clearance of printing by object) or a change of the settings or the maximum height fall back
+///to a full reslice, so the result always equals `slice`.
+pub fn slice_object(
+    input: SliceInput<Mask, ModifierMesh>,
+    index: usize,
+    previous: &SliceResult,
+    settings: &Settings,
+    process: &Process,
+) -> Result<SliceResult, SlicerErrors> {
+    let max = max_point(&input.objects);
+
+    let isolated = input.masks.is_empty()
+        && input.modifiers.is_empty()
+        && index < input.objects.len()
+        && input.objects.len() == previous.object_moves.len()
+        && input.objects.len() == previous.object_outlines.len()
+        && !settings.skirt.is_enabled()
+        && !settings.brim_width.is_enabled()
+        && !settings.ooze_shield.is_enabled()
+        && !settings.wipe_tower.is_enabled()
+        && !settings.object_prime.is_enabled()
+        && settings.overlapping_objects == OverlapPolicy::Warn
+        && settings.print_sequence == PrintSequence::ByLayer
+        && max.z == previous.max_height
+        && !settings.adaptive_layer_height.is_enabled()
+        && previous.z_range.is_none()
+        && serde_json::to_value(settings).ok() == serde_json::to_value(&previous.settings).ok();
+
+    if !isolated {
+        return slice(input, settings, process);
+    }
+
+    process.set_task("Creating Tower".to_string());
+    process.set_progress(0.1);
+
+    let mesh = &input.objects[index];
+    let tower =
+        TriangleTower::from_triangles_and_vertices(mesh.triangles(), mesh.vertices().to_vec())?;
+
+    process.set_task("Slicing".to_string());
+    process.set_progress(0.2);
 
-    let mut moves = generate_moves(objects, settings, process)?;
+    let layers = slicing::estimated_layer_count(max.z, None, settings);
+    let progress = slicing::LayerProgress::new(process, 0.2..0.3, layers);
+    let mut object =
+        slicing::slice_single_with_progress(&tower, max.z, None, None, settings, Some(&progress))?;
+    object.extruder = mesh.extruder_index();
+    let mut object_outlines = previous.object_outlines.clone();
+    object_outlines[index] = slicing::ObjectOutline::new(&object);
+    let mut objects = vec![object];
+
+    slicing::check_cancelled(process)?;
+    process.set_task("Creating Support Towers".to_string());
+    process.set_progress(0.3);
+    SupportTowerPass::pass(&mut objects, settings);
+
+    slicing::check_cancelled(process)?;
+    process.set_task("Generate Moves".to_string());
+    let mut object = objects.pop().expect("Object was sliced");
+    let progress =
+        slicing::LayerProgress::new(process, 0.3..0.5, object.layers.len() * MOVE_PASSES);
+    generate_object_moves(&mut object, settings, &progress)?;
+
+    process.set_progress(0.5);
+
+    let mut object_moves = previous.object_moves.clone();
+    object_moves[index] = convert_object_into_moves(index, object, settings);
+
+    //Overlap, retraction and fiber turn warnings are recalculated for the new object and moves
+    let mut warnings: Vec<SlicerWarnings> = previous
+        .warnings
+        .iter()
+        .filter(|warning| {
+            !matches!(
+                warning,
+                SlicerWarnings::ObjectsOverlap { .. }
+                    | SlicerWarnings::ExcessiveRetraction { .. }
+                    | SlicerWarnings::FiberTurnsTooTight { .. }
+            )
+        })
+        .cloned()
+        .collect();
+    warnings.extend(slicing::overlap_warnings(&object_outlines));
+
+    finish_slice(
+        object_moves,
+        object_outlines,
+        max.z,
+        None,
+        warnings,
+        settings,
+        process,
+    )
+}
+
+///The area of the bed the first layer of the objects touches, including the skirt, brim and ooze
+///shield. Only the first layer is sliced, so this is cheap enough to preview while objects move.
+pub fn first_layer_coverage(
+    objects: &[ObjectMesh],
+    settings: &Settings,
+) -> Result<MultiPolygon<f32>, SlicerErrors> {
+    if objects.is_empty() {
+        return Ok(MultiPolygon(vec![]));
+    }
+
+    let max = max_point(objects);
+    let towers = create_towers(objects)?;
+
+    let first_layer_height = settings.get_layer_settings(0, 0.0).layer_height;
+    let mut objects = slicing::slice(
+        &towers,
+        max.z,
+        Some((0.0, first_layer_height)),
+        None,
+        settings,
+    )?;
+
+    SkirtPass::pass(&mut objects, settings);
+    OozeShieldPass::pass(&mut objects, settings);
+    BrimPass::pass(&mut objects, settings);
+    WipeTowerPass::pass(&mut objects, settings);
+    ObjectPrimePass::pass(&mut objects, settings);
+
+    Ok(objects
+        .iter()
+        .filter_map(|object| object.layers.first())
+        .flat_map(|slice| {
+            std::iter::once(slice.main_polygon.clone()).chain(
+                slice
+                    .fixed_chains
+                    .iter()
+                    .map(|chain| MultiPolygon(vec![Polygon::from(chain)])),
+            )
+        })
+        .fold(MultiPolygon(vec![]), |coverage, polygon| {
+            coverage.union_with(&polygon)
+        }))
+}
+
+///A single warning for every layer whose extrusion widths are clamped, rather than one per layer
+fn clamped_width_warning(objects: &[Object], settings: &Settings) -> Option<SlicerWarnings> {
+    let layers: BTreeSet<usize> = objects
+        .iter()
+        .flat_map(|object| &object.layers)
+        .filter(|slice| settings.extrusion_width_clamped(slice.layer, slice.get_height()))
+        .map(|slice| slice.layer)
+        .collect();
+
+    if layers.is_empty() {
+        return None;
+    }
+
+    let (min_width, max_width) = settings.extrusion_width_range();
+    let warning = SlicerWarnings::ExtrusionWidthClamped {
+        layers: layers.len(),
+        min_width,
+        max_width,
+    };
+    log::warn!("{}", warning.get_code_and_message().1);
+
+    Some(warning)
+}
+
+fn finish_slice(
+    object_moves: Vec<ObjectMoves>,
+    object_outlines: Vec<slicing::ObjectOutline>,
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+    mut warnings: Vec<SlicerWarnings>,
+    settings: &Settings,
+    process: &Process,
+) -> Result<SliceResult, SlicerErrors> {
+    slicing::check_cancelled(process)?;
+    let mut moves = flatten_object_moves(&object_moves, settings);
 
     process.set_task("Optimizing".to_string());
     process.set_progress(0.6);
     OptimizePass::pass(&mut moves, settings);
+    ArcFitPass::pass(&mut moves, settings);
 
+    slicing::check_cancelled(process)?;
     process.set_task("Slowing Down Layers".to_string());
     process.set_progress(0.7);
     SlowDownLayerPass::pass(&mut moves, settings);
 
+    StandbyTravelPass::pass(&mut moves, settings);
+
+    let downgraded = FiberRadiusPass::downgrade_tight_turns(&mut moves, settings);
+    if downgraded > 0 {
+        let warning = SlicerWarnings::FiberTurnsTooTight {
+            segments: downgraded,
+            min_radius: settings.fiber.min_radius,
+        };
+        log::warn!("{}", warning.get_code_and_message().1);
+        warnings.push(warning);
+    }
+
     MergeFiberPass::pass(&mut moves, settings);
 
     EvalIdPass::pass(&mut moves, settings);
 
+    slicing::check_cancelled(process)?;
     process.set_task("Calculating Values".to_string());
     process.set_progress(0.75);
 
     let calculated_values = calculation::calculate_values(&moves, settings);
+    warnings.extend(calculation::excessive_retraction_warnings(&moves, settings));
 
     Ok(SliceResult {
         moves,
         calculated_values,
         settings: settings.clone(),
+        warnings,
+        object_moves,
+        object_outlines,
+        max_height,
+        z_range,
     })
 }
 
@@ -148,62 +612,124 @@ fn generate_moves(
     mut objects: Vec<Object>,
     settings: &Settings,
     process: &Process,
-) -> Result<Vec<Command>, SlicerErrors> {
+) -> Result<Vec<ObjectMoves>, SlicerErrors> {
     //Creates Support Towers
+    slicing::check_cancelled(process)?;
     process.set_task("Creating Support Towers".to_string());
     process.set_progress(0.3);
     SupportTowerPass::pass(&mut objects, settings);
 
     //Adds a skirt
+    slicing::check_cancelled(process)?;
     process.set_task("Creating Skirt".to_string());
     SkirtPass::pass(&mut objects, settings);
 
+    //Adds an ooze shield
+    slicing::check_cancelled(process)?;
+    process.set_task("Creating Ooze Shield".to_string());
+    OozeShieldPass::pass(&mut objects, settings);
+
     //Adds a brim
+    slicing::check_cancelled(process)?;
     process.set_task("Creating Brim".to_string());
     BrimPass::pass(&mut objects, settings);
 
+    //Adds a wipe tower clear of everything else
+    slicing::check_cancelled(process)?;
+    process.set_task("Creating Wipe Tower".to_string());
+    WipeTowerPass::pass(&mut objects, settings);
+
+    //Adds a prime line in front of each object
+    slicing::check_cancelled(process)?;
+    process.set_task("Creating Object Primes".to_string());
+    ObjectPrimePass::pass(&mut objects, settings);
+
+    slicing::check_cancelled(process)?;
     process.set_task("Generate Moves".to_string());
+    let layers: usize = objects.iter().map(|object| object.layers.len()).sum();
+    let progress = slicing::LayerProgress::new(process, 0.3..0.5, layers * MOVE_PASSES);
     let v: Result<Vec<()>, SlicerErrors> = objects
         .par_iter_mut()
-        .map(|object| {
-            let slices = &mut object.layers;
+        .map(|object| generate_object_moves(object, settings, &progress))
+        .collect();
 
-            //Shrink layer
-            ShrinkPass::pass(slices, settings)?;
+    process.set_progress(0.5);
 
-            //Handle Perimeters
-            WallPass::pass(slices, settings)?;
+    v?;
 
-            //Handle Bridging
-            BridgingPass::pass(slices, settings)?;
+    Ok(objects
+        .into_iter()
+        .enumerate()
+        .map(|(object_num, object)| convert_object_into_moves(object_num, object, settings))
+        .collect())
+}
 
-            //Handle Top Layer
-            TopLayerPass::pass(slices, settings)?;
+///The number of slice passes `generate_object_moves` runs, each adds the layers of the object to
+///the progress and stops the object once the process is cancelled
+const MOVE_PASSES: usize = 15;
 
-            //Handle Top And Bottom Layers
-            TopAndBottomLayersPass::pass(slices, settings)?;
+fn generate_object_moves(
+    object: &mut Object,
+    settings: &Settings,
+    progress: &slicing::LayerProgress,
+) -> Result<(), SlicerErrors> {
+    let slices = &mut object.layers;
+    let layers = slices.len();
+    let step = |result: Result<(), SlicerErrors>| {
+        result?;
+        progress.add(layers);
+        progress.check_cancelled()
+    };
+
+    //Spiralized layers must each have a single contour
+    step(SpiralizePass::pass(slices, settings))?;
+
+    //Shrink layer
+    step(ShrinkPass::pass(slices, settings))?;
+
+    //Thin features get a single wall of varying width
+    step(VariableWidthWallPass::pass(slices, settings))?;
+
+    //Handle Perimeters
+    step(WallPass::pass(slices, settings))?;
+
+    if settings.outline_only || settings.spiralize {
+        //The passes filling the inside are skipped
+        progress.add(layers * (MOVE_PASSES - 5));
+        return step(OrderPass::pass(slices, settings));
+    }
 
-            //Handle Support
-            SupportPass::pass(slices, settings)?;
+    //Fill thin gaps left between the walls
+    step(GapFillPass::pass(slices, settings))?;
 
-            FiberInfillPass::pass(slices, settings)?;
+    //Handle Bridging
+    step(BridgingPass::pass(slices, settings))?;
 
-            //Lightning Infill
-            LightningFillPass::pass(slices, settings)?;
+    //Handle Top Layer
+    step(TopLayerPass::pass(slices, settings))?;
 
-            //Fill Remaining areas
-            FillAreaPass::pass(slices, settings)?;
+    //Handle Top And Bottom Layers
+    step(TopAndBottomLayersPass::pass(slices, settings))?;
 
-            //Order the move chains
-            OrderPass::pass(slices, settings)
-        })
-        .collect();
+    //Smooth the top surfaces
+    step(IroningPass::pass(slices, settings))?;
 
-    process.set_progress(0.5);
+    //Step the infill density up below top surfaces
+    step(TopTransitionPass::pass(slices, settings))?;
 
-    v?;
+    //Handle Support
+    step(SupportPass::pass(slices, settings))?;
+
+    step(FiberInfillPass::pass(slices, settings))?;
+
+    //Lightning Infill
+    step(LightningFillPass::pass(slices, settings))?;
 
-    Ok(convert_objects_into_moves(objects, settings))
+    //Fill Remaining areas
+    step(FillAreaPass::pass(slices, settings))?;
+
+    //Order the move chains
+    step(OrderPass::pass(slices, settings))
 }
 
 fn generate_mask_moves(
@@ -211,16 +737,29 @@ fn generate_mask_moves(
     settings: &Settings,
     process: &Process,
 ) -> Result<(), SlicerErrors> {
+    //Masks only change how the inside of the objects is filled
+    if settings.outline_only || settings.spiralize {
+        return Ok(());
+    }
+
     let v: Result<Vec<()>, SlicerErrors> = masks
         .par_iter_mut()
         .map(|object| {
+            slicing::check_cancelled(process)?;
+
             let settings = &object
                 .mask_settings()
                 .clone()
                 .combine_settings(settings.clone());
+            let angle_offset = object.mask_settings().infill_angle_offset;
 
             let slices = &mut object.layers;
 
+            //Rotate the mask infill against the infill of the objects around it
+            slices
+                .iter_mut()
+                .for_each(|slice| slice.layer_settings.infill_angle_offset = angle_offset);
+
             //Shrink layer
             ShrinkPass::pass(slices, settings)?;
 
@@ -236,6 +775,9 @@ fn generate_mask_moves(
             //Handle Top And Bottom Layers
             TopAndBottomLayersPass::pass(slices, settings)?;
 
+            //Step the infill density up below top surfaces
+            TopTransitionPass::pass(slices, settings)?;
+
             //Lightning Infill
             LightningFillPass::pass(slices, settings)?;
 
@@ -269,6 +811,9 @@ pub struct Slice {
     ///The area that will be filled by support towers
     pub support_tower: Option<MultiPolygon<f32>>,
 
+    ///The area filled as a top surface of the object, with nothing printed on top of it
+    pub top_surface: MultiPolygon<f32>,
+
     ///Theses moves ares applied in order and the start of the commands for the slice.
     pub fixed_chains: Vec<MoveChain>,
 
@@ -284,6 +829,9 @@ pub struct Slice {
     ///A copy of this layers settings
     pub layer_settings: LayerSettings,
 
+    ///Regions of this slice filled with the settings of a modifier mesh, highest priority first
+    pub modifier_regions: Vec<modifier::ModifierRegion>,
+
     pub layer: usize,
 }
 impl Slice {
@@ -307,11 +855,13 @@ impl Slice {
             remaining_area: MultiPolygon(vec![polygon]),
             support_interface: None,
             support_tower: None,
+            top_surface: MultiPolygon(vec![]),
             fixed_chains: vec![],
             chains: vec![],
             bottom_height,
             top_height,
             layer_settings,
+            modifier_regions: vec![],
             layer,
         }
     }
@@ -366,11 +916,13 @@ impl Slice {
             remaining_area: multi_polygon.simplify_vw(&0.001),
             support_interface: None,
             support_tower: None,
+            top_surface: MultiPolygon(vec![]),
             chains: vec![],
             fixed_chains: vec![],
             bottom_height,
             top_height,
             layer_settings,
+            modifier_regions: vec![],
             layer,
         })
     }
@@ -391,6 +943,175 @@ pub enum SolidInfillTypes {
     RectilinearCustom(f32),
 }
 
+///Order in which objects are printed
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum PrintSequence {
+    ///Print all objects layer by layer together
+    ByLayer,
+
+    ///Print each object completely before starting the next one
+    ByObject,
+}
+
+///Which solid fill to use on layers that are both top and bottom layers, such as thin plates
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum ThinPartSolidPolicy {
+    ///Fill the entire layer as a top surface
+    TopWins,
+
+    ///Fill the entire layer as bottom solid infill
+    BottomWins,
+
+    ///Fill exposed areas as top surface and the rest as solid infill
+    BothSolid,
+}
+
+///How to handle objects whose layers overlap each other
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum OverlapPolicy {
+    ///Log a warning and print the objects as they are
+    Warn,
+
+    ///Merge overlapping objects into one object, printed with the extruder of the lowest indexed one
+    Union,
+
+    ///Fail the slice
+    Error,
+}
+
+///Firmware dialect the gcode is written for
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum GcodeFlavor {
+    ///Marlin and compatible firmware
+    Marlin,
+
+    ///RepRapFirmware as used on Duet boards
+    RepRapFirmware,
+}
+
+///What to do with a placeholder in the custom gcode that doesn't name a setting
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum UnknownPlaceholderPolicy {
+    ///Fail the gcode generation
+    Error,
+
+    ///Keep the placeholder as written and log a warning
+    KeepLiteral,
+}
+
+///Where to start the walls of each layer
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum SeamPosition {
+    ///The vertex where the wall changes direction the most
+    LargestTurn,
+
+    ///The vertex furthest towards the back of the bed
+    Rear,
+
+    ///The most concave corner, where the seam is hidden best. Walls without a concave corner
+    ///use the rear vertex.
+    SharpestCorner,
+
+    ///The vertex closest to where the nozzle comes from, keeping the travels short
+    Nearest,
+
+    ///A random vertex, spreading the seam over the whole wall
+    Random,
+
+    ///The vertex closest to the seams of the previous layer, stacking them vertically
+    Aligned,
+}
+
+///Which way the retraction wipe moves the nozzle
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum WipeDirection {
+    ///Back over the path that was just printed
+    AlongPath,
+
+    ///From the end of outer walls towards the inside of the part, so the wipe doesn't mark the
+    ///visible surface. Other paths wipe along the path.
+    Inward,
+}
+
+///How the nozzle travels to the first chain of a new layer
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum ApproachMode {
+    ///Travel straight to the first chain with the retraction of the layer start
+    Direct,
+
+    ///Lift above the new layer before the travel and descend onto the first chain
+    LiftDescend,
+
+    ///Travel to the first chain through the inside of the layer without retracting, or like
+    ///Direct when there is no way inside
+    Comb,
+}
+
+///Pattern of the support layers directly below the model
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum SupportInterfacePattern {
+    ///Parallel lines, perpendicular to the support ribs
+    Lines,
+
+    ///Lines in both directions
+    Grid,
+
+    ///Loops following the outline of the interface inwards
+    Concentric,
+}
+
+///Shape of the support below overhangs
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum SupportStyle {
+    ///Columns straight down from the overhangs, filled with ribs
+    Grid,
+
+    ///Branches growing down from the overhangs that merge into thicker trunks
+    Tree,
+}
+
+///Surface of the build plate. Each type nudges the first layer from the regular settings so it
+///sticks, unless the first layer sets the value itself.
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum BedType {
+    ///Smooth PEI sheet, the regular settings are tuned for it and used as they are
+    SmoothPei,
+
+    ///Textured PEI sheet, the first layer is squished 0.02mm further into the texture and the
+    ///bed is 5°C hotter
+    TexturedPei,
+
+    ///Glass plate, the bed is 10°C hotter and the fan stays off for the first layer
+    Glass,
+}
+
+impl BedType {
+    ///Change in mm of the height of the first layer, negative is closer to the bed
+    pub fn first_layer_z_offset(&self) -> f32 {
+        match self {
+            BedType::SmoothPei | BedType::Glass => 0.0,
+            BedType::TexturedPei => -0.02,
+        }
+    }
+
+    ///Change in °C of the bed temperature during the first layer
+    pub fn first_layer_bed_temp_delta(&self) -> f32 {
+        match self {
+            BedType::SmoothPei => 0.0,
+            BedType::TexturedPei => 5.0,
+            BedType::Glass => 10.0,
+        }
+    }
+
+    ///Fraction of the fan speed used during the first layer
+    pub fn first_layer_fan_factor(&self) -> f32 {
+        match self {
+            BedType::SmoothPei | BedType::TexturedPei => 1.0,
+            BedType::Glass => 0.0,
+        }
+    }
+}
+
 ///Types of partial infill
 #[derive(Clone, Copy, Debug, PartialEq, EnumIter, Serialize, Deserialize)]
 pub enum PartialInfillTypes {
@@ -406,8 +1127,19 @@ pub enum PartialInfillTypes {
     /// Creates a 3d cube structure.
     Cubic,
 
+    ///A 3d cube structure with cells that are smaller near the walls and larger in the center
+    AdaptiveCubic,
+
+    ///Follows the gyroid surface, which turns between layers so the infill is equally strong in
+    ///every direction
+    Gyroid,
+
     ///Creates lightning shaped infill that retracts into the print walls
     Lightning,
+
+    ///Follows the medial axis of the polygon. If true the beads are thickened to fill the local
+    ///width of the part
+    Skeleton(bool),
 }
 
 #[derive(Debug)]
@@ -415,6 +1147,9 @@ pub enum PartialInfillTypes {
 pub struct Object {
     /// The slices for this model sorted from lowest to highest.
     pub layers: Vec<Slice>,
+
+    /// The extruder that prints this model
+    pub extruder: usize,
 }
 
 ///Calculated values about an entire print
@@ -426,6 +1161,9 @@ pub struct CalculatedValues {
     ///Total plastic used by the print in grams
     pub plastic_weight: f32,
 
+    ///Plastic used by each extruder in grams
+    pub extruder_weights: Vec<f32>,
+
     ///Total plastic used by the print in mm of filament
     pub plastic_length: f32,
 
@@ -433,6 +1171,17 @@ pub struct CalculatedValues {
 
     ///Total time to print in seconds
     pub total_time: f32,
+
+    ///Center of the mass of plastic and fiber deposited by the print in mm, or the center of the
+    ///bed at z 0 if nothing is printed
+    pub center_of_mass: Vec3,
+
+    ///Time in seconds and plastic used in mm of filament by the extrusions of each trace type.
+    ///Plastic laid down with fiber only counts towards the time
+    pub trace_breakdown: std::collections::HashMap<TraceType, (f32, f32)>,
+
+    ///Time in seconds spent travelling and retracting
+    pub travel_time: f32,
 }
 
 impl CalculatedValues {
@@ -449,3 +1198,1662 @@ impl CalculatedValues {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Area, EuclideanDistance};
+    use shared::object::ObjectVertex;
+
+    pub(crate) fn cube(min: Vec3, size: f32) -> ObjectMesh {
+        cuboid(min, Vec3::splat(size))
+    }
+
+    pub(crate) fn cuboid(min: Vec3, size: Vec3) -> ObjectMesh {
+        let vertices = [
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (1.0, 1.0, 0.0),
+            (0.0, 1.0, 0.0),
+            (0.0, 0.0, 1.0),
+            (1.0, 0.0, 1.0),
+            (1.0, 1.0, 1.0),
+            (0.0, 1.0, 1.0),
+        ]
+        .into_iter()
+        .map(|(x, y, z)| {
+            ObjectVertex::new(min.x + x * size.x, min.y + y * size.y, min.z + z * size.z)
+        })
+        .collect();
+
+        let triangles = [
+            [0, 2, 1],
+            [0, 3, 2],
+            [4, 5, 6],
+            [4, 6, 7],
+            [0, 1, 5],
+            [0, 5, 4],
+            [3, 7, 6],
+            [3, 6, 2],
+            [0, 4, 7],
+            [0, 7, 3],
+            [1, 2, 6],
+            [1, 6, 5],
+        ]
+        .into_iter()
+        .map(|triangle| triangle.into())
+        .collect();
+
+        let mut mesh = ObjectMesh::new(vertices, triangles);
+        mesh.sort_indices();
+        mesh
+    }
+
+    #[test]
+    fn incremental_slice_matches_full_slice() {
+        let settings = Settings::default();
+        let process = Process::new();
+
+        let input = |objects: &[ObjectMesh]| SliceInput {
+            objects: objects.to_vec(),
+            masks: vec![],
+            modifiers: vec![],
+        };
+        //Move the second object and slice it again on its own
+        let move_second = |objects: &[ObjectMesh], to: Vec3| {
+            let previous = slice(input(objects), &settings, &process).unwrap();
+
+            let mut moved = objects.to_vec();
+            moved[1] = cube(to, 10.0);
+
+            let incremental =
+                slice_object(input(&moved), 1, &previous, &settings, &process).unwrap();
+            let full = slice(input(&moved), &settings, &process).unwrap();
+
+            assert_eq!(incremental.moves, full.moves);
+            assert_eq!(incremental.warnings, full.warnings);
+            (previous, incremental)
+        };
+
+        move_second(
+            &[
+                cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                cube(Vec3::new(60.0, 20.0, 0.0), 10.0),
+            ],
+            Vec3::new(80.0, 40.0, 0.0),
+        );
+
+        //Moving out of an overlap drops its warning
+        let overlap = SlicerWarnings::ObjectsOverlap {
+            first: 0,
+            second: 1,
+        };
+        let (previous, incremental) = move_second(
+            &[
+                cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                cube(Vec3::new(25.0, 25.0, 0.0), 10.0),
+            ],
+            Vec3::new(60.0, 20.0, 0.0),
+        );
+        assert!(previous.warnings.contains(&overlap));
+        assert!(!incremental.warnings.contains(&overlap));
+
+        //Moving into one adds it
+        let (previous, incremental) = move_second(
+            &[
+                cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                cube(Vec3::new(60.0, 20.0, 0.0), 10.0),
+            ],
+            Vec3::new(25.0, 25.0, 0.0),
+        );
+        assert!(!previous.warnings.contains(&overlap));
+        assert!(incremental.warnings.contains(&overlap));
+    }
+
+    #[test]
+    fn overlapping_objects() {
+        let settings = Settings::default();
+        let objects = vec![
+            cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+            cube(Vec3::new(40.0, 20.0, 0.0), 10.0),
+            cube(Vec3::new(25.0, 25.0, 0.0), 10.0),
+        ];
+
+        let towers = create_towers(&objects).unwrap();
+        let sliced = || slicing::slice(&towers, 10.0, None, None, &settings).unwrap();
+        let outlines: Vec<slicing::ObjectOutline> =
+            sliced().iter().map(slicing::ObjectOutline::new).collect();
+
+        assert_eq!(
+            slicing::resolve_overlapping_objects(
+                sliced(),
+                &outlines,
+                OverlapPolicy::Error,
+                &mut vec![]
+            )
+            .unwrap_err(),
+            SlicerErrors::ObjectsOverlap {
+                first: 0,
+                second: 2
+            }
+        );
+
+        let mut warnings = vec![];
+        assert_eq!(
+            slicing::resolve_overlapping_objects(
+                sliced(),
+                &outlines,
+                OverlapPolicy::Warn,
+                &mut warnings
+            )
+            .unwrap()
+            .len(),
+            3
+        );
+        assert_eq!(
+            warnings,
+            vec![SlicerWarnings::ObjectsOverlap {
+                first: 0,
+                second: 2
+            }]
+        );
+
+        let mut objects = sliced();
+        objects[1].extruder = 2;
+        objects[2].extruder = 1;
+        let merged = slicing::resolve_overlapping_objects(
+            objects,
+            &outlines,
+            OverlapPolicy::Union,
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].extruder, 0);
+        assert_eq!(merged[1].extruder, 2);
+
+        //The first and last cube are fused into one solid with the shared 5x5 region once
+        let area = |object: usize| merged[object].layers[5].main_polygon.unsigned_area();
+        assert!((area(0) - 175.0).abs() < 0.01, "{}", area(0));
+        assert!((area(1) - 100.0).abs() < 0.01, "{}", area(1));
+        assert_eq!(merged[0].layers[5].main_polygon.0.len(), 1);
+    }
+
+    #[test]
+    fn slice_key_ignores_gcode_only_settings() {
+        let input = |size: f32| SliceInput {
+            objects: vec![cube(Vec3::new(20.0, 20.0, 0.0), size)],
+            masks: vec![],
+            modifiers: vec![],
+        };
+        let settings = Settings::default();
+        let key = slice_key(&input(10.0), &settings);
+
+        let mut gcode_only = settings.clone();
+        gcode_only.starting_instructions = "G28 ; home".to_string();
+        gcode_only.bed_transform.offset = (5.0, 0.0);
+        assert_eq!(slice_key(&input(10.0), &gcode_only), key);
+
+        let mut geometry = settings.clone();
+        geometry.layer_height = 0.3;
+        assert_ne!(slice_key(&input(10.0), &geometry), key);
+        assert_ne!(slice_key(&input(11.0), &settings), key);
+
+        //Every gcode only setting names a setting, so renaming one can't go unnoticed
+        let value = serde_json::to_value(&settings).unwrap();
+        for name in GCODE_ONLY_SETTINGS {
+            assert!(value.get(*name).is_some(), "{} is not a setting", name);
+        }
+    }
+
+    #[test]
+    fn layer_progress_estimates_the_time_left() {
+        let process = Process::new();
+        let progress = slicing::LayerProgress::new(&process, 0.2..0.4, 4);
+
+        progress.add(1);
+        assert!((process.get() - 0.25).abs() < 0.0001);
+        assert!(process.eta().is_some_and(|eta| eta >= 0.0));
+
+        //Estimates that were too low don't run past the range
+        progress.add(5);
+        assert!((process.get() - 0.4).abs() < 0.0001);
+        assert_eq!(process.eta(), Some(0.0));
+
+        process.set_progress(0.5);
+        assert_eq!(process.eta(), None);
+    }
+
+    #[test]
+    fn towers_are_built_lazily_and_can_be_cancelled() {
+        let settings = Settings::default();
+        let objects = || {
+            vec![
+                cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                cube(Vec3::new(40.0, 20.0, 0.0), 10.0),
+            ]
+        };
+
+        //Building each tower while its object is sliced gives the same layers
+        let towers = create_towers(&objects()).unwrap();
+        let eager = slicing::slice(&towers, 10.0, None, None, &settings).unwrap();
+        let process = Process::new();
+        let lazy =
+            slicing::slice_meshes(&objects(), 10.0, None, None, &settings, &process, 0.2..0.5)
+                .unwrap();
+        assert!((process.get() - 0.5).abs() < 0.0001);
+
+        assert_eq!(eager.len(), lazy.len());
+        for (eager, lazy) in eager.iter().zip(&lazy) {
+            assert_eq!(eager.layers.len(), lazy.layers.len());
+            assert!(eager
+                .layers
+                .iter()
+                .zip(&lazy.layers)
+                .all(|(a, b)| a.main_polygon == b.main_polygon));
+        }
+
+        process.cancel();
+        assert!(matches!(
+            slicing::create_towers_with_progress(&objects(), &process, 0.1..0.2),
+            Err(SlicerErrors::Cancelled)
+        ));
+        assert!(matches!(
+            slicing::slice_meshes(&objects(), 10.0, None, None, &settings, &process, 0.2..0.5),
+            Err(SlicerErrors::Cancelled)
+        ));
+        assert!(matches!(
+            slicing::slice_towers_with_progress(
+                &towers,
+                10.0,
+                None,
+                None,
+                &settings,
+                &process,
+                0.2..0.5
+            ),
+            Err(SlicerErrors::Cancelled)
+        ));
+
+        //Generating moves stops after the pass that was running
+        let mut object = slicing::slice(&towers, 10.0, None, None, &settings)
+            .unwrap()
+            .remove(0);
+        let progress = slicing::LayerProgress::new(&process, 0.3..0.5, 0);
+        assert!(matches!(
+            generate_object_moves(&mut object, &settings, &progress),
+            Err(SlicerErrors::Cancelled)
+        ));
+
+        let input = SliceInput {
+            objects: objects(),
+            masks: vec![],
+            modifiers: vec![],
+        };
+        assert!(matches!(
+            slice(input, &settings, &process),
+            Err(SlicerErrors::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn sequential_printing_needs_clearance() {
+        let mut settings = Settings {
+            print_sequence: PrintSequence::ByObject,
+            sequential_clearance: 15.0,
+            ..Default::default()
+        };
+        let objects = vec![
+            cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+            cube(Vec3::new(40.0, 20.0, 0.0), 10.0),
+            cube(Vec3::new(20.0, 60.0, 0.0), 10.0),
+        ];
+
+        let towers = create_towers(&objects).unwrap();
+        let sliced = slicing::slice(&towers, 10.0, None, None, &settings).unwrap();
+
+        //Only the first two objects are within 15mm of each other
+        assert_eq!(
+            slicing::check_sequential_clearance(&sliced, &settings).unwrap_err(),
+            SlicerErrors::SequentialClearance {
+                objects: vec![0, 1],
+                clearance: 15.0
+            }
+        );
+
+        settings.sequential_clearance = 5.0;
+        assert!(slicing::check_sequential_clearance(&sliced, &settings).is_ok());
+
+        //Printing layer by layer never passes finished objects
+        settings.sequential_clearance = 15.0;
+        settings.print_sequence = PrintSequence::ByLayer;
+        assert!(slicing::check_sequential_clearance(&sliced, &settings).is_ok());
+    }
+
+    #[test]
+    fn sequential_cooling_parks_small_layers() {
+        let mut settings = Settings {
+            print_sequence: PrintSequence::ByObject,
+            ..Default::default()
+        };
+        *settings.sequential_cooling.enabled_mut() = true;
+        settings.sequential_cooling.park_x = 5.0;
+        settings.sequential_cooling.park_y = 5.0;
+
+        let process = Process::new();
+        let input = || SliceInput {
+            objects: vec![cube(Vec3::new(100.0, 100.0, 0.0), 2.0)],
+            masks: vec![],
+            modifiers: vec![],
+        };
+
+        let parked = |moves: &[Command]| -> Vec<Coord<f32>> {
+            moves
+                .iter()
+                .enumerate()
+                .filter(|(_, cmd)| matches!(cmd, Command::Delay { .. }))
+                .filter_map(|(index, _)| {
+                    moves[..index].iter().rev().find_map(|cmd| match cmd {
+                        Command::MoveTo { end } => Some(*end),
+                        _ => None,
+                    })
+                })
+                .collect()
+        };
+
+        let result = slice(input(), &settings, &process).unwrap();
+        let parks = parked(&result.moves);
+        assert!(!parks.is_empty());
+        assert!(parks.iter().all(|park| *park == Coord { x: 5.0, y: 5.0 }));
+
+        //Parking lifts above the print and the next layer doesn't retract a second time
+        let moves = &result.moves;
+        let retraction = |cmd: &Command| match cmd {
+            Command::SetState { new_state } if new_state.retract != RetractionType::NoRetract => {
+                Some(new_state.retract.clone())
+            }
+            _ => None,
+        };
+        for (index, _) in moves
+            .iter()
+            .enumerate()
+            .filter(|(_, cmd)| matches!(cmd, Command::Delay { .. }))
+        {
+            let top = moves[..index]
+                .iter()
+                .filter_map(|cmd| match cmd {
+                    Command::LayerChange { z, .. } => Some(*z),
+                    _ => None,
+                })
+                .fold(0.0, f32::max);
+            let lift = moves[..index].iter().rev().find_map(retraction);
+            assert!(matches!(lift, Some(RetractionType::ClearanceLift { z }) if z > top));
+
+            let next = moves[index..].iter().find_map(retraction);
+            assert_ne!(next, Some(RetractionType::Retract));
+        }
+
+        //A park position on top of the object is replaced with a bed corner
+        settings.sequential_cooling.park_x = 101.0;
+        settings.sequential_cooling.park_y = 101.0;
+        let result = slice(input(), &settings, &process).unwrap();
+        let parks = parked(&result.moves);
+        assert!(!parks.is_empty());
+        assert!(parks
+            .iter()
+            .all(|park| (park.x - 101.0).abs() > 11.0 || (park.y - 101.0).abs() > 11.0));
+
+        settings.print_sequence = PrintSequence::ByLayer;
+        let result = slice(input(), &settings, &process).unwrap();
+        assert!(parked(&result.moves).is_empty());
+    }
+
+    #[test]
+    fn each_object_is_primed_before_it_starts() {
+        let mut settings = Settings {
+            print_sequence: PrintSequence::ByObject,
+            ..Default::default()
+        };
+        *settings.object_prime.enabled_mut() = true;
+        settings.object_prime.length = 20.0;
+        settings.object_prime.distance = 3.0;
+
+        let process = Process::new();
+        let input = || SliceInput {
+            objects: vec![
+                cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                cube(Vec3::new(60.0, 20.0, 0.0), 10.0),
+            ],
+            masks: vec![],
+            modifiers: vec![],
+        };
+
+        //The first extrusion of the first layer of each object
+        let first_extrusions = |moves: &[Command]| -> Vec<(Coord<f32>, Coord<f32>)> {
+            (0..2)
+                .map(|object| {
+                    let start = moves
+                        .iter()
+                        .tuple_windows()
+                        .position(|(a, b)| {
+                            *a == Command::ChangeObject {
+                                object,
+                                extruder: 0,
+                            } && matches!(b, Command::LayerChange { index: 0, .. })
+                        })
+                        .expect("Every object has a first layer");
+
+                    moves[start..]
+                        .iter()
+                        .find_map(|cmd| match cmd {
+                            Command::MoveAndExtrude { start, end, .. } => Some((*start, *end)),
+                            _ => None,
+                        })
+                        .expect("Every object is printed")
+                })
+                .collect()
+        };
+
+        let primed = slice(input(), &settings, &process).unwrap();
+        for ((start, end), object_x) in first_extrusions(&primed.moves)
+            .into_iter()
+            .zip([20.0, 60.0])
+        {
+            assert!((start.y - 17.0).abs() < 0.01 && (end.y - 17.0).abs() < 0.01);
+            assert!((start.x.min(end.x) - object_x).abs() < 0.01, "{:?}", start);
+            assert!(((end.x - start.x).abs() - 20.0).abs() < 0.01, "{:?}", end);
+        }
+
+        //The prime is configured per object and counted with the rest of the print
+        settings.object_prime.object_lengths = vec![0.0];
+        let partly_primed = slice(input(), &settings, &process).unwrap();
+        let extrusions = first_extrusions(&partly_primed.moves);
+        assert!(extrusions[0].0.y >= 20.0 && extrusions[0].1.y >= 20.0);
+        assert!((extrusions[1].0.y - 17.0).abs() < 0.01);
+        assert!(
+            partly_primed.calculated_values.plastic_volume
+                < primed.calculated_values.plastic_volume
+        );
+    }
+
+    #[test]
+    fn extrusion_segments_of_cube() {
+        //Without arcs every extrusion command is a single straight bead
+        let mut settings = Settings::default();
+        *settings.arc_tolerance.enabled_mut() = false;
+        let process = Process::new();
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 20.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &process,
+        )
+        .unwrap();
+
+        let segments: Vec<ExtrusionSegment> = result.extrusion_segments().collect();
+
+        let expected: usize = result
+            .moves
+            .iter()
+            .map(|cmd| match cmd {
+                Command::MoveAndExtrude { .. }
+                | Command::MoveAndExtrudeFiber { .. }
+                | Command::MoveAndExtrudeFiberAndCut { .. } => 1,
+                Command::Arc { .. } => panic!("No arcs are fitted"),
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(segments.len(), expected);
+
+        let fiber_commands = result
+            .moves
+            .iter()
+            .filter(|cmd| {
+                matches!(
+                    cmd,
+                    Command::MoveAndExtrudeFiber { .. } | Command::MoveAndExtrudeFiberAndCut { .. }
+                )
+            })
+            .count();
+        let fiber_segments = segments
+            .iter()
+            .filter(|segment| matches!(segment.move_type, MoveType::WithFiber(_)))
+            .count();
+        assert!(fiber_commands > 0);
+        assert_eq!(fiber_segments, fiber_commands);
+
+        assert!(segments
+            .iter()
+            .all(|segment| segment.z > 0.0 && segment.z <= 20.0 + settings.layer_height));
+    }
+
+    #[test]
+    fn thin_part_solid_policy() {
+        let process = Process::new();
+
+        //Fill area of each trace type per layer
+        let fill_area = |policy| {
+            let settings = Settings {
+                layer_height: 0.6,
+                layer_settings: vec![],
+                top_layers: 4,
+                bottom_layers: 4,
+                thin_part_solid_policy: policy,
+                ..Default::default()
+            };
+
+            let result = slice(
+                SliceInput {
+                    objects: vec![cuboid(
+                        Vec3::new(50.0, 50.0, 0.0),
+                        Vec3::new(20.0, 20.0, 2.4),
+                    )],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                &settings,
+                &process,
+            )
+            .unwrap();
+
+            let mut areas: Vec<(f32, f32, f32)> = vec![];
+            for segment in result.extrusion_segments() {
+                if areas.last().map(|(z, _, _)| *z) != Some(segment.z) {
+                    areas.push((segment.z, 0.0, 0.0));
+                }
+                let area = segment.start.euclidean_distance(&segment.end) * segment.width;
+                let last = areas.last_mut().unwrap();
+                match segment.move_type.print_type() {
+                    Some(TraceType::TopSolidInfill) => last.1 += area,
+                    Some(TraceType::SolidInfill) => last.2 += area,
+                    _ => {}
+                }
+            }
+            areas
+        };
+
+        let top = fill_area(ThinPartSolidPolicy::TopWins);
+        let bottom = fill_area(ThinPartSolidPolicy::BottomWins);
+        let both = fill_area(ThinPartSolidPolicy::BothSolid);
+
+        assert_eq!(top.len(), 4);
+        assert_eq!(bottom.len(), 4);
+        assert_eq!(both.len(), 4);
+
+        for layer in 0..4 {
+            let (_, top_surface, solid) = top[layer];
+            assert!(top_surface > 0.0 && solid == 0.0, "{:?}", top[layer]);
+
+            let (_, top_surface, solid) = bottom[layer];
+            assert!(top_surface == 0.0 && solid > 0.0, "{:?}", bottom[layer]);
+
+            //Every policy fills each layer once, never twice
+            let bottom_total = bottom[layer].1 + bottom[layer].2;
+            for (_, top_surface, solid) in [top[layer], both[layer]] {
+                let total = top_surface + solid;
+                assert!(
+                    (total - bottom_total).abs() < bottom_total * 0.15,
+                    "{} {}",
+                    total,
+                    bottom_total
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn partial_z_range_slice() {
+        let settings = Settings::default();
+        let process = Process::new();
+
+        let layers = |z_range| {
+            let result = slice_z_range(
+                SliceInput {
+                    objects: vec![cuboid(
+                        Vec3::new(50.0, 50.0, 0.0),
+                        Vec3::new(10.0, 10.0, 100.0),
+                    )],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                Some(z_range),
+                &settings,
+                &process,
+            )
+            .unwrap();
+
+            result
+                .moves
+                .iter()
+                .filter_map(|cmd| match cmd {
+                    Command::LayerChange { z, index } => Some((*index, *z)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        //The first layer is 0.3mm and the rest 0.6mm, so layer i ends at 0.3 + 0.6 * i
+        let bottom = layers((0.0, 10.0));
+        assert_eq!(bottom.len(), 17);
+        assert!(bottom
+            .iter()
+            .enumerate()
+            .all(|(i, (index, z))| *index == i && (z - (0.3 + 0.6 * i as f32)).abs() < 0.001));
+
+        //Layers keep their index from the full slice
+        let window = layers((5.0, 10.0));
+        assert_eq!(window.first().unwrap().0, 9);
+        assert_eq!(window.last().unwrap().0, 16);
+        assert_eq!(window[..], bottom[9..]);
+    }
+
+    #[test]
+    fn partial_slice_above_the_bed_has_no_first_layer_extras() {
+        let process = Process::new();
+        let extrusions = |settings: &Settings, z_range| {
+            let result = slice_z_range(
+                SliceInput {
+                    objects: vec![cuboid(
+                        Vec3::new(50.0, 50.0, 0.0),
+                        Vec3::new(10.0, 10.0, 100.0),
+                    )],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                Some(z_range),
+                settings,
+                &process,
+            )
+            .unwrap();
+
+            assert_eq!(
+                result.write_gcode_to(std::env::temp_dir().join("partial.gcode")),
+                Err(SlicerErrors::PartialSlice {
+                    min: z_range.0,
+                    max: z_range.1
+                })
+            );
+            result.extrusion_segments().count()
+        };
+
+        let plain = Settings::default();
+        let mut extras = Settings::default();
+        *extras.skirt.enabled_mut() = true;
+        *extras.brim_width.enabled_mut() = true;
+        *extras.brim_width = 3.0;
+
+        //The skirt and brim only go around the layer on the bed
+        assert!(extrusions(&extras, (0.0, 10.0)) > extrusions(&plain, (0.0, 10.0)));
+        assert_eq!(
+            extrusions(&extras, (5.0, 10.0)),
+            extrusions(&plain, (5.0, 10.0))
+        );
+    }
+
+    #[test]
+    fn out_of_range_extrusion_width_is_clamped() {
+        let mut settings = Settings::default();
+        settings.extrusion_width.infill = 2.0;
+        settings.extrusion_width.exterior_surface_perimeter = 0.1;
+        settings.extrusion_width.travel = 3.0;
+        settings.clamp_extrusion_width = true;
+
+        let layer_settings = settings.get_layer_settings(1, 0.9);
+        assert_eq!(layer_settings.extrusion_width.infill, 0.8);
+        assert!((layer_settings.extrusion_width.exterior_surface_perimeter - 0.24).abs() < 0.0001);
+        assert_eq!(layer_settings.extrusion_width.solid_infill, 0.4);
+        //Travel doesn't extrude, so its width is left alone
+        assert_eq!(layer_settings.extrusion_width.travel, 3.0);
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 20.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        //Every plastic only extrusion, and with it the E amount, uses the clamped width
+        assert!(result
+            .extrusion_segments()
+            .filter(|segment| matches!(segment.move_type, MoveType::WithoutFiber(_)))
+            .all(|segment| segment.width > 0.2399 && segment.width < 0.8001));
+
+        //A single warning covers every clamped layer
+        let clamped: Vec<&SlicerWarnings> = result
+            .warnings
+            .iter()
+            .filter(|warning| matches!(warning, SlicerWarnings::ExtrusionWidthClamped { .. }))
+            .collect();
+        assert!(matches!(
+            clamped[..],
+            [SlicerWarnings::ExtrusionWidthClamped { layers, .. }] if *layers > 1
+        ));
+
+        settings.clamp_extrusion_width = false;
+        assert_eq!(
+            settings.get_layer_settings(1, 0.9).extrusion_width.infill,
+            2.0
+        );
+    }
+
+    ///A single mesh of 4x4 pillars so every layer has 16 islands
+    fn pillar_grid() -> ObjectMesh {
+        let (vertices, triangles) = (0..16)
+            .map(|i| {
+                cuboid(
+                    Vec3::new(
+                        40.0 + (i % 4) as f32 * 15.0,
+                        40.0 + (i / 4) as f32 * 15.0,
+                        0.0,
+                    ),
+                    Vec3::new(6.0, 6.0, 3.0),
+                )
+            })
+            .enumerate()
+            .fold(
+                (vec![], vec![]),
+                |(mut vertices, mut triangles), (i, pillar)| {
+                    vertices.extend_from_slice(pillar.vertices());
+                    triangles.extend(pillar.triangles().iter().map(|triangle| {
+                        let mut triangle = *triangle;
+                        (0..3).for_each(|v| triangle[v] += i * 8);
+                        triangle
+                    }));
+                    (vertices, triangles)
+                },
+            );
+        let mut pillars = ObjectMesh::new(vertices, triangles);
+        pillars.sort_indices();
+        pillars
+    }
+
+    #[test]
+    fn minimize_retractions_on_pillar_grid() {
+        let pillars = pillar_grid();
+
+        let slice_pillars = |settings: &Settings| {
+            slice(
+                SliceInput {
+                    objects: vec![pillars.clone()],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                settings,
+                &Process::new(),
+            )
+            .unwrap()
+        };
+        let retractions = |result: &SliceResult| {
+            result
+                .moves
+                .iter()
+                .filter(|cmd| {
+                    matches!(
+                        cmd,
+                        Command::SetState {
+                            new_state: StateChange {
+                                retract: RetractionType::Retract | RetractionType::MoveRetract(_),
+                                ..
+                            }
+                        }
+                    )
+                })
+                .count()
+        };
+        let extruded_length = |result: &SliceResult| {
+            result
+                .extrusion_segments()
+                .map(|segment| segment.start.euclidean_distance(&segment.end))
+                .sum::<f32>()
+        };
+
+        let mut settings = Settings::default();
+        let before = slice_pillars(&settings);
+
+        settings.minimize_retractions = true;
+        let after = slice_pillars(&settings);
+
+        assert!(
+            retractions(&after) < retractions(&before),
+            "{} retractions after, {} before",
+            retractions(&after),
+            retractions(&before)
+        );
+
+        //Same moves, just in a different order
+        let (length_before, length_after) = (extruded_length(&before), extruded_length(&after));
+        assert!((length_before - length_after).abs() < length_before * 0.001);
+        assert_eq!(
+            before
+                .moves
+                .iter()
+                .filter(|cmd| matches!(cmd, Command::LayerChange { .. }))
+                .count(),
+            after
+                .moves
+                .iter()
+                .filter(|cmd| matches!(cmd, Command::LayerChange { .. }))
+                .count()
+        );
+    }
+
+    #[test]
+    fn empty_input_is_an_error() {
+        let result = slice(
+            SliceInput {
+                objects: vec![],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &Settings::default(),
+            &Process::new(),
+        );
+
+        assert_eq!(result.unwrap_err(), SlicerErrors::NoObjects);
+    }
+
+    #[test]
+    fn mask_only_input_is_an_error() {
+        let mask = Mask::new(
+            cube(Vec3::new(50.0, 50.0, 0.0), 10.0),
+            MaskSettings::default(),
+        );
+
+        let result = slice(
+            SliceInput {
+                objects: vec![],
+                masks: vec![mask],
+                modifiers: vec![],
+            },
+            &Settings::default(),
+            &Process::new(),
+        );
+
+        assert_eq!(result.unwrap_err(), SlicerErrors::NoObjects);
+    }
+
+    #[test]
+    fn first_layer_coverage_matches_first_layer_area() {
+        let mut settings = Settings::default();
+        let objects = vec![
+            cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+            cuboid(Vec3::new(60.0, 40.0, 0.0), Vec3::new(15.0, 5.0, 8.0)),
+        ];
+
+        let coverage = first_layer_coverage(&objects, &settings).unwrap();
+
+        let towers = create_towers(&objects).unwrap();
+        let first_layers: f32 = slicing::slice(&towers, 10.0, None, None, &settings)
+            .unwrap()
+            .iter()
+            .map(|object| object.layers[0].main_polygon.unsigned_area())
+            .sum();
+
+        assert!((coverage.unsigned_area() - first_layers).abs() < 0.01);
+        assert!((coverage.unsigned_area() - 175.0).abs() < 0.5);
+
+        //The skirt touches the bed as well
+        settings.skirt.layers = 1;
+        settings.skirt.distance = 5.0;
+        *settings.skirt.enabled_mut() = true;
+        let with_skirt = first_layer_coverage(&objects, &settings).unwrap();
+        assert!(with_skirt.unsigned_area() > coverage.unsigned_area() + 1.0);
+    }
+
+    #[test]
+    fn report_round_trips_through_json() {
+        let settings = Settings::default();
+        let result = slice(
+            SliceInput {
+                objects: vec![
+                    cube(Vec3::new(20.0, 20.0, 0.0), 10.0),
+                    cube(Vec3::new(25.0, 25.0, 0.0), 10.0),
+                ],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        let report = result.report();
+        assert_eq!(report.version, SLICE_REPORT_VERSION);
+        assert!(report.warnings.contains(&SlicerWarnings::ObjectsOverlap {
+            first: 0,
+            second: 1
+        }));
+
+        let json = serde_json::to_string_pretty(&report).unwrap();
+        let parsed: SliceReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&report).unwrap()
+        );
+
+        //Json is valid hjson, so reports can be read back the same way as settings files
+        let parsed: SliceReport = deser_hjson::from_str(&json).unwrap();
+        assert_eq!(parsed.warnings, report.warnings);
+        assert_eq!(
+            parsed.calculated_values.total_time,
+            report.calculated_values.total_time
+        );
+    }
+
+    #[test]
+    fn bed_type_adjusts_the_first_layer() {
+        let mut settings = Settings::default();
+        settings.layer_settings.clear();
+        settings.filament.bed_temp = 60.0;
+        settings.fan.fan_speed = 100.0;
+        settings.fan.disable_fan_for_layers = 0;
+
+        settings.bed_type = BedType::SmoothPei;
+        let smooth = settings.get_layer_settings(0, 0.0);
+        assert_eq!(smooth.bed_temp, 60.0);
+        assert_eq!(smooth.fan_speed, 100.0);
+        assert_eq!(smooth.z_offset, 0.0);
+
+        settings.bed_type = BedType::TexturedPei;
+        let textured = settings.get_layer_settings(0, 0.0);
+        assert_eq!(textured.bed_temp, 65.0);
+        assert!((textured.z_offset + 0.02).abs() < 0.0001);
+
+        settings.bed_type = BedType::Glass;
+        let glass = settings.get_layer_settings(0, 0.0);
+        assert_eq!(glass.bed_temp, 70.0);
+        assert_eq!(glass.fan_speed, 0.0);
+
+        //Only the first layer is adjusted
+        let second = settings.get_layer_settings(1, 0.5);
+        assert_eq!(second.bed_temp, 60.0);
+        assert_eq!(second.fan_speed, 100.0);
+        assert_eq!(second.z_offset, 0.0);
+
+        //Values set for the first layer win over the bed type
+        settings.layer_settings.push((
+            LayerRange::SingleLayer(0),
+            PartialLayerSettings {
+                bed_temp: Some(55.0),
+                fan_speed: Some(50.0),
+                z_offset: Some(0.05),
+                ..Default::default()
+            },
+        ));
+        let overridden = settings.get_layer_settings(0, 0.0);
+        assert_eq!(overridden.bed_temp, 55.0);
+        assert_eq!(overridden.fan_speed, 50.0);
+        assert_eq!(overridden.z_offset, 0.05);
+    }
+
+    #[test]
+    fn invalid_settings_stop_the_slice() {
+        let mut settings = Settings::default();
+        settings.inner_walls_every_layers = 0;
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 10.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SlicerErrors::SettingLessThanOrEqualToZero { setting, .. })
+                if setting == "inner_walls_every_layers"
+        ));
+    }
+
+    #[test]
+    fn extrusion_width_ramps_over_first_layers() {
+        let mut settings = Settings::default();
+        settings.width_ramp_layers = 4;
+        settings.first_layer_width_multiplier = 1.5;
+
+        let width = |layer: usize| settings.get_layer_settings(layer, 0.0).extrusion_width;
+        assert!((width(0).infill - 0.6).abs() < 0.0001);
+        assert!((width(2).infill - 0.5).abs() < 0.0001);
+        assert!((width(2).bridge - 0.5).abs() < 0.0001);
+        assert!((width(4).infill - 0.4).abs() < 0.0001);
+        assert!((width(10).infill - 0.4).abs() < 0.0001);
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 10.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        //The walls are printed, and with it the E amount calculated, with the ramped width
+        let wall_widths = |z: f32| {
+            result
+                .extrusion_segments()
+                .filter(|segment| (segment.z - z).abs() < 0.001)
+                .filter(|segment| segment.move_type == MoveType::WithoutFiber(TraceType::WallOuter))
+                .map(|segment| segment.width)
+                .collect::<Vec<_>>()
+        };
+
+        for (z, expected) in [(0.3, 0.6), (1.5, 0.5), (3.3, 0.4)] {
+            let widths = wall_widths(z);
+            assert!(!widths.is_empty(), "No walls at {z}");
+            assert!(
+                widths.iter().all(|width| (width - expected).abs() < 0.0001),
+                "{widths:?} at {z}"
+            );
+        }
+    }
+
+    #[test]
+    fn outline_only_prints_a_single_perimeter() {
+        let mut settings = Settings::default();
+        settings.outline_only = true;
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 10.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        assert!(result.extrusion_segments().all(|segment| matches!(
+            segment.move_type,
+            MoveType::WithoutFiber(TraceType::WallOuter)
+        )));
+
+        //One loop around the 10mm cube per layer, first layer included
+        let layers = result
+            .extrusion_segments()
+            .into_group_map_by(|segment| (segment.z * 1000.0).round() as i32);
+        let layer_changes = result
+            .moves
+            .iter()
+            .filter(|cmd| matches!(cmd, Command::LayerChange { .. }))
+            .count();
+        assert_eq!(layers.len(), layer_changes);
+        assert!(layers.contains_key(&300));
+
+        for (z, segments) in layers {
+            let length: f32 = segments
+                .iter()
+                .map(|segment| segment.start.euclidean_distance(&segment.end))
+                .sum();
+            assert!((length - 38.4).abs() < 1.0, "{length} at {z}");
+        }
+
+        //Written through the regular gcode path
+        let mut writer = gcode::mem::GCodeMemoryWriter::new();
+        let navigator = gcode::write_gcode(&result.moves, &settings, &mut writer).unwrap();
+        let gcode = writer.finish(navigator).gcode;
+        assert!(gcode
+            .lines()
+            .any(|line| line.starts_with("G1") && line.contains('E')));
+    }
+
+    #[test]
+    fn spiralize_ramps_a_single_wall() {
+        let mut settings = Settings::default();
+        settings.spiralize = true;
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(50.0, 50.0, 0.0), 10.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        assert!(result.extrusion_segments().all(|segment| matches!(
+            segment.move_type,
+            MoveType::WithoutFiber(TraceType::WallOuter)
+        )));
+
+        let top = result
+            .moves
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Command::LayerChange { z, .. } => Some(*z),
+                _ => None,
+            })
+            .fold(0.0, f32::max);
+
+        let mut writer = gcode::mem::GCodeMemoryWriter::new();
+        let navigator = gcode::write_gcode(&result.moves, &settings, &mut writer).unwrap();
+        let gcode = writer.finish(navigator).gcode;
+
+        //Past the flat first layer every extrusion carries its own, rising height
+        let heights: Vec<f32> = gcode
+            .lines()
+            .filter(|line| line.starts_with("G1 X") && line.contains(" E"))
+            .filter_map(|line| {
+                line.split_whitespace()
+                    .find_map(|word| word.strip_prefix('Z'))
+                    .and_then(|z| z.parse().ok())
+            })
+            .collect();
+
+        assert!(heights.len() > 100);
+        assert!(heights
+            .iter()
+            .tuple_windows()
+            .all(|(a, b)| *b >= *a - 0.0001));
+        assert!(heights.iter().dedup().count() > heights.len() / 2);
+        assert!((heights.last().unwrap() - top).abs() < 0.001);
+
+        //Two outlines on a layer can't be joined into one spiral
+        let (vertices, triangles) = [
+            cube(Vec3::new(50.0, 50.0, 0.0), 10.0),
+            cube(Vec3::new(70.0, 50.0, 0.0), 10.0),
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(
+            (vec![], vec![]),
+            |(mut vertices, mut triangles), (i, cube)| {
+                vertices.extend_from_slice(cube.vertices());
+                triangles.extend(cube.triangles().iter().map(|triangle| {
+                    let mut triangle = *triangle;
+                    (0..3).for_each(|v| triangle[v] += i * 8);
+                    triangle
+                }));
+                (vertices, triangles)
+            },
+        );
+        let mut cubes = ObjectMesh::new(vertices, triangles);
+        cubes.sort_indices();
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cubes],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        );
+        assert!(matches!(
+            result,
+            Err(SlicerErrors::SpiralizeContourCount {
+                layer: 0,
+                contours: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn excessive_retractions_are_warned_about() {
+        let mut settings = Settings::default();
+        *settings.max_retractions_per_layer.enabled_mut() = true;
+        *settings.max_retractions_per_layer = 10;
+
+        let slice_pillars = |settings: &Settings| {
+            slice(
+                SliceInput {
+                    objects: vec![pillar_grid()],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                settings,
+                &Process::new(),
+            )
+            .unwrap()
+        };
+
+        let result = slice_pillars(&settings);
+        let retractions = calculation::retractions_per_layer(&result.moves);
+
+        let excessive: Vec<(usize, usize)> = result
+            .warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                SlicerWarnings::ExcessiveRetraction { layer, count } => Some((*layer, *count)),
+                _ => None,
+            })
+            .collect();
+
+        //Every layer has 16 islands, so the worst layers are reported, worst first
+        assert!(!excessive.is_empty());
+        assert!(excessive.len() <= 5);
+        assert!(excessive.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        let worst = retractions.iter().map(|(_, count)| *count).max().unwrap();
+        assert_eq!(excessive[0].1, worst);
+        assert!(excessive
+            .iter()
+            .all(|(layer, count)| count > &10 && retractions.get(layer) == Some(count)));
+
+        *settings.max_retractions_per_layer = 1000;
+        assert!(slice_pillars(&settings)
+            .warnings
+            .iter()
+            .all(|warning| !matches!(warning, SlicerWarnings::ExcessiveRetraction { .. })));
+    }
+
+    #[test]
+    fn retractions_of_separate_objects_add_up_per_layer() {
+        let result = slice(
+            SliceInput {
+                objects: vec![
+                    cuboid(Vec3::new(40.0, 40.0, 0.0), Vec3::new(6.0, 6.0, 3.0)),
+                    cuboid(Vec3::new(100.0, 40.0, 0.0), Vec3::new(6.0, 6.0, 3.0)),
+                ],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &Settings::default(),
+            &Process::new(),
+        )
+        .unwrap();
+
+        let layer_changes = result
+            .moves
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Command::LayerChange { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let total = result
+            .moves
+            .iter()
+            .filter(|cmd| {
+                matches!(
+                    cmd,
+                    Command::SetState {
+                        new_state: StateChange {
+                            retract: RetractionType::Retract | RetractionType::MoveRetract(_),
+                            ..
+                        }
+                    }
+                )
+            })
+            .count();
+
+        //Both objects change to every layer, but each layer is counted once
+        let retractions = calculation::retractions_per_layer(&result.moves);
+        assert!(layer_changes.len() > retractions.len());
+        assert_eq!(
+            retractions.keys().copied().collect::<Vec<_>>(),
+            layer_changes
+                .into_iter()
+                .unique()
+                .sorted()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(retractions.values().sum::<usize>(), total);
+    }
+
+    #[test]
+    fn modifier_mesh_overrides_infill_in_its_volume() {
+        let settings = Settings::default();
+        let modifier = |infill_percentage: f32, priority: i32| {
+            ModifierMesh::new(
+                cuboid(Vec3::new(30.0, 30.0, 0.0), Vec3::new(20.0, 40.0, 20.0)),
+                PartialSettings {
+                    infill_percentage: Some(infill_percentage),
+                    ..Default::default()
+                },
+                priority,
+            )
+        };
+
+        //The lower priority modifier is declared first and covers the same volume
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(40.0, 40.0, 0.0), 20.0)],
+                masks: vec![],
+                modifiers: vec![modifier(0.05, 0), modifier(0.8, 1)],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        //Infill length on either side of the modifiers edge at x = 50, away from the solid layers
+        let (inside, outside) = result
+            .extrusion_segments()
+            .filter(|segment| segment.z > 5.0 && segment.z < 15.0)
+            .filter(|segment| segment.move_type == MoveType::WithoutFiber(TraceType::Infill))
+            .fold((0.0, 0.0), |(inside, outside), segment| {
+                let length = segment.start.euclidean_distance(&segment.end);
+                if (segment.start.x + segment.end.x) / 2.0 < 50.0 {
+                    (inside + length, outside)
+                } else {
+                    (inside, outside + length)
+                }
+            });
+
+        assert!(outside > 0.0);
+        assert!(
+            inside > 2.5 * outside,
+            "Infill inside {inside} outside {outside}"
+        );
+    }
+
+    #[test]
+    fn infill_line_spacing_follows_density() {
+        let settings = Settings::default();
+        let layer_settings = settings.get_layer_settings(1, 1.2);
+        let width = layer_settings.extrusion_width.infill;
+
+        //Solid linear infill puts the lines one extrusion width apart
+        assert_eq!(
+            layer_settings.partial_infill_line_spacing(PartialInfillTypes::Linear, 1.0),
+            Some(width)
+        );
+        assert_eq!(
+            layer_settings.partial_infill_line_spacing(PartialInfillTypes::Linear, 0.25),
+            Some(width * 4.0)
+        );
+
+        //Each of the three directions of the triangle pattern carries a third of the density
+        assert_eq!(
+            layer_settings.partial_infill_line_spacing(PartialInfillTypes::Triangle, 1.0),
+            Some(width * 3.0)
+        );
+        assert_eq!(
+            layer_settings.partial_infill_line_spacing(PartialInfillTypes::Lightning, 1.0),
+            None
+        );
+        assert_eq!(layer_settings.infill_line_spacing(1.0), Some(width));
+    }
+
+    #[test]
+    fn adaptive_layers_follow_surface_slope() {
+        let mut settings = Settings::default();
+        *settings.adaptive_layer_height.enabled_mut() = true;
+        let first_layer_height = settings.get_layer_settings(0, 0.0).layer_height;
+
+        //A shallow pyramid, its sides rise 4mm over 20mm
+        let pyramid = {
+            let vertices = [
+                (0.0, 0.0, 0.0),
+                (40.0, 0.0, 0.0),
+                (40.0, 40.0, 0.0),
+                (0.0, 40.0, 0.0),
+                (20.0, 20.0, 4.0),
+            ]
+            .into_iter()
+            .map(|(x, y, z)| ObjectVertex::new(x + 20.0, y + 20.0, z))
+            .collect();
+
+            let triangles = [
+                [0, 2, 1],
+                [0, 3, 2],
+                [0, 1, 4],
+                [1, 2, 4],
+                [2, 3, 4],
+                [3, 0, 4],
+            ]
+            .into_iter()
+            .map(|triangle| triangle.into())
+            .collect();
+
+            let mut mesh = ObjectMesh::new(vertices, triangles);
+            mesh.sort_indices();
+            mesh
+        };
+
+        let heights = |mesh: &ObjectMesh, max_height: f32| {
+            let towers = create_towers(&[mesh.clone()]).unwrap();
+            slicing::adaptive_layer_heights(&towers, max_height, &settings)
+                .unwrap()
+                .unwrap()
+        };
+
+        //Vertical walls use the thickest layers
+        let cube_heights = heights(&cube(Vec3::new(20.0, 20.0, 0.0), 10.0), 10.0);
+        assert_eq!(cube_heights[0], first_layer_height);
+        assert!(cube_heights[1..]
+            .iter()
+            .all(|height| (height - settings.adaptive_layer_height.max_height).abs() < 1e-6));
+
+        //Shallow slopes use thinner layers
+        let pyramid_heights = heights(&pyramid, 4.0);
+        assert_eq!(pyramid_heights[0], first_layer_height);
+        assert!(pyramid_heights[1..].iter().all(|height| {
+            *height >= settings.adaptive_layer_height.min_height
+                && *height < settings.adaptive_layer_height.max_height
+        }));
+
+        //The sliced layers use the heights without gaps
+        let towers = create_towers(&[pyramid.clone()]).unwrap();
+        let objects =
+            slicing::slice(&towers, 4.0, None, Some(&pyramid_heights), &settings).unwrap();
+        let layers = &objects[0].layers;
+        assert!(layers
+            .windows(2)
+            .all(|pair| pair[0].top_height == pair[1].bottom_height));
+        assert!(layers
+            .iter()
+            .zip(&pyramid_heights)
+            .all(|(layer, height)| (layer.top_height - layer.bottom_height - height).abs() < 1e-4));
+
+        //More, thinner layers take longer to print
+        let total_time = |settings: &Settings| {
+            slice(
+                SliceInput {
+                    objects: vec![pyramid.clone()],
+                    masks: vec![],
+                    modifiers: vec![],
+                },
+                settings,
+                &Process::new(),
+            )
+            .unwrap()
+            .calculated_values
+            .total_time
+        };
+        let mut fixed = settings.clone();
+        *fixed.adaptive_layer_height.enabled_mut() = false;
+        assert!(total_time(&settings) > total_time(&fixed));
+    }
+
+    #[test]
+    fn center_of_mass_of_symmetric_cube() {
+        let settings = Settings::default();
+
+        let result = slice(
+            SliceInput {
+                objects: vec![cube(Vec3::new(100.0, 100.0, 0.0), 10.0)],
+                masks: vec![],
+                modifiers: vec![],
+            },
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        let center = result.calculated_values.center_of_mass;
+        assert!(
+            center.distance(Vec3::new(105.0, 105.0, 5.0)) < 0.3,
+            "center of mass {center} is off the center of the cube"
+        );
+
+        //Nothing printed falls back to the middle of the bed
+        assert_eq!(
+            calculation::calculate_values(&[], &settings).center_of_mass,
+            Vec3::new(settings.print_x / 2.0, settings.print_y / 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn fiber_replaces_plastic_in_the_center_of_mass() {
+        let mut settings = Settings::default();
+        settings.fiber.density = settings.filament.density;
+
+        let line = |y: f32| (Coord { x: 0.0, y }, Coord { x: 10.0, y });
+        let ((plastic_start, plastic_end), (fiber_start, fiber_end)) = (line(0.0), line(10.0));
+        let moves = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            Command::SetState {
+                new_state: StateChange {
+                    movement_speed: Some(10.0),
+                    ..Default::default()
+                },
+            },
+            Command::MoveAndExtrude {
+                id: None,
+                start: plastic_start,
+                end: plastic_end,
+                thickness: 0.2,
+                width: 0.8,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+            Command::MoveAndExtrudeFiber {
+                id: None,
+                start: fiber_start,
+                end: fiber_end,
+                thickness: 0.2,
+                width: 0.8,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+
+        //A fiber as dense as the plastic weighs the same as the plastic bead it sits in
+        let center = calculation::calculate_values(&moves, &settings).center_of_mass;
+        assert!(
+            center.distance(Vec3::new(5.0, 5.0, 0.1)) < 0.001,
+            "center of mass {center} is pulled towards the fiber"
+        );
+    }
+
+    #[test]
+    fn tiny_layer_height_hits_layer_cap() {
+        let settings = Settings {
+            layer_height: 0.0001,
+            ..Default::default()
+        };
+        let towers = create_towers(&[cuboid(
+            Vec3::new(20.0, 20.0, 0.0),
+            Vec3::new(10.0, 10.0, 200.0),
+        )])
+        .unwrap();
+
+        let error = slicing::slice(&towers, 200.0, None, None, &settings).unwrap_err();
+        match &error {
+            SlicerErrors::TooManyLayers { computed, cap } => {
+                assert!(*computed > 1_000_000, "{}", computed);
+                assert_eq!(*cap, settings.max_layers);
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+        assert!(error.to_string().contains("layer_height"));
+
+        //Raising the cap is the way out for prints that really need that many layers
+        let settings = Settings {
+            layer_height: 0.1,
+            max_layers: 1000,
+            ..Default::default()
+        };
+        assert!(slicing::slice(&towers, 200.0, None, None, &settings).is_err());
+        let settings = Settings {
+            max_layers: 3000,
+            ..settings
+        };
+        assert!(slicing::slice(&towers, 200.0, None, None, &settings).is_ok());
+
+        //Adaptive layers are capped as well, without sampling the tower at the tiny height
+        let mut settings = Settings {
+            max_layers: 1000,
+            ..Default::default()
+        };
+        *settings.adaptive_layer_height.enabled_mut() = true;
+        settings.adaptive_layer_height.min_height = 0.0001;
+        settings.adaptive_layer_height.max_height = 0.0001;
+
+        let error = slicing::adaptive_layer_heights(&towers, 200.0, &settings).unwrap_err();
+        assert!(matches!(
+            error,
+            SlicerErrors::TooManyLayers { cap: 1000, .. }
+        ));
+    }
+
+    #[test]
+    fn coasting_follows_the_wall_after_optimizing() {
+        let mut settings = Settings::default();
+        *settings.coasting.enabled_mut() = true;
+        settings.coasting.volume = 2.0;
+        settings.coasting.min_volume_before_coast = 0.0;
+
+        //The last side of the 0.4 wide loop holds 1.6 mm³, so the coast starts on the side before
+        let mut slice = Slice::from_single_point_loop(
+            vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+            0.0,
+            0.2,
+            0,
+            &settings,
+        );
+        slice.fixed_chains = vec![MoveChain {
+            start_point: Coord { x: 0.0, y: 0.0 },
+            moves: [(20.0, 0.0), (20.0, 20.0), (0.0, 20.0), (0.0, 0.0)]
+                .into_iter()
+                .map(|(x, y)| Move {
+                    end: Coord { x, y },
+                    width: 0.4,
+                    move_type: MoveType::WithoutFiber(TraceType::WallOuter),
+                })
+                .collect(),
+            is_loop: true,
+        }];
+        slice.chains = vec![];
+
+        let object = Object {
+            layers: vec![slice],
+            extruder: 0,
+        };
+        let object_outlines = vec![slicing::ObjectOutline::new(&object)];
+        let object_moves = vec![convert_object_into_moves(0, object, &settings)];
+        let result = finish_slice(
+            object_moves,
+            object_outlines,
+            0.2,
+            None,
+            vec![],
+            &settings,
+            &Process::new(),
+        )
+        .unwrap();
+
+        //The coast turns the corner instead of cutting across the loop
+        let travels: Vec<Coord<f32>> = result
+            .moves
+            .iter()
+            .skip_while(|cmd| !matches!(cmd, Command::MoveAndExtrude { .. }))
+            .filter_map(|cmd| match cmd {
+                Command::MoveTo { end } => Some(*end),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            travels,
+            vec![Coord { x: 0.0, y: 20.0 }, Coord { x: 0.0, y: 0.0 }]
+        );
+    }
+}
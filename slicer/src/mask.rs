@@ -1,8 +1,7 @@
 use std::ops::{Deref, DerefMut};
 
-use geo::{Area, HasDimensions, Simplify};
+use geo::{Area, BoundingRect, Intersects, Rect, Simplify};
 use glam::{Mat4, Vec3};
-use log::info;
 use shared::object::ObjectMesh;
 
 use crate::{
@@ -47,7 +46,13 @@ impl Mask {
         self.mesh.transform(transform);
     }
 
-    pub fn into_object(self, max: Vec3, settings: &Settings) -> Result<ObjectMask, SlicerErrors> {
+    pub fn into_object(
+        self,
+        max: Vec3,
+        z_range: Option<(f32, f32)>,
+        layer_heights: Option<&[f32]>,
+        settings: &Settings,
+    ) -> Result<ObjectMask, SlicerErrors> {
         let tower = TriangleTower::from_triangles_and_vertices(
             self.mesh.triangles(),
             self.mesh.vertices().to_vec(),
@@ -55,7 +60,7 @@ impl Mask {
 
         let settings = self.settings.clone().combine_settings(settings.clone());
 
-        let obj = slicing::slice_single(&tower, max.z, &settings)?;
+        let obj = slicing::slice_single(&tower, max.z, z_range, layer_heights, &settings)?;
 
         Ok(ObjectMask {
             obj,
@@ -64,6 +69,47 @@ impl Mask {
     }
 }
 
+///Bounding boxes of the object layers, grouped by layer index, so masks are only cropped against
+///the object layers they can overlap. Empty object layers are left out.
+pub struct LayerBounds {
+    layers: Vec<Vec<(usize, Rect<f32>)>>,
+}
+
+impl LayerBounds {
+    pub fn new(objects: &[Object]) -> Self {
+        let layer_count = objects
+            .iter()
+            .map(|object| object.layers.len())
+            .max()
+            .unwrap_or(0);
+
+        let layers = (0..layer_count)
+            .map(|index| {
+                objects
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(object_index, object)| {
+                        let rect = object.layers.get(index)?.main_polygon.bounding_rect()?;
+                        Some((object_index, rect))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { layers }
+    }
+
+    ///Indices of the objects whose layer at `index` overlaps `rect`, in object order
+    fn overlapping(&self, index: usize, rect: Rect<f32>) -> impl Iterator<Item = usize> + '_ {
+        self.layers
+            .get(index)
+            .into_iter()
+            .flatten()
+            .filter(move |(_, bounds)| bounds.intersects(&rect))
+            .map(|(object_index, _)| *object_index)
+    }
+}
+
 pub struct ObjectMask {
     obj: Object,
     settings: MaskSettings,
@@ -88,22 +134,19 @@ impl ObjectMask {
         &self.settings
     }
 
-    pub fn crop(&mut self, objects: &[Object], max: Vec3) {
+    pub fn crop(&mut self, objects: &[Object], bounds: &LayerBounds, max: Vec3) {
         self.layers
             .iter_mut()
             .enumerate()
             .for_each(|(index, layer)| {
                 let mut remaining_polygon = layer.main_polygon.clone();
-                for object in objects.iter() {
-                    if let Some(layer) = object.layers.get(index) {
-                        if layer.main_polygon.is_empty() {
-                            info!("Layer is empty, skipping {}", index);
-                            info!("Area: {}", layer.main_polygon.unsigned_area());
-                            continue;
-                        }
-
-                        remaining_polygon =
-                            remaining_polygon.difference_with(&layer.main_polygon.simplify(&0.2));
+
+                //Object layers outside the mask layers bounds can't overlap it
+                if let Some(mask_bounds) = layer.main_polygon.bounding_rect() {
+                    for object in bounds.overlapping(index, mask_bounds) {
+                        remaining_polygon = remaining_polygon.difference_with(
+                            &objects[object].layers[index].main_polygon.simplify(&0.2),
+                        );
                     }
                 }
 
@@ -125,3 +168,128 @@ impl ObjectMask {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::Slice;
+
+    fn square(x: f32, y: f32, size: f32, layer: usize, settings: &Settings) -> Slice {
+        Slice::from_single_point_loop(
+            vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)].into_iter(),
+            layer as f32 * 0.2,
+            (layer + 1) as f32 * 0.2,
+            layer,
+            settings,
+        )
+    }
+
+    //A grid of small pillars with masks scattered over and between them
+    fn scene(grid: usize, masks: usize, layers: usize) -> (Vec<Object>, Vec<ObjectMask>) {
+        let settings = Settings::default();
+
+        let objects = (0..grid * grid)
+            .map(|i| Object {
+                layers: (0..layers)
+                    .map(|layer| {
+                        let (x, y) = ((i % grid) as f32 * 5.0, (i / grid) as f32 * 5.0);
+                        square(x, y, 3.0, layer, &settings)
+                    })
+                    .collect(),
+                extruder: 0,
+            })
+            .collect();
+
+        let masks = (0..masks)
+            .map(|i| {
+                let x = (i * 7 % (grid * 5)) as f32 + 0.5;
+                let y = (i * 3 % (grid * 5)) as f32 + 0.5;
+
+                ObjectMask {
+                    obj: Object {
+                        layers: (0..layers)
+                            .map(|layer| square(x, y, 2.5, layer, &settings))
+                            .collect(),
+                        extruder: 0,
+                    },
+                    settings: MaskSettings::default(),
+                }
+            })
+            .collect();
+
+        (objects, masks)
+    }
+
+    fn crop_brute_force(mask: &mut ObjectMask, objects: &[Object]) {
+        for (index, layer) in mask.layers.iter_mut().enumerate() {
+            let mut remaining_polygon = layer.main_polygon.clone();
+            for object in objects {
+                if let Some(object_layer) = object.layers.get(index) {
+                    remaining_polygon = remaining_polygon
+                        .difference_with(&object_layer.main_polygon.simplify(&0.2));
+                }
+            }
+
+            layer.main_polygon = layer.main_polygon.difference_with(&remaining_polygon);
+        }
+    }
+
+    #[test]
+    fn crop_matches_brute_force() {
+        let (objects, mut masks) = scene(6, 40, 3);
+        let (_, mut expected) = scene(6, 40, 3);
+        let bounds = LayerBounds::new(&objects);
+        let max = Vec3::new(30.0, 30.0, 10.0);
+
+        for (mask, expected) in masks.iter_mut().zip(expected.iter_mut()) {
+            mask.crop(&objects, &bounds, max);
+            crop_brute_force(expected, &objects);
+
+            assert_eq!(mask.layers.len(), expected.layers.len());
+            for (layer, expected) in mask.layers.iter().zip(expected.layers.iter()) {
+                let difference = layer.main_polygon.xor_with(&expected.main_polygon);
+                assert!(difference.unsigned_area() < 1e-6);
+            }
+        }
+
+        //Some masks are cropped away entirely, others keep part of their area
+        let areas: Vec<f32> = masks
+            .iter()
+            .map(|mask| mask.layers[0].main_polygon.unsigned_area())
+            .collect();
+        assert!(areas.iter().any(|area| *area < f32::EPSILON));
+        assert!(areas.iter().any(|area| *area > 1.0));
+    }
+
+    //The layer bounds have to beat cropping against every object on a large scene. Run with
+    //`cargo test --release -- --ignored crop_benchmark`
+    #[test]
+    #[ignore]
+    fn crop_benchmark() {
+        let (objects, mut masks) = scene(20, 200, 10);
+        let (_, mut expected) = scene(20, 200, 10);
+        let max = Vec3::new(100.0, 100.0, 10.0);
+
+        let start = Instant::now();
+        expected
+            .iter_mut()
+            .for_each(|mask| crop_brute_force(mask, &objects));
+        let brute_force = start.elapsed();
+
+        let start = Instant::now();
+        let bounds = LayerBounds::new(&objects);
+        masks
+            .iter_mut()
+            .for_each(|mask| mask.crop(&objects, &bounds, max));
+        let indexed = start.elapsed();
+
+        assert!(
+            indexed < brute_force,
+            "With layer bounds {:?}, brute force {:?}",
+            indexed,
+            brute_force
+        );
+    }
+}
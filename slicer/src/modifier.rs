@@ -0,0 +1,160 @@
+use std::ops::{Deref, DerefMut};
+
+use geo::{HasDimensions, MultiPolygon};
+use glam::{Mat4, Vec3};
+use shared::object::ObjectMesh;
+
+use crate::{
+    error::SlicerErrors, plotter::polygon_operations::PolygonOperations, slicing,
+    tower::TriangleTower, LayerSettings, Object, PartialSettings, Settings, Slice,
+};
+
+///A mesh that overrides settings for the infill of the objects inside its volume. Where
+///modifiers overlap, the one with the highest priority wins.
+#[derive(Debug, Clone)]
+pub struct ModifierMesh {
+    mesh: ObjectMesh,
+    settings: PartialSettings,
+    priority: i32,
+}
+
+impl Deref for ModifierMesh {
+    type Target = ObjectMesh;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mesh
+    }
+}
+
+impl DerefMut for ModifierMesh {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.mesh
+    }
+}
+
+impl ModifierMesh {
+    pub fn new(mesh: ObjectMesh, settings: PartialSettings, priority: i32) -> Self {
+        Self {
+            mesh,
+            settings,
+            priority,
+        }
+    }
+
+    pub fn settings(&self) -> &PartialSettings {
+        &self.settings
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub fn transform(&mut self, transform: Mat4) {
+        self.mesh.transform(transform);
+    }
+
+    ///Slice the modifier with the objects settings, so its layers line up with the objects layers
+    pub fn into_object(
+        self,
+        max: Vec3,
+        z_range: Option<(f32, f32)>,
+        layer_heights: Option<&[f32]>,
+        settings: &Settings,
+    ) -> Result<ObjectModifier, SlicerErrors> {
+        let tower = TriangleTower::from_triangles_and_vertices(
+            self.mesh.triangles(),
+            self.mesh.vertices().to_vec(),
+        )?;
+
+        let obj = slicing::slice_single(&tower, max.z, z_range, layer_heights, settings)?;
+
+        Ok(ObjectModifier {
+            obj,
+            settings: self.settings.override_settings(settings.clone()),
+            priority: self.priority,
+        })
+    }
+}
+
+pub struct ObjectModifier {
+    obj: Object,
+    settings: Settings,
+    priority: i32,
+}
+
+///The part of a layer covered by a modifier and the settings used to fill it
+#[derive(Debug, Clone)]
+pub struct ModifierRegion {
+    pub area: MultiPolygon<f32>,
+    pub layer_settings: LayerSettings,
+}
+
+///Attach the modifier regions to every object layer they overlap, highest priority first.
+///Modifiers with the same priority keep the order they were declared in.
+pub fn apply_modifiers(objects: &mut [Object], mut modifiers: Vec<ObjectModifier>) {
+    modifiers.sort_by_key(|modifier| std::cmp::Reverse(modifier.priority));
+
+    for slice in objects
+        .iter_mut()
+        .flat_map(|object| object.layers.iter_mut())
+    {
+        slice.modifier_regions = modifiers
+            .iter()
+            .filter_map(|modifier| {
+                let layers = &modifier.obj.layers;
+                let layer = layers
+                    .binary_search_by_key(&slice.layer, |layer| layer.layer)
+                    .ok()
+                    .map(|index| &layers[index])?;
+
+                let area = layer.main_polygon.intersection_with(&slice.main_polygon);
+                if area.is_empty() {
+                    return None;
+                }
+
+                Some(ModifierRegion {
+                    area,
+                    layer_settings: modifier
+                        .settings
+                        .get_layer_settings(slice.layer, slice.get_height()),
+                })
+            })
+            .collect();
+    }
+}
+
+///Run `fill` on the parts of the remaining area covered by each modifier region using the regions
+///settings, and then on the rest of the remaining area with the slices own settings. Whatever
+///`fill` leaves unfilled stays in the remaining area.
+pub fn fill_with_modifiers(slice: &mut Slice, fill: impl Fn(&mut Slice)) {
+    if slice.modifier_regions.is_empty() {
+        fill(slice);
+        return;
+    }
+
+    let regions = std::mem::take(&mut slice.modifier_regions);
+    let layer_settings = slice.layer_settings.clone();
+
+    let mut unclaimed = std::mem::replace(&mut slice.remaining_area, MultiPolygon(vec![]));
+    let mut unfilled = MultiPolygon(vec![]);
+
+    for region in &regions {
+        slice.remaining_area = unclaimed.intersection_with(&region.area);
+        unclaimed = unclaimed.difference_with(&region.area);
+
+        if slice.remaining_area.is_empty() {
+            continue;
+        }
+
+        slice.layer_settings = region.layer_settings.clone();
+        fill(slice);
+        unfilled = unfilled.union_with(&slice.remaining_area);
+    }
+
+    slice.layer_settings = layer_settings;
+    slice.remaining_area = unclaimed;
+    fill(slice);
+
+    slice.remaining_area = slice.remaining_area.union_with(&unfilled);
+    slice.modifier_regions = regions;
+}
@@ -1,13 +1,21 @@
 use geo::{line_string, Coord, EuclideanDistance, MultiPolygon, Polygon};
-use glam::{vec2, Vec4};
+use glam::{vec2, Vec2, Vec4};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumCount;
 
-use crate::{command_pass::CommandPass, LayerSettings};
+use crate::{command_pass::CommandPass, settings::fiber::FiberSettings, LayerSettings};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct MoveId(pub(crate) usize);
 
+impl MoveId {
+    ///An id with the given index, ids are otherwise only handed out by the slicer
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn new(index: usize) -> Self {
+        MoveId(index)
+    }
+}
+
 struct MoveIdGenerator {
     current: usize,
 }
@@ -34,7 +42,8 @@ impl CommandPass for EvalIdPass {
             match command {
                 Command::MoveAndExtrude { id, .. }
                 | Command::MoveAndExtrudeFiber { id, .. }
-                | Command::MoveAndExtrudeFiberAndCut { id, .. } => *id = Some(gen.next_id()),
+                | Command::MoveAndExtrudeFiberAndCut { id, .. }
+                | Command::Arc { id, .. } => *id = Some(gen.next_id()),
                 _ => {}
             }
         }
@@ -48,6 +57,12 @@ impl CommandPass for MergeFiberPass {
         let mut current_index = 0;
         while current_index < cmds.len() {
             if let Some(chain) = FiberChain::find_next(cmds, current_index, settings) {
+                let anchor = if chain.length >= settings.fiber.min_length {
+                    chain.anchor_moves(cmds, &settings.fiber)
+                } else {
+                    vec![]
+                };
+
                 if chain.start_index == chain.end_index {
                     // assume that the chain is a single move
                     let (start, end, thickness, width) = match cmds[chain.start_index] {
@@ -88,8 +103,16 @@ impl CommandPass for MergeFiberPass {
                 } else if chain.length >= settings.fiber.min_length {
                     // backtrace where to cut
                     chain.find_cut_and_set(cmds, settings.fiber.cut_before);
+
+                    //The fiber stays attached across bridged travels, so they must not retract
+                    //or lift
+                    for command in &mut cmds[chain.start_index..=chain.end_index] {
+                        if let Command::SetState { new_state } = command {
+                            new_state.retract = RetractionType::NoRetract;
+                        }
+                    }
                 } else {
-                    // change fiber chain to normal moves
+                    // change fiber chain to normal moves, bridged travels stay travels
                     for i in chain.start_index..=chain.end_index {
                         let (start, end, thickness, width) = match cmds[i] {
                             Command::MoveAndExtrudeFiber {
@@ -99,7 +122,7 @@ impl CommandPass for MergeFiberPass {
                                 width,
                                 ..
                             } => (start, end, thickness, width),
-                            _ => unreachable!(),
+                            _ => continue,
                         };
 
                         cmds[i] = Command::MoveAndExtrude {
@@ -115,7 +138,8 @@ impl CommandPass for MergeFiberPass {
                     }
                 }
 
-                current_index = chain.end_index + 1;
+                current_index = chain.end_index + 1 + anchor.len();
+                cmds.splice(chain.start_index..chain.start_index, anchor);
             } else {
                 current_index += 1;
             }
@@ -138,6 +162,7 @@ impl FiberChain {
     ) -> Option<FiberChain> {
         let start_index = current_index;
         let mut last_direction = None;
+        let mut last_end = None;
         let mut length = 0.0;
 
         while current_index < cmds.len() {
@@ -154,6 +179,7 @@ impl FiberChain {
                         if angle.to_degrees().abs() <= settings.fiber.max_angle {
                             length += start.euclidean_distance(&end);
                             last_direction = Some(direction);
+                            last_end = Some(end);
 
                             current_index += 1;
                         } else {
@@ -167,10 +193,33 @@ impl FiberChain {
                         length += start.euclidean_distance(&end);
 
                         last_direction = Some(direction);
+                        last_end = Some(end);
 
                         current_index += 1;
                     }
                 }
+                //The fiber spans a short travel to a run going on the same way, the travel doesn't
+                //add to the length of the chain
+                Command::SetState { .. } | Command::MoveTo { .. } if last_end.is_some() => {
+                    let bridged = Self::bridged_travel(
+                        cmds,
+                        current_index,
+                        last_end.expect("Checked by the guard"),
+                        last_direction.expect("Set with the last end"),
+                        settings,
+                    );
+
+                    match bridged {
+                        Some(next_index) => current_index = next_index,
+                        None => {
+                            return Some(FiberChain {
+                                start_index,
+                                end_index: current_index - 1,
+                                length,
+                            });
+                        }
+                    }
+                }
                 _ => {
                     if start_index == current_index {
                         return None;
@@ -196,6 +245,104 @@ impl FiberChain {
         }
     }
 
+    ///Index of the fiber move continuing the chain after a travel starting at `index`, if the
+    ///travel is short enough to bridge and keeps the direction of the chain. The state changes
+    ///the plotter emits around every travel are skipped.
+    fn bridged_travel(
+        cmds: &[Command],
+        index: usize,
+        last_end: Coord<f32>,
+        last_dir: Vec2,
+        settings: &crate::Settings,
+    ) -> Option<usize> {
+        let keeps_direction = |from: Coord<f32>, to: Coord<f32>| {
+            let direction = vec2(to.x - from.x, to.y - from.y);
+            direction.length() <= f32::EPSILON
+                || direction.normalize().angle_to(last_dir).to_degrees().abs()
+                    <= settings.fiber.max_angle
+        };
+
+        let mut gap_end = None;
+        for (i, command) in cmds.iter().enumerate().skip(index) {
+            match command {
+                Command::SetState { .. } | Command::ChangeType { .. } => {}
+                Command::MoveTo { end } if gap_end.is_none() => gap_end = Some(*end),
+                Command::MoveAndExtrudeFiber { start, end, .. } => {
+                    let gap_end = gap_end?;
+
+                    return (last_end.euclidean_distance(&gap_end) < settings.fiber.max_bridge_gap
+                        && keeps_direction(last_end, gap_end)
+                        && keeps_direction(*start, *end))
+                    .then_some(i);
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    ///Fiber moves running back and forth along the first `anchor_length` of the chain, to be
+    ///printed before it so the fiber is held down before the main run pulls on it. They follow the
+    ///path of the chain, so they never leave the area it was generated in.
+    fn anchor_moves(&self, cmds: &[Command], fiber: &FiberSettings) -> Vec<Command> {
+        if fiber.anchor_loops == 0 || fiber.anchor_length <= 0.0 {
+            return vec![];
+        }
+
+        //The start of the chain up to the anchor length or a bridged travel, as (start, end,
+        //thickness, width)
+        let mut remaining = fiber.anchor_length.min(self.length);
+        let mut path = vec![];
+        for command in &cmds[self.start_index..=self.end_index] {
+            let Command::MoveAndExtrudeFiber {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } = *command
+            else {
+                break;
+            };
+
+            let length = start.euclidean_distance(&end);
+            if remaining <= 0.0 {
+                break;
+            }
+
+            let end = if length > remaining {
+                start + (end - start) * (remaining / length)
+            } else {
+                end
+            };
+            remaining -= length;
+            path.push((start, end, thickness, width));
+        }
+
+        let back: Vec<_> = path
+            .iter()
+            .rev()
+            .map(|(start, end, thickness, width)| (*end, *start, *thickness, *width))
+            .collect();
+
+        (0..fiber.anchor_loops)
+            .flat_map(|_| path.iter().chain(back.iter()))
+            .map(
+                |(start, end, thickness, width)| Command::MoveAndExtrudeFiber {
+                    id: None,
+                    start: *start,
+                    end: *end,
+                    thickness: *thickness,
+                    width: *width,
+
+                    #[cfg(debug_assertions)]
+                    debug: "Anchor".to_string(),
+                },
+            )
+            .collect()
+    }
+
     fn find_cut_and_set(&self, cmds: &mut [Command], cut_before: f32) {
         let mut distance_backtraced = 0.0;
 
@@ -228,6 +375,7 @@ impl FiberChain {
                         return;
                     }
                 }
+                //Bridged travels don't carry fiber that can be cut
                 _ => {}
             }
         }
@@ -236,6 +384,103 @@ impl FiberChain {
     }
 }
 
+///Fiber can't follow turns tighter than `FiberSettings::min_radius`. The shorter move at such a
+///turn is printed with plastic only, which splits the fiber chain there.
+pub struct FiberRadiusPass {}
+
+impl CommandPass for FiberRadiusPass {
+    fn pass(cmds: &mut Vec<Command>, settings: &crate::Settings) {
+        Self::downgrade_tight_turns(cmds, settings);
+    }
+}
+
+impl FiberRadiusPass {
+    ///Downgrade the fiber moves at tight turns and return how many were downgraded
+    pub fn downgrade_tight_turns(cmds: &mut [Command], settings: &crate::Settings) -> usize {
+        let fiber = &settings.fiber;
+        if fiber.min_radius <= 0.0 {
+            return 0;
+        }
+
+        let mut tight: Vec<usize> = cmds
+            .windows(2)
+            .enumerate()
+            .filter_map(|(index, pair)| match pair {
+                [Command::MoveAndExtrudeFiber {
+                    start: first_start,
+                    end: first_end,
+                    ..
+                }, Command::MoveAndExtrudeFiber {
+                    start: second_start,
+                    end: second_end,
+                    ..
+                }] if first_end == second_start => {
+                    let first_length = first_start.euclidean_distance(first_end);
+                    let second_length = second_start.euclidean_distance(second_end);
+                    let radius =
+                        turn_radius(*first_start, *first_end, *second_end, fiber.max_angle)?;
+
+                    (radius < fiber.min_radius).then_some(if first_length <= second_length {
+                        index
+                    } else {
+                        index + 1
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        tight.dedup();
+
+        for &index in &tight {
+            if let Command::MoveAndExtrudeFiber {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } = cmds[index]
+            {
+                cmds[index] = Command::MoveAndExtrude {
+                    start,
+                    end,
+                    thickness,
+                    width,
+                    id: None,
+
+                    #[cfg(debug_assertions)]
+                    debug: format!("Fiber turn too tight"),
+                };
+            }
+        }
+
+        tight.len()
+    }
+}
+
+///Radius of the arc the two moves through `corner` approximate, from the shorter move and the
+///turn at the corner. None for straight moves and for turns above `max_angle`, where the fiber
+///chain is cut anyway.
+fn turn_radius(
+    start: Coord<f32>,
+    corner: Coord<f32>,
+    end: Coord<f32>,
+    max_angle: f32,
+) -> Option<f32> {
+    let first = vec2(corner.x - start.x, corner.y - start.y);
+    let second = vec2(end.x - corner.x, end.y - corner.y);
+    if first.length() <= f32::EPSILON || second.length() <= f32::EPSILON {
+        return None;
+    }
+
+    let turn = first.angle_to(second).abs();
+    if turn <= f32::EPSILON || turn.to_degrees() > max_angle {
+        return None;
+    }
+
+    //A chord of a circle turns by the angle it spans
+    Some(first.length().min(second.length()) / (2.0 * (turn / 2.0).sin()))
+}
+
 ///A move of the plotter
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Move {
@@ -261,6 +506,76 @@ pub struct MoveChain {
 }
 
 impl MoveChain {
+    ///Whether any move of the chain lays fiber
+    pub fn has_fiber(&self) -> bool {
+        self.moves
+            .iter()
+            .any(|m| matches!(m.move_type, MoveType::WithFiber(_)))
+    }
+
+    ///Rotates every closed loop of the chain to start at the vertex returned by `pick`, which gets
+    ///the vertices of the loop in print order and the position the nozzle comes from. Loops are
+    ///separated by travels and keep their closing move. Returns the start of every closed loop.
+    pub fn rotate_loops(
+        &mut self,
+        mut pick: impl FnMut(&[Coord<f32>], Coord<f32>) -> Option<usize>,
+    ) -> Vec<Coord<f32>> {
+        let mut starts = vec![];
+        let mut position = self.start_point;
+        let mut index = 0;
+
+        while index < self.moves.len() {
+            let travel = (self.moves[index].move_type == MoveType::Travel).then_some(index);
+            let first = travel.map_or(index, |travel| travel + 1);
+            let end = self.moves[first..]
+                .iter()
+                .position(|m| m.move_type == MoveType::Travel)
+                .map_or(self.moves.len(), |offset| first + offset);
+
+            let start = travel.map_or(self.start_point, |travel| self.moves[travel].end);
+            let loop_moves = &mut self.moves[first..end];
+
+            if loop_moves.len() > 2 && loop_moves.last().is_some_and(|m| m.end == start) {
+                let vertices: Vec<Coord<f32>> = std::iter::once(start)
+                    .chain(loop_moves[..loop_moves.len() - 1].iter().map(|m| m.end))
+                    .collect();
+
+                let new_start = match pick(&vertices, position) {
+                    Some(offset) if offset < vertices.len() => {
+                        //The move into the new start closes the rotated loop
+                        loop_moves.rotate_left(offset);
+                        vertices[offset]
+                    }
+                    _ => start,
+                };
+
+                //The travels leading to the loop, and the chain start before them, follow it
+                let mut lead = travel.map_or(0, |travel| travel + 1);
+                while lead > 0
+                    && self.moves[lead - 1].move_type == MoveType::Travel
+                    && self.moves[lead - 1].end == start
+                {
+                    lead -= 1;
+                    self.moves[lead].end = new_start;
+                }
+                if lead == 0 && self.start_point == start {
+                    self.start_point = new_start;
+                }
+
+                starts.push(new_start);
+                position = new_start;
+            } else if let Some(last) = loop_moves.last() {
+                position = last.end;
+            } else {
+                position = start;
+            }
+
+            index = end;
+        }
+
+        starts
+    }
+
     pub fn trace_area(&self) -> MultiPolygon<f32> {
         let mut polygons = vec![];
         let mut current_loc = self.start_point;
@@ -324,6 +639,9 @@ pub enum TraceType {
 
     ///Support towers and interface
     Support,
+
+    ///Re-tracing a finished top surface to smooth it
+    Ironing,
 }
 
 impl std::fmt::Display for TraceType {
@@ -338,6 +656,7 @@ impl std::fmt::Display for TraceType {
             TraceType::InteriorWallInner => write!(f, "Interior Inner Perimeter"),
             TraceType::Bridging => write!(f, "Bridging"),
             TraceType::Support => write!(f, "Support"),
+            TraceType::Ironing => write!(f, "Ironing"),
         }
     }
 }
@@ -354,6 +673,7 @@ impl TraceType {
             TraceType::InteriorWallInner => Vec4::new(1.0, 1.0, 0.0, 1.0),
             TraceType::Bridging => Vec4::new(0.0, 1.0, 1.0, 1.0),
             TraceType::Support => Vec4::new(1.0, 1.0, 0.0, 1.0),
+            TraceType::Ironing => Vec4::new(1.0, 0.0, 1.0, 1.0),
         }
     }
 }
@@ -470,6 +790,9 @@ pub enum Command {
 
     ///An arc move of the extruder
     Arc {
+        ///The id of the arc, shared with the first move it replaced
+        id: Option<MoveId>,
+
         ///start point of the arc
         start: Coord<f32>,
 
@@ -493,6 +816,9 @@ pub enum Command {
     ChangeObject {
         ///The index of the new object being changed to
         object: usize,
+
+        ///The extruder that prints the new object
+        extruder: usize,
     },
     ChangeType {
         ///The new print type to change to
@@ -524,6 +850,126 @@ impl Command {
     }
 }
 
+///Maximum angle in radians covered by a single segment when splitting arcs
+const ARC_SEGMENT_ANGLE: f32 = std::f32::consts::PI / 18.0;
+
+///A single deposited bead, for use by external analysis such as FEA preprocessing
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtrusionSegment {
+    ///Start point of the bead
+    pub start: Coord<f32>,
+
+    ///End point of the bead
+    pub end: Coord<f32>,
+
+    ///Height of the top of the layer the bead was deposited on
+    pub z: f32,
+
+    ///The extrusion width
+    pub width: f32,
+
+    ///The height thickness of the bead
+    pub thickness: f32,
+
+    ///The trace type of the bead and whether it contains fiber
+    pub move_type: MoveType,
+}
+
+///Iterate over every deposited bead in the commands as straight segments. Arcs are split into
+///segments covering at most 10 degrees each.
+pub fn extrusion_segments(commands: &[Command]) -> impl Iterator<Item = ExtrusionSegment> + '_ {
+    let mut current_type = TraceType::Infill;
+    let mut current_z = 0.0;
+
+    commands.iter().flat_map(move |command| {
+        let (z, print_type) = (current_z, current_type);
+        let segment = move |start, end, thickness, width, fiber| ExtrusionSegment {
+            start,
+            end,
+            z,
+            width,
+            thickness,
+            move_type: MoveType::from_type(print_type, fiber),
+        };
+
+        match command {
+            Command::MoveAndExtrude {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } => vec![segment(*start, *end, *thickness, *width, false)],
+            Command::MoveAndExtrudeFiber {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            }
+            | Command::MoveAndExtrudeFiberAndCut {
+                start,
+                end,
+                thickness,
+                width,
+                ..
+            } => vec![segment(*start, *end, *thickness, *width, true)],
+            Command::Arc {
+                start,
+                end,
+                center,
+                clockwise,
+                thickness,
+                width,
+                ..
+            } => split_arc(*start, *end, *center, *clockwise)
+                .map(|(start, end)| segment(start, end, *thickness, *width, false))
+                .collect(),
+            Command::LayerChange { z, .. } => {
+                current_z = *z;
+                vec![]
+            }
+            Command::ChangeType { print_type } => {
+                current_type = *print_type;
+                vec![]
+            }
+            _ => vec![],
+        }
+    })
+}
+
+///Split an arc into straight segments that follow it within a small angle
+pub fn split_arc(
+    start: Coord<f32>,
+    end: Coord<f32>,
+    center: Coord<f32>,
+    clockwise: bool,
+) -> impl Iterator<Item = (Coord<f32>, Coord<f32>)> {
+    let radius = start.euclidean_distance(&center);
+    let start_angle = (start.y - center.y).atan2(start.x - center.x);
+    let end_angle = (end.y - center.y).atan2(end.x - center.x);
+
+    let mut sweep = (end_angle - start_angle).rem_euclid(std::f32::consts::TAU);
+    if clockwise && sweep > 0.0 {
+        sweep -= std::f32::consts::TAU;
+    }
+
+    let pieces = (sweep.abs() / ARC_SEGMENT_ANGLE).ceil().max(1.0) as usize;
+
+    let point = move |index: usize| {
+        if index == pieces {
+            return end;
+        }
+        let angle = start_angle + sweep * index as f32 / pieces as f32;
+        Coord {
+            x: center.x + radius * angle.cos(),
+            y: center.y + radius * angle.sin(),
+        }
+    };
+
+    (0..pieces).map(move |index| (point(index), point(index + 1)))
+}
+
 ///A change in the state of the printer. all fields are optional and should only be set when the state is changing.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum RetractionType {
@@ -536,9 +982,20 @@ pub enum RetractionType {
     ///Standard Retract without Move
     Retract,
 
+    ///Lift the nozzle for the travel without retracting the filament
+    Lift,
+
     ///MoveWhileRetracting
     ///Vector of (retraction amount, points to travel to)
     MoveRetract(Vec<(f32, Coord<f32>)>),
+
+    ///Lift the nozzle above a newly started layer, even if it is still lifted above the previous
+    ///one. Retracts first if requested and the filament isn't retracted yet
+    LayerLift { retract: bool },
+
+    ///Retract if the filament isn't retracted yet and lift the nozzle to at least the height, to
+    ///travel clear of everything printed
+    ClearanceLift { z: f32 },
 }
 
 impl RetractionType {
@@ -550,7 +1007,10 @@ impl RetractionType {
             RetractionType::NoRetract => rtb,
             RetractionType::Unretract => RetractionType::Unretract,
             RetractionType::Retract => RetractionType::Retract,
+            RetractionType::Lift => RetractionType::Lift,
             RetractionType::MoveRetract(m) => RetractionType::MoveRetract(m),
+            RetractionType::LayerLift { retract } => RetractionType::LayerLift { retract },
+            RetractionType::ClearanceLift { z } => RetractionType::ClearanceLift { z },
         }
     }
 }
@@ -632,7 +1092,18 @@ impl StateChange {
                 if self.retract == new_state.retract {
                     RetractionType::NoRetract
                 } else if let RetractionType::MoveRetract(_m) = &self.retract {
-                    if new_state.retract == RetractionType::Retract {
+                    if matches!(
+                        new_state.retract,
+                        RetractionType::Retract | RetractionType::Lift
+                    ) {
+                        RetractionType::NoRetract
+                    } else {
+                        self.retract = new_state.retract.clone().or(self.retract.clone());
+                        new_state.retract.clone()
+                    }
+                } else if self.retract == RetractionType::Retract {
+                    //Already retracted and lifted, the filament stays retracted for the travel
+                    if new_state.retract == RetractionType::Lift {
                         RetractionType::NoRetract
                     } else {
                         self.retract = new_state.retract.clone().or(self.retract.clone());
@@ -759,6 +1230,26 @@ impl MoveChain {
         self.start_point.x = nx;
         self.start_point.y = ny;
     }
+
+    ///Reverse the direction the chain is traversed in. Each move keeps its type and width.
+    pub fn reverse(&mut self) {
+        let Some(last) = self.moves.last().map(|m| m.end) else {
+            return;
+        };
+
+        let ends = std::iter::once(self.start_point)
+            .chain(self.moves.iter().map(|m| m.end))
+            .collect::<Vec<_>>();
+
+        self.moves = self
+            .moves
+            .iter()
+            .zip(ends)
+            .rev()
+            .map(|(m, start)| Move { end: start, ..*m })
+            .collect();
+        self.start_point = last;
+    }
 }
 
 fn update_state(move_type: &TraceType, settings: &LayerSettings, cmds: &mut Vec<Command>) {
@@ -805,7 +1296,11 @@ fn update_state(move_type: &TraceType, settings: &LayerSettings, cmds: &mut Vec<
                     bed_temp: None,
                     extruder_temp: None,
                     fan_speed: None,
-                    movement_speed: Some(settings.speed.bridge),
+                    movement_speed: Some(if settings.bridge.is_enabled() {
+                        settings.bridge.speed
+                    } else {
+                        settings.speed.bridge
+                    }),
                     acceleration: Some(settings.acceleration.bridge),
                     retract: RetractionType::Unretract,
                 },
@@ -871,5 +1366,261 @@ fn update_state(move_type: &TraceType, settings: &LayerSettings, cmds: &mut Vec<
                 },
             });
         }
+        TraceType::Ironing => {
+            cmds.push(Command::SetState {
+                new_state: StateChange {
+                    bed_temp: None,
+                    extruder_temp: None,
+                    fan_speed: None,
+                    movement_speed: Some(settings.speed.ironing),
+                    acceleration: Some(settings.acceleration.ironing),
+                    retract: RetractionType::Unretract,
+                },
+            });
+        }
+    }
+
+    //Bridges bump the fan, everything else sets it back to the fan speed of the layer
+    if settings.bridge.is_enabled() {
+        if let Some(Command::SetState { new_state }) = cmds.last_mut() {
+            new_state.fan_speed = Some(if *move_type == TraceType::Bridging {
+                settings.bridge.fan_speed
+            } else {
+                settings.fan_speed
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Settings;
+
+    fn fiber(start: f32, end: f32) -> Command {
+        Command::MoveAndExtrudeFiber {
+            id: None,
+            start: Coord { x: start, y: 0.0 },
+            end: Coord { x: end, y: 0.0 },
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        }
+    }
+
+    #[test]
+    fn extrusion_segments_of_commands() {
+        let commands = vec![
+            Command::LayerChange { z: 0.4, index: 1 },
+            Command::ChangeType {
+                print_type: TraceType::WallOuter,
+            },
+            Command::MoveAndExtrude {
+                id: None,
+                start: Coord { x: 0.0, y: 0.0 },
+                end: Coord { x: 10.0, y: 0.0 },
+                thickness: 0.2,
+                width: 0.45,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+            Command::MoveTo {
+                end: Coord { x: 20.0, y: 0.0 },
+            },
+            //A counter clockwise eighth of a circle around the origin, split into five pieces of
+            //9 degrees
+            Command::Arc {
+                id: None,
+                start: Coord { x: 10.0, y: 0.0 },
+                end: Coord {
+                    x: 7.071_068,
+                    y: 7.071_068,
+                },
+                center: Coord { x: 0.0, y: 0.0 },
+                clockwise: false,
+                width: 0.4,
+                thickness: 0.2,
+            },
+            Command::ChangeType {
+                print_type: TraceType::Infill,
+            },
+            fiber(0.0, 5.0),
+        ];
+
+        let segments: Vec<ExtrusionSegment> = extrusion_segments(&commands).collect();
+        assert_eq!(segments.len(), 7);
+
+        assert_eq!(
+            segments[0],
+            ExtrusionSegment {
+                start: Coord { x: 0.0, y: 0.0 },
+                end: Coord { x: 10.0, y: 0.0 },
+                z: 0.4,
+                width: 0.45,
+                thickness: 0.2,
+                move_type: MoveType::WithoutFiber(TraceType::WallOuter),
+            }
+        );
+
+        let arc_ends = [
+            (9.876_883, 1.564_345),
+            (9.510_565, 3.090_170),
+            (8.910_065, 4.539_905),
+            (8.090_170, 5.877_853),
+            (7.071_068, 7.071_068),
+        ];
+        let mut previous = Coord { x: 10.0, y: 0.0 };
+        for (segment, (x, y)) in segments[1..6].iter().zip(arc_ends) {
+            assert_eq!(segment.start, previous);
+            assert!((segment.end.x - x).abs() < 0.0001, "{:?}", segment.end);
+            assert!((segment.end.y - y).abs() < 0.0001, "{:?}", segment.end);
+            assert_eq!(segment.width, 0.4);
+            assert_eq!(segment.z, 0.4);
+            assert_eq!(
+                segment.move_type,
+                MoveType::WithoutFiber(TraceType::WallOuter)
+            );
+            previous = segment.end;
+        }
+
+        assert_eq!(
+            segments[6],
+            ExtrusionSegment {
+                start: Coord { x: 0.0, y: 0.0 },
+                end: Coord { x: 5.0, y: 0.0 },
+                z: 0.4,
+                width: 0.4,
+                thickness: 0.2,
+                move_type: MoveType::WithFiber(TraceType::Infill),
+            }
+        );
+    }
+
+    #[test]
+    fn fiber_chains_are_anchored_along_their_start() {
+        let mut settings = Settings::default();
+        settings.fiber.anchor_length = 12.0;
+        settings.fiber.anchor_loops = 2;
+
+        let mut cmds = vec![
+            Command::LayerChange { z: 0.2, index: 0 },
+            fiber(0.0, 10.0),
+            fiber(10.0, 30.0),
+        ];
+        MergeFiberPass::pass(&mut cmds, &settings);
+
+        let anchor: Vec<(f32, f32)> = cmds[1..9]
+            .iter()
+            .map(|cmd| match cmd {
+                Command::MoveAndExtrudeFiber { start, end, .. } => (start.x, end.x),
+                _ => panic!("Anchor isn't made of fiber moves: {:?}", cmd),
+            })
+            .collect();
+        let run = [(0.0, 10.0), (10.0, 12.0), (12.0, 10.0), (10.0, 0.0)];
+        assert_eq!(anchor, [run, run].concat());
+
+        //The chain itself follows the anchor and is still cut before its end
+        assert_eq!(cmds[9], fiber(0.0, 10.0));
+        assert!(matches!(
+            cmds[10],
+            Command::MoveAndExtrudeFiberAndCut { cut_pos, .. } if cut_pos == 20.0
+        ));
+
+        let values = crate::calculation::calculate_values(&cmds, &settings);
+        assert!((values.fiber_length - (30.0 + 2.0 * 24.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn short_travels_between_fiber_runs_are_bridged() {
+        let cmds = vec![
+            fiber(0.0, 15.0),
+            Command::MoveTo {
+                end: Coord { x: 15.5, y: 0.0 },
+            },
+            fiber(15.5, 30.0),
+        ];
+
+        //Each run alone is too short to hold fiber
+        let mut settings = Settings::default();
+        let mut separate = cmds.clone();
+        MergeFiberPass::pass(&mut separate, &settings);
+        assert!(matches!(separate[0], Command::MoveAndExtrude { .. }));
+        assert!(matches!(separate[2], Command::MoveAndExtrude { .. }));
+
+        //Together they are, and the cut is placed without counting the travel
+        settings.fiber.max_bridge_gap = 1.0;
+        let mut bridged = cmds.clone();
+        MergeFiberPass::pass(&mut bridged, &settings);
+        assert!(matches!(
+            bridged[0],
+            Command::MoveAndExtrudeFiberAndCut { cut_pos, .. } if cut_pos == 20.0
+        ));
+        assert_eq!(bridged[1], cmds[1]);
+        assert!(matches!(bridged[2], Command::MoveAndExtrudeFiber { .. }));
+
+        //A travel turning away from the run isn't bridged
+        let mut turned = cmds.clone();
+        turned[1] = Command::MoveTo {
+            end: Coord { x: 15.0, y: 0.5 },
+        };
+        turned[2] = Command::MoveAndExtrudeFiber {
+            id: None,
+            start: Coord { x: 15.0, y: 0.5 },
+            end: Coord { x: 30.0, y: 0.5 },
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        };
+        MergeFiberPass::pass(&mut turned, &settings);
+        assert!(matches!(turned[0], Command::MoveAndExtrude { .. }));
+    }
+
+    #[test]
+    fn fiber_moves_at_tight_turns_lose_their_fiber() {
+        let fiber = |start: (f32, f32), end: (f32, f32)| Command::MoveAndExtrudeFiber {
+            id: None,
+            start: Coord::from(start),
+            end: Coord::from(end),
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        };
+        let (cos, sin) = (30.0f32.to_radians().cos(), 30.0f32.to_radians().sin());
+
+        let mut settings = Settings::default();
+        settings.fiber.min_radius = 5.0;
+
+        //A 1 mm move turning 30 degrees is a kink of about 2 mm radius
+        let mut kinked = vec![
+            fiber((0.0, 0.0), (10.0, 0.0)),
+            fiber((10.0, 0.0), (10.0 + cos, sin)),
+            fiber((10.0 + cos, sin), (10.0 + 11.0 * cos, 11.0 * sin)),
+        ];
+        assert_eq!(
+            FiberRadiusPass::downgrade_tight_turns(&mut kinked, &settings),
+            1
+        );
+        assert!(matches!(kinked[0], Command::MoveAndExtrudeFiber { .. }));
+        assert!(matches!(kinked[1], Command::MoveAndExtrude { .. }));
+        assert!(matches!(kinked[2], Command::MoveAndExtrudeFiber { .. }));
+
+        //The same turn between long moves is a wide arc
+        let mut wide = vec![
+            fiber((0.0, 0.0), (10.0, 0.0)),
+            fiber((10.0, 0.0), (10.0 + 10.0 * cos, 10.0 * sin)),
+        ];
+        let original = wide.clone();
+        assert_eq!(
+            FiberRadiusPass::downgrade_tight_turns(&mut wide, &settings),
+            0
+        );
+        assert_eq!(wide, original);
     }
 }
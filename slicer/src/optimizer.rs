@@ -1,17 +1,19 @@
 use geo::algorithm::euclidean_length::EuclideanLength;
 use geo::euclidean_distance::EuclideanDistance;
-use geo::{Coord, Line};
+use geo::{Coord, Line, Rect};
 use itertools::Itertools;
+use ordered_float::OrderedFloat;
 
 use crate::settings::Settings;
-use crate::{Command, RetractionType, StateChange};
+use crate::{Command, RetractionType, StateChange, TraceType};
 
 pub fn unary_optimizer(cmds: &mut Vec<Command>) {
     cmds.retain(|cmd| match cmd {
         Command::MoveTo { .. } => true,
         Command::MoveAndExtrude { start, end, .. }
-        | Command::MoveAndExtrudeFiber { start, end, .. }
-        | Command::MoveAndExtrudeFiberAndCut { start, end, .. } => start != end,
+        | Command::MoveAndExtrudeFiber { start, end, .. } => start != end,
+        //The cut has to happen even if the move is empty
+        Command::MoveAndExtrudeFiberAndCut { .. } => true,
         Command::LayerChange { .. } => true,
         Command::ChangeObject { .. } => true,
         Command::ChangeType { .. } => true,
@@ -31,130 +33,522 @@ pub fn unary_optimizer(cmds: &mut Vec<Command>) {
     });
 }
 
+///Moves shorter than this don't go anywhere
+const ZERO_LENGTH: f32 = 0.0001;
+
+///Remove extrusions and travels that don't go anywhere. The material of a removed extrusion is
+///added to the extrusion of the same kind it connects to, so the extruded volume doesn't change.
+///Fiber cuts are always kept.
+pub fn zero_length_optimizer(cmds: &mut Vec<Command>) {
+    //Where the head starts is unknown, so the first travel is always kept
+    let mut current_pos: Option<Coord<f32>> = None;
+    let mut removed = vec![false; cmds.len()];
+
+    for index in 0..cmds.len() {
+        let position = current_pos;
+        let mut next_pos = position.unwrap_or_else(Coord::zero);
+        if track_position(&cmds[index], &mut next_pos) {
+            current_pos = Some(next_pos);
+        }
+
+        match &cmds[index] {
+            Command::MoveTo { end } => {
+                removed[index] = position
+                    .is_some_and(|position| end.euclidean_distance(&position) < ZERO_LENGTH);
+            }
+            Command::MoveAndExtrude { start, end, .. }
+            | Command::MoveAndExtrudeFiber { start, end, .. }
+                if start.euclidean_distance(end) < ZERO_LENGTH =>
+            {
+                let degenerate = cmds[index].clone();
+
+                let merged = (0..index)
+                    .rev()
+                    .find(|previous| !removed[*previous])
+                    .is_some_and(|previous| {
+                        absorb_extrusion(&mut cmds[previous], &degenerate, true)
+                    });
+
+                if !merged {
+                    if let Some(next) = cmds.get_mut(index + 1) {
+                        absorb_extrusion(next, &degenerate, false);
+                    }
+                }
+
+                //An extrusion with nothing to attach to holds far less material than the
+                //extruder can meter, so it is dropped either way
+                removed[index] = true;
+            }
+            _ => {}
+        }
+    }
+
+    let mut removed = removed.into_iter();
+    cmds.retain(|_| !removed.next().unwrap_or(false));
+}
+
+///Stretch `target` over the degenerate extrusion before or after it and widen it so it holds
+///the material of both. Returns false if the two aren't the same kind of extrusion or don't
+///connect.
+fn absorb_extrusion(target: &mut Command, degenerate: &Command, target_first: bool) -> bool {
+    if std::mem::discriminant(target) != std::mem::discriminant(degenerate) {
+        return false;
+    }
+
+    let (d_start, d_end, d_volume) = match degenerate {
+        Command::MoveAndExtrude {
+            start,
+            end,
+            thickness,
+            width,
+            ..
+        }
+        | Command::MoveAndExtrudeFiber {
+            start,
+            end,
+            thickness,
+            width,
+            ..
+        } => (
+            *start,
+            *end,
+            start.euclidean_distance(end) * thickness * width,
+        ),
+        _ => return false,
+    };
+
+    match target {
+        Command::MoveAndExtrude {
+            start,
+            end,
+            thickness,
+            width,
+            ..
+        }
+        | Command::MoveAndExtrudeFiber {
+            start,
+            end,
+            thickness,
+            width,
+            ..
+        } => {
+            let (new_start, new_end) = if target_first {
+                if end.euclidean_distance(&d_start) >= ZERO_LENGTH {
+                    return false;
+                }
+                (*start, d_end)
+            } else {
+                if start.euclidean_distance(&d_end) >= ZERO_LENGTH {
+                    return false;
+                }
+                (d_start, *end)
+            };
+
+            let length = new_start.euclidean_distance(&new_end);
+            if length < ZERO_LENGTH {
+                return false;
+            }
+
+            let volume = start.euclidean_distance(end) * *thickness * *width + d_volume;
+
+            *start = new_start;
+            *end = new_end;
+            *width = volume / (length * *thickness);
+            true
+        }
+        _ => false,
+    }
+}
+
 pub fn binary_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
     let mut current_pos = Coord::zero();
 
+    //Where the merged command so far starts and ends, to tell if travels continue in a line
+    let mut first_start = Coord::zero();
+    let mut first_end = Coord::zero();
+    if let Some(cmd) = cmds.first() {
+        track_position(cmd, &mut first_end);
+    }
+
     *cmds = cmds
         .drain(..)
         .coalesce(move |first, second| {
-            match (first.clone(), second.clone()) {
-                (
-                    Command::MoveAndExtrude {
+            let mut second_end = first_end;
+            track_position(&second, &mut second_end);
+
+            let merged = merge_commands(first, second, first_start, &mut current_pos, settings);
+            if merged.is_err() {
+                first_start = first_end;
+            }
+            first_end = second_end;
+
+            merged
+        })
+        .collect();
+}
+
+///Merge two commands into one if possible, otherwise they are returned, possibly changed
+fn merge_commands(
+    first: Command,
+    second: Command,
+    first_start: Coord<f32>,
+    current_pos: &mut Coord<f32>,
+    settings: &Settings,
+) -> Result<Command, (Command, Command)> {
+    match (first.clone(), second.clone()) {
+        (
+            Command::MoveAndExtrude {
+                start: f_start,
+                end: f_end,
+                thickness: f_thick,
+                width: f_width,
+                ..
+            },
+            Command::MoveAndExtrude {
+                start: s_start,
+                end: s_end,
+                thickness: s_thick,
+                width: s_width,
+                ..
+            },
+        ) => {
+            *current_pos = s_end;
+
+            if f_end == s_start && s_width == f_width && s_thick == f_thick {
+                let det = (((f_start.x - s_start.x) * (s_start.y - s_end.y))
+                    - ((f_start.y - s_start.y) * (s_start.x - s_end.x)))
+                    .abs();
+
+                if det < 0.00001 {
+                    //Colinear
+
+                    return Ok(Command::MoveAndExtrude {
                         start: f_start,
-                        end: f_end,
-                        thickness: f_thick,
-                        width: f_width,
-                        ..
-                    },
-                    Command::MoveAndExtrude {
-                        start: s_start,
                         end: s_end,
-                        thickness: s_thick,
+                        thickness: f_thick,
                         width: s_width,
-                        ..
-                    },
-                ) => {
-                    current_pos = s_end;
-
-                    if f_end == s_start && s_width == f_width && s_thick == f_thick {
-                        let det = (((f_start.x - s_start.x) * (s_start.y - s_end.y))
-                            - ((f_start.y - s_start.y) * (s_start.x - s_end.x)))
-                            .abs();
-
-                        if det < 0.00001 {
-                            //Colinear
-
-                            return Ok(Command::MoveAndExtrude {
-                                start: f_start,
-                                end: s_end,
-                                thickness: f_thick,
-                                width: s_width,
-                                id: None,
-
-                                #[cfg(debug_assertions)]
-                                debug: format!("{:?} -> {:?}", f_start, s_end),
-                            });
-                        }
-                    }
-                }
-                (Command::MoveTo { .. }, Command::MoveTo { end: s_end }) => {
-                    current_pos = s_end;
-                    return Ok(Command::MoveTo { end: s_end });
-                }
-                (Command::Delay { msec: t1 }, Command::Delay { msec: t2 }) => {
-                    //merge back to back delays
-                    return Ok(Command::Delay { msec: t1 + t2 });
-                }
+                        id: None,
 
-                (Command::ChangeObject { .. }, Command::ChangeObject { object }) => {
-                    // skip an object change followed by another change
-                    return Ok(Command::ChangeObject { object });
+                        #[cfg(debug_assertions)]
+                        debug: format!("{:?} -> {:?}", f_start, s_end),
+                    });
                 }
+            }
+        }
+        (Command::MoveTo { end: f_end }, Command::MoveTo { end: s_end }) => {
+            *current_pos = s_end;
 
-                (
+            //Travels that bend follow a path on purpose, like coasting along a wall
+            let along = f_end - first_start;
+            let next = s_end - f_end;
+            let det = (along.x * next.y - along.y * next.x).abs();
+            let dot = along.x * next.x + along.y * next.y;
+
+            if det < 0.00001 && dot >= 0.0 {
+                return Ok(Command::MoveTo { end: s_end });
+            }
+        }
+        (Command::Delay { msec: t1 }, Command::Delay { msec: t2 }) => {
+            //merge back to back delays
+            return Ok(Command::Delay { msec: t1 + t2 });
+        }
+
+        (Command::ChangeObject { .. }, Command::ChangeObject { object, extruder }) => {
+            // skip an object change followed by another change
+            return Ok(Command::ChangeObject { object, extruder });
+        }
+
+        (Command::SetState { new_state: f_state }, Command::SetState { new_state: s_state }) => {
+            return Ok(Command::SetState {
+                new_state: f_state.combine(&s_state),
+            });
+        }
+        (
+            Command::SetState {
+                new_state: mut f_state,
+            },
+            Command::MoveTo { end },
+        ) => {
+            if matches!(
+                f_state.retract,
+                RetractionType::Retract | RetractionType::Lift
+            ) && Line::new(*current_pos, end).euclidean_length()
+                < settings.minimum_retract_distance
+            {
+                *current_pos = end;
+
+                //remove retract command
+                f_state.retract = RetractionType::NoRetract;
+
+                return Err((
                     Command::SetState { new_state: f_state },
-                    Command::SetState { new_state: s_state },
-                ) => {
-                    return Ok(Command::SetState {
-                        new_state: f_state.combine(&s_state),
-                    });
-                }
-                (
-                    Command::SetState {
-                        new_state: mut f_state,
-                    },
                     Command::MoveTo { end },
-                ) => {
-                    if f_state.retract == RetractionType::Retract
-                        && Line::new(current_pos, end).euclidean_length()
-                            < settings.minimum_retract_distance
-                    {
-                        current_pos = end;
-
-                        //remove retract command
-                        f_state.retract = RetractionType::NoRetract;
-
-                        return Err((
-                            Command::SetState { new_state: f_state },
-                            Command::MoveTo { end },
-                        ));
-                    } else if let RetractionType::MoveRetract(_) = f_state.retract {
-                        if Line::new(current_pos, end).euclidean_length()
-                            < settings.minimum_retract_distance
-                        {
-                            current_pos = end;
-
-                            //remove retract command
-                            f_state.retract = RetractionType::NoRetract;
-
-                            return Err((
-                                Command::SetState { new_state: f_state },
-                                Command::MoveTo { end },
-                            ));
-                        }
-                    } else {
-                        current_pos = end;
+                ));
+            } else if let RetractionType::MoveRetract(_) = f_state.retract {
+                if Line::new(*current_pos, end).euclidean_length()
+                    < settings.minimum_retract_distance
+                {
+                    *current_pos = end;
+
+                    //remove retract command
+                    f_state.retract = RetractionType::NoRetract;
+
+                    return Err((
+                        Command::SetState { new_state: f_state },
+                        Command::MoveTo { end },
+                    ));
+                }
+            } else {
+                *current_pos = end;
+            }
+        }
+        (
+            _,
+            Command::MoveAndExtrude {
+                start: _s_start,
+                end: s_end,
+                ..
+            },
+        ) => {
+            *current_pos = s_end;
+        }
+        (_, Command::MoveTo { end: s_end }) => {
+            *current_pos = s_end;
+        }
+        (_, _) => {}
+    }
+
+    Err((first, second))
+}
+
+///A run of commands starting at a travel and ending before the next one
+struct TravelBlock {
+    start: usize,
+    end: usize,
+    start_point: Coord<f32>,
+    end_point: Coord<f32>,
+    bounds: Rect<f32>,
+    print_type: Option<TraceType>,
+    needs_type: bool,
+}
+
+///Group the travel blocks of each layer into islands and print every island in one go.
+///
+///Blocks whose extents are within the minimum retract distance of each other belong to the same
+///island and keep their original relative order, so walls are still printed before the infill
+///they enclose. Islands are visited nearest first. Travels between blocks of one island are
+///usually short enough to skip the retraction, so the number of retractions drops to roughly one
+///per island instead of one per island for every feature type. Objects are never interleaved.
+pub fn island_optimizer(cmds: &mut Vec<Command>, settings: &Settings) {
+    //Blocks are only reordered within a layer of a single object
+    let sections: Vec<_> = std::iter::once(0)
+        .chain(cmds.iter().positions(|cmd| {
+            matches!(
+                cmd,
+                Command::LayerChange { .. } | Command::ChangeObject { .. }
+            )
+        }))
+        .chain(std::iter::once(cmds.len()))
+        .tuple_windows()
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| start..end)
+        .collect();
+
+    let mut optimized = Vec::with_capacity(cmds.len());
+    let mut current_pos = Coord::zero();
+    let mut original_type = None;
+    let mut emitted_type = None;
+
+    for section in sections {
+        let section = &cmds[section];
+        let block_starts: Vec<usize> = section
+            .iter()
+            .positions(|cmd| matches!(cmd, Command::MoveTo { .. }))
+            .collect();
+        let prefix_end = block_starts.first().copied().unwrap_or(section.len());
+
+        for cmd in &section[..prefix_end] {
+            track_type(cmd, &mut original_type);
+            track_type(cmd, &mut emitted_type);
+            track_position(cmd, &mut current_pos);
+            optimized.push(cmd.clone());
+        }
+
+        let blocks: Vec<TravelBlock> = block_starts
+            .iter()
+            .copied()
+            .zip(
+                block_starts
+                    .iter()
+                    .skip(1)
+                    .copied()
+                    .chain(std::iter::once(section.len())),
+            )
+            .map(|(start, end)| {
+                let print_type = original_type;
+                let mut end_point = Coord::zero();
+                let mut bounds: Option<Rect<f32>> = None;
+                let mut needs_type = false;
+                let mut type_seen = false;
+
+                for cmd in &section[start..end] {
+                    track_type(cmd, &mut original_type);
+
+                    match cmd {
+                        Command::ChangeType { .. } => type_seen = true,
+                        Command::MoveAndExtrude { .. }
+                        | Command::MoveAndExtrudeFiber { .. }
+                        | Command::MoveAndExtrudeFiberAndCut { .. }
+                        | Command::Arc { .. } => needs_type |= !type_seen,
+                        _ => {}
+                    }
+
+                    if track_position(cmd, &mut end_point) {
+                        bounds = Some(match bounds {
+                            Some(rect) => Rect::new(
+                                Coord {
+                                    x: rect.min().x.min(end_point.x),
+                                    y: rect.min().y.min(end_point.y),
+                                },
+                                Coord {
+                                    x: rect.max().x.max(end_point.x),
+                                    y: rect.max().y.max(end_point.y),
+                                },
+                            ),
+                            None => Rect::new(end_point, end_point),
+                        });
                     }
                 }
-                (
-                    _,
-                    Command::MoveAndExtrude {
-                        start: _s_start,
-                        end: s_end,
-                        ..
-                    },
-                ) => {
-                    current_pos = s_end;
+
+                let start_point = match section[start] {
+                    Command::MoveTo { end } => end,
+                    _ => unreachable!("Blocks start with a travel"),
+                };
+
+                TravelBlock {
+                    start,
+                    end,
+                    start_point,
+                    end_point,
+                    bounds: bounds.unwrap_or_else(|| Rect::new(start_point, start_point)),
+                    print_type,
+                    needs_type,
+                }
+            })
+            .collect();
+
+        for island in order_islands(&blocks, current_pos, settings.minimum_retract_distance) {
+            for block in island.into_iter().map(|index| &blocks[index]) {
+                optimized.push(section[block.start].clone());
+
+                //Blocks split from the middle of a chain rely on the type set before them
+                if let Some(print_type) = block.print_type {
+                    if block.needs_type && emitted_type != Some(print_type) {
+                        optimized.push(Command::ChangeType { print_type });
+                        emitted_type = Some(print_type);
+                    }
                 }
-                (_, Command::MoveTo { end: s_end }) => {
-                    current_pos = s_end;
+
+                for cmd in &section[block.start + 1..block.end] {
+                    track_type(cmd, &mut emitted_type);
+                    optimized.push(cmd.clone());
                 }
-                (_, _) => {}
+                current_pos = block.end_point;
             }
+        }
+    }
 
-            Err((first, second))
-        })
+    *cmds = optimized;
+}
+
+///Group the blocks into islands and order the islands nearest first starting from the position
+fn order_islands(
+    blocks: &[TravelBlock],
+    mut position: Coord<f32>,
+    distance: f32,
+) -> Vec<Vec<usize>> {
+    //Union find over blocks whose bounds are closer than the distance
+    let mut parents: Vec<usize> = (0..blocks.len()).collect();
+
+    let sorted: Vec<usize> = (0..blocks.len())
+        .sorted_by_key(|index| OrderedFloat(blocks[*index].bounds.min().x))
         .collect();
+
+    for (i, &a) in sorted.iter().enumerate() {
+        let bounds_a = blocks[a].bounds;
+        for &b in sorted[i + 1..]
+            .iter()
+            .take_while(|b| blocks[**b].bounds.min().x - bounds_a.max().x < distance)
+        {
+            let bounds_b = blocks[b].bounds;
+            if bounds_b.min().y - bounds_a.max().y < distance
+                && bounds_a.min().y - bounds_b.max().y < distance
+            {
+                let (root_a, root_b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+                parents[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+    }
+
+    let mut islands: Vec<Vec<usize>> = vec![];
+    let mut island_of_root = vec![None; blocks.len()];
+    for index in 0..blocks.len() {
+        let root = find_root(&mut parents, index);
+        match island_of_root[root] {
+            Some(island) => islands[island].push(index),
+            None => {
+                island_of_root[root] = Some(islands.len());
+                islands.push(vec![index]);
+            }
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(islands.len());
+    while !islands.is_empty() {
+        let next = islands
+            .iter()
+            .position_min_by_key(|island| {
+                OrderedFloat(blocks[island[0]].start_point.euclidean_distance(&position))
+            })
+            .expect("Islands is not empty");
+        let island = islands.remove(next);
+        position = blocks[*island.last().expect("Islands are not empty")].end_point;
+        ordered.push(island);
+    }
+
+    ordered
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+fn track_type(cmd: &Command, print_type: &mut Option<TraceType>) {
+    if let Command::ChangeType {
+        print_type: new_type,
+    } = cmd
+    {
+        *print_type = Some(*new_type);
+    }
+}
+
+///Update the position to the end of the command, returning whether the command moves the head
+fn track_position(cmd: &Command, position: &mut Coord<f32>) -> bool {
+    match cmd {
+        Command::MoveTo { end }
+        | Command::MoveAndExtrude { end, .. }
+        | Command::MoveAndExtrudeFiber { end, .. }
+        | Command::MoveAndExtrudeFiberAndCut { end, .. }
+        | Command::Arc { end, .. } => {
+            *position = *end;
+            true
+        }
+        _ => false,
+    }
 }
 
 pub fn state_optomizer(cmds: &mut Vec<Command>) {
@@ -261,8 +655,8 @@ pub fn arc_optomizer(cmds: &mut Vec<Command>) {
     }
 
     for (center, range, thickness, width) in ranges {
-        let start = if let Command::MoveAndExtrude { start, .. } = cmds[*range.start()] {
-            start
+        let (id, start) = if let Command::MoveAndExtrude { id, start, .. } = cmds[*range.start()] {
+            (id, start)
         } else {
             unreachable!()
         };
@@ -277,6 +671,7 @@ pub fn arc_optomizer(cmds: &mut Vec<Command>) {
         }
 
         cmds[*range.start()] = Command::Arc {
+            id,
             start,
             end,
             clockwise: true,
@@ -295,6 +690,161 @@ pub fn arc_optomizer(cmds: &mut Vec<Command>) {
     }
 }
 
+///Fewest extrusions worth replacing with an arc
+const MIN_ARC_SEGMENTS: usize = 4;
+
+///Replace runs of connected extrusions that lie on a circle with arcs. Every point of a run has to
+///be within the tolerance of the arc and the arc may not bulge further than the tolerance from
+///any of the segments. Fiber moves are never fitted as the cutter needs them piecewise linear.
+pub fn arc_fit_optimizer(cmds: &mut [Command], tolerance: f32) {
+    let mut index = 0;
+
+    while index < cmds.len() {
+        let Some((start, _, thickness, width)) = plastic_extrusion(&cmds[index]) else {
+            index += 1;
+            continue;
+        };
+
+        let mut points = vec![start];
+        let mut fitted = None;
+
+        for (cmd_index, cmd) in cmds.iter().enumerate().skip(index) {
+            let Some((move_start, move_end, move_thickness, move_width)) = plastic_extrusion(cmd)
+            else {
+                break;
+            };
+
+            if Some(&move_start) != points.last()
+                || move_thickness != thickness
+                || move_width != width
+            {
+                break;
+            }
+
+            points.push(move_end);
+
+            //Runs that are still straight may curve further on
+            if points.len() > MIN_ARC_SEGMENTS && !is_straight(&points, tolerance) {
+                match fit_arc(&points, tolerance) {
+                    Some((center, clockwise)) => {
+                        fitted = Some((cmd_index, move_end, center, clockwise))
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        if let Some((last_index, end, center, clockwise)) = fitted {
+            let id = match cmds[index] {
+                Command::MoveAndExtrude { id, .. } => id,
+                _ => None,
+            };
+
+            cmds[index] = Command::Arc {
+                id,
+                start,
+                end,
+                center,
+                clockwise,
+                thickness,
+                width,
+            };
+
+            for cmd in &mut cmds[index + 1..=last_index] {
+                *cmd = Command::NoAction;
+            }
+
+            index = last_index + 1;
+        } else {
+            index += 1;
+        }
+    }
+}
+
+fn plastic_extrusion(cmd: &Command) -> Option<(Coord<f32>, Coord<f32>, f32, f32)> {
+    match cmd {
+        Command::MoveAndExtrude {
+            start,
+            end,
+            thickness,
+            width,
+            ..
+        } => Some((*start, *end, *thickness, *width)),
+        _ => None,
+    }
+}
+
+///Whether the middle of the run is within the tolerance of the line between its ends
+fn is_straight(points: &[Coord<f32>], tolerance: f32) -> bool {
+    let (first, middle, last) = (
+        points[0],
+        points[points.len() / 2],
+        points[points.len() - 1],
+    );
+
+    let chord = last - first;
+    let chord_length = first.euclidean_distance(&last);
+    let bulge = ((middle.x - first.x) * chord.y - (middle.y - first.y) * chord.x).abs();
+
+    chord_length != 0.0 && bulge / chord_length <= tolerance
+}
+
+///The center and direction of the arc through the points, if they all lie on it within the
+///tolerance
+fn fit_arc(points: &[Coord<f32>], tolerance: f32) -> Option<(Coord<f32>, bool)> {
+    let first = *points.first()?;
+    let middle = points[points.len() / 2];
+    let last = *points.last()?;
+
+    let center = circumcenter(first, middle, last)?;
+    let radius = center.euclidean_distance(&first);
+    let clockwise = (middle.x - first.x) * (last.y - middle.y)
+        - (middle.y - first.y) * (last.x - middle.x)
+        < 0.0;
+
+    let mut sweep = 0.0;
+    for (start, end) in points.iter().tuple_windows() {
+        if (center.euclidean_distance(end) - radius).abs() > tolerance {
+            return None;
+        }
+
+        //How far the arc bulges out past the segment
+        let half_length = start.euclidean_distance(end) / 2.0;
+        if half_length >= radius
+            || radius - (radius * radius - half_length * half_length).sqrt() > tolerance
+        {
+            return None;
+        }
+
+        let (from, to) = (*start - center, *end - center);
+        let step = (from.x * to.y - from.y * to.x).atan2(from.x * to.x + from.y * to.y);
+        if step == 0.0 || (step < 0.0) != clockwise {
+            return None;
+        }
+        sweep += step.abs();
+    }
+
+    //The gcode writer measures the arc from its chord, which only holds up to half a circle
+    (sweep < std::f32::consts::PI).then_some((center, clockwise))
+}
+
+fn circumcenter(a: Coord<f32>, b: Coord<f32>, c: Coord<f32>) -> Option<Coord<f32>> {
+    //Relative to the first point to keep the precision
+    let (b, c) = (b - a, c - a);
+    let d = 2.0 * (b.x * c.y - b.y * c.x);
+    if d == 0.0 {
+        return None;
+    }
+
+    let b_sq = b.x * b.x + b.y * b.y;
+    let c_sq = c.x * c.x + c.y * c.y;
+
+    Some(Coord {
+        x: a.x + (c.y * b_sq - b.y * c_sq) / d,
+        y: a.y + (b.x * c_sq - c.x * b_sq) / d,
+    })
+}
+
 fn line_bisector(p0: &Coord<f32>, p1: &Coord<f32>, p2: &Coord<f32>) -> (Coord<f32>, Coord<f32>) {
     let ray_start = *p1;
 
@@ -524,4 +1074,206 @@ mod tests {
             panic!("Command should be an arc")
         }
     }
+
+    fn extrude(start: (f32, f32), end: (f32, f32)) -> Command {
+        Command::MoveAndExtrude {
+            start: start.into(),
+            end: end.into(),
+            thickness: 0.2,
+            width: 0.4,
+            id: None,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        }
+    }
+
+    fn extruded_volume(cmds: &[Command]) -> f32 {
+        cmds.iter()
+            .map(|cmd| match cmd {
+                Command::MoveAndExtrude {
+                    start,
+                    end,
+                    thickness,
+                    width,
+                    ..
+                }
+                | Command::MoveAndExtrudeFiberAndCut {
+                    start,
+                    end,
+                    thickness,
+                    width,
+                    ..
+                } => start.euclidean_distance(end) * thickness * width,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn zero_length_moves_are_removed() {
+        let mut commands = vec![
+            Command::MoveTo {
+                end: Coord { x: 0.0, y: 0.0 },
+            },
+            extrude((0.0, 0.0), (10.0, 0.0)),
+            //Left behind by merging, slightly off the end of the previous move
+            extrude((10.0, 0.0), (10.00005, 0.0)),
+            extrude((10.00005, 0.0), (10.00005, 10.0)),
+            Command::MoveTo {
+                end: Coord {
+                    x: 10.00005,
+                    y: 10.0,
+                },
+            },
+            Command::MoveAndExtrudeFiberAndCut {
+                start: Coord {
+                    x: 10.00005,
+                    y: 10.0,
+                },
+                end: Coord {
+                    x: 10.00005,
+                    y: 10.0,
+                },
+                thickness: 0.2,
+                width: 0.4,
+                cut_pos: 0.0,
+                id: None,
+
+                #[cfg(debug_assertions)]
+                debug: String::new(),
+            },
+        ];
+        let volume = extruded_volume(&commands);
+
+        zero_length_optimizer(&mut commands);
+
+        assert_eq!(commands.len(), 4);
+        assert!(matches!(commands[0], Command::MoveTo { .. }));
+        assert!(matches!(
+            commands[3],
+            Command::MoveAndExtrudeFiberAndCut { .. }
+        ));
+
+        //The remaining moves go somewhere, still connect and hold the same material
+        let moves: Vec<(Coord<f32>, Coord<f32>)> = commands[1..3]
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Command::MoveAndExtrude { start, end, .. } => Some((*start, *end)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(moves.len(), 2);
+        assert!(moves
+            .iter()
+            .all(|(start, end)| start.euclidean_distance(end) >= ZERO_LENGTH));
+        assert_eq!(moves[0].1, moves[1].0);
+        assert!((extruded_volume(&commands) - volume).abs() < 1e-5);
+    }
+
+    fn circle_point(angle: f32) -> (f32, f32) {
+        (100.0 + 20.0 * angle.cos(), 100.0 + 20.0 * angle.sin())
+    }
+
+    fn circle_extrusions(from: f32, to: f32, steps: usize) -> Vec<Command> {
+        (0..=steps)
+            .map(|step| circle_point(from + (to - from) * step as f32 / steps as f32))
+            .tuple_windows()
+            .map(|(start, end)| extrude(start, end))
+            .collect()
+    }
+
+    #[test]
+    fn arcs_are_fitted_in_both_directions() {
+        for (from, to, clockwise) in [(0.0, 2.0, false), (2.0, 0.0, true)] {
+            let mut commands = circle_extrusions(from, to, 40);
+
+            arc_fit_optimizer(&mut commands, 0.02);
+            unary_optimizer(&mut commands);
+
+            assert_eq!(commands.len(), 1);
+            if let Command::Arc {
+                start,
+                end,
+                center,
+                clockwise: arc_clockwise,
+                ..
+            } = commands[0]
+            {
+                assert_eq!(arc_clockwise, clockwise);
+                assert!(center.euclidean_distance(&Coord { x: 100.0, y: 100.0 }) < 0.01);
+                assert!(start.euclidean_distance(&Coord::from(circle_point(from))) < 0.0001);
+                assert!(end.euclidean_distance(&Coord::from(circle_point(to))) < 0.0001);
+            } else {
+                panic!("Command should be an arc")
+            }
+        }
+    }
+
+    #[test]
+    fn arcs_stay_within_tolerance() {
+        //A full circle is split into arcs of less than half a circle
+        let mut commands = circle_extrusions(0.0, std::f32::consts::TAU, 100);
+        arc_fit_optimizer(&mut commands, 0.02);
+        unary_optimizer(&mut commands);
+        assert_eq!(
+            commands
+                .iter()
+                .filter(|cmd| matches!(cmd, Command::Arc { .. }))
+                .count(),
+            2
+        );
+        assert!(commands.len() <= 4);
+
+        //Coarse segments are further from the arc than allowed
+        let mut commands = circle_extrusions(0.0, 2.0, 8);
+        arc_fit_optimizer(&mut commands, 0.02);
+        unary_optimizer(&mut commands);
+        assert_eq!(commands.len(), 8);
+
+        //Straight lines stay lines
+        let mut commands = (0..10)
+            .map(|x| extrude((x as f32, 0.0), (x as f32 + 1.0, 0.0)))
+            .collect::<Vec<_>>();
+        arc_fit_optimizer(&mut commands, 0.02);
+        assert!(commands
+            .iter()
+            .all(|cmd| matches!(cmd, Command::MoveAndExtrude { .. })));
+    }
+
+    #[test]
+    fn fiber_is_never_fitted() {
+        let mut commands: Vec<Command> = circle_extrusions(0.0, 2.0, 40)
+            .into_iter()
+            .map(|cmd| match cmd {
+                Command::MoveAndExtrude {
+                    start,
+                    end,
+                    thickness,
+                    width,
+                    id,
+
+                    #[cfg(debug_assertions)]
+                    debug,
+                } => Command::MoveAndExtrudeFiber {
+                    start,
+                    end,
+                    thickness,
+                    width,
+                    id,
+
+                    #[cfg(debug_assertions)]
+                    debug,
+                },
+                cmd => cmd,
+            })
+            .collect();
+
+        arc_fit_optimizer(&mut commands, 0.02);
+
+        assert_eq!(commands.len(), 40);
+        assert!(commands
+            .iter()
+            .all(|cmd| matches!(cmd, Command::MoveAndExtrudeFiber { .. })));
+    }
 }
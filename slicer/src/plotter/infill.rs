@@ -1,13 +1,28 @@
-use crate::settings::LayerSettings;
-use crate::utils::point_y_lerp;
+use crate::settings::{fiber::OrientationField, LayerSettings};
 use crate::{
     Move, MoveChain, MoveType, PartialInfillTypes, PassContext, SolidInfillTypes, TraceType,
 };
 
-use super::monotone::get_monotone_sections;
 use super::polygon_operations::PolygonOperations;
+use super::skeleton::{boundary_distance, medial_axis};
+use geo::coordinate_position::{CoordPos, CoordinatePosition};
+use geo::line_intersection::{line_intersection, LineIntersection};
 use geo::prelude::*;
 use geo::*;
+use glam::{vec2, Vec2};
+use std::collections::{BTreeMap, BTreeSet};
+
+//Resolution of the marching squares tracing the gyroid contour
+const GYROID_SAMPLES_PER_PERIOD: f32 = 16.0;
+
+//Times the cells of the adaptive cubic infill are halved from the center towards the boundary
+const ADAPTIVE_CUBIC_DEPTH: i32 = 3;
+
+//Fraction of a segment within which cuts by the boundary are treated as the same point
+const CLIP_EPSILON: f32 = 1e-5;
+
+//Length in mm of the steps lines following an orientation field are traced with
+const FIELD_TRACE_STEP: f32 = 0.5;
 
 pub fn linear_fill_polygon(
     poly: &Polygon<f32>,
@@ -15,33 +30,17 @@ pub fn linear_fill_polygon(
     fill_type: MoveType,
     angle: f32,
 ) -> Vec<MoveChain> {
-    let rotate_poly = poly.rotate_around_point(angle, Point(Coord::zero()));
-
-    let mut new_moves: Vec<MoveChain> = rotate_poly
-        .offset_from(
-            ((-settings.extrusion_width.interior_inner_perimeter / 2.0)
-                * (1.0 - settings.infill_perimeter_overlap_percentage))
-                + (settings.extrusion_width.interior_inner_perimeter / 2.0),
-        )
-        .iter()
-        .flat_map(|polygon| {
-            spaced_fill_polygon(
-                polygon,
-                settings,
-                fill_type,
-                settings
-                    .extrusion_width
-                    .get_value_for_movement_type(&fill_type),
-                0.0,
-            )
-        })
-        .collect();
-
-    for chain in new_moves.iter_mut() {
-        chain.rotate(-angle.to_radians());
-    }
-
-    new_moves
+    overlap_linear_fill_polygon(
+        poly,
+        settings,
+        fill_type,
+        settings
+            .extrusion_width
+            .get_value_for_movement_type(&fill_type),
+        angle,
+        0.0,
+        settings.solid_infill_overlap_percentage,
+    )
 }
 
 pub fn partial_linear_fill_polygon(
@@ -51,13 +50,33 @@ pub fn partial_linear_fill_polygon(
     spacing: f32,
     angle: f32,
     offset: f32,
+) -> Vec<MoveChain> {
+    overlap_linear_fill_polygon(
+        poly,
+        settings,
+        fill_type,
+        spacing,
+        angle,
+        offset,
+        settings.infill_perimeter_overlap_percentage,
+    )
+}
+
+pub fn overlap_linear_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f32,
+    angle: f32,
+    offset: f32,
+    overlap_percentage: f32,
 ) -> Vec<MoveChain> {
     let rotate_poly = poly.rotate_around_point(angle, Point(Coord::zero()));
 
     let mut new_moves: Vec<MoveChain> = rotate_poly
         .offset_from(
             ((-settings.extrusion_width.interior_inner_perimeter / 2.0)
-                * (1.0 - settings.infill_perimeter_overlap_percentage))
+                * (1.0 - overlap_percentage))
                 + (settings.extrusion_width.interior_inner_perimeter / 2.0),
         )
         .iter()
@@ -94,6 +113,75 @@ pub fn support_linear_fill_polygon(
     new_moves
 }
 
+///Loops following the outline of the polygon and its holes inwards, `spacing` apart
+pub fn concentric_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f32,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    let mut chains = vec![];
+    let mut current = poly.offset_from(-width / 2.0);
+
+    while !current.0.is_empty() {
+        chains.extend(
+            current
+                .iter()
+                .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+                .filter(|ring| ring.0.len() > 1)
+                .map(|ring| MoveChain {
+                    start_point: ring.0[0],
+                    moves: ring.0[1..]
+                        .iter()
+                        .map(|end| Move {
+                            end: *end,
+                            move_type: fill_type,
+                            width,
+                        })
+                        .collect(),
+                    is_loop: true,
+                }),
+        );
+
+        current = current.offset_from(-spacing);
+    }
+
+    chains
+}
+
+///Angle of the solid fill of a region. The infill angle of the layer takes precedence, then
+///aligned regions run along the longest edge of their minimum bounding rectangle and the others
+///use `default_angle`. The infill angle offset of the layer is added on top.
+pub fn solid_fill_angle(poly: &Polygon<f32>, settings: &LayerSettings, default_angle: f32) -> f32 {
+    if let Some(angle) = settings.infill_angle {
+        return angle + settings.infill_angle_offset;
+    }
+
+    if !settings.align_solid_to_longest_edge {
+        return default_angle + settings.infill_angle_offset;
+    }
+
+    poly.minimum_rotated_rect()
+        .and_then(|rect| {
+            rect.exterior().lines().max_by(|a, b| {
+                a.euclidean_length()
+                    .partial_cmp(&b.euclidean_length())
+                    .expect("Lengths should not be NAN")
+            })
+        })
+        .map(|edge| {
+            //The fill lines run along x after rotating the region by the angle
+            let delta = edge.delta();
+            -delta.y.atan2(delta.x).to_degrees()
+        })
+        .unwrap_or(default_angle)
+        + settings.infill_angle_offset
+}
+
 pub fn solid_infill_polygon(
     poly: &Polygon<f32>,
     settings: &LayerSettings,
@@ -104,13 +192,17 @@ pub fn solid_infill_polygon(
     match settings.solid_infill_type {
         SolidInfillTypes::Rectilinear => {
             //120 degrees between layers
-            let angle = 45.0 + (120_f32) * layer_count as f32;
+            let angle = solid_fill_angle(poly, settings, 45.0 + (120_f32) * layer_count as f32);
 
             linear_fill_polygon(poly, settings, fill_type, angle)
         }
 
         SolidInfillTypes::RectilinearCustom(degrees_per_angle) => {
-            let angle = 45.0 + (degrees_per_angle) * layer_count as f32;
+            let angle = solid_fill_angle(
+                poly,
+                settings,
+                45.0 + (degrees_per_angle) * layer_count as f32,
+            );
 
             linear_fill_polygon(poly, settings, fill_type, angle)
         }
@@ -132,87 +224,69 @@ pub fn partial_infill_polygon(
 
     let fill_type = ctx.move_from_trace_type(TraceType::Infill);
 
+    //Only used by the patterns made of spaced lines
+    let spacing = settings
+        .partial_infill_line_spacing(partial_infill_type, fill_ratio)
+        .unwrap_or(settings.extrusion_width.infill);
+    //An infill angle for the layer turns the pattern so its first direction lies at that angle
+    let first_direction = match partial_infill_type {
+        PartialInfillTypes::Linear => 0.0,
+        _ => 45.0,
+    };
+    let offset = settings.infill_angle_offset
+        + settings
+            .infill_angle
+            .map_or(0.0, |angle| angle - first_direction);
+
     match partial_infill_type {
-        PartialInfillTypes::Linear => partial_linear_fill_polygon(
-            poly,
-            settings,
-            fill_type,
-            settings.extrusion_width.infill / fill_ratio,
-            0.0,
-            0.0,
-        ),
+        PartialInfillTypes::Linear => {
+            partial_linear_fill_polygon(poly, settings, fill_type, spacing, offset, 0.0)
+        }
         PartialInfillTypes::Rectilinear => {
-            let mut fill = partial_linear_fill_polygon(
-                poly,
-                settings,
-                fill_type,
-                2.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0,
-                0.0,
-            );
+            let mut fill =
+                partial_linear_fill_polygon(poly, settings, fill_type, spacing, 45.0 + offset, 0.0);
             fill.append(&mut partial_linear_fill_polygon(
                 poly,
                 settings,
                 fill_type,
-                2.0 * settings.extrusion_width.infill / fill_ratio,
-                135.0,
+                spacing,
+                135.0 + offset,
                 0.0,
             ));
             fill
         }
         PartialInfillTypes::Triangle => {
-            let mut fill = partial_linear_fill_polygon(
-                poly,
-                settings,
-                fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0,
-                0.0,
-            );
+            let mut fill =
+                partial_linear_fill_polygon(poly, settings, fill_type, spacing, 45.0 + offset, 0.0);
             fill.append(&mut partial_linear_fill_polygon(
                 poly,
                 settings,
                 fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 60.0,
+                spacing,
+                45.0 + 60.0 + offset,
                 0.0,
             ));
             fill.append(&mut partial_linear_fill_polygon(
                 poly,
                 settings,
                 fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 120.0,
+                spacing,
+                45.0 + 120.0 + offset,
                 0.0,
             ));
             fill
         }
         PartialInfillTypes::Cubic => {
-            let mut fill = partial_linear_fill_polygon(
-                poly,
-                settings,
-                fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0,
-                layer_height / std::f32::consts::SQRT_2,
-            );
-            fill.append(&mut partial_linear_fill_polygon(
-                poly,
-                settings,
-                fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 120.0,
-                layer_height / std::f32::consts::SQRT_2,
-            ));
-            fill.append(&mut partial_linear_fill_polygon(
-                poly,
-                settings,
-                fill_type,
-                3.0 * settings.extrusion_width.infill / fill_ratio,
-                45.0 + 240.0,
-                layer_height / std::f32::consts::SQRT_2,
-            ));
-            fill
+            cubic_fill_polygon(poly, settings, fill_type, spacing, layer_height, offset)
+        }
+        PartialInfillTypes::AdaptiveCubic => {
+            adaptive_cubic_fill_polygon(poly, settings, fill_type, spacing, layer_height, offset)
+        }
+        PartialInfillTypes::Gyroid => {
+            gyroid_fill_polygon(poly, settings, fill_type, spacing, layer_height, offset)
+        }
+        PartialInfillTypes::Skeleton(thickened) => {
+            skeleton_fill_polygon(poly, settings, fill_type, thickened)
         }
         PartialInfillTypes::Lightning => {
             unreachable!()
@@ -220,150 +294,695 @@ pub fn partial_infill_polygon(
     }
 }
 
-pub fn spaced_fill_polygon(
+///Lines in 3 directions that shift with the height `z`, so consecutive layers stack into cubes
+fn cubic_fill_polygon(
     poly: &Polygon<f32>,
     settings: &LayerSettings,
     fill_type: MoveType,
     spacing: f32,
-    offset: f32,
+    z: f32,
+    angle: f32,
 ) -> Vec<MoveChain> {
-    get_monotone_sections(poly)
-        .iter()
-        .filter_map(|section| {
-            let mut current_y = (((section.left_chain[0].y + offset) / spacing).floor()
-                - (offset / spacing))
-                * spacing;
+    [45.0, 45.0 + 120.0, 45.0 + 240.0]
+        .into_iter()
+        .flat_map(|direction| {
+            partial_linear_fill_polygon(
+                poly,
+                settings,
+                fill_type,
+                spacing,
+                direction + angle,
+                z / std::f32::consts::SQRT_2,
+            )
+        })
+        .collect()
+}
 
-            let mut orient = true;
+///Cubic infill in cells that are finest, `spacing` wide, at the boundary of the polygon and
+///double in size towards its center. The regions filled at a higher density, like the transition
+///below top surfaces, start from a smaller spacing and so subdivide further. The cells lie on a
+///grid fixed to the bed, and the lines of a cell continue those of its neighbours, so the cells
+///of consecutive layers stack into a 3D structure.
+pub fn adaptive_cubic_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f32,
+    z: f32,
+    angle: f32,
+) -> Vec<MoveChain> {
+    let region = MultiPolygon(vec![poly.clone()]);
 
-            let mut start_point = None;
+    adaptive_cubic_cells(poly, spacing)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, cells)| !cells.is_empty())
+        .flat_map(|(level, cells)| {
+            let cell_spacing = spacing * 2_f32.powi(level as i32);
+
+            //Neighbouring cells of a size are filled together so their lines join up
+            MultiPolygon(cells)
+                .union_with(&MultiPolygon(vec![]))
+                .intersection_with(&region)
+                .into_iter()
+                .flat_map(|area| {
+                    cubic_fill_polygon(&area, settings, fill_type, cell_spacing, z, angle)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
 
-            let mut left_index = 0;
-            let mut right_index = 0;
+///The cells of the adaptive cubic infill reaching into the polygon, by level. Cells of level `n`
+///are `spacing * 2^n` wide. A cell is split into four while the boundary of the polygon is closer
+///to its center than its width.
+pub(super) fn adaptive_cubic_cells(poly: &Polygon<f32>, spacing: f32) -> Vec<Vec<Polygon<f32>>> {
+    let mut levels = vec![vec![]; ADAPTIVE_CUBIC_DEPTH as usize + 1];
+
+    let Some(bounds) = poly.bounding_rect() else {
+        return levels;
+    };
+
+    let root_size = spacing * 2_f32.powi(ADAPTIVE_CUBIC_DEPTH);
+    let grid_range =
+        |min: f32, max: f32| (min / root_size).floor() as i32..=(max / root_size).floor() as i32;
+
+    let mut cells: Vec<(i32, Coord<f32>)> = grid_range(bounds.min().x, bounds.max().x)
+        .flat_map(|x| {
+            grid_range(bounds.min().y, bounds.max().y).map(move |y| {
+                (
+                    ADAPTIVE_CUBIC_DEPTH,
+                    Coord {
+                        x: x as f32 * root_size,
+                        y: y as f32 * root_size,
+                    },
+                )
+            })
+        })
+        .collect();
 
-            let mut moves = vec![];
+    while let Some((level, min)) = cells.pop() {
+        let size = spacing * 2_f32.powi(level);
+        let center = Point(min + Coord::from((size / 2.0, size / 2.0)));
+        let distance = boundary_distance(poly, &center);
 
-            loop {
-                let mut connect_chain = vec![];
-                while left_index < section.left_chain.len()
-                    && section.left_chain[left_index].y > current_y
-                {
-                    if orient {
-                        connect_chain.push(section.left_chain[left_index]);
-                    }
-                    left_index += 1;
-                }
+        //Cells that don't reach into the polygon are left out
+        if !poly.contains(&center) && distance > size * std::f32::consts::FRAC_1_SQRT_2 {
+            continue;
+        }
 
-                if left_index == section.left_chain.len() || left_index == 0 {
-                    break;
-                }
+        if level > 0 && distance < size {
+            let half = size / 2.0;
+            cells.extend(
+                [(0.0, 0.0), (half, 0.0), (0.0, half), (half, half)]
+                    .into_iter()
+                    .map(|offset| (level - 1, min + Coord::from(offset))),
+            );
+        } else {
+            levels[level as usize]
+                .push(Rect::new(min, min + Coord::from((size, size))).to_polygon());
+        }
+    }
 
-                while right_index < section.right_chain.len()
-                    && section.right_chain[right_index].y > current_y
-                {
-                    if !orient {
-                        connect_chain.push(section.right_chain[right_index]);
-                    }
-                    right_index += 1;
-                }
+    levels
+}
 
-                if right_index == section.right_chain.len() || right_index == 0 {
-                    break;
-                }
+///Gyroid infill with the given period. The gyroid surface is cut at height `z` and the contour is
+///traced inside the polygon, so consecutive layers stack into the 3D gyroid
+pub fn gyroid_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    period: f32,
+    z: f32,
+    angle: f32,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+    let rotate_poly = poly.rotate_around_point(angle, Point(Coord::zero()));
+
+    let mut new_moves: Vec<MoveChain> = rotate_poly
+        .offset_from(
+            ((-settings.extrusion_width.interior_inner_perimeter / 2.0)
+                * (1.0 - settings.infill_perimeter_overlap_percentage))
+                + (settings.extrusion_width.interior_inner_perimeter / 2.0),
+        )
+        .iter()
+        .flat_map(|polygon| gyroid_contours(polygon, period, z))
+        .filter(|line| line.euclidean_length() >= width)
+        .map(|line| MoveChain {
+            start_point: line.0[0],
+            moves: line.0[1..]
+                .iter()
+                .map(|end| Move {
+                    end: *end,
+                    move_type: fill_type,
+                    width,
+                })
+                .collect(),
+            is_loop: line.is_closed(),
+        })
+        .collect();
 
-                let left_top = section.left_chain[left_index - 1];
-                let left_bot = section.left_chain[left_index];
-                let right_top = section.right_chain[right_index - 1];
-                let right_bot = section.right_chain[right_index];
-
-                let left_point = point_y_lerp(&left_top, &left_bot, current_y);
-                let right_point = point_y_lerp(&right_top, &right_bot, current_y);
-
-                //add moves to connect lines together
-                if start_point.is_some() {
-                    //Only if not first point
-                    let mut y = None;
-
-                    for point in connect_chain {
-                        moves.push(Move {
-                            end: point,
-                            //don''t fill lateral y moves
-                            move_type: if y == Some(point.y) {
-                                MoveType::Travel
-                            } else {
-                                fill_type
-                            },
-                            width: settings
-                                .extrusion_width
-                                .get_value_for_movement_type(&fill_type),
-                        });
-
-                        y = Some(point.y);
+    for chain in new_moves.iter_mut() {
+        chain.rotate(-angle.to_radians());
+    }
+
+    new_moves
+}
+
+///The zero contour of the gyroid at height `z` inside the polygon, traced with marching squares.
+///Each contour is returned as one line for as long as it stays inside the polygon.
+fn gyroid_contours(polygon: &Polygon<f32>, period: f32, z: f32) -> Vec<LineString<f32>> {
+    let Some(rect) = polygon.bounding_rect() else {
+        return vec![];
+    };
+
+    let k = std::f32::consts::TAU / period;
+    let gyroid = |x: f32, y: f32| {
+        (k * x).sin() * (k * y).cos()
+            + (k * y).sin() * (k * z).cos()
+            + (k * z).sin() * (k * x).cos()
+    };
+
+    //The samples are aligned to a global grid so neighbouring regions trace the same contour
+    let step = period / GYROID_SAMPLES_PER_PERIOD;
+    let origin = Coord {
+        x: (rect.min().x / step).floor() * step,
+        y: (rect.min().y / step).floor() * step,
+    };
+    let columns = ((rect.max().x - origin.x) / step).ceil() as usize + 2;
+    let rows = ((rect.max().y - origin.y) / step).ceil() as usize + 2;
+
+    let sample = |column: usize, row: usize| Coord {
+        x: origin.x + column as f32 * step,
+        y: origin.y + row as f32 * step,
+    };
+    let values: Vec<Vec<f32>> = (0..columns)
+        .map(|column| {
+            (0..rows)
+                .map(|row| {
+                    let point = sample(column, row);
+                    gyroid(point.x, point.y)
+                })
+                .collect()
+        })
+        .collect();
+
+    //An edge of the grid from a sample to the next one along x, or along y if the flag is set
+    type Edge = (usize, usize, bool);
+    let crossing = |(column, row, along_y): Edge| -> Option<Coord<f32>> {
+        let (next_column, next_row) = if along_y {
+            (column, row + 1)
+        } else {
+            (column + 1, row)
+        };
+        let a = values[column][row];
+        let b = values[next_column][next_row];
+
+        ((a < 0.0) != (b < 0.0)).then(|| {
+            let start = sample(column, row);
+            start + (sample(next_column, next_row) - start) * (a / (a - b))
+        })
+    };
+
+    //Edges joined by the contour within a cell
+    let mut neighbours: BTreeMap<Edge, Vec<Edge>> = BTreeMap::new();
+    for column in 0..columns - 1 {
+        for row in 0..rows - 1 {
+            //Counterclockwise from the bottom
+            let edges = [
+                (column, row, false),
+                (column + 1, row, true),
+                (column, row + 1, false),
+                (column, row, true),
+            ];
+            let crossed: Vec<Edge> = edges
+                .into_iter()
+                .filter(|edge| crossing(*edge).is_some())
+                .collect();
+
+            let joins = match crossed.len() {
+                2 => vec![(crossed[0], crossed[1])],
+                4 => {
+                    //A saddle, the center decides which pair of opposite corners is connected
+                    let center = sample(column, row)
+                        + Coord {
+                            x: step / 2.0,
+                            y: step / 2.0,
+                        };
+                    if (gyroid(center.x, center.y) < 0.0) == (values[column][row] < 0.0) {
+                        vec![(edges[0], edges[1]), (edges[2], edges[3])]
+                    } else {
+                        vec![(edges[3], edges[0]), (edges[1], edges[2])]
                     }
                 }
+                _ => vec![],
+            };
 
-                start_point = start_point.or(Some(Coord {
-                    x: left_point.x,
-                    y: current_y,
-                }));
+            for (a, b) in joins {
+                neighbours.entry(a).or_default().push(b);
+                neighbours.entry(b).or_default().push(a);
+            }
+        }
+    }
 
-                if orient {
-                    moves.push(Move {
-                        end: Coord {
-                            x: left_point.x,
-                            y: current_y,
-                        },
-                        move_type: fill_type,
-                        width: settings
-                            .extrusion_width
-                            .get_value_for_movement_type(&fill_type),
-                    });
-
-                    moves.push(Move {
-                        end: Coord {
-                            x: right_point.x,
-                            y: current_y,
-                        },
-                        move_type: fill_type,
-                        width: settings
-                            .extrusion_width
-                            .get_value_for_movement_type(&fill_type),
-                    });
-                } else {
-                    moves.push(Move {
-                        end: Coord {
-                            x: right_point.x,
-                            y: current_y,
-                        },
-                        move_type: fill_type,
-                        width: settings
-                            .extrusion_width
-                            .get_value_for_movement_type(&fill_type),
-                    });
-
-                    moves.push(Move {
-                        end: Coord {
-                            x: left_point.x,
-                            y: current_y,
-                        },
-                        move_type: fill_type,
-                        width: settings
-                            .extrusion_width
-                            .get_value_for_movement_type(&fill_type),
-                    });
-                }
+    //Open contours are followed from one of their ends, whatever is left over are loops
+    let ends = neighbours
+        .iter()
+        .filter(|(_, joined)| joined.len() == 1)
+        .map(|(edge, _)| *edge);
+    let starts: Vec<Edge> = ends.chain(neighbours.keys().copied()).collect();
+
+    let mut visited = BTreeSet::new();
+    let mut contours = vec![];
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+
+        let mut path = vec![start];
+        let mut current = start;
+        while let Some(next) = neighbours[&current]
+            .iter()
+            .find(|edge| !visited.contains(*edge))
+        {
+            visited.insert(*next);
+            path.push(*next);
+            current = *next;
+        }
+
+        if path.len() > 2 && neighbours[&current].contains(&start) {
+            path.push(start);
+        }
+
+        let points = path
+            .into_iter()
+            .map(|edge| crossing(edge).expect("Joined edges are crossed by the contour"))
+            .collect();
+        contours.push(LineString(points));
+    }
+
+    clip_lines_to_polygon(contours, polygon)
+}
+
+///Fiber infill along an orientation field cut at height `z`. Lines are traced through the field
+///from seeds `spacing` apart across the middle of the polygon and clipped to it, so they curve with
+///the field. Where the field converges the lines come closer than `spacing`, they are not pushed
+///apart.
+pub fn field_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f32,
+    field: &OrientationField,
+    z: f32,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+    let max_angle = settings.fiber.max_angle.to_radians();
+
+    poly.offset_from(
+        ((-settings.extrusion_width.interior_inner_perimeter / 2.0)
+            * (1.0 - settings.infill_perimeter_overlap_percentage))
+            + (settings.extrusion_width.interior_inner_perimeter / 2.0),
+    )
+    .iter()
+    .flat_map(|polygon| {
+        let Some(rect) = polygon.bounding_rect() else {
+            return vec![];
+        };
+        let center = vec2(rect.center().x, rect.center().y);
+        let Some(direction) = field.direction_at(center.x, center.y, z) else {
+            return vec![];
+        };
+
+        //Seeds on a line across the field far enough out to reach every corner of the polygon
+        let seeds = (rect.width().hypot(rect.height()) / 2.0 / spacing).ceil() as i32;
+        let lines = (-seeds..=seeds).flat_map(|seed| {
+            let start = center + direction.perp() * (seed as f32 * spacing);
+            trace_field_line(field, start, direction, z, &rect, max_angle)
+        });
+
+        clip_lines_to_polygon(lines, polygon)
+    })
+    .filter(|line| line.euclidean_length() >= width)
+    .enumerate()
+    .map(|(index, mut line)| {
+        //Every other line runs back so consecutive lines start close to each other
+        if index % 2 == 1 {
+            line.0.reverse();
+        }
+
+        MoveChain {
+            start_point: line.0[0],
+            moves: line.0[1..]
+                .iter()
+                .map(|end| Move {
+                    end: *end,
+                    move_type: fill_type,
+                    width,
+                })
+                .collect(),
+            is_loop: false,
+        }
+    })
+    .collect()
+}
 
-                orient = !orient;
-                current_y -= spacing;
+///Follow the field from `seed` both ways until the line leaves `bounds`. The fiber can't follow a
+///bend sharper than `max_angle` radians, so the line is split where the field turns further than
+///that within one step.
+fn trace_field_line(
+    field: &OrientationField,
+    seed: Vec2,
+    heading: Vec2,
+    z: f32,
+    bounds: &Rect<f32>,
+    max_angle: f32,
+) -> Vec<LineString<f32>> {
+    //A field turning in circles would otherwise never leave the bounds
+    let max_steps = ((bounds.width() + bounds.height()) * 2.0 / FIELD_TRACE_STEP) as usize;
+    let inside = |point: Vec2| {
+        point.x >= bounds.min().x - FIELD_TRACE_STEP
+            && point.x <= bounds.max().x + FIELD_TRACE_STEP
+            && point.y >= bounds.min().y - FIELD_TRACE_STEP
+            && point.y <= bounds.max().y + FIELD_TRACE_STEP
+    };
+
+    //The pieces of the line from the seed onwards
+    let half = |mut heading: Vec2| {
+        let mut pieces = vec![vec![seed]];
+        let mut position = seed;
+        let mut previous: Option<Vec2> = None;
+
+        for _ in 0..max_steps {
+            let Some(direction) = field.direction_at(position.x, position.y, z) else {
+                break;
+            };
+            //The field has no sign, keep going the way the line already goes
+            let direction = if direction.dot(heading) < 0.0 {
+                -direction
+            } else {
+                direction
+            };
+
+            if previous.is_some_and(|previous| direction.angle_to(previous).abs() > max_angle) {
+                pieces.push(vec![position]);
             }
 
-            start_point.map(|start_point| MoveChain {
-                start_point,
-                moves,
-                is_loop: false,
-            })
+            position += direction * FIELD_TRACE_STEP;
+            heading = direction;
+            previous = Some(direction);
+            pieces
+                .last_mut()
+                .expect("Starts with a piece")
+                .push(position);
+
+            if !inside(position) {
+                break;
+            }
+        }
+
+        pieces
+    };
+
+    let mut backward = half(-heading).into_iter();
+    let mut forward = half(heading).into_iter();
+
+    //The first pieces of both halves meet at the seed
+    let mut joined: Vec<Vec2> = backward.next().expect("Starts with a piece");
+    joined.reverse();
+    joined.extend(
+        forward
+            .next()
+            .expect("Starts with a piece")
+            .into_iter()
+            .skip(1),
+    );
+
+    let mut pieces: Vec<Vec<Vec2>> = backward
+        .rev()
+        .map(|mut piece| {
+            piece.reverse();
+            piece
         })
-        .collect::<Vec<_>>()
+        .collect();
+    pieces.push(joined);
+    pieces.extend(forward);
+
+    pieces
+        .into_iter()
+        .filter(|piece| piece.len() >= 2)
+        .map(|piece| {
+            piece
+                .into_iter()
+                .map(|point| Coord {
+                    x: point.x,
+                    y: point.y,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+///Splits the lines into their parts inside the polygon, leaving out the parts in its holes. The
+///boundary counts as inside, so a line touching a hole at a vertex or running along one of its
+///edges stays in one piece. A closed line that never leaves the polygon stays whole.
+pub fn clip_lines_to_polygon(
+    lines: impl IntoIterator<Item = LineString<f32>>,
+    polygon: &Polygon<f32>,
+) -> Vec<LineString<f32>> {
+    let edges: Vec<Line<f32>> = std::iter::once(polygon.exterior())
+        .chain(polygon.interiors())
+        .flat_map(|ring| ring.lines())
+        .collect();
+
+    lines
         .into_iter()
+        .flat_map(|line| clip_line_to_edges(line, polygon, &edges))
         .collect()
 }
+
+fn clip_line_to_edges(
+    line: LineString<f32>,
+    polygon: &Polygon<f32>,
+    edges: &[Line<f32>],
+) -> Vec<LineString<f32>> {
+    //Cut every segment wherever it meets the boundary, each piece is then inside or outside as a whole
+    let mut pieces: Vec<(Coord<f32>, Coord<f32>, bool)> = vec![];
+    for segment in line.lines() {
+        let length_squared = segment.dx() * segment.dx() + segment.dy() * segment.dy();
+        if length_squared <= 0.0 {
+            continue;
+        }
+        let parameter = |point: Coord<f32>| {
+            let offset = point - segment.start;
+            (offset.x * segment.dx() + offset.y * segment.dy()) / length_squared
+        };
+
+        let mut cuts: Vec<f32> = edges
+            .iter()
+            .flat_map(|edge| match line_intersection(segment, *edge) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => {
+                    vec![parameter(intersection)]
+                }
+                Some(LineIntersection::Collinear { intersection }) => {
+                    vec![parameter(intersection.start), parameter(intersection.end)]
+                }
+                None => vec![],
+            })
+            .filter(|cut| *cut > CLIP_EPSILON && *cut < 1.0 - CLIP_EPSILON)
+            .chain([0.0, 1.0])
+            .collect();
+        cuts.sort_by(|a, b| a.partial_cmp(b).expect("Cuts are finite"));
+        cuts.dedup_by(|a, b| *a - *b < CLIP_EPSILON);
+
+        pieces.extend(cuts.windows(2).map(|pair| {
+            let start = segment.start + segment.delta() * pair[0];
+            let end = segment.start + segment.delta() * pair[1];
+            let inside = polygon.coordinate_position(&((start + end) / 2.0)) != CoordPos::Outside;
+            (start, end, inside)
+        }));
+    }
+
+    if pieces.iter().all(|(_, _, inside)| *inside) {
+        return if pieces.is_empty() {
+            vec![]
+        } else {
+            vec![line]
+        };
+    }
+
+    let mut parts: Vec<Vec<Coord<f32>>> = vec![];
+    let mut previous_inside = false;
+    for (start, end, inside) in &pieces {
+        match (previous_inside, inside) {
+            (true, true) => parts.last_mut().expect("A part was started").push(*end),
+            (false, true) => parts.push(vec![*start, *end]),
+            _ => {}
+        }
+        previous_inside = *inside;
+    }
+
+    //The parts of a closed line on either side of its start are one part
+    let wraps =
+        matches!(pieces.first(), Some((_, _, true))) && matches!(pieces.last(), Some((_, _, true)));
+    if line.is_closed() && wraps && parts.len() > 1 {
+        let first = parts.remove(0);
+        parts
+            .last_mut()
+            .expect("More than one part")
+            .extend_from_slice(&first[1..]);
+    }
+
+    parts.into_iter().map(LineString).collect()
+}
+
+pub fn skeleton_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    thickened: bool,
+) -> Vec<MoveChain> {
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+
+    //A skeleton collapsed to its center still gets a short bead
+    let half_bead = Coord {
+        x: width / 2.0,
+        y: 0.0,
+    };
+
+    medial_axis(poly, width / 2.0, width / 2.0)
+        .into_iter()
+        .map(|line| match line.0.as_slice() {
+            [center] => vec![*center - half_bead, *center + half_bead],
+            points => points.to_vec(),
+        })
+        .filter(|points| points.len() > 1)
+        .map(|points| MoveChain {
+            start_point: points[0],
+            moves: points[1..]
+                .iter()
+                .map(|end| Move {
+                    end: *end,
+                    move_type: fill_type,
+                    width: if thickened {
+                        (2.0 * boundary_distance(poly, &Point(*end))).clamp(width, 2.0 * width)
+                    } else {
+                        width
+                    },
+                })
+                .collect(),
+            is_loop: false,
+        })
+        .collect()
+}
+
+///Lines `spacing` apart across the polygon, clipped to it. Each line is joined to the end of a
+///line above where the step between them is short and stays inside the polygon, so the fill runs
+///back and forth in as few chains as possible.
+pub fn spaced_fill_polygon(
+    poly: &Polygon<f32>,
+    settings: &LayerSettings,
+    fill_type: MoveType,
+    spacing: f32,
+    offset: f32,
+) -> Vec<MoveChain> {
+    let Some(rect) = poly.bounding_rect() else {
+        return vec![];
+    };
+    let width = settings
+        .extrusion_width
+        .get_value_for_movement_type(&fill_type);
+    let fill_move = |end: Coord<f32>| Move {
+        end,
+        move_type: fill_type,
+        width,
+    };
+    let stays_inside = |from: Coord<f32>, to: Coord<f32>| {
+        let step = LineString(vec![from, to]);
+        matches!(clip_lines_to_polygon([step.clone()], poly).as_slice(), [part] if *part == step)
+    };
+
+    //The lines are on the multiples of the spacing shifted by the offset, from the top down.
+    //Lines on the top or bottom edge would only trace the boundary
+    let top = ((rect.max().y + offset) / spacing).floor();
+    let heights = (0..)
+        .map(|step| (top - step as f32) * spacing - offset)
+        .skip_while(|y| *y >= rect.max().y)
+        .take_while(|y| *y > rect.min().y);
+
+    //Every chain with the index of the last line it reached
+    let mut chains: Vec<(MoveChain, usize)> = vec![];
+
+    for (index, y) in heights.enumerate() {
+        let line = LineString(vec![
+            Coord {
+                x: rect.min().x - spacing,
+                y,
+            },
+            Coord {
+                x: rect.max().x + spacing,
+                y,
+            },
+        ]);
+
+        for segment in clip_lines_to_polygon([line], poly) {
+            let (left, right) = (segment.0[0], segment.0[segment.0.len() - 1]);
+
+            //The closest chain ending on the line above that can step over to this segment
+            let joined = chains
+                .iter_mut()
+                .filter(|(_, last)| *last + 1 == index)
+                .filter_map(|(chain, last)| {
+                    let end = chain.moves.last().map_or(chain.start_point, |m| m.end);
+                    let (near, far) =
+                        if end.euclidean_distance(&left) <= end.euclidean_distance(&right) {
+                            (left, right)
+                        } else {
+                            (right, left)
+                        };
+
+                    //Longer steps would lay a bead across the lines beside a hole or notch,
+                    //those segments start a chain of their own
+                    let step = end.euclidean_distance(&near);
+                    (step <= 2.0 * spacing && stays_inside(end, near))
+                        .then_some((step, chain, last, near, far))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+
+            match joined {
+                Some((_, chain, last, near, far)) => {
+                    chain.moves.push(fill_move(near));
+                    chain.moves.push(fill_move(far));
+                    *last = index;
+                }
+                None => {
+                    //Every other line starts on the right, so chains that can't be joined still
+                    //start close to where the last one ended
+                    let (start, end) = if index % 2 == 0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+
+                    chains.push((
+                        MoveChain {
+                            start_point: start,
+                            moves: vec![fill_move(end)],
+                            is_loop: false,
+                        },
+                        index,
+                    ));
+                }
+            }
+        }
+    }
+
+    chains.into_iter().map(|(chain, _)| chain).collect()
+}
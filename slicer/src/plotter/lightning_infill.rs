@@ -491,7 +491,10 @@ fn get_closest_intersection_point_on_polygon(
         .map(|(c, _d)| c)
 }
 
-fn closest_point_exterior_point(poly: &MultiPolygon<f32>, p: &Point<f32>) -> Closest<f32> {
+pub(super) fn closest_point_exterior_point(
+    poly: &MultiPolygon<f32>,
+    p: &Point<f32>,
+) -> Closest<f32> {
     closest_of(
         poly.iter()
             .flat_map(|p| p.interiors().iter().chain(std::iter::once(p.exterior()))),
@@ -1,15 +1,19 @@
 mod infill;
 pub(crate) mod lightning_infill;
-mod monotone;
 pub mod polygon_operations;
+mod skeleton;
 pub(crate) mod support;
+pub(crate) mod tree_support;
 mod walls;
 
 use crate::{Move, MoveChain, PassContext, TraceType};
 
-use crate::settings::SkirtSettings;
+use crate::settings::{CoastingSettings, IroningSettings, SkirtSettings};
 use crate::utils::point_lerp;
-use crate::{Command, MoveType, Object, RetractionType, Settings, Slice, StateChange};
+use crate::{
+    calculation, ApproachMode, Command, MoveType, Object, PartialInfillTypes, PrintSequence,
+    RetractionType, Settings, Slice, StateChange, WipeDirection,
+};
 use geo::coordinate_position::CoordPos;
 use geo::coordinate_position::CoordinatePosition;
 use geo::prelude::*;
@@ -19,6 +23,7 @@ use itertools::Itertools;
 use ordered_float::OrderedFloat;
 use polygon_operations::PolygonOperations;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use skeleton::{boundary_distance, medial_axis, BoundaryIndex};
 use walls::*;
 
 pub trait Plotter {
@@ -28,6 +33,9 @@ pub trait Plotter {
         wall_ranges: &[u32],
         layer: usize,
     );
+    fn place_seams(&mut self, previous_seams: &[Coord<f32>], layer: usize) -> Vec<Coord<f32>>;
+    fn slice_thin_walls_into_chains(&mut self);
+    fn fill_gaps(&mut self, min_width: f32, max_width: f32);
     fn shrink_layer(&mut self);
     fn fill_remaining_area_partially(
         &mut self,
@@ -42,13 +50,26 @@ pub trait Plotter {
         layer_count: usize,
         ctx: &PassContext,
     );
-    fn fill_solid_bridge_area(&mut self, layer_below: &MultiPolygon<f32>, ctx: &PassContext);
+    fn fill_transition_area(
+        &mut self,
+        area: &MultiPolygon<f32>,
+        fill_ratio: f32,
+        layer_count: usize,
+        ctx: &PassContext,
+    );
+    fn fill_solid_bridge_area(
+        &mut self,
+        layer_below: &MultiPolygon<f32>,
+        settings: &Settings,
+        ctx: &PassContext,
+    );
     fn fill_solid_top_layer(
         &mut self,
         layer_above: &MultiPolygon<f32>,
         layer: usize,
         ctx: &PassContext,
     );
+    fn iron_top_surface(&mut self, ironing: &IroningSettings, layer: usize);
     fn generate_skirt(
         &mut self,
         convex_polygon: &Polygon<f32>,
@@ -56,6 +77,8 @@ pub trait Plotter {
         settings: &Settings,
     );
     fn generate_brim(&mut self, entire_first_layer: MultiPolygon<f32>, brim_width: f32);
+    fn generate_wipe_tower(&mut self, tower: &Polygon<f32>);
+    fn generate_prime(&mut self, start: Coord<f32>, end: Coord<f32>);
     fn order_chains(&mut self);
     fn slice_into_commands(&mut self, commands: &mut Vec<Command>, layer_thickness: f32);
 }
@@ -67,6 +90,14 @@ impl Plotter for Slice {
         wall_ranges: &[u32],
         layer: usize,
     ) {
+        //Layers between the inner wall layers only print the outer wall, infill takes the rest.
+        //Modifiers aren't validated, an interval of 0 prints the inner walls on every layer.
+        let number_of_perimeters =
+            match layer.checked_rem(self.layer_settings.inner_walls_every_layers) {
+                None | Some(0) => number_of_perimeters,
+                Some(_) => number_of_perimeters.min(1),
+            };
+
         let mut new_chains = self
             .remaining_area
             .iter()
@@ -100,7 +131,103 @@ impl Plotter for Slice {
                     * self.layer_settings.extrusion_width.exterior_inner_perimeter)
         };
 
-        self.remaining_area = self.remaining_area.offset_from(-perimeter_inset);
+        //Walls widened by the alternating wall width push the remaining area further in
+        let alternating_inset: f32 = (0..number_of_perimeters)
+            .map(|wall| alternating_width(&self.layer_settings, wall, layer))
+            .sum();
+
+        self.remaining_area = self
+            .remaining_area
+            .offset_from(-(perimeter_inset + alternating_inset));
+    }
+
+    fn place_seams(&mut self, previous_seams: &[Coord<f32>], layer: usize) -> Vec<Coord<f32>> {
+        let position = self.layer_settings.seam_position;
+        let hints = &self.layer_settings.seam_hints;
+
+        self.fixed_chains
+            .iter_mut()
+            .filter(|chain| chain.is_loop)
+            .flat_map(|chain| {
+                chain.rotate_loops(|vertices, from| {
+                    dependent_seam_index(vertices, from, previous_seams, position, hints, layer)
+                })
+            })
+            .collect()
+    }
+
+    fn slice_thin_walls_into_chains(&mut self) {
+        let wall_width = self
+            .layer_settings
+            .extrusion_width
+            .exterior_surface_perimeter;
+
+        //Anything that doesn't survive an opening by two walls gets a single wall. Squared joins
+        //would cut the corners off and leave slivers there for the thin walls.
+        let body = self
+            .remaining_area
+            .offset_miter_from(-wall_width)
+            .offset_miter_from(wall_width);
+        let thin = self.remaining_area.difference_with(&body);
+
+        self.fixed_chains.extend(thin_wall_chains(
+            &thin,
+            &self.remaining_area,
+            &body,
+            &self.layer_settings,
+        ));
+        self.remaining_area = body;
+    }
+
+    fn fill_gaps(&mut self, min_width: f32, max_width: f32) {
+        //Anything that doesn't survive an opening by the max width is too thin for infill
+        let main_area = self
+            .remaining_area
+            .offset_from(-max_width / 2.0)
+            .offset_from(max_width / 2.0);
+
+        let gaps = self.remaining_area.difference_with(&main_area);
+
+        let move_type = MoveType::WithoutFiber(TraceType::SolidInfill);
+        let resolution = max_width / 10.0;
+
+        let mut filled_gaps = vec![];
+
+        for gap in &gaps {
+            let new_chains: Vec<MoveChain> = medial_axis(gap, resolution, resolution)
+                .into_iter()
+                .filter(|line| line.0.len() > 1)
+                .map(|line| MoveChain {
+                    start_point: line.0[0],
+                    moves: line.0[1..]
+                        .iter()
+                        .map(|end| Move {
+                            end: *end,
+                            move_type,
+                            //The bead matches the local width of the gap
+                            width: (2.0 * boundary_distance(gap, &Point(*end))).min(max_width),
+                        })
+                        .collect(),
+                    is_loop: false,
+                })
+                .collect();
+
+            //Gaps thinner than the min width everywhere would only get a smear of plastic
+            let widest = new_chains
+                .iter()
+                .flat_map(|chain| chain.moves.iter())
+                .map(|m| m.width)
+                .fold(0.0, f32::max);
+
+            if !new_chains.is_empty() && widest >= min_width {
+                self.chains.extend(new_chains);
+                filled_gaps.push(gap.clone());
+            }
+        }
+
+        self.remaining_area = self
+            .remaining_area
+            .difference_with(&MultiPolygon(filled_gaps));
     }
 
     fn shrink_layer(&mut self) {
@@ -129,15 +256,33 @@ impl Plotter for Slice {
 
         //For each region still available fill wih infill
         for poly in self.remaining_area.iter() {
-            let new_moves = partial_infill_polygon(
-                poly,
-                &self.layer_settings,
-                fill_ratio,
-                layer_count,
-                self.get_height(),
-                self.layer_settings.fiber.infill.partial_infill_type,
-                ctx,
-            );
+            //Tiny regions are left empty, they are still enclosed by the walls
+            if poly.unsigned_area() < self.layer_settings.skip_infill_below_area {
+                continue;
+            }
+
+            //An empty field has no direction to follow, the fiber falls back to the fixed angle
+            let new_moves = match &self.layer_settings.fiber.orientation_field {
+                Some(field) if ctx.is_fiber() && !field.is_empty() => field_fill_polygon(
+                    poly,
+                    &self.layer_settings,
+                    ctx.move_from_trace_type(TraceType::Infill),
+                    self.layer_settings
+                        .partial_infill_line_spacing(PartialInfillTypes::Linear, fill_ratio)
+                        .expect("Linear infill is made of spaced lines"),
+                    field,
+                    self.get_height(),
+                ),
+                _ => partial_infill_polygon(
+                    poly,
+                    &self.layer_settings,
+                    fill_ratio,
+                    layer_count,
+                    self.get_height(),
+                    self.layer_settings.fiber.infill.partial_infill_type,
+                    ctx,
+                ),
+            };
 
             let trace_polygons: Vec<Polygon<f32>> =
                 new_moves.par_iter().map(|chain| chain.into()).collect();
@@ -155,6 +300,16 @@ impl Plotter for Slice {
     }
 
     fn fill_remaining_area(&mut self, solid: bool, layer_count: usize, ctx: &PassContext) {
+        //Periodic solid layers through the sparse infill act as internal shells, counted from
+        //the first layer of the print. Modifiers aren't validated, an interval of 0 disables them.
+        let solid = solid
+            || (!ctx.is_fiber()
+                && self.layer_settings.solid_infill_every_layers.is_enabled()
+                && self
+                    .layer
+                    .checked_rem(*self.layer_settings.solid_infill_every_layers)
+                    == Some(0));
+
         //For each region still available fill wih infill
         for poly in &self.remaining_area {
             if solid {
@@ -169,7 +324,7 @@ impl Plotter for Slice {
                 for chain in new_moves {
                     self.chains.push(chain);
                 }
-            } else {
+            } else if poly.unsigned_area() >= self.layer_settings.skip_infill_below_area {
                 let fill_ratio = if ctx.is_fiber() {
                     self.layer_settings.fiber.infill.infill_percentage
                 } else {
@@ -218,7 +373,7 @@ impl Plotter for Slice {
                     poly,
                     layer_settings,
                     MoveType::WithoutFiber(TraceType::SolidInfill),
-                    angle,
+                    solid_fill_angle(poly, layer_settings, angle),
                 )
                 .into_iter()
             }));
@@ -226,32 +381,106 @@ impl Plotter for Slice {
         self.remaining_area = self.remaining_area.difference_with(&solid_area)
     }
 
-    fn fill_solid_bridge_area(&mut self, layer_below: &MultiPolygon<f32>, _ctx: &PassContext) {
+    fn fill_transition_area(
+        &mut self,
+        area: &MultiPolygon<f32>,
+        fill_ratio: f32,
+        layer_count: usize,
+        ctx: &PassContext,
+    ) {
+        //Fill the part of the remaining area inside the transition area at the given density
+        let transition_area = self.remaining_area.intersection_with(area);
+
+        for poly in &transition_area {
+            if poly.unsigned_area() < self.layer_settings.skip_infill_below_area {
+                continue;
+            }
+
+            let new_moves = partial_infill_polygon(
+                poly,
+                &self.layer_settings,
+                fill_ratio,
+                layer_count,
+                self.get_height(),
+                self.layer_settings.partial_infill_type,
+                ctx,
+            );
+
+            self.chains.extend(new_moves);
+        }
+
+        self.remaining_area = self.remaining_area.difference_with(&transition_area)
+    }
+
+    fn fill_solid_bridge_area(
+        &mut self,
+        layer_below: &MultiPolygon<f32>,
+        settings: &Settings,
+        _ctx: &PassContext,
+    ) {
         //For each area not in this slice that is in the other polygon, fill solid
 
-        let solid_area = self
-            .remaining_area
-            .difference_with(layer_below)
+        let mut unsupported_area = self.remaining_area.difference_with(layer_below);
+
+        //Spans that are too long are supported from below and filled as regular solid layers
+        if settings.max_bridge_length.is_enabled() {
+            let long_spans = long_bridge_spans(
+                &self.main_polygon,
+                layer_below,
+                self.layer_settings.extrusion_width.bridge,
+                *settings.max_bridge_length,
+            );
+
+            unsupported_area = unsupported_area.difference_with(&long_spans);
+        }
+
+        let solid_area = unsupported_area
             .offset_from(self.layer_settings.extrusion_width.bridge * 4.0)
             .intersection_with(&self.remaining_area);
 
         let layer_settings = &self.layer_settings;
+        let spacing = layer_settings.extrusion_width.bridge / settings.bridge_density;
+        let bridge_type = MoveType::WithoutFiber(TraceType::Bridging);
+        let flow_ratio = if settings.bridge.is_enabled() {
+            settings.bridge.flow_ratio
+        } else {
+            1.0
+        };
+
         self.chains
             .extend(&mut solid_area.0.iter().flat_map(|poly| {
-                let unsupported_area: MultiPolygon<f32> = poly.difference_with(layer_below);
-                let mut angle = get_optimal_bridge_angle(poly, &unsupported_area);
+                let angle = if settings.bridge_angle_override.is_enabled() {
+                    *settings.bridge_angle_override
+                } else {
+                    let unsupported_area: MultiPolygon<f32> = poly.difference_with(layer_below);
+                    let mut angle = get_optimal_bridge_angle(poly, &unsupported_area);
 
-                if angle < 0.0 {
-                    angle += 180.0;
-                }
+                    if angle < 0.0 {
+                        angle += 180.0;
+                    }
 
-                linear_fill_polygon(
+                    angle
+                };
+
+                overlap_linear_fill_polygon(
                     poly,
                     layer_settings,
-                    MoveType::WithoutFiber(TraceType::Bridging),
+                    bridge_type,
+                    spacing,
                     angle,
+                    0.0,
+                    layer_settings.solid_infill_overlap_percentage,
                 )
                 .into_iter()
+                .map(move |mut chain| {
+                    //Less plastic keeps the strands taut instead of sagging
+                    for m in chain.moves.iter_mut() {
+                        if m.move_type == bridge_type {
+                            m.width *= flow_ratio;
+                        }
+                    }
+                    chain
+                })
             }));
 
         self.remaining_area = self.remaining_area.difference_with(&solid_area)
@@ -265,14 +494,17 @@ impl Plotter for Slice {
     ) {
         //For each area not in this slice that is in the other polygon, fill solid
 
-        let solid_area = self
-            .remaining_area
-            .difference_with(layer_above)
+        let top_area = self.remaining_area.difference_with(layer_above);
+        let solid_area = top_area
             .offset_from(self.layer_settings.extrusion_width.solid_top_infill * 4.0)
             .intersection_with(&self.remaining_area);
 
         for poly in &solid_area {
-            let angle = 45.0 + (120_f32) * layer_count as f32;
+            let angle = solid_fill_angle(
+                poly,
+                &self.layer_settings,
+                45.0 + (120_f32) * layer_count as f32,
+            );
 
             let new_moves = linear_fill_polygon(
                 poly,
@@ -286,57 +518,82 @@ impl Plotter for Slice {
             }
         }
 
+        //Only the open top is ironed, not the solid area grown under the layer above
+        self.top_surface = self.top_surface.union_with(&top_area);
         self.remaining_area = self.remaining_area.difference_with(&solid_area)
     }
 
+    fn iron_top_surface(&mut self, ironing: &IroningSettings, layer_count: usize) {
+        //Cross the lines of the top infill so the nozzle flattens its ridges
+        let angle = 135.0 + (120_f32) * layer_count as f32;
+        let ironing_type = MoveType::WithoutFiber(TraceType::Ironing);
+
+        let new_chains = self
+            .top_surface
+            .iter()
+            .flat_map(|poly| {
+                overlap_linear_fill_polygon(
+                    poly,
+                    &self.layer_settings,
+                    ironing_type,
+                    ironing.spacing,
+                    angle,
+                    0.0,
+                    self.layer_settings.solid_infill_overlap_percentage,
+                )
+            })
+            .map(|mut chain| {
+                //Only a trickle of plastic is extruded to fill the gaps between the top lines
+                for m in chain.moves.iter_mut() {
+                    if m.move_type == ironing_type {
+                        m.width *= ironing.flow;
+                    }
+                }
+                chain
+            })
+            .collect::<Vec<_>>();
+
+        self.chains.extend(new_chains);
+    }
+
     fn generate_skirt(
         &mut self,
         convex_polygon: &Polygon<f32>,
         skirt_settings: &SkirtSettings,
         settings: &Settings,
     ) {
-        let offset_hull_multi = convex_polygon.offset_from(skirt_settings.distance);
-
-        assert_eq!(offset_hull_multi.0.len(), 1);
+        let bound = |point: &Coord<f32>| Coord {
+            x: point.x.max(0.0).min(settings.print_x),
+            y: point.y.max(0.0).min(settings.print_y),
+        };
 
-        let moves = offset_hull_multi.0[0]
-            .exterior()
-            .0
-            .iter()
-            .circular_tuple_windows::<(_, _)>()
-            .map(|(&_start, &end)| {
-                let bounded_endpoint = Coord {
-                    x: end.x.max(0.0).min(settings.print_x),
-                    y: end.y.max(0.0).min(settings.print_y),
-                };
+        //An empty hull has no skirt, one that falls apart gets a loop around every part
+        for polygon in convex_polygon.offset_from(skirt_settings.distance) {
+            let Some(start) = polygon.exterior().0.first() else {
+                continue;
+            };
 
-                Move {
-                    end: bounded_endpoint,
+            let moves = polygon
+                .exterior()
+                .0
+                .iter()
+                .circular_tuple_windows::<(_, _)>()
+                .map(|(_start, end)| Move {
+                    end: bound(end),
                     move_type: MoveType::WithoutFiber(TraceType::WallOuter),
                     width: self
                         .layer_settings
                         .extrusion_width
                         .exterior_surface_perimeter,
-                }
-            })
-            .collect();
+                })
+                .collect();
 
-        let start_point = Coord {
-            x: offset_hull_multi.0[0].exterior()[0]
-                .x
-                .max(0.0)
-                .min(settings.print_x),
-            y: offset_hull_multi.0[0].exterior()[0]
-                .y
-                .max(0.0)
-                .min(settings.print_y),
-        };
-
-        self.fixed_chains.push(MoveChain {
-            start_point,
-            moves,
-            is_loop: true,
-        });
+            self.fixed_chains.push(MoveChain {
+                start_point: bound(start),
+                moves,
+                is_loop: true,
+            });
+        }
     }
 
     fn generate_brim(&mut self, entire_first_layer: MultiPolygon<f32>, brim_width: f32) {
@@ -378,42 +635,78 @@ impl Plotter for Slice {
         );
     }
 
-    fn order_chains(&mut self) {
-        //Order Chains for fastest print
-        let ordered_chains = if !self.chains.is_empty() {
-            let mut ordered_chains = vec![self.chains.swap_remove(0)];
+    fn generate_wipe_tower(&mut self, tower: &Polygon<f32>) {
+        let mut chains = solid_infill_polygon(
+            tower,
+            &self.layer_settings,
+            MoveType::WithoutFiber(TraceType::SolidInfill),
+            self.layer,
+            self.top_height - self.bottom_height,
+        );
 
-            while !self.chains.is_empty() {
-                let index = self
-                    .chains
-                    .iter()
-                    .position_min_by_key(|a| {
-                        OrderedFloat(
-                            ordered_chains
-                                .last()
-                                .expect("Chains is tests not to be empty")
-                                .moves
-                                .last()
-                                .expect("chain should contain moves")
-                                .end
-                                .euclidean_distance(&a.start_point),
-                        )
-                    })
-                    .expect("Chains is tests not to be empty");
-                let closest_chain = self.chains.remove(index);
-                ordered_chains.push(closest_chain);
-            }
+        //The tower goes first so the nozzle is primed before it reaches the objects
+        chains.append(&mut self.fixed_chains);
+        self.fixed_chains = chains;
+    }
 
-            ordered_chains
-        } else {
-            vec![]
+    fn generate_prime(&mut self, start: Coord<f32>, end: Coord<f32>) {
+        let prime = MoveChain {
+            start_point: start,
+            moves: vec![Move {
+                end,
+                move_type: MoveType::WithoutFiber(TraceType::WallOuter),
+                width: self
+                    .layer_settings
+                    .extrusion_width
+                    .exterior_surface_perimeter,
+            }],
+            is_loop: false,
         };
 
+        //The prime goes first so the object starts with a full nozzle
+        self.fixed_chains.insert(0, prime);
+    }
+
+    fn order_chains(&mut self) {
+        //Ironing smooths the finished top surface so it follows everything else on the layer
+        let (ironing, chains): (Vec<_>, Vec<_>) = self.chains.drain(..).partition(|chain| {
+            chain
+                .moves
+                .iter()
+                .any(|m| m.move_type == MoveType::WithoutFiber(TraceType::Ironing))
+        });
+
+        let mut ordered_chains = vec![];
+        append_closest_chains(&mut ordered_chains, chains);
+        append_closest_chains(&mut ordered_chains, ironing);
+
         self.chains = ordered_chains;
     }
 
     fn slice_into_commands(&mut self, commands: &mut Vec<Command>, layer_thickness: f32) {
         if !self.fixed_chains.is_empty() {
+            //Retracting next to fiber would pull the fiber back with the filament
+            let suppress_retraction = self.layer_settings.fiber.suppress_retraction;
+            let mut chains = self
+                .fixed_chains
+                .drain(..)
+                .chain(self.chains.drain(..))
+                .peekable();
+
+            let first_has_fiber =
+                suppress_retraction && chains.peek().is_some_and(MoveChain::has_fiber);
+            let approach_retract = match self.layer_settings.layer_change_approach {
+                //Combing drops the retraction later if the travel can be routed inside the layer
+                ApproachMode::Direct | ApproachMode::Comb if first_has_fiber => {
+                    RetractionType::Lift
+                }
+                ApproachMode::Direct | ApproachMode::Comb => RetractionType::Retract,
+                //The previous layer left the nozzle lifted above its own height only
+                ApproachMode::LiftDescend => RetractionType::LayerLift {
+                    retract: !first_has_fiber,
+                },
+            };
+
             commands.push(Command::SetState {
                 new_state: StateChange {
                     extruder_temp: None,
@@ -421,21 +714,48 @@ impl Plotter for Slice {
                     fan_speed: None,
                     movement_speed: None,
                     acceleration: None,
-                    retract: RetractionType::Retract,
+                    retract: approach_retract,
                 },
             });
 
-            for chain in self.fixed_chains.drain(..).chain(self.chains.drain(..)) {
+            while let Some(mut chain) = chains.next() {
+                let lift_only = suppress_retraction
+                    && (chain.has_fiber() || chains.peek().is_some_and(MoveChain::has_fiber));
+
+                //Fiber is pulled through the nozzle by the plastic and can't coast
+                let coast_moves = if self.layer_settings.coasting.is_enabled() && !chain.has_fiber()
+                {
+                    split_coast(&mut chain, &self.layer_settings.coasting, layer_thickness)
+                } else {
+                    vec![]
+                };
+
                 let retraction_length = self.layer_settings.retraction_length;
-                let retract_command = if self.layer_settings.retraction_wipe.is_enabled() {
+                let retract_command = if lift_only {
+                    Command::SetState {
+                        new_state: StateChange {
+                            bed_temp: None,
+                            extruder_temp: None,
+                            fan_speed: None,
+                            movement_speed: Some(self.layer_settings.speed.travel),
+                            acceleration: Some(self.layer_settings.acceleration.travel),
+                            retract: RetractionType::Lift,
+                        },
+                    }
+                } else if self.layer_settings.retraction_wipe.is_enabled() {
                     let retraction_wipe = &self.layer_settings.retraction_wipe;
 
+                    //The wipe follows the path the nozzle took, coasting included
+                    let path = chain.moves.iter().chain(coast_moves.iter());
+                    let inward_end = match retraction_wipe.direction {
+                        WipeDirection::AlongPath => None,
+                        WipeDirection::Inward => {
+                            inward_wipe_end(path.clone(), retraction_wipe.distance)
+                        }
+                    };
                     let ordered: Vec<Coord<f32>> = if chain.is_loop {
                         //fixme this is bad, yes I know but I don't care
-                        chain
-                            .moves
-                            .iter()
-                            .rev()
+                        path.rev()
                             .take_while(|m| m.move_type != MoveType::Travel)
                             .map(|m| m.end)
                             .collect::<Vec<_>>()
@@ -443,7 +763,7 @@ impl Plotter for Slice {
                             .rev()
                             .collect_vec()
                     } else {
-                        chain.moves.iter().rev().map(|m| m.end).collect_vec()
+                        path.rev().map(|m| m.end).collect_vec()
                     };
 
                     let mut remaining_distance = retraction_wipe.distance;
@@ -506,6 +826,11 @@ impl Plotter for Slice {
                         }
                     }
 
+                    //Outer walls wipe straight into the part instead
+                    if let Some(end) = inward_end {
+                        wipe_moves = vec![(retraction_length, end)];
+                    }
+
                     Command::SetState {
                         new_state: StateChange {
                             extruder_temp: None,
@@ -533,6 +858,7 @@ impl Plotter for Slice {
                     end: chain.start_point,
                 });
                 commands.append(&mut chain.create_commands(&self.layer_settings, layer_thickness));
+                commands.extend(coast_moves.iter().map(|m| Command::MoveTo { end: m.end }));
 
                 commands.push(retract_command);
             }
@@ -540,6 +866,132 @@ impl Plotter for Slice {
     }
 }
 
+///End of a wipe from the end of an outer wall loop towards the inside of the loop, along the
+///bisector of the last corner. The wipe is shortened until it ends inside the loop. None if the
+///path doesn't end with an outer wall loop or no wipe fits inside.
+fn inward_wipe_end<'a>(
+    path: impl DoubleEndedIterator<Item = &'a Move>,
+    distance: f32,
+) -> Option<Coord<f32>> {
+    let mut loop_moves: Vec<&Move> = path
+        .rev()
+        .take_while(|m| m.move_type != MoveType::Travel)
+        .collect();
+    loop_moves.reverse();
+
+    let last = loop_moves.last()?;
+    if !matches!(
+        last.move_type,
+        MoveType::WithoutFiber(TraceType::WallOuter) | MoveType::WithFiber(TraceType::WallOuter)
+    ) {
+        return None;
+    }
+
+    //The loop closes at its last point
+    let end = last.end;
+    let previous = loop_moves.iter().rev().map(|m| m.end).find(|p| *p != end)?;
+    let next = loop_moves.iter().map(|m| m.end).find(|p| *p != end)?;
+
+    let polygon = Polygon::new(loop_moves.iter().map(|m| m.end).collect(), vec![]);
+
+    //The inside is left of the path for counter clockwise loops
+    let winding = polygon.signed_area().signum();
+    let inward_normal = |from: Coord<f32>, to: Coord<f32>| {
+        let direction = to - from;
+        Coord {
+            x: -direction.y,
+            y: direction.x,
+        } * (winding / from.euclidean_distance(&to))
+    };
+    let bisector = inward_normal(previous, end) + inward_normal(end, next);
+    let length = bisector.x.hypot(bisector.y);
+    if length < f32::EPSILON {
+        return None;
+    }
+
+    (0..8)
+        .map(|halving| end + bisector * (distance / (length * 2.0_f32.powi(halving))))
+        .find(|point| polygon.contains(point))
+}
+
+///Splits the moves holding the coasting volume off the end of the last extrusion of the chain.
+///Extrusions holding less than the coasting volume, or less than the minimum volume to coast,
+///are left whole and nothing is returned.
+fn split_coast(chain: &mut MoveChain, coasting: &CoastingSettings, thickness: f32) -> Vec<Move> {
+    //Volume of each move of the last extrusion, which starts after the last travel
+    let first = chain
+        .moves
+        .iter()
+        .rposition(|m| m.move_type == MoveType::Travel)
+        .map_or(0, |travel| travel + 1);
+    let volumes: Vec<(Coord<f32>, f32)> = (first..chain.moves.len())
+        .map(|index| {
+            let start = match index {
+                0 => chain.start_point,
+                index => chain.moves[index - 1].end,
+            };
+            let m = &chain.moves[index];
+
+            (
+                start,
+                start.euclidean_distance(&m.end) * m.width * thickness,
+            )
+        })
+        .collect();
+
+    let total: f32 = volumes.iter().map(|(_, volume)| volume).sum();
+    if total <= coasting.volume || total < coasting.min_volume_before_coast {
+        return vec![];
+    }
+
+    let mut remaining = coasting.volume;
+    let mut split = chain.moves.len();
+    for (start, volume) in volumes.into_iter().rev() {
+        if remaining <= 0.0 {
+            break;
+        }
+
+        split -= 1;
+        if volume > remaining {
+            //Only the end of this move coasts
+            let m = chain.moves[split];
+            let end = point_lerp(&start, &m.end, 1.0 - remaining / volume);
+            chain.moves.insert(split, Move { end, ..m });
+            split += 1;
+        }
+        remaining -= volume;
+    }
+
+    chain.moves.split_off(split)
+}
+
+///Order chains for fastest print, each chain starts closest to where the previous one ended
+fn append_closest_chains(ordered_chains: &mut Vec<MoveChain>, mut chains: Vec<MoveChain>) {
+    if ordered_chains.is_empty() && !chains.is_empty() {
+        ordered_chains.push(chains.swap_remove(0));
+    }
+
+    while !chains.is_empty() {
+        let index = chains
+            .iter()
+            .position_min_by_key(|a| {
+                OrderedFloat(
+                    ordered_chains
+                        .last()
+                        .expect("Chains is tests not to be empty")
+                        .moves
+                        .last()
+                        .expect("chain should contain moves")
+                        .end
+                        .euclidean_distance(&a.start_point),
+                )
+            })
+            .expect("Chains is tests not to be empty");
+        let closest_chain = chains.remove(index);
+        ordered_chains.push(closest_chain);
+    }
+}
+
 fn perpendicular_vector(dx: f32, dy: f32, length: f32) -> (f32, f32) {
     let magnitude = (dx.powi(2) + dy.powi(2)).sqrt();
     let unit_dx = dx / magnitude;
@@ -549,13 +1001,19 @@ fn perpendicular_vector(dx: f32, dy: f32, length: f32) -> (f32, f32) {
 
 impl From<&MoveChain> for Polygon<f32> {
     fn from(chain: &MoveChain) -> Self {
-        let points = chain
+        //The first move starts at the start point of the chain
+        let start = chain
             .moves
-            .iter()
-            .filter_map(|m| match m.move_type {
+            .first()
+            .filter(|m| m.move_type != MoveType::Travel)
+            .map(|m| (chain.start_point, m.width / 2.0));
+
+        let points = start
+            .into_iter()
+            .chain(chain.moves.iter().filter_map(|m| match m.move_type {
                 MoveType::WithFiber(_) | MoveType::WithoutFiber(_) => Some((m.end, m.width / 2.0)),
                 _ => None,
-            })
+            }))
             .collect_vec();
 
         let mut outer_ring = Vec::with_capacity(points.len() * 2 + 1);
@@ -567,8 +1025,11 @@ impl From<&MoveChain> for Polygon<f32> {
 
             let dx = end.x - start.x;
             let dy = end.y - start.y;
+            if dx == 0.0 && dy == 0.0 {
+                continue;
+            }
 
-            // Calculate perpendicular vectors for the buffer
+            // Calculate perpendicular vectors for the buffer, each segment with its own width
             let (px, py) = perpendicular_vector(dx, dy, window[1].1);
 
             // Add points to the outer and inner rings
@@ -591,6 +1052,10 @@ impl From<&MoveChain> for Polygon<f32> {
             });
         }
 
+        if outer_ring.is_empty() {
+            return Polygon::new(LineString(vec![]), vec![]);
+        }
+
         // Close the polygon
         inner_ring.reverse();
         outer_ring.extend(inner_ring);
@@ -600,6 +1065,81 @@ impl From<&MoveChain> for Polygon<f32> {
     }
 }
 
+///The parts of the area without the layer below under them that span further than the max
+///bridge length. Bridging and the support below long bridges both measure the outline, so they
+///agree on which spans are supported. Parts without anything below them are always too long.
+pub(crate) fn long_bridge_spans(
+    area: &MultiPolygon<f32>,
+    layer_below: &MultiPolygon<f32>,
+    resolution: f32,
+    max_bridge_length: f32,
+) -> MultiPolygon<f32> {
+    let unsupported_area = area.difference_with(layer_below);
+    let (Some(unsupported_bounds), Some(below_bounds)) = (
+        unsupported_area.bounding_rect(),
+        layer_below.bounding_rect(),
+    ) else {
+        return unsupported_area;
+    };
+
+    //The outline below is indexed once for every part, the index covers both so the search
+    //around any sampled point reaches all of it
+    let segments: Vec<Line<f32>> = layer_below
+        .iter()
+        .flat_map(|poly| std::iter::once(poly.exterior()).chain(poly.interiors()))
+        .flat_map(|ring| ring.lines())
+        .collect();
+    let bounds = Rect::new(
+        Coord {
+            x: unsupported_bounds.min().x.min(below_bounds.min().x),
+            y: unsupported_bounds.min().y.min(below_bounds.min().y),
+        },
+        Coord {
+            x: unsupported_bounds.max().x.max(below_bounds.max().x),
+            y: unsupported_bounds.max().y.max(below_bounds.max().y),
+        },
+    );
+    let index = BoundaryIndex::new(&segments, bounds, resolution);
+
+    unsupported_area
+        .into_iter()
+        .filter(|poly| bridge_span(poly, &index, resolution) > max_bridge_length)
+        .collect()
+}
+
+///The longest unsupported span of an area, measured as twice the largest distance from any
+///point of the area to the outline of the layer below. The area is sampled on a grid of size
+///`resolution`.
+fn bridge_span(
+    unsupported_area: &Polygon<f32>,
+    layer_below: &BoundaryIndex,
+    resolution: f32,
+) -> f32 {
+    let Some(bounds) = unsupported_area.bounding_rect() else {
+        return 0.0;
+    };
+
+    let cells_x = (bounds.width() / resolution).ceil().max(1.0) as usize;
+    let cells_y = (bounds.height() / resolution).ceil().max(1.0) as usize;
+
+    (0..cells_x)
+        .flat_map(|x| (0..cells_y).map(move |y| (x, y)))
+        .map(|(x, y)| {
+            Point::new(
+                bounds.min().x + (x as f32 + 0.5) * bounds.width() / cells_x as f32,
+                bounds.min().y + (y as f32 + 0.5) * bounds.height() / cells_y as f32,
+            )
+        })
+        .filter(|point| unsupported_area.contains(point))
+        .filter_map(|point| {
+            layer_below
+                .nearest(point.0)
+                .map(|nearest| point.euclidean_distance(&Point(nearest)))
+        })
+        .fold(0.0, f32::max)
+        * 2.0
+}
+
 fn get_optimal_bridge_angle(fill_area: &Polygon<f32>, unsupported_area: &MultiPolygon<f32>) -> f32 {
     let unsuported_lines: Vec<_> = unsupported_area
         .iter()
@@ -660,55 +1200,1790 @@ fn get_optimal_bridge_angle(fill_area: &Polygon<f32>, unsupported_area: &MultiPo
         .unwrap_or(0.0)
 }
 
-pub fn convert_objects_into_moves(objects: Vec<Object>, settings: &Settings) -> Vec<Command> {
-    // info!("Convert into Commnds");
-    let mut layer_moves: Vec<(f32, Vec<Command>)> = objects
-        .into_iter()
-        .enumerate()
-        .map(|(object_num, object)| {
-            let mut last_layer = 0.0;
+///The commands of a single object grouped by the top height of each layer
+pub type ObjectMoves = Vec<(f32, Vec<Command>)>;
 
-            object
-                .layers
-                .into_iter()
-                .enumerate()
-                .map(|(layer_num, mut slice)| {
-                    let layer_settings = settings.get_layer_settings(layer_num, slice.top_height);
-                    let mut moves = vec![];
-                    moves.push(Command::ChangeObject { object: object_num });
-                    moves.push(Command::LayerChange {
-                        z: slice.top_height,
-                        index: layer_num,
-                    });
-                    moves.push(Command::SetState {
-                        new_state: StateChange {
-                            extruder_temp: Some(layer_settings.extruder_temp),
-                            bed_temp: Some(layer_settings.bed_temp),
-                            fan_speed: Some(if layer_num < settings.fan.disable_fan_for_layers {
-                                0.0
-                            } else {
-                                settings.fan.fan_speed
-                            }),
-                            movement_speed: None,
-                            acceleration: None,
-                            retract: RetractionType::NoRetract,
-                        },
-                    });
-                    slice.slice_into_commands(&mut moves, slice.top_height - last_layer);
+pub fn convert_object_into_moves(
+    object_num: usize,
+    object: Object,
+    settings: &Settings,
+) -> ObjectMoves {
+    let mut last_layer = object
+        .layers
+        .first()
+        .map(|slice| slice.bottom_height)
+        .unwrap_or(0.0);
 
-                    last_layer = slice.top_height;
-                    (slice.top_height, moves)
-                })
-                .collect::<Vec<(f32, Vec<Command>)>>()
-        })
-        .flat_map(|a| a.into_iter())
-        .collect();
+    let extruder = object.extruder;
 
-    layer_moves
-        .sort_by(|(a, _), (b, _)| a.partial_cmp(b).expect("No NAN layer heights are allowed"));
+    //Where the previous layer of the object left the nozzle
+    let mut last_position = None;
 
-    layer_moves
+    object
+        .layers
         .into_iter()
-        .flat_map(|(_, layer_moves)| layer_moves)
+        .map(|mut slice| {
+            let layer_num = slice.layer;
+            let layer_settings = settings.get_layer_settings(layer_num, slice.top_height);
+
+            let extruder_temp = settings.extruder_layer_temp(extruder, &layer_settings);
+
+            let mut moves = vec![];
+            moves.push(Command::ChangeObject {
+                object: object_num,
+                extruder,
+            });
+            moves.push(Command::LayerChange {
+                z: slice.top_height + layer_settings.z_offset,
+                index: layer_num,
+            });
+            moves.push(Command::SetState {
+                new_state: StateChange {
+                    extruder_temp: Some(extruder_temp),
+                    bed_temp: Some(layer_settings.bed_temp),
+                    fan_speed: Some(layer_settings.fan_speed),
+                    movement_speed: None,
+                    acceleration: None,
+                    retract: RetractionType::NoRetract,
+                },
+            });
+            slice.slice_into_commands(&mut moves, slice.top_height - last_layer);
+
+            if slice.layer_settings.layer_change_approach == ApproachMode::Comb {
+                if let Some(from) = last_position {
+                    comb_layer_approach(&mut moves, from, &slice);
+                }
+            }
+            last_position = moves.iter().rev().find_map(move_end).or(last_position);
+
+            last_layer = slice.top_height;
+            (slice.top_height, moves)
+        })
         .collect()
 }
+
+pub fn flatten_object_moves(object_moves: &[ObjectMoves], settings: &Settings) -> Vec<Command> {
+    match settings.print_sequence {
+        PrintSequence::ByLayer => {
+            let mut layer_moves: Vec<&(f32, Vec<Command>)> =
+                object_moves.iter().flatten().collect();
+
+            layer_moves.sort_by(|(a, _), (b, _)| {
+                a.partial_cmp(b).expect("No NAN layer heights are allowed")
+            });
+
+            layer_moves
+                .into_iter()
+                .flat_map(|(_, layer_moves)| layer_moves.iter().cloned())
+                .collect()
+        }
+        PrintSequence::ByObject => {
+            let park = settings
+                .sequential_cooling
+                .is_enabled()
+                .then(|| safe_park_position(object_moves, settings));
+
+            //The way to the park position can pass over the objects printed before
+            let mut printed_height: f32 = 0.0;
+            let mut parked = false;
+            let mut moves = vec![];
+            for (_, layer_moves) in object_moves.iter().flatten() {
+                printed_height = layer_moves
+                    .iter()
+                    .filter_map(|cmd| match cmd {
+                        Command::LayerChange { z, .. } => Some(*z),
+                        _ => None,
+                    })
+                    .fold(printed_height, f32::max);
+
+                let start = moves.len();
+                moves.extend(layer_moves.iter().cloned());
+
+                //The filament is still retracted from parking, so the approach of the layer only
+                //lifts instead of retracting a second time
+                if parked {
+                    let approach = moves[start..].iter_mut().find_map(|cmd| match cmd {
+                        Command::SetState { new_state }
+                            if new_state.retract != RetractionType::NoRetract =>
+                        {
+                            Some(&mut new_state.retract)
+                        }
+                        _ => None,
+                    });
+                    if let Some(retract) = approach {
+                        if *retract == RetractionType::Retract {
+                            *retract = RetractionType::Lift;
+                        }
+                    }
+                }
+
+                let cooling = match park {
+                    Some(park) => cooling_moves(layer_moves, park, printed_height, settings),
+                    None => vec![],
+                };
+                parked = !cooling.is_empty();
+                moves.extend(cooling);
+            }
+            moves
+        }
+    }
+}
+
+///Height in mm the head keeps above everything printed on its way to the park position
+const PARK_CLEARANCE: f32 = 2.0;
+
+///A layer of an object printed on its own can finish faster than the slow down threshold. Park
+///away from the objects above the highest printed point, wait out the remaining time so the
+///layer can cool and return above the end of the layer.
+fn cooling_moves(
+    layer_moves: &[Command],
+    park: Coord<f32>,
+    printed_height: f32,
+    settings: &Settings,
+) -> Vec<Command> {
+    let layer_time = calculation::calculate_values(layer_moves, settings).total_time;
+    let wait = settings.fan.slow_down_threshold - layer_time;
+
+    let Some(layer_end) = layer_moves.iter().rev().find_map(extrusion_end) else {
+        return vec![];
+    };
+    if wait <= 0.0 {
+        return vec![];
+    }
+
+    vec![
+        Command::SetState {
+            new_state: StateChange {
+                movement_speed: Some(settings.speed.travel),
+                retract: RetractionType::ClearanceLift {
+                    z: printed_height + PARK_CLEARANCE,
+                },
+                ..Default::default()
+            },
+        },
+        Command::MoveTo { end: park },
+        Command::Delay {
+            msec: (wait * 1000.0) as u64,
+        },
+        Command::MoveTo { end: layer_end },
+    ]
+}
+
+///The configured park position, or the bed corner furthest from the objects if the configured
+///position is within the clearance of an object
+fn safe_park_position(object_moves: &[ObjectMoves], settings: &Settings) -> Coord<f32> {
+    let footprints: Vec<Polygon<f32>> = object_moves
+        .iter()
+        .filter_map(|object| {
+            let points: Vec<Point<f32>> = object
+                .iter()
+                .flat_map(|(_, layer_moves)| layer_moves.iter().filter_map(extrusion_end))
+                .map(Point)
+                .collect();
+
+            MultiPoint(points)
+                .bounding_rect()
+                .map(|rect| rect.to_polygon())
+        })
+        .collect();
+
+    let clearance = |point: Coord<f32>| {
+        footprints
+            .iter()
+            .map(|footprint| footprint.euclidean_distance(&Point(point)))
+            .fold(f32::INFINITY, f32::min)
+    };
+
+    let cooling = &settings.sequential_cooling;
+    let configured = Coord {
+        x: cooling.park_x,
+        y: cooling.park_y,
+    };
+
+    if clearance(configured) >= cooling.clearance {
+        return configured;
+    }
+
+    [
+        Coord { x: 0.0, y: 0.0 },
+        Coord {
+            x: settings.print_x,
+            y: 0.0,
+        },
+        Coord {
+            x: 0.0,
+            y: settings.print_y,
+        },
+        Coord {
+            x: settings.print_x,
+            y: settings.print_y,
+        },
+    ]
+    .into_iter()
+    .max_by_key(|corner| OrderedFloat(clearance(*corner)))
+    .expect("The bed has corners")
+}
+
+///Most corners of a layer the first travel is routed around, more complex layers are too slow to
+///route and travel directly
+const MAX_COMB_CORNERS: usize = 200;
+
+///Route the first travel of the layer from `from` through the inside of the layer and drop the
+///retraction before it. Layers without a way inside keep the direct travel and its retraction.
+fn comb_layer_approach(moves: &mut Vec<Command>, from: Coord<f32>, slice: &Slice) {
+    let Some(travel) = moves
+        .iter()
+        .position(|cmd| matches!(cmd, Command::MoveTo { .. }))
+    else {
+        return;
+    };
+    let Command::MoveTo { end: to } = moves[travel] else {
+        return;
+    };
+
+    let clearance = slice
+        .layer_settings
+        .extrusion_width
+        .exterior_surface_perimeter;
+    let Some(path) = comb_path(from, to, &slice.main_polygon, clearance) else {
+        return;
+    };
+
+    //The retraction of the layer start comes right before the travel
+    if let Some(Command::SetState { new_state }) = moves[..travel]
+        .iter_mut()
+        .rev()
+        .find(|cmd| matches!(cmd, Command::SetState { .. }))
+    {
+        new_state.retract = RetractionType::NoRetract;
+    }
+
+    moves.splice(
+        travel..travel,
+        path.into_iter().map(|end| Command::MoveTo { end }),
+    );
+}
+
+///Corners to travel over from `from` to `to` without leaving the area, shortest first. The corners
+///are those of the area shrunk by the clearance, so the travel keeps off the outer wall. None if
+///either end is outside the area or there is no way through.
+fn comb_path(
+    from: Coord<f32>,
+    to: Coord<f32>,
+    area: &MultiPolygon<f32>,
+    clearance: f32,
+) -> Option<Vec<Coord<f32>>> {
+    let inside = |a: Coord<f32>, b: Coord<f32>| {
+        let step = LineString(vec![a, b]);
+        area.0.iter().any(|polygon| {
+            let parts = clip_lines_to_polygon([step.clone()], polygon);
+            matches!(parts.as_slice(), [part] if *part == step)
+        })
+    };
+
+    if !area.contains(&from) || !area.contains(&to) {
+        return None;
+    }
+    if from == to || inside(from, to) {
+        return Some(vec![]);
+    }
+
+    let corners: Vec<Coord<f32>> = area
+        .offset_from(-clearance)
+        .0
+        .iter()
+        .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+        .flat_map(|ring| ring.0.iter().skip(1).copied())
+        .collect();
+    if corners.len() > MAX_COMB_CORNERS {
+        return None;
+    }
+
+    //Shortest path over the corners that see each other, checked only when they would shorten it
+    let points: Vec<Coord<f32>> = std::iter::once(from)
+        .chain(corners)
+        .chain(std::iter::once(to))
+        .collect();
+    let target = points.len() - 1;
+    let mut distances = vec![f32::INFINITY; points.len()];
+    let mut previous = vec![None; points.len()];
+    let mut visited = vec![false; points.len()];
+    distances[0] = 0.0;
+
+    while let Some(current) = (0..points.len())
+        .filter(|index| !visited[*index] && distances[*index].is_finite())
+        .min_by(|a, b| distances[*a].total_cmp(&distances[*b]))
+    {
+        if current == target {
+            break;
+        }
+        visited[current] = true;
+
+        for (next, point) in points.iter().enumerate() {
+            let distance = distances[current] + points[current].euclidean_distance(point);
+            if !visited[next] && distance < distances[next] && inside(points[current], *point) {
+                distances[next] = distance;
+                previous[next] = Some(current);
+            }
+        }
+    }
+
+    //Walk back from the target, leaving out the ends
+    let mut path = vec![];
+    let mut current = previous[target]?;
+    while current != 0 {
+        path.push(points[current]);
+        current = previous[current]?;
+    }
+    path.reverse();
+    Some(path)
+}
+
+fn move_end(cmd: &Command) -> Option<Coord<f32>> {
+    match cmd {
+        Command::MoveTo { end } => Some(*end),
+        cmd => extrusion_end(cmd),
+    }
+}
+
+fn extrusion_end(cmd: &Command) -> Option<Coord<f32>> {
+    match cmd {
+        Command::MoveAndExtrude { end, .. }
+        | Command::MoveAndExtrudeFiber { end, .. }
+        | Command::MoveAndExtrudeFiberAndCut { end, .. }
+        | Command::Arc { end, .. } => Some(*end),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_slice(settings: &Settings) -> Slice {
+        Slice::from_single_point_loop(
+            vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+            0.0,
+            0.2,
+            1,
+            settings,
+        )
+    }
+
+    fn longest_move(chains: &[MoveChain]) -> Coord<f32> {
+        chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point)
+                    .chain(chain.moves.iter().map(|m| m.end))
+                    .tuple_windows::<(_, _)>()
+                    .map(|(start, end)| end - start)
+            })
+            .max_by_key(|dir| OrderedFloat(dir.x * dir.x + dir.y * dir.y))
+            .expect("No moves generated")
+    }
+
+    #[test]
+    fn bridge_angle_override() {
+        let mut settings = Settings::default();
+        *settings.bridge_angle_override.enabled_mut() = true;
+
+        for angle in [0.0, 90.0] {
+            *settings.bridge_angle_override = angle;
+
+            let mut slice = square_slice(&settings);
+            slice.fill_solid_bridge_area(
+                &MultiPolygon(vec![]),
+                &settings,
+                &PassContext::new().without_fiber(),
+            );
+
+            let dir = longest_move(&slice.chains);
+            let (along, across) = if angle == 0.0 {
+                (dir.x, dir.y)
+            } else {
+                (dir.y, dir.x)
+            };
+
+            assert!(along.abs() > 10.0);
+            assert!(across.abs() < 0.001);
+        }
+    }
+
+    //A square infill region with a sliver of the width left between walls
+    fn square_with_sliver(width: f32) -> MultiPolygon<f32> {
+        let (low, high) = (5.0 - width / 2.0, 5.0 + width / 2.0);
+        MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, low),
+                (20.0, low),
+                (20.0, high),
+                (10.0, high),
+                (10.0, 10.0),
+                (0.0, 10.0),
+            ]),
+            vec![],
+        )])
+    }
+
+    #[test]
+    fn gap_fill_bead_in_thin_gap() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+        slice.remaining_area = square_with_sliver(0.6);
+
+        slice.fill_gaps(0.2, 1.0);
+
+        let bead = slice
+            .chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point)
+                    .chain(chain.moves.iter().map(|m| m.end))
+                    .zip(chain.moves.iter())
+            })
+            .find(|(start, m)| {
+                (start.y - 5.0).abs() < 0.2
+                    && (m.end.y - 5.0).abs() < 0.2
+                    && (m.end.x - start.x).abs() > 5.0
+            })
+            .map(|(_, m)| m)
+            .expect("No gap fill bead along the sliver");
+
+        assert!(bead.width > 0.4 && bead.width <= 0.6 + 0.001);
+
+        //The main region is left for infill
+        assert!(slice.remaining_area.unsigned_area() > 90.0);
+        assert!(slice
+            .remaining_area
+            .iter()
+            .all(|poly| poly.bounding_rect().unwrap().max().x < 11.0));
+    }
+
+    #[test]
+    fn gap_fill_skips_gaps_below_min_width() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+        slice.remaining_area = square_with_sliver(0.15);
+
+        slice.fill_gaps(0.2, 0.5);
+
+        //The sliver is too thin for a bead and stays with the rest of the area
+        assert!(slice.chains.is_empty());
+        assert!(slice.remaining_area.unsigned_area() > 101.4);
+
+        slice.fill_gaps(0.1, 0.5);
+        assert!(!slice.chains.is_empty());
+    }
+
+    #[test]
+    fn zero_gap_fill_width_is_rejected() {
+        //A gap width of 0 would trace the medial axis on an empty grid forever
+        let mut invalid = Settings::default();
+        *invalid.gap_fill.enabled_mut() = true;
+        *invalid.gap_fill = 0.0;
+        let error = match invalid.validate_settings() {
+            crate::SettingsValidationResult::Error(error) => error,
+            result => panic!("Expected an error, got {:?}", result),
+        };
+        assert_eq!(
+            error,
+            crate::SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "gap_fill".to_string(),
+                value: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn skip_infill_in_tiny_pocket() {
+        let square = |x: f32, y: f32, size: f32| {
+            Polygon::new(
+                LineString::from(vec![
+                    (x, y),
+                    (x + size, y),
+                    (x + size, y + size),
+                    (x, y + size),
+                ]),
+                vec![],
+            )
+        };
+
+        let in_pocket = |slice: &Slice| {
+            slice
+                .chains
+                .iter()
+                .flat_map(|chain| chain.moves.iter())
+                .filter(|m| m.end.x > 14.9 && m.end.y > 14.9)
+                .count()
+        };
+
+        let mut settings = Settings {
+            infill_percentage: 0.5,
+            ..Default::default()
+        };
+
+        for skip in [0.0, 5.0] {
+            settings.skip_infill_below_area = skip;
+
+            let mut slice = square_slice(&settings);
+            slice.remaining_area =
+                MultiPolygon(vec![square(0.0, 0.0, 10.0), square(15.0, 15.0, 2.0)]);
+            slice.fill_remaining_area(false, 1, &PassContext::new().without_fiber());
+
+            assert!(!slice.chains.is_empty());
+            if skip == 0.0 {
+                assert!(in_pocket(&slice) > 0);
+            } else {
+                assert_eq!(in_pocket(&slice), 0);
+            }
+        }
+
+        //Solid infill is not affected
+        let mut slice = square_slice(&settings);
+        slice.remaining_area = MultiPolygon(vec![square(15.0, 15.0, 2.0)]);
+        slice.fill_remaining_area(true, 1, &PassContext::new().without_fiber());
+        assert!(in_pocket(&slice) > 0);
+    }
+
+    #[test]
+    fn zero_solid_infill_interval_from_a_modifier_is_disabled() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+        *slice.layer_settings.solid_infill_every_layers.enabled_mut() = true;
+        *slice.layer_settings.solid_infill_every_layers = 0;
+
+        slice.fill_remaining_area(false, 1, &PassContext::new().without_fiber());
+
+        assert!(slice.chains.iter().all(|chain| chain
+            .moves
+            .iter()
+            .all(|m| m.move_type != MoveType::WithoutFiber(TraceType::SolidInfill))));
+    }
+
+    #[test]
+    fn zero_inner_wall_interval_from_a_modifier_prints_every_layer() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+        slice.layer_settings.inner_walls_every_layers = 0;
+
+        slice.slice_walls_into_chains(3, &[], 1);
+
+        assert!(slice.fixed_chains.iter().any(|chain| chain
+            .moves
+            .iter()
+            .any(|m| m.move_type.print_type() == Some(TraceType::WallInner))));
+    }
+
+    fn min_x(chains: &[MoveChain]) -> f32 {
+        chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point).chain(chain.moves.iter().map(|m| m.end))
+            })
+            .map(|point| point.x)
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn solid_infill_overlap_is_separate_from_sparse() {
+        let square = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+            vec![],
+        );
+        let fill_type = MoveType::WithoutFiber(TraceType::SolidInfill);
+
+        let mut settings = Settings::default();
+        let layer_settings = settings.get_layer_settings(1, 0.2);
+        let default_solid = min_x(&linear_fill_polygon(
+            &square,
+            &layer_settings,
+            fill_type,
+            0.0,
+        ));
+        let default_sparse = min_x(&partial_linear_fill_polygon(
+            &square,
+            &layer_settings,
+            fill_type,
+            1.0,
+            0.0,
+            0.0,
+        ));
+
+        settings.solid_infill_overlap_percentage = 0.75;
+        let layer_settings = settings.get_layer_settings(1, 0.2);
+        let solid = min_x(&linear_fill_polygon(
+            &square,
+            &layer_settings,
+            fill_type,
+            0.0,
+        ));
+        let sparse = min_x(&partial_linear_fill_polygon(
+            &square,
+            &layer_settings,
+            fill_type,
+            1.0,
+            0.0,
+            0.0,
+        ));
+
+        assert!(solid < default_solid);
+        assert!((sparse - default_sparse).abs() < 0.0001);
+    }
+
+    #[test]
+    fn fiber_transitions_only_lift() {
+        use crate::{
+            command_pass::CommandPass,
+            gcode::{mem::GCodeMemoryWriter, write_gcode},
+            EvalIdPass,
+        };
+
+        let chain = |x: f32, fiber: bool| MoveChain {
+            start_point: Coord { x, y: 0.0 },
+            moves: vec![Move {
+                end: Coord { x, y: 10.0 },
+                width: 0.4,
+                move_type: MoveType::from_type(TraceType::Infill, fiber),
+            }],
+            is_loop: false,
+        };
+
+        let settings = Settings::default();
+        assert!(settings.fiber.suppress_retraction);
+
+        let mut slice = square_slice(&settings);
+        slice.fixed_chains = vec![chain(0.0, false)];
+        slice.chains = vec![chain(5.0, false), chain(10.0, true), chain(15.0, false)];
+
+        let mut cmds = vec![Command::LayerChange { z: 0.2, index: 0 }];
+        slice.slice_into_commands(&mut cmds, 0.2);
+        EvalIdPass::pass(&mut cmds, &settings);
+
+        let mut writer = GCodeMemoryWriter::new();
+        let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+        let output = writer.finish(navigator).gcode;
+
+        let has_word = |line: &str, prefix: char| {
+            line.split([' ', ';']).any(|word| {
+                word.strip_prefix(prefix)
+                    .is_some_and(|n| n.parse::<f32>().is_ok())
+            })
+        };
+
+        //Collapse the gcode into the order filament is retracted and plastic or fiber is laid
+        let events: Vec<&str> = output
+            .lines()
+            .filter_map(|line| {
+                if line.contains("; Retract") {
+                    assert!(!has_word(line, 'D'), "fiber retracted: {}", line);
+                    Some("retract")
+                } else if line.contains("; Unretract") {
+                    Some("unretract")
+                } else if line.starts_with("G1 X") && has_word(line, 'D') {
+                    Some("fiber")
+                } else if line.starts_with("G1 X") && has_word(line, 'E') {
+                    Some("plastic")
+                } else {
+                    None
+                }
+            })
+            .dedup()
+            .collect();
+
+        assert_eq!(
+            events,
+            [
+                "retract",
+                "unretract",
+                "plastic",
+                "retract",
+                "unretract",
+                "plastic",
+                "fiber",
+                "plastic",
+                "retract",
+            ]
+        );
+    }
+
+    #[test]
+    fn extruders_follow_the_layer_temperature() {
+        use crate::{FilamentSettings, LayerRange, PartialLayerSettings};
+
+        let mut settings = Settings::default();
+        settings.filament.extruder_temp = 210.0;
+        settings.extruders = vec![
+            FilamentSettings::default(),
+            FilamentSettings {
+                extruder_temp: 240.0,
+                ..Default::default()
+            },
+        ];
+        //The first layer is printed 5 degrees hotter
+        settings.layer_settings = vec![(
+            LayerRange::SingleLayer(0),
+            PartialLayerSettings {
+                extruder_temp: Some(215.0),
+                ..Default::default()
+            },
+        )];
+
+        let square = |layer: usize| {
+            Slice::from_single_point_loop(
+                vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                layer as f32 * 0.2,
+                (layer + 1) as f32 * 0.2,
+                layer,
+                &settings,
+            )
+        };
+        let object = Object {
+            layers: vec![square(0), square(1)],
+            extruder: 1,
+        };
+
+        let temps: Vec<f32> = convert_object_into_moves(0, object, &settings)
+            .iter()
+            .flat_map(|(_, cmds)| cmds)
+            .filter_map(|cmd| match cmd {
+                Command::SetState { new_state } => new_state.extruder_temp,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(temps, vec![245.0, 240.0]);
+    }
+
+    #[test]
+    fn skirt_around_empty_hull_is_skipped() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+
+        let skirt = SkirtSettings {
+            layers: 1,
+            distance: 2.0,
+        };
+        slice.generate_skirt(&Polygon::new(LineString(vec![]), vec![]), &skirt, &settings);
+        assert!(slice.fixed_chains.is_empty());
+    }
+
+    #[test]
+    fn fiber_bridges_lifted_travel_between_chains() {
+        use crate::{command_pass::CommandPass, MergeFiberPass};
+
+        let chain = |from: f32, to: f32| MoveChain {
+            start_point: Coord { x: 0.0, y: from },
+            moves: vec![Move {
+                end: Coord { x: 0.0, y: to },
+                width: 0.4,
+                move_type: MoveType::WithFiber(TraceType::Infill),
+            }],
+            is_loop: false,
+        };
+
+        let mut settings = Settings::default();
+        settings.fiber.max_bridge_gap = 1.0;
+
+        let mut slice = square_slice(&settings);
+        slice.chains = vec![chain(0.0, 15.0), chain(15.5, 30.0)];
+
+        let mut cmds = vec![Command::LayerChange { z: 0.2, index: 0 }];
+        slice.slice_into_commands(&mut cmds, 0.2);
+        MergeFiberPass::pass(&mut cmds, &settings);
+
+        //Each run alone is shorter than the minimum fiber length, together they are one chain
+        let fiber_moves: Vec<usize> = cmds
+            .iter()
+            .enumerate()
+            .filter(|(_, cmd)| {
+                matches!(
+                    cmd,
+                    Command::MoveAndExtrudeFiber { .. } | Command::MoveAndExtrudeFiberAndCut { .. }
+                )
+            })
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(fiber_moves.len(), 2);
+        assert!(!cmds
+            .iter()
+            .any(|cmd| matches!(cmd, Command::MoveAndExtrude { .. })));
+        assert_eq!(
+            cmds.iter()
+                .filter(|cmd| matches!(cmd, Command::MoveAndExtrudeFiberAndCut { .. }))
+                .count(),
+            1
+        );
+
+        //The fiber stays attached across the travel, so nothing retracts or lifts in the gap
+        let gap = &cmds[fiber_moves[0]..fiber_moves[1]];
+        assert!(gap.iter().any(|cmd| matches!(cmd, Command::MoveTo { .. })));
+        assert!(gap.iter().all(|cmd| !matches!(
+            cmd,
+            Command::SetState { new_state } if new_state.retract != RetractionType::NoRetract
+        )));
+    }
+
+    #[test]
+    fn lift_descend_lifts_before_first_layer_move() {
+        use crate::{
+            command_pass::{CommandPass, OptimizePass},
+            gcode::{mem::GCodeMemoryWriter, write_gcode},
+            EvalIdPass,
+        };
+
+        let layer_change = |approach: ApproachMode| {
+            let mut settings = Settings::default();
+            settings.layer_change_approach = approach;
+
+            let mut cmds = vec![];
+            for (index, (z, x)) in [(0.2, 0.0), (0.4, 10.0)].into_iter().enumerate() {
+                let mut slice = square_slice(&settings);
+                slice.fixed_chains = vec![MoveChain {
+                    start_point: Coord { x, y: 0.0 },
+                    moves: vec![Move {
+                        end: Coord { x, y: 10.0 },
+                        width: 0.4,
+                        move_type: MoveType::WithoutFiber(TraceType::Infill),
+                    }],
+                    is_loop: false,
+                }];
+
+                cmds.push(Command::LayerChange { z, index });
+                slice.slice_into_commands(&mut cmds, 0.2);
+            }
+            OptimizePass::pass(&mut cmds, &settings);
+            EvalIdPass::pass(&mut cmds, &settings);
+
+            let mut writer = GCodeMemoryWriter::new();
+            let navigator = write_gcode(&cmds, &settings, &mut writer).unwrap();
+            let output = writer.finish(navigator).gcode;
+
+            //Everything between the first layer's last extrusion and the descent onto the second
+            let mut lines: Vec<String> = output
+                .lines()
+                .take_while(|line| !line.starts_with("G1 Z0.40000; z unlift"))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .take_while(|line| !(line.starts_with("G1 X") && line.contains(" E")))
+                .map(str::to_string)
+                .collect();
+            lines.reverse();
+            lines
+        };
+
+        let is_travel = |line: &String| line.starts_with("G1 X");
+        let is_layer_lift = |line: &String| line.starts_with("G1 Z1.00000");
+
+        let lines = layer_change(ApproachMode::LiftDescend);
+        let lift = lines
+            .iter()
+            .position(is_layer_lift)
+            .expect("no lift above the new layer");
+        let travel = lines
+            .iter()
+            .position(is_travel)
+            .expect("no travel to the new layer");
+        assert!(lift < travel, "{:?}", lines);
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.contains("; Retract"))
+                .count(),
+            1
+        );
+
+        //Direct keeps travelling at the lift of the previous layer
+        let lines = layer_change(ApproachMode::Direct);
+        assert!(lines.iter().any(is_travel));
+        assert!(!lines.iter().any(is_layer_lift), "{:?}", lines);
+    }
+
+    #[test]
+    fn comb_travels_inside_the_layer() {
+        let mut settings = Settings::default();
+        settings.layer_change_approach = ApproachMode::Comb;
+
+        //An L shape, the previous layer ends in one arm and the next one starts in the other
+        let outline = [
+            (0.0, 0.0),
+            (20.0, 0.0),
+            (20.0, 5.0),
+            (5.0, 5.0),
+            (5.0, 20.0),
+            (0.0, 20.0),
+        ];
+        let layer = |layer: usize, start: Coord<f32>, end: Coord<f32>| {
+            let mut slice = Slice::from_single_point_loop(
+                outline.into_iter(),
+                layer as f32 * 0.2,
+                (layer + 1) as f32 * 0.2,
+                layer,
+                &settings,
+            );
+            slice.fixed_chains = vec![MoveChain {
+                start_point: start,
+                moves: vec![Move {
+                    end,
+                    width: 0.4,
+                    move_type: MoveType::WithoutFiber(TraceType::Infill),
+                }],
+                is_loop: false,
+            }];
+            slice
+        };
+        let object = Object {
+            layers: vec![
+                layer(0, Coord { x: 10.0, y: 2.5 }, Coord { x: 18.0, y: 2.5 }),
+                layer(1, Coord { x: 2.5, y: 18.0 }, Coord { x: 2.5, y: 10.0 }),
+            ],
+            extruder: 0,
+        };
+
+        let moves = convert_object_into_moves(0, object, &settings);
+        let second = &moves[1].1;
+
+        //The travel turns at the inner corner instead of crossing outside of the L
+        let travels: Vec<Coord<f32>> = second
+            .iter()
+            .take_while(|cmd| !matches!(cmd, Command::MoveAndExtrude { .. }))
+            .filter_map(|cmd| match cmd {
+                Command::MoveTo { end } => Some(*end),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(travels.len(), 2, "{:?}", travels);
+        assert!(travels[0].x < 5.0 && travels[0].y < 5.0, "{:?}", travels);
+        assert_eq!(travels[1], Coord { x: 2.5, y: 18.0 });
+
+        let polygon = Polygon::new(LineString::from(outline.to_vec()), vec![]);
+        let path = [Coord { x: 18.0, y: 2.5 }, travels[0], travels[1]];
+        assert!(path
+            .windows(2)
+            .all(|step| polygon.contains(&Line::new(step[0], step[1]))));
+
+        //No retraction is needed for the way inside
+        assert!(second
+            .iter()
+            .take_while(|cmd| !matches!(cmd, Command::MoveAndExtrude { .. }))
+            .all(|cmd| !matches!(
+                cmd,
+                Command::SetState { new_state } if new_state.retract != RetractionType::NoRetract
+            )));
+    }
+
+    #[test]
+    fn aligned_solid_infill_runs_along_longest_edge() {
+        //A long thin plate tilted by 30 degrees
+        let plate = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (40.0, 0.0), (40.0, 5.0), (0.0, 5.0)]),
+            vec![],
+        )
+        .rotate_around_point(30.0, Point::new(0.0, 0.0));
+
+        let fill_directions = |align: bool| -> Vec<f32> {
+            let mut settings = Settings::default();
+            settings.align_solid_to_longest_edge = align;
+
+            let mut slice = Slice::from_single_point_loop(
+                plate.exterior().0.iter().map(|point| (point.x, point.y)),
+                0.0,
+                0.2,
+                1,
+                &settings,
+            );
+            slice.fill_solid_top_layer(&MultiPolygon(vec![]), 1, &PassContext::new());
+
+            //Direction of every line longer than the plate is wide, in degrees modulo 180
+            slice
+                .chains
+                .iter()
+                .flat_map(|chain| {
+                    std::iter::once(chain.start_point)
+                        .chain(chain.moves.iter().map(|m| m.end))
+                        .tuple_windows()
+                })
+                .filter(|(start, end)| start.euclidean_distance(end) > 10.0)
+                .map(|(start, end)| {
+                    (end.y - start.y)
+                        .atan2(end.x - start.x)
+                        .to_degrees()
+                        .rem_euclid(180.0)
+                })
+                .collect()
+        };
+
+        let aligned = fill_directions(true);
+        assert!(!aligned.is_empty());
+        assert!(
+            aligned.iter().all(|angle| (angle - 30.0).abs() < 0.5),
+            "{:?}",
+            aligned
+        );
+
+        //Disabled keeps the rotating angle, which crosses the plate
+        assert!(fill_directions(false)
+            .iter()
+            .all(|angle| (angle - 30.0).abs() > 1.0));
+    }
+
+    #[test]
+    fn coasting_stops_extruding_before_the_end() {
+        let mut settings = Settings::default();
+        *settings.coasting.enabled_mut() = true;
+        settings.coasting.volume = 0.08;
+        settings.coasting.min_volume_before_coast = 0.0;
+
+        //A 0.4 wide line on a 0.2 layer holds 0.08 mm³ per mm
+        let commands = |length: f32| {
+            let mut slice = square_slice(&settings);
+            slice.fixed_chains = vec![MoveChain {
+                start_point: Coord { x: 0.0, y: 0.0 },
+                moves: vec![Move {
+                    end: Coord { x: 0.0, y: length },
+                    width: 0.4,
+                    move_type: MoveType::WithoutFiber(TraceType::Infill),
+                }],
+                is_loop: false,
+            }];
+
+            let mut cmds = vec![];
+            slice.slice_into_commands(&mut cmds, 0.2);
+            cmds
+        };
+
+        let cmds = commands(10.0);
+        let extrusion_end = cmds.iter().find_map(|cmd| match cmd {
+            Command::MoveAndExtrude { end, .. } => Some(*end),
+            _ => None,
+        });
+        assert!((extrusion_end.unwrap().y - 9.0).abs() < 1e-4);
+        assert!(matches!(
+            cmds.iter().rev().nth(1),
+            Some(Command::MoveTo { end }) if *end == Coord { x: 0.0, y: 10.0 }
+        ));
+
+        //Lines holding less than the coasting volume are printed whole
+        let cmds = commands(0.5);
+        let extrusion_end = cmds.iter().find_map(|cmd| match cmd {
+            Command::MoveAndExtrude { end, .. } => Some(*end),
+            _ => None,
+        });
+        assert_eq!(extrusion_end, Some(Coord { x: 0.0, y: 0.5 }));
+        assert_eq!(
+            cmds.iter()
+                .filter(|cmd| matches!(cmd, Command::MoveTo { .. }))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn outer_wall_wipes_inward() {
+        let wipe_moves = |direction: WipeDirection| {
+            let mut settings = Settings::default();
+            *settings.retraction_wipe.enabled_mut() = true;
+            settings.retraction_wipe.direction = direction;
+
+            let mut slice = square_slice(&settings);
+            slice.fixed_chains = vec![MoveChain {
+                start_point: Coord { x: 0.0, y: 0.0 },
+                moves: [(20.0, 0.0), (20.0, 20.0), (0.0, 20.0), (0.0, 0.0)]
+                    .into_iter()
+                    .map(|(x, y)| Move {
+                        end: Coord { x, y },
+                        width: 0.4,
+                        move_type: MoveType::WithoutFiber(TraceType::WallOuter),
+                    })
+                    .collect(),
+                is_loop: true,
+            }];
+
+            let mut cmds = vec![];
+            slice.slice_into_commands(&mut cmds, 0.2);
+            cmds.iter()
+                .find_map(|cmd| match cmd {
+                    Command::SetState { new_state } => match &new_state.retract {
+                        RetractionType::MoveRetract(moves) => Some(moves.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        //Along the path the wipe retraces the wall on the surface
+        let along = wipe_moves(WipeDirection::AlongPath);
+        assert!(along
+            .iter()
+            .all(|(_, point)| point.y == 0.0 || point.x == 20.0));
+
+        //Inward it heads into the corner of the square by the wipe distance
+        let inward = wipe_moves(WipeDirection::Inward);
+        assert_eq!(inward.len(), 1);
+        let (retraction, end) = inward[0];
+        assert!((retraction - Settings::default().retract_length).abs() < 1e-5);
+        assert!(end.x > 0.0 && end.x < 20.0 && end.y > 0.0 && end.y < 20.0);
+        assert!((end.x - end.y).abs() < 1e-4);
+        assert!((end.x.hypot(end.y) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn infill_angle_offset_rotates_the_infill() {
+        let settings = Settings::default();
+
+        //Direction of the first long infill line, in degrees modulo 180
+        let direction = |offset: f32, solid: bool, ctx: &PassContext| -> f32 {
+            let mut slice = square_slice(&settings);
+            slice.layer_settings.partial_infill_type = crate::PartialInfillTypes::Linear;
+            slice.layer_settings.infill_angle_offset = offset;
+            slice.fill_remaining_area(solid, 3, ctx);
+
+            slice
+                .chains
+                .iter()
+                .flat_map(|chain| {
+                    std::iter::once(chain.start_point)
+                        .chain(chain.moves.iter().map(|m| m.end))
+                        .tuple_windows()
+                        .zip(chain.moves.iter())
+                })
+                .filter(|((start, end), m)| {
+                    m.move_type != MoveType::Travel && start.euclidean_distance(end) > 5.0
+                })
+                .map(|((start, end), _)| {
+                    (end.y - start.y)
+                        .atan2(end.x - start.x)
+                        .to_degrees()
+                        .rem_euclid(180.0)
+                })
+                .next()
+                .expect("Infill has long lines")
+        };
+
+        //Solid, sparse and fiber infill all turn by the offset on top of the layer's own angle
+        for (solid, ctx) in [
+            (true, PassContext::new()),
+            (false, PassContext::new()),
+            (false, PassContext::new().with_fiber()),
+        ] {
+            let base = direction(0.0, solid, &ctx);
+            let rotated = direction(30.0, solid, &ctx);
+            let difference = (base - rotated).rem_euclid(180.0);
+            assert!((difference - 30.0).abs() < 0.5, "{} {}", base, rotated);
+        }
+    }
+
+    #[test]
+    fn first_layer_infill_angle_only_turns_the_first_layer() {
+        //Direction of the first long infill line of a layer, in degrees modulo 180
+        let direction = |layer: usize, solid: bool, first_layer_angle: bool| -> f32 {
+            let mut settings = Settings::default();
+            settings.partial_infill_type = crate::PartialInfillTypes::Linear;
+            *settings.first_layer_infill_angle.enabled_mut() = first_layer_angle;
+            *settings.first_layer_infill_angle = 90.0;
+
+            let mut slice = Slice::from_single_point_loop(
+                vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                layer as f32 * 0.2,
+                (layer + 1) as f32 * 0.2,
+                layer,
+                &settings,
+            );
+            slice.fill_remaining_area(solid, layer, &PassContext::new());
+
+            slice
+                .chains
+                .iter()
+                .flat_map(|chain| {
+                    std::iter::once(chain.start_point)
+                        .chain(chain.moves.iter().map(|m| m.end))
+                        .tuple_windows()
+                        .zip(chain.moves.iter())
+                })
+                .filter(|((start, end), m)| {
+                    m.move_type != MoveType::Travel && start.euclidean_distance(end) > 5.0
+                })
+                .map(|((start, end), _)| {
+                    (end.y - start.y)
+                        .atan2(end.x - start.x)
+                        .to_degrees()
+                        .rem_euclid(180.0)
+                })
+                .next()
+                .expect("Infill has long lines")
+        };
+
+        for solid in [true, false] {
+            //Lines along y on the first layer, for solid and sparse infill alike
+            let first = direction(0, solid, true);
+            assert!((first - 90.0).abs() < 0.5, "{} {}", solid, first);
+            assert!((direction(0, solid, false) - 90.0).abs() > 1.0);
+
+            //The second layer keeps the angle of the layer progression
+            assert!((direction(1, solid, true) - direction(1, solid, false)).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn chain_polygon_follows_the_width_of_each_move() {
+        let extrusion = |x: f32, width: f32| Move {
+            end: Coord { x, y: 0.0 },
+            move_type: MoveType::WithoutFiber(TraceType::WallOuter),
+            width,
+        };
+        let chain = MoveChain {
+            start_point: Coord { x: 0.0, y: 0.0 },
+            moves: vec![extrusion(10.0, 0.4), extrusion(20.0, 0.8)],
+            is_loop: false,
+        };
+
+        let area = Polygon::from(&chain).unsigned_area();
+        assert!((area - (10.0 * 0.4 + 10.0 * 0.8)).abs() < 0.01, "{}", area);
+    }
+
+    #[test]
+    fn thin_walls_fill_a_tapering_wedge() {
+        let settings = Settings::default();
+        let mut slice = Slice::from_single_point_loop(
+            vec![(0.0, 0.0), (20.0, 1.0), (0.0, 2.0)].into_iter(),
+            0.0,
+            0.2,
+            0,
+            &settings,
+        );
+        let wedge = slice.remaining_area.clone();
+
+        slice.slice_thin_walls_into_chains();
+
+        //The tip is too thin for a wall on each side and is left to the thin walls
+        let thin_area = wedge.difference_with(&slice.remaining_area).unsigned_area();
+        assert!(thin_area > 1.0, "{}", thin_area);
+        assert!(!slice.fixed_chains.is_empty());
+
+        //The thin walls fill the tip up to the area left for the regular walls
+        let covered = slice
+            .fixed_chains
+            .iter()
+            .map(|chain| MultiPolygon(vec![Polygon::from(chain)]))
+            .fold(slice.remaining_area.clone(), |covered, wall| {
+                covered.union_with(&wall)
+            });
+        let uncovered = wedge.difference_with(&covered).unsigned_area();
+        assert!(
+            uncovered < 0.1 * thin_area,
+            "{} of {} left unfilled",
+            uncovered,
+            thin_area
+        );
+
+        //The wall along the wedge narrows towards the tip
+        let wall = slice
+            .fixed_chains
+            .iter()
+            .max_by_key(|chain| chain.moves.len())
+            .unwrap();
+        let widths = wall.moves.iter().map(|m| m.width);
+        let widest = widths.clone().fold(0.0, f32::max);
+        let narrowest = widths.fold(f32::INFINITY, f32::min);
+        assert!(widest > 2.0 * narrowest, "{} {}", widest, narrowest);
+    }
+
+    #[test]
+    fn thin_walls_leave_square_corners_alone() {
+        let settings = Settings::default();
+        let mut slice = square_slice(&settings);
+        let square = slice.remaining_area.clone();
+
+        slice.slice_thin_walls_into_chains();
+
+        //Nothing of a plain square is thin, the corners stay with the regular walls
+        assert!(slice.fixed_chains.is_empty());
+        let lost = square
+            .difference_with(&slice.remaining_area)
+            .unsigned_area();
+        assert!(lost < 0.001, "{}", lost);
+    }
+
+    #[test]
+    fn gyroid_infill_matches_the_requested_density() {
+        let settings = Settings::default();
+
+        let fill = |bottom: f32| -> Vec<MoveChain> {
+            let mut slice = Slice::from_single_point_loop(
+                vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                bottom,
+                bottom + 0.2,
+                1,
+                &settings,
+            );
+            slice.layer_settings.partial_infill_type = crate::PartialInfillTypes::Gyroid;
+            slice.layer_settings.infill_percentage = 0.2;
+            slice.fill_remaining_area(false, 1, &PassContext::new());
+            slice.chains
+        };
+
+        let chains = fill(0.0);
+        let extruded: f32 = chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point)
+                    .chain(chain.moves.iter().map(|m| m.end))
+                    .tuple_windows()
+                    .zip(chain.moves.iter())
+            })
+            .map(|((start, end), m)| start.euclidean_distance(&end) * m.width)
+            .sum();
+        let density = extruded / 400.0;
+        assert!((0.15..0.25).contains(&density), "{}", density);
+
+        //The contour shifts with the height so the layers build up the 3D surface
+        assert_ne!(fill(1.0)[0].start_point, chains[0].start_point);
+    }
+
+    #[test]
+    fn adaptive_cubic_cells_shrink_towards_the_boundary() {
+        let spacing = 3.0;
+        let square = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)]),
+            vec![],
+        );
+
+        let cells = infill::adaptive_cubic_cells(&square, spacing);
+        let size_at = |x: f32, y: f32| {
+            cells
+                .iter()
+                .enumerate()
+                .find(|(_, cells)| cells.iter().any(|cell| cell.contains(&Point::new(x, y))))
+                .map(|(level, _)| spacing * 2_f32.powi(level as i32))
+                .expect("Point not covered by a cell")
+        };
+
+        assert_eq!(size_at(1.0, 50.5), spacing);
+        assert_eq!(size_at(50.5, 98.5), spacing);
+        assert_eq!(size_at(50.5, 50.5), spacing * 8.0);
+
+        //The cells lie on a grid fixed to the bed, so the cells of each layer line up
+        assert!(cells.iter().flatten().all(|cell| {
+            let min = cell.bounding_rect().unwrap().min();
+            [min.x, min.y]
+                .iter()
+                .all(|v| ((v / spacing).round() * spacing - v).abs() < 0.001)
+        }));
+
+        //The infill stays inside the region it fills
+        let settings = Settings::default();
+        let mut slice = Slice::from_single_point_loop(
+            vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)].into_iter(),
+            0.0,
+            0.2,
+            1,
+            &settings,
+        );
+        slice.layer_settings.partial_infill_type = crate::PartialInfillTypes::AdaptiveCubic;
+        slice.layer_settings.infill_percentage = 0.2;
+        slice.fill_remaining_area(false, 1, &PassContext::new());
+
+        assert!(!slice.chains.is_empty());
+        assert!(slice
+            .chains
+            .iter()
+            .flat_map(
+                |chain| std::iter::once(chain.start_point).chain(chain.moves.iter().map(|m| m.end))
+            )
+            .all(|point| (-0.5..=100.5).contains(&point.x) && (-0.5..=100.5).contains(&point.y)));
+    }
+
+    #[test]
+    fn bridges_print_with_their_own_flow_and_fan() {
+        let mut settings = Settings::default();
+        settings.fan.fan_speed = 40.0;
+        *settings.bridge.enabled_mut() = true;
+        settings.bridge.flow_ratio = 0.5;
+        settings.bridge.fan_speed = 100.0;
+
+        let bridge_type = MoveType::WithoutFiber(TraceType::Bridging);
+        let ctx = PassContext::new().without_fiber();
+
+        //The middle of the square spans the gap between two strips on the layer below
+        let strip = |min_x: f32, max_x: f32| {
+            Polygon::new(
+                LineString::from(vec![
+                    (min_x, 0.0),
+                    (max_x, 0.0),
+                    (max_x, 20.0),
+                    (min_x, 20.0),
+                ]),
+                vec![],
+            )
+        };
+        let layer_below = MultiPolygon(vec![strip(0.0, 6.0), strip(14.0, 20.0)]);
+
+        let mut slice = square_slice(&settings);
+        slice.fill_solid_bridge_area(&layer_below, &settings, &ctx);
+        slice.fill_remaining_area(true, 1, &ctx);
+
+        let (bridges, others): (Vec<_>, Vec<_>) = slice
+            .chains
+            .drain(..)
+            .partition(|chain| chain.moves.iter().any(|m| m.move_type == bridge_type));
+        assert!(!bridges.is_empty());
+        assert!(others.len() >= 2);
+
+        let bridge_width = slice.layer_settings.extrusion_width.bridge;
+        assert!(bridges
+            .iter()
+            .flat_map(|chain| chain.moves.iter())
+            .filter(|m| m.move_type == bridge_type)
+            .all(|m| (m.width - bridge_width * 0.5).abs() < 1e-4));
+
+        //Regular infill on both sides of the bridge region of the layer
+        let mut others = others.into_iter();
+        slice.fixed_chains = others
+            .next()
+            .into_iter()
+            .chain(bridges)
+            .chain(others)
+            .collect();
+
+        let mut cmds = vec![];
+        slice.slice_into_commands(&mut cmds, 0.2);
+
+        let mut fan = None;
+        let mut print_type = None;
+        let mut extrusion_fans = vec![];
+        for cmd in &cmds {
+            match cmd {
+                Command::SetState { new_state } => fan = new_state.fan_speed.or(fan),
+                Command::ChangeType {
+                    print_type: new_type,
+                } => print_type = Some(*new_type),
+                Command::MoveAndExtrude { .. } => {
+                    let expected = if print_type == Some(TraceType::Bridging) {
+                        100.0
+                    } else {
+                        40.0
+                    };
+                    assert_eq!(fan, Some(expected), "{:?}", print_type);
+                    extrusion_fans.push(expected);
+                }
+                _ => {}
+            }
+        }
+
+        extrusion_fans.dedup();
+        assert_eq!(extrusion_fans, vec![40.0, 100.0, 40.0]);
+    }
+
+    fn square_with_hole() -> Polygon<f32> {
+        Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]),
+            vec![LineString::from(vec![
+                (4.0, 4.0),
+                (6.0, 4.0),
+                (6.0, 6.0),
+                (4.0, 6.0),
+            ])],
+        )
+    }
+
+    //First and last point of every part of the clipped line
+    fn clipped_ends(line: Vec<(f32, f32)>) -> Vec<((f32, f32), (f32, f32))> {
+        clip_lines_to_polygon(vec![LineString::from(line)], &square_with_hole())
+            .into_iter()
+            .map(|part| {
+                let round = |c: &Coord<f32>| {
+                    (
+                        (c.x * 1000.0).round() / 1000.0,
+                        (c.y * 1000.0).round() / 1000.0,
+                    )
+                };
+                (round(&part.0[0]), round(part.0.last().unwrap()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clipped_lines_skip_the_hole() {
+        assert_eq!(
+            clipped_ends(vec![(-5.0, 5.0), (15.0, 5.0)]),
+            vec![((0.0, 5.0), (4.0, 5.0)), ((6.0, 5.0), (10.0, 5.0))]
+        );
+    }
+
+    #[test]
+    fn clipped_lines_through_vertices() {
+        //Through two corners of the outline and two of the hole
+        assert_eq!(
+            clipped_ends(vec![(-5.0, -5.0), (15.0, 15.0)]),
+            vec![((0.0, 0.0), (4.0, 4.0)), ((6.0, 6.0), (10.0, 10.0))]
+        );
+    }
+
+    #[test]
+    fn clipped_lines_tangent_to_the_hole_stay_whole() {
+        //Touching a corner of the hole
+        assert_eq!(
+            clipped_ends(vec![(1.0, -1.0), (11.0, 9.0)]),
+            vec![((2.0, 0.0), (10.0, 8.0))]
+        );
+
+        //Running along an edge of the hole
+        assert_eq!(
+            clipped_ends(vec![(-1.0, 4.0), (11.0, 4.0)]),
+            vec![((0.0, 4.0), (10.0, 4.0))]
+        );
+    }
+
+    #[test]
+    fn clipped_lines_inside_the_hole_are_left_out() {
+        assert!(clipped_ends(vec![(4.5, 5.0), (5.5, 5.0)]).is_empty());
+
+        //Even with both ends on the edges of the hole
+        assert!(clipped_ends(vec![(4.0, 5.0), (6.0, 5.0)]).is_empty());
+    }
+
+    #[test]
+    fn clipped_closed_lines_join_across_their_start() {
+        let around_hole = vec![(2.0, 2.0), (8.0, 2.0), (8.0, 8.0), (2.0, 8.0), (2.0, 2.0)];
+        let clipped = clip_lines_to_polygon(
+            vec![LineString::from(around_hole.clone())],
+            &square_with_hole(),
+        );
+        assert_eq!(clipped, vec![LineString::from(around_hole)]);
+
+        //Leaving through the right edge and coming back
+        assert_eq!(
+            clipped_ends(vec![
+                (1.0, 1.0),
+                (12.0, 1.0),
+                (12.0, 3.0),
+                (1.0, 3.0),
+                (1.0, 1.0)
+            ]),
+            vec![((10.0, 3.0), (10.0, 1.0))]
+        );
+    }
+
+    #[test]
+    fn linear_fill_goes_around_the_hole() {
+        let settings = Settings::default().get_layer_settings(1, 0.2);
+        let fill_type = MoveType::WithoutFiber(TraceType::Infill);
+        let chains = spaced_fill_polygon(&square_with_hole(), &settings, fill_type, 1.0, 0.0);
+
+        let segments: Vec<(Coord<f32>, Coord<f32>)> = chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point)
+                    .chain(chain.moves.iter().map(|m| m.end))
+                    .tuple_windows()
+            })
+            .collect();
+
+        //Nothing is laid in the hole, the lines beside it end on its edges
+        let hole = Rect::new(Coord { x: 4.0, y: 4.0 }, Coord { x: 6.0, y: 6.0 }).to_polygon();
+        assert!(segments
+            .iter()
+            .all(|(start, end)| !hole.contains(&Point((*start + *end) / 2.0))));
+        let beside_hole: Vec<f32> = segments
+            .iter()
+            .filter(|(start, end)| start.y == 5.0 && end.y == 5.0)
+            .flat_map(|(start, end)| [start.x, end.x])
+            .map(|x| (x * 1000.0).round() / 1000.0)
+            .sorted_by(|a, b| a.total_cmp(b))
+            .collect();
+        assert_eq!(beside_hole, vec![0.0, 4.0, 6.0, 10.0]);
+
+        //The lines on top and bottom of the hole aren't split by its edges
+        let across = |y: f32| {
+            segments
+                .iter()
+                .any(|(start, end)| start.y == y && end.y == y && (start.x - end.x).abs() > 9.999)
+        };
+        assert!(across(4.0) && across(6.0));
+    }
+
+    #[test]
+    fn skeleton_fill_of_a_round_blob_is_a_short_bead() {
+        let settings = Settings::default().get_layer_settings(1, 0.2);
+        let fill_type = MoveType::WithoutFiber(TraceType::Infill);
+        let blob = Polygon::new(
+            LineString::from(
+                (0..32)
+                    .map(|i| {
+                        let angle = i as f32 * std::f32::consts::TAU / 32.0;
+                        (angle.cos(), angle.sin())
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            vec![],
+        );
+
+        let chains = skeleton_fill_polygon(&blob, &settings, fill_type, true);
+
+        assert_eq!(chains.len(), 1);
+        let bead = &chains[0];
+        assert_eq!(bead.moves.len(), 1);
+        assert!(bead.start_point.euclidean_distance(&bead.moves[0].end) > 0.1);
+        assert!(blob.contains(&Point((bead.start_point + bead.moves[0].end) / 2.0)));
+    }
+
+    #[test]
+    fn linear_fill_joins_only_neighbouring_lines() {
+        let settings = Settings::default().get_layer_settings(1, 0.2);
+        let fill_type = MoveType::WithoutFiber(TraceType::Infill);
+
+        for offset in [0.0, 0.25, 0.5] {
+            let chains =
+                spaced_fill_polygon(&square_with_hole(), &settings, fill_type, 1.0, offset);
+
+            //Steps from one line to the next beside the hole are left to a travel
+            let joins: Vec<f32> = chains
+                .iter()
+                .flat_map(|chain| {
+                    std::iter::once(chain.start_point)
+                        .chain(chain.moves.iter().map(|m| m.end))
+                        .tuple_windows()
+                })
+                .filter(|(start, end): &(Coord<f32>, Coord<f32>)| start.y != end.y)
+                .map(|(start, end)| start.euclidean_distance(&end))
+                .collect();
+            assert!(!joins.is_empty());
+            assert!(joins.iter().all(|join| *join <= 2.0), "{:?}", joins);
+        }
+    }
+
+    #[test]
+    fn orientation_map_from_an_image() {
+        use crate::settings::fiber::{FiberSettings, OrientationField};
+
+        //The top row of the image is the row furthest along y
+        let field = OrientationField::from_angle_image(2, &[255, 255, 0, 0], 2.0);
+        assert_eq!(
+            field.angles(),
+            vec![vec![vec![0.0, 0.0], vec![180.0, 180.0]]]
+        );
+        assert_eq!((field.spacing_x, field.spacing_y), (2.0, 2.0));
+
+        let mut value = serde_json::to_value(FiberSettings::default()).unwrap();
+        value["orientation_map"] = serde_json::to_value(&field).unwrap();
+        let fiber: FiberSettings = serde_json::from_value(value).unwrap();
+        assert_eq!(fiber.orientation_field.as_deref(), Some(&field));
+
+        //A short row is rejected before slicing
+        let ragged = OrientationField::new(
+            [0.0; 3],
+            [1.0; 3],
+            vec![vec![vec![0.0, 0.0]], vec![vec![0.0]]],
+        );
+        let mut settings = Settings::default();
+        settings.fiber.orientation_field = Some(std::sync::Arc::new(ragged));
+        let error = match settings.validate_settings() {
+            crate::SettingsValidationResult::Error(error) => error,
+            result => panic!("Expected an error, got {:?}", result),
+        };
+        assert_eq!(
+            error,
+            crate::SlicerErrors::RaggedOrientationField { layer: 1 }
+        );
+    }
+
+    #[test]
+    fn empty_orientation_field_falls_back_to_the_fixed_angle() {
+        use crate::settings::fiber::OrientationField;
+
+        let mut settings = Settings::default();
+        settings.fiber.orientation_field = Some(std::sync::Arc::new(OrientationField::new(
+            [0.0; 3],
+            [10.0; 3],
+            vec![],
+        )));
+
+        let mut slice = square_slice(&settings);
+        slice.fill_remaining_area_partially(1, 0.5, &PassContext::new().with_fiber());
+
+        assert!(slice.chains.iter().any(|chain| chain
+            .moves
+            .iter()
+            .any(|m| m.move_type == MoveType::WithFiber(TraceType::Infill))));
+    }
+
+    #[test]
+    fn fiber_follows_a_rotating_orientation_field() {
+        use crate::settings::fiber::OrientationField;
+
+        let field = |angles: Vec<f32>| {
+            OrientationField::new([0.0, 0.0, 0.0], [10.0; 3], vec![vec![angles]])
+        };
+
+        //Fibers have no head or tail, halfway between 170° and 10° runs along x
+        let across = field(vec![170.0, 10.0])
+            .direction_at(5.0, 0.0, 0.0)
+            .expect("Field has angles");
+        assert!(across.y.abs() < 0.001);
+
+        let field = field(vec![0.0, 20.0, 40.0, 60.0]);
+        let square = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (30.0, 0.0), (30.0, 30.0), (0.0, 30.0)]),
+            vec![],
+        );
+        let settings = Settings::default();
+        let layer_settings = settings.get_layer_settings(1, 0.2);
+        let chains = field_fill_polygon(
+            &square,
+            &layer_settings,
+            MoveType::WithFiber(TraceType::Infill),
+            2.0,
+            &field,
+            0.0,
+        );
+        assert!(!chains.is_empty());
+
+        //Angle between two directions regardless of which way along them
+        let axial_angle = |a: Coord<f32>, b: Coord<f32>| {
+            let angle = (a.y.atan2(a.x) - b.y.atan2(b.x))
+                .to_degrees()
+                .rem_euclid(180.0);
+            angle.min(180.0 - angle)
+        };
+
+        let mut bends = false;
+        for chain in &chains {
+            let segments: Vec<(Coord<f32>, Coord<f32>)> = std::iter::once(chain.start_point)
+                .chain(chain.moves.iter().map(|m| m.end))
+                .tuple_windows::<(_, _)>()
+                .collect();
+
+            //The clipped ends no longer start on a traced point
+            for (start, end) in &segments[1..segments.len().saturating_sub(1)] {
+                let direction = |point: Coord<f32>| {
+                    let direction = field
+                        .direction_at(point.x, point.y, 0.0)
+                        .expect("Field has angles");
+                    Coord {
+                        x: direction.x,
+                        y: direction.y,
+                    }
+                };
+                let along = *end - *start;
+                assert!(
+                    axial_angle(along, direction(*start)) < 1.0
+                        || axial_angle(along, direction(*end)) < 1.0
+                );
+            }
+
+            for ((start, end), (next_start, next_end)) in segments.iter().tuple_windows() {
+                assert!(axial_angle(*end - *start, *next_end - *next_start) <= 45.0);
+            }
+
+            if let (Some((first_start, first_end)), Some((last_start, last_end))) =
+                (segments.first(), segments.last())
+            {
+                bends |= axial_angle(*first_end - *first_start, *last_end - *last_start) > 20.0;
+            }
+        }
+        assert!(bends);
+    }
+}
@@ -5,6 +5,9 @@ use geo::*;
 pub trait PolygonOperations {
     fn offset_from(&self, delta: f32) -> MultiPolygon<f32>;
 
+    ///Offset that keeps the corners sharp instead of squaring them off
+    fn offset_miter_from(&self, delta: f32) -> MultiPolygon<f32>;
+
     fn difference_with(&self, other: &MultiPolygon<f32>) -> MultiPolygon<f32>;
 
     fn intersection_with(&self, other: &MultiPolygon<f32>) -> MultiPolygon<f32>;
@@ -26,6 +29,16 @@ impl PolygonOperations for MultiPolygon<f32> {
         )
     }
 
+    fn offset_miter_from(&self, delta: f32) -> MultiPolygon<f32> {
+        geo_clipper::Clipper::offset(
+            self,
+            delta,
+            geo_clipper::JoinType::Miter(2.0),
+            geo_clipper::EndType::ClosedPolygon,
+            1000000.0,
+        )
+    }
+
     fn difference_with(&self, other: &MultiPolygon<f32>) -> MultiPolygon<f32> {
         geo_clipper::Clipper::difference(self, other, 1000000.0)
     }
@@ -54,6 +67,16 @@ impl PolygonOperations for Polygon<f32> {
         )
     }
 
+    fn offset_miter_from(&self, delta: f32) -> MultiPolygon<f32> {
+        geo_clipper::Clipper::offset(
+            self,
+            delta,
+            geo_clipper::JoinType::Miter(2.0),
+            geo_clipper::EndType::ClosedPolygon,
+            1000000.0,
+        )
+    }
+
     fn difference_with(&self, other: &MultiPolygon<f32>) -> MultiPolygon<f32> {
         geo_clipper::Clipper::difference(self, other, 1000000.0)
     }
@@ -0,0 +1,443 @@
+use std::collections::{HashMap, HashSet};
+
+use geo::prelude::*;
+use geo::{Closest, Coord, Line, LineString, Point, Polygon, Rect};
+use ordered_float::OrderedFloat;
+
+type Cell = (i32, i32);
+
+//Orthogonal neighbours first so traced lines prefer straight steps over diagonal ones
+const NEIGHBOURS: [Cell; 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (-1, 1),
+    (1, -1),
+    (-1, -1),
+];
+
+///Cosine of the minimum angle between the nearest boundary directions of two neighbouring
+///cells for a ridge to run between them. Filters the shallow spokes of tessellated curves.
+const RIDGE_COS_THRESHOLD: f32 = 0.5;
+
+struct SampledCell {
+    center: Coord<f32>,
+    distance: f32,
+    direction: Coord<f32>,
+}
+
+///Approximate the medial axis of a polygon including its holes.
+///
+///The polygon is sampled on a grid of size `resolution` and a cell is part of the skeleton when
+///its nearest boundary point points in a substantially different direction than a neighbour's.
+///A ridge without ends, like the one running around a hole, is returned as a closed line.
+///Cells closer than `min_distance` to the boundary are pruned. If the skeleton collapses to a
+///small blob, such as for a near circular polygon, a single line containing only the central
+///point is returned.
+pub fn medial_axis(
+    poly: &Polygon<f32>,
+    resolution: f32,
+    min_distance: f32,
+) -> Vec<LineString<f32>> {
+    let Some(bounds) = poly.bounding_rect() else {
+        return vec![];
+    };
+
+    let cells_x = (bounds.width() / resolution).ceil() as i32;
+    let cells_y = (bounds.height() / resolution).ceil() as i32;
+
+    let segments: Vec<Line<f32>> = std::iter::once(poly.exterior())
+        .chain(poly.interiors())
+        .flat_map(|ring| ring.lines())
+        .collect();
+    let index = BoundaryIndex::new(&segments, bounds, resolution);
+
+    //The boundary crossings of each row of cells, a cell is inside after an odd number of them
+    let mut crossings: Vec<Vec<f32>> = vec![vec![]; cells_y.max(0) as usize];
+    for segment in &segments {
+        let row_of = |y: f32| ((y - bounds.min().y) / resolution - 0.5).ceil().max(0.0) as usize;
+        let (low, high) = (
+            segment.start.y.min(segment.end.y),
+            segment.start.y.max(segment.end.y),
+        );
+
+        for (row, row_crossings) in crossings
+            .iter_mut()
+            .enumerate()
+            .take(row_of(high) + 1)
+            .skip(row_of(low))
+        {
+            let y = bounds.min().y + (row as f32 + 0.5) * resolution;
+            if (segment.start.y > y) != (segment.end.y > y) {
+                let t = (y - segment.start.y) / (segment.end.y - segment.start.y);
+                row_crossings.push(segment.start.x + (segment.end.x - segment.start.x) * t);
+            }
+        }
+    }
+    for row_crossings in crossings.iter_mut() {
+        row_crossings.sort_by(|a, b| a.partial_cmp(b).expect("Crossings should not be NAN"));
+    }
+
+    let samples: HashMap<Cell, SampledCell> = (0..cells_x)
+        .flat_map(|x| (0..cells_y).map(move |y| (x, y)))
+        .filter_map(|(x, y)| {
+            let center = Coord {
+                x: bounds.min().x + (x as f32 + 0.5) * resolution,
+                y: bounds.min().y + (y as f32 + 0.5) * resolution,
+            };
+
+            if crossings[y as usize].partition_point(|crossing| *crossing < center.x) % 2 == 0 {
+                return None;
+            }
+
+            let nearest = index.nearest(center)?;
+            let offset = nearest - center;
+            let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+
+            (distance > f32::EPSILON).then(|| {
+                (
+                    (x, y),
+                    SampledCell {
+                        center,
+                        distance,
+                        direction: offset / distance,
+                    },
+                )
+            })
+        })
+        .collect();
+
+    let tie_tolerance = resolution * 0.001;
+
+    let ridge: HashSet<Cell> = samples
+        .iter()
+        .filter(|(_, sample)| sample.distance >= min_distance)
+        .filter(|((x, y), sample)| {
+            NEIGHBOURS.iter().any(|(dx, dy)| {
+                let neighbour_cell = (x + dx, y + dy);
+                let Some(neighbour) = samples.get(&neighbour_cell) else {
+                    return false;
+                };
+
+                let cos = sample.direction.x * neighbour.direction.x
+                    + sample.direction.y * neighbour.direction.y;
+
+                //Only keep the deeper cell of the pair so the ridge stays a single cell wide
+                let deeper = if (sample.distance - neighbour.distance).abs() <= tie_tolerance {
+                    (neighbour_cell.1, neighbour_cell.0) > (*y, *x)
+                } else {
+                    sample.distance > neighbour.distance
+                };
+
+                cos < RIDGE_COS_THRESHOLD && deeper
+            })
+        })
+        .map(|(cell, _)| *cell)
+        .collect();
+
+    if ridge.is_empty() {
+        return vec![];
+    }
+
+    let (min_x, max_x) = ridge
+        .iter()
+        .map(|cell| cell.0)
+        .fold((i32::MAX, i32::MIN), |(min, max), x| {
+            (min.min(x), max.max(x))
+        });
+    let (min_y, max_y) = ridge
+        .iter()
+        .map(|cell| cell.1)
+        .fold((i32::MAX, i32::MIN), |(min, max), y| {
+            (min.min(y), max.max(y))
+        });
+
+    if max_x - min_x <= 2 && max_y - min_y <= 2 {
+        let center = ridge
+            .iter()
+            .map(|cell| &samples[cell])
+            .max_by_key(|sample| OrderedFloat(sample.distance))
+            .expect("Ridge is not empty")
+            .center;
+
+        return vec![LineString::new(vec![center])];
+    }
+
+    trace_ridge(&ridge)
+        .into_iter()
+        .map(|cells| {
+            LineString::new(
+                cells
+                    .iter()
+                    .map(|cell| samples[cell].center)
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .filter(|line| line.euclidean_length() >= 2.0 * resolution)
+        .map(|line| line.simplify(&(resolution / 2.0)))
+        .collect()
+}
+
+///Distance from the point to the closest exterior or interior ring of the polygon
+pub fn boundary_distance(poly: &Polygon<f32>, point: &Point<f32>) -> f32 {
+    nearest_boundary_point(poly, point)
+        .map(|nearest| Point(nearest).euclidean_distance(point))
+        .unwrap_or(0.0)
+}
+
+fn nearest_boundary_point(poly: &Polygon<f32>, point: &Point<f32>) -> Option<Coord<f32>> {
+    std::iter::once(poly.exterior())
+        .chain(poly.interiors())
+        .filter_map(|ring| match ring.closest_point(point) {
+            Closest::Intersection(p) | Closest::SinglePoint(p) => Some(p),
+            Closest::Indeterminate => None,
+        })
+        .min_by_key(|p| OrderedFloat(p.euclidean_distance(point)))
+        .map(|p| p.0)
+}
+
+///Boundary segments of a polygon sorted into square buckets, so finding the nearest boundary point
+///only looks at the buckets around a point instead of every segment
+pub(super) struct BoundaryIndex<'a> {
+    origin: Coord<f32>,
+    size: f32,
+    extent: i32,
+    buckets: HashMap<Cell, Vec<&'a Line<f32>>>,
+}
+
+impl<'a> BoundaryIndex<'a> {
+    pub(super) fn new(segments: &'a [Line<f32>], bounds: Rect<f32>, resolution: f32) -> Self {
+        //Around one segment per bucket, but no smaller than the sampling grid
+        let span = bounds.width().max(bounds.height());
+        let size = (span / (segments.len() as f32).sqrt().max(1.0)).max(resolution);
+
+        let mut index = BoundaryIndex {
+            origin: bounds.min(),
+            size,
+            extent: (span / size).ceil() as i32 + 1,
+            buckets: HashMap::new(),
+        };
+
+        for segment in segments {
+            let (min_x, min_y) = index.bucket(Coord {
+                x: segment.start.x.min(segment.end.x),
+                y: segment.start.y.min(segment.end.y),
+            });
+            let (max_x, max_y) = index.bucket(Coord {
+                x: segment.start.x.max(segment.end.x),
+                y: segment.start.y.max(segment.end.y),
+            });
+
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    index.buckets.entry((x, y)).or_default().push(segment);
+                }
+            }
+        }
+
+        index
+    }
+
+    fn bucket(&self, point: Coord<f32>) -> Cell {
+        (
+            ((point.x - self.origin.x) / self.size).floor() as i32,
+            ((point.y - self.origin.y) / self.size).floor() as i32,
+        )
+    }
+
+    ///The closest point of any segment. The buckets are searched in growing squares around the
+    ///point until the next square can't hold anything closer.
+    pub(super) fn nearest(&self, point: Coord<f32>) -> Option<Coord<f32>> {
+        let (center_x, center_y) = self.bucket(point);
+        let mut best: Option<(f32, Coord<f32>)> = None;
+
+        for ring in 0..=self.extent {
+            let square = (-ring..=ring).flat_map(|dx| {
+                (-ring..=ring)
+                    .filter(move |dy| dx.abs() == ring || dy.abs() == ring)
+                    .map(move |dy| (center_x + dx, center_y + dy))
+            });
+
+            for segment in square.filter_map(|cell| self.buckets.get(&cell)).flatten() {
+                let closest = closest_on_segment(segment, point);
+                let distance = Point(closest).euclidean_distance(&Point(point));
+
+                if !matches!(best, Some((best_distance, _)) if best_distance <= distance) {
+                    best = Some((distance, closest));
+                }
+            }
+
+            if best.is_some_and(|(distance, _)| distance <= ring as f32 * self.size) {
+                break;
+            }
+        }
+
+        best.map(|(_, closest)| closest)
+    }
+}
+
+fn closest_on_segment(segment: &Line<f32>, point: Coord<f32>) -> Coord<f32> {
+    let delta = segment.delta();
+    let length_squared = delta.x * delta.x + delta.y * delta.y;
+    if length_squared <= f32::EPSILON {
+        return segment.start;
+    }
+
+    let offset = point - segment.start;
+    let t = ((offset.x * delta.x + offset.y * delta.y) / length_squared).clamp(0.0, 1.0);
+    segment.start + delta * t
+}
+
+fn trace_ridge(ridge: &HashSet<Cell>) -> Vec<Vec<Cell>> {
+    let mut remaining = ridge.clone();
+    let mut lines = vec![];
+
+    let neighbours = |cell: Cell| {
+        NEIGHBOURS
+            .iter()
+            .map(move |(dx, dy)| (cell.0 + dx, cell.1 + dy))
+    };
+
+    while !remaining.is_empty() {
+        //Start from the tip of a branch where possible, sorted to keep the output deterministic
+        let start = remaining
+            .iter()
+            .copied()
+            .filter(|cell| neighbours(*cell).filter(|n| remaining.contains(n)).count() <= 1)
+            .min()
+            .or_else(|| remaining.iter().copied().min())
+            .expect("Remaining is not empty");
+
+        remaining.remove(&start);
+
+        //Join the branch onto an already traced junction if it touches one
+        let mut cells: Vec<Cell> = neighbours(start)
+            .find(|n| ridge.contains(n) && !remaining.contains(n))
+            .into_iter()
+            .collect();
+        cells.push(start);
+
+        let mut current = start;
+        while let Some(next) = neighbours(current).find(|n| remaining.contains(n)) {
+            remaining.remove(&next);
+            cells.push(next);
+            current = next;
+        }
+
+        if let Some(junction) =
+            neighbours(current).find(|n| ridge.contains(n) && !cells.contains(n))
+        {
+            cells.push(junction);
+        } else if let Some(closing) =
+            neighbours(current).find(|n| cells[..cells.len().saturating_sub(3)].contains(n))
+        {
+            //The line ran around a loop back to where it entered it
+            cells.push(closing);
+        }
+
+        lines.push(cells);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangle_has_central_spine() {
+        let rectangle = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 10.0), (0.0, 10.0)]),
+            vec![],
+        );
+
+        let skeleton = medial_axis(&rectangle, 0.5, 0.5);
+
+        let has_spine = skeleton.iter().any(|line| {
+            line.lines().any(|segment| {
+                (segment.start.y - 5.0).abs() <= 0.5
+                    && (segment.end.y - 5.0).abs() <= 0.5
+                    && (segment.end.x - segment.start.x).abs() >= 8.0
+            })
+        });
+
+        assert!(has_spine, "No central spine in {:?}", skeleton);
+    }
+
+    #[test]
+    fn circle_collapses_to_center_point() {
+        let circle = Polygon::new(
+            LineString::from(
+                (0..64)
+                    .map(|i| {
+                        let angle = i as f32 * std::f32::consts::TAU / 64.0;
+                        (10.0 * angle.cos(), 10.0 * angle.sin())
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            vec![],
+        );
+
+        let skeleton = medial_axis(&circle, 0.5, 0.5);
+
+        assert_eq!(skeleton.len(), 1);
+        assert_eq!(skeleton[0].0.len(), 1);
+        assert!(skeleton[0].0[0].x.abs() < 0.5);
+        assert!(skeleton[0].0[0].y.abs() < 0.5);
+    }
+
+    #[test]
+    fn ring_of_cells_is_traced_closed() {
+        let ring: HashSet<Cell> = (0..6)
+            .flat_map(|i| [(i, 0), (6, i), (6 - i, 6), (0, 6 - i)])
+            .collect();
+
+        let lines = trace_ridge(&ring);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].len(), ring.len() + 1);
+        assert_eq!(lines[0].first(), lines[0].last());
+    }
+
+    #[test]
+    fn band_around_a_hole_has_a_loop() {
+        let square = |min: f32, max: f32| {
+            LineString::from(vec![(min, min), (max, min), (max, max), (min, max)])
+        };
+        let band = Polygon::new(square(0.0, 20.0), vec![square(2.0, 18.0)]);
+
+        let skeleton = medial_axis(&band, 0.25, 0.5);
+        assert!(!skeleton.is_empty());
+
+        //The skeleton stays in the band, only the spurs into the outer corners come closer to
+        //the walls than the middle of the band
+        for point in skeleton.iter().flat_map(|line| line.0.iter()) {
+            assert!(
+                band.contains(&Point(*point)),
+                "{:?} is outside the band",
+                point
+            );
+
+            let distance = boundary_distance(&band, &Point(*point));
+            assert!(
+                distance > 0.4 && distance < 1.3,
+                "{:?} is {} from a wall",
+                point,
+                distance
+            );
+        }
+
+        //And goes all the way around
+        let length: f32 = skeleton.iter().map(|line| line.euclidean_length()).sum();
+        assert!(length > 68.0, "{} in {:?}", length, skeleton);
+        for side in [(10.0, 1.0), (19.0, 10.0), (10.0, 19.0), (1.0, 10.0)] {
+            let side = Point::from(side);
+            assert!(skeleton
+                .iter()
+                .any(|line| line.euclidean_distance(&side) < 0.3));
+        }
+    }
+}
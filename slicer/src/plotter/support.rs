@@ -1,12 +1,24 @@
-use geo::MultiPolygon;
+use geo::{Intersects, MultiPolygon, Polygon};
 
-use crate::{settings::SupportSettings, TraceType, MoveType, Slice};
+use crate::{
+    settings::SupportSettings, MoveType, Slice, SupportInterfacePattern, SupportStyle, TraceType,
+};
 
-use super::{polygon_operations::PolygonOperations, support_linear_fill_polygon};
+use super::{
+    concentric_fill_polygon, polygon_operations::PolygonOperations, support_linear_fill_polygon,
+};
+
+//Slack when comparing heights against the z gap, so a gap of exactly one layer is kept
+const Z_GAP_TOLERANCE: f32 = 1e-4;
 
 pub trait Supporter {
     fn add_support_polygons(&mut self, slice_above: &Slice, support_settings: &SupportSettings);
-    fn fill_support_polygons(&mut self, support_settings: &SupportSettings);
+    fn fill_support_polygons(
+        &mut self,
+        support_settings: &SupportSettings,
+        column_ranges: &[(f32, f32)],
+        interface: &MultiPolygon<f32>,
+    );
     fn get_support_polygon(&self) -> MultiPolygon<f32>;
 }
 
@@ -38,9 +50,23 @@ impl Supporter for Slice {
         } else if let Some(above_support_tower) = &slice_above.support_tower {
             self.support_tower = Some(above_support_tower.clone());
         }
+
+        //Support right below the overhang, the z gap decides whether it is kept
+        if let Some(overhang) = &self.support_interface {
+            self.support_tower = Some(match &self.support_tower {
+                Some(tower) => tower.union_with(overhang),
+                None => overhang.clone(),
+            });
+        }
     }
 
-    fn fill_support_polygons(&mut self, support_settings: &SupportSettings) {
+    fn fill_support_polygons(
+        &mut self,
+        support_settings: &SupportSettings,
+        column_ranges: &[(f32, f32)],
+        interface: &MultiPolygon<f32>,
+    ) {
+        let height = self.get_height();
         let layer_settings = &self.layer_settings;
         /* if let Some(tower_polygon) = &self.support_interface{
 
@@ -56,18 +82,80 @@ impl Supporter for Slice {
 
         if let Some(tower_polygon) = &self.support_tower {
             self.fixed_chains
-                .extend(tower_polygon.iter().flat_map(|poly| {
-                    support_linear_fill_polygon(
-                        poly,
-                        layer_settings,
-                        MoveType::WithoutFiber(TraceType::Support),
-                        support_settings.support_spacing,
-                        90.0,
-                        0.0,
-                    )
-                    .into_iter()
+                .extend(tower_polygon.iter().enumerate().flat_map(|(index, poly)| {
+                    //Interpolate the density over the height of the column this polygon is part of
+                    let (bottom, top) = column_ranges
+                        .get(index)
+                        .copied()
+                        .unwrap_or((height, height));
+                    let progress = if top > bottom {
+                        ((height - bottom) / (top - bottom)).clamp(0.0, 1.0)
+                    } else {
+                        1.0
+                    };
+                    let density = support_settings.support_density_bottom
+                        + (support_settings.support_density_top
+                            - support_settings.support_density_bottom)
+                            * progress;
+
+                    //The interface is filled separately with its own pattern
+                    let body = if interface.0.is_empty() {
+                        vec![poly.clone()]
+                    } else {
+                        MultiPolygon(vec![poly.clone()])
+                            .difference_with(interface)
+                            .0
+                    };
+
+                    body.into_iter().flat_map(move |poly| {
+                        let spacing = support_settings.support_spacing / density;
+                        match support_settings.style {
+                            SupportStyle::Grid => support_linear_fill_polygon(
+                                &poly,
+                                layer_settings,
+                                MoveType::WithoutFiber(TraceType::Support),
+                                spacing,
+                                90.0,
+                                0.0,
+                            ),
+                            //Rings hold a round branch together better than ribs across it
+                            SupportStyle::Tree => concentric_fill_polygon(
+                                &poly,
+                                layer_settings,
+                                MoveType::WithoutFiber(TraceType::Support),
+                                spacing,
+                            ),
+                        }
+                    })
                 }));
         }
+
+        let interface_type = MoveType::WithoutFiber(TraceType::Support);
+        let spacing = support_settings.interface_spacing;
+        self.fixed_chains.extend(interface.iter().flat_map(|poly| {
+            let lines = |angle| {
+                support_linear_fill_polygon(
+                    poly,
+                    layer_settings,
+                    interface_type,
+                    spacing,
+                    angle,
+                    0.0,
+                )
+            };
+
+            match support_settings.interface_pattern {
+                SupportInterfacePattern::Lines => lines(0.0),
+                SupportInterfacePattern::Grid => {
+                    let mut chains = lines(0.0);
+                    chains.extend(lines(90.0));
+                    chains
+                }
+                SupportInterfacePattern::Concentric => {
+                    concentric_fill_polygon(poly, layer_settings, interface_type, spacing)
+                }
+            }
+        }));
     }
 
     fn get_support_polygon(&self) -> MultiPolygon<f32> {
@@ -79,3 +167,119 @@ impl Supporter for Slice {
         }
     }
 }
+
+///Remove the support within `z_gap` below the model, so the top of every support sits that far
+///below the overhang it holds up
+pub fn apply_support_z_gap(slices: &mut [Slice], z_gap: f32) {
+    let towers: Vec<Option<MultiPolygon<f32>>> = slices
+        .iter()
+        .enumerate()
+        .map(|(q, slice)| {
+            let tower = slice.support_tower.as_ref()?;
+            let model = slices[q + 1..]
+                .iter()
+                .take_while(|above| {
+                    above.bottom_height < slice.top_height + z_gap - Z_GAP_TOLERANCE
+                })
+                .fold(MultiPolygon(vec![]), |model, above| {
+                    model.union_with(&above.main_polygon)
+                });
+
+            let tower = tower.difference_with(&model);
+            (!tower.0.is_empty()).then_some(tower)
+        })
+        .collect();
+
+    for (slice, tower) in slices.iter_mut().zip(towers) {
+        slice.support_tower = tower;
+    }
+}
+
+///The part of the support of every slice in the top `interface_layers` layers below an overhang,
+///which is filled with the interface pattern instead of the support ribs. Only the area right
+///below the overhang is interface, not the whole support around it.
+pub fn support_interface_areas(
+    slices: &[Slice],
+    interface_layers: usize,
+    z_gap: f32,
+) -> Vec<MultiPolygon<f32>> {
+    //Every overhang with the highest layer of the support below it
+    let overhangs: Vec<(usize, &MultiPolygon<f32>)> = (1..slices.len())
+        .filter_map(|k| {
+            let overhang = slices[k - 1].support_interface.as_ref()?;
+            let top = slices[..k].iter().rposition(|below| {
+                slices[k].bottom_height - below.top_height >= z_gap - Z_GAP_TOLERANCE
+            })?;
+            Some((top, overhang))
+        })
+        .collect();
+
+    (0..slices.len())
+        .map(|q| {
+            let overhangs = overhangs
+                .iter()
+                .filter(|(top, _)| *top >= q && *top < q + interface_layers)
+                .fold(MultiPolygon(vec![]), |overhangs, (_, overhang)| {
+                    overhangs.union_with(overhang)
+                });
+
+            match &slices[q].support_tower {
+                Some(tower) if !overhangs.0.is_empty() => tower.intersection_with(&overhangs),
+                _ => MultiPolygon(vec![]),
+            }
+        })
+        .collect()
+}
+
+///The bottom and top height of the support column each support tower polygon of every slice is
+///part of. Columns are followed through the overlapping tower polygons of neighbouring layers.
+pub fn support_column_ranges(slices: &[Slice]) -> Vec<Vec<(f32, f32)>> {
+    let towers: Vec<&[Polygon<f32>]> = slices
+        .iter()
+        .map(|slice| {
+            slice
+                .support_tower
+                .as_ref()
+                .map(|tower| tower.0.as_slice())
+                .unwrap_or(&[])
+        })
+        .collect();
+
+    //Extend the height of every polygon by the polygons it touches on the neighbouring layer
+    let connected_height = |poly: &Polygon<f32>,
+                            neighbour: Option<(&[Polygon<f32>], &Vec<f32>)>,
+                            own: f32,
+                            pick: fn(f32, f32) -> f32| {
+        neighbour
+            .into_iter()
+            .flat_map(|(polygons, heights)| polygons.iter().zip(heights))
+            .filter(|(other, _)| other.intersects(poly))
+            .fold(own, |height, (_, other)| pick(height, *other))
+    };
+
+    let mut bottoms: Vec<Vec<f32>> = vec![vec![]; slices.len()];
+    for q in 0..slices.len() {
+        let below = q.checked_sub(1).map(|b| (towers[b], &bottoms[b]));
+        let column = towers[q]
+            .iter()
+            .map(|poly| connected_height(poly, below, slices[q].bottom_height, f32::min))
+            .collect();
+        bottoms[q] = column;
+    }
+
+    let mut tops: Vec<Vec<f32>> = vec![vec![]; slices.len()];
+    for q in (0..slices.len()).rev() {
+        let above = (q + 1 < slices.len()).then(|| (towers[q + 1], &tops[q + 1]));
+        let column = towers[q]
+            .iter()
+            .map(|poly| connected_height(poly, above, slices[q].top_height, f32::max))
+            .collect();
+        tops[q] = column;
+    }
+
+    bottoms
+        .into_iter()
+        .zip(tops)
+        .map(|(bottoms, tops)| bottoms.into_iter().zip(tops).collect())
+        .collect()
+}
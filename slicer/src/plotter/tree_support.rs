@@ -0,0 +1,212 @@
+use geo::prelude::*;
+use geo::{Closest, Coord, LineString, MultiPolygon, Point, Polygon};
+use ordered_float::OrderedFloat;
+
+use crate::settings::SupportSettings;
+use crate::Object;
+
+use super::lightning_infill::closest_point_exterior_point;
+use super::polygon_operations::PolygonOperations;
+
+//Nodes closer than this many branch diameters lean towards each other to merge
+const MERGE_RANGE: f32 = 5.0;
+
+//Branches thicken with the number of tips they carry, up to this many times the branch diameter
+const MAX_THICKENING: f32 = 3.0;
+
+//Corners of the circle drawn for a branch
+const BRANCH_SEGMENTS: usize = 16;
+
+//Gap kept between the branches and the model
+const MODEL_GAP: f32 = 0.2;
+
+///A branch of a tree support where it crosses a layer
+#[derive(Debug, Clone, Copy)]
+struct TreeNode {
+    position: Coord<f32>,
+
+    ///Number of overhang tips the branch holds up
+    tips: usize,
+}
+
+impl TreeNode {
+    fn radius(&self, branch_diameter: f32) -> f32 {
+        //Slower than the combined area of the tips, so merging saves material
+        branch_diameter / 2.0 * (self.tips as f32).powf(0.25).min(MAX_THICKENING)
+    }
+}
+
+///Grow tree supports from the overhangs of the object down to the bed. Every overhang is held up
+///by tips that lean together into fewer, thicker branches on the way down while steering around
+///the model. The branches of each layer become its support tower, so they are filled like the
+///grid supports.
+pub fn add_tree_support(obj: &mut Object, support: &SupportSettings) {
+    let branch_diameter = support.tree_branch_diameter;
+    let mut nodes: Vec<TreeNode> = vec![];
+
+    for q in (1..obj.layers.len()).rev() {
+        let (below, above) = obj.layers.split_at_mut(q);
+        let layer = below.last_mut().expect("q is at least 1");
+        let above = &above[0];
+
+        let max_move = (above.get_height() - layer.get_height())
+            * support.max_overhang_angle.to_radians().tan();
+
+        //Overhangs are recorded like the grid supports do, so the interface layers work the same
+        let overhang = above
+            .main_polygon
+            .difference_with(&layer.main_polygon.offset_from(max_move));
+        if !overhang.0.is_empty() {
+            nodes.extend(
+                tip_positions(&overhang, branch_diameter)
+                    .into_iter()
+                    .map(|position| TreeNode { position, tips: 1 }),
+            );
+            layer.support_interface = Some(overhang);
+        }
+
+        if nodes.is_empty() {
+            continue;
+        }
+
+        nodes = grow_nodes(&nodes, &layer.main_polygon, branch_diameter, max_move);
+
+        let branches = MultiPolygon(
+            nodes
+                .iter()
+                .map(|node| circle(node.position, node.radius(branch_diameter)))
+                .collect(),
+        )
+        .difference_with(&layer.main_polygon.offset_from(MODEL_GAP));
+
+        if !branches.0.is_empty() {
+            layer.support_tower = Some(match &layer.support_tower {
+                Some(tower) => tower.union_with(&branches),
+                None => branches,
+            });
+        }
+    }
+}
+
+///Points holding up an overhang, on a grid `spacing` apart that is fixed to the bed so tips of
+///consecutive layers line up. Overhangs too small to contain a grid point get a single tip.
+fn tip_positions(overhang: &MultiPolygon<f32>, spacing: f32) -> Vec<Coord<f32>> {
+    let grid = |min: f32, max: f32| (min / spacing).ceil() as i32..=(max / spacing).floor() as i32;
+
+    overhang
+        .iter()
+        .flat_map(|poly| {
+            let Some(bounds) = poly.bounding_rect() else {
+                return vec![];
+            };
+
+            let points: Vec<Coord<f32>> = grid(bounds.min().x, bounds.max().x)
+                .flat_map(|x| {
+                    grid(bounds.min().y, bounds.max().y).map(move |y| Coord {
+                        x: x as f32 * spacing,
+                        y: y as f32 * spacing,
+                    })
+                })
+                .filter(|point| poly.contains(point))
+                .collect();
+
+            if points.is_empty() {
+                poly.interior_point()
+                    .map(|point| point.0)
+                    .into_iter()
+                    .collect()
+            } else {
+                points
+            }
+        })
+        .collect()
+}
+
+///Move the nodes down a layer. Each node leans towards its closest neighbour in range, is pushed
+///clear of the model and merges with the node it then overlaps. Nodes above the model rest on it
+///and end there.
+fn grow_nodes(
+    nodes: &[TreeNode],
+    model: &MultiPolygon<f32>,
+    branch_diameter: f32,
+    max_move: f32,
+) -> Vec<TreeNode> {
+    let mut grown: Vec<TreeNode> = vec![];
+
+    for (index, node) in nodes.iter().enumerate() {
+        if model.contains(&Point(node.position)) {
+            continue;
+        }
+
+        let closest = nodes
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, other)| other.position)
+            .min_by_key(|other| OrderedFloat(other.euclidean_distance(&node.position)));
+
+        let leaned = match closest {
+            Some(target)
+                if target.euclidean_distance(&node.position) < MERGE_RANGE * branch_diameter =>
+            {
+                //Both nodes lean in, so neither needs to cover more than half the distance
+                let distance = target.euclidean_distance(&node.position);
+                node.position + (target - node.position) * (max_move / distance).min(0.5)
+            }
+            _ => node.position,
+        };
+
+        //Leaning into the model is not worth it, the branch keeps going straight down instead
+        let position = if model.contains(&Point(leaned)) {
+            node.position
+        } else {
+            leaned
+        };
+        let position = clear_of_model(position, model, node.radius(branch_diameter) + MODEL_GAP);
+        let node = TreeNode { position, ..*node };
+
+        match grown
+            .iter_mut()
+            .find(|other| other.position.euclidean_distance(&node.position) < branch_diameter / 2.0)
+        {
+            Some(other) => {
+                let tips = other.tips + node.tips;
+                other.position = (other.position * other.tips as f32
+                    + node.position * node.tips as f32)
+                    / tips as f32;
+                other.tips = tips;
+            }
+            None => grown.push(node),
+        }
+    }
+
+    grown
+}
+
+///Push a position outside of the model away from its closest edge, until it is `clearance` from it
+fn clear_of_model(position: Coord<f32>, model: &MultiPolygon<f32>, clearance: f32) -> Coord<f32> {
+    let edge = match closest_point_exterior_point(model, &Point(position)) {
+        Closest::SinglePoint(point) | Closest::Intersection(point) => point.0,
+        Closest::Indeterminate => return position,
+    };
+
+    let distance = edge.euclidean_distance(&position);
+    if distance >= clearance || distance <= f32::EPSILON {
+        position
+    } else {
+        edge + (position - edge) * (clearance / distance)
+    }
+}
+
+fn circle(center: Coord<f32>, radius: f32) -> Polygon<f32> {
+    Polygon::new(
+        LineString::from_iter((0..BRANCH_SEGMENTS).map(|index| {
+            let angle = index as f32 * std::f32::consts::TAU / BRANCH_SEGMENTS as f32;
+            Coord {
+                x: center.x + radius * angle.cos(),
+                y: center.y + radius * angle.sin(),
+            }
+        })),
+        vec![],
+    )
+}
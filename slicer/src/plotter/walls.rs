@@ -3,11 +3,15 @@ use geo::*;
 
 use glam::vec2;
 use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::settings::LayerSettings;
-use crate::{Move, MoveChain, MoveType, TraceType};
+use crate::settings::{FuzzySkinSettings, LayerSettings, SeamHint};
+use crate::{Move, MoveChain, MoveType, SeamPosition, TraceType};
 
 use super::polygon_operations::PolygonOperations;
+use super::skeleton::{boundary_distance, medial_axis};
+use crate::utils::point_lerp;
 
 pub fn determine_move_type(
     settings: &LayerSettings,
@@ -63,11 +67,42 @@ pub fn determine_move_type(
     }
 }
 
-pub fn seam<'a>(points: &'a [Coord<f32>]) -> Vec<&'a Coord<f32>> {
+///Minimum turn in radians for a vertex to count as a corner
+const MIN_CORNER_TURN: f32 = 0.01;
+
+///Rotates the points of a wall loop to start at the seam. `hole` tells whether the loop runs
+///around a hole, where the corners that are concave for the print turn the other way.
+pub fn seam<'a>(
+    points: &'a [Coord<f32>],
+    hints: &[SeamHint],
+    position: SeamPosition,
+    hole: bool,
+) -> Vec<&'a Coord<f32>> {
     if points.len() < 3 {
         return points.iter().collect();
     }
 
+    let index = hinted_seam_index(points, hints).or_else(|| match position {
+        //Those depending on other loops start at the largest turn until place_seams moves them
+        SeamPosition::LargestTurn
+        | SeamPosition::Nearest
+        | SeamPosition::Random
+        | SeamPosition::Aligned => None,
+        SeamPosition::Rear => Some(rear_index(points)),
+        SeamPosition::SharpestCorner => {
+            Some(sharpest_corner_index(points, hole).unwrap_or_else(|| rear_index(points)))
+        }
+    });
+
+    if let Some(index) = index {
+        return points
+            .iter()
+            .cycle()
+            .skip(index)
+            .take(points.len())
+            .collect();
+    }
+
     let mut last_direction = None;
     let (mut max_angle, mut index) = (0.0, None);
 
@@ -103,6 +138,263 @@ pub fn seam<'a>(points: &'a [Coord<f32>]) -> Vec<&'a Coord<f32>> {
         .collect()
 }
 
+///Returns the index of the vertex furthest towards the back of the bed
+fn rear_index(points: &[Coord<f32>]) -> usize {
+    points
+        .iter()
+        .position_min_by(|a, b| b.y.partial_cmp(&a.y).expect("Points should not be NAN"))
+        .unwrap_or(0)
+}
+
+///Returns the index of the most concave vertex of the loop, or None if every vertex is convex
+fn sharpest_corner_index(points: &[Coord<f32>], hole: bool) -> Option<usize> {
+    //Rings repeat their first point at the end, the vertex is only considered once
+    let ring = match points {
+        [first, .., last] if first == last => &points[..points.len() - 1],
+        _ => points,
+    };
+
+    if ring.len() < 3 {
+        return None;
+    }
+
+    //The print lies left of counter clockwise outlines and right of counter clockwise holes
+    let winding = ring
+        .iter()
+        .circular_tuple_windows()
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        .signum();
+    let concave_turn = if hole { winding } else { -winding };
+
+    ring.iter()
+        .circular_tuple_windows::<(_, _, _)>()
+        .enumerate()
+        .filter_map(|(index, (previous, vertex, next))| {
+            let incoming = vec2(vertex.x - previous.x, vertex.y - previous.y);
+            let outgoing = vec2(next.x - vertex.x, next.y - vertex.y);
+            let turn = incoming.perp_dot(outgoing).atan2(incoming.dot(outgoing));
+
+            //The windows start at the first point, so the corner is at the following index
+            (turn * concave_turn > MIN_CORNER_TURN)
+                .then_some(((index + 1) % ring.len(), turn.abs()))
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Angles should not be NAN"))
+        .map(|(index, _)| index)
+}
+
+///Returns the index of the vertex a loop should start at for the seam positions that depend on
+///other loops, or None to keep its seam. `from` is where the nozzle comes from and
+///`previous_seams` are the seams of the layer below. Random seams only depend on the layer and
+///the loop, so slicing again gives the same seams.
+pub fn dependent_seam_index(
+    vertices: &[Coord<f32>],
+    from: Coord<f32>,
+    previous_seams: &[Coord<f32>],
+    position: SeamPosition,
+    hints: &[SeamHint],
+    layer: usize,
+) -> Option<usize> {
+    //Painted seams always win
+    if vertices.is_empty() || hinted_seam_index(vertices, hints).is_some() {
+        return None;
+    }
+
+    let closest_to = |target: &Coord<f32>| {
+        vertices
+            .iter()
+            .map(|vertex| vertex.euclidean_distance(target))
+            .position_min_by(|a, b| a.partial_cmp(b).expect("Distances should not be NAN"))
+    };
+
+    match position {
+        SeamPosition::Nearest => closest_to(&from),
+        SeamPosition::Random => {
+            let start = vertices[0];
+            let seed = (layer as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (((start.x.to_bits() as u64) << 32) | start.y.to_bits() as u64);
+
+            Some(StdRng::seed_from_u64(seed).gen_range(0..vertices.len()))
+        }
+        SeamPosition::Aligned => previous_seams
+            .iter()
+            .filter_map(|seam| {
+                closest_to(seam).map(|index| (index, vertices[index].euclidean_distance(seam)))
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Distances should not be NAN"))
+            .map(|(index, _)| index),
+        SeamPosition::LargestTurn | SeamPosition::Rear | SeamPosition::SharpestCorner => None,
+    }
+}
+
+///Returns the index of the vertex closest to any painted seam hint within its radius
+fn hinted_seam_index(points: &[Coord<f32>], hints: &[SeamHint]) -> Option<usize> {
+    hints
+        .iter()
+        .flat_map(|hint| {
+            let hint_point = Coord {
+                x: hint.x,
+                y: hint.y,
+            };
+
+            points
+                .iter()
+                .enumerate()
+                .map(move |(index, point)| (index, point.euclidean_distance(&hint_point)))
+                .filter(move |(_, distance)| *distance <= hint.radius)
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Distances should not be NAN"))
+        .map(|(index, _)| index)
+}
+
+///Width added to a wall by the alternating wall width, with wall 0 being the outer wall. Every
+///other wall is wider and the wider walls swap each layer, so the boundaries between the walls
+///interlock across the layers.
+pub fn alternating_width(settings: &LayerSettings, wall: usize, layer: usize) -> f32 {
+    if settings.alternating_wall_width.is_enabled() && (wall + layer) % 2 == 1 {
+        *settings.alternating_wall_width
+    } else {
+        0.0
+    }
+}
+
+///Splits the plastic outer wall moves of a loop every point distance and moves the new points
+///randomly to either side of the wall. The corners stay in place. Points move inwards by at most
+///half the wall width so they stay clear of the inner wall, and never further than they are from
+///the rest of the loop so concave corners don't fold over. The jitter only depends on the seed,
+///the layer and the loop, so slicing again gives the same surface.
+fn fuzzy_skin(
+    start_point: Coord<f32>,
+    moves: Vec<Move>,
+    fuzzy: &FuzzySkinSettings,
+    wall_width: f32,
+    layer: usize,
+) -> Vec<Move> {
+    let ring: LineString<f32> = std::iter::once(start_point)
+        .chain(moves.iter().map(|m| m.end))
+        .collect();
+
+    //The inside is left of the path for counter clockwise loops
+    let winding = Polygon::new(ring.clone(), vec![]).signed_area().signum();
+
+    let seed = (fuzzy.seed as u64)
+        ^ (layer as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (((start_point.x.to_bits() as u64) << 32) | start_point.y.to_bits() as u64);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut current = start_point;
+    let mut fuzzy_moves = vec![];
+
+    for m in moves {
+        let length = current.euclidean_distance(&m.end);
+        let pieces = (length / fuzzy.point_distance).floor() as usize;
+
+        if m.move_type == MoveType::WithoutFiber(TraceType::WallOuter) && pieces > 1 {
+            let direction = (m.end - current) / length;
+            let inward = Coord {
+                x: -direction.y,
+                y: direction.x,
+            } * winding;
+
+            for piece in 1..pieces {
+                let point = current + (m.end - current) * (piece as f32 / pieces as f32);
+
+                let mut offset = rng
+                    .gen_range(-fuzzy.thickness / 2.0..=fuzzy.thickness / 2.0)
+                    .min(wall_width / 2.0);
+                let clearance = Point::from(point + inward * offset).euclidean_distance(&ring);
+                if clearance < offset.abs() {
+                    offset = clearance.copysign(offset);
+                }
+
+                fuzzy_moves.push(Move {
+                    end: point + inward * offset,
+                    ..m
+                });
+            }
+        }
+
+        current = m.end;
+        fuzzy_moves.push(m);
+    }
+
+    fuzzy_moves
+}
+
+///Single walls along the medial axis of the thin parts of a layer, the features too narrow for a
+///wall on each side. The width of the wall follows the distance to the outline so it fills
+///tapering wedges. Ends that stop short of the area printed with regular walls are extended up to
+///it, so no gap is left where the two meet.
+pub fn thin_wall_chains(
+    thin: &MultiPolygon<f32>,
+    outline: &MultiPolygon<f32>,
+    body: &MultiPolygon<f32>,
+    settings: &LayerSettings,
+) -> Vec<MoveChain> {
+    let wall_width = settings.extrusion_width.exterior_surface_perimeter;
+    let resolution = wall_width / 10.0;
+    let move_type = MoveType::WithoutFiber(TraceType::WallOuter);
+
+    //A point is closer to the boundary of the outline it lies in than to any other outline
+    let width_at = |point: Coord<f32>| {
+        let distance = outline
+            .iter()
+            .map(|poly| boundary_distance(poly, &Point(point)))
+            .fold(f32::INFINITY, f32::min);
+        (2.0 * distance).min(2.0 * wall_width)
+    };
+
+    let extend = |end: Coord<f32>, from: Coord<f32>, width: f32| {
+        let gap = Point(end).euclidean_distance(body);
+        let length = from.euclidean_distance(&end);
+        if body.0.is_empty() || length < f32::EPSILON || gap > width + resolution {
+            end
+        } else {
+            end + (end - from) * (gap / length)
+        }
+    };
+
+    thin.iter()
+        .flat_map(|piece| medial_axis(piece, resolution, resolution))
+        //Drops the slivers cut off the corners of the outline
+        .filter(|line| line.0.len() > 1 && line.euclidean_length() >= wall_width)
+        .map(|line| {
+            //Short moves so the width can follow the outline
+            let mut points: Vec<Coord<f32>> = line
+                .lines()
+                .flat_map(|segment| {
+                    let steps = (segment.euclidean_length() / wall_width).ceil().max(1.0);
+                    (0..steps as usize).map(move |step| {
+                        point_lerp(&segment.start, &segment.end, step as f32 / steps)
+                    })
+                })
+                .chain(line.0.last().copied())
+                .collect();
+            let widths: Vec<f32> = points.iter().map(|point| width_at(*point)).collect();
+
+            let last = points.len() - 1;
+            points[0] = extend(points[0], points[1], widths[0]);
+            points[last] = extend(points[last], points[last - 1], widths[last]);
+
+            MoveChain {
+                start_point: points[0],
+                moves: points
+                    .iter()
+                    .zip(widths.iter())
+                    .tuple_windows()
+                    .map(|((_, start_width), (end, end_width))| Move {
+                        end: *end,
+                        move_type,
+                        //The bead tapers from the width at its start to the width at its end
+                        width: (start_width + end_width) / 2.0,
+                    })
+                    .collect(),
+                is_loop: false,
+            }
+        })
+        .collect()
+}
+
 pub fn inset_polygon_recursive(
     poly: &MultiPolygon<f32>,
     settings: &LayerSettings,
@@ -113,19 +405,27 @@ pub fn inset_polygon_recursive(
     wall_ranges: &[u32],
 ) -> Option<MoveChain> {
     let mut move_chains = vec![];
-    let inset_poly = poly.offset_from(
-        if outer_perimeter {
-            settings.extrusion_width.interior_surface_perimeter
-        } else {
-            settings.extrusion_width.interior_inner_perimeter
-        } / -2.0,
-    );
+
+    //Both insets use the width of this wall, so the walls tile whatever their width
+    let extra_width = alternating_width(settings, number_of_walls - 1 - walls_left, layer);
+    let wall_width = if outer_perimeter {
+        settings.extrusion_width.interior_surface_perimeter
+    } else {
+        settings.extrusion_width.interior_inner_perimeter
+    } + extra_width;
+
+    let inset_poly = poly.offset_from(wall_width / -2.0);
 
     for raw_polygon in inset_poly.0.iter() {
         let polygon = raw_polygon.simplify(&0.01);
         let mut outer_chains = vec![];
 
-        let seamed_poly = seam(&polygon.exterior().0);
+        let seamed_poly = seam(
+            &polygon.exterior().0,
+            &settings.seam_hints,
+            settings.seam_position,
+            false,
+        );
         let start_point = seamed_poly[0].clone();
 
         let moves: Vec<Move> = seamed_poly
@@ -158,11 +458,18 @@ pub fn inset_polygon_recursive(
                     move_type,
                     width: settings
                         .extrusion_width
-                        .get_value_for_movement_type(&move_type),
+                        .get_value_for_movement_type(&move_type)
+                        + extra_width,
                 }
             })
             .collect();
 
+        let moves = if outer_perimeter && settings.fuzzy_skin.is_enabled() {
+            fuzzy_skin(start_point, moves, &settings.fuzzy_skin, wall_width, layer)
+        } else {
+            moves
+        };
+
         outer_chains.push(MoveChain {
             start_point,
             moves,
@@ -191,7 +498,12 @@ pub fn inset_polygon_recursive(
                 )
             };
 
-            let seamed_poly = seam(&interior.0);
+            let seamed_poly = seam(
+                &interior.0,
+                &settings.seam_hints,
+                settings.seam_position,
+                true,
+            );
             let start_point = seamed_poly[0].clone();
 
             for (&_start, &end) in seamed_poly.into_iter().circular_tuple_windows::<(_, _)>() {
@@ -200,7 +512,8 @@ pub fn inset_polygon_recursive(
                     move_type,
                     width: settings
                         .extrusion_width
-                        .get_value_for_movement_type(&move_type),
+                        .get_value_for_movement_type(&move_type)
+                        + extra_width,
                 });
             }
 
@@ -213,13 +526,7 @@ pub fn inset_polygon_recursive(
 
         let mut inner_chains = vec![];
         if walls_left != 0 {
-            let rec_inset_poly = polygon.offset_from(
-                if outer_perimeter {
-                    settings.extrusion_width.interior_surface_perimeter
-                } else {
-                    settings.extrusion_width.interior_inner_perimeter
-                } / -2.0,
-            );
+            let rec_inset_poly = polygon.offset_from(wall_width / -2.0);
 
             for polygon_rec in rec_inset_poly {
                 if let Some(mc) = inset_polygon_recursive(
@@ -276,7 +583,324 @@ fn test_seam() {
     ];
 
     println!("{:?}", points);
-    println!("{:?}", seam(&points));
+    println!("{:?}", seam(&points, &[], SeamPosition::LargestTurn, false));
 
     panic!("Test not implemented");
 }
+
+#[test]
+fn test_seam_hint() {
+    let hints = [SeamHint {
+        x: 10.5,
+        y: 10.5,
+        radius: 2.0,
+    }];
+
+    // The same corner on loops of different layers
+    for size in [10.0, 10.4, 9.6] {
+        let points = [
+            Coord { x: 0.0, y: 0.0 },
+            Coord { x: size, y: 0.0 },
+            Coord { x: size, y: size },
+            Coord { x: 0.0, y: size },
+        ];
+
+        assert_eq!(
+            *seam(&points, &hints, SeamPosition::LargestTurn, false)[0],
+            Coord { x: size, y: size }
+        );
+    }
+
+    // Without hints the seam is placed by the default policy
+    let points = [
+        Coord { x: 0.0, y: 0.0 },
+        Coord { x: 10.0, y: 0.0 },
+        Coord { x: 10.0, y: 10.0 },
+        Coord { x: 0.0, y: 10.0 },
+    ];
+
+    let far_hint = [SeamHint {
+        x: 50.0,
+        y: 50.0,
+        radius: 2.0,
+    }];
+
+    assert_eq!(
+        seam(&points, &far_hint, SeamPosition::LargestTurn, false),
+        seam(&points, &[], SeamPosition::LargestTurn, false)
+    );
+}
+
+#[test]
+fn alternating_wall_width_tiles() {
+    let mut settings = crate::Settings::default();
+    *settings.alternating_wall_width.enabled_mut() = true;
+    *settings.alternating_wall_width = 0.1;
+    let settings = settings.get_layer_settings(1, 1.2);
+
+    let square = MultiPolygon(vec![Polygon::new(
+        LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+        vec![],
+    )]);
+
+    //Distance of each wall from the outline and its width, from the outer wall inwards
+    let walls = |layer: usize| -> Vec<(f32, f32)> {
+        let chain = inset_polygon_recursive(&square, &settings, true, 3, 2, layer, &[]).unwrap();
+
+        chain
+            .moves
+            .split(|m| m.move_type == MoveType::Travel)
+            .filter(|moves| !moves.is_empty())
+            .map(|moves| {
+                let inset = moves.iter().map(|m| m.end.x).fold(f32::INFINITY, f32::min);
+                (inset, moves[0].width)
+            })
+            .sorted_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .collect()
+    };
+
+    for layer in 0..2 {
+        let walls = walls(layer);
+        assert_eq!(walls.len(), 3);
+
+        //The wider walls swap every layer
+        let expected = if layer == 0 {
+            [0.4, 0.5, 0.4]
+        } else {
+            [0.5, 0.4, 0.5]
+        };
+        assert!(walls
+            .iter()
+            .zip(expected)
+            .all(|((_, width), expected)| (width - expected).abs() < 1e-5));
+
+        //Each wall starts where the one outside of it ends
+        assert!((walls[0].0 - walls[0].1 / 2.0).abs() < 1e-3);
+        for (outer, inner) in walls.iter().tuple_windows() {
+            assert!(
+                ((inner.0 - outer.0) - (outer.1 + inner.1) / 2.0).abs() < 1e-3,
+                "{:?}",
+                walls
+            );
+        }
+    }
+}
+
+#[test]
+fn test_seam_sharpest_corner() {
+    let l_shape = [
+        Coord { x: 0.0, y: 0.0 },
+        Coord { x: 20.0, y: 0.0 },
+        Coord { x: 20.0, y: 10.0 },
+        Coord { x: 10.0, y: 10.0 },
+        Coord { x: 10.0, y: 20.0 },
+        Coord { x: 0.0, y: 20.0 },
+        Coord { x: 0.0, y: 0.0 },
+    ];
+    let notch = Coord { x: 10.0, y: 10.0 };
+
+    // The seam lands in the notch whichever way the loop runs
+    let seamed = seam(&l_shape, &[], SeamPosition::SharpestCorner, false);
+    assert_eq!(*seamed[0], notch);
+    assert_eq!(seamed.len(), l_shape.len());
+
+    let reversed = l_shape.iter().rev().copied().collect::<Vec<_>>();
+    assert_eq!(
+        *seam(&reversed, &[], SeamPosition::SharpestCorner, false)[0],
+        notch
+    );
+
+    // Around a hole of the same shape the notch is convex for the print
+    assert_ne!(
+        *seam(&l_shape, &[], SeamPosition::SharpestCorner, true)[0],
+        notch
+    );
+
+    // Without concave corners the seam falls back to the rear
+    let square = [
+        Coord { x: 0.0, y: 0.0 },
+        Coord { x: 10.0, y: 0.0 },
+        Coord { x: 10.0, y: 10.0 },
+        Coord { x: 0.0, y: 10.0 },
+        Coord { x: 0.0, y: 0.0 },
+    ];
+    assert_eq!(
+        *seam(&square, &[], SeamPosition::SharpestCorner, false)[0],
+        Coord { x: 10.0, y: 10.0 }
+    );
+
+    // Loops too short to have corners are left as they are
+    let short = [Coord { x: 0.0, y: 0.0 }, Coord { x: 1.0, y: 0.0 }];
+    assert_eq!(
+        seam(&short, &[], SeamPosition::SharpestCorner, false),
+        short.iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_seam_aligned() {
+    let mut settings = crate::Settings::default();
+    settings.seam_position = SeamPosition::Aligned;
+    let settings = settings.get_layer_settings(1, 0.4);
+
+    let square = MultiPolygon(vec![Polygon::new(
+        LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+        vec![],
+    )]);
+
+    let mut chain = inset_polygon_recursive(&square, &settings, true, 2, 1, 1, &[]).unwrap();
+    let before = chain.moves.iter().map(|m| m.end).collect::<Vec<_>>();
+
+    let previous_seams = [Coord { x: 20.0, y: 0.0 }];
+    let seams = chain.rotate_loops(|vertices, from| {
+        dependent_seam_index(
+            vertices,
+            from,
+            &previous_seams,
+            settings.seam_position,
+            &[],
+            1,
+        )
+    });
+
+    //Both walls start at their corner closest to the seam below
+    assert_eq!(seams.len(), 2);
+    for seam in &seams {
+        assert!(seam.x > 19.0 && seam.y < 1.0, "{:?}", seams);
+    }
+    assert_eq!(chain.start_point, seams[0]);
+    assert_eq!(chain.moves[0].end, seams[0]);
+
+    //The loops are rotated in place and still end where they start
+    let loops = chain
+        .moves
+        .split(|m| m.move_type == MoveType::Travel)
+        .filter(|moves| !moves.is_empty())
+        .collect::<Vec<_>>();
+    for (moves, seam) in loops.iter().zip(&seams) {
+        assert_eq!(moves.last().unwrap().end, *seam);
+    }
+    assert_eq!(chain.moves.len(), before.len());
+    assert!(before
+        .iter()
+        .all(|end| chain.moves.iter().any(|m| m.end == *end)));
+
+    //Without seams below the loops keep their start
+    let starts = chain.rotate_loops(|vertices, from| {
+        dependent_seam_index(vertices, from, &[], settings.seam_position, &[], 1)
+    });
+    assert_eq!(starts, seams);
+}
+
+#[test]
+fn test_seam_nearest() {
+    let square = [
+        Coord { x: 0.0, y: 0.0 },
+        Coord { x: 20.0, y: 0.0 },
+        Coord { x: 20.0, y: 20.0 },
+        Coord { x: 0.0, y: 20.0 },
+    ];
+
+    let nearest =
+        |from: Coord<f32>| dependent_seam_index(&square, from, &[], SeamPosition::Nearest, &[], 0);
+
+    //The loop starts at the corner closest to where the nozzle comes from
+    assert_eq!(nearest(Coord { x: 25.0, y: 22.0 }), Some(2));
+    assert_eq!(nearest(Coord { x: -3.0, y: 18.0 }), Some(3));
+
+    //A painted seam wins over the nearest corner
+    let hint = SeamHint {
+        x: 0.0,
+        y: 0.0,
+        radius: 1.0,
+    };
+    assert_eq!(
+        dependent_seam_index(
+            &square,
+            Coord { x: 25.0, y: 22.0 },
+            &[],
+            SeamPosition::Nearest,
+            &[hint],
+            0
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_seam_random_is_reproducible() {
+    let circle = (0..64)
+        .map(|i| {
+            let angle = i as f32 / 64.0 * std::f32::consts::TAU;
+            Coord {
+                x: 10.0 * angle.cos(),
+                y: 10.0 * angle.sin(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let random = |layer: usize| {
+        dependent_seam_index(
+            &circle,
+            Coord { x: 0.0, y: 0.0 },
+            &[],
+            SeamPosition::Random,
+            &[],
+            layer,
+        )
+        .expect("Random seams always pick a vertex")
+    };
+
+    //Slicing again gives the same seams
+    let seams = (0..20).map(random).collect::<Vec<_>>();
+    assert_eq!(seams, (0..20).map(random).collect::<Vec<_>>());
+    assert!(seams.iter().all(|index| *index < circle.len()));
+
+    //The seams still move around from layer to layer
+    assert!(seams.iter().unique().count() > 5, "{:?}", seams);
+}
+
+#[test]
+fn fuzzy_skin_is_reproducible() {
+    let square = MultiPolygon(vec![Polygon::new(
+        LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+        vec![],
+    )]);
+
+    let outer_wall = |settings: &crate::Settings| {
+        let settings = settings.get_layer_settings(1, 0.4);
+        inset_polygon_recursive(&square, &settings, true, 1, 0, 1, &[])
+            .unwrap()
+            .moves
+    };
+
+    let mut settings = crate::Settings::default();
+    let smooth = outer_wall(&settings);
+
+    *settings.fuzzy_skin.enabled_mut() = true;
+    settings.fuzzy_skin.thickness = 0.4;
+    settings.fuzzy_skin.point_distance = 1.0;
+    let fuzzy = outer_wall(&settings);
+
+    //Slicing again gives the same surface
+    assert_eq!(fuzzy, outer_wall(&settings));
+    assert!(fuzzy.len() > smooth.len() * 10);
+
+    //Every point stays within half the thickness of the smooth wall, which it keeps the corners of
+    let smooth_ring: LineString<f32> = smooth.iter().map(|m| m.end).collect();
+    let max_distance = fuzzy
+        .iter()
+        .map(|m| Point::from(m.end).euclidean_distance(&smooth_ring))
+        .fold(0.0, f32::max);
+    assert!(max_distance > 0.01 && max_distance <= 0.2 + 1e-4);
+    assert!(smooth.iter().all(|s| fuzzy.iter().any(|f| f.end == s.end)));
+
+    //Another seed gives another surface
+    settings.fuzzy_skin.seed = 1;
+    assert_ne!(fuzzy, outer_wall(&settings));
+
+    //Fiber walls are left smooth
+    *settings.fiber.wall_pattern.enabled_mut() = true;
+    settings.fiber.wall_pattern.pattern = crate::fiber::WallPatternType::Full;
+    assert_eq!(outer_wall(&settings).len(), smooth.len());
+}
@@ -1,10 +1,15 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 
+use glam::Vec2;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::SlicerErrors, warning::SlicerWarnings, MoveType, PartialInfillTypes, SolidInfillTypes,
-    TraceType,
+    error::SlicerErrors, warning::SlicerWarnings, ApproachMode, BedType, GcodeFlavor, MoveType,
+    OverlapPolicy, PartialInfillTypes, PrintSequence, SeamPosition, SolidInfillTypes,
+    SupportInterfacePattern, SupportStyle, ThinPartSolidPolicy, TraceType,
+    UnknownPlaceholderPolicy, WipeDirection,
 };
 
 macro_rules! setting_less_than_or_equal_to_zero {
@@ -44,6 +49,30 @@ macro_rules! setting_less_than_zero {
     }};
 }
 
+macro_rules! setting_not_a_percentage {
+    ($settings:ident,$setting:ident) => {{
+        if !(0.0..=1.0).contains(&($settings.$setting as f32)) {
+            return SettingsValidationResult::Error(SlicerErrors::SettingNotAPercentage {
+                setting: stringify!($setting).to_string(),
+                value: $settings.$setting as f32,
+            });
+        }
+    }};
+}
+
+macro_rules! option_setting_not_a_percentage {
+    ($settings:ident,$setting:ident) => {{
+        if let Some(temp) = $settings.$setting {
+            if !(0.0..=1.0).contains(&(temp as f32)) {
+                return SettingsValidationResult::Error(SlicerErrors::SettingNotAPercentage {
+                    setting: stringify!($setting).to_string(),
+                    value: temp as f32,
+                });
+            }
+        }
+    }};
+}
+
 macro_rules! option_setting_less_than_zero {
     ($settings:ident,$setting:ident) => {{
         if let Some(temp) = $settings.$setting {
@@ -59,8 +88,26 @@ macro_rules! option_setting_less_than_zero {
     }};
 }
 
+///Settings that are only read when writing the gcode. Changing them doesn't change the moves of a
+///slice, every other setting is treated as changing the geometry.
+pub const GCODE_ONLY_SETTINGS: &[&str] = &[
+    "starting_instructions",
+    "ending_instructions",
+    "before_layer_change_instructions",
+    "after_layer_change_instructions",
+    "object_change_instructions",
+    "resume_instructions",
+    "layer_gcode_hooks",
+    "bed_transform",
+    "final_retract_no_lift",
+    "resume_from_layer",
+    "gcode_flavor",
+    "pressure_advance",
+    "unknown_placeholder_policy",
+];
+
 ///A complete settings file for the entire slicer.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Settings {
     ///The height of the layers
     pub layer_height: f32,
@@ -139,6 +186,9 @@ pub struct Settings {
     ///Overlap between infill and interior perimeters
     pub infill_perimeter_overlap_percentage: f32,
 
+    ///Overlap between solid infill and interior perimeters
+    pub solid_infill_overlap_percentage: f32,
+
     ///Solid Infill type
     pub solid_infill_type: SolidInfillTypes,
 
@@ -160,6 +210,10 @@ pub struct Settings {
     /// The instructions to append between object changes
     pub object_change_instructions: String,
 
+    /// The instructions to home and heat up the printer when resuming a print, instead of the
+    /// starting instructions
+    pub resume_instructions: String,
+
     ///Maximum Acceleration in x dimension
     pub max_acceleration_x: f32,
     ///Maximum Acceleration in y dimension
@@ -198,6 +252,174 @@ pub struct Settings {
     ///Maximum feedrate for e dimension
     pub maximum_feedrate_e: f32,
 
+    ///Fixed angle for bridge infill, if None the optimal angle is calculated
+    pub bridge_angle_override: OptionalSetting<f32>,
+
+    ///Density of bridge infill as a fraction of the solid infill density, from 0 to 1
+    pub bridge_density: f32,
+
+    ///The ooze shield settings, if None no ooze shield will be generated
+    pub ooze_shield: OptionalSetting<OozeShieldSettings>,
+
+    ///Painted seam positions, the seam is placed on the closest vertex within the hint radius
+    pub seam_hints: Vec<SeamHint>,
+
+    ///Maximum width of gaps between the walls filled with a single bead, if None no gap fill will be performed
+    pub gap_fill: OptionalSetting<f32>,
+
+    ///Skip the z lift on the retraction after the final extrusion of the print
+    pub final_retract_no_lift: bool,
+
+    ///Transform applied to XY coordinates of the output gcode
+    pub bed_transform: BedTransform,
+
+    ///How to handle objects that overlap each other
+    pub overlapping_objects: OverlapPolicy,
+
+    ///Whether objects are printed layer by layer together or one after another
+    pub print_sequence: PrintSequence,
+
+    ///Park and wait out the slow down threshold on layers of objects printed one after another
+    pub sequential_cooling: OptionalSetting<SequentialCoolingSettings>,
+
+    ///Solid fill used on layers that are within both the top and bottom layers
+    pub thin_part_solid_policy: ThinPartSolidPolicy,
+
+    ///Points where the print pauses and displays a message at the closest layer for verification
+    pub witness_points: Vec<WitnessPoint>,
+
+    ///Regions of sparse infill smaller than this area in mm² are left empty
+    pub skip_infill_below_area: f32,
+
+    ///Objects with a first layer smaller than this area in mm² get a brim wide enough to reach it. Not used when a brim width is set
+    pub auto_brim_min_contact_area: OptionalSetting<f32>,
+
+    ///Clamp extrusion widths to between 0.6 and 2 times the nozzle diameter instead of only warning
+    pub clamp_extrusion_width: bool,
+
+    ///Print the islands of a layer one after another to reduce the number of retractions
+    pub minimize_retractions: bool,
+
+    ///Longest unsupported span to bridge, longer spans are supported from below instead
+    pub max_bridge_length: OptionalSetting<f32>,
+
+    ///Number of layers over which the extrusion width tapers from the first layer multiplier to the regular width
+    pub width_ramp_layers: usize,
+
+    ///Multiplier of the extrusion width on the first layer of the width ramp
+    pub first_layer_width_multiplier: f32,
+
+    ///Only print a single outer perimeter on every layer, skipping infill, top/bottom layers and support
+    pub outline_only: bool,
+
+    ///Resume a failed print from this layer, only the layers from it on are extruded
+    pub resume_from_layer: OptionalSetting<usize>,
+
+    ///Warn about layers with more retractions than this
+    pub max_retractions_per_layer: OptionalSetting<usize>,
+
+    ///Firmware dialect the gcode is written for
+    pub gcode_flavor: GcodeFlavor,
+
+    ///Pressure advance factor set at the start of the print
+    pub pressure_advance: OptionalSetting<f32>,
+
+    ///Number of layers below top surfaces whose infill density steps up from sparse to solid
+    pub top_transition_layers: usize,
+
+    ///What to do with placeholders in the custom gcode that don't name a setting
+    pub unknown_placeholder_policy: UnknownPlaceholderPolicy,
+
+    ///Remove moves that don't go anywhere, keeping the material of removed extrusions
+    pub remove_zero_length_moves: bool,
+
+    ///Height map of an uneven bed that the first layers follow
+    pub bed_mesh: OptionalSetting<HeightMap>,
+
+    ///Number of layers over which the bed mesh fades out to flat
+    pub bed_mesh_fade_layers: usize,
+
+    ///Width in mm added to every other wall, swapping walls each layer so the walls interlock
+    pub alternating_wall_width: OptionalSetting<f32>,
+
+    ///Fill the sparse infill solid on every layer that is a multiple of this, forming internal shells
+    pub solid_infill_every_layers: OptionalSetting<usize>,
+
+    ///Vary the layer height with the slope of the surface instead of using the fixed layer height
+    pub adaptive_layer_height: OptionalSetting<AdaptiveLayerSettings>,
+
+    ///Where the seam of the walls is placed when no seam hint is close
+    pub seam_position: SeamPosition,
+
+    ///Filament loaded in each extruder, objects choose theirs by index. Empty for a single extruder using the filament settings
+    pub extruders: Vec<FilamentSettings>,
+
+    ///Length in mm to retract the filament before switching to another extruder
+    pub toolchange_retract_length: f32,
+
+    ///Fit runs of short extrusions into G2/G3 arcs that stray at most this far in mm from the path
+    pub arc_tolerance: OptionalSetting<f32>,
+
+    ///Cool the nozzle to a standby temperature during long pauses, reheating before printing again
+    pub standby_temp: OptionalSetting<StandbyTempSettings>,
+
+    ///Smooth top surfaces by re-tracing them with the nozzle while extruding very little
+    pub ironing: OptionalSetting<IroningSettings>,
+
+    ///Print the inner walls only on layers that are a multiple of this, infill takes their place
+    ///on the other layers. The outer wall prints on every layer
+    pub inner_walls_every_layers: usize,
+
+    ///How the nozzle travels to the first chain of each layer
+    pub layer_change_approach: ApproachMode,
+
+    ///Run solid infill along the longest edge of each region's minimum bounding rectangle
+    pub align_solid_to_longest_edge: bool,
+
+    ///Jitter the outer walls perpendicular to the path for a textured surface
+    pub fuzzy_skin: OptionalSetting<FuzzySkinSettings>,
+
+    ///Slicing fails instead of creating more layers than this, guarding against tiny layer heights
+    pub max_layers: usize,
+
+    ///Stop extruding just before the end of each path and let the pressure in the nozzle finish it
+    pub coasting: OptionalSetting<CoastingSettings>,
+
+    ///Settings for a tower printed first on every layer to prime the nozzle
+    pub wipe_tower: OptionalSetting<WipeTowerSettings>,
+
+    ///Print features too thin for a wall on each side as a single wall of varying width
+    pub variable_width_walls: bool,
+
+    ///Prime line printed next to each object before it starts when printing one object after another
+    pub object_prime: OptionalSetting<ObjectPrimeSettings>,
+
+    ///Flow, fan and speed overrides for bridges
+    pub bridge: OptionalSetting<BridgeSettings>,
+
+    ///Number of perimeters on the top and bottom solid layers, in place of the number of perimeters
+    pub top_bottom_perimeters: OptionalSetting<usize>,
+
+    ///Print only the outer wall, rising continuously instead of stepping at each layer change
+    pub spiralize: bool,
+
+    ///Angle of the infill on the first layer in degrees, instead of the angle the layer would get
+    pub first_layer_infill_angle: OptionalSetting<f32>,
+
+    ///Travels longer than this in mm cool the nozzle to the standby temperature of the standby
+    ///temp settings, reheating in time to print again at the end of the travel
+    pub standby_travel_threshold: OptionalSetting<f32>,
+
+    ///Surface of the build plate, adjusts the first layer
+    pub bed_type: BedType,
+
+    ///Radius in mm around the nozzle that must be clear of printed objects when printing by object
+    pub sequential_clearance: f32,
+
+    ///Gcode written after the layer change of every layer in the range, for example M600 to
+    ///change filament
+    pub layer_gcode_hooks: Vec<(LayerRange, String)>,
+
     ///Settings for specific layers
     pub layer_settings: Vec<(LayerRange, PartialLayerSettings)>,
 }
@@ -219,6 +441,7 @@ impl Default for Settings {
                 travel: 0.4,
                 bridge: 0.4,
                 support: 0.4,
+                ironing: 0.4,
                 exterior_surface_perimeter: 0.4,
                 fiber_factor: 0.5,
             },
@@ -243,6 +466,7 @@ impl Default for Settings {
                 travel: 180.0,
                 bridge: 30.0,
                 support: 50.0,
+                ironing: 15.0,
                 exterior_surface_perimeter: 40.0,
                 fiber_factor: 0.5,
             },
@@ -256,6 +480,7 @@ impl Default for Settings {
                 travel: 1000.0,
                 bridge: 1000.0,
                 support: 1000.0,
+                ironing: 1000.0,
                 exterior_surface_perimeter: 800.0,
                 fiber_factor: 0.5,
             },
@@ -268,6 +493,7 @@ impl Default for Settings {
             inner_perimeters_first: true,
             minimum_retract_distance: 1.0,
             infill_perimeter_overlap_percentage: 0.25,
+            solid_infill_overlap_percentage: 0.25,
             solid_infill_type: SolidInfillTypes::Rectilinear,
             partial_infill_type: PartialInfillTypes::Linear,
             starting_instructions: "G90 ; use absolute coordinates \n\
@@ -296,6 +522,12 @@ impl Default for Settings {
             before_layer_change_instructions: "".to_string(),
             after_layer_change_instructions: "".to_string(),
             object_change_instructions: "".to_string(),
+            resume_instructions: "M140 S[Bed Temperature] ; set bed temp\n\
+                                M104 S[Extruder Temperature] ; set extruder temp\n\
+                                M190 S[Bed Temperature] ; wait for bed temp\n\
+                                M109 S[Extruder Temperature] ; wait for extruder temp\n\
+                                G28 X Y ; home X and Y only to keep clear of the part\n"
+                .to_string(),
             max_acceleration_x: 1000.0,
             max_acceleration_y: 1000.0,
             max_acceleration_z: 1000.0,
@@ -321,6 +553,7 @@ impl Default for Settings {
                         travel: 5.0,
                         bridge: 20.0,
                         support: 20.0,
+                        ironing: 20.0,
                         exterior_surface_perimeter: 20.0,
                         fiber_factor: 0.5,
                     }),
@@ -339,23 +572,150 @@ impl Default for Settings {
             maximum_feedrate_z: 12.0,
             maximum_feedrate_e: 120.0,
             retraction_wipe: OptionalSetting::default(),
+            bridge_angle_override: OptionalSetting::default(),
+            bridge_density: 1.0,
+            ooze_shield: OptionalSetting::default(),
+            seam_hints: vec![],
+            gap_fill: OptionalSetting::default(),
+            final_retract_no_lift: false,
+            bed_transform: BedTransform::default(),
+            overlapping_objects: OverlapPolicy::Warn,
+            print_sequence: PrintSequence::ByLayer,
+            sequential_cooling: OptionalSetting::default(),
+            thin_part_solid_policy: ThinPartSolidPolicy::BothSolid,
+            witness_points: vec![],
+            skip_infill_below_area: 0.0,
+            auto_brim_min_contact_area: OptionalSetting::default(),
+            clamp_extrusion_width: false,
+            minimize_retractions: false,
+            max_bridge_length: OptionalSetting {
+                setting: 20.0,
+                enabled: false,
+            },
+            width_ramp_layers: 0,
+            first_layer_width_multiplier: 1.0,
+            outline_only: false,
+            resume_from_layer: OptionalSetting::default(),
+            max_retractions_per_layer: OptionalSetting {
+                setting: 50,
+                enabled: false,
+            },
+            gcode_flavor: GcodeFlavor::Marlin,
+            pressure_advance: OptionalSetting {
+                setting: 0.05,
+                enabled: false,
+            },
+            top_transition_layers: 0,
+            unknown_placeholder_policy: UnknownPlaceholderPolicy::KeepLiteral,
+            remove_zero_length_moves: true,
+            bed_mesh: OptionalSetting::default(),
+            bed_mesh_fade_layers: 3,
+            alternating_wall_width: OptionalSetting {
+                setting: 0.05,
+                enabled: false,
+            },
+            solid_infill_every_layers: OptionalSetting {
+                setting: 10,
+                enabled: false,
+            },
+            adaptive_layer_height: OptionalSetting::default(),
+            seam_position: SeamPosition::LargestTurn,
+            extruders: vec![],
+            toolchange_retract_length: 10.0,
+            arc_tolerance: OptionalSetting {
+                setting: 0.02,
+                enabled: false,
+            },
+            standby_temp: OptionalSetting::default(),
+            ironing: OptionalSetting::default(),
+            inner_walls_every_layers: 1,
+            layer_change_approach: ApproachMode::Direct,
+            align_solid_to_longest_edge: false,
+            fuzzy_skin: OptionalSetting::default(),
+            max_layers: 100_000,
+            coasting: OptionalSetting::default(),
+            wipe_tower: OptionalSetting::default(),
+            variable_width_walls: false,
+            object_prime: OptionalSetting::default(),
+            bridge: OptionalSetting::default(),
+            top_bottom_perimeters: OptionalSetting {
+                setting: 1,
+                enabled: false,
+            },
+            spiralize: false,
+            first_layer_infill_angle: OptionalSetting::default(),
+            standby_travel_threshold: OptionalSetting {
+                setting: 100.0,
+                enabled: false,
+            },
+            bed_type: BedType::SmoothPei,
+            sequential_clearance: 20.0,
+            layer_gcode_hooks: vec![],
         }
     }
 }
 
 impl Settings {
-    ///Get the layer settings for a specific layer index and height
-    pub fn get_layer_settings(&self, layer: usize, height: f32) -> LayerSettings {
-        let changes = self
-            .layer_settings
+    fn layer_changes(&self, layer: usize, height: f32) -> PartialLayerSettings {
+        self.layer_settings
             .iter()
-            .filter(|(layer_range, _)| match layer_range {
-                LayerRange::LayerCountRange { end, start } => *start <= layer && layer <= *end,
-                LayerRange::HeightRange { end, start } => *start <= height && height <= *end,
-                LayerRange::SingleLayer(filter_layer) => *filter_layer == layer,
-            })
+            .filter(|(layer_range, _)| layer_range.contains(layer, height))
             .map(|(_lr, pls)| pls)
-            .fold(PartialLayerSettings::default(), |a, b| a.combine(b));
+            .fold(PartialLayerSettings::default(), |a, b| a.combine(b))
+    }
+
+    ///The extrusion widths of a layer before they are clamped
+    fn unclamped_extrusion_width(
+        &self,
+        layer: usize,
+        changes: &PartialLayerSettings,
+    ) -> MovementParameter {
+        let mut extrusion_width = changes
+            .extrusion_width
+            .clone()
+            .unwrap_or_else(|| self.extrusion_width.clone());
+
+        //Linearly taper from the first layer multiplier to the regular width over the ramp
+        if layer < self.width_ramp_layers {
+            let progress = layer as f32 / self.width_ramp_layers as f32;
+            extrusion_width.scale(
+                self.first_layer_width_multiplier
+                    + (1.0 - self.first_layer_width_multiplier) * progress,
+            );
+        }
+
+        extrusion_width
+    }
+
+    ///The range extrusion widths are clamped to when `clamp_extrusion_width` is set
+    pub fn extrusion_width_range(&self) -> (f32, f32) {
+        (self.nozzle_diameter * 0.6, self.nozzle_diameter * 2.0)
+    }
+
+    ///Whether `get_layer_settings` clamps the extrusion widths of the layer
+    pub fn extrusion_width_clamped(&self, layer: usize, height: f32) -> bool {
+        let (min, max) = self.extrusion_width_range();
+
+        self.clamp_extrusion_width
+            && self
+                .unclamped_extrusion_width(layer, &self.layer_changes(layer, height))
+                .clamp(min, max)
+    }
+
+    ///Get the layer settings for a specific layer index and height
+    pub fn get_layer_settings(&self, layer: usize, height: f32) -> LayerSettings {
+        let changes = self.layer_changes(layer, height);
+
+        //The bed type only nudges the first layer, values set for the layer itself win
+        let first_layer_bed = (layer == 0).then_some(self.bed_type);
+
+        let mut extrusion_width = self.unclamped_extrusion_width(layer, &changes);
+
+        //Clamped layers are reported once per slice, this is called for every layer and frame
+        if self.clamp_extrusion_width {
+            let (min, max) = self.extrusion_width_range();
+            extrusion_width.clamp(min, max);
+        }
 
         LayerSettings {
             layer_height: changes.layer_height.unwrap_or(self.layer_height),
@@ -367,9 +727,7 @@ impl Settings {
             acceleration: changes
                 .acceleration
                 .unwrap_or_else(|| self.acceleration.clone()),
-            extrusion_width: changes
-                .extrusion_width
-                .unwrap_or_else(|| self.extrusion_width.clone()),
+            extrusion_width,
             solid_infill_type: changes.solid_infill_type.unwrap_or(self.solid_infill_type),
             partial_infill_type: changes
                 .partial_infill_type
@@ -378,16 +736,85 @@ impl Settings {
             infill_perimeter_overlap_percentage: changes
                 .infill_perimeter_overlap_percentage
                 .unwrap_or(self.infill_perimeter_overlap_percentage),
+            solid_infill_overlap_percentage: changes
+                .solid_infill_overlap_percentage
+                .unwrap_or(self.solid_infill_overlap_percentage),
             inner_perimeters_first: changes
                 .inner_perimeters_first
                 .unwrap_or(self.inner_perimeters_first),
-            bed_temp: changes.bed_temp.unwrap_or(self.filament.bed_temp),
+            bed_temp: changes.bed_temp.unwrap_or_else(|| {
+                self.filament.bed_temp
+                    + first_layer_bed.map_or(0.0, |bed| bed.first_layer_bed_temp_delta())
+            }),
             extruder_temp: changes.extruder_temp.unwrap_or(self.filament.extruder_temp),
             retraction_wipe: changes
                 .retraction_wipe
                 .unwrap_or(self.retraction_wipe.clone()),
             retraction_length: changes.retraction_length.unwrap_or(self.retract_length),
+            seam_hints: self.seam_hints.clone(),
+            seam_position: self.seam_position,
+            skip_infill_below_area: self.skip_infill_below_area,
+            alternating_wall_width: self.alternating_wall_width,
+            solid_infill_every_layers: self.solid_infill_every_layers,
+            inner_walls_every_layers: self.inner_walls_every_layers,
+            layer_change_approach: self.layer_change_approach,
+            align_solid_to_longest_edge: self.align_solid_to_longest_edge,
+            fuzzy_skin: self.fuzzy_skin.clone(),
+            coasting: self.coasting.clone(),
+            bridge: self.bridge.clone(),
+            fan_speed: changes.fan_speed.unwrap_or_else(|| {
+                if layer < self.fan.disable_fan_for_layers {
+                    0.0
+                } else {
+                    self.fan.fan_speed
+                        * first_layer_bed.map_or(1.0, |bed| bed.first_layer_fan_factor())
+                }
+            }),
+            z_offset: changes
+                .z_offset
+                .unwrap_or_else(|| first_layer_bed.map_or(0.0, |bed| bed.first_layer_z_offset())),
+            infill_angle_offset: 0.0,
+            infill_angle: (layer == 0 && self.first_layer_infill_angle.is_enabled())
+                .then_some(*self.first_layer_infill_angle),
+        }
+    }
+
+    ///The filament loaded in an extruder. Without configured extruders, or for an extruder
+    ///beyond them, this is the filament settings.
+    pub fn extruder_filament(&self, extruder: usize) -> &FilamentSettings {
+        self.extruders.get(extruder).unwrap_or(&self.filament)
+    }
+
+    ///The nozzle temperature of an extruder on a layer. Every extruder heats to the temperature of
+    ///its own filament, moved by as much as the layer moves the filament settings temperature.
+    pub fn extruder_layer_temp(&self, extruder: usize, layer_settings: &LayerSettings) -> f32 {
+        self.extruder_filament(extruder).extruder_temp + layer_settings.extruder_temp
+            - self.filament.extruder_temp
+    }
+
+    ///The temperature the nozzle cools to during a pause of this many milliseconds, if the pause
+    ///is long enough and the standby temperature is below the printing temperature
+    pub fn standby_temperature(&self, msec: u64, nozzle_temp: f32) -> Option<f32> {
+        let standby_temp = &*self.standby_temp;
+
+        (self.standby_temp.is_enabled()
+            && msec as f32 / 1000.0 >= standby_temp.min_idle_time
+            && standby_temp.temperature < nozzle_temp)
+            .then_some(standby_temp.temperature)
+    }
+
+    ///Hash of the settings that change the moves of a slice, leaving out `GCODE_ONLY_SETTINGS`
+    pub fn geometry_hash(&self) -> u64 {
+        let mut value = serde_json::to_value(self).expect("Settings serialize to json");
+        if let Some(settings) = value.as_object_mut() {
+            for name in GCODE_ONLY_SETTINGS {
+                settings.remove(*name);
+            }
         }
+
+        let mut hasher = DefaultHasher::new();
+        value.to_string().hash(&mut hasher);
+        hasher.finish()
     }
 
     ///Validate settings and return any warnings and errors
@@ -422,6 +849,149 @@ impl Settings {
         setting_less_than_zero!(self, minimum_feedrate_travel);
         setting_less_than_zero!(self, minimum_feedrate_print);
         setting_less_than_zero!(self, minimum_retract_distance);
+        setting_less_than_zero!(self, sequential_clearance);
+        setting_less_than_or_equal_to_zero!(self, bridge_density);
+        setting_not_a_percentage!(self, bridge_density);
+        setting_not_a_percentage!(self, infill_perimeter_overlap_percentage);
+        setting_not_a_percentage!(self, solid_infill_overlap_percentage);
+        setting_less_than_or_equal_to_zero!(self, first_layer_width_multiplier);
+
+        let support = &*self.support;
+        setting_less_than_or_equal_to_zero!(support, support_density_bottom);
+        setting_less_than_or_equal_to_zero!(support, support_density_top);
+        setting_less_than_or_equal_to_zero!(support, interface_spacing);
+        setting_less_than_or_equal_to_zero!(support, tree_branch_diameter);
+        setting_less_than_zero!(support, z_gap);
+
+        let fiber = &self.fiber;
+        setting_less_than_zero!(fiber, anchor_length);
+        setting_less_than_zero!(fiber, max_bridge_gap);
+        setting_less_than_zero!(fiber, min_radius);
+
+        if let Some(field) = &self.fiber.orientation_field {
+            setting_less_than_or_equal_to_zero!(field, spacing_x);
+            setting_less_than_or_equal_to_zero!(field, spacing_y);
+            setting_less_than_or_equal_to_zero!(field, spacing_z);
+
+            if let Some(layer) = field.ragged_layer() {
+                return SettingsValidationResult::Error(SlicerErrors::RaggedOrientationField {
+                    layer,
+                });
+            }
+        }
+
+        if self.solid_infill_every_layers.is_enabled() && *self.solid_infill_every_layers == 0 {
+            return SettingsValidationResult::Error(SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "solid_infill_every_layers".to_string(),
+                value: 0.0,
+            });
+        }
+
+        if self.resume_from_layer.is_enabled() && self.print_sequence == PrintSequence::ByObject {
+            return SettingsValidationResult::Error(SlicerErrors::ResumeByObject);
+        }
+
+        if self.top_bottom_perimeters.is_enabled() && *self.top_bottom_perimeters == 0 {
+            return SettingsValidationResult::Error(SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "top_bottom_perimeters".to_string(),
+                value: 0.0,
+            });
+        }
+
+        if self.adaptive_layer_height.is_enabled() {
+            let adaptive_layer_height = &*self.adaptive_layer_height;
+            setting_less_than_or_equal_to_zero!(adaptive_layer_height, min_height);
+            setting_less_than_or_equal_to_zero!(adaptive_layer_height, max_height);
+            setting_not_a_percentage!(adaptive_layer_height, quality_factor);
+        }
+
+        if self.standby_temp.is_enabled() {
+            let standby_temp = &*self.standby_temp;
+            setting_less_than_zero!(standby_temp, temperature);
+            setting_less_than_zero!(standby_temp, min_idle_time);
+            setting_less_than_or_equal_to_zero!(standby_temp, reheat_rate);
+        }
+
+        if self.standby_travel_threshold.is_enabled() && *self.standby_travel_threshold <= 0.0 {
+            return SettingsValidationResult::Error(SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "standby_travel_threshold".to_string(),
+                value: *self.standby_travel_threshold,
+            });
+        }
+
+        if self.ironing.is_enabled() {
+            let ironing = &*self.ironing;
+            setting_less_than_zero!(ironing, flow);
+            setting_less_than_or_equal_to_zero!(ironing, spacing);
+        }
+
+        if self.fuzzy_skin.is_enabled() {
+            let fuzzy_skin = &*self.fuzzy_skin;
+            setting_less_than_zero!(fuzzy_skin, thickness);
+            setting_less_than_or_equal_to_zero!(fuzzy_skin, point_distance);
+        }
+
+        if self.wipe_tower.is_enabled() {
+            let wipe_tower = &*self.wipe_tower;
+            setting_less_than_zero!(wipe_tower, x);
+            setting_less_than_zero!(wipe_tower, y);
+            setting_less_than_or_equal_to_zero!(wipe_tower, width);
+            setting_less_than_zero!(wipe_tower, purge_volume);
+        }
+
+        if self.coasting.is_enabled() {
+            let coasting = &*self.coasting;
+            setting_less_than_zero!(coasting, volume);
+            setting_less_than_zero!(coasting, min_volume_before_coast);
+        }
+
+        if self.object_prime.is_enabled() {
+            let object_prime = &*self.object_prime;
+            setting_less_than_zero!(object_prime, length);
+            setting_less_than_zero!(object_prime, distance);
+
+            if let Some(length) = object_prime
+                .object_lengths
+                .iter()
+                .find(|length| **length < 0.0)
+            {
+                return SettingsValidationResult::Error(SlicerErrors::SettingLessThanZero {
+                    setting: "object_lengths".to_string(),
+                    value: *length,
+                });
+            }
+        }
+
+        if self.bridge.is_enabled() {
+            let bridge = &*self.bridge;
+            setting_less_than_or_equal_to_zero!(bridge, flow_ratio);
+            setting_less_than_zero!(bridge, fan_speed);
+            setting_less_than_or_equal_to_zero!(bridge, speed);
+        }
+
+        //The medial axis of the gaps is traced on a grid a tenth of the gap width wide
+        if self.gap_fill.is_enabled() && *self.gap_fill <= 0.0 {
+            return SettingsValidationResult::Error(SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "gap_fill".to_string(),
+                value: *self.gap_fill,
+            });
+        }
+
+        if self.arc_tolerance.is_enabled() && *self.arc_tolerance <= 0.0 {
+            return SettingsValidationResult::Error(SlicerErrors::SettingLessThanOrEqualToZero {
+                setting: "arc_tolerance".to_string(),
+                value: *self.arc_tolerance,
+            });
+        }
+
+        if self.bed_mesh.is_enabled() {
+            let bed_mesh = &*self.bed_mesh;
+            setting_less_than_or_equal_to_zero!(bed_mesh, spacing_x);
+            setting_less_than_or_equal_to_zero!(bed_mesh, spacing_y);
+        }
+        setting_less_than_zero!(self, toolchange_retract_length);
+        setting_less_than_or_equal_to_zero!(self, inner_walls_every_layers);
+        setting_less_than_or_equal_to_zero!(self, max_layers);
 
         if self.layer_height < self.nozzle_diameter * 0.2 {
             return SettingsValidationResult::Warning(SlicerWarnings::LayerSizeTooLow {
@@ -435,6 +1005,21 @@ impl Settings {
             });
         }
 
+        if self.adaptive_layer_height.is_enabled() {
+            let adaptive = &*self.adaptive_layer_height;
+            if adaptive.min_height < self.nozzle_diameter * 0.2 {
+                return SettingsValidationResult::Warning(SlicerWarnings::LayerSizeTooLow {
+                    layer_height: adaptive.min_height,
+                    nozzle_diameter: self.nozzle_diameter,
+                });
+            } else if adaptive.max_height > self.nozzle_diameter * 0.8 {
+                return SettingsValidationResult::Warning(SlicerWarnings::LayerSizeTooHigh {
+                    layer_height: adaptive.max_height,
+                    nozzle_diameter: self.nozzle_diameter,
+                });
+            }
+        }
+
         let r = check_extrusions(&self.extrusion_width, self.nozzle_diameter);
         match r {
             SettingsValidationResult::NoIssue => {}
@@ -478,6 +1063,8 @@ impl Settings {
             option_setting_less_than_or_equal_to_zero!(pls, layer_height);
             option_setting_less_than_zero!(pls, infill_percentage);
             option_setting_less_than_zero!(pls, retraction_length);
+            option_setting_not_a_percentage!(pls, infill_perimeter_overlap_percentage);
+            option_setting_not_a_percentage!(pls, solid_infill_overlap_percentage);
 
             if let Some(layer_height) = pls.layer_height {
                 if layer_height < self.nozzle_diameter * 0.2 {
@@ -535,7 +1122,7 @@ impl Settings {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct OptionalSetting<T> {
     setting: T,
     enabled: bool,
@@ -574,153 +1161,20 @@ impl<T> OptionalSetting<T> {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct MaskSettings {
     pub epsilon: f32,
     pub wall_seperated: bool,
+
+    ///Rotation in degrees added to the infill angle of every layer of the mask, so its infill
+    ///crosses and interlocks with the infill around it
+    pub infill_angle_offset: f32,
     settings: PartialSettings,
 }
 
 impl MaskSettings {
-    pub fn combine_settings(self, mut settings: Settings) -> Settings {
-        fn set_setting<T>(value: Option<T>, value_mut: &mut T) {
-            if let Some(value) = value {
-                *value_mut = value;
-            }
-        }
-
-        set_setting(self.settings.layer_height, &mut settings.layer_height);
-        set_setting(self.settings.extrusion_width, &mut settings.extrusion_width);
-        set_setting(self.settings.filament, &mut settings.filament);
-        set_setting(self.settings.fiber, &mut settings.fiber);
-        set_setting(self.settings.fan, &mut settings.fan);
-        set_setting(self.settings.skirt, &mut settings.skirt);
-        set_setting(self.settings.support, &mut settings.support);
-        set_setting(self.settings.nozzle_diameter, &mut settings.nozzle_diameter);
-        set_setting(self.settings.retract_length, &mut settings.retract_length);
-        set_setting(self.settings.retract_lift_z, &mut settings.retract_lift_z);
-        set_setting(self.settings.retract_speed, &mut settings.retract_speed);
-        set_setting(self.settings.retraction_wipe, &mut settings.retraction_wipe);
-        set_setting(self.settings.speed, &mut settings.speed);
-        set_setting(self.settings.acceleration, &mut settings.acceleration);
-        set_setting(
-            self.settings.infill_percentage,
-            &mut settings.infill_percentage,
-        );
-        set_setting(
-            self.settings.inner_perimeters_first,
-            &mut settings.inner_perimeters_first,
-        );
-        set_setting(
-            self.settings.number_of_perimeters,
-            &mut settings.number_of_perimeters,
-        );
-        set_setting(self.settings.top_layers, &mut settings.top_layers);
-        set_setting(self.settings.bottom_layers, &mut settings.bottom_layers);
-        set_setting(self.settings.print_x, &mut settings.print_x);
-        set_setting(self.settings.print_y, &mut settings.print_y);
-        set_setting(self.settings.print_z, &mut settings.print_z);
-        set_setting(self.settings.brim_width, &mut settings.brim_width);
-        set_setting(
-            self.settings.layer_shrink_amount,
-            &mut settings.layer_shrink_amount,
-        );
-        set_setting(
-            self.settings.minimum_retract_distance,
-            &mut settings.minimum_retract_distance,
-        );
-        set_setting(
-            self.settings.infill_perimeter_overlap_percentage,
-            &mut settings.infill_perimeter_overlap_percentage,
-        );
-        set_setting(
-            self.settings.solid_infill_type,
-            &mut settings.solid_infill_type,
-        );
-        set_setting(
-            self.settings.partial_infill_type,
-            &mut settings.partial_infill_type,
-        );
-        set_setting(
-            self.settings.starting_instructions,
-            &mut settings.starting_instructions,
-        );
-        set_setting(
-            self.settings.ending_instructions,
-            &mut settings.ending_instructions,
-        );
-        set_setting(
-            self.settings.before_layer_change_instructions,
-            &mut settings.before_layer_change_instructions,
-        );
-        set_setting(
-            self.settings.after_layer_change_instructions,
-            &mut settings.after_layer_change_instructions,
-        );
-        set_setting(
-            self.settings.object_change_instructions,
-            &mut settings.object_change_instructions,
-        );
-        set_setting(
-            self.settings.max_acceleration_x,
-            &mut settings.max_acceleration_x,
-        );
-        set_setting(
-            self.settings.max_acceleration_y,
-            &mut settings.max_acceleration_y,
-        );
-        set_setting(
-            self.settings.max_acceleration_z,
-            &mut settings.max_acceleration_z,
-        );
-        set_setting(
-            self.settings.max_acceleration_e,
-            &mut settings.max_acceleration_e,
-        );
-        set_setting(
-            self.settings.max_acceleration_extruding,
-            &mut settings.max_acceleration_extruding,
-        );
-        set_setting(
-            self.settings.max_acceleration_travel,
-            &mut settings.max_acceleration_travel,
-        );
-        set_setting(
-            self.settings.max_acceleration_retracting,
-            &mut settings.max_acceleration_retracting,
-        );
-        set_setting(self.settings.max_jerk_x, &mut settings.max_jerk_x);
-        set_setting(self.settings.max_jerk_y, &mut settings.max_jerk_y);
-        set_setting(self.settings.max_jerk_z, &mut settings.max_jerk_z);
-        set_setting(self.settings.max_jerk_e, &mut settings.max_jerk_e);
-
-        set_setting(
-            self.settings.minimum_feedrate_print,
-            &mut settings.minimum_feedrate_print,
-        );
-        set_setting(
-            self.settings.minimum_feedrate_travel,
-            &mut settings.minimum_feedrate_travel,
-        );
-        set_setting(
-            self.settings.maximum_feedrate_x,
-            &mut settings.maximum_feedrate_x,
-        );
-        set_setting(
-            self.settings.maximum_feedrate_y,
-            &mut settings.maximum_feedrate_y,
-        );
-        set_setting(
-            self.settings.maximum_feedrate_z,
-            &mut settings.maximum_feedrate_z,
-        );
-        set_setting(
-            self.settings.maximum_feedrate_e,
-            &mut settings.maximum_feedrate_e,
-        );
-        set_setting(self.settings.layer_settings, &mut settings.layer_settings);
-
-        settings
+    pub fn combine_settings(self, settings: Settings) -> Settings {
+        self.settings.override_settings(settings)
     }
 }
 
@@ -767,6 +1221,9 @@ pub struct LayerSettings {
     ///Overlap between infill and interior perimeters
     pub infill_perimeter_overlap_percentage: f32,
 
+    ///Overlap between solid infill and interior perimeters
+    pub solid_infill_overlap_percentage: f32,
+
     ///Controls the order of perimeters
     pub inner_perimeters_first: bool,
 
@@ -781,31 +1238,109 @@ pub struct LayerSettings {
 
     ///Retraction Distance
     pub retraction_length: f32,
-}
 
-///A set of values for different movement types
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct MovementParameter {
-    ///Value for interior (perimeters that are inside the model
-    pub interior_inner_perimeter: f32,
+    ///Painted seam positions
+    pub seam_hints: Vec<SeamHint>,
 
-    /// Value for interior perimeters surface perimeter
-    pub interior_surface_perimeter: f32,
+    ///Where the seam is placed away from the seam hints
+    pub seam_position: SeamPosition,
 
-    ///Value for exterior perimeters that are inside the model
-    pub exterior_inner_perimeter: f32,
+    ///Regions of sparse infill smaller than this area are left empty
+    pub skip_infill_below_area: f32,
 
-    ///Value for exterior surface perimeter
-    pub exterior_surface_perimeter: f32,
+    ///Width added to every other wall
+    pub alternating_wall_width: OptionalSetting<f32>,
 
-    ///Value for solid top infill moves
-    pub solid_top_infill: f32,
+    ///Layers whose index is a multiple of this fill the sparse infill solid
+    pub solid_infill_every_layers: OptionalSetting<usize>,
 
-    ///Value for solid infill moves
-    pub solid_infill: f32,
+    ///Layers whose index is a multiple of this print the inner walls
+    pub inner_walls_every_layers: usize,
 
-    ///Value for pertial infill moves
-    pub infill: f32,
+    ///How the nozzle travels to the first chain of the layer
+    pub layer_change_approach: ApproachMode,
+
+    ///Run solid infill along the longest edge of each region instead of rotating it every layer
+    pub align_solid_to_longest_edge: bool,
+
+    ///Jitter the outer walls perpendicular to the path for a textured surface
+    pub fuzzy_skin: OptionalSetting<FuzzySkinSettings>,
+
+    ///Stop extruding just before the end of each path and let the pressure in the nozzle finish it
+    pub coasting: OptionalSetting<CoastingSettings>,
+
+    ///Flow, fan and speed overrides for bridges
+    pub bridge: OptionalSetting<BridgeSettings>,
+
+    ///Fan speed of the layer outside of bridges
+    pub fan_speed: f32,
+
+    ///Distance in mm the layer is printed above its sliced height, negative is closer to the bed
+    pub z_offset: f32,
+
+    ///Rotation in degrees added to the infill angles of the layer, set by the mask the layer
+    ///belongs to
+    pub infill_angle_offset: f32,
+
+    ///Angle of the infill of the layer in place of the one from the layer progression, only set
+    ///on the first layer
+    pub infill_angle: Option<f32>,
+}
+
+impl LayerSettings {
+    ///Distance between the lines of each direction of the partial infill at the given density
+    pub fn infill_line_spacing(&self, fill_ratio: f32) -> Option<f32> {
+        self.partial_infill_line_spacing(self.partial_infill_type, fill_ratio)
+    }
+
+    ///Distance between the lines of each direction of a partial infill pattern at the given
+    ///density. Patterns with lines in several directions space each direction further apart so
+    ///the pattern as a whole still has the requested density. For the gyroid this is the period of
+    ///the surface. None for patterns that are not made of evenly spaced lines.
+    pub fn partial_infill_line_spacing(
+        &self,
+        pattern: PartialInfillTypes,
+        fill_ratio: f32,
+    ) -> Option<f32> {
+        let directions = match pattern {
+            PartialInfillTypes::Linear => 1.0,
+            PartialInfillTypes::Rectilinear => 2.0,
+            //The adaptive cubic is spaced like the cubic in its finest cells
+            PartialInfillTypes::Triangle
+            | PartialInfillTypes::Cubic
+            | PartialInfillTypes::AdaptiveCubic => 3.0,
+            //A layer of the gyroid has on average 2.48 periods of contour per square period
+            PartialInfillTypes::Gyroid => 2.48,
+            PartialInfillTypes::Lightning | PartialInfillTypes::Skeleton(_) => return None,
+        };
+
+        Some(directions * self.extrusion_width.infill / fill_ratio)
+    }
+}
+
+///A set of values for different movement types
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MovementParameter {
+    ///Value for interior (perimeters that are inside the model
+    pub interior_inner_perimeter: f32,
+
+    /// Value for interior perimeters surface perimeter
+    pub interior_surface_perimeter: f32,
+
+    ///Value for exterior perimeters that are inside the model
+    pub exterior_inner_perimeter: f32,
+
+    ///Value for exterior surface perimeter
+    pub exterior_surface_perimeter: f32,
+
+    ///Value for solid top infill moves
+    pub solid_top_infill: f32,
+
+    ///Value for solid infill moves
+    pub solid_infill: f32,
+
+    ///Value for pertial infill moves
+    pub infill: f32,
 
     ///Value for travel moves
     pub travel: f32,
@@ -816,6 +1351,9 @@ pub struct MovementParameter {
     ///Value for support structures
     pub support: f32,
 
+    ///Value for ironing top surfaces
+    pub ironing: f32,
+
     pub fiber_factor: f32,
 }
 
@@ -831,6 +1369,7 @@ impl Default for MovementParameter {
             travel: 1000.0,
             bridge: 1000.0,
             support: 1000.0,
+            ironing: 1000.0,
             exterior_surface_perimeter: 800.0,
             fiber_factor: 0.5,
         }
@@ -851,6 +1390,59 @@ impl MovementParameter {
         }
     }
 
+    ///Clamp every extrusion value into the range `[min, max]`, returning whether any value
+    ///changed. Travel doesn't extrude and `fiber_factor` is a multiplier rather than a value, both
+    ///are left untouched.
+    pub fn clamp(&mut self, min: f32, max: f32) -> bool {
+        let mut changed = false;
+
+        for value in self.extrusion_values_mut() {
+            let clamped = value.clamp(min, max);
+            changed |= clamped != *value;
+            *value = clamped;
+        }
+
+        changed
+    }
+
+    ///Multiply every value by `factor`, leaving `fiber_factor` untouched
+    pub fn scale(&mut self, factor: f32) {
+        for value in self.values_mut() {
+            *value *= factor;
+        }
+    }
+
+    fn extrusion_values_mut(&mut self) -> [&mut f32; 10] {
+        [
+            &mut self.interior_inner_perimeter,
+            &mut self.interior_surface_perimeter,
+            &mut self.exterior_inner_perimeter,
+            &mut self.exterior_surface_perimeter,
+            &mut self.solid_top_infill,
+            &mut self.solid_infill,
+            &mut self.infill,
+            &mut self.bridge,
+            &mut self.support,
+            &mut self.ironing,
+        ]
+    }
+
+    fn values_mut(&mut self) -> [&mut f32; 11] {
+        [
+            &mut self.interior_inner_perimeter,
+            &mut self.interior_surface_perimeter,
+            &mut self.exterior_inner_perimeter,
+            &mut self.exterior_surface_perimeter,
+            &mut self.solid_top_infill,
+            &mut self.solid_infill,
+            &mut self.infill,
+            &mut self.travel,
+            &mut self.bridge,
+            &mut self.support,
+            &mut self.ironing,
+        ]
+    }
+
     fn get_value_for_movement_print_type(&self, move_type: &TraceType) -> f32 {
         match move_type {
             TraceType::TopSolidInfill => self.solid_top_infill,
@@ -862,12 +1454,13 @@ impl MovementParameter {
             TraceType::InteriorWallInner => self.interior_inner_perimeter,
             TraceType::Bridging => self.bridge,
             TraceType::Support => self.support,
+            TraceType::Ironing => self.ironing,
         }
     }
 }
 
 ///Settings for a filament
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FilamentSettings {
     ///Diameter of this filament in mm
     pub diameter: f32,
@@ -886,7 +1479,7 @@ pub struct FilamentSettings {
 }
 
 ///Settigns for the fans
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FanSettings {
     ///The default fan speed
     pub fan_speed: f32,
@@ -925,12 +1518,15 @@ impl Default for FanSettings {
 }
 
 pub mod fiber {
-    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    use glam::{vec2, Vec2};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use strum_macros::EnumIter;
 
     use crate::PartialInfillTypes;
 
-    use super::OptionalSetting;
+    use super::{grid_cell, OptionalSetting};
 
     use nom::{
         character::complete::{char, digit1},
@@ -946,7 +1542,7 @@ pub mod fiber {
         Full,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
     pub struct WallPattern {
         pub pattern: WallPatternType,
         // pub only_on_outer: bool,
@@ -994,7 +1590,7 @@ pub mod fiber {
         pub steps: usize,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
     pub struct Infill {
         pub partial_infill_type: PartialInfillTypes,
         pub infill_percentage: f32,
@@ -1004,10 +1600,10 @@ pub mod fiber {
         pub air_space: bool,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
     pub struct ContinuousFiberSettings {}
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
     pub struct FiberSettings {
         pub diameter: f32,
         pub cut_before: f32,
@@ -1024,94 +1620,740 @@ pub mod fiber {
         pub speed_factor: f32,
         pub acceleration_factor: f32,
         pub jerk_factor: f32,
+
+        ///Reverse the sweep direction of fiber infill on every other layer to balance residual stresses
+        pub alternate_direction: bool,
+
+        ///Distance the ends of fiber infill extend into fiber walls so the two bond
+        pub wall_infill_overlap: f32,
+
+        ///Only lift the nozzle when travelling to or from fiber, the filament is not retracted as
+        ///pulling it back would drag the fiber with it
+        pub suppress_retraction: bool,
+
+        ///Density of the fiber in grams per cm^3
+        pub density: f32,
+
+        ///Length in mm of the start of a fiber chain that is run back and forth to anchor the
+        ///fiber before the main run
+        pub anchor_length: f32,
+
+        ///Number of times the anchor is run back and forth, 0 to not anchor the fiber
+        pub anchor_loops: usize,
+
+        ///Travels shorter than this in mm between two fiber runs going the same way are bridged
+        ///by the fiber, so the runs are cut as one chain
+        pub max_bridge_gap: f32,
+
+        ///Smallest radius in mm the fiber can turn around, moves at tighter turns are printed
+        ///without fiber. 0 to allow any turn within the max angle.
+        pub min_radius: f32,
+
+        ///Directions the fiber infill follows in place of straight lines. Shared, as the
+        ///settings of every layer hold a copy of the fiber settings.
+        #[serde(
+            default,
+            alias = "orientation_map",
+            skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_field",
+            deserialize_with = "deserialize_field"
+        )]
+        pub orientation_field: Option<Arc<OrientationField>>,
+    }
+
+    fn serialize_field<S: Serializer>(
+        field: &Option<Arc<OrientationField>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        field.as_deref().serialize(serializer)
+    }
+
+    fn deserialize_field<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Arc<OrientationField>>, D::Error> {
+        Ok(Option::<OrientationField>::deserialize(deserializer)?.map(Arc::new))
+    }
+
+    ///Fiber directions on a regular grid in the coordinates the objects are sliced in, for
+    ///example the principal stress directions from a simulation
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    #[serde(from = "OrientationGrid")]
+    pub struct OrientationField {
+        ///X position in mm of the first grid point
+        pub origin_x: f32,
+
+        ///Y position in mm of the first grid point
+        pub origin_y: f32,
+
+        ///Z position in mm of the first grid point
+        pub origin_z: f32,
+
+        ///Distance in mm between grid points along x
+        pub spacing_x: f32,
+
+        ///Distance in mm between grid points along y
+        pub spacing_y: f32,
+
+        ///Distance in mm between grid points along z
+        pub spacing_z: f32,
+
+        ///Angle in degrees from the x axis of the fiber at each grid point, one grid of rows for
+        ///each z and one row for each y
+        angles: Vec<Vec<Vec<f32>>>,
+
+        ///Fewest rows of any layer, counted once when the field is built
+        #[serde(skip)]
+        rows: usize,
+
+        ///Fewest angles of any row, counted once when the field is built
+        #[serde(skip)]
+        columns: usize,
+    }
+
+    ///The saved form of an [`OrientationField`], the grid is counted when it's loaded
+    #[derive(Deserialize)]
+    struct OrientationGrid {
+        origin_x: f32,
+        origin_y: f32,
+        origin_z: f32,
+        spacing_x: f32,
+        spacing_y: f32,
+        spacing_z: f32,
+        angles: Vec<Vec<Vec<f32>>>,
+    }
+
+    impl From<OrientationGrid> for OrientationField {
+        fn from(grid: OrientationGrid) -> Self {
+            OrientationField::new(
+                [grid.origin_x, grid.origin_y, grid.origin_z],
+                [grid.spacing_x, grid.spacing_y, grid.spacing_z],
+                grid.angles,
+            )
+        }
+    }
+
+    impl OrientationField {
+        ///A field with its first grid point at `origin` and `spacing` mm between grid points
+        ///along x, y and z. `angles` holds one grid of rows for each z and one row for each y.
+        pub fn new(origin: [f32; 3], spacing: [f32; 3], angles: Vec<Vec<Vec<f32>>>) -> Self {
+            let rows = angles.iter().map(|layer| layer.len()).min().unwrap_or(0);
+            let columns = angles
+                .iter()
+                .flatten()
+                .map(|row| row.len())
+                .min()
+                .unwrap_or(0);
+
+            OrientationField {
+                origin_x: origin[0],
+                origin_y: origin[1],
+                origin_z: origin[2],
+                spacing_x: spacing[0],
+                spacing_y: spacing[1],
+                spacing_z: spacing[2],
+                angles,
+                rows,
+                columns,
+            }
+        }
+
+        ///Angles in degrees at each grid point, one grid of rows for each z and one row for
+        ///each y
+        pub fn angles(&self) -> &[Vec<Vec<f32>>] {
+            &self.angles
+        }
+
+        ///Whether the grid holds no angles, so there is no direction anywhere
+        pub fn is_empty(&self) -> bool {
+            self.rows == 0 || self.columns == 0
+        }
+
+        ///Index of the first layer whose rows don't all hold as many angles as the rows of the
+        ///first layer, or that holds a different number of rows. None if the grid is regular.
+        pub fn ragged_layer(&self) -> Option<usize> {
+            let first = self.angles.first()?;
+            let columns = first.first().map(|row| row.len()).unwrap_or(0);
+
+            self.angles.iter().position(|layer| {
+                layer.len() != first.len() || layer.iter().any(|row| row.len() != columns)
+            })
+        }
+
+        ///A flat field from the pixels of a grayscale image `width` pixels wide, where black is 0°
+        ///and white is 180°. The image lies on the bed with its bottom left corner at the origin
+        ///and `pixel_size` mm between pixels, the same angles are used at every height.
+        pub fn from_angle_image(width: usize, pixels: &[u8], pixel_size: f32) -> Self {
+            let rows = pixels
+                .chunks_exact(width.max(1))
+                .rev()
+                .map(|row| {
+                    row.iter()
+                        .map(|value| *value as f32 / 255.0 * 180.0)
+                        .collect()
+                })
+                .collect();
+
+            OrientationField::new([0.0, 0.0, 0.0], [pixel_size, pixel_size, 1.0], vec![rows])
+        }
+
+        ///Unit direction of the fiber at a point, interpolated between the grid points. Points
+        ///outside the grid use the direction at the nearest edge. The direction of a fiber has no
+        ///sign, so a direction and its opposite are the same. None for an empty grid.
+        pub fn direction_at(&self, x: f32, y: f32, z: f32) -> Option<Vec2> {
+            let layers = self.angles.len();
+            if self.is_empty() {
+                return None;
+            }
+
+            let (x0, x1, tx) = grid_cell(x, self.origin_x, self.spacing_x, self.columns);
+            let (y0, y1, ty) = grid_cell(y, self.origin_y, self.spacing_y, self.rows);
+            let (z0, z1, tz) = grid_cell(z, self.origin_z, self.spacing_z, layers);
+
+            //Doubling the angles maps opposite directions onto each other, so 170° and 10°
+            //average to 0° and not to 90°
+            let doubled = |layer: usize, row: usize, column: usize| {
+                let angle = (self.angles[layer][row][column] * 2.0).to_radians();
+                vec2(angle.cos(), angle.sin())
+            };
+            let row = |layer: usize, row: usize| {
+                doubled(layer, row, x0) * (1.0 - tx) + doubled(layer, row, x1) * tx
+            };
+            let layer = |layer: usize| row(layer, y0) * (1.0 - ty) + row(layer, y1) * ty;
+            let average = layer(z0) * (1.0 - tz) + layer(z1) * tz;
+
+            let angle = average.y.atan2(average.x) / 2.0;
+            Some(vec2(angle.cos(), angle.sin()))
+        }
+    }
+
+    impl Default for FiberSettings {
+        fn default() -> Self {
+            FiberSettings {
+                diameter: 0.15,
+                cut_before: 20.0,
+                min_length: 25.0,
+                max_angle: 45.0,
+
+                continuous: OptionalSetting {
+                    setting: ContinuousFiberSettings {},
+                    enabled: false,
+                },
+
+                wall_pattern: OptionalSetting {
+                    setting: WallPattern {
+                        pattern: WallPatternType::Alternating,
+                        alternating_layer_width: 1,
+                        alternating_layer_spacing: 0,
+                        alternating_wall_width: 1,
+                        alternating_wall_spacing: 1,
+                        alternating_step: 1,
+                        wall_ranges: "".to_string(),
+                    },
+                    enabled: true,
+                },
+
+                infill: OptionalSetting {
+                    setting: Infill {
+                        partial_infill_type: PartialInfillTypes::Linear,
+                        infill_percentage: 0.2,
+                        width: 1,
+                        spacing: 1,
+                        air_space: false,
+                        solid_infill: false,
+                    },
+                    enabled: true,
+                },
+
+                speed_factor: 1.4,
+                acceleration_factor: 1.0,
+                jerk_factor: 1.0,
+                alternate_direction: false,
+                wall_infill_overlap: 0.0,
+                suppress_retraction: true,
+                density: 1.8,
+                anchor_length: 3.0,
+                anchor_loops: 0,
+                max_bridge_gap: 0.0,
+                min_radius: 0.0,
+                orientation_field: None,
+            }
+        }
+    }
+}
+
+///Support settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SupportSettings {
+    ///Angle to start production supports in degrees
+    pub max_overhang_angle: f32,
+
+    ///Spacing between the ribs of support
+    pub support_spacing: f32,
+
+    ///Density relative to the support spacing at the bottom of a support column
+    pub support_density_bottom: f32,
+
+    ///Density relative to the support spacing at the top of a support column
+    pub support_density_top: f32,
+
+    ///Number of support layers below the model filled with the interface pattern
+    pub interface_layers: usize,
+
+    ///Pattern of the interface layers
+    pub interface_pattern: SupportInterfacePattern,
+
+    ///Spacing between the lines of the interface layers
+    pub interface_spacing: f32,
+
+    ///Whether the support is built from columns or branches
+    pub style: SupportStyle,
+
+    ///Diameter of a single branch of tree support, branches thicken as they merge
+    pub tree_branch_diameter: f32,
+
+    ///Vertical gap between the top of the support and the model resting on it
+    pub z_gap: f32,
+}
+
+impl Default for SupportSettings {
+    fn default() -> Self {
+        SupportSettings {
+            max_overhang_angle: 45.0,
+            support_spacing: 2.0,
+            support_density_bottom: 1.0,
+            support_density_top: 1.0,
+            interface_layers: 0,
+            interface_pattern: SupportInterfacePattern::Grid,
+            interface_spacing: 0.8,
+            style: SupportStyle::Grid,
+            tree_branch_diameter: 3.0,
+            z_gap: 0.2,
+        }
+    }
+}
+
+///The Settings for Skirt generation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkirtSettings {
+    ///the number of layer to generate the skirt
+    pub layers: usize,
+
+    ///Distance from the models to place the skirt
+    pub distance: f32,
+}
+
+impl Default for SkirtSettings {
+    fn default() -> Self {
+        SkirtSettings {
+            layers: 1,
+            distance: 10.0,
+        }
+    }
+}
+
+///Limits for layer heights adapted to the slope of the surface
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveLayerSettings {
+    ///Thinnest layer in mm, used on nearly flat surfaces
+    pub min_height: f32,
+
+    ///Thickest layer in mm, used on vertical walls
+    pub max_height: f32,
+
+    ///Between 0 and 1, higher values use thinner layers on sloped surfaces
+    pub quality_factor: f32,
+}
+
+impl Default for AdaptiveLayerSettings {
+    fn default() -> Self {
+        AdaptiveLayerSettings {
+            min_height: 0.2,
+            max_height: 0.3,
+            quality_factor: 0.5,
+        }
+    }
+}
+
+///A painted seam position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeamHint {
+    ///X position of the hint in mm
+    pub x: f32,
+
+    ///Y position of the hint in mm
+    pub y: f32,
+
+    ///Maximum distance from the hint to a wall vertex for the hint to apply
+    pub radius: f32,
+}
+
+///A location where the print pauses and shows a message so it can be verified
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WitnessPoint {
+    ///X position of the point in mm
+    pub x: f32,
+
+    ///Y position of the point in mm
+    pub y: f32,
+
+    ///Z position of the point in mm, matched to the closest layer
+    pub z: f32,
+
+    ///Message to display on the printer
+    pub message: String,
+
+    ///Time to pause in milliseconds
+    pub dwell: u64,
+}
+
+///Transform from slicer coordinates to printer coordinates, applied around the bed center
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BedTransform {
+    ///Mirror the X axis
+    pub flip_x: bool,
+
+    ///Mirror the Y axis
+    pub flip_y: bool,
+
+    ///Number of 90 degree counter clockwise rotations applied after flipping
+    pub rotate_90_steps: usize,
+
+    ///Offset in mm applied after flipping and rotating
+    pub offset: (f32, f32),
+}
+
+impl BedTransform {
+    ///Transform a point from slicer space into printer space
+    pub fn apply(&self, point: Vec2, bed_center: Vec2) -> Vec2 {
+        self.apply_vector(point - bed_center) + bed_center + Vec2::from(self.offset)
+    }
+
+    ///Transform a relative vector, such as an arc center offset, ignoring the offset
+    pub fn apply_vector(&self, mut vector: Vec2) -> Vec2 {
+        if self.flip_x {
+            vector.x = -vector.x;
+        }
+        if self.flip_y {
+            vector.y = -vector.y;
+        }
+        for _ in 0..self.rotate_90_steps % 4 {
+            vector = vector.perp();
+        }
+        vector
+    }
+
+    ///Transform a point from printer space back into slicer space
+    pub fn invert(&self, point: Vec2, bed_center: Vec2) -> Vec2 {
+        let mut vector = point - bed_center - Vec2::from(self.offset);
+        for _ in 0..self.rotate_90_steps % 4 {
+            vector = -vector.perp();
+        }
+        if self.flip_y {
+            vector.y = -vector.y;
+        }
+        if self.flip_x {
+            vector.x = -vector.x;
+        }
+        vector + bed_center
+    }
+
+    ///Whether the transform mirrors the bed, which reverses the direction of arcs
+    pub fn is_mirrored(&self) -> bool {
+        self.flip_x != self.flip_y
+    }
+
+    ///The point to transform around and the shift applied afterwards for a print covering `min`
+    ///to `max` on a bed of the size. A quarter turn around the center of a bed that isn't square
+    ///can move the print off the bed, so the print turns around its own center instead and is
+    ///moved back onto the bed where it overhangs.
+    pub fn placement(&self, min: Vec2, max: Vec2, bed: Vec2) -> (Vec2, Vec2) {
+        if self.rotate_90_steps % 2 == 0 || (bed.x - bed.y).abs() <= f32::EPSILON {
+            return (bed / 2.0, Vec2::ZERO);
+        }
+
+        let center = (min + max) / 2.0;
+        let half = self.apply_vector((max - min) / 2.0).abs();
+        let shift = |center: f32, half: f32, size: f32| {
+            if center - half < 0.0 {
+                half - center
+            } else if center + half > size {
+                size - center - half
+            } else {
+                0.0
+            }
+        };
+
+        (
+            center,
+            Vec2::new(
+                shift(center.x, half.x, bed.x),
+                shift(center.y, half.y, bed.y),
+            ),
+        )
+    }
+}
+
+///Parking and waiting for layers that print too quickly when objects are printed one at a time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SequentialCoolingSettings {
+    ///X position in mm to park the head while a layer cools
+    pub park_x: f32,
+
+    ///Y position in mm to park the head while a layer cools
+    pub park_y: f32,
+
+    ///Minimum distance in mm from the park position to any object, otherwise the furthest bed
+    ///corner is used
+    pub clearance: f32,
+}
+
+impl Default for SequentialCoolingSettings {
+    fn default() -> Self {
+        SequentialCoolingSettings {
+            park_x: 0.0,
+            park_y: 0.0,
+            clearance: 10.0,
+        }
+    }
+}
+
+///A line primed next to an object before it starts, so the nozzle isn't empty after the travel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ObjectPrimeSettings {
+    ///Length in mm of the prime line
+    pub length: f32,
+
+    ///Distance in mm between the prime line and the object
+    pub distance: f32,
+
+    ///Length in mm of the prime line of each object by index, objects without an entry use the
+    ///length. A length of 0 skips the prime for that object
+    pub object_lengths: Vec<f32>,
+}
+
+impl Default for ObjectPrimeSettings {
+    fn default() -> Self {
+        ObjectPrimeSettings {
+            length: 20.0,
+            distance: 3.0,
+            object_lengths: vec![],
+        }
+    }
+}
+
+///Overrides for the infill printed over nothing, so the strands sag less
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BridgeSettings {
+    ///Ratio of the regular extrusion used for bridges
+    pub flow_ratio: f32,
+
+    ///Fan speed in percent while printing bridges
+    pub fan_speed: f32,
+
+    ///Speed in mm/s of bridges, in place of the bridge movement speed
+    pub speed: f32,
+}
+
+impl Default for BridgeSettings {
+    fn default() -> Self {
+        BridgeSettings {
+            flow_ratio: 0.9,
+            fan_speed: 100.0,
+            speed: 25.0,
+        }
+    }
+}
+
+///Cooling the nozzle down while the print pauses so it doesn't ooze
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StandbyTempSettings {
+    ///Temperature in °C the nozzle is kept at during the pause
+    pub temperature: f32,
+
+    ///Shortest pause in seconds worth cooling down and reheating for
+    pub min_idle_time: f32,
+
+    ///Rate in °C per second the nozzle heats up at, used to estimate the time spent reheating
+    pub reheat_rate: f32,
+}
+
+impl Default for StandbyTempSettings {
+    fn default() -> Self {
+        StandbyTempSettings {
+            temperature: 150.0,
+            min_idle_time: 30.0,
+            reheat_rate: 2.0,
+        }
+    }
+}
+
+///Ironing runs the nozzle over finished top surfaces to melt them flat
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IroningSettings {
+    ///Fraction of the normal extrusion of an ironing line, just enough to fill small gaps
+    pub flow: f32,
+
+    ///Distance in mm between ironing lines
+    pub spacing: f32,
+}
+
+impl Default for IroningSettings {
+    fn default() -> Self {
+        IroningSettings {
+            flow: 0.15,
+            spacing: 0.1,
+        }
+    }
+}
+
+///Fuzzy skin roughens the outer walls by moving their points randomly to either side
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FuzzySkinSettings {
+    ///Maximum distance in mm between the jittered points on both sides of the wall
+    pub thickness: f32,
+
+    ///Distance in mm between the jittered points along the wall
+    pub point_distance: f32,
+
+    ///Seed of the jitter, slicing again with the same seed gives the same surface
+    pub seed: usize,
+}
+
+impl Default for FuzzySkinSettings {
+    fn default() -> Self {
+        FuzzySkinSettings {
+            thickness: 0.3,
+            point_distance: 0.8,
+            seed: 0,
+        }
+    }
+}
+
+///Coasting replaces the end of each extrusion path with a move that doesn't extrude, the pressure
+///left in the nozzle prints it instead of forming a blob
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CoastingSettings {
+    ///Volume in mm³ of plastic at the end of a path that is printed without extruding
+    pub volume: f32,
+
+    ///Paths holding less plastic than this in mm³ are printed without coasting
+    pub min_volume_before_coast: f32,
+}
+
+impl Default for CoastingSettings {
+    fn default() -> Self {
+        CoastingSettings {
+            volume: 0.064,
+            min_volume_before_coast: 0.8,
+        }
+    }
+}
+
+///Height of the bed surface measured on a regular grid in printer coordinates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeightMap {
+    ///X position in mm of the first grid point
+    pub origin_x: f32,
+
+    ///Y position in mm of the first grid point
+    pub origin_y: f32,
+
+    ///Distance in mm between grid points along x
+    pub spacing_x: f32,
+
+    ///Distance in mm between grid points along y
+    pub spacing_y: f32,
+
+    ///Height in mm of each grid point relative to a flat bed, one row for each y
+    pub heights: Vec<Vec<f32>>,
+}
+
+impl Default for HeightMap {
+    fn default() -> Self {
+        HeightMap {
+            origin_x: 0.0,
+            origin_y: 0.0,
+            spacing_x: 10.0,
+            spacing_y: 10.0,
+            heights: vec![],
+        }
     }
+}
 
-    impl Default for FiberSettings {
-        fn default() -> Self {
-            FiberSettings {
-                diameter: 0.15,
-                cut_before: 20.0,
-                min_length: 25.0,
-                max_angle: 45.0,
+///Position in grid cells along one axis of a grid with `points` points, clamped to the grid. The
+///two points to interpolate between and the fraction of the way to the second one.
+fn grid_cell(position: f32, origin: f32, spacing: f32, points: usize) -> (usize, usize, f32) {
+    let last = points.saturating_sub(1);
+    let position = ((position - origin) / spacing).clamp(0.0, last as f32);
+    let low = (position.floor() as usize).min(last);
+    (low, (low + 1).min(last), position - low as f32)
+}
 
-                continuous: OptionalSetting {
-                    setting: ContinuousFiberSettings {},
-                    enabled: false,
-                },
+impl HeightMap {
+    ///Height of the bed at a point, interpolated bilinearly between the grid points. Points
+    ///outside the grid use the height at the nearest edge.
+    pub fn height_at(&self, x: f32, y: f32) -> f32 {
+        let rows = self.heights.len();
+        let columns = self.heights.iter().map(|row| row.len()).min().unwrap_or(0);
+        if columns == 0 {
+            return 0.0;
+        }
 
-                wall_pattern: OptionalSetting {
-                    setting: WallPattern {
-                        pattern: WallPatternType::Alternating,
-                        alternating_layer_width: 1,
-                        alternating_layer_spacing: 0,
-                        alternating_wall_width: 1,
-                        alternating_wall_spacing: 1,
-                        alternating_step: 1,
-                        wall_ranges: "".to_string(),
-                    },
-                    enabled: true,
-                },
+        let (x0, x1, tx) = grid_cell(x, self.origin_x, self.spacing_x, columns);
+        let (y0, y1, ty) = grid_cell(y, self.origin_y, self.spacing_y, rows);
 
-                infill: OptionalSetting {
-                    setting: Infill {
-                        partial_infill_type: PartialInfillTypes::Linear,
-                        infill_percentage: 0.2,
-                        width: 1,
-                        spacing: 1,
-                        air_space: false,
-                        solid_infill: false,
-                    },
-                    enabled: true,
-                },
+        let row = |y: usize| self.heights[y][x0] * (1.0 - tx) + self.heights[y][x1] * tx;
+        row(y0) * (1.0 - ty) + row(y1) * ty
+    }
 
-                speed_factor: 1.4,
-                acceleration_factor: 1.0,
-                jerk_factor: 1.0,
-            }
-        }
+    ///Longest move that still follows the height map closely
+    pub fn max_segment_length(&self) -> f32 {
+        self.spacing_x.min(self.spacing_y) / 2.0
     }
 }
 
-///Support settings
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SupportSettings {
-    ///Angle to start production supports in degrees
-    pub max_overhang_angle: f32,
+///The Settings for the wipe tower
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WipeTowerSettings {
+    ///X position in mm of the front left corner of the tower
+    pub x: f32,
 
-    ///Spacing between the ribs of support
-    pub support_spacing: f32,
+    ///Y position in mm of the front left corner of the tower
+    pub y: f32,
+
+    ///Width in mm of the square tower
+    pub width: f32,
+
+    ///Volume in mm^3 purged on the tower by the new extruder after each tool change
+    pub purge_volume: f32,
 }
 
-impl Default for SupportSettings {
+impl Default for WipeTowerSettings {
     fn default() -> Self {
-        SupportSettings {
-            max_overhang_angle: 45.0,
-            support_spacing: 2.0,
+        WipeTowerSettings {
+            x: 170.0,
+            y: 170.0,
+            width: 20.0,
+            purge_volume: 20.0,
         }
     }
 }
 
-///The Settings for Skirt generation
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SkirtSettings {
-    ///the number of layer to generate the skirt
-    pub layers: usize,
-
-    ///Distance from the models to place the skirt
+///The Settings for Ooze shield generation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OozeShieldSettings {
+    ///Distance from the models to place the ooze shield
     pub distance: f32,
 }
 
-impl Default for SkirtSettings {
+impl Default for OozeShieldSettings {
     fn default() -> Self {
-        SkirtSettings {
-            layers: 1,
-            distance: 10.0,
-        }
+        OozeShieldSettings { distance: 2.0 }
     }
 }
 
 ///The Settings for Skirt generation
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RetractionWipeSettings {
     ///The speed the retract wipe move
     pub speed: f32,
@@ -1121,6 +2363,9 @@ pub struct RetractionWipeSettings {
 
     ///Wipe Distance in mm
     pub distance: f32,
+
+    ///Which way the nozzle wipes
+    pub direction: WipeDirection,
 }
 
 impl Default for RetractionWipeSettings {
@@ -1129,12 +2374,13 @@ impl Default for RetractionWipeSettings {
             speed: 40.0,
             acceleration: 1000.0,
             distance: 2.0,
+            direction: WipeDirection::AlongPath,
         }
     }
 }
 
 ///A partial complete settings file
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct PartialSettings {
     ///The height of the layers
     pub layer_height: Option<f32>,
@@ -1208,6 +2454,9 @@ pub struct PartialSettings {
     ///Overlap between infill and interior perimeters
     pub infill_perimeter_overlap_percentage: Option<f32>,
 
+    ///Overlap between solid infill and interior perimeters
+    pub solid_infill_overlap_percentage: Option<f32>,
+
     ///Solid Infill type
     pub solid_infill_type: Option<SolidInfillTypes>,
 
@@ -1229,6 +2478,10 @@ pub struct PartialSettings {
     /// The instructions to append between object changes
     pub object_change_instructions: Option<String>,
 
+    /// The instructions to home and heat up the printer when resuming a print, instead of the
+    /// starting instructions
+    pub resume_instructions: Option<String>,
+
     ///Other files to load
     pub other_files: Option<Vec<String>>,
 
@@ -1270,6 +2523,174 @@ pub struct PartialSettings {
     ///Maximum feedrate for e dimension
     pub maximum_feedrate_e: Option<f32>,
 
+    ///Fixed angle for bridge infill, if None the optimal angle is calculated
+    pub bridge_angle_override: Option<OptionalSetting<f32>>,
+
+    ///Density of bridge infill as a fraction of the solid infill density, from 0 to 1
+    pub bridge_density: Option<f32>,
+
+    ///The ooze shield settings, if None no ooze shield will be generated
+    pub ooze_shield: Option<OptionalSetting<OozeShieldSettings>>,
+
+    ///Painted seam positions, the seam is placed on the closest vertex within the hint radius
+    pub seam_hints: Option<Vec<SeamHint>>,
+
+    ///Maximum width of gaps between the walls filled with a single bead, if None no gap fill will be performed
+    pub gap_fill: Option<OptionalSetting<f32>>,
+
+    ///Skip the z lift on the retraction after the final extrusion of the print
+    pub final_retract_no_lift: Option<bool>,
+
+    ///Transform applied to XY coordinates of the output gcode
+    pub bed_transform: Option<BedTransform>,
+
+    ///How to handle objects that overlap each other
+    pub overlapping_objects: Option<OverlapPolicy>,
+
+    ///Whether objects are printed layer by layer together or one after another
+    pub print_sequence: Option<PrintSequence>,
+
+    ///Park and wait out the slow down threshold on layers of objects printed one after another
+    pub sequential_cooling: Option<OptionalSetting<SequentialCoolingSettings>>,
+
+    ///Solid fill used on layers that are within both the top and bottom layers
+    pub thin_part_solid_policy: Option<ThinPartSolidPolicy>,
+
+    ///Points where the print pauses and displays a message at the closest layer for verification
+    pub witness_points: Option<Vec<WitnessPoint>>,
+
+    ///Regions of sparse infill smaller than this area in mm² are left empty
+    pub skip_infill_below_area: Option<f32>,
+
+    ///Objects with a first layer smaller than this area in mm² get a brim wide enough to reach it. Not used when a brim width is set
+    pub auto_brim_min_contact_area: Option<OptionalSetting<f32>>,
+
+    ///Clamp extrusion widths to between 0.6 and 2 times the nozzle diameter instead of only warning
+    pub clamp_extrusion_width: Option<bool>,
+
+    ///Print the islands of a layer one after another to reduce the number of retractions
+    pub minimize_retractions: Option<bool>,
+
+    ///Longest unsupported span to bridge, longer spans are supported from below instead
+    pub max_bridge_length: Option<OptionalSetting<f32>>,
+
+    ///Number of layers over which the extrusion width tapers from the first layer multiplier to the regular width
+    pub width_ramp_layers: Option<usize>,
+
+    ///Multiplier of the extrusion width on the first layer of the width ramp
+    pub first_layer_width_multiplier: Option<f32>,
+
+    ///Only print a single outer perimeter on every layer, skipping infill, top/bottom layers and support
+    pub outline_only: Option<bool>,
+
+    ///Resume a failed print from this layer, only the layers from it on are extruded
+    pub resume_from_layer: Option<OptionalSetting<usize>>,
+
+    ///Warn about layers with more retractions than this
+    pub max_retractions_per_layer: Option<OptionalSetting<usize>>,
+
+    ///Firmware dialect the gcode is written for
+    pub gcode_flavor: Option<GcodeFlavor>,
+
+    ///Pressure advance factor set at the start of the print
+    pub pressure_advance: Option<OptionalSetting<f32>>,
+
+    ///Number of layers below top surfaces whose infill density steps up from sparse to solid
+    pub top_transition_layers: Option<usize>,
+
+    ///What to do with placeholders in the custom gcode that don't name a setting
+    pub unknown_placeholder_policy: Option<UnknownPlaceholderPolicy>,
+
+    ///Remove moves that don't go anywhere, keeping the material of removed extrusions
+    pub remove_zero_length_moves: Option<bool>,
+
+    ///Height map of an uneven bed that the first layers follow
+    pub bed_mesh: Option<OptionalSetting<HeightMap>>,
+
+    ///Number of layers over which the bed mesh fades out to flat
+    pub bed_mesh_fade_layers: Option<usize>,
+
+    ///Width in mm added to every other wall, swapping walls each layer so the walls interlock
+    pub alternating_wall_width: Option<OptionalSetting<f32>>,
+
+    ///Fill the sparse infill solid on every layer that is a multiple of this, forming internal shells
+    pub solid_infill_every_layers: Option<OptionalSetting<usize>>,
+
+    ///Vary the layer height with the slope of the surface instead of using the fixed layer height
+    pub adaptive_layer_height: Option<OptionalSetting<AdaptiveLayerSettings>>,
+
+    ///Where the seam of the walls is placed when no seam hint is close
+    pub seam_position: Option<SeamPosition>,
+
+    ///Filament loaded in each extruder, objects choose theirs by index. Empty for a single extruder using the filament settings
+    pub extruders: Option<Vec<FilamentSettings>>,
+
+    ///Length in mm to retract the filament before switching to another extruder
+    pub toolchange_retract_length: Option<f32>,
+
+    ///Fit runs of short extrusions into G2/G3 arcs that stray at most this far in mm from the path
+    pub arc_tolerance: Option<OptionalSetting<f32>>,
+
+    ///Cool the nozzle to a standby temperature during long pauses, reheating before printing again
+    pub standby_temp: Option<OptionalSetting<StandbyTempSettings>>,
+
+    ///Smooth top surfaces by re-tracing them with the nozzle while extruding very little
+    pub ironing: Option<OptionalSetting<IroningSettings>>,
+
+    ///Print the inner walls only on layers that are a multiple of this, infill takes their place
+    ///on the other layers. The outer wall prints on every layer
+    pub inner_walls_every_layers: Option<usize>,
+
+    ///How the nozzle travels to the first chain of each layer
+    pub layer_change_approach: Option<ApproachMode>,
+
+    ///Run solid infill along the longest edge of each region's minimum bounding rectangle
+    pub align_solid_to_longest_edge: Option<bool>,
+
+    ///Jitter the outer walls perpendicular to the path for a textured surface
+    pub fuzzy_skin: Option<OptionalSetting<FuzzySkinSettings>>,
+
+    ///Slicing fails instead of creating more layers than this, guarding against tiny layer heights
+    pub max_layers: Option<usize>,
+
+    ///Stop extruding just before the end of each path and let the pressure in the nozzle finish it
+    pub coasting: Option<OptionalSetting<CoastingSettings>>,
+
+    ///Settings for a tower printed first on every layer to prime the nozzle
+    pub wipe_tower: Option<OptionalSetting<WipeTowerSettings>>,
+
+    ///Print features too thin for a wall on each side as a single wall of varying width
+    pub variable_width_walls: Option<bool>,
+
+    ///Prime line printed next to each object before it starts when printing one object after another
+    pub object_prime: Option<OptionalSetting<ObjectPrimeSettings>>,
+
+    ///Flow, fan and speed overrides for bridges
+    pub bridge: Option<OptionalSetting<BridgeSettings>>,
+
+    ///Number of perimeters on the top and bottom solid layers, in place of the number of perimeters
+    pub top_bottom_perimeters: Option<OptionalSetting<usize>>,
+
+    ///Print only the outer wall, rising continuously instead of stepping at each layer change
+    pub spiralize: Option<bool>,
+
+    ///Angle of the infill on the first layer in degrees, instead of the angle the layer would get
+    pub first_layer_infill_angle: Option<OptionalSetting<f32>>,
+
+    ///Travels longer than this in mm cool the nozzle to the standby temperature of the standby
+    ///temp settings, reheating in time to print again at the end of the travel
+    pub standby_travel_threshold: Option<OptionalSetting<f32>>,
+
+    ///Surface of the build plate, adjusts the first layer
+    pub bed_type: Option<BedType>,
+
+    ///Radius in mm around the nozzle that must be clear of printed objects when printing by object
+    pub sequential_clearance: Option<f32>,
+
+    ///Gcode written after the layer change of every layer in the range, for example M600 to
+    ///change filament
+    pub layer_gcode_hooks: Option<Vec<(LayerRange, String)>>,
+
     ///Settings for specific layers
     pub layer_settings: Option<Vec<(LayerRange, PartialLayerSettings)>>,
 }
@@ -1287,6 +2708,244 @@ impl PartialSettings {
         })
     }
 
+    ///Replace every setting present in this partial settings file
+    pub fn override_settings(self, mut settings: Settings) -> Settings {
+        fn set_setting<T>(value: Option<T>, value_mut: &mut T) {
+            if let Some(value) = value {
+                *value_mut = value;
+            }
+        }
+
+        set_setting(self.layer_height, &mut settings.layer_height);
+        set_setting(self.extrusion_width, &mut settings.extrusion_width);
+        set_setting(self.filament, &mut settings.filament);
+        set_setting(self.fiber, &mut settings.fiber);
+        set_setting(self.fan, &mut settings.fan);
+        set_setting(self.skirt, &mut settings.skirt);
+        set_setting(self.support, &mut settings.support);
+        set_setting(self.nozzle_diameter, &mut settings.nozzle_diameter);
+        set_setting(self.retract_length, &mut settings.retract_length);
+        set_setting(self.retract_lift_z, &mut settings.retract_lift_z);
+        set_setting(self.retract_speed, &mut settings.retract_speed);
+        set_setting(self.retraction_wipe, &mut settings.retraction_wipe);
+        set_setting(self.speed, &mut settings.speed);
+        set_setting(self.acceleration, &mut settings.acceleration);
+        set_setting(self.infill_percentage, &mut settings.infill_percentage);
+        set_setting(
+            self.inner_perimeters_first,
+            &mut settings.inner_perimeters_first,
+        );
+        set_setting(
+            self.number_of_perimeters,
+            &mut settings.number_of_perimeters,
+        );
+        set_setting(self.top_layers, &mut settings.top_layers);
+        set_setting(self.bottom_layers, &mut settings.bottom_layers);
+        set_setting(self.print_x, &mut settings.print_x);
+        set_setting(self.print_y, &mut settings.print_y);
+        set_setting(self.print_z, &mut settings.print_z);
+        set_setting(self.brim_width, &mut settings.brim_width);
+        set_setting(self.layer_shrink_amount, &mut settings.layer_shrink_amount);
+        set_setting(
+            self.minimum_retract_distance,
+            &mut settings.minimum_retract_distance,
+        );
+        set_setting(
+            self.infill_perimeter_overlap_percentage,
+            &mut settings.infill_perimeter_overlap_percentage,
+        );
+        set_setting(
+            self.solid_infill_overlap_percentage,
+            &mut settings.solid_infill_overlap_percentage,
+        );
+        set_setting(self.solid_infill_type, &mut settings.solid_infill_type);
+        set_setting(self.partial_infill_type, &mut settings.partial_infill_type);
+        set_setting(
+            self.starting_instructions,
+            &mut settings.starting_instructions,
+        );
+        set_setting(self.ending_instructions, &mut settings.ending_instructions);
+        set_setting(
+            self.before_layer_change_instructions,
+            &mut settings.before_layer_change_instructions,
+        );
+        set_setting(
+            self.after_layer_change_instructions,
+            &mut settings.after_layer_change_instructions,
+        );
+        set_setting(
+            self.object_change_instructions,
+            &mut settings.object_change_instructions,
+        );
+        set_setting(self.resume_instructions, &mut settings.resume_instructions);
+        set_setting(self.max_acceleration_x, &mut settings.max_acceleration_x);
+        set_setting(self.max_acceleration_y, &mut settings.max_acceleration_y);
+        set_setting(self.max_acceleration_z, &mut settings.max_acceleration_z);
+        set_setting(self.max_acceleration_e, &mut settings.max_acceleration_e);
+        set_setting(
+            self.max_acceleration_extruding,
+            &mut settings.max_acceleration_extruding,
+        );
+        set_setting(
+            self.max_acceleration_travel,
+            &mut settings.max_acceleration_travel,
+        );
+        set_setting(
+            self.max_acceleration_retracting,
+            &mut settings.max_acceleration_retracting,
+        );
+        set_setting(self.max_jerk_x, &mut settings.max_jerk_x);
+        set_setting(self.max_jerk_y, &mut settings.max_jerk_y);
+        set_setting(self.max_jerk_z, &mut settings.max_jerk_z);
+        set_setting(self.max_jerk_e, &mut settings.max_jerk_e);
+
+        set_setting(
+            self.minimum_feedrate_print,
+            &mut settings.minimum_feedrate_print,
+        );
+        set_setting(
+            self.minimum_feedrate_travel,
+            &mut settings.minimum_feedrate_travel,
+        );
+        set_setting(self.maximum_feedrate_x, &mut settings.maximum_feedrate_x);
+        set_setting(self.maximum_feedrate_y, &mut settings.maximum_feedrate_y);
+        set_setting(self.maximum_feedrate_z, &mut settings.maximum_feedrate_z);
+        set_setting(self.maximum_feedrate_e, &mut settings.maximum_feedrate_e);
+        set_setting(
+            self.bridge_angle_override,
+            &mut settings.bridge_angle_override,
+        );
+        set_setting(self.bridge_density, &mut settings.bridge_density);
+        set_setting(self.ooze_shield, &mut settings.ooze_shield);
+        set_setting(self.seam_hints, &mut settings.seam_hints);
+        set_setting(self.gap_fill, &mut settings.gap_fill);
+        set_setting(
+            self.final_retract_no_lift,
+            &mut settings.final_retract_no_lift,
+        );
+        set_setting(self.bed_transform, &mut settings.bed_transform);
+        set_setting(self.overlapping_objects, &mut settings.overlapping_objects);
+        set_setting(self.print_sequence, &mut settings.print_sequence);
+        set_setting(self.sequential_cooling, &mut settings.sequential_cooling);
+        set_setting(
+            self.thin_part_solid_policy,
+            &mut settings.thin_part_solid_policy,
+        );
+        set_setting(self.witness_points, &mut settings.witness_points);
+        set_setting(
+            self.skip_infill_below_area,
+            &mut settings.skip_infill_below_area,
+        );
+        set_setting(
+            self.auto_brim_min_contact_area,
+            &mut settings.auto_brim_min_contact_area,
+        );
+        set_setting(
+            self.clamp_extrusion_width,
+            &mut settings.clamp_extrusion_width,
+        );
+        set_setting(
+            self.minimize_retractions,
+            &mut settings.minimize_retractions,
+        );
+        set_setting(self.max_bridge_length, &mut settings.max_bridge_length);
+        set_setting(self.width_ramp_layers, &mut settings.width_ramp_layers);
+        set_setting(
+            self.first_layer_width_multiplier,
+            &mut settings.first_layer_width_multiplier,
+        );
+        set_setting(self.outline_only, &mut settings.outline_only);
+        set_setting(self.resume_from_layer, &mut settings.resume_from_layer);
+        set_setting(
+            self.max_retractions_per_layer,
+            &mut settings.max_retractions_per_layer,
+        );
+        set_setting(self.gcode_flavor, &mut settings.gcode_flavor);
+        set_setting(self.pressure_advance, &mut settings.pressure_advance);
+        set_setting(
+            self.top_transition_layers,
+            &mut settings.top_transition_layers,
+        );
+        set_setting(
+            self.unknown_placeholder_policy,
+            &mut settings.unknown_placeholder_policy,
+        );
+        set_setting(
+            self.remove_zero_length_moves,
+            &mut settings.remove_zero_length_moves,
+        );
+        set_setting(self.bed_mesh, &mut settings.bed_mesh);
+        set_setting(
+            self.bed_mesh_fade_layers,
+            &mut settings.bed_mesh_fade_layers,
+        );
+        set_setting(
+            self.alternating_wall_width,
+            &mut settings.alternating_wall_width,
+        );
+        set_setting(
+            self.solid_infill_every_layers,
+            &mut settings.solid_infill_every_layers,
+        );
+        set_setting(
+            self.adaptive_layer_height,
+            &mut settings.adaptive_layer_height,
+        );
+        set_setting(self.seam_position, &mut settings.seam_position);
+        set_setting(self.extruders, &mut settings.extruders);
+        set_setting(
+            self.toolchange_retract_length,
+            &mut settings.toolchange_retract_length,
+        );
+        set_setting(self.arc_tolerance, &mut settings.arc_tolerance);
+        set_setting(self.standby_temp, &mut settings.standby_temp);
+        set_setting(self.ironing, &mut settings.ironing);
+        set_setting(
+            self.inner_walls_every_layers,
+            &mut settings.inner_walls_every_layers,
+        );
+        set_setting(
+            self.layer_change_approach,
+            &mut settings.layer_change_approach,
+        );
+        set_setting(
+            self.align_solid_to_longest_edge,
+            &mut settings.align_solid_to_longest_edge,
+        );
+        set_setting(self.fuzzy_skin, &mut settings.fuzzy_skin);
+        set_setting(self.max_layers, &mut settings.max_layers);
+        set_setting(self.coasting, &mut settings.coasting);
+        set_setting(self.wipe_tower, &mut settings.wipe_tower);
+        set_setting(
+            self.variable_width_walls,
+            &mut settings.variable_width_walls,
+        );
+        set_setting(self.object_prime, &mut settings.object_prime);
+        set_setting(self.bridge, &mut settings.bridge);
+        set_setting(
+            self.top_bottom_perimeters,
+            &mut settings.top_bottom_perimeters,
+        );
+        set_setting(self.spiralize, &mut settings.spiralize);
+        set_setting(
+            self.first_layer_infill_angle,
+            &mut settings.first_layer_infill_angle,
+        );
+        set_setting(
+            self.standby_travel_threshold,
+            &mut settings.standby_travel_threshold,
+        );
+        set_setting(self.bed_type, &mut settings.bed_type);
+        set_setting(
+            self.sequential_clearance,
+            &mut settings.sequential_clearance,
+        );
+        set_setting(self.layer_gcode_hooks, &mut settings.layer_gcode_hooks);
+        set_setting(self.layer_settings, &mut settings.layer_settings);
+
+        settings
+    }
+
     fn combine_with_other_files(&mut self) -> Result<(), SlicerErrors> {
         let files: Vec<String> = self
             .other_files
@@ -1351,6 +3010,9 @@ impl PartialSettings {
             infill_perimeter_overlap_percentage: self
                 .infill_perimeter_overlap_percentage
                 .or(other.infill_perimeter_overlap_percentage),
+            solid_infill_overlap_percentage: self
+                .solid_infill_overlap_percentage
+                .or(other.solid_infill_overlap_percentage),
             solid_infill_type: self.solid_infill_type.or(other.solid_infill_type),
             partial_infill_type: self.partial_infill_type.or(other.partial_infill_type),
             starting_instructions: self
@@ -1373,6 +3035,10 @@ impl PartialSettings {
                 .object_change_instructions
                 .clone()
                 .or(other.object_change_instructions),
+            resume_instructions: self
+                .resume_instructions
+                .clone()
+                .or(other.resume_instructions),
             other_files: None,
             max_acceleration_x: self.max_acceleration_x.or(other.max_acceleration_x),
             max_acceleration_y: self.max_acceleration_y.or(other.max_acceleration_y),
@@ -1399,6 +3065,104 @@ impl PartialSettings {
             maximum_feedrate_y: self.maximum_feedrate_y.or(other.maximum_feedrate_y),
             maximum_feedrate_z: self.maximum_feedrate_z.or(other.maximum_feedrate_z),
             maximum_feedrate_e: self.maximum_feedrate_e.or(other.maximum_feedrate_e),
+            bridge_angle_override: self.bridge_angle_override.or(other.bridge_angle_override),
+            bridge_density: self.bridge_density.or(other.bridge_density),
+            ooze_shield: self
+                .ooze_shield
+                .clone()
+                .or_else(|| other.ooze_shield.clone()),
+            seam_hints: self.seam_hints.clone().or_else(|| other.seam_hints.clone()),
+            gap_fill: self.gap_fill.or(other.gap_fill),
+            final_retract_no_lift: self.final_retract_no_lift.or(other.final_retract_no_lift),
+            bed_transform: self
+                .bed_transform
+                .clone()
+                .or_else(|| other.bed_transform.clone()),
+            overlapping_objects: self.overlapping_objects.or(other.overlapping_objects),
+            print_sequence: self.print_sequence.or(other.print_sequence),
+            sequential_cooling: self
+                .sequential_cooling
+                .clone()
+                .or_else(|| other.sequential_cooling.clone()),
+            thin_part_solid_policy: self.thin_part_solid_policy.or(other.thin_part_solid_policy),
+            witness_points: self
+                .witness_points
+                .clone()
+                .or_else(|| other.witness_points.clone()),
+            skip_infill_below_area: self.skip_infill_below_area.or(other.skip_infill_below_area),
+            auto_brim_min_contact_area: self
+                .auto_brim_min_contact_area
+                .or(other.auto_brim_min_contact_area),
+            clamp_extrusion_width: self.clamp_extrusion_width.or(other.clamp_extrusion_width),
+            minimize_retractions: self.minimize_retractions.or(other.minimize_retractions),
+            max_bridge_length: self.max_bridge_length.or(other.max_bridge_length),
+            width_ramp_layers: self.width_ramp_layers.or(other.width_ramp_layers),
+            first_layer_width_multiplier: self
+                .first_layer_width_multiplier
+                .or(other.first_layer_width_multiplier),
+            outline_only: self.outline_only.or(other.outline_only),
+            resume_from_layer: self.resume_from_layer.or(other.resume_from_layer),
+            max_retractions_per_layer: self
+                .max_retractions_per_layer
+                .or(other.max_retractions_per_layer),
+            gcode_flavor: self.gcode_flavor.or(other.gcode_flavor),
+            pressure_advance: self.pressure_advance.or(other.pressure_advance),
+            top_transition_layers: self.top_transition_layers.or(other.top_transition_layers),
+            unknown_placeholder_policy: self
+                .unknown_placeholder_policy
+                .or(other.unknown_placeholder_policy),
+            remove_zero_length_moves: self
+                .remove_zero_length_moves
+                .or(other.remove_zero_length_moves),
+            bed_mesh: self.bed_mesh.clone().or_else(|| other.bed_mesh.clone()),
+            bed_mesh_fade_layers: self.bed_mesh_fade_layers.or(other.bed_mesh_fade_layers),
+            alternating_wall_width: self.alternating_wall_width.or(other.alternating_wall_width),
+            solid_infill_every_layers: self
+                .solid_infill_every_layers
+                .or(other.solid_infill_every_layers),
+            adaptive_layer_height: self.adaptive_layer_height.or(other.adaptive_layer_height),
+            seam_position: self.seam_position.or(other.seam_position),
+            extruders: self.extruders.clone().or_else(|| other.extruders.clone()),
+            toolchange_retract_length: self
+                .toolchange_retract_length
+                .or(other.toolchange_retract_length),
+            arc_tolerance: self.arc_tolerance.or(other.arc_tolerance),
+            standby_temp: self
+                .standby_temp
+                .clone()
+                .or_else(|| other.standby_temp.clone()),
+            ironing: self.ironing.clone().or_else(|| other.ironing.clone()),
+            inner_walls_every_layers: self
+                .inner_walls_every_layers
+                .or(other.inner_walls_every_layers),
+            layer_change_approach: self.layer_change_approach.or(other.layer_change_approach),
+            align_solid_to_longest_edge: self
+                .align_solid_to_longest_edge
+                .or(other.align_solid_to_longest_edge),
+            fuzzy_skin: self.fuzzy_skin.clone().or_else(|| other.fuzzy_skin.clone()),
+            max_layers: self.max_layers.or(other.max_layers),
+            coasting: self.coasting.clone().or_else(|| other.coasting.clone()),
+            wipe_tower: self.wipe_tower.clone().or_else(|| other.wipe_tower.clone()),
+            variable_width_walls: self.variable_width_walls.or(other.variable_width_walls),
+            object_prime: self
+                .object_prime
+                .clone()
+                .or_else(|| other.object_prime.clone()),
+            bridge: self.bridge.clone().or_else(|| other.bridge.clone()),
+            top_bottom_perimeters: self.top_bottom_perimeters.or(other.top_bottom_perimeters),
+            spiralize: self.spiralize.or(other.spiralize),
+            first_layer_infill_angle: self
+                .first_layer_infill_angle
+                .or(other.first_layer_infill_angle),
+            standby_travel_threshold: self
+                .standby_travel_threshold
+                .or(other.standby_travel_threshold),
+            bed_type: self.bed_type.or(other.bed_type),
+            sequential_clearance: self.sequential_clearance.or(other.sequential_clearance),
+            layer_gcode_hooks: self
+                .layer_gcode_hooks
+                .clone()
+                .or_else(|| other.layer_gcode_hooks.clone()),
             layer_settings: {
                 match (self.layer_settings.as_ref(), other.layer_settings.as_ref()) {
                     (None, None) => None,
@@ -1416,7 +3180,7 @@ impl PartialSettings {
 }
 
 /// The different types of layer ranges supported
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum LayerRange {
     ///A single single based on the index
     SingleLayer(usize),
@@ -1440,8 +3204,19 @@ pub enum LayerRange {
     },
 }
 
+impl LayerRange {
+    ///Whether the layer with the index `layer` at `height` is in the range
+    pub fn contains(&self, layer: usize, height: f32) -> bool {
+        match self {
+            LayerRange::LayerCountRange { end, start } => *start <= layer && layer <= *end,
+            LayerRange::HeightRange { end, start } => *start <= height && height <= *end,
+            LayerRange::SingleLayer(filter_layer) => *filter_layer == layer,
+        }
+    }
+}
+
 ///A Partial List of all slicer settings
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct PartialLayerSettings {
     ///The height of the layers
     pub layer_height: Option<f32>,
@@ -1472,6 +3247,9 @@ pub struct PartialLayerSettings {
     ///Overlap between infill and interior perimeters
     pub infill_perimeter_overlap_percentage: Option<f32>,
 
+    ///Overlap between solid infill and interior perimeters
+    pub solid_infill_overlap_percentage: Option<f32>,
+
     ///Controls the order of perimeters
     pub inner_perimeters_first: Option<bool>,
 
@@ -1486,6 +3264,12 @@ pub struct PartialLayerSettings {
 
     ///Retraction Distance
     pub retraction_length: Option<f32>,
+
+    ///Fan speed outside of bridges
+    pub fan_speed: Option<f32>,
+
+    ///Distance the layer is printed above its sliced height
+    pub z_offset: Option<f32>,
 }
 
 impl PartialLayerSettings {
@@ -1515,10 +3299,15 @@ impl PartialLayerSettings {
             infill_perimeter_overlap_percentage: self
                 .infill_perimeter_overlap_percentage
                 .or(other.infill_perimeter_overlap_percentage),
+            solid_infill_overlap_percentage: self
+                .solid_infill_overlap_percentage
+                .or(other.solid_infill_overlap_percentage),
             solid_infill_type: self.solid_infill_type.or(other.solid_infill_type),
             partial_infill_type: self.partial_infill_type.or(other.partial_infill_type),
             layer_shrink_amount: self.layer_shrink_amount.or(other.layer_shrink_amount),
             retraction_length: self.retraction_length.or(other.retraction_length),
+            fan_speed: self.fan_speed.or(other.fan_speed),
+            z_offset: self.z_offset.or(other.z_offset),
         }
     }
 }
@@ -1557,6 +3346,9 @@ fn try_convert_partial_to_settings(part: PartialSettings) -> Result<Settings, St
         infill_perimeter_overlap_percentage: part
             .infill_perimeter_overlap_percentage
             .ok_or("infill_perimeter_overlap_percentage")?,
+        solid_infill_overlap_percentage: part
+            .solid_infill_overlap_percentage
+            .ok_or("solid_infill_overlap_percentage")?,
         solid_infill_type: part.solid_infill_type.ok_or("solid_infill_type")?,
         partial_infill_type: part.partial_infill_type.ok_or("partial_infill_type")?,
         starting_instructions: part.starting_instructions.ok_or("starting_instructions")?,
@@ -1570,6 +3362,7 @@ fn try_convert_partial_to_settings(part: PartialSettings) -> Result<Settings, St
         object_change_instructions: part
             .object_change_instructions
             .ok_or("object_change_instructions")?,
+        resume_instructions: part.resume_instructions.ok_or("resume_instructions")?,
 
         max_acceleration_x: part.max_acceleration_x.ok_or("max_acceleration_x")?,
         max_acceleration_y: part.max_acceleration_y.ok_or("max_acceleration_y")?,
@@ -1598,6 +3391,89 @@ fn try_convert_partial_to_settings(part: PartialSettings) -> Result<Settings, St
         maximum_feedrate_y: part.maximum_feedrate_y.ok_or("maximum_feedrate_y")?,
         maximum_feedrate_z: part.maximum_feedrate_z.ok_or("maximum_feedrate_z")?,
         maximum_feedrate_e: part.maximum_feedrate_e.ok_or("maximum_feedrate_e")?,
+        bridge_angle_override: part.bridge_angle_override.ok_or("bridge_angle_override")?,
+        bridge_density: part.bridge_density.ok_or("bridge_density")?,
+        ooze_shield: part.ooze_shield.ok_or("ooze_shield")?,
+        seam_hints: part.seam_hints.ok_or("seam_hints")?,
+        gap_fill: part.gap_fill.ok_or("gap_fill")?,
+        final_retract_no_lift: part.final_retract_no_lift.ok_or("final_retract_no_lift")?,
+        bed_transform: part.bed_transform.ok_or("bed_transform")?,
+        overlapping_objects: part.overlapping_objects.ok_or("overlapping_objects")?,
+        print_sequence: part.print_sequence.ok_or("print_sequence")?,
+        sequential_cooling: part.sequential_cooling.ok_or("sequential_cooling")?,
+        thin_part_solid_policy: part
+            .thin_part_solid_policy
+            .ok_or("thin_part_solid_policy")?,
+        witness_points: part.witness_points.ok_or("witness_points")?,
+        skip_infill_below_area: part
+            .skip_infill_below_area
+            .ok_or("skip_infill_below_area")?,
+        auto_brim_min_contact_area: part
+            .auto_brim_min_contact_area
+            .ok_or("auto_brim_min_contact_area")?,
+        clamp_extrusion_width: part.clamp_extrusion_width.ok_or("clamp_extrusion_width")?,
+        minimize_retractions: part.minimize_retractions.ok_or("minimize_retractions")?,
+        max_bridge_length: part.max_bridge_length.ok_or("max_bridge_length")?,
+        width_ramp_layers: part.width_ramp_layers.ok_or("width_ramp_layers")?,
+        first_layer_width_multiplier: part
+            .first_layer_width_multiplier
+            .ok_or("first_layer_width_multiplier")?,
+        outline_only: part.outline_only.ok_or("outline_only")?,
+        resume_from_layer: part.resume_from_layer.ok_or("resume_from_layer")?,
+        max_retractions_per_layer: part
+            .max_retractions_per_layer
+            .ok_or("max_retractions_per_layer")?,
+        gcode_flavor: part.gcode_flavor.ok_or("gcode_flavor")?,
+        pressure_advance: part.pressure_advance.ok_or("pressure_advance")?,
+        top_transition_layers: part.top_transition_layers.ok_or("top_transition_layers")?,
+        unknown_placeholder_policy: part
+            .unknown_placeholder_policy
+            .ok_or("unknown_placeholder_policy")?,
+        remove_zero_length_moves: part
+            .remove_zero_length_moves
+            .ok_or("remove_zero_length_moves")?,
+        bed_mesh: part.bed_mesh.ok_or("bed_mesh")?,
+        bed_mesh_fade_layers: part.bed_mesh_fade_layers.ok_or("bed_mesh_fade_layers")?,
+        alternating_wall_width: part
+            .alternating_wall_width
+            .ok_or("alternating_wall_width")?,
+        solid_infill_every_layers: part
+            .solid_infill_every_layers
+            .ok_or("solid_infill_every_layers")?,
+        adaptive_layer_height: part.adaptive_layer_height.ok_or("adaptive_layer_height")?,
+        seam_position: part.seam_position.ok_or("seam_position")?,
+        extruders: part.extruders.ok_or("extruders")?,
+        toolchange_retract_length: part
+            .toolchange_retract_length
+            .ok_or("toolchange_retract_length")?,
+        arc_tolerance: part.arc_tolerance.ok_or("arc_tolerance")?,
+        standby_temp: part.standby_temp.ok_or("standby_temp")?,
+        ironing: part.ironing.ok_or("ironing")?,
+        inner_walls_every_layers: part
+            .inner_walls_every_layers
+            .ok_or("inner_walls_every_layers")?,
+        layer_change_approach: part.layer_change_approach.ok_or("layer_change_approach")?,
+        align_solid_to_longest_edge: part
+            .align_solid_to_longest_edge
+            .ok_or("align_solid_to_longest_edge")?,
+        fuzzy_skin: part.fuzzy_skin.ok_or("fuzzy_skin")?,
+        max_layers: part.max_layers.ok_or("max_layers")?,
+        coasting: part.coasting.ok_or("coasting")?,
+        wipe_tower: part.wipe_tower.ok_or("wipe_tower")?,
+        variable_width_walls: part.variable_width_walls.ok_or("variable_width_walls")?,
+        object_prime: part.object_prime.ok_or("object_prime")?,
+        bridge: part.bridge.ok_or("bridge")?,
+        top_bottom_perimeters: part.top_bottom_perimeters.ok_or("top_bottom_perimeters")?,
+        spiralize: part.spiralize.ok_or("spiralize")?,
+        first_layer_infill_angle: part
+            .first_layer_infill_angle
+            .ok_or("first_layer_infill_angle")?,
+        standby_travel_threshold: part
+            .standby_travel_threshold
+            .ok_or("standby_travel_threshold")?,
+        bed_type: part.bed_type.ok_or("bed_type")?,
+        sequential_clearance: part.sequential_clearance.ok_or("sequential_clearance")?,
+        layer_gcode_hooks: part.layer_gcode_hooks.ok_or("layer_gcode_hooks")?,
         layer_settings: part.layer_settings.unwrap_or_default(),
     })
 }
@@ -1780,6 +3656,15 @@ fn check_accelerations(
         });
     }
 
+    //ironing
+    if (speed.ironing * speed.ironing) / (2.0 * acceleration.ironing) > min_bed_dimension {
+        return SettingsValidationResult::Warning(SlicerWarnings::AccelerationTooLow {
+            acceleration: acceleration.ironing,
+            speed: speed.ironing,
+            bed_size: min_bed_dimension,
+        });
+    }
+
     //interior_surface_perimeter
     if (speed.interior_surface_perimeter * speed.interior_surface_perimeter)
         / (2.0 * acceleration.interior_surface_perimeter)
@@ -1830,3 +3715,55 @@ fn check_accelerations(
 
     SettingsValidationResult::NoIssue
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_adaptive_layer_height_is_valid() {
+        //The default range fits the default nozzle as well as the common 0.4mm nozzle
+        for nozzle_diameter in [0.8, 0.4] {
+            let mut settings = Settings {
+                nozzle_diameter,
+                layer_height: nozzle_diameter / 2.0,
+                ..Default::default()
+            };
+            *settings.adaptive_layer_height.enabled_mut() = true;
+
+            let result = settings.validate_settings();
+            assert!(
+                !matches!(
+                    result,
+                    SettingsValidationResult::Warning(
+                        SlicerWarnings::LayerSizeTooLow { .. }
+                            | SlicerWarnings::LayerSizeTooHigh { .. }
+                    )
+                ),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn bridge_density_above_solid_is_rejected() {
+        let settings = Settings {
+            bridge_density: 1.5,
+            ..Default::default()
+        };
+
+        //Bridge lines closer than solid infill would pile up on each other
+        let error = match settings.validate_settings() {
+            SettingsValidationResult::Error(error) => error,
+            result => panic!("Expected an error, got {:?}", result),
+        };
+        assert_eq!(
+            error,
+            SlicerErrors::SettingNotAPercentage {
+                setting: "bridge_density".to_string(),
+                value: 1.5,
+            }
+        );
+    }
+}
@@ -1,14 +1,21 @@
-use crate::plotter::support::Supporter;
+use crate::plotter::support::{
+    apply_support_z_gap, support_column_ranges, support_interface_areas, Supporter,
+};
 
 use crate::error::SlicerErrors;
+use crate::modifier::fill_with_modifiers;
 use crate::plotter::lightning_infill::lightning_infill;
 use crate::plotter::polygon_operations::PolygonOperations;
-use crate::plotter::Plotter;
-use crate::settings::Settings;
-use crate::{MoveType, Object, PartialInfillTypes, Slice, TraceType};
+use crate::plotter::tree_support::add_tree_support;
+use crate::plotter::{long_bridge_spans, Plotter};
+use crate::settings::{Settings, SkirtSettings};
+use crate::{
+    MoveType, Object, PartialInfillTypes, PrintSequence, Slice, SupportStyle, ThinPartSolidPolicy,
+    TraceType,
+};
 use geo::prelude::*;
 use geo::*;
-use log::info;
+use log::{info, warn};
 use rayon::prelude::*;
 
 #[derive(Debug)]
@@ -53,6 +60,12 @@ pub trait ObjectPass {
     fn pass(objects: &mut Vec<Object>, settings: &Settings);
 }
 
+///Whether the object's slices start with the first layer on the bed. A partial slice of a z range
+///above the bed has no first layer for the brim, skirt or prime.
+fn starts_on_the_bed(object: &Object) -> bool {
+    object.layers.first().is_some_and(|slice| slice.layer == 0)
+}
+
 pub struct BrimPass {}
 
 impl ObjectPass for BrimPass {
@@ -60,12 +73,17 @@ impl ObjectPass for BrimPass {
         if settings.brim_width.is_enabled() {
             let width = *settings.brim_width;
 
+            if !objects.first().is_some_and(starts_on_the_bed) {
+                return;
+            }
+
             // display_state_update("Generating Moves: Brim", send_messages);
             //Add to first object
 
             let first_layer_multipolygon: MultiPolygon<f32> = MultiPolygon(
                 objects
                     .iter()
+                    .filter(|object| starts_on_the_bed(object))
                     .flat_map(|poly| {
                         let first_slice = poly.layers.first().expect("Object needs a Slice");
 
@@ -86,14 +104,90 @@ impl ObjectPass for BrimPass {
                 .get_mut(0)
                 .expect("Object needs a Slice")
                 .generate_brim(first_layer_multipolygon, width);
+        } else if settings.auto_brim_min_contact_area.is_enabled() {
+            let target_area = *settings.auto_brim_min_contact_area;
+
+            for object in objects
+                .iter_mut()
+                .filter(|object| starts_on_the_bed(object))
+            {
+                let first_slice = object.layers.first_mut().expect("Object needs a Slice");
+                let line_width = first_slice
+                    .layer_settings
+                    .extrusion_width
+                    .exterior_surface_perimeter;
+
+                if let Some(lines) =
+                    auto_brim_lines(&first_slice.main_polygon, target_area, line_width, settings)
+                {
+                    //Half a line of slack so rounding can't drop the outermost line
+                    let footprint = first_slice.main_polygon.clone();
+                    first_slice.generate_brim(footprint, (lines as f32 + 0.5) * line_width);
+                }
+            }
         }
     }
 }
 
+///Number of brim lines needed for the footprint to reach the target contact area. Returns None if
+///the footprint is large enough or there is no room on the bed.
+fn auto_brim_lines(
+    footprint: &MultiPolygon<f32>,
+    target_area: f32,
+    line_width: f32,
+    settings: &Settings,
+) -> Option<usize> {
+    let area = footprint.unsigned_area();
+    if area >= target_area {
+        return None;
+    }
+
+    let bounds = footprint.bounding_rect()?;
+    let max_lines = (bounds
+        .min()
+        .x
+        .min(bounds.min().y)
+        .min(settings.print_x - bounds.max().x)
+        .min(settings.print_y - bounds.max().y)
+        / line_width)
+        .floor();
+
+    if max_lines < 1.0 {
+        warn!("No room on the bed for a brim around a small object");
+        return None;
+    }
+
+    //Start from the width a round footprint would need: area + perimeter * w + pi * w^2
+    let perimeter: f32 = footprint
+        .iter()
+        .map(|poly| poly.exterior().euclidean_length())
+        .sum();
+    let estimate = (-perimeter
+        + (perimeter * perimeter + 4.0 * std::f32::consts::PI * (target_area - area)).sqrt())
+        / (2.0 * std::f32::consts::PI);
+
+    let mut lines = (estimate / line_width).ceil().clamp(1.0, max_lines);
+    while lines < max_lines
+        && footprint.offset_from(lines * line_width).unsigned_area() < target_area
+    {
+        lines += 1.0;
+    }
+
+    if footprint.offset_from(lines * line_width).unsigned_area() < target_area {
+        warn!("Brim around a small object is limited by the bed size");
+    }
+
+    Some(lines as usize)
+}
+
 pub struct SupportTowerPass {}
 
 impl ObjectPass for SupportTowerPass {
     fn pass(objects: &mut Vec<Object>, settings: &Settings) {
+        if settings.outline_only || settings.spiralize {
+            return;
+        }
+
         if settings.support.is_enabled() {
             let support = &settings.support;
 
@@ -101,14 +195,54 @@ impl ObjectPass for SupportTowerPass {
             //Add to first object
 
             objects.par_iter_mut().for_each(|obj| {
-                (1..obj.layers.len()).rev().for_each(|q| {
-                    //todo Fix this, it feels hacky
-                    if let [ref mut layer, ref mut above, ..] = &mut obj.layers[q - 1..=q] {
-                        layer.add_support_polygons(above, support);
-                    } else {
-                        unreachable!()
-                    }
-                });
+                match support.style {
+                    SupportStyle::Grid => (1..obj.layers.len()).rev().for_each(|q| {
+                        //todo Fix this, it feels hacky
+                        if let [ref mut layer, ref mut above, ..] = &mut obj.layers[q - 1..=q] {
+                            layer.add_support_polygons(above, support);
+                        } else {
+                            unreachable!()
+                        }
+                    }),
+                    SupportStyle::Tree => add_tree_support(obj, support),
+                }
+
+                apply_support_z_gap(&mut obj.layers, support.z_gap);
+            });
+        }
+
+        if settings.max_bridge_length.is_enabled() {
+            objects.par_iter_mut().for_each(|obj| {
+                add_long_bridge_support(obj, *settings.max_bridge_length);
+            });
+        }
+    }
+}
+
+///Add support towers below every unsupported span longer than the max bridge length, all the
+///way down to the bed or the model
+fn add_long_bridge_support(obj: &mut Object, max_bridge_length: f32) {
+    let mut tower = MultiPolygon(vec![]);
+
+    for q in (1..obj.layers.len()).rev() {
+        let (below, above) = obj.layers.split_at_mut(q);
+        let layer = below.last_mut().expect("q is at least 1");
+        let above = &above[0];
+
+        let long_spans = long_bridge_spans(
+            &above.main_polygon,
+            &layer.main_polygon,
+            above.layer_settings.extrusion_width.bridge,
+            max_bridge_length,
+        );
+
+        tower = tower.union_with(&long_spans);
+
+        let layer_tower = tower.difference_with(&layer.main_polygon.offset_from(0.2));
+        if !layer_tower.0.is_empty() {
+            layer.support_tower = Some(match &layer.support_tower {
+                Some(existing) => existing.union_with(&layer_tower),
+                None => layer_tower,
             });
         }
     }
@@ -130,7 +264,7 @@ impl ObjectPass for SkirtPass {
                     object
                         .layers
                         .iter()
-                        .take(skirt.layers)
+                        .take_while(|slice| slice.layer < skirt.layers)
                         .map(|m| m.main_polygon.union_with(&m.get_support_polygon()))
                 })
                 .fold(MultiPolygon(vec![]), |a, b| a.union_with(&b))
@@ -142,12 +276,294 @@ impl ObjectPass for SkirtPass {
                 .expect("Needs an object")
                 .layers
                 .iter_mut()
-                .take(skirt.layers)
+                .take_while(|slice| slice.layer < skirt.layers)
                 .for_each(|slice| slice.generate_skirt(&convex_hull, skirt, settings))
         }
     }
 }
 
+pub struct OozeShieldPass {}
+
+impl ObjectPass for OozeShieldPass {
+    fn pass(objects: &mut Vec<Object>, settings: &Settings) {
+        if settings.ooze_shield.is_enabled() {
+            let shield = SkirtSettings {
+                layers: 1,
+                distance: settings.ooze_shield.distance,
+            };
+
+            let layer_count = objects
+                .iter()
+                .map(|object| object.layers.len())
+                .max()
+                .unwrap_or(0);
+
+            //Regenerate the shield around the hull of all objects on each layer
+            for layer in 0..layer_count {
+                let convex_hull = objects
+                    .iter()
+                    .filter_map(|object| object.layers.get(layer))
+                    .map(|slice| slice.main_polygon.union_with(&slice.get_support_polygon()))
+                    .fold(MultiPolygon(vec![]), |a, b| a.union_with(&b))
+                    .convex_hull();
+
+                if let Some(slice) = objects
+                    .iter_mut()
+                    .find_map(|object| object.layers.get_mut(layer))
+                {
+                    slice.generate_skirt(&convex_hull, &shield, settings);
+                }
+            }
+        }
+    }
+}
+
+///Distance in mm the wipe tower keeps from everything else printed on the bed
+const WIPE_TOWER_CLEARANCE: f32 = 2.0;
+
+pub struct WipeTowerPass {}
+
+impl ObjectPass for WipeTowerPass {
+    fn pass(objects: &mut Vec<Object>, settings: &Settings) {
+        if !settings.wipe_tower.is_enabled() || objects.is_empty() {
+            return;
+        }
+
+        if settings.print_sequence == PrintSequence::ByObject {
+            warn!("No wipe tower is printed when printing one object after another");
+            return;
+        }
+
+        let wipe_tower = &settings.wipe_tower;
+        let width = wipe_tower.width;
+
+        let layer_count = objects
+            .iter()
+            .map(|object| object.layers.len())
+            .max()
+            .unwrap_or(0);
+
+        //The objects on each layer in the order they are printed, lowest first and in object order
+        //on equal heights, with the depth of every tool change purge in that order
+        //Printers start on the first extruder
+        let mut current_extruder = 0;
+        let layers: Vec<(Vec<usize>, Vec<(usize, f32)>)> = (0..layer_count)
+            .map(|layer| {
+                let mut printed: Vec<usize> = (0..objects.len())
+                    .filter(|index| layer < objects[*index].layers.len())
+                    .collect();
+                printed.sort_by(|a, b| {
+                    let a = objects[*a].layers[layer].top_height;
+                    let b = objects[*b].layers[layer].top_height;
+                    a.total_cmp(&b)
+                });
+
+                //Every tool change on the layer purges the new tool on a band of the tower deep
+                //enough to hold the purge volume
+                let mut purges = vec![];
+                for index in &printed {
+                    let object = &objects[*index];
+                    if object.extruder != current_extruder {
+                        let slice = &object.layers[layer];
+                        let height = slice.top_height - slice.bottom_height;
+                        purges.push((*index, wipe_tower.purge_volume / (height * width)));
+                        current_extruder = object.extruder;
+                    }
+                }
+
+                (printed, purges)
+            })
+            .collect();
+
+        //With tool changes the tower only has to reach the last layer with one, without any it
+        //is printed over the full height
+        let tower_layers = layers
+            .iter()
+            .rposition(|(_, purges)| !purges.is_empty())
+            .map(|layer| layer + 1)
+            .unwrap_or(layer_count);
+
+        //Everything on the bed up to the top of the tower, including skirt, brim and ooze shield
+        let keep_out = objects
+            .iter()
+            .flat_map(|object| {
+                object.layers.iter().take(tower_layers).map(|slice| {
+                    slice
+                        .fixed_chains
+                        .iter()
+                        .map(|chain| MultiPolygon(vec![Polygon::from(chain)]))
+                        .fold(
+                            slice.main_polygon.union_with(&slice.get_support_polygon()),
+                            |a, b| a.union_with(&b),
+                        )
+                })
+            })
+            .fold(MultiPolygon(vec![]), |a, b| a.union_with(&b))
+            .convex_hull()
+            .offset_from(WIPE_TOWER_CLEARANCE);
+
+        //The configured position, or else the first bed corner with room for the tower
+        let corners = [
+            (0.0, 0.0),
+            (settings.print_x - width, 0.0),
+            (0.0, settings.print_y - width),
+            (settings.print_x - width, settings.print_y - width),
+        ];
+        let tower_at = |x: f32, y: f32| {
+            Rect::new(
+                Coord { x, y },
+                Coord {
+                    x: x + width,
+                    y: y + width,
+                },
+            )
+            .to_polygon()
+        };
+        let position = std::iter::once((wipe_tower.x, wipe_tower.y))
+            .chain(corners)
+            .filter(|(x, y)| {
+                *x >= 0.0
+                    && *y >= 0.0
+                    && x + width <= settings.print_x
+                    && y + width <= settings.print_y
+            })
+            .find(|(x, y)| !keep_out.intersects(&tower_at(*x, *y)));
+
+        let (x, y) = match position {
+            Some((x, y)) => {
+                if (x, y) != (wipe_tower.x, wipe_tower.y) {
+                    info!("The wipe tower was moved to a corner of the bed clear of the objects");
+                }
+                (x, y)
+            }
+            None => {
+                warn!("No room on the bed for the wipe tower");
+                return;
+            }
+        };
+        let band = |front: f32, depth: f32| {
+            Rect::new(
+                Coord { x, y: front },
+                Coord {
+                    x: x + width,
+                    y: front + depth,
+                },
+            )
+            .to_polygon()
+        };
+
+        let mut purge_clipped = false;
+
+        for (layer, (printed, purges)) in layers.into_iter().take(tower_layers).enumerate() {
+            let total_depth: f32 = purges.iter().map(|(_, depth)| depth).sum();
+            let scale = if total_depth > width {
+                purge_clipped = true;
+                width / total_depth
+            } else {
+                1.0
+            };
+
+            let mut front = y;
+            for (index, depth) in purges {
+                let depth = depth * scale;
+                if depth > 0.0 {
+                    objects[index].layers[layer].generate_wipe_tower(&band(front, depth));
+                    front += depth;
+                }
+            }
+
+            //The first object on the layer prints first, so it carries the rest of the tower
+            if let Some(first) = printed.first() {
+                if front < y + width {
+                    objects[*first].layers[layer]
+                        .generate_wipe_tower(&band(front, y + width - front));
+                }
+            }
+        }
+
+        if purge_clipped {
+            warn!("The wipe tower is too small for the purge volume of every tool change");
+        }
+    }
+}
+
+pub struct ObjectPrimePass {}
+
+impl ObjectPass for ObjectPrimePass {
+    fn pass(objects: &mut Vec<Object>, settings: &Settings) {
+        if !settings.object_prime.is_enabled() || settings.print_sequence != PrintSequence::ByObject
+        {
+            return;
+        }
+
+        let object_prime = &settings.object_prime;
+
+        //The first layer of each object including its skirt and brim
+        let footprints: Vec<Option<Rect<f32>>> = objects
+            .iter()
+            .map(|object| {
+                object.layers.first().and_then(|slice| {
+                    slice
+                        .fixed_chains
+                        .iter()
+                        .map(|chain| MultiPolygon(vec![Polygon::from(chain)]))
+                        .fold(slice.main_polygon.clone(), |a, b| a.union_with(&b))
+                        .bounding_rect()
+                })
+            })
+            .collect();
+
+        for (index, object) in objects.iter_mut().enumerate() {
+            let length = object_prime
+                .object_lengths
+                .get(index)
+                .copied()
+                .unwrap_or(object_prime.length);
+
+            let first_layer = object.layers.first_mut().filter(|slice| slice.layer == 0);
+            let (Some(slice), Some(footprint)) = (first_layer, footprints[index]) else {
+                continue;
+            };
+
+            if length <= 0.0 {
+                continue;
+            }
+
+            let x = footprint.min().x.min(settings.print_x - length).max(0.0);
+            let prime_at = |y: f32| {
+                Line::new(
+                    Coord { x, y },
+                    Coord {
+                        x: (x + length).min(settings.print_x),
+                        y,
+                    },
+                )
+            };
+
+            //In front of the object, or behind it if the front is off the bed or over another object
+            let front = footprint.min().y - object_prime.distance;
+            let back = footprint.max().y + object_prime.distance;
+            let prime = [front, back]
+                .into_iter()
+                .filter(|y| (0.0..=settings.print_y).contains(y))
+                .map(prime_at)
+                .find(|line| {
+                    footprints
+                        .iter()
+                        .enumerate()
+                        .filter(|(other, _)| *other != index)
+                        .filter_map(|(_, footprint)| footprint.as_ref())
+                        .all(|footprint| !footprint.intersects(line))
+                });
+
+            match prime {
+                Some(line) => slice.generate_prime(line.start, line.end),
+                None => warn!("No room on the bed to prime object {}", index),
+            }
+        }
+    }
+}
+
 pub trait SlicePass {
     fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors>;
 }
@@ -176,16 +592,85 @@ impl SlicePass for WallPass {
             .parse_range()
             .unwrap_or((&settings.fiber.wall_pattern.wall_ranges, Vec::new()));
 
+        let slice_count = slices.len();
+
         slices
             .par_iter_mut()
             .enumerate()
             .for_each(|(layer_num, slice)| {
-                slice.slice_walls_into_chains(
-                    settings.number_of_perimeters,
-                    &wall_ranges,
-                    layer_num,
-                );
+                //The top and bottom layers are backed by solid infill and can do with fewer walls
+                let number_of_perimeters = if settings.outline_only || settings.spiralize {
+                    1
+                } else if settings.top_bottom_perimeters.is_enabled()
+                    && is_top_or_bottom_layer(layer_num, slice_count, settings)
+                {
+                    *settings.top_bottom_perimeters
+                } else {
+                    settings.number_of_perimeters
+                };
+
+                slice.slice_walls_into_chains(number_of_perimeters, &wall_ranges, layer_num);
+            });
+
+        //Seams placed relative to other loops need all the walls, and the layer below, first
+        slices
+            .iter_mut()
+            .enumerate()
+            .fold(vec![], |previous_seams, (layer_num, slice)| {
+                slice.place_seams(&previous_seams, layer_num)
+            });
+        Ok(())
+    }
+}
+
+pub struct SpiralizePass {}
+
+impl SlicePass for SpiralizePass {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
+        //The wall of a spiral can only follow a single contour without jumping
+        if settings.spiralize {
+            if let Some((layer, contours)) = slices
+                .iter()
+                .map(|slice| {
+                    let contours = slice
+                        .main_polygon
+                        .iter()
+                        .map(|poly| 1 + poly.interiors().len())
+                        .sum::<usize>();
+                    (slice.layer, contours)
+                })
+                .find(|(_, contours)| *contours != 1)
+            {
+                return Err(SlicerErrors::SpiralizeContourCount { layer, contours });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct VariableWidthWallPass {}
+
+impl SlicePass for VariableWidthWallPass {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
+        if settings.variable_width_walls && !settings.spiralize {
+            slices.par_iter_mut().for_each(|slice| {
+                slice.slice_thin_walls_into_chains();
             });
+        }
+        Ok(())
+    }
+}
+
+pub struct GapFillPass {}
+
+impl SlicePass for GapFillPass {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
+        if settings.gap_fill.is_enabled() {
+            // display_state_update("Generating Moves: Gap Fill", send_messages);
+            slices.par_iter_mut().for_each(|slice| {
+                slice.fill_gaps(settings.nozzle_diameter / 2.0, *settings.gap_fill);
+            });
+        }
         Ok(())
     }
 }
@@ -193,12 +678,12 @@ impl SlicePass for WallPass {
 pub struct BridgingPass {}
 
 impl SlicePass for BridgingPass {
-    fn pass(slices: &mut Vec<Slice>, _settings: &Settings) -> Result<(), SlicerErrors> {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
         // display_state_update("Generating Moves: Bridging", send_messages);
         (1..slices.len()).for_each(|q| {
             let below = slices[q - 1].main_polygon.clone();
 
-            slices[q].fill_solid_bridge_area(&below, &PassContext::new().without_fiber());
+            slices[q].fill_solid_bridge_area(&below, settings, &PassContext::new().without_fiber());
         });
         Ok(())
     }
@@ -206,9 +691,18 @@ impl SlicePass for BridgingPass {
 pub struct TopLayerPass {}
 
 impl SlicePass for TopLayerPass {
-    fn pass(slices: &mut Vec<Slice>, _settings: &Settings) -> Result<(), SlicerErrors> {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
         // display_state_update("Generating Moves: Top Layer", send_messages);
-        (0..slices.len() - 1).for_each(|q| {
+        let slice_count = slices.len();
+
+        (0..slice_count - 1).for_each(|q| {
+            //Thin layers are filled entirely by the top and bottom pass unless both fills apply
+            if is_thin_layer(q, slice_count, settings)
+                && settings.thin_part_solid_policy != ThinPartSolidPolicy::BothSolid
+            {
+                return;
+            }
+
             let above = slices[q + 1].main_polygon.clone();
 
             slices[q].fill_solid_top_layer(&above, q, &PassContext::new().without_fiber());
@@ -285,26 +779,133 @@ impl SlicePass for TopAndBottomLayersPass {
         slices
             .par_iter_mut()
             .enumerate()
-            .filter(|(layer_num, _)| {
-                *layer_num < settings.bottom_layers
-                    || settings.top_layers + *layer_num + 1 > slice_count
-            })
+            .filter(|(layer_num, _)| is_top_or_bottom_layer(*layer_num, slice_count, settings))
             .for_each(|(layer_num, slice)| {
-                slice.fill_remaining_area(true, layer_num, &PassContext::new().without_fiber());
+                if is_thin_layer(layer_num, slice_count, settings)
+                    && settings.thin_part_solid_policy == ThinPartSolidPolicy::TopWins
+                {
+                    //Nothing is above, so the entire remaining area is filled as top surface
+                    slice.fill_solid_top_layer(
+                        &MultiPolygon(vec![]),
+                        layer_num,
+                        &PassContext::new().without_fiber(),
+                    );
+                } else {
+                    //Nothing is printed above the last layer, all of it is top surface
+                    if layer_num + 1 == slice_count {
+                        slice.top_surface = slice.remaining_area.clone();
+                    }
+                    slice.fill_remaining_area(true, layer_num, &PassContext::new().without_fiber());
+                }
             });
         Ok(())
     }
 }
 
+pub struct IroningPass {}
+
+impl SlicePass for IroningPass {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
+        if settings.ironing.is_enabled() {
+            slices
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(layer_num, slice)| {
+                    slice.iron_top_surface(&settings.ironing, layer_num);
+                });
+        }
+        Ok(())
+    }
+}
+
+///Fills the layers right below the solid top layers with infill that steps up from the sparse
+///density to solid, so the top surface isn't bridged over sparse infill
+pub struct TopTransitionPass {}
+
+impl SlicePass for TopTransitionPass {
+    fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
+        let transition_layers = settings.top_transition_layers;
+        if transition_layers == 0 {
+            return Ok(());
+        }
+
+        let top_layers = settings.top_layers;
+
+        //For each layer, the areas whose top surface is 1 to transition_layers layers above the
+        //solid top layers. Layers past the top of the object cover nothing, so only areas below
+        //top surfaces are ever part of a transition.
+        let transitions: Vec<Vec<MultiPolygon<f32>>> = (0..slices.len())
+            .into_par_iter()
+            .map(|q| {
+                let mut covered = vec![slices[q].main_polygon.clone()];
+                for n in 1..=top_layers + transition_layers {
+                    let next = match slices.get(q + n) {
+                        Some(slice) => covered[n - 1].intersection_with(&slice.main_polygon),
+                        None => MultiPolygon(vec![]),
+                    };
+                    covered.push(next);
+                }
+
+                covered[top_layers..]
+                    .windows(2)
+                    .map(|pair| pair[0].difference_with(&pair[1]))
+                    .collect()
+            })
+            .collect();
+
+        slices.par_iter_mut().zip(transitions).enumerate().for_each(
+            |(layer_num, (slice, transitions))| {
+                for (step, area) in transitions.iter().enumerate() {
+                    let fill_ratio = transition_density(
+                        step + 1,
+                        transition_layers,
+                        slice.layer_settings.infill_percentage,
+                    );
+
+                    slice.fill_transition_area(
+                        area,
+                        fill_ratio,
+                        layer_num,
+                        &PassContext::new().without_fiber(),
+                    );
+                }
+            },
+        );
+        Ok(())
+    }
+}
+
+///Density of the transition layer `step` layers below the solid top layers. The density steps up
+///evenly from sparse at the bottom of the transition to solid right below the top layers.
+fn transition_density(step: usize, transition_layers: usize, sparse: f32) -> f32 {
+    let solid_fraction = (transition_layers + 1 - step) as f32 / (transition_layers + 1) as f32;
+    sparse + (1.0 - sparse) * solid_fraction
+}
+
+///Whether the layer is within both the bottom layers and the top layers of the object
+fn is_thin_layer(layer_num: usize, slice_count: usize, settings: &Settings) -> bool {
+    layer_num < settings.bottom_layers && settings.top_layers + layer_num + 1 > slice_count
+}
+
+fn is_top_or_bottom_layer(layer_num: usize, slice_count: usize, settings: &Settings) -> bool {
+    layer_num < settings.bottom_layers || settings.top_layers + layer_num + 1 > slice_count
+}
+
 pub struct SupportPass {}
 
 impl SlicePass for SupportPass {
     fn pass(slices: &mut Vec<Slice>, settings: &Settings) -> Result<(), SlicerErrors> {
-        if settings.support.is_enabled() {
+        //Long bridges are supported even if support is otherwise disabled
+        if settings.support.is_enabled() || settings.max_bridge_length.is_enabled() {
             let support = &settings.support;
+            let column_ranges = support_column_ranges(slices);
+            let interfaces =
+                support_interface_areas(slices, support.interface_layers, support.z_gap);
 
-            for slice in slices.iter_mut() {
-                slice.fill_support_polygons(support);
+            for ((slice, column_ranges), interface) in
+                slices.iter_mut().zip(&column_ranges).zip(&interfaces)
+            {
+                slice.fill_support_polygons(support, column_ranges, interface);
             }
         }
         Ok(())
@@ -320,12 +921,13 @@ impl SlicePass for FiberInfillPass {
             let width = settings.fiber.infill.width;
             let spacing = settings.fiber.infill.spacing;
             let cycle_length = width + spacing;
+            let is_fiber_layer = |layer_num: usize| ((layer_num + 1) % cycle_length) >= spacing;
 
             slices
                 .par_iter_mut()
                 .enumerate()
                 .for_each(|(layer_num, slice)| {
-                    if ((layer_num + 1) % cycle_length) < spacing {
+                    if !is_fiber_layer(layer_num) {
                         if !settings.fiber.infill.air_space {
                             info!("Filling remaining area without fiber");
                             slice.fill_remaining_area_partially(
@@ -335,11 +937,30 @@ impl SlicePass for FiberInfillPass {
                             );
                         }
                     } else {
+                        let first_fiber_chain = slice.chains.len();
+
                         slice.fill_remaining_area_partially(
                             layer_num,
                             settings.fiber.infill.infill_percentage,
                             &PassContext::new().with_fiber(),
                         );
+
+                        if settings.fiber.wall_infill_overlap > 0.0 {
+                            anchor_fiber_infill(
+                                slice,
+                                first_fiber_chain,
+                                settings.fiber.wall_infill_overlap,
+                            );
+                        }
+
+                        //Sweep every other fiber layer from the opposite end
+                        let fiber_layers_below =
+                            (0..layer_num).filter(|l| is_fiber_layer(*l)).count();
+                        if settings.fiber.alternate_direction && fiber_layers_below % 2 == 1 {
+                            slice.chains[first_fiber_chain..]
+                                .iter_mut()
+                                .for_each(|chain| chain.reverse());
+                        }
                     }
                 });
         }
@@ -348,6 +969,80 @@ impl SlicePass for FiberInfillPass {
     }
 }
 
+///Extend the ends of the fiber infill chains straight into the fiber walls so they bond. An end is
+///only extended if the extended point lies within a fiber wall bead. The extension continues the
+///last segment in the same direction, so it adds no bend and no cut to the fiber.
+fn anchor_fiber_infill(slice: &mut Slice, first_fiber_chain: usize, overlap: f32) {
+    let fiber_walls: Vec<(Line<f32>, f32)> = slice
+        .fixed_chains
+        .iter()
+        .flat_map(|chain| {
+            chain
+                .moves
+                .iter()
+                .scan(chain.start_point, |position, m| {
+                    let line = Line::new(*position, m.end);
+                    *position = m.end;
+                    Some((line, m))
+                })
+                .filter(|(_, m)| {
+                    matches!(
+                        m.move_type,
+                        MoveType::WithFiber(
+                            TraceType::WallOuter
+                                | TraceType::WallInner
+                                | TraceType::InteriorWallOuter
+                                | TraceType::InteriorWallInner
+                        )
+                    )
+                })
+                .map(|(line, m)| (line, m.width))
+        })
+        .collect();
+
+    if fiber_walls.is_empty() {
+        return;
+    }
+
+    let extend = |from: Coord<f32>, to: Coord<f32>| {
+        let length = from.euclidean_distance(&to);
+        if length < f32::EPSILON {
+            return None;
+        }
+
+        let extended = to + (to - from) * (overlap / length);
+        fiber_walls
+            .iter()
+            .any(|(line, width)| line.euclidean_distance(&Point(extended)) <= width / 2.0)
+            .then_some(extended)
+    };
+
+    for chain in slice.chains[first_fiber_chain..].iter_mut() {
+        let Some(first) = chain.moves.first() else {
+            continue;
+        };
+
+        if matches!(first.move_type, MoveType::WithFiber(_)) {
+            if let Some(start) = extend(first.end, chain.start_point) {
+                chain.start_point = start;
+            }
+        }
+
+        let last_index = chain.moves.len() - 1;
+        let from = last_index
+            .checked_sub(1)
+            .map(|i| chain.moves[i].end)
+            .unwrap_or(chain.start_point);
+        let last = &mut chain.moves[last_index];
+
+        if matches!(last.move_type, MoveType::WithFiber(_)) {
+            if let Some(end) = extend(from, last.end) {
+                last.end = end;
+            }
+        }
+    }
+}
+
 pub struct FillAreaPass {}
 
 impl SlicePass for FillAreaPass {
@@ -370,17 +1065,21 @@ impl SlicePass for FillAreaPass {
                         true
                     };
 
-                    slice.fill_remaining_area(
-                        fiber,
-                        layer_num,
-                        &PassContext::new().without_fiber(),
-                    );
+                    fill_with_modifiers(slice, |slice| {
+                        slice.fill_remaining_area(
+                            fiber,
+                            layer_num,
+                            &PassContext::new().without_fiber(),
+                        )
+                    });
                 } else {
-                    slice.fill_remaining_area(
-                        false,
-                        layer_num,
-                        &PassContext::new().without_fiber(),
-                    );
+                    fill_with_modifiers(slice, |slice| {
+                        slice.fill_remaining_area(
+                            false,
+                            layer_num,
+                            &PassContext::new().without_fiber(),
+                        )
+                    });
                 }
             });
         Ok(())
@@ -412,3 +1111,1048 @@ impl SlicePass for OrderPass {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoveChain, SupportInterfacePattern};
+    use itertools::Itertools;
+
+    fn fiber_sweeps(settings: &Settings) -> Vec<(f32, f32)> {
+        let mut slices = (0..2)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        FiberInfillPass::pass(&mut slices, settings).unwrap();
+
+        //Start and end y of the fiber on each layer
+        slices
+            .iter()
+            .map(|slice| {
+                let chain = slice
+                    .chains
+                    .iter()
+                    .find(|chain| {
+                        chain
+                            .moves
+                            .iter()
+                            .any(|m| matches!(m.move_type, MoveType::WithFiber(_)))
+                    })
+                    .expect("No fiber infill generated");
+                (chain.start_point.y, chain.moves.last().unwrap().end.y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fiber_infill_anchors_into_fiber_walls() {
+        let sliced = |overlap: f32| {
+            let mut settings = Settings::default();
+            settings.fiber.wall_pattern.pattern = crate::fiber::WallPatternType::Full;
+            settings.fiber.wall_infill_overlap = overlap;
+
+            let mut slices = vec![Slice::from_single_point_loop(
+                vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                0.0,
+                0.2,
+                0,
+                &settings,
+            )];
+
+            WallPass::pass(&mut slices, &settings).unwrap();
+            FiberInfillPass::pass(&mut slices, &settings).unwrap();
+            slices.remove(0)
+        };
+        let infill_ends = |slice: &Slice| -> Vec<[Coord<f32>; 2]> {
+            slice
+                .chains
+                .iter()
+                .filter(|chain| {
+                    chain
+                        .moves
+                        .iter()
+                        .any(|m| m.move_type == MoveType::WithFiber(TraceType::Infill))
+                })
+                .map(|chain| [chain.start_point, chain.moves.last().unwrap().end])
+                .collect()
+        };
+
+        let slice = sliced(0.2);
+        let fiber_walls: Vec<(Line<f32>, f32)> = slice
+            .fixed_chains
+            .iter()
+            .flat_map(|chain| {
+                std::iter::once(chain.start_point)
+                    .chain(chain.moves.iter().map(|m| m.end))
+                    .tuple_windows()
+                    .zip(chain.moves.iter())
+                    .filter(|(_, m)| matches!(m.move_type, MoveType::WithFiber(_)))
+                    .map(|((start, end), m)| (Line::new(start, end), m.width))
+            })
+            .collect();
+        assert!(!fiber_walls.is_empty());
+
+        let anchored = infill_ends(&slice);
+        assert!(!anchored.is_empty());
+
+        for end in anchored.iter().flatten() {
+            assert!(
+                fiber_walls.iter().any(|(line, width)| {
+                    line.euclidean_distance(&Point(*end)) <= width / 2.0 + 0.001
+                }),
+                "Fiber infill end {:?} is not in a fiber wall",
+                end
+            );
+        }
+
+        //Without the overlap the same chains stop short by the overlap at both ends
+        let baseline = infill_ends(&sliced(0.0));
+        assert_eq!(baseline.len(), anchored.len());
+        for (before, after) in baseline.iter().flatten().zip(anchored.iter().flatten()) {
+            let extension = before.euclidean_distance(after);
+            assert!((extension - 0.2).abs() < 0.001, "{}", extension);
+        }
+    }
+
+    fn square_object(x: f32, y: f32, size: f32, settings: &Settings) -> Object {
+        Object {
+            layers: vec![Slice::from_single_point_loop(
+                vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)].into_iter(),
+                0.0,
+                0.3,
+                0,
+                settings,
+            )],
+            extruder: 0,
+        }
+    }
+
+    #[test]
+    fn auto_brim_for_small_contact() {
+        let mut settings = Settings::default();
+        *settings.auto_brim_min_contact_area.enabled_mut() = true;
+        *settings.auto_brim_min_contact_area = 50.0;
+
+        let mut objects = vec![
+            square_object(100.0, 100.0, 2.0, &settings),
+            square_object(20.0, 20.0, 20.0, &settings),
+            square_object(0.5, 50.0, 2.0, &settings),
+        ];
+
+        BrimPass::pass(&mut objects, &settings);
+
+        //Just enough lines to reach the target area
+        let small = &objects[0].layers[0];
+        let lines = small.fixed_chains.len() as f32;
+        let contact = |lines: f32| small.main_polygon.offset_from(lines * 0.4).unsigned_area();
+        assert!(lines > 0.0);
+        assert!(contact(lines) >= 50.0);
+        assert!(contact(lines - 1.0) < 50.0);
+
+        assert!(objects[1].layers[0].fixed_chains.is_empty());
+
+        //Limited by the edge of the bed
+        let edge = &objects[2].layers[0];
+        assert_eq!(edge.fixed_chains.len(), 1);
+        assert!(edge
+            .fixed_chains
+            .iter()
+            .flat_map(|chain| chain.moves.iter())
+            .all(|m| m.end.x >= 0.0));
+    }
+
+    #[test]
+    fn fiber_alternate_direction() {
+        let mut settings = Settings::default();
+        settings.fiber.infill.spacing = 0;
+
+        let sweeps = fiber_sweeps(&settings);
+        assert_eq!(sweeps[0], sweeps[1]);
+
+        settings.fiber.alternate_direction = true;
+        let sweeps = fiber_sweeps(&settings);
+        let (start, end) = sweeps[0];
+        let (reversed_start, reversed_end) = sweeps[1];
+        assert!((start - end).abs() > 10.0);
+        assert_eq!(reversed_start, end);
+        assert_eq!(reversed_end, start);
+    }
+
+    fn bridged_pillars(gap: f32, settings: &Settings) -> Object {
+        let square = |min_x: f32, max_x: f32| {
+            LineString::from(vec![
+                (min_x, 0.0),
+                (max_x, 0.0),
+                (max_x, 10.0),
+                (min_x, 10.0),
+            ])
+        };
+
+        //Two pillars for three layers with a deck spanning the gap on the fourth
+        let layers = (0..4)
+            .map(|layer| {
+                let outlines = if layer < 3 {
+                    vec![square(0.0, 5.0), square(5.0 + gap, 10.0 + gap)]
+                } else {
+                    vec![square(0.0, 10.0 + gap)]
+                };
+
+                Slice::from_multiple_point_loop(
+                    MultiLineString(outlines),
+                    layer as f32 * 0.3,
+                    (layer + 1) as f32 * 0.3,
+                    layer,
+                    settings,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        Object {
+            layers,
+            extruder: 0,
+        }
+    }
+
+    #[test]
+    fn long_bridges_are_supported() {
+        let mut settings = Settings::default();
+        *settings.max_bridge_length.enabled_mut() = true;
+        *settings.max_bridge_length = 20.0;
+
+        let has_trace = |chains: &[MoveChain], trace: TraceType| {
+            chains
+                .iter()
+                .flat_map(|chain| chain.moves.iter())
+                .any(|m| m.move_type == MoveType::WithoutFiber(trace))
+        };
+
+        for (gap, expect_support) in [(25.0, true), (21.0, true), (19.0, false), (5.0, false)] {
+            let mut objects = vec![bridged_pillars(gap, &settings)];
+            SupportTowerPass::pass(&mut objects, &settings);
+
+            let slices = &mut objects[0].layers;
+            BridgingPass::pass(slices, &settings).unwrap();
+            SupportPass::pass(slices, &settings).unwrap();
+
+            let deck = &slices[3];
+            assert_eq!(
+                has_trace(&deck.chains, TraceType::Bridging),
+                !expect_support,
+                "Gap of {gap}mm"
+            );
+
+            for slice in &slices[..3] {
+                assert_eq!(
+                    has_trace(&slice.fixed_chains, TraceType::Support),
+                    expect_support,
+                    "Gap of {gap}mm on layer {}",
+                    slice.layer
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn support_density_follows_column_height() {
+        let mut settings = Settings::default();
+        *settings.support.enabled_mut() = true;
+        settings.support.support_density_bottom = 0.25;
+        settings.support.support_density_top = 1.0;
+
+        let square = |x: f32| {
+            MultiPolygon(vec![Polygon::new(
+                LineString::from(vec![(x, 0.0), (x + 20.0, 0.0), (x + 20.0, 20.0), (x, 20.0)]),
+                vec![],
+            )])
+        };
+
+        //A tall column on layers 0 to 8 and a short one on layers 4 to 8, next to a small model
+        let mut slices = (0..9)
+            .map(|layer| {
+                let mut slice = Slice::from_single_point_loop(
+                    vec![
+                        (100.0, 100.0),
+                        (101.0, 100.0),
+                        (101.0, 101.0),
+                        (100.0, 101.0),
+                    ]
+                    .into_iter(),
+                    layer as f32 * 0.3,
+                    (layer + 1) as f32 * 0.3,
+                    layer,
+                    &settings,
+                );
+
+                let mut tower = square(0.0);
+                if layer >= 4 {
+                    tower = tower.union_with(&square(40.0));
+                }
+                slice.support_tower = Some(tower);
+                slice
+            })
+            .collect::<Vec<_>>();
+
+        SupportPass::pass(&mut slices, &settings).unwrap();
+
+        //Length of the support extruded in the column starting at min_x
+        let support_length = |layer: usize, min_x: f32| -> f32 {
+            slices[layer]
+                .fixed_chains
+                .iter()
+                .filter(|chain| chain.start_point.x >= min_x && chain.start_point.x < min_x + 30.0)
+                .map(|chain| {
+                    chain
+                        .moves
+                        .iter()
+                        .fold((chain.start_point, 0.0), |(start, length), m| {
+                            let support = m.move_type == MoveType::WithoutFiber(TraceType::Support);
+                            let segment = if support {
+                                start.euclidean_distance(&m.end)
+                            } else {
+                                0.0
+                            };
+                            (m.end, length + segment)
+                        })
+                        .1
+                })
+                .sum()
+        };
+
+        assert!(support_length(0, 0.0) * 2.0 < support_length(8, 0.0));
+
+        //The short column is as sparse at its own bottom as the tall one is at the bed
+        assert!(support_length(4, 40.0) < support_length(4, 0.0));
+        assert!(
+            (support_length(4, 40.0) - support_length(0, 0.0)).abs() < support_length(0, 0.0) * 0.5
+        );
+        assert!(
+            (support_length(8, 40.0) - support_length(8, 0.0)).abs()
+                < support_length(8, 0.0) * 0.15
+        );
+    }
+
+    #[test]
+    fn support_interface_uses_its_own_pattern() {
+        let mut settings = Settings::default();
+        *settings.support.enabled_mut() = true;
+        settings.support.interface_layers = 2;
+        settings.support.interface_pattern = SupportInterfacePattern::Grid;
+
+        let plate = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+            vec![],
+        )]);
+
+        //A small pillar with a plate hanging in the air on the last layer
+        let layers = (0..10)
+            .map(|layer| {
+                let mut slice = Slice::from_single_point_loop(
+                    vec![(40.0, 40.0), (41.0, 40.0), (41.0, 41.0), (40.0, 41.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                );
+
+                if layer == 9 {
+                    slice.main_polygon = slice.main_polygon.union_with(&plate);
+                    slice.remaining_area = slice.main_polygon.clone();
+                }
+                slice
+            })
+            .collect();
+
+        let mut objects = vec![Object {
+            layers,
+            extruder: 0,
+        }];
+        SupportTowerPass::pass(&mut objects, &settings);
+        let slices = &mut objects[0].layers;
+        SupportPass::pass(slices, &settings).unwrap();
+
+        //Number of long support lines along x and along y
+        let directions = |layer: usize| {
+            slices[layer]
+                .fixed_chains
+                .iter()
+                .flat_map(|chain| {
+                    let starts =
+                        std::iter::once(chain.start_point).chain(chain.moves.iter().map(|m| m.end));
+                    starts.zip(chain.moves.iter())
+                })
+                .filter(|(_, m)| m.move_type == MoveType::WithoutFiber(TraceType::Support))
+                .fold((0, 0), |(along_x, along_y), (start, m)| {
+                    let (dx, dy) = ((m.end.x - start.x).abs(), (m.end.y - start.y).abs());
+                    (
+                        along_x + usize::from(dx > 5.0 && dy < 0.01),
+                        along_y + usize::from(dy > 5.0 && dx < 0.01),
+                    )
+                })
+        };
+
+        //The two layers below the overhang are a grid, the rest of the support are ribs along y
+        for layer in [6, 7] {
+            let (along_x, along_y) = directions(layer);
+            assert!(
+                along_x > 10 && along_y > 10,
+                "{along_x} {along_y} on {layer}"
+            );
+        }
+        for layer in [1, 3, 5] {
+            let (along_x, along_y) = directions(layer);
+            assert_eq!(along_x, 0, "Lines along x on {layer}");
+            assert!(along_y > 5, "{along_y} on {layer}");
+        }
+
+        //Interface lines are denser than the ribs
+        assert!(directions(7).1 > directions(5).1);
+    }
+
+    #[test]
+    fn support_z_gap_lowers_the_interface() {
+        let plate = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)]),
+            vec![],
+        )]);
+
+        let support_slices = |z_gap: f32| {
+            let mut settings = Settings::default();
+            *settings.support.enabled_mut() = true;
+            settings.support.interface_layers = 2;
+            settings.support.z_gap = z_gap;
+
+            //A small pillar with a plate hanging in the air on the last layer
+            let layers = (0..10)
+                .map(|layer| {
+                    let mut slice = Slice::from_single_point_loop(
+                        vec![(9.0, 9.0), (10.0, 9.0), (10.0, 10.0), (9.0, 10.0)].into_iter(),
+                        layer as f32 * 0.2,
+                        (layer + 1) as f32 * 0.2,
+                        layer,
+                        &settings,
+                    );
+
+                    if layer == 9 {
+                        slice.main_polygon = slice.main_polygon.union_with(&plate);
+                        slice.remaining_area = slice.main_polygon.clone();
+                    }
+                    slice
+                })
+                .collect();
+
+            let mut objects = vec![Object {
+                layers,
+                extruder: 0,
+            }];
+            SupportTowerPass::pass(&mut objects, &settings);
+            let slices = objects.remove(0).layers;
+            let interfaces = support_interface_areas(&slices, 2, z_gap);
+            (slices, interfaces)
+        };
+
+        let below_plate = |slice: &Slice| {
+            slice
+                .support_tower
+                .as_ref()
+                .map(|tower| tower.intersection_with(&plate).unsigned_area())
+                .unwrap_or(0.0)
+        };
+
+        //Without a gap the support touches the plate
+        let (slices, interfaces) = support_slices(0.0);
+        assert!(below_plate(&slices[8]) > 300.0);
+        assert!(interfaces[8].unsigned_area() > 300.0);
+        assert!(interfaces[6].0.is_empty());
+
+        //With a gap of two layers, the top two layers of support are gone and the interface moves down
+        let (slices, interfaces) = support_slices(0.4);
+        assert!(below_plate(&slices[8]) < 0.001);
+        assert!(below_plate(&slices[7]) < 0.001);
+        assert!(below_plate(&slices[6]) > 300.0);
+        assert!(interfaces[7].0.is_empty());
+        assert!(interfaces[6].unsigned_area() > 300.0);
+        assert!(interfaces[5].unsigned_area() > 300.0);
+        assert!(interfaces[4].0.is_empty());
+
+        //The interface is only the part of the support right below the plate
+        let tower = slices[6].support_tower.as_ref().unwrap();
+        assert!(interfaces[6].unsigned_area() < tower.unsigned_area());
+        assert!(interfaces[6].difference_with(&plate).unsigned_area() < 0.001);
+    }
+
+    #[test]
+    fn tree_support_branches_around_the_model() {
+        let square = |min: f32, max: f32| {
+            MultiPolygon(vec![Polygon::new(
+                LineString::from(vec![(min, min), (max, min), (max, max), (min, max)]),
+                vec![],
+            )])
+        };
+
+        //A plate on a thin pillar, with a short block under one corner of it
+        let support_objects = |style: SupportStyle| {
+            let mut settings = Settings::default();
+            *settings.support.enabled_mut() = true;
+            settings.support.style = style;
+
+            let layers = (0..30)
+                .map(|layer| {
+                    let mut slice = Slice::from_single_point_loop(
+                        vec![(14.0, 14.0), (16.0, 14.0), (16.0, 16.0), (14.0, 16.0)].into_iter(),
+                        layer as f32 * 0.2,
+                        (layer + 1) as f32 * 0.2,
+                        layer,
+                        &settings,
+                    );
+
+                    if layer < 15 {
+                        slice.main_polygon = slice.main_polygon.union_with(&square(2.0, 6.0));
+                    }
+                    if layer == 29 {
+                        slice.main_polygon = slice.main_polygon.union_with(&square(0.0, 30.0));
+                    }
+                    slice.remaining_area = slice.main_polygon.clone();
+                    slice
+                })
+                .collect();
+
+            let mut objects = vec![Object {
+                layers,
+                extruder: 0,
+            }];
+            SupportTowerPass::pass(&mut objects, &settings);
+            SupportPass::pass(&mut objects[0].layers, &settings).unwrap();
+            objects.remove(0).layers
+        };
+
+        let tree = support_objects(SupportStyle::Tree);
+        let grid = support_objects(SupportStyle::Grid);
+
+        for slice in &tree {
+            let tower = slice.support_tower.clone().unwrap_or(MultiPolygon(vec![]));
+            assert!(
+                tower.intersection_with(&slice.main_polygon).unsigned_area() < 0.001,
+                "Branches inside the model on {}",
+                slice.get_height()
+            );
+        }
+
+        let area = |slices: &[Slice]| {
+            slices[0]
+                .support_tower
+                .as_ref()
+                .map(|tower| tower.unsigned_area())
+                .unwrap_or(0.0)
+        };
+        assert!(area(&tree) > 0.0);
+        assert!(area(&tree) < area(&grid));
+
+        assert!(tree[0].fixed_chains.iter().any(|chain| chain
+            .moves
+            .iter()
+            .any(|m| m.move_type == MoveType::WithoutFiber(TraceType::Support))));
+    }
+
+    #[test]
+    fn top_transition_steps_density_up_to_solid() {
+        let mut settings = Settings::default();
+        settings.top_layers = 2;
+        settings.bottom_layers = 3;
+        settings.top_transition_layers = 3;
+
+        let mut slices = (0..12)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        TopAndBottomLayersPass::pass(&mut slices, &settings).unwrap();
+        TopTransitionPass::pass(&mut slices, &settings).unwrap();
+        FillAreaPass::pass(&mut slices, &settings).unwrap();
+
+        //Length of the infill extruded on a layer, the area is the same on every layer
+        let infill_length = |layer: usize| -> f32 {
+            slices[layer]
+                .chains
+                .iter()
+                .map(|chain| {
+                    chain
+                        .moves
+                        .iter()
+                        .fold((chain.start_point, 0.0), |(start, length), m| {
+                            let infill = matches!(
+                                m.move_type,
+                                MoveType::WithoutFiber(TraceType::Infill | TraceType::SolidInfill)
+                            );
+                            let segment = if infill {
+                                start.euclidean_distance(&m.end)
+                            } else {
+                                0.0
+                            };
+                            (m.end, length + segment)
+                        })
+                        .1
+                })
+                .sum()
+        };
+
+        let sparse = infill_length(5);
+        let solid = infill_length(10);
+
+        //Layers 7 to 9 sit below the solid top layers and increase in density towards them
+        let transition = [7, 8, 9].map(infill_length);
+        assert!(sparse < transition[0], "{} {:?}", sparse, transition);
+        assert!(transition[0] < transition[1] && transition[1] < transition[2]);
+        assert!(transition[2] < solid, "{:?} {}", transition, solid);
+
+        //Nothing changes above the bottom layers
+        assert!((infill_length(3) - sparse).abs() < sparse * 0.1);
+
+        let densities = (1..=3)
+            .map(|step| transition_density(step, 3, settings.infill_percentage))
+            .collect::<Vec<_>>();
+        assert!(densities
+            .iter()
+            .all(|density| *density > settings.infill_percentage && *density < 1.0));
+        assert!(densities.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn solid_infill_every_n_layers() {
+        let mut settings = Settings::default();
+        *settings.solid_infill_every_layers.enabled_mut() = true;
+        *settings.solid_infill_every_layers = 3;
+
+        let mut slices = (0..10)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        FillAreaPass::pass(&mut slices, &settings).unwrap();
+
+        let has_trace = |slice: &Slice, trace: TraceType| {
+            slice.chains.iter().any(|chain| {
+                chain
+                    .moves
+                    .iter()
+                    .any(|m| m.move_type == MoveType::WithoutFiber(trace))
+            })
+        };
+
+        for (layer, slice) in slices.iter().enumerate() {
+            let solid = layer % 3 == 0;
+            assert_eq!(has_trace(slice, TraceType::SolidInfill), solid, "{}", layer);
+            assert_eq!(has_trace(slice, TraceType::Infill), !solid, "{}", layer);
+        }
+    }
+
+    #[test]
+    fn ironing_only_on_top_surfaces() {
+        let mut settings = Settings::default();
+        *settings.ironing.enabled_mut() = true;
+
+        //A 20mm square base with a 10mm square tower on top of it
+        let mut slices = (0..12)
+            .map(|layer| {
+                let (min, max) = if layer < 6 { (0.0, 20.0) } else { (5.0, 15.0) };
+                Slice::from_single_point_loop(
+                    vec![(min, min), (max, min), (max, max), (min, max)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        TopLayerPass::pass(&mut slices, &settings).unwrap();
+        TopAndBottomLayersPass::pass(&mut slices, &settings).unwrap();
+        IroningPass::pass(&mut slices, &settings).unwrap();
+        FillAreaPass::pass(&mut slices, &settings).unwrap();
+        OrderPass::pass(&mut slices, &settings).unwrap();
+
+        let is_ironing = |chain: &MoveChain| {
+            chain
+                .moves
+                .iter()
+                .any(|m| m.move_type == MoveType::WithoutFiber(TraceType::Ironing))
+        };
+
+        //The ledge of the base and the top of the tower, not the solid layers below them
+        for (layer, slice) in slices.iter().enumerate() {
+            let ironed = slice.chains.iter().any(is_ironing);
+            assert_eq!(ironed, layer == 5 || layer == 11, "{}", layer);
+        }
+
+        //The ledge is ironed right up to the tower, not below it
+        let ledge_ends = slices[5]
+            .chains
+            .iter()
+            .filter(|chain| is_ironing(chain))
+            .flat_map(|chain| chain.moves.iter().map(|m| m.end))
+            .collect::<Vec<_>>();
+        let on_tower = |margin: f32| {
+            move |end: &Coord<f32>| {
+                let tower = 5.0 + margin..15.0 - margin;
+                tower.contains(&end.x) && tower.contains(&end.y)
+            }
+        };
+        assert!(!ledge_ends.iter().any(on_tower(0.5)));
+        assert!(ledge_ends.iter().any(on_tower(-0.5)));
+
+        //Ironing follows the top infill it smooths and barely extrudes
+        let top = &slices[11].chains;
+        let first_ironing = top.iter().position(is_ironing).unwrap();
+        assert!(first_ironing > 0);
+        assert!(top[first_ironing..].iter().all(is_ironing));
+        assert!(top[first_ironing..]
+            .iter()
+            .flat_map(|chain| chain.moves.iter())
+            .filter(|m| m.move_type != MoveType::Travel)
+            .all(|m| m.width < settings.extrusion_width.ironing));
+    }
+
+    #[test]
+    fn inner_walls_only_on_matching_layers() {
+        let mut settings = Settings::default();
+        settings.number_of_perimeters = 3;
+        settings.inner_walls_every_layers = 2;
+
+        let mut slices = (0..6)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        WallPass::pass(&mut slices, &settings).unwrap();
+
+        let has_trace = |slice: &Slice, trace: TraceType| {
+            slice.fixed_chains.iter().any(|chain| {
+                chain
+                    .moves
+                    .iter()
+                    .any(|m| m.move_type.print_type() == Some(trace))
+            })
+        };
+
+        for (layer, slice) in slices.iter().enumerate() {
+            let inner_walls = layer % 2 == 0;
+            assert!(has_trace(slice, TraceType::WallOuter), "{}", layer);
+            assert_eq!(
+                has_trace(slice, TraceType::WallInner),
+                inner_walls,
+                "{}",
+                layer
+            );
+
+            //Without the inner walls only the outer wall is taken from the area left for infill
+            let inset = if inner_walls {
+                settings.extrusion_width.exterior_surface_perimeter
+                    + 2.0 * settings.extrusion_width.exterior_inner_perimeter
+            } else {
+                settings.extrusion_width.exterior_surface_perimeter
+            };
+            let expected = (20.0 - 2.0 * inset).powi(2);
+            let area = slice.remaining_area.unsigned_area();
+            assert!(
+                (area - expected).abs() < 1.0,
+                "{} {} {}",
+                layer,
+                area,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn wipe_tower_prints_first_clear_of_the_objects() {
+        let mut settings = Settings::default();
+        *settings.wipe_tower.enabled_mut() = true;
+        settings.wipe_tower.x = 10.0;
+        settings.wipe_tower.y = 10.0;
+        settings.wipe_tower.width = 10.0;
+
+        let mut objects = vec![
+            square_object(5.0, 5.0, 20.0, &settings),
+            square_object(100.0, 100.0, 20.0, &settings),
+        ];
+        objects[1].layers.push(Slice::from_single_point_loop(
+            vec![
+                (100.0, 100.0),
+                (120.0, 100.0),
+                (120.0, 120.0),
+                (100.0, 120.0),
+            ]
+            .into_iter(),
+            0.3,
+            0.5,
+            1,
+            &settings,
+        ));
+
+        WipeTowerPass::pass(&mut objects, &settings);
+
+        //The configured position overlaps the first object, so the tower moves to the first free
+        //bed corner and is printed first on every layer
+        for slice in [&objects[0].layers[0], &objects[1].layers[1]] {
+            let first = slice.fixed_chains.first().expect("Tower is printed");
+            assert!(first
+                .moves
+                .iter()
+                .filter(|m| m.move_type != MoveType::Travel)
+                .all(|m| m.move_type == MoveType::WithoutFiber(TraceType::SolidInfill)));
+            assert!(first.moves.iter().all(|m| {
+                m.end.x >= settings.print_x - 10.0 - 0.001 && m.end.y <= 10.0 + 0.001
+            }));
+        }
+        assert!(objects[1].layers[0].fixed_chains.is_empty());
+    }
+
+    #[test]
+    fn tool_changes_purge_on_the_wipe_tower() {
+        let mut settings = Settings::default();
+        *settings.wipe_tower.enabled_mut() = true;
+        settings.wipe_tower.x = 0.0;
+        settings.wipe_tower.y = 0.0;
+        settings.wipe_tower.width = 10.0;
+        //A band 2 mm deep on the 0.3 mm layers
+        settings.wipe_tower.purge_volume = 6.0;
+
+        let mut objects = vec![
+            square_object(100.0, 100.0, 20.0, &settings),
+            square_object(150.0, 100.0, 20.0, &settings),
+        ];
+        objects[1].extruder = 1;
+        for (object, x) in objects.iter_mut().zip([100.0, 150.0]) {
+            object.layers.push(Slice::from_single_point_loop(
+                vec![(x, 100.0), (x + 20.0, 100.0), (x + 20.0, 120.0), (x, 120.0)].into_iter(),
+                0.3,
+                0.6,
+                1,
+                &settings,
+            ));
+        }
+
+        WipeTowerPass::pass(&mut objects, &settings);
+
+        //The tower bands each object's slice prints, as the index of the 2 mm purge band from the
+        //front, or None for the rest of the tower. A layer has a band per tool change
+        let bands = |object: usize, layer: usize| -> Vec<Option<usize>> {
+            let purge_bands = layer + 1;
+
+            objects[object].layers[layer]
+                .fixed_chains
+                .iter()
+                .map(|chain| {
+                    let (min_y, max_y) = chain
+                        .moves
+                        .iter()
+                        .map(|m| m.end.y)
+                        .chain(std::iter::once(chain.start_point.y))
+                        .fold((f32::MAX, f32::MIN), |(min, max), y| {
+                            (min.min(y), max.max(y))
+                        });
+                    assert!(max_y <= 10.5, "Tower chain outside the tower");
+
+                    let band = ((min_y + max_y) / 4.0) as usize;
+                    (max_y - min_y <= 2.5 && band < purge_bands).then_some(band)
+                })
+                .dedup()
+                .collect()
+        };
+
+        //The second object changes to its extruder on the first layer, both change on the next
+        assert_eq!(bands(0, 0), vec![None]);
+        assert_eq!(bands(1, 0), vec![Some(0)]);
+        assert_eq!(
+            bands(0, 1).iter().sorted().collect_vec(),
+            vec![&None, &Some(0)]
+        );
+        assert_eq!(bands(1, 1), vec![Some(1)]);
+
+        //A purge for every tool change
+        let purges: usize = (0..2)
+            .flat_map(|object| (0..2).map(move |layer| (object, layer)))
+            .map(|(object, layer)| bands(object, layer).iter().flatten().count())
+            .sum();
+        assert_eq!(purges, 3);
+    }
+
+    #[test]
+    fn wipe_tower_stops_at_the_last_tool_change() {
+        let mut settings = Settings::default();
+        *settings.wipe_tower.enabled_mut() = true;
+        settings.wipe_tower.x = 0.0;
+        settings.wipe_tower.y = 0.0;
+        settings.wipe_tower.width = 10.0;
+        settings.wipe_tower.purge_volume = 6.0;
+
+        let mut objects = vec![
+            square_object(100.0, 100.0, 20.0, &settings),
+            square_object(150.0, 100.0, 20.0, &settings),
+        ];
+        objects[1].extruder = 1;
+
+        //The first object goes on alone on its own extruder and overhangs the tower position
+        //above the last tool change
+        for (layer, size) in [(1, 20.0), (2, 20.0), (3, 130.0)] {
+            let x = 120.0 - size;
+            objects[0].layers.push(Slice::from_single_point_loop(
+                vec![(x, x), (120.0, x), (120.0, 120.0), (x, 120.0)].into_iter(),
+                layer as f32 * 0.3,
+                (layer + 1) as f32 * 0.3,
+                layer,
+                &settings,
+            ));
+        }
+
+        WipeTowerPass::pass(&mut objects, &settings);
+
+        let in_tower = |object: usize, layer: usize| {
+            let chains = &objects[object].layers[layer].fixed_chains;
+            !chains.is_empty()
+                && chains.iter().all(|chain| {
+                    chain
+                        .moves
+                        .iter()
+                        .map(|m| m.end)
+                        .chain(std::iter::once(chain.start_point))
+                        .all(|point| point.x <= 10.5 && point.y <= 10.5)
+                })
+        };
+
+        //Only the part below the tower keeps it out, so it stays at the configured position
+        assert!(in_tower(0, 0));
+        assert!(in_tower(1, 0));
+        assert!(in_tower(0, 1));
+        assert!(objects[0].layers[2].fixed_chains.is_empty());
+        assert!(objects[0].layers[3].fixed_chains.is_empty());
+    }
+
+    #[test]
+    fn ooze_shield_surrounds_all_objects_on_every_layer() {
+        let mut settings = Settings::default();
+        *settings.ooze_shield.enabled_mut() = true;
+        settings.ooze_shield.distance = 2.0;
+
+        let mut objects = vec![
+            square_object(100.0, 100.0, 20.0, &settings),
+            square_object(150.0, 100.0, 20.0, &settings),
+        ];
+        objects[1].layers.push(Slice::from_single_point_loop(
+            vec![
+                (150.0, 100.0),
+                (170.0, 100.0),
+                (170.0, 120.0),
+                (150.0, 120.0),
+            ]
+            .into_iter(),
+            0.3,
+            0.6,
+            1,
+            &settings,
+        ));
+
+        OozeShieldPass::pass(&mut objects, &settings);
+
+        let x_range = |chain: &MoveChain| {
+            chain
+                .moves
+                .iter()
+                .map(|m| m.end.x)
+                .fold((f32::MAX, f32::MIN), |(min, max), x| {
+                    (min.min(x), max.max(x))
+                })
+        };
+
+        //One loop per layer, carried by the first object on the layer
+        assert_eq!(objects[0].layers[0].fixed_chains.len(), 1);
+        assert!(objects[1].layers[0].fixed_chains.is_empty());
+        let (min, max) = x_range(&objects[0].layers[0].fixed_chains[0]);
+        assert!((min - 98.0).abs() < 0.01 && (max - 172.0).abs() < 0.01);
+
+        //Above the first object the shield only surrounds the second
+        assert_eq!(objects[1].layers[1].fixed_chains.len(), 1);
+        let (min, max) = x_range(&objects[1].layers[1].fixed_chains[0]);
+        assert!((min - 148.0).abs() < 0.01 && (max - 172.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn top_and_bottom_layers_use_fewer_perimeters() {
+        let mut settings = Settings::default();
+        settings.number_of_perimeters = 3;
+        settings.top_layers = 3;
+        settings.bottom_layers = 2;
+        *settings.top_bottom_perimeters.enabled_mut() = true;
+        *settings.top_bottom_perimeters = 1;
+
+        let mut slices = (0..10)
+            .map(|layer| {
+                Slice::from_single_point_loop(
+                    vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].into_iter(),
+                    layer as f32 * 0.2,
+                    (layer + 1) as f32 * 0.2,
+                    layer,
+                    &settings,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        WallPass::pass(&mut slices, &settings).unwrap();
+
+        for (layer, slice) in slices.iter().enumerate() {
+            let top_or_bottom = layer < 2 || layer >= 7;
+            let has_inner_walls = slice.fixed_chains.iter().any(|chain| {
+                chain
+                    .moves
+                    .iter()
+                    .any(|m| m.move_type.print_type() == Some(TraceType::WallInner))
+            });
+            assert_eq!(has_inner_walls, !top_or_bottom, "{}", layer);
+
+            let inset = if top_or_bottom {
+                settings.extrusion_width.exterior_surface_perimeter
+            } else {
+                settings.extrusion_width.exterior_surface_perimeter
+                    + 2.0 * settings.extrusion_width.exterior_inner_perimeter
+            };
+            let expected = (20.0 - 2.0 * inset).powi(2);
+            let area = slice.remaining_area.unsigned_area();
+            assert!(
+                (area - expected).abs() < 1.0,
+                "{} {} {}",
+                layer,
+                area,
+                expected
+            );
+        }
+    }
+}
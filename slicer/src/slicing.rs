@@ -1,133 +1,662 @@
-use geo::Coord;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use geo::prelude::*;
+use geo::{Coord, MultiPolygon, Polygon};
+use itertools::Itertools;
+use log::warn;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
-use shared::object::ObjectVertex;
+use shared::object::{ObjectMesh, ObjectVertex};
+use shared::process::Process;
 
 use super::{
     error::SlicerErrors,
+    plotter::polygon_operations::PolygonOperations,
     settings::Settings,
-    tower::{TriangleTower, TriangleTowerIterator},
-    Object, Slice,
+    tower::{create_tower, TriangleTower, TriangleTowerIterator},
+    warning::SlicerWarnings,
+    Object, OverlapPolicy, PrintSequence, Slice,
 };
 
+///Overlaps smaller than this area in mm² are treated as objects touching
+const OVERLAP_AREA_EPSILON: f32 = 0.0001;
+
+//Layers are cut in about this many chunks per thread, so uneven chunks even out
+const CHUNKS_PER_THREAD: usize = 4;
+
+//Fewest layers in a chunk, each chunk costs a copy of the rings of the tower
+const MIN_CHUNK_LAYERS: usize = 8;
+
+///Heights of a layer, the outline is cut between the bottom and top
+#[derive(Clone, Copy, Debug)]
+struct LayerHeights {
+    bottom: f32,
+    cut: f32,
+    top: f32,
+}
+
+type LayerLoops = Vec<Vec<ObjectVertex>>;
+
 pub fn slice(
     towers: &[TriangleTower],
     max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
     settings: &Settings,
 ) -> Result<Vec<Object>, SlicerErrors> {
+    check_layer_count(max_height, layer_heights, settings)?;
+
     towers
         .iter()
-        .map(|tower| {
-            let mut tower_iter = TriangleTowerIterator::new(tower);
+        .map(|tower| slice_single(tower, max_height, z_range, layer_heights, settings))
+        .collect()
+}
+
+///Slice the meshes like `slice`, but only build the tower of each object while it is sliced, so
+///the towers of a large assembly are never all in memory at once. Cancelling the process stops
+///before the next object.
+pub fn slice_meshes(
+    meshes: &[ObjectMesh],
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+    process: &Process,
+    progress: Range<f32>,
+) -> Result<Vec<Object>, SlicerErrors> {
+    check_layer_count(max_height, layer_heights, settings)?;
+    let layers = estimated_layer_count(max_height, layer_heights, settings);
+
+    map_with_progress(meshes, process, progress, |mesh, progress| {
+        let tower = create_tower(mesh)?;
+        let progress = LayerProgress::new(process, progress, layers);
+        slice_single_with_progress(
+            &tower,
+            max_height,
+            z_range,
+            layer_heights,
+            settings,
+            Some(&progress),
+        )
+    })
+}
+
+///Build the towers of all meshes, stopping before the next one when the process is cancelled
+pub fn create_towers_with_progress(
+    meshes: &[ObjectMesh],
+    process: &Process,
+    progress: Range<f32>,
+) -> Result<Vec<TriangleTower>, SlicerErrors> {
+    map_with_progress(meshes, process, progress, |mesh, _| create_tower(mesh))
+}
+
+///Slice the towers like `slice` while moving the progress through `progress`. Cancelling the
+///process stops at the next layer.
+pub fn slice_towers_with_progress(
+    towers: &[TriangleTower],
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+    process: &Process,
+    progress: Range<f32>,
+) -> Result<Vec<Object>, SlicerErrors> {
+    check_layer_count(max_height, layer_heights, settings)?;
+    let layers = estimated_layer_count(max_height, layer_heights, settings);
+
+    map_with_progress(towers, process, progress, |tower, progress| {
+        let progress = LayerProgress::new(process, progress, layers);
+        slice_single_with_progress(
+            tower,
+            max_height,
+            z_range,
+            layer_heights,
+            settings,
+            Some(&progress),
+        )
+    })
+}
+
+///Map the items in order while moving the progress through `progress`. Each item is handed its
+///share of the progress. Everything built so far is dropped when the process is cancelled.
+fn map_with_progress<I, T>(
+    items: &[I],
+    process: &Process,
+    progress: Range<f32>,
+    mut map: impl FnMut(&I, Range<f32>) -> Result<T, SlicerErrors>,
+) -> Result<Vec<T>, SlicerErrors> {
+    let at = |count: usize| {
+        progress.start + (progress.end - progress.start) * count as f32 / items.len() as f32
+    };
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            check_cancelled(process)?;
 
-            let mut layer = 0.0;
+            let mapped = map(item, at(index)..at(index + 1))?;
+            process.set_progress(at(index + 1));
 
-            let mut first_layer = true;
+            Ok(mapped)
+        })
+        .collect()
+}
 
-            let res_points: Result<Vec<(f32, f32, Vec<Vec<ObjectVertex>>)>, SlicerErrors> =
-                std::iter::repeat(())
-                    .enumerate()
-                    .map(|(layer_count, _)| {
-                        //Advance to the correct height
-                        let layer_height =
-                            settings.get_layer_settings(layer_count, layer).layer_height;
+///Stop with `SlicerErrors::Cancelled` once the process is cancelled
+pub(crate) fn check_cancelled(process: &Process) -> Result<(), SlicerErrors> {
+    if process.is_cancelled() {
+        Err(SlicerErrors::Cancelled)
+    } else {
+        Ok(())
+    }
+}
 
-                        let bottom_height = layer;
-                        layer += layer_height / 2.0;
-                        tower_iter.advance_to_height(layer)?;
-                        layer += layer_height / 2.0;
+///Moves the progress of a process through a range as work on the layers is done, from any
+///thread. The time left is estimated from the time taken per unit of work so far.
+pub(crate) struct LayerProgress<'a> {
+    process: &'a Process,
+    range: Range<f32>,
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+}
 
-                        let top_height = layer;
+impl<'a> LayerProgress<'a> {
+    ///Progress through `range` over `total` units of work, the estimate doesn't need to be exact
+    pub(crate) fn new(process: &'a Process, range: Range<f32>, total: usize) -> Self {
+        Self {
+            process,
+            range,
+            total,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+        }
+    }
 
-                        first_layer = false;
+    ///Stop with `SlicerErrors::Cancelled` once the process is cancelled
+    pub(crate) fn check_cancelled(&self) -> Result<(), SlicerErrors> {
+        check_cancelled(self.process)
+    }
 
-                        //Get the ordered lists of points
-                        Ok((bottom_height, top_height, tower_iter.get_points()))
-                    })
-                    .take_while(|r| {
-                        if let Ok((bottom, top, layer_loops)) = r {
-                            !layer_loops.is_empty() || ((bottom + top) / 2.0 <= max_height)
-                        } else {
-                            true
-                        }
-                    })
-                    .collect();
+    ///Record `units` more units of work as done
+    pub(crate) fn add(&self, units: usize) {
+        let done = self.done.fetch_add(units, Ordering::Relaxed) + units;
+        if self.total == 0 || done == 0 {
+            return;
+        }
 
-            let points = res_points?;
+        let remaining = self.total.saturating_sub(done);
+        let eta = self.start.elapsed().as_secs_f32() / done as f32 * remaining as f32;
+        let fraction = (done as f32 / self.total as f32).min(1.0);
 
-            let slices: Result<Vec<Slice>, SlicerErrors> = points
-                .par_iter()
-                .enumerate()
-                .map(|(count, (bot, top, layer_loops))| {
-                    //Add this slice to the
-                    let slice = Slice::from_multiple_point_loop(
-                        layer_loops
-                            .iter()
-                            .map(|verts| {
-                                verts
-                                    .iter()
-                                    .map(|v| Coord { x: v.x, y: v.y })
-                                    .collect::<Vec<Coord<f32>>>()
-                            })
-                            .collect(),
-                        *bot,
-                        *top,
-                        count,
-                        settings,
-                    );
-                    slice
+        self.process.set_progress_with_eta(
+            self.range.start + (self.range.end - self.range.start) * fraction,
+            eta,
+        );
+    }
+}
+
+///The number of layers every object is sliced into
+pub(crate) fn estimated_layer_count(
+    max_height: f32,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+) -> usize {
+    match layer_heights {
+        Some(heights) => heights.len(),
+        None => (max_height / settings.layer_height).ceil() as usize,
+    }
+}
+
+fn check_layer_count(
+    max_height: f32,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+) -> Result<(), SlicerErrors> {
+    //A tiny layer height would slice for ages and run out of memory before failing
+    let computed = estimated_layer_count(max_height, layer_heights, settings);
+    if computed > settings.max_layers {
+        return Err(SlicerErrors::TooManyLayers {
+            computed,
+            cap: settings.max_layers,
+        });
+    }
+
+    Ok(())
+}
+
+///Height of every layer adapted to the slope of the surfaces of the towers, or None when the
+///layer heights come from the settings. The same heights are used for every object, mask and
+///modifier so their layers line up. The first layer keeps its configured height for adhesion.
+pub fn adaptive_layer_heights(
+    towers: &[TriangleTower],
+    max_height: f32,
+    settings: &Settings,
+) -> Result<Option<Vec<f32>>, SlicerErrors> {
+    if !settings.adaptive_layer_height.is_enabled() {
+        return Ok(None);
+    }
+
+    let adaptive = &*settings.adaptive_layer_height;
+    let min_layer = adaptive.min_height;
+    let max_layer = adaptive.max_height.max(min_layer);
+
+    //Largest step from a layer to the surface, a layer of height h on a surface whose normal has a
+    //vertical component n steps h * n
+    let max_step = max_layer + (min_layer - max_layer) * adaptive.quality_factor;
+    let allowed_height = |flatness: f32| {
+        if flatness * max_layer <= max_step {
+            max_layer
+        } else {
+            (max_step / flatness).max(min_layer)
+        }
+    };
+
+    //Flatness of all towers sampled at heights finer than the thinnest layer. A tiny minimum
+    //height would sample for ages, no more than two samples per allowed layer are taken.
+    let sample_count = ((max_height / (min_layer / 2.0)).ceil() as usize)
+        .min(2 * settings.max_layers)
+        .max(1);
+    let step = max_height / sample_count as f32;
+    let mut iterators: Vec<TriangleTowerIterator> =
+        towers.iter().map(TriangleTowerIterator::new).collect();
+    let samples: Vec<(f32, f32)> = (0..sample_count)
+        .map(|sample| (sample as f32 + 0.5) * step)
+        .map(|z| {
+            let mut flatness = 0.0f32;
+            for iterator in iterators.iter_mut() {
+                iterator.advance_to_height(z)?;
+                flatness = flatness.max(iterator.surface_flatness());
+            }
+            Ok((z, flatness))
+        })
+        .collect::<Result<_, SlicerErrors>>()?;
+
+    let mut heights = vec![settings.get_layer_settings(0, 0.0).layer_height];
+    let mut bottom = heights[0];
+    let mut samples = samples.as_slice();
+
+    while bottom < max_height {
+        if heights.len() >= settings.max_layers {
+            return Err(SlicerErrors::TooManyLayers {
+                computed: heights.len() + ((max_height - bottom) / min_layer).ceil() as usize,
+                cap: settings.max_layers,
+            });
+        }
+
+        samples = &samples[samples.partition_point(|(z, _)| *z < bottom)..];
+
+        //Shrink the layer until every surface it crosses is within the allowed step
+        let mut height = max_layer;
+        for (z, flatness) in samples {
+            if *z > bottom + height {
+                break;
+            }
+            height = height.min(allowed_height(*flatness));
+        }
+
+        heights.push(height);
+        bottom += height;
+    }
+
+    Ok(Some(heights))
+}
+
+///The outline of every layer of a sliced object, kept to find overlaps with objects that are
+///sliced later on their own
+#[derive(Debug, Clone)]
+pub struct ObjectOutline {
+    ///The bottom height, top height and outline of every layer, from lowest to highest
+    layers: Vec<(f32, f32, MultiPolygon<f32>)>,
+}
+
+impl ObjectOutline {
+    pub fn new(object: &Object) -> Self {
+        ObjectOutline {
+            layers: object
+                .layers
+                .iter()
+                .map(|slice| {
+                    (
+                        slice.bottom_height,
+                        slice.top_height,
+                        slice.main_polygon.clone(),
+                    )
                 })
-                .collect();
+                .collect(),
+        }
+    }
+
+    ///The outline of the layer that contains the height, as objects can be sliced with different
+    ///layer heights
+    fn layer_at(&self, z: f32) -> Option<&MultiPolygon<f32>> {
+        let index = self.layers.partition_point(|(_, top, _)| *top <= z);
+        self.layers
+            .get(index)
+            .filter(|(bottom, _, _)| *bottom <= z)
+            .map(|(_, _, outline)| outline)
+    }
+}
 
-            Ok(Object { layers: slices? })
+///A warning for every pair of objects whose layers overlap
+pub fn overlap_warnings(outlines: &[ObjectOutline]) -> Vec<SlicerWarnings> {
+    find_overlapping_objects(outlines)
+        .into_iter()
+        .map(|(first, second)| {
+            let warning = SlicerWarnings::ObjectsOverlap { first, second };
+            warn!("{}", warning.get_code_and_message().1);
+            warning
         })
         .collect()
 }
 
-pub fn slice_single(
+///Handle objects whose layers overlap according to the policy, the outlines belong to the objects
+///of the same index. When unioning, every group of overlapping objects is merged into the lowest
+///indexed object of the group, which keeps its extruder, so the overlap is printed once.
+pub fn resolve_overlapping_objects(
+    objects: Vec<Object>,
+    outlines: &[ObjectOutline],
+    policy: OverlapPolicy,
+    warnings: &mut Vec<SlicerWarnings>,
+) -> Result<Vec<Object>, SlicerErrors> {
+    match policy {
+        OverlapPolicy::Warn => warnings.extend(overlap_warnings(outlines)),
+        OverlapPolicy::Error => {
+            if let Some((first, second)) = find_overlapping_objects(outlines).first().copied() {
+                return Err(SlicerErrors::ObjectsOverlap { first, second });
+            }
+        }
+        OverlapPolicy::Union => {
+            //Every object points to the lowest indexed object it overlaps through any chain of
+            //overlaps. Pairs come in order of the first object, so the target is always final.
+            let mut targets: Vec<usize> = (0..objects.len()).collect();
+            for (first, second) in find_overlapping_objects(outlines) {
+                let (first, second) = (targets[first], targets[second]);
+                let (target, other) = (first.min(second), first.max(second));
+                for entry in targets.iter_mut().filter(|entry| **entry == other) {
+                    *entry = target;
+                }
+            }
+
+            let mut merged: Vec<Option<Object>> = objects.into_iter().map(Some).collect();
+            for (index, target) in targets.iter().copied().enumerate() {
+                if index != target {
+                    let other = merged[index].take().expect("Objects are merged once");
+                    if let Some(target) = merged[target].as_mut() {
+                        merge_object(target, other);
+                    }
+                }
+            }
+
+            return Ok(merged.into_iter().flatten().collect());
+        }
+    }
+
+    Ok(objects)
+}
+
+fn find_overlapping_objects(outlines: &[ObjectOutline]) -> Vec<(usize, usize)> {
+    (0..outlines.len())
+        .tuple_combinations()
+        .filter(|&(first, second)| {
+            outlines[first]
+                .layers
+                .iter()
+                .filter_map(|(bottom, top, a)| {
+                    outlines[second]
+                        .layer_at((bottom + top) / 2.0)
+                        .map(|b| (a, b))
+                })
+                .any(|(a, b)| {
+                    let bounds_intersect = match (a.bounding_rect(), b.bounding_rect()) {
+                        (Some(a), Some(b)) => a.intersects(&b),
+                        _ => false,
+                    };
+
+                    bounds_intersect
+                        && a.intersection_with(b).unsigned_area() > OVERLAP_AREA_EPSILON
+                })
+        })
+        .collect()
+}
+
+///When printing by object, the print head passes the finished objects while printing the next
+///one. Every object has to keep the clearance from all others, otherwise the colliding objects are
+///returned as an error.
+pub fn check_sequential_clearance(
+    objects: &[Object],
+    settings: &Settings,
+) -> Result<(), SlicerErrors> {
+    if settings.print_sequence != PrintSequence::ByObject {
+        return Ok(());
+    }
+
+    //Outline of everything the object covers over its whole height
+    let footprints: Vec<Option<Polygon<f32>>> = objects
+        .iter()
+        .map(|object| {
+            let polygons = MultiPolygon(
+                object
+                    .layers
+                    .iter()
+                    .flat_map(|slice| slice.main_polygon.0.iter().cloned())
+                    .collect(),
+            );
+            (!polygons.0.is_empty()).then(|| polygons.convex_hull())
+        })
+        .collect();
+
+    let colliding: Vec<usize> = (0..objects.len())
+        .tuple_combinations()
+        .filter(
+            |&(first, second)| match (&footprints[first], &footprints[second]) {
+                (Some(a), Some(b)) => a.euclidean_distance(b) < settings.sequential_clearance,
+                _ => false,
+            },
+        )
+        .flat_map(|(first, second)| [first, second])
+        .sorted()
+        .dedup()
+        .collect();
+
+    if colliding.is_empty() {
+        Ok(())
+    } else {
+        Err(SlicerErrors::SequentialClearance {
+            objects: colliding,
+            clearance: settings.sequential_clearance,
+        })
+    }
+}
+
+///Union every layer of `other` into the layer of `target` at the same height. Layers of `other`
+///at heights `target` has no layer for are added as they are.
+fn merge_object(target: &mut Object, other: Object) {
+    let mut added = vec![];
+    for layer in other.layers {
+        let z = (layer.bottom_height + layer.top_height) / 2.0;
+        let index = target.layers.partition_point(|slice| slice.top_height <= z);
+        match target
+            .layers
+            .get_mut(index)
+            .filter(|slice| slice.bottom_height <= z)
+        {
+            Some(slice) => {
+                slice.main_polygon = slice.main_polygon.union_with(&layer.main_polygon);
+                slice.remaining_area = slice.remaining_area.union_with(&layer.remaining_area);
+            }
+            None => added.push(layer),
+        }
+    }
+
+    if !added.is_empty() {
+        target.layers.extend(added);
+        target
+            .layers
+            .sort_by(|a, b| a.bottom_height.total_cmp(&b.bottom_height));
+    }
+}
+
+///Heights of the layers of the tower up to the first layer `slice_single` doesn't keep, which is
+///above both the objects and the tower or past the end of the range
+fn layer_heights_of_tower(
     tower: &TriangleTower,
     max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
     settings: &Settings,
-) -> Result<Object, SlicerErrors> {
-    let mut tower_iter = TriangleTowerIterator::new(tower);
-
+) -> Result<Vec<LayerHeights>, SlicerErrors> {
+    let tower_top = tower.top_height();
+    let mut heights = vec![];
     let mut layer = 0.0;
 
-    let mut first_layer = true;
+    for layer_count in 0.. {
+        let layer_height = layer_heights
+            .and_then(|heights| heights.get(layer_count).copied())
+            .unwrap_or_else(|| settings.get_layer_settings(layer_count, layer).layer_height);
+
+        //Layer settings can shrink the layer height below the estimate of slice
+        if layer_count >= settings.max_layers && layer < max_height {
+            return Err(SlicerErrors::TooManyLayers {
+                computed: layer_count + ((max_height - layer) / layer_height).ceil() as usize,
+                cap: settings.max_layers,
+            });
+        }
 
-    let res_points: Result<Vec<(f32, f32, Vec<Vec<ObjectVertex>>)>, SlicerErrors> =
-        std::iter::repeat(())
-            .enumerate()
-            .map(|(layer_count, _)| {
-                //Advance to the correct height
-                let layer_height = settings.get_layer_settings(layer_count, layer).layer_height;
+        let bottom = layer;
+        layer += layer_height / 2.0;
+        let cut = layer;
+        layer += layer_height / 2.0;
+        heights.push(LayerHeights {
+            bottom,
+            cut,
+            top: layer,
+        });
 
-                let bottom_height = layer;
-                layer += layer_height / 2.0;
-                tower_iter.advance_to_height(layer)?;
-                layer += layer_height / 2.0;
+        //Every ring of the tower is closed above its highest vertex, so the layer is empty
+        let center = (bottom + layer) / 2.0;
+        if (center > max_height && center > tower_top)
+            || z_range.is_some_and(|(_, end)| center > end)
+        {
+            break;
+        }
+    }
 
-                let top_height = layer;
+    Ok(heights)
+}
 
-                first_layer = false;
+///The loops of every layer, in chunks of `chunk_size` layers that are cut on their own threads.
+///A single iterator walks up the tower and hands a copy of its rings to each chunk as it passes
+///the chunk's first layer, so no chunk replays the tower from the bottom. The loops are the same
+///as cutting every layer in order. A chunk ends at its first error, and no chunks follow an error
+///of the walk up. Cancelling the process of `progress` ends every chunk at its next layer.
+fn layer_loops(
+    tower: &TriangleTower,
+    heights: &[LayerHeights],
+    z_range: Option<(f32, f32)>,
+    chunk_size: usize,
+    progress: Option<&LayerProgress>,
+) -> Vec<Vec<Result<LayerLoops, SlicerErrors>>> {
+    let check_cancelled = || progress.map_or(Ok(()), LayerProgress::check_cancelled);
 
-                //Get the ordered lists of points
-                Ok((bottom_height, top_height, tower_iter.get_points()))
-            })
-            .take_while(|r| {
-                if let Ok((bottom, top, layer_loops)) = r {
-                    !layer_loops.is_empty() || ((bottom + top) / 2.0 <= max_height)
-                } else {
-                    true
+    let chunks: Vec<&[LayerHeights]> = heights.chunks(chunk_size.max(1)).collect();
+    let mut chunk_loops: Vec<Vec<Result<LayerLoops, SlicerErrors>>> =
+        chunks.iter().map(|_| vec![]).collect();
+
+    rayon::scope(|scope| {
+        let mut walk = TriangleTowerIterator::new(tower);
+
+        for (chunk, loops) in chunks.iter().zip(chunk_loops.iter_mut()) {
+            if let Err(err) = walk.advance_to_height(chunk[0].cut) {
+                loops.push(Err(err));
+                break;
+            }
+
+            let mut tower_iter = walk.clone();
+            scope.spawn(move |_| {
+                for layer in chunk.iter() {
+                    let result = check_cancelled()
+                        .and_then(|()| tower_iter.advance_to_height(layer.cut))
+                        .map(|()| {
+                            //Layers below the range are skipped without building their loops
+                            match z_range {
+                                Some((start, _)) if (layer.bottom + layer.top) / 2.0 < start => {
+                                    vec![]
+                                }
+                                _ => tower_iter.get_points(),
+                            }
+                        });
+
+                    let failed = result.is_err();
+                    loops.push(result);
+                    if failed {
+                        break;
+                    }
                 }
-            })
-            .collect();
+            });
+        }
+    });
 
-    let points = res_points?;
+    chunk_loops
+}
+
+///Slice a single tower into layers. With a z range only the layers whose center lies within the
+///range are kept, keeping the layer index they have in a full slice. Layer heights that aren't
+///given come from the settings.
+pub fn slice_single(
+    tower: &TriangleTower,
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+) -> Result<Object, SlicerErrors> {
+    slice_single_with_progress(tower, max_height, z_range, layer_heights, settings, None)
+}
+
+///Slice a single tower like `slice_single`, adding each finished layer to `progress`
+pub(crate) fn slice_single_with_progress(
+    tower: &TriangleTower,
+    max_height: f32,
+    z_range: Option<(f32, f32)>,
+    layer_heights: Option<&[f32]>,
+    settings: &Settings,
+    progress: Option<&LayerProgress>,
+) -> Result<Object, SlicerErrors> {
+    let heights = layer_heights_of_tower(tower, max_height, z_range, layer_heights, settings)?;
+
+    let chunk_size =
+        (heights.len() / (rayon::current_num_threads() * CHUNKS_PER_THREAD)).max(MIN_CHUNK_LAYERS);
+    let chunk_loops = layer_loops(tower, &heights, z_range, chunk_size, progress);
+
+    //Layers are kept up to the first empty one above the objects, or the end of the range
+    let mut points: Vec<(f32, f32, LayerLoops)> = vec![];
+    for (layer, loops) in heights.iter().zip(chunk_loops.into_iter().flatten()) {
+        let loops = loops?;
+
+        let center = (layer.bottom + layer.top) / 2.0;
+        if (loops.is_empty() && center > max_height) || z_range.is_some_and(|(_, end)| center > end)
+        {
+            break;
+        }
+
+        points.push((layer.bottom, layer.top, loops));
+    }
 
     let slices: Result<Vec<Slice>, SlicerErrors> = points
         .par_iter()
         .enumerate()
+        .filter(|(_, (bot, top, _))| {
+            z_range.map_or(true, |(start, end)| {
+                (start..=end).contains(&((bot + top) / 2.0))
+            })
+        })
         .map(|(count, (bot, top, layer_loops))| {
+            if let Some(progress) = progress {
+                progress.check_cancelled()?;
+            }
+
             //Add this slice to the
             let slice = Slice::from_multiple_point_loop(
                 layer_loops
@@ -144,9 +673,108 @@ pub fn slice_single(
                 count,
                 settings,
             );
+            if let Some(progress) = progress {
+                progress.add(1);
+            }
             slice
         })
         .collect();
 
-    Ok(Object { layers: slices? })
+    Ok(Object {
+        layers: slices?,
+        extruder: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///A prism that twists as it rises, so the tower has events between most layers
+    fn twisted_prism(sides: usize, rings: usize, height: f32) -> ObjectMesh {
+        let mut vertices: Vec<ObjectVertex> = (0..=rings)
+            .flat_map(|ring| {
+                (0..sides).map(move |side| {
+                    let angle = std::f32::consts::TAU * side as f32 / sides as f32
+                        + ring as f32 * 3.0f32.to_radians();
+                    ObjectVertex::new(
+                        10.0 + 5.0 * angle.cos(),
+                        10.0 + 5.0 * angle.sin(),
+                        height * ring as f32 / rings as f32,
+                    )
+                })
+            })
+            .collect();
+        let bottom = vertices.len();
+        vertices.push(ObjectVertex::new(10.0, 10.0, 0.0));
+        vertices.push(ObjectVertex::new(10.0, 10.0, height));
+        let index = |ring: usize, side: usize| ring * sides + side % sides;
+
+        let mut triangles: Vec<[usize; 3]> = vec![];
+        for side in 0..sides {
+            for ring in 0..rings {
+                let (a, b) = (index(ring, side), index(ring, side + 1));
+                let (c, d) = (index(ring + 1, side + 1), index(ring + 1, side));
+                triangles.extend([[a, b, c], [a, c, d]]);
+            }
+
+            triangles.push([bottom, index(0, side + 1), index(0, side)]);
+            triangles.push([bottom + 1, index(rings, side), index(rings, side + 1)]);
+        }
+
+        let mut mesh = ObjectMesh::new(
+            vertices,
+            triangles
+                .into_iter()
+                .map(|triangle| triangle.into())
+                .collect(),
+        );
+        mesh.sort_indices();
+        mesh
+    }
+
+    #[test]
+    fn chunked_layers_match_cutting_in_order() {
+        let settings = Settings::default();
+        let tower = create_tower(&twisted_prism(16, 40, 20.0)).unwrap();
+        let heights = layer_heights_of_tower(&tower, 20.0, None, None, &settings).unwrap();
+
+        let loops = |chunk_size: usize| -> Vec<LayerLoops> {
+            layer_loops(&tower, &heights, None, chunk_size, None)
+                .into_iter()
+                .flatten()
+                .map(|loops| loops.unwrap())
+                .collect()
+        };
+
+        let in_order = loops(heights.len());
+        assert_eq!(in_order.len(), heights.len());
+        assert!(in_order.iter().filter(|loops| !loops.is_empty()).count() > 50);
+
+        for chunk_size in [1, 3, MIN_CHUNK_LAYERS] {
+            assert_eq!(loops(chunk_size), in_order);
+        }
+
+        //Layers below the range are left empty
+        let ranged = layer_loops(&tower, &heights, Some((10.0, 20.0)), 3, None);
+        let first = ranged.into_iter().flatten().next().unwrap().unwrap();
+        assert!(first.is_empty());
+    }
+
+    #[test]
+    fn cancelled_chunks_stop_at_their_next_layer() {
+        let settings = Settings::default();
+        let tower = create_tower(&twisted_prism(16, 40, 20.0)).unwrap();
+        let heights = layer_heights_of_tower(&tower, 20.0, None, None, &settings).unwrap();
+
+        let process = Process::new();
+        process.cancel();
+        let progress = LayerProgress::new(&process, 0.0..1.0, heights.len());
+
+        let chunks = layer_loops(&tower, &heights, None, 3, Some(&progress));
+        assert!(chunks.len() > 1);
+        for chunk in chunks {
+            assert!(matches!(chunk[..], [Err(SlicerErrors::Cancelled)]));
+        }
+    }
 }
@@ -110,6 +110,14 @@ impl TriangleTower {
         })
     }
 
+    ///Height of the highest vertex, every ring is closed above it
+    pub fn top_height(&self) -> f32 {
+        match self.tower_vertices.len() {
+            0 => f32::NEG_INFINITY,
+            len => self.get_height_of_vertex(len - 1),
+        }
+    }
+
     pub fn get_height_of_vertex(&self, index: usize) -> f32 {
         if index >= self.tower_vertices.len() {
             f32::INFINITY
@@ -389,6 +397,7 @@ fn join_fragments(fragments: &mut Vec<TowerRing>) {
     }
 }
 
+#[derive(Clone)]
 pub struct TriangleTowerIterator<'s> {
     tower: &'s TriangleTower,
     tower_vert_index: usize,
@@ -459,6 +468,42 @@ impl<'s> TriangleTowerIterator<'s> {
         Ok(())
     }
 
+    ///How flat the surface is at the current height, the largest vertical component of the
+    ///normals of the faces cut by the plane. 0 for vertical walls and 1 for horizontal surfaces.
+    pub fn surface_flatness(&self) -> f32 {
+        self.active_rings
+            .iter()
+            .flat_map(|ring| {
+                let edges: Vec<(usize, usize)> = ring
+                    .elements
+                    .iter()
+                    .filter_map(|e| match e {
+                        TowerRingElement::Edge {
+                            start_index,
+                            end_index,
+                        } => Some((*start_index, *end_index)),
+                        TowerRingElement::Face { .. } => None,
+                    })
+                    .collect();
+
+                //Consecutive edges of a ring are the edges of the face between them
+                edges
+                    .windows(2)
+                    .filter_map(|pair| {
+                        let (a, b) = pair[0];
+                        let c = [pair[1].0, pair[1].1]
+                            .into_iter()
+                            .find(|index| *index != a && *index != b)?;
+
+                        let [a, b, c] = [a, b, c].map(|index| *self.tower.vertices[index]);
+                        let normal = (b - a).cross(c - a).normalize_or_zero();
+                        Some(normal.z.abs())
+                    })
+                    .collect::<Vec<f32>>()
+            })
+            .fold(0.0, f32::max)
+    }
+
     pub fn get_points(&self) -> Vec<Vec<ObjectVertex>> {
         self.active_rings
             .iter()
@@ -494,12 +539,11 @@ impl<'s> TriangleTowerIterator<'s> {
 }
 
 pub fn create_towers(models: &[ObjectMesh]) -> Result<Vec<TriangleTower>, SlicerErrors> {
-    models
-        .iter()
-        .map(|mesh| {
-            TriangleTower::from_triangles_and_vertices(mesh.triangles(), mesh.vertices().to_vec())
-        })
-        .collect()
+    models.iter().map(create_tower).collect()
+}
+
+pub fn create_tower(mesh: &ObjectMesh) -> Result<TriangleTower, SlicerErrors> {
+    TriangleTower::from_triangles_and_vertices(mesh.triangles(), mesh.vertices().to_vec())
 }
 
 #[cfg(test)]
@@ -1,14 +1,6 @@
 use geo::Coord;
 use glam::Vec2;
 
-#[inline]
-pub fn point_y_lerp(a: &Coord<f32>, b: &Coord<f32>, y: f32) -> Coord<f32> {
-    Coord {
-        x: lerp(a.x, b.x, (y - a.y) / (b.y - a.y)),
-        y,
-    }
-}
-
 #[inline]
 pub fn point_lerp(a: &Coord<f32>, b: &Coord<f32>, f: f32) -> Coord<f32> {
     Coord {
@@ -65,6 +65,40 @@ pub enum SlicerWarnings {
         ///The extrusion width
         extrusion_width: f32,
     },
+
+    ///Two objects overlap and the overlap will be printed twice
+    ObjectsOverlap {
+        ///Index of the first object
+        first: usize,
+        ///Index of the second object
+        second: usize,
+    },
+
+    ///A layer has more retractions than the configured maximum
+    ExcessiveRetraction {
+        ///The index of the layer
+        layer: usize,
+        ///The number of retractions on the layer
+        count: usize,
+    },
+
+    ///Fiber moves at turns tighter than the minimum fiber radius were printed without fiber
+    FiberTurnsTooTight {
+        ///The number of moves printed without fiber
+        segments: usize,
+        ///The minimum fiber radius
+        min_radius: f32,
+    },
+
+    ///Extrusion widths of some layers were clamped to the range the nozzle can print
+    ExtrusionWidthClamped {
+        ///The number of layers with a clamped width
+        layers: usize,
+        ///The smallest allowed width
+        min_width: f32,
+        ///The largest allowed width
+        max_width: f32,
+    },
 }
 
 impl SlicerWarnings {
@@ -95,6 +129,18 @@ impl SlicerWarnings {
             SlicerWarnings::ExtrusionWidthTooLow { nozzle_diameter, extrusion_width } => {
                 (0x1007, format!("The provided extrusion width({} mm) is less than 60% of the nozzle diameter({} mm).", extrusion_width, nozzle_diameter))
             }
+            SlicerWarnings::ObjectsOverlap { first, second } => {
+                (0x1008, format!("Object {} overlaps object {}, the overlapping region will be printed twice.", first, second))
+            }
+            SlicerWarnings::ExcessiveRetraction { layer, count } => {
+                (0x1009, format!("Layer {} has {} retractions, which can grind the filament.", layer, count))
+            }
+            SlicerWarnings::FiberTurnsTooTight { segments, min_radius } => {
+                (0x100A, format!("{} fiber moves turn tighter than the minimum radius({} mm) and are printed without fiber.", segments, min_radius))
+            }
+            SlicerWarnings::ExtrusionWidthClamped { layers, min_width, max_width } => {
+                (0x100B, format!("The extrusion widths of {} layers were clamped to between {} mm and {} mm.", layers, min_width, max_width))
+            }
         }
     }
 }
@@ -0,0 +1,41 @@
+use std::path::Path;
+
+///Write a binary STL of an axis aligned box with a corner at the origin
+pub fn write_cuboid_stl(path: &Path, size: [f32; 3]) {
+    let corner = |index: usize| {
+        [
+            (index & 1) as f32 * size[0],
+            ((index >> 1) & 1) as f32 * size[1],
+            ((index >> 2) & 1) as f32 * size[2],
+        ]
+    };
+
+    //Two outward facing triangles for each side
+    let triangles = [
+        [0, 2, 1],
+        [1, 2, 3],
+        [4, 5, 6],
+        [5, 7, 6],
+        [0, 1, 4],
+        [1, 5, 4],
+        [2, 6, 3],
+        [3, 6, 7],
+        [0, 4, 2],
+        [2, 4, 6],
+        [1, 3, 5],
+        [3, 7, 5],
+    ];
+
+    let mut bytes = vec![0; 80];
+    bytes.extend((triangles.len() as u32).to_le_bytes());
+    for triangle in triangles {
+        //The normal is ignored when loading
+        bytes.extend([0.0_f32; 3].iter().flat_map(|v| v.to_le_bytes()));
+        for vertex in triangle {
+            bytes.extend(corner(vertex).iter().flat_map(|v| v.to_le_bytes()));
+        }
+        bytes.extend([0; 2]);
+    }
+
+    std::fs::write(path, bytes).unwrap();
+}
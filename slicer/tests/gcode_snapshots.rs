@@ -0,0 +1,172 @@
+//! Slices fixed models and compares the gcode with the golden files in `tests/goldens`, so
+//! refactors can't silently change the output.
+//!
+//! After an intended change of the output, regenerate the goldens with
+//! `UPDATE_GOLDENS=1 cargo test -p slicer --test gcode_snapshots` and review their diff. The same
+//! command writes the golden of a new snapshot, without it a missing golden fails the test.
+
+use std::path::{Path, PathBuf};
+
+use slicer::gcode::{write_gcode, GCodeFileWriter};
+use slicer::{slice_from_stl, PartialInfillTypes, Settings};
+
+mod common;
+
+//Numbers are written with 5 decimals, differences below this are floating point noise
+const NUMBER_TOLERANCE: f64 = 0.001;
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("goldens")
+        .join(format!("{}.gcode", name))
+}
+
+///Slice a box of `size` with the settings and write the gcode of it
+fn slice_to_gcode(name: &str, size: [f32; 3], settings: &Settings) -> String {
+    let dir = std::env::temp_dir().join(format!(
+        "fiberslice-snapshot-{}-{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let stl = dir.join("model.stl");
+    common::write_cuboid_stl(&stl, size);
+    let result = slice_from_stl(&stl, settings).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut gcode = vec![];
+    write_gcode(
+        &result.moves,
+        &result.settings,
+        &mut GCodeFileWriter::new(&mut gcode),
+    )
+    .unwrap();
+
+    String::from_utf8(gcode).unwrap()
+}
+
+///Lines of the gcode split into words, without empty lines and with any run of whitespace
+///between words treated the same
+fn normalize(gcode: &str) -> Vec<Vec<&str>> {
+    gcode
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+///Words match if they are equal, or are the same axis or parameter with values within the
+///tolerance
+fn words_match(expected: &str, actual: &str) -> bool {
+    let parameter = |word: &str| {
+        let mut chars = word.chars();
+        let letter = chars.next().filter(|c| c.is_ascii_alphabetic())?;
+        Some((letter, chars.as_str().parse::<f64>().ok()?))
+    };
+
+    expected == actual
+        || match (parameter(expected), parameter(actual)) {
+            (Some((a, x)), Some((b, y))) => a == b && (x - y).abs() <= NUMBER_TOLERANCE,
+            _ => false,
+        }
+}
+
+///Compare the gcode with its golden file, or write the golden file when regenerating was asked for
+fn assert_matches_golden(name: &str, gcode: &str) {
+    let path = golden_path(name);
+
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, gcode).unwrap();
+        eprintln!("Wrote golden file {}", path.display());
+        return;
+    }
+
+    assert!(
+        path.exists(),
+        "The golden file {} of {} is missing. Run with UPDATE_GOLDENS=1 to write it and commit it",
+        path.display(),
+        name
+    );
+
+    let golden = std::fs::read_to_string(&path).unwrap();
+    let expected = normalize(&golden);
+    let actual = normalize(gcode);
+
+    for (line, (expected, actual)) in expected.iter().zip(&actual).enumerate() {
+        let same = expected.len() == actual.len()
+            && expected.iter().zip(actual).all(|(e, a)| words_match(e, a));
+        assert!(
+            same,
+            "{} differs from {} at line {}:\nexpected: {}\n  actual: {}\nRun with UPDATE_GOLDENS=1 \
+             if the change is intended",
+            name,
+            path.display(),
+            line + 1,
+            expected.join(" "),
+            actual.join(" ")
+        );
+    }
+
+    assert_eq!(
+        expected.len(),
+        actual.len(),
+        "{} has a different number of lines than {}. Run with UPDATE_GOLDENS=1 if the change is \
+         intended",
+        name,
+        path.display()
+    );
+}
+
+#[test]
+fn comparison_ignores_insignificant_differences() {
+    assert_eq!(
+        normalize("G1  X1.00000\tY2.00000 \r\n\n;LAYER:1\n"),
+        vec![vec!["G1", "X1.00000", "Y2.00000"], vec![";LAYER:1"]]
+    );
+
+    assert!(words_match("X1.00000", "X1.00001"));
+    assert!(words_match("D0.50000", "D0.50040"));
+    assert!(!words_match("X1.00000", "X1.01000"));
+    assert!(!words_match("X1.00000", "Y1.00000"));
+    assert!(!words_match(";TYPE:WALL", ";TYPE:FILL"));
+}
+
+#[test]
+fn cube_with_fiber() {
+    let gcode = slice_to_gcode("cube_with_fiber", [10.0, 10.0, 4.0], &Settings::default());
+
+    //The snapshot has to cover the fiber axis and the annotations
+    let lines = normalize(&gcode);
+    assert!(lines
+        .iter()
+        .any(|words| words[0] == "G1" && words.iter().any(|word| word.starts_with('D'))));
+    assert!(lines.iter().any(|words| words[0].starts_with(";TYPE:")));
+    assert!(lines.iter().any(|words| words[0].starts_with(";LAYER:")));
+
+    assert_matches_golden("cube_with_fiber", &gcode);
+}
+
+#[test]
+fn block_with_gyroid_infill() {
+    let settings = Settings {
+        partial_infill_type: PartialInfillTypes::Gyroid,
+        ..Default::default()
+    };
+
+    let gcode = slice_to_gcode("block_with_gyroid_infill", [30.0, 10.0, 4.0], &settings);
+    assert_matches_golden("block_with_gyroid_infill", &gcode);
+}
+
+#[test]
+fn spiralized_cube() {
+    let settings = Settings {
+        spiralize: true,
+        ..Default::default()
+    };
+
+    let gcode = slice_to_gcode("spiralized_cube", [10.0, 10.0, 4.0], &settings);
+    assert_matches_golden("spiralized_cube", &gcode);
+}
@@ -0,0 +1,31 @@
+use slicer::{slice_from_stl, Command, Settings, SlicerErrors};
+
+mod common;
+
+#[test]
+fn slice_stl_without_viewer() {
+    let dir = std::env::temp_dir().join(format!("fiberslice-headless-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let settings = Settings::default();
+    let stl = dir.join("cube.stl");
+    common::write_cuboid_stl(&stl, [10.0; 3]);
+
+    let result = slice_from_stl(&stl, &settings).unwrap();
+    assert!(result
+        .moves
+        .iter()
+        .any(|cmd| matches!(cmd, Command::MoveAndExtrude { .. })));
+
+    let gcode = dir.join("cube.gcode");
+    result.write_gcode_to(&gcode).unwrap();
+    let written = std::fs::read_to_string(&gcode).unwrap();
+    assert!(written.lines().any(|line| line.starts_with("G1 ")));
+
+    assert!(matches!(
+        slice_from_stl(dir.join("missing.stl"), &settings),
+        Err(SlicerErrors::ObjectFileNotFound { .. })
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
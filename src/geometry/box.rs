@@ -81,6 +81,15 @@ impl BoundingBox {
             && point.z <= self.max.z
     }
 
+    pub fn intersects(&self, min: Vec3, max: Vec3) -> bool {
+        self.min.x <= max.x
+            && min.x <= self.max.x
+            && self.min.y <= max.y
+            && min.y <= self.max.y
+            && self.min.z <= max.z
+            && min.z <= self.max.z
+    }
+
     pub fn faces(&self) -> [QuadFace; 6] {
         [
             QuadFace {
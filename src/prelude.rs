@@ -329,6 +329,39 @@ impl Default for Mode {
     }
 }
 
+///Where the viewer puts the origin of the coordinates it shows. Only the display changes, the
+///gcode always uses the coordinates of the printer
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, EnumIter)]
+pub enum OriginMode {
+    #[default]
+    Center,
+    FrontLeft,
+}
+
+impl OriginMode {
+    ///Where the origin is on a bed of `print_x` by `print_y`, in printer coordinates
+    pub fn origin(&self, print_x: f32, print_y: f32) -> glam::Vec2 {
+        match self {
+            Self::Center => glam::vec2(print_x, print_y) / 2.0,
+            Self::FrontLeft => glam::Vec2::ZERO,
+        }
+    }
+
+    ///The displayed coordinates of a point given in printer coordinates
+    pub fn display(&self, printer: glam::Vec2, print_x: f32, print_y: f32) -> glam::Vec2 {
+        printer - self.origin(print_x, print_y)
+    }
+}
+
+impl std::fmt::Display for OriginMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Center => write!(f, "Center"),
+            Self::FrontLeft => write!(f, "Front Left"),
+        }
+    }
+}
+
 pub trait Destroyable {
     fn destroy(&self);
     fn is_destroyed(&self) -> bool;
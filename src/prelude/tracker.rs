@@ -29,9 +29,17 @@ impl ProcessTracker {
         });
     }
 
-    pub fn add(&mut self, id: u32, mut name: String) -> Shared<Process> {
-        let process = Shared::new(Process::new());
+    pub fn add(&mut self, id: u32, name: String) -> Shared<Process> {
+        self.track(id, name, Shared::new(Process::new()))
+    }
 
+    ///Show the progress of a process created elsewhere, like `add`
+    pub fn track(
+        &mut self,
+        id: u32,
+        mut name: String,
+        process: Shared<Process>,
+    ) -> Shared<Process> {
         {
             let inner_map = self.map.get(&id).unwrap();
 
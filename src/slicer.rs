@@ -1,11 +1,12 @@
 use std::fmt::Debug;
 
 use log::info;
-use shared::{object::ObjectMesh, SliceInput};
-use slicer::{Mask, Settings};
+use shared::{object::ObjectMesh, process::Process, SliceInput};
+use slicer::{Mask, Settings, SliceResult, SlicerErrors};
 use tokio::task::JoinHandle;
 
 use crate::{
+    prelude::{Shared, SharedMut},
     ui::{api::trim_text, custom_toasts::SLICING_PROGRESS},
     GlobalState, RootEvent,
 };
@@ -14,6 +15,11 @@ use crate::{
 pub struct Slicer {
     pub settings: Settings,
     handle: Option<JoinHandle<()>>,
+    //The process of the latest slice, cancelled when it is replaced
+    process: Option<Shared<Process>>,
+    //The last slice and its key, reused while the meshes and the settings changing the moves stay
+    //the same
+    cache: SharedMut<Option<(u64, SliceResult)>>,
 }
 
 fn try_load_settings() -> Option<Settings> {
@@ -42,51 +48,104 @@ impl Default for Slicer {
             return Self {
                 settings,
                 handle: None,
+                process: None,
+                cache: SharedMut::default(),
             };
         }
 
         Self {
             settings: Settings::default(),
             handle: None,
+            process: None,
+            cache: SharedMut::default(),
         }
     }
 }
 
 impl Slicer {
+    ///Slice the objects on the bed, a slice that is still running is cancelled
     pub fn slice(&mut self, global_state: &GlobalState<RootEvent>) {
-        if let Some(handle) = self.handle.take() {
-            if !handle.is_finished() {
-                return;
-            }
-        }
+        self.cancel();
 
         let settings = self.settings.clone();
         let objects: Vec<ObjectMesh> = global_state.viewer.prepare_objects(&settings);
         let masks: Vec<Mask> = global_state.viewer.prepare_masks(&settings);
+        let input = SliceInput {
+            objects,
+            masks,
+            modifiers: vec![],
+        };
+        let key = slicer::slice_key(&input, &settings);
 
         let global_state = global_state.clone();
+        let process = Shared::new(Process::new());
+        self.process = Some(process.clone());
+        let cache = self.cache.clone();
 
         let handle = tokio::spawn(async move {
-            let process = global_state
-                .progress_tracker
-                .write()
-                .add(SLICING_PROGRESS, trim_text::<20, 4>("Slicing model"));
-
-            let result = slicer::slice(SliceInput { objects, masks }, &settings, &process)
-                .expect("Failed to slice model");
-
-            global_state.viewer.load_sliced(result, process);
+            let process = global_state.progress_tracker.write().track(
+                SLICING_PROGRESS,
+                trim_text::<20, 4>("Slicing model"),
+                process,
+            );
+
+            let cached = cache
+                .read()
+                .as_ref()
+                .filter(|(cached_key, _)| *cached_key == key)
+                .map(|(_, result)| result.clone());
+
+            let result = match cached {
+                Some(result) => {
+                    info!("Only gcode settings changed, reusing the previous slice");
+                    Ok(result.with_settings(&settings))
+                }
+                None => slicer::slice(input, &settings, &process).map(|result| {
+                    *cache.write() = Some((key, result.clone()));
+                    result
+                }),
+            };
 
-            global_state
-                .ui_event_writer
-                .send(crate::ui::UiEvent::ShowSuccess(
-                    "Slicing finished".to_string(),
-                ));
+            match result {
+                //A slice finishing as it is cancelled is outdated as well
+                Ok(_) | Err(SlicerErrors::Cancelled) if process.is_cancelled() => {
+                    info!("Slicing cancelled");
+                    process.finish();
+                }
+                Ok(result) => {
+                    global_state.viewer.load_sliced(result, process);
+
+                    global_state
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowSuccess(
+                            "Slicing finished".to_string(),
+                        ));
+                }
+                Err(err) => {
+                    process.finish();
+
+                    global_state
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowError(err.get_code_and_message().1));
+                }
+            }
         });
 
         self.handle = Some(handle);
     }
 
+    ///Forget the last slice, so the next slice runs from scratch
+    pub fn clear_cache(&mut self) {
+        *self.cache.write() = None;
+    }
+
+    ///Stop the running slice at its next check
+    fn cancel(&mut self) {
+        if let Some(process) = self.process.take() {
+            process.cancel();
+        }
+    }
+
     pub fn save(&self) {
         let path = crate::CONFIG.get().unwrap().settings_path.clone();
 
@@ -96,6 +155,8 @@ impl Slicer {
     }
 
     pub fn exit(&mut self) {
+        self.cancel();
+
         if let Some(handle) = self.handle.take() {
             if !handle.is_finished() {
                 handle.abort();
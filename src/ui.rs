@@ -41,10 +41,12 @@ use boundary::Boundary;
 #[derive(Debug, Clone)]
 pub enum UiEvent {
     ShowInfo(String),
+    ShowWarning(String),
     ShowSuccess(String),
     ShowError(String),
     ShowProgressBar(u32, String),
     GCodeReaderLookAt(usize),
+    GCodeCursorMoved(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -249,7 +251,7 @@ impl<'a> Adapter<'a, RootEvent, UiState, (UiUpdateOutput, (f32, f32, f32, f32)),
     fn handle_event(
         &mut self,
         wgpu_context: &WgpuContext,
-        _global_state: &GlobalState<RootEvent>,
+        global_state: &GlobalState<RootEvent>,
         event: UiEvent,
     ) {
         match event {
@@ -267,6 +269,20 @@ impl<'a> Adapter<'a, RootEvent, UiState, (UiUpdateOutput, (f32, f32, f32, f32)),
 
                 wgpu_context.window.request_redraw();
             }
+            UiEvent::ShowWarning(message) => {
+                self.screen.add_toast(
+                    egui_toast::Toast::with_name("Warning".into())
+                        .kind(egui_toast::ToastKind::Warning)
+                        .text(message)
+                        .options(
+                            ToastOptions::default()
+                                .duration_in_seconds(5.0)
+                                .show_progress(true),
+                        ),
+                );
+
+                wgpu_context.window.request_redraw();
+            }
             UiEvent::ShowSuccess(message) => {
                 self.screen.add_toast(
                     egui_toast::Toast::with_name("Success".into())
@@ -308,6 +324,11 @@ impl<'a> Adapter<'a, RootEvent, UiState, (UiUpdateOutput, (f32, f32, f32, f32)),
             UiEvent::GCodeReaderLookAt(line) => {
                 self.screen.tools_mut().gcode_tool.look_at(line);
             }
+            UiEvent::GCodeCursorMoved(line) => {
+                global_state.viewer.select_trace_at_line(line);
+
+                wgpu_context.window.request_redraw();
+            }
         }
     }
 }
@@ -124,6 +124,7 @@ impl CADTools {
                                     let path = FileDialog::new()
                                         .set_location("~")
                                         .add_filter("STL Files", &["stl"])
+                                        .add_filter("3MF Files", &["3mf"])
                                         .show_open_single_file()
                                         .unwrap();
 
@@ -1,8 +1,10 @@
 use egui::TextWrapMode;
 use egui::Ui;
 use native_dialog::FileDialog;
+use strum::IntoEnumIterator;
 
 use crate::config;
+use crate::prelude::OriginMode;
 use crate::ui::boundary::Boundary;
 use crate::ui::UiComponent;
 use crate::ui::UiComponentState;
@@ -13,6 +15,10 @@ use crate::RootEvent;
 pub struct MenubarState {
     enabled: bool,
     boundary: Boundary,
+
+    grid: bool,
+    grid_spacing: f32,
+    origin: OriginMode,
 }
 
 impl MenubarState {
@@ -20,6 +26,10 @@ impl MenubarState {
         Self {
             enabled: true,
             boundary: Boundary::zero(),
+
+            grid: false,
+            grid_spacing: 10.0,
+            origin: OriginMode::default(),
         }
     }
 }
@@ -66,6 +76,7 @@ impl<'a> UiComponent for Menubar<'a> {
                     egui::menu::bar(ui, |ui| {
                         file_button(ui, shared_state);
                         self.window_button(ui, shared_state);
+                        self.view_button(ui, shared_state);
                         // self.setting_button(ui, shared_state);
                         help_button(ui, shared_state);
                     });
@@ -98,6 +109,56 @@ impl<'a> Menubar<'a> {
         });
     }
 
+    fn view_button(
+        &mut self,
+        ui: &mut Ui,
+        (_ui_state, global_state): &(UiState, GlobalState<RootEvent>),
+    ) {
+        ui.menu_button("View", |ui| {
+            ui.set_min_width(220.0);
+            ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+
+            let mut changed = ui.checkbox(&mut self.state.grid, "Bed Grid").changed();
+
+            ui.add_enabled_ui(self.state.grid, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Grid Spacing");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.state.grid_spacing)
+                                .range(1.0..=100.0)
+                                .suffix(" mm"),
+                        )
+                        .changed();
+                });
+            });
+
+            if changed {
+                global_state
+                    .viewer
+                    .set_grid(self.state.grid.then_some(self.state.grid_spacing));
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Origin");
+                egui::ComboBox::from_id_salt("menubar-origin")
+                    .selected_text(self.state.origin.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in OriginMode::iter() {
+                            if ui
+                                .selectable_value(&mut self.state.origin, mode, mode.to_string())
+                                .changed()
+                            {
+                                global_state.viewer.set_origin_display(mode);
+                            }
+                        }
+                    });
+            });
+        });
+    }
+
     #[allow(dead_code)]
     fn setting_button(&mut self, ui: &mut Ui, _shared_state: &(UiState, GlobalState<RootEvent>)) {
         ui.menu_button("Settings", |ui| {
@@ -122,6 +183,7 @@ fn file_button(ui: &mut Ui, (_ui_state, global_state): &(UiState, GlobalState<Ro
             let path = FileDialog::new()
                 .set_location("~")
                 .add_filter("STL Files", &["stl"])
+                .add_filter("3MF Files", &["3mf"])
                 .show_open_single_file()
                 .unwrap();
 
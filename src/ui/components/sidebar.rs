@@ -9,6 +9,7 @@ use api::size_fixed::StaticSizedLabel;
 use api::trim_text;
 use egui::*;
 use settings::UiSetting;
+use slicer::gcode::GCodeFormat;
 
 use crate::config;
 use crate::prelude::Destroyable;
@@ -285,7 +286,17 @@ fn show_buttons(shared_state: &(UiState, GlobalState<RootEvent>), ui: &mut Ui) {
             .add_enabled(shared_state.1.viewer.already_sliced(), export_button)
             .clicked()
         {
-            shared_state.1.viewer.export_gcode();
+            shared_state.1.viewer.export_gcode(GCodeFormat::Text);
+        }
+
+        let export_binary_button = Button::new("Export Binary GCode")
+            .min_size(Vec2::new(ui.available_width() * 0.5, 20.0));
+
+        if ui
+            .add_enabled(shared_state.1.viewer.already_sliced(), export_binary_button)
+            .clicked()
+        {
+            shared_state.1.viewer.export_gcode(GCodeFormat::Binary);
         }
 
         let rich_text = RichText::new("Slice")
@@ -62,10 +62,24 @@ fn show_progress(ui: &mut egui::Ui, toast: &mut Toast, process: &Arc<Process>) -
                     .animate(true)
                     .desired_width(PROGRESS_BAR_WIDTH),
             );
+
+            if let Some(eta) = process.eta() {
+                ui.label(format!("About {} left", format_eta(eta)));
+            }
         })
         .response
 }
 
+fn format_eta(secs: f32) -> String {
+    let secs = secs.ceil() as u64;
+
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
 fn show_finished(ui: &mut egui::Ui, toast: &mut Toast) -> egui::Response {
     egui::Frame::window(ui.style())
         .show(ui, |ui| {
@@ -15,16 +15,32 @@ use egui_toast::Toasts;
 pub struct ViewerTooltip {
     server_type: String,
     model: String,
+    position: Option<glam::Vec2>,
 }
 
 impl ViewerTooltip {
     pub fn new(server_type: String, model: String) -> Self {
-        Self { server_type, model }
+        Self {
+            server_type,
+            model,
+            position: None,
+        }
+    }
+
+    pub fn with_position(self, position: glam::Vec2) -> Self {
+        Self {
+            position: Some(position),
+            ..self
+        }
     }
 
     pub fn show(&self, ui: &mut egui::Ui) {
         ui.label(egui::RichText::new(&self.server_type).strong());
         ui.label(&self.model);
+
+        if let Some(position) = self.position {
+            ui.label(format!("X {:.1} mm  Y {:.1} mm", position.x, position.y));
+        }
     }
 }
 
@@ -1,7 +1,8 @@
-use std::{fmt::Debug, ops::RangeInclusive};
+use std::{fmt::Debug, ops::RangeInclusive, sync::Arc};
 
 use egui::{DragValue, InnerResponse, Response, Ui};
 use egui_code_editor::{ColorTheme, Syntax};
+use native_dialog::FileDialog;
 use slicer::{
     fiber::{self, FiberSettings},
     FanSettings, FilamentSettings, MovementParameter, OptionalSetting, RetractionWipeSettings,
@@ -30,10 +31,43 @@ impl UiSetting for slicer::Settings {
     fn show_general(&mut self, ui: &mut egui::Ui) {
         show_f32(&mut self.layer_height, "Layer height", Some("mm"), 0.0, ui);
 
+        show_optional_setting(
+            &mut self.adaptive_layer_height,
+            "Adaptive layer height",
+            |adaptive, ui| {
+                show_f32(&mut adaptive.min_height, "Min height", Some("mm"), 0.2, ui);
+                show_f32(&mut adaptive.max_height, "Max height", Some("mm"), 0.3, ui);
+                show_f32(&mut adaptive.quality_factor, "Quality", None, 0.5, ui);
+            },
+            false,
+            ui,
+        );
+
         egui::CollapsingHeader::new("Extrusion Width")
             .default_open(true)
             .show(ui, |ui| {
                 ExtrusionMovementParameter(&mut self.extrusion_width).show(ui);
+                show_bool(
+                    &mut self.clamp_extrusion_width,
+                    "Clamp to nozzle range",
+                    None,
+                    false,
+                    ui,
+                );
+                show_usize(
+                    &mut self.width_ramp_layers,
+                    "Width ramp layers",
+                    None,
+                    0,
+                    ui,
+                );
+                show_f32(
+                    &mut self.first_layer_width_multiplier,
+                    "First layer width multiplier",
+                    None,
+                    1.0,
+                    ui,
+                );
             });
 
         egui::CollapsingHeader::new("Filament")
@@ -91,6 +125,8 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_combo(&mut self.layer_change_approach, "Layer change approach", ui);
+
         show_f32(
             &mut self.retract_speed,
             "Retract speed",
@@ -99,6 +135,15 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        //The filament of each extruder is edited in the settings file
+        show_f32(
+            &mut self.toolchange_retract_length,
+            "Toolchange retract length",
+            Some("mm"),
+            10.0,
+            ui,
+        );
+
         show_optional_setting(
             &mut self.retraction_wipe,
             "Retraction Wipe Settings",
@@ -109,6 +154,23 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_optional_setting(
+            &mut self.coasting,
+            "Coasting",
+            |settings, ui| {
+                show_f32(&mut settings.volume, "Volume", Some("mm³"), 0.064, ui);
+                show_f32(
+                    &mut settings.min_volume_before_coast,
+                    "Min volume before coast",
+                    Some("mm³"),
+                    0.8,
+                    ui,
+                );
+            },
+            false,
+            ui,
+        );
+
         egui::CollapsingHeader::new("Movement Speed")
             .default_open(true)
             .show(ui, |ui| {
@@ -129,6 +191,16 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_optional_setting(
+            &mut self.solid_infill_every_layers,
+            "Solid infill every",
+            |setting, ui| {
+                show_usize(setting, "Layers", None, 10, ui);
+            },
+            false,
+            ui,
+        );
+
         show_bool(
             &mut self.inner_perimeters_first,
             "Inner perimeters first",
@@ -137,6 +209,9 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_bool(&mut self.outline_only, "Outline only", None, false, ui);
+        show_bool(&mut self.spiralize, "Spiralize", None, false, ui);
+
         show_usize(
             &mut self.number_of_perimeters,
             "Number of perimeters",
@@ -145,8 +220,72 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_optional_setting(
+            &mut self.top_bottom_perimeters,
+            "Top and bottom perimeters",
+            |setting, ui| {
+                show_usize(setting, "Perimeters", None, 1, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_usize(
+            &mut self.inner_walls_every_layers,
+            "Inner walls every layers",
+            None,
+            1,
+            ui,
+        );
+
+        show_bool(
+            &mut self.variable_width_walls,
+            "Variable width thin walls",
+            None,
+            false,
+            ui,
+        );
+
+        show_combo(&mut self.seam_position, "Seam position", ui);
+
+        show_optional_setting(
+            &mut self.alternating_wall_width,
+            "Alternating wall width",
+            |setting, ui| {
+                show_f32(setting, "Extra width", Some("mm"), 0.05, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.fuzzy_skin,
+            "Fuzzy skin",
+            |settings, ui| {
+                show_f32(&mut settings.thickness, "Thickness", Some("mm"), 0.3, ui);
+                show_f32(
+                    &mut settings.point_distance,
+                    "Point distance",
+                    Some("mm"),
+                    0.8,
+                    ui,
+                );
+                show_usize(&mut settings.seed, "Seed", None, 0, ui);
+            },
+            false,
+            ui,
+        );
+
         show_usize(&mut self.top_layers, "Top layers", None, 4, ui);
 
+        show_usize(
+            &mut self.top_transition_layers,
+            "Top transition layers",
+            None,
+            0,
+            ui,
+        );
+
         show_usize(&mut self.bottom_layers, "Bottom layers", None, 4, ui);
 
         show_optional_setting(
@@ -159,6 +298,16 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_optional_setting(
+            &mut self.auto_brim_min_contact_area,
+            "Auto brim",
+            |setting, ui| {
+                show_f32(setting, "Min contact area", Some("mm²"), 0.0, ui);
+            },
+            false,
+            ui,
+        );
+
         show_optional_setting(
             &mut self.layer_shrink_amount,
             "Layer shrink amount",
@@ -185,8 +334,273 @@ impl UiSetting for slicer::Settings {
             ui,
         );
 
+        show_f32(
+            &mut self.solid_infill_overlap_percentage,
+            "Solid infill perimeter overlap percentage",
+            Some("%"),
+            0.0,
+            ui,
+        );
+
         show_combo(&mut self.solid_infill_type, "Solid infill type", ui);
+        show_bool(
+            &mut self.align_solid_to_longest_edge,
+            "Align solid infill to longest edge",
+            None,
+            false,
+            ui,
+        );
         show_combo(&mut self.partial_infill_type, "Partial infill type", ui);
+
+        //Spacing the infill percentage results in above the first layer
+        let layer_settings = self.get_layer_settings(1, self.layer_height * 2.0);
+        if let Some(spacing) = layer_settings.infill_line_spacing(layer_settings.infill_percentage)
+        {
+            ui.label(format!("Infill line spacing: {:.2} mm", spacing));
+        }
+
+        show_optional_setting(
+            &mut self.first_layer_infill_angle,
+            "First layer infill angle",
+            |setting, ui| {
+                show_f32(setting, "Angle", Some("°"), 0.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.bridge_angle_override,
+            "Bridge angle override",
+            |setting, ui| {
+                show_f32(setting, "Bridge angle", Some("°"), 0.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_f32_with_range(
+            &mut self.bridge_density,
+            0.05..=1.0,
+            "Bridge density",
+            Some("× solid"),
+            1.0,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.max_bridge_length,
+            "Max bridge length",
+            |setting, ui| {
+                show_f32(setting, "Length", Some("mm"), 20.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.bridge,
+            "Bridge overrides",
+            |settings, ui| {
+                show_f32(&mut settings.flow_ratio, "Flow ratio", None, 0.9, ui);
+                show_f32(&mut settings.fan_speed, "Fan speed", Some("%"), 100.0, ui);
+                show_f32(&mut settings.speed, "Speed", Some("mm/s"), 25.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.ooze_shield,
+            "Ooze Shield",
+            |settings, ui| {
+                show_f32(&mut settings.distance, "Distance", Some("mm"), 2.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.wipe_tower,
+            "Wipe tower",
+            |settings, ui| {
+                show_f32(&mut settings.x, "X", Some("mm"), 170.0, ui);
+                show_f32(&mut settings.y, "Y", Some("mm"), 170.0, ui);
+                show_f32(&mut settings.width, "Width", Some("mm"), 20.0, ui);
+                show_f32(
+                    &mut settings.purge_volume,
+                    "Purge volume",
+                    Some("mm³"),
+                    20.0,
+                    ui,
+                );
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.gap_fill,
+            "Gap fill",
+            |setting, ui| {
+                show_f32(setting, "Max gap width", Some("mm"), 0.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_bool(
+            &mut self.minimize_retractions,
+            "Minimize retractions between islands",
+            None,
+            false,
+            ui,
+        );
+
+        show_bool(
+            &mut self.remove_zero_length_moves,
+            "Remove zero length moves",
+            None,
+            true,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.max_retractions_per_layer,
+            "Warn about retractions per layer",
+            |setting, ui| {
+                show_usize(setting, "Max retractions", None, 50, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.resume_from_layer,
+            "Resume from layer",
+            |setting, ui| {
+                show_usize(setting, "Layer", None, 0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_bool(
+            &mut self.final_retract_no_lift,
+            "No z lift on final retract",
+            None,
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.arc_tolerance,
+            "Arc fitting",
+            |setting, ui| {
+                show_f32(setting, "Tolerance", Some("mm"), 0.02, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_combo(&mut self.overlapping_objects, "Overlapping objects", ui);
+
+        show_combo(&mut self.print_sequence, "Print sequence", ui);
+        show_f32(
+            &mut self.sequential_clearance,
+            "Sequential clearance",
+            Some("mm"),
+            20.0,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.sequential_cooling,
+            "Sequential cooling",
+            |settings, ui| {
+                show_f32(&mut settings.park_x, "Park X", Some("mm"), 0.0, ui);
+                show_f32(&mut settings.park_y, "Park Y", Some("mm"), 0.0, ui);
+                show_f32(&mut settings.clearance, "Clearance", Some("mm"), 10.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.object_prime,
+            "Object prime",
+            |settings, ui| {
+                show_f32(&mut settings.length, "Length", Some("mm"), 20.0, ui);
+                show_f32(&mut settings.distance, "Distance", Some("mm"), 3.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.standby_temp,
+            "Standby temperature",
+            |settings, ui| {
+                show_f32(
+                    &mut settings.temperature,
+                    "Temperature",
+                    Some("°C"),
+                    150.0,
+                    ui,
+                );
+                show_f32(
+                    &mut settings.min_idle_time,
+                    "Min idle time",
+                    Some("s"),
+                    30.0,
+                    ui,
+                );
+                show_f32(
+                    &mut settings.reheat_rate,
+                    "Reheat rate",
+                    Some("°C/s"),
+                    2.0,
+                    ui,
+                );
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.standby_travel_threshold,
+            "Standby on long travels",
+            |setting, ui| {
+                show_f32(setting, "Min travel length", Some("mm"), 100.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_optional_setting(
+            &mut self.ironing,
+            "Ironing",
+            |settings, ui| {
+                show_f32(&mut settings.flow, "Flow", None, 0.15, ui);
+                show_f32(&mut settings.spacing, "Spacing", Some("mm"), 0.1, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_combo(
+            &mut self.thin_part_solid_policy,
+            "Thin part solid policy",
+            ui,
+        );
+
+        show_f32(
+            &mut self.skip_infill_below_area,
+            "Skip infill below area",
+            Some("mm²"),
+            0.0,
+            ui,
+        );
     }
 
     fn show_printer(&mut self, ui: &mut egui::Ui) {
@@ -236,6 +650,58 @@ impl UiSetting for slicer::Settings {
                 .viewer
                 .update_printer_dimension(self.print_x, self.print_y, self.print_z);
         }
+
+        show_combo(&mut self.bed_type, "Bed type", ui);
+
+        egui::CollapsingHeader::new("Bed Transform")
+            .default_open(false)
+            .show(ui, |ui| {
+                show_bool(&mut self.bed_transform.flip_x, "Flip X", None, false, ui);
+                show_bool(&mut self.bed_transform.flip_y, "Flip Y", None, false, ui);
+                show_usize(
+                    &mut self.bed_transform.rotate_90_steps,
+                    "Rotate 90° steps",
+                    None,
+                    0,
+                    ui,
+                );
+                show_f32(
+                    &mut self.bed_transform.offset.0,
+                    "Offset X",
+                    Some("mm"),
+                    0.0,
+                    ui,
+                );
+                show_f32(
+                    &mut self.bed_transform.offset.1,
+                    "Offset Y",
+                    Some("mm"),
+                    0.0,
+                    ui,
+                );
+            });
+
+        //The heights themselves are edited in the settings file
+        show_optional_setting(
+            &mut self.bed_mesh,
+            "Bed mesh",
+            |mesh, ui| {
+                show_f32(&mut mesh.origin_x, "Origin X", Some("mm"), 0.0, ui);
+                show_f32(&mut mesh.origin_y, "Origin Y", Some("mm"), 0.0, ui);
+                show_f32(&mut mesh.spacing_x, "Spacing X", Some("mm"), 10.0, ui);
+                show_f32(&mut mesh.spacing_y, "Spacing Y", Some("mm"), 10.0, ui);
+            },
+            false,
+            ui,
+        );
+
+        show_usize(
+            &mut self.bed_mesh_fade_layers,
+            "Bed mesh fade layers",
+            None,
+            3,
+            ui,
+        );
     }
 
     fn show_layer_specific(&mut self, _ui: &mut egui::Ui) {
@@ -243,6 +709,14 @@ impl UiSetting for slicer::Settings {
     }
 
     fn show_instructions(&mut self, ui: &mut egui::Ui) {
+        show_combo(
+            &mut self.unknown_placeholder_policy,
+            "Unknown placeholders",
+            ui,
+        );
+
+        ui.add_space(10.0);
+
         ui.label("Starting instructions");
 
         egui::ScrollArea::both()
@@ -334,9 +808,43 @@ impl UiSetting for slicer::Settings {
                     .with_syntax(Syntax::gcode())
                     .show(ui, &mut self.object_change_instructions);
             });
+
+        ui.separator();
+
+        ui.add_space(10.0);
+
+        ui.label("Resume instructions");
+
+        egui::ScrollArea::both()
+            .id_salt("resume instruction editor scroll area")
+            .max_height(75.0)
+            .show(ui, |ui| {
+                egui_code_editor::CodeEditor::default()
+                    .id_source("resume instruction editor")
+                    .with_fontsize(14.0)
+                    .with_rows(5)
+                    .with_theme(ColorTheme::GRUVBOX)
+                    .with_numlines(false)
+                    .with_syntax(Syntax::gcode())
+                    .show(ui, &mut self.resume_instructions);
+            });
     }
 
     fn show_limits(&mut self, ui: &mut egui::Ui) {
+        show_combo(&mut self.gcode_flavor, "GCode flavor", ui);
+
+        show_usize(&mut self.max_layers, "Max layers", None, 100_000, ui);
+
+        show_optional_setting(
+            &mut self.pressure_advance,
+            "Pressure advance",
+            |setting, ui| {
+                show_f32(setting, "Factor", None, 0.05, ui);
+            },
+            false,
+            ui,
+        );
+
         show_f32(
             &mut self.max_acceleration_x,
             "Max acceleration X",
@@ -508,6 +1016,8 @@ impl<'a> UiWidgetComponent for ExtrusionMovementParameter<'a> {
         show_f32(&mut self.0.bridge, "Bridge", None, 0.0, ui);
 
         show_f32(&mut self.0.support, "Support", None, 0.0, ui);
+
+        show_f32(&mut self.0.ironing, "Ironing", None, 0.0, ui);
     }
 }
 
@@ -568,6 +1078,8 @@ impl UiWidgetComponent for MovementParameter {
         show_f32(&mut self.bridge, "Bridge", Some("mm/s"), 0.0, ui);
 
         show_f32(&mut self.support, "Support", Some("mm/s"), 0.0, ui);
+
+        show_f32(&mut self.ironing, "Ironing", Some("mm/s"), 0.0, ui);
     }
 }
 
@@ -690,6 +1202,50 @@ impl UiWidgetComponent for SupportSettings {
             settings_default.support_spacing,
             ui,
         );
+        show_f32(
+            &mut self.support_density_bottom,
+            "Density at the bottom",
+            None,
+            settings_default.support_density_bottom,
+            ui,
+        );
+        show_f32(
+            &mut self.support_density_top,
+            "Density at the top",
+            None,
+            settings_default.support_density_top,
+            ui,
+        );
+        show_usize(
+            &mut self.interface_layers,
+            "Interface layers",
+            None,
+            settings_default.interface_layers,
+            ui,
+        );
+        show_combo(&mut self.interface_pattern, "Interface pattern", ui);
+        show_f32(
+            &mut self.interface_spacing,
+            "Interface spacing",
+            Some("mm"),
+            settings_default.interface_spacing,
+            ui,
+        );
+        show_f32(
+            &mut self.z_gap,
+            "Z gap",
+            Some("mm"),
+            settings_default.z_gap,
+            ui,
+        );
+        show_combo(&mut self.style, "Style", ui);
+        show_f32(
+            &mut self.tree_branch_diameter,
+            "Tree branch diameter",
+            Some("mm"),
+            settings_default.tree_branch_diameter,
+            ui,
+        );
     }
 }
 
@@ -718,6 +1274,7 @@ impl UiWidgetComponent for RetractionWipeSettings {
             settings_default.distance,
             ui,
         );
+        show_combo(&mut self.direction, "Direction", ui);
     }
 }
 
@@ -774,6 +1331,113 @@ impl UiWidgetComponent for FiberSettings {
             settings_default.max_angle,
             ui,
         );
+        show_bool(
+            &mut self.alternate_direction,
+            "Alternate Direction",
+            None,
+            settings_default.alternate_direction,
+            ui,
+        );
+        show_f32(
+            &mut self.wall_infill_overlap,
+            "Wall Infill Overlap",
+            Some("mm"),
+            settings_default.wall_infill_overlap,
+            ui,
+        );
+        show_bool(
+            &mut self.suppress_retraction,
+            "Suppress Retraction",
+            None,
+            settings_default.suppress_retraction,
+            ui,
+        );
+        show_f32(
+            &mut self.density,
+            "Density",
+            Some("g/cm³"),
+            settings_default.density,
+            ui,
+        );
+        //Angles are loaded from a grayscale image, black is 0° and white 180°
+        ui.horizontal(|ui| {
+            crate::config::gui::settings::SETTINGS_LABEL.label(ui, "Orientation Map");
+
+            if ui.button("Load").clicked() {
+                let path = FileDialog::new()
+                    .set_location("~")
+                    .add_filter("Images", &["png", "jpg", "jpeg"])
+                    .show_open_single_file();
+
+                let show_error = |message: String| {
+                    GLOBAL_STATE
+                        .read()
+                        .as_ref()
+                        .unwrap()
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowError(message));
+                };
+
+                match path {
+                    Ok(Some(path)) => match image::open(path) {
+                        Ok(image) => {
+                            let image = image.to_luma8();
+                            self.orientation_field =
+                                Some(Arc::new(fiber::OrientationField::from_angle_image(
+                                    image.width() as usize,
+                                    image.as_raw(),
+                                    1.0,
+                                )));
+                        }
+                        Err(e) => show_error(format!("Failed to load orientation map: {}", e)),
+                    },
+                    //The dialog was closed without picking a file
+                    Ok(None) => {}
+                    Err(e) => show_error(format!("Failed to open the file dialog: {}", e)),
+                }
+            }
+
+            if self.orientation_field.is_some() && ui.button("Clear").clicked() {
+                self.orientation_field = None;
+            }
+        });
+
+        if let Some(field) = &mut self.orientation_field {
+            let field = Arc::make_mut(field);
+            show_f32(&mut field.origin_x, "Map Origin X", Some("mm"), 0.0, ui);
+            show_f32(&mut field.origin_y, "Map Origin Y", Some("mm"), 0.0, ui);
+            show_f32(&mut field.spacing_x, "Map Spacing X", Some("mm"), 1.0, ui);
+            show_f32(&mut field.spacing_y, "Map Spacing Y", Some("mm"), 1.0, ui);
+        }
+
+        show_f32(
+            &mut self.anchor_length,
+            "Anchor Length",
+            Some("mm"),
+            settings_default.anchor_length,
+            ui,
+        );
+        show_usize(
+            &mut self.anchor_loops,
+            "Anchor Loops",
+            None,
+            settings_default.anchor_loops,
+            ui,
+        );
+        show_f32(
+            &mut self.max_bridge_gap,
+            "Max Bridge Gap",
+            Some("mm"),
+            settings_default.max_bridge_gap,
+            ui,
+        );
+        show_f32(
+            &mut self.min_radius,
+            "Min Turn Radius",
+            Some("mm"),
+            settings_default.min_radius,
+            ui,
+        );
 
         show_optional_setting(
             &mut self.continuous,
@@ -19,7 +19,7 @@ impl Tool for DebugTool<'_> {
     fn show(
         &mut self,
         ctx: &egui::Context,
-        (_ui_state, _global_state): &(UiState, GlobalState<RootEvent>),
+        (_ui_state, global_state): &(UiState, GlobalState<RootEvent>),
     ) -> bool {
         let mut pointer_over_tool = false;
 
@@ -42,6 +42,10 @@ impl Tool for DebugTool<'_> {
                         //
                     }
 
+                    if ui.button("Clear slice cache").clicked() {
+                        global_state.slicer.write().clear_cache();
+                    }
+
                     pointer_over_tool = ui.ui_contains_pointer();
                 });
         }
@@ -48,6 +48,7 @@ impl Tool for ExplorerTool<'_> {
                 .show(ctx, |ui| {
                     let objects = global_state.viewer.objects();
                     let masks = global_state.viewer.masks();
+                    let extruder_count = global_state.slicer.read().settings.extruders.len();
 
                     ui.heading("Objects");
                     ui.add_space(5.0);
@@ -76,6 +77,18 @@ impl Tool for ExplorerTool<'_> {
 
                                                         ui.add_space(10.0);
 
+                                                        //Without configured extruders everything
+                                                        //prints with the first one
+                                                        if extruder_count > 1 {
+                                                            show_extruder(
+                                                                ui,
+                                                                &name,
+                                                                extruder_count,
+                                                                global_state,
+                                                            );
+                                                            ui.add_space(5.0);
+                                                        }
+
                                                         if ui.button("Select").clicked() {
                                                             global_state
                                                                 .viewer
@@ -146,3 +159,26 @@ impl Tool for ExplorerTool<'_> {
         pointer_over_tool
     }
 }
+
+fn show_extruder(
+    ui: &mut egui::Ui,
+    name: &str,
+    extruder_count: usize,
+    global_state: &GlobalState<RootEvent>,
+) {
+    let Some(mut extruder) = global_state.viewer.object_extruder(name) else {
+        return;
+    };
+
+    egui::ComboBox::from_id_salt(Id::new(("object extruder", name)))
+        .selected_text(format!("Extruder {}", extruder))
+        .show_ui(ui, |ui| {
+            for index in 0..extruder_count {
+                ui.selectable_value(&mut extruder, index, format!("Extruder {}", index));
+            }
+        });
+
+    if global_state.viewer.object_extruder(name) != Some(extruder) {
+        global_state.viewer.set_object_extruder(name, extruder);
+    }
+}
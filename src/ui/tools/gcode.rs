@@ -4,7 +4,7 @@ use egui_code_editor::{ColorTheme, Syntax};
 use crate::{
     ui::{
         widgets::reader::{EfficientReader, ReadSection},
-        UiState,
+        UiEvent, UiState,
     },
     viewer::GCodeSyntax,
     GlobalState, RootEvent,
@@ -64,7 +64,7 @@ impl Tool for GCodeTool<'_> {
                     .collapsible(false)
                     .frame(frame)
                     .show(ctx, |ui| {
-                        EfficientReader::new(&mut self.state.view)
+                        let output = EfficientReader::new(&mut self.state.view)
                             .id_source("code editor")
                             .with_fontsize(14.0)
                             .with_theme(ColorTheme::GRUVBOX)
@@ -73,6 +73,20 @@ impl Tool for GCodeTool<'_> {
                             // .with_focus(Some(ReadSection::new(0, 20)))
                             .show(ui, &sliced_gcode.gcode, &sliced_gcode.line_breaks);
 
+                        //The reader isn't interactive, so the clicked row stands in for a cursor
+                        let clicked = ui.input(|input| input.pointer.primary_clicked());
+                        if let Some(pos) = output.response.hover_pos().filter(|_| clicked) {
+                            let row = output
+                                .galley
+                                .cursor_from_pos(pos - output.galley_pos)
+                                .rcursor
+                                .row;
+
+                            global_state
+                                .ui_event_writer
+                                .send(UiEvent::GCodeCursorMoved(self.state.view.offset() + row));
+                        }
+
                         pointer_over_tool = ui.ui_contains_pointer();
                     });
             });
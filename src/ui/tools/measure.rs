@@ -0,0 +1,98 @@
+use egui::Color32;
+use glam::vec3;
+
+use crate::{geometry::BoundingBox, ui::UiState, GlobalState, RootEvent};
+
+use super::{create_tool, impl_tool_state_trait, impl_with_state, Tool};
+
+#[derive(Debug, Default)]
+pub struct MeasureToolState {
+    enabled: bool,
+    anchored: bool,
+
+    selected: Option<usize>,
+}
+
+impl_tool_state_trait!(MeasureToolState, "Measure", "measure_tool.svg");
+
+create_tool!(MeasureTool, MeasureToolState);
+impl_with_state!(MeasureTool, MeasureToolState);
+
+impl Tool for MeasureTool<'_> {
+    fn show(
+        &mut self,
+        ctx: &egui::Context,
+        (_ui_state, global_state): &(UiState, GlobalState<RootEvent>),
+    ) -> bool {
+        let mut pointer_over_tool = false;
+
+        if self.state.enabled {
+            let mut frame = egui::Frame::window(&ctx.style());
+            frame.fill = Color32::from_rgba_premultiplied(
+                frame.fill.r(),
+                frame.fill.g(),
+                frame.fill.b(),
+                220,
+            );
+
+            egui::Window::new("Measure")
+                .open(&mut self.state.enabled)
+                .movable(!self.state.anchored)
+                .collapsible(false)
+                .resizable(false)
+                .frame(frame)
+                .show(ctx, |ui| {
+                    ui.label("Right click two points on the toolpath or the build plate");
+                    ui.label("A third click starts a new measurement, Escape clears it");
+
+                    ui.separator();
+
+                    match global_state.viewer.measured_distance() {
+                        Some(measured) => {
+                            ui.label(format!("{}", measured));
+                        }
+                        None => {
+                            ui.label("No measurement");
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Clear").clicked() {
+                            global_state.viewer.clear_measurement();
+                            self.state.selected = None;
+                        }
+
+                        //Every visible trace above the rectangle spanned by the two points
+                        let measured = global_state.viewer.measured_distance();
+                        if ui
+                            .add_enabled(measured.is_some(), egui::Button::new("Select traces"))
+                            .clicked()
+                        {
+                            if let Some(measured) = measured {
+                                let min = measured.start.min(measured.end);
+                                let max = measured.start.max(measured.end);
+                                let region = BoundingBox::new(
+                                    vec3(min.x, f32::MIN, min.z),
+                                    vec3(max.x, f32::MAX, max.z),
+                                );
+
+                                self.state.selected =
+                                    Some(global_state.viewer.select_sliced_region(&region));
+                            }
+                        }
+                    });
+
+                    if let Some(selected) = self.state.selected {
+                        ui.label(format!("{} traces selected", selected));
+                    }
+
+                    pointer_over_tool = ui.ui_contains_pointer();
+                });
+        }
+
+        //Closing the window stops measuring
+        global_state.viewer.enable_measure(self.state.enabled);
+
+        pointer_over_tool
+    }
+}
@@ -9,6 +9,8 @@ use super::UiState;
 mod debug;
 mod explorer;
 mod gcode;
+mod measure;
+mod seam;
 mod visibility;
 
 pub trait Tool {
@@ -24,7 +26,9 @@ pub struct Tools {
     pub camera_tool: CameraToolState,
     pub gcode_tool: gcode::GCodeToolState,
     pub visibility_tool: visibility::VisibilityToolState,
+    pub measure_tool: measure::MeasureToolState,
     pub explorer_tool: explorer::ExplorerToolState,
+    pub seam_tool: seam::SeamToolState,
 
     #[cfg(debug_assertions)]
     profile_tool: ProfilerState,
@@ -46,10 +50,14 @@ impl Tools {
                 pointer_over_tool |=
                     visibility::VisibilityTool::with_state(&mut self.visibility_tool)
                         .show(ctx, shared_state);
+                pointer_over_tool |= measure::MeasureTool::with_state(&mut self.measure_tool)
+                    .show(ctx, shared_state);
             }
             crate::prelude::Mode::Prepare(PrepareMode::Objects) => {
                 pointer_over_tool |= explorer::ExplorerTool::with_state(&mut self.explorer_tool)
                     .show(ctx, shared_state);
+                pointer_over_tool |=
+                    seam::SeamTool::with_state(&mut self.seam_tool).show(ctx, shared_state);
             }
             crate::prelude::Mode::Prepare(PrepareMode::Masks) => {
                 pointer_over_tool |= explorer::ExplorerTool::with_state(&mut self.explorer_tool)
@@ -83,7 +91,11 @@ impl Tools {
 
         match &*mode {
             crate::prelude::Mode::Preview => r#fn(
-                &mut [&mut self.gcode_tool, &mut self.visibility_tool],
+                &mut [
+                    &mut self.gcode_tool,
+                    &mut self.visibility_tool,
+                    &mut self.measure_tool,
+                ],
                 &mut [
                     &mut self.camera_tool,
                     #[cfg(debug_assertions)]
@@ -93,7 +105,7 @@ impl Tools {
                 ],
             ),
             crate::prelude::Mode::Prepare(PrepareMode::Objects) => r#fn(
-                &mut [&mut self.explorer_tool],
+                &mut [&mut self.explorer_tool, &mut self.seam_tool],
                 &mut [
                     &mut self.camera_tool,
                     #[cfg(debug_assertions)]
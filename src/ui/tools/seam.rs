@@ -0,0 +1,132 @@
+use egui::Color32;
+use slicer::SeamHint;
+
+use crate::{ui::UiState, GlobalState, RootEvent};
+
+use super::{create_tool, impl_tool_state_trait, impl_with_state, Tool};
+
+#[derive(Debug)]
+pub struct SeamToolState {
+    enabled: bool,
+    anchored: bool,
+
+    //Radius in mm given to newly painted seams
+    radius: f32,
+}
+
+impl Default for SeamToolState {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            anchored: false,
+            radius: 2.0,
+        }
+    }
+}
+
+impl_tool_state_trait!(SeamToolState, "Seam Painting", "seam_tool.svg");
+
+create_tool!(SeamTool, SeamToolState);
+impl_with_state!(SeamTool, SeamToolState);
+
+impl Tool for SeamTool<'_> {
+    fn show(
+        &mut self,
+        ctx: &egui::Context,
+        (_ui_state, global_state): &(UiState, GlobalState<RootEvent>),
+    ) -> bool {
+        let mut pointer_over_tool = false;
+
+        if self.state.enabled {
+            let mut frame = egui::Frame::window(&ctx.style());
+            frame.fill = Color32::from_rgba_premultiplied(
+                frame.fill.r(),
+                frame.fill.g(),
+                frame.fill.b(),
+                220,
+            );
+
+            egui::Window::new("Seam Painting")
+                .open(&mut self.state.enabled)
+                .movable(!self.state.anchored)
+                .collapsible(false)
+                .resizable(false)
+                .frame(frame)
+                .show(ctx, |ui| {
+                    ui.label("Right click on an object to paint a seam position");
+                    ui.label("Walls passing within the radius start at the painted position");
+
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        ui.label("Radius");
+                        ui.add(
+                            egui::DragValue::new(&mut self.state.radius)
+                                .range(0.5..=50.0)
+                                .suffix(" mm"),
+                        );
+                    });
+
+                    ui.separator();
+
+                    global_state.slicer.write_with_fn(|slicer| {
+                        let hints = &mut slicer.settings.seam_hints;
+
+                        let mut removed = None;
+                        egui::ScrollArea::vertical()
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                for (index, hint) in hints.iter().enumerate() {
+                                    ui.horizontal(|ui| {
+                                        ui.label(format!(
+                                            "X {:.2} mm  Y {:.2} mm  Radius {:.2} mm",
+                                            hint.x, hint.y, hint.radius
+                                        ));
+
+                                        if ui.small_button("Remove").clicked() {
+                                            removed = Some(index);
+                                        }
+                                    });
+                                }
+                            });
+
+                        if let Some(index) = removed {
+                            hints.remove(index);
+                        }
+
+                        if hints.is_empty() {
+                            ui.label("No painted seams");
+                        }
+
+                        if ui
+                            .add_enabled(!hints.is_empty(), egui::Button::new("Clear"))
+                            .clicked()
+                        {
+                            hints.clear();
+                        }
+                    });
+
+                    pointer_over_tool = ui.ui_contains_pointer();
+                });
+        }
+
+        //Closing the window stops painting
+        global_state.viewer.enable_seam_paint(self.state.enabled);
+
+        let painted = global_state.viewer.take_painted_seams();
+        if !painted.is_empty() {
+            global_state.slicer.write_with_fn(|slicer| {
+                slicer
+                    .settings
+                    .seam_hints
+                    .extend(painted.into_iter().map(|point| SeamHint {
+                        x: point.x,
+                        y: point.y,
+                        radius: self.state.radius,
+                    }));
+            });
+        }
+
+        pointer_over_tool
+    }
+}
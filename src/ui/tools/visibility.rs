@@ -3,7 +3,11 @@ use slicer::TraceType;
 use strum::EnumCount;
 use wgpu::Color;
 
-use crate::{ui::UiState, GlobalState, RootEvent};
+use crate::{
+    ui::UiState,
+    viewer::trace::{speed_gradient, ColorMode, FiberHighlight, SpeedRange},
+    GlobalState, RootEvent,
+};
 
 use super::{create_tool, impl_tool_state_trait, impl_with_state, Tool};
 
@@ -15,6 +19,8 @@ pub struct VisibilityToolState {
     trace_types: [bool; TraceType::COUNT],
     travel: bool,
     fiber: bool,
+    fiber_highlight: FiberHighlight,
+    color_mode: ColorMode,
 }
 
 impl Default for VisibilityToolState {
@@ -26,6 +32,8 @@ impl Default for VisibilityToolState {
             trace_types: [true; TraceType::COUNT],
             travel: false,
             fiber: true,
+            fiber_highlight: FiberHighlight::default(),
+            color_mode: ColorMode::default(),
         }
     }
 }
@@ -100,6 +108,27 @@ impl Tool for VisibilityTool<'_> {
                             global_state.viewer.enable_fiber(self.state.fiber);
                         }
 
+                        if self.state.fiber
+                            && Self::show_fiber_highlight(&mut self.state.fiber_highlight, ui).inner
+                        {
+                            global_state.viewer.set_fiber_highlight(
+                                self.state.fiber_highlight.color,
+                                self.state.fiber_highlight.scale,
+                            );
+                        }
+
+                        ui.separator();
+
+                        if Self::show_color_mode(&mut self.state.color_mode, ui).inner {
+                            global_state.viewer.set_color_mode(self.state.color_mode);
+                        }
+
+                        if self.state.color_mode == ColorMode::Speed {
+                            if let Some(range) = global_state.viewer.sliced_speed_range() {
+                                Self::show_speed_legend(&range, ui);
+                            }
+                        }
+
                         ui.separator();
 
                         pointer_over_tool = ui.ui_contains_pointer();
@@ -125,6 +154,35 @@ impl<'a> VisibilityTool<'a> {
         })
     }
 
+    fn show_fiber_highlight(
+        highlight: &mut FiberHighlight,
+        ui: &mut egui::Ui,
+    ) -> InnerResponse<bool> {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Fiber Highlight")
+                    .font(FontId::monospace(15.0))
+                    .strong()
+                    .color(Color32::BLACK),
+            );
+
+            let mut color = highlight.color.to_array();
+            let color_changed = ui.color_edit_button_rgba_unmultiplied(&mut color).changed();
+            highlight.color = color.into();
+
+            let scale_changed = ui
+                .add(
+                    egui::DragValue::new(&mut highlight.scale)
+                        .range(0.5..=4.0)
+                        .speed(0.05)
+                        .suffix("x"),
+                )
+                .changed();
+
+            color_changed || scale_changed
+        })
+    }
+
     fn show_travel_checkbox(travel: &mut bool, ui: &mut egui::Ui) -> InnerResponse<bool> {
         ui.horizontal(|ui| {
             ui.checkbox(
@@ -200,6 +258,64 @@ impl<'a> VisibilityTool<'a> {
         })
     }
 
+    fn show_color_mode(color_mode: &mut ColorMode, ui: &mut egui::Ui) -> InnerResponse<bool> {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Color by")
+                    .font(FontId::monospace(15.0))
+                    .strong()
+                    .color(Color32::BLACK),
+            );
+
+            let by_type = ui.selectable_value(color_mode, ColorMode::TraceType, "Type");
+            let by_speed = ui.selectable_value(color_mode, ColorMode::Speed, "Speed");
+
+            by_type.changed() || by_speed.changed()
+        })
+    }
+
+    ///A bar with the speed gradient from the slowest to the fastest extrusion of the toolpath
+    fn show_speed_legend(range: &SpeedRange, ui: &mut egui::Ui) {
+        const STEPS: usize = 32;
+
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), 14.0), egui::Sense::hover());
+
+        let step_width = rect.width() / STEPS as f32;
+        for step in 0..STEPS {
+            let color = speed_gradient((step as f32 + 0.5) / STEPS as f32);
+
+            ui.painter().rect_filled(
+                egui::Rect::from_min_size(
+                    rect.min + egui::vec2(step as f32 * step_width, 0.0),
+                    egui::vec2(step_width + 0.5, rect.height()),
+                ),
+                0.0,
+                Color32::from_rgb(
+                    (color.x * 255.0) as u8,
+                    (color.y * 255.0) as u8,
+                    (color.z * 255.0) as u8,
+                ),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new(format!("{:.0} mm/s", range.min))
+                    .font(FontId::monospace(12.0))
+                    .color(Color32::BLACK),
+            );
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(
+                    RichText::new(format!("{:.0} mm/s", range.max))
+                        .font(FontId::monospace(12.0))
+                        .color(Color32::BLACK),
+                );
+            });
+        });
+    }
+
     fn show_transparent_vision_checkbox(
         transparent_vision: &mut bool,
         ui: &mut egui::Ui,
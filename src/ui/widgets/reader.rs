@@ -180,6 +180,10 @@ impl ReadSection {
         ReadSection { offset, size }
     }
 
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     #[allow(dead_code)]
     pub fn with_offset(self, offset: usize) -> Self {
         ReadSection { offset, ..self }
@@ -0,0 +1,156 @@
+use glam::{vec3, vec4, Vec3, Vec4};
+
+use crate::render::{model::Model, Renderable, Vertex};
+
+const LINE_COLOR: Vec4 = vec4(1.0, 0.8, 0.0, 1.0);
+
+//Half the length of the arms of the cross marking a picked point
+const MARKER_SIZE: f32 = 1.0;
+
+///Distance between two points picked in the viewer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasuredDistance {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl MeasuredDistance {
+    ///Difference from the start to the end in printer axes. The viewer's y axis is the height,
+    ///its z axis the printer's y axis
+    pub fn delta(&self) -> Vec3 {
+        let delta = self.end - self.start;
+        vec3(delta.x, delta.z, delta.y)
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.start.distance(self.end)
+    }
+}
+
+impl std::fmt::Display for MeasuredDistance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let delta = self.delta();
+
+        writeln!(f, "Distance {:.2} mm", self.distance())?;
+        write!(
+            f,
+            "dX {:.2} mm  dY {:.2} mm  dZ {:.2} mm",
+            delta.x, delta.y, delta.z
+        )
+    }
+}
+
+///Measures the distance between two points picked one after the other. Picking a third point
+///starts a new measurement.
+#[derive(Debug)]
+pub struct Measurement {
+    points: Vec<Vec3>,
+
+    lines: Model<Vertex>,
+}
+
+impl Measurement {
+    pub fn instance() -> Self {
+        Self {
+            points: Vec::new(),
+            lines: Model::create(),
+        }
+    }
+
+    pub fn pick(&mut self, point: Vec3) {
+        if self.points.len() >= 2 {
+            self.points.clear();
+        }
+
+        self.points.push(point);
+        self.rebuild();
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.rebuild();
+    }
+
+    ///Whether the first point is picked and the measurement waits for the second one
+    pub fn is_started(&self) -> bool {
+        self.points.len() == 1
+    }
+
+    pub fn result(&self) -> Option<MeasuredDistance> {
+        match self.points[..] {
+            [start, end] => Some(MeasuredDistance { start, end }),
+            _ => None,
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let vertices = measurement_vertices(&self.points);
+
+        self.lines.set_enabled(!vertices.is_empty());
+        self.lines.awaken(&vertices);
+    }
+
+    pub fn render_lines<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.lines.render(render_pass);
+    }
+}
+
+///Line list vertices of a cross at every picked point and the line between them
+fn measurement_vertices(points: &[Vec3]) -> Vec<Vertex> {
+    let mut segments: Vec<(Vec3, Vec3)> = points
+        .iter()
+        .flat_map(|point| {
+            [Vec3::X, Vec3::Y, Vec3::Z]
+                .map(|axis| (*point - axis * MARKER_SIZE, *point + axis * MARKER_SIZE))
+        })
+        .collect();
+
+    if let [start, end] = points {
+        segments.push((*start, *end));
+    }
+
+    segments
+        .into_iter()
+        .flat_map(|(start, end)| [start, end])
+        .map(|point| Vertex {
+            position: point.to_array(),
+            normal: [0.0, 0.0, 0.0],
+            color: LINE_COLOR.to_array(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_is_reported_in_printer_axes() {
+        let measured = MeasuredDistance {
+            start: vec3(1.0, 0.2, 2.0),
+            end: vec3(4.0, 0.2, 6.0),
+        };
+
+        assert_eq!(measured.delta(), vec3(3.0, 4.0, 0.0));
+        assert!((measured.distance() - 5.0).abs() < f32::EPSILON);
+
+        let up = MeasuredDistance {
+            start: Vec3::ZERO,
+            end: vec3(0.0, 2.0, 0.0),
+        };
+        assert_eq!(up.delta(), vec3(0.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn picked_points_are_marked_and_joined() {
+        assert!(measurement_vertices(&[]).is_empty());
+
+        //A cross of three lines at the first point
+        assert_eq!(measurement_vertices(&[Vec3::ZERO]).len(), 6);
+
+        let vertices = measurement_vertices(&[Vec3::ZERO, vec3(10.0, 0.0, 0.0)]);
+        assert_eq!(vertices.len(), 14);
+        assert_eq!(vertices[12].position, [0.0, 0.0, 0.0]);
+        assert_eq!(vertices[13].position, [10.0, 0.0, 0.0]);
+    }
+}
@@ -6,12 +6,15 @@ use std::{
 
 use egui::ahash::HashMap;
 use egui_code_editor::Syntax;
-use glam::Mat4;
+use geo::MultiPolygon;
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use log::{info, warn};
 use parking_lot::RwLock;
 use server::CADObject;
 use shared::{object::ObjectMesh, process::Process};
-use slicer::{Mask, MoveType, Settings, SliceResult, SlicedGCode, TraceType};
+use slicer::{
+    gcode::GCodeFormat, Mask, MoveId, MoveType, Settings, SliceResult, SlicedGCode, TraceType,
+};
 use trace::bit_representation;
 use winit::{
     event::{KeyEvent, MouseButton},
@@ -20,8 +23,10 @@ use winit::{
 
 use crate::{
     geometry::BoundingBox,
-    input::{interact::InteractiveModel, MouseClickEvent, MouseMotionEvent},
-    prelude::{Mode, PrepareMode, WgpuContext},
+    input::{
+        hitbox::HitboxNode, interact::InteractiveModel, MouseClickEvent, MouseMotionEvent, Ray,
+    },
+    prelude::{Mode, OriginMode, PrepareMode, WgpuContext},
     render::{RenderDescriptor, Vertex},
     ui::screen::ViewerTooltip,
     GlobalState, RootEvent,
@@ -30,6 +35,7 @@ use crate::{
 mod camera;
 pub use camera::*;
 
+pub mod measure;
 pub mod select;
 pub mod server;
 pub mod trace;
@@ -77,12 +83,25 @@ pub struct Viewer {
     mask_selector: RwLock<select::Selector>,
     trace_selector: RwLock<select::Selector>,
 
+    measurement: RwLock<measure::Measurement>,
+    measuring: AtomicBool,
+
+    seam_painting: AtomicBool,
+    painted_seams: RwLock<Vec<Vec2>>,
+
     tooltip: RwLock<Option<ViewerTooltip>>,
     mode: RwLock<Option<Mode>>,
 
     transparent_vision: AtomicBool,
+    origin_mode: RwLock<OriginMode>,
+
+    //The object transforms and settings of the last first layer coverage and how often they
+    //changed
+    coverage_inputs: RwLock<(u64, Option<CoverageInputs>)>,
 }
 
+type CoverageInputs = (Vec<(String, [f32; 16])>, Settings);
+
 impl Viewer {
     pub fn instance(context: &WgpuContext) -> Self {
         Self {
@@ -95,9 +114,17 @@ impl Viewer {
             mask_selector: RwLock::new(select::Selector::instance()),
             trace_selector: RwLock::new(select::Selector::instance()),
 
+            measurement: RwLock::new(measure::Measurement::instance()),
+            measuring: AtomicBool::new(false),
+
+            seam_painting: AtomicBool::new(false),
+            painted_seams: RwLock::new(Vec::new()),
+
             tooltip: RwLock::new(None),
             mode: RwLock::new(None),
             transparent_vision: AtomicBool::new(false),
+            origin_mode: RwLock::new(OriginMode::default()),
+            coverage_inputs: RwLock::new((0, None)),
         }
     }
 
@@ -105,6 +132,7 @@ impl Viewer {
         *self.mode.write() = Some(mode);
 
         self.update_tooltip(None);
+        self.measurement.write().clear();
 
         match mode {
             Mode::Prepare(PrepareMode::Objects) => {
@@ -126,11 +154,55 @@ impl Viewer {
         self.env_server.write().update_printer_dimension(x, y, z);
     }
 
+    pub fn set_grid(&self, spacing: Option<f32>) {
+        self.env_server.write().set_grid(spacing);
+    }
+
+    ///Shows coordinates from the bed center or its front left corner. The sliced coordinates
+    ///don't change
+    pub fn set_origin_display(&self, mode: OriginMode) {
+        *self.origin_mode.write() = mode;
+        self.env_server.write().set_origin_display(mode);
+    }
+
     pub fn update(&self, global_state: &GlobalState<RootEvent>) {
         // self.env_server.write().update(global_state);
         self.object_selector.write().update();
         self.mask_selector.write().update();
 
+        if let Some(Mode::Prepare(_)) = *self.mode.read() {
+            let slicer_read = global_state.slicer.read();
+            let settings = &slicer_read.settings;
+
+            //Only reslice the first layer when an object moved or the settings changed
+            let transforms: Vec<(String, [f32; 16])> = self
+                .objects()
+                .into_iter()
+                .map(|(name, object)| (name, object.transformation().to_cols_array()))
+                .collect();
+
+            let revision = {
+                let mut inputs = self.coverage_inputs.write();
+                let (revision, last) = &mut *inputs;
+                if !last
+                    .as_ref()
+                    .is_some_and(|(last_transforms, last_settings)| {
+                        *last_transforms == transforms && last_settings == settings
+                    })
+                {
+                    *revision += 1;
+                    *last = Some((transforms, settings.clone()));
+                }
+                *revision
+            };
+
+            self.env_server.write().update_coverage(
+                revision,
+                || self.prepare_objects(settings),
+                settings,
+            );
+        }
+
         self.sliced_object_server
             .write()
             .update(global_state.clone())
@@ -148,6 +220,11 @@ impl Viewer {
     pub fn volume_box(&self) -> BoundingBox {
         *self.env_server.read().volume_box()
     }
+
+    ///The area of the bed touched by the first layer of the objects, brim and skirt
+    pub fn first_layer_coverage(&self) -> MultiPolygon<f32> {
+        self.env_server.read().first_layer_coverage().clone()
+    }
 }
 
 impl Viewer {
@@ -168,6 +245,19 @@ impl Viewer {
         *self.tooltip.write() = tooltip;
     }
 
+    ///Tooltip of a hovered model with the position of its center in the displayed coordinates
+    fn model_tooltip(&self, server_type: &str, model: &CADObject) -> ViewerTooltip {
+        let (min, max) = model.aabb();
+        let center = model.transformation().transform_point3((min + max) / 2.0);
+
+        //Objects are placed relative to the bed center, like when they are sliced
+        let bed = self.volume_box().max;
+        let printer = glam::vec2(center.x + bed.x / 2.0, center.z + bed.z / 2.0);
+
+        ViewerTooltip::new(server_type.to_string(), format!("{}", model))
+            .with_position(self.origin_mode.read().display(printer, bed.x, bed.z))
+    }
+
     pub fn read_tooltip_with_fn(&self, r#fn: impl FnOnce(&ViewerTooltip)) {
         if let Some(tooltip) = &*self.tooltip.read() {
             r#fn(tooltip);
@@ -194,6 +284,14 @@ impl Viewer {
         self.mask_server.read().models()
     }
 
+    pub fn object_extruder(&self, name: &str) -> Option<usize> {
+        self.object_server.read().extruder(name)
+    }
+
+    pub fn set_object_extruder(&self, name: &str, extruder: usize) {
+        self.object_server.write().set_extruder(name, extruder);
+    }
+
     pub fn sliced_count_map(&self) -> Option<HashMap<TraceType, usize>> {
         self.sliced_object_server
             .read()
@@ -208,6 +306,30 @@ impl Viewer {
         gcode.map(read_fn)
     }
 
+    /// Selects the trace written at a line of the gcode, so the 3D view shows where the line
+    /// is printed. A trace that is already selected stays selected.
+    pub fn select_trace_at_line(&self, line: usize) {
+        let server_read = self.sliced_object_server.read();
+
+        let trace = server_read
+            .get_gcode()
+            .and_then(|gcode| gcode.navigator.trace_at_line(line))
+            .and_then(|id| server_read.get_sliced()?.model.find_trace(&id));
+
+        if let Some(trace) = trace {
+            let model = trace as Arc<dyn InteractiveModel>;
+            let mut trace_selector = self.trace_selector.write();
+
+            if !trace_selector
+                .selected()
+                .iter()
+                .any(|selected| Arc::ptr_eq(selected, &model))
+            {
+                trace_selector.select(model);
+            }
+        }
+    }
+
     pub fn sliced_max_layer(&self) -> Option<u32> {
         self.sliced_object_server
             .read()
@@ -223,6 +345,23 @@ impl Viewer {
         self.sliced_object_server.write().enable_fiber(opaque);
     }
 
+    pub fn set_fiber_highlight(&self, color: Vec4, scale: f32) {
+        self.sliced_object_server
+            .write()
+            .set_fiber_highlight(trace::FiberHighlight { color, scale });
+    }
+
+    pub fn set_color_mode(&self, color_mode: trace::ColorMode) {
+        self.sliced_object_server.write().set_color_mode(color_mode);
+    }
+
+    pub fn sliced_speed_range(&self) -> Option<trace::SpeedRange> {
+        self.sliced_object_server
+            .read()
+            .get_sliced()
+            .and_then(|toolpath| toolpath.speed_range)
+    }
+
     pub fn update_gpu_min_layer(&self, layer: u32) {
         self.sliced_object_server.write().update_min_layer(layer);
     }
@@ -234,12 +373,21 @@ impl Viewer {
     pub fn is_move_active(&self, move_type: &MoveType, layer: u32) -> bool {
         let server_read = self.sliced_object_server.read();
 
-        let (min, max) = (*server_read.min_layer(), *server_read.max_layer());
+        self.is_move_active_in(&server_read, move_type, layer)
+    }
+
+    fn is_move_active_in(
+        &self,
+        server: &server::SlicedObjectServer,
+        move_type: &MoveType,
+        layer: u32,
+    ) -> bool {
+        let (min, max) = (*server.min_layer(), *server.max_layer());
 
-        let visibility = *server_read.visibility();
+        let visibility = *server.visibility();
 
         match move_type {
-            MoveType::Travel => server_read.is_travel_visible(),
+            MoveType::Travel => server.is_travel_visible(),
             MoveType::WithoutFiber(trace_type) => {
                 if (bit_representation(trace_type) & visibility) > 0
                     && !self.is_transparent_vision()
@@ -250,9 +398,7 @@ impl Viewer {
                 }
             }
             MoveType::WithFiber(trace_type) => {
-                if (bit_representation(trace_type) & visibility) > 0
-                    && server_read.is_fiber_visible()
-                {
+                if (bit_representation(trace_type) & visibility) > 0 && server.is_fiber_visible() {
                     layer >= min && layer <= max
                 } else {
                     false
@@ -261,6 +407,40 @@ impl Viewer {
         }
     }
 
+    /// Returns the ids of all currently visible traces inside the given region.
+    pub fn query_sliced_region(&self, region: &BoundingBox) -> Vec<MoveId> {
+        let server_read = self.sliced_object_server.read();
+
+        server_read
+            .get_sliced()
+            .map(|sliced| {
+                sliced.query_region(region, |move_type, layer| {
+                    self.is_move_active_in(&server_read, move_type, layer)
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    /// Selects all currently visible traces inside the given region in place of the current
+    /// selection. Returns the number of selected traces.
+    pub fn select_sliced_region(&self, region: &BoundingBox) -> usize {
+        let ids = self.query_sliced_region(region);
+
+        let server_read = self.sliced_object_server.read();
+        let traces = server_read
+            .get_sliced()
+            .map(|sliced| sliced.model.find_traces(&ids))
+            .unwrap_or_default();
+
+        let mut trace_selector = self.trace_selector.write();
+        trace_selector.clear();
+        for trace in traces {
+            trace_selector.select_multiple(trace as Arc<dyn InteractiveModel>);
+        }
+
+        trace_selector.selected().len()
+    }
+
     pub fn enable_transparent_vision(&self, b: bool) {
         self.transparent_vision
             .store(b, std::sync::atomic::Ordering::Relaxed);
@@ -283,12 +463,106 @@ impl Viewer {
             .update_visibility(visibility);
     }
 
+    ///While enabled, right clicks in the preview pick the points of a measurement instead of
+    ///selecting traces. Disabling it clears the measurement.
+    pub fn enable_measure(&self, b: bool) {
+        let was_enabled = self.measuring.swap(b, std::sync::atomic::Ordering::Relaxed);
+
+        if was_enabled && !b {
+            self.clear_measurement();
+        }
+    }
+
+    pub fn is_measuring(&self) -> bool {
+        self.measuring.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn measured_distance(&self) -> Option<measure::MeasuredDistance> {
+        self.measurement.read().result()
+    }
+
+    pub fn clear_measurement(&self) {
+        self.measurement.write().clear();
+        self.update_tooltip(None);
+    }
+
+    ///The point of the toolpath hit by the ray, or where it hits the build plate
+    fn measured_point(&self, ray: &Ray) -> Option<Vec3> {
+        let trace = self.sliced_object_server.read().check_hit(ray, 2);
+
+        if let Some(distance) = trace.and_then(|trace| HitboxNode::check_hit(&*trace, ray)) {
+            return Some(ray.origin + ray.direction * distance);
+        }
+
+        //The toolpath starts at a height of 0, the plate is drawn slightly below it
+        if ray.direction.y.abs() <= f32::EPSILON || ray.origin.y * ray.direction.y > 0.0 {
+            return None;
+        }
+
+        Some(ray.intersection_plane(Vec3::Y, Vec3::ZERO))
+    }
+
+    fn pick_measured_point(&self, ray: &Ray) {
+        let Some(point) = self.measured_point(ray) else {
+            return;
+        };
+
+        let mut measurement = self.measurement.write();
+        measurement.pick(point);
+
+        let tooltip = match measurement.result() {
+            Some(measured) => ViewerTooltip::new("Measure".to_string(), format!("{}", measured)),
+            None => ViewerTooltip::new("Measure".to_string(), "Pick the second point".to_string()),
+        };
+        drop(measurement);
+
+        self.update_tooltip(Some(tooltip));
+    }
+
+    ///While enabled, right clicks on an object in the prepare view paint a seam position instead
+    ///of selecting the object
+    pub fn enable_seam_paint(&self, b: bool) {
+        let was_enabled = self
+            .seam_painting
+            .swap(b, std::sync::atomic::Ordering::Relaxed);
+
+        if was_enabled && !b {
+            self.painted_seams.write().clear();
+        }
+    }
+
+    pub fn is_painting_seams(&self) -> bool {
+        self.seam_painting
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    ///Bed positions of the seams painted since the last call
+    pub fn take_painted_seams(&self) -> Vec<Vec2> {
+        std::mem::take(&mut *self.painted_seams.write())
+    }
+
+    fn paint_seam(&self, ray: &Ray) {
+        let Some(face) = self.object_server.read().check_hit(ray, 1, false) else {
+            return;
+        };
+        let Some(distance) = HitboxNode::check_hit(&*face, ray) else {
+            return;
+        };
+        let point = ray.origin + ray.direction * distance;
+
+        //Objects are placed relative to the bed center, like when they are sliced
+        let bed = self.volume_box().max;
+        self.painted_seams
+            .write()
+            .push(Vec2::new(point.x + bed.x / 2.0, point.z + bed.z / 2.0));
+    }
+
     pub fn already_sliced(&self) -> bool {
         self.sliced_object_server.read().get_sliced().is_some()
     }
 
-    pub fn export_gcode(&self) {
-        self.sliced_object_server.write().export();
+    pub fn export_gcode(&self, format: GCodeFormat) {
+        self.sliced_object_server.write().export(format);
     }
 }
 
@@ -353,33 +627,21 @@ impl Viewer {
         match *self.mode.read() {
             Some(Mode::Prepare(PrepareMode::Objects)) => {
                 if let Some(model) = self.object_server.read().check_hit(&event.ray, 0, false) {
-                    self.update_tooltip(Some(ViewerTooltip::new(
-                        "Obj".to_string(),
-                        format!("{}", model),
-                    )));
+                    self.update_tooltip(Some(self.model_tooltip("Obj", &model)));
                 } else if let Some(model) = self.mask_server.read().check_hit(&event.ray, 0, false)
                 {
-                    self.update_tooltip(Some(ViewerTooltip::new(
-                        "Mask".to_string(),
-                        format!("{}", model),
-                    )));
+                    self.update_tooltip(Some(self.model_tooltip("Mask", &model)));
                 } else {
                     self.update_tooltip(None);
                 }
             }
             Some(Mode::Prepare(PrepareMode::Masks)) => {
                 if let Some(model) = self.mask_server.read().check_hit(&event.ray, 0, false) {
-                    self.update_tooltip(Some(ViewerTooltip::new(
-                        "Mask".to_string(),
-                        format!("{}", model),
-                    )));
+                    self.update_tooltip(Some(self.model_tooltip("Mask", &model)));
                 } else if let Some(model) =
                     self.object_server.read().check_hit(&event.ray, 0, false)
                 {
-                    self.update_tooltip(Some(ViewerTooltip::new(
-                        "Obj".to_string(),
-                        format!("{}", model),
-                    )));
+                    self.update_tooltip(Some(self.model_tooltip("Obj", &model)));
                 } else {
                     self.update_tooltip(None);
                 }
@@ -392,6 +654,9 @@ impl Viewer {
         if event.state.is_pressed() {
             if let MouseButton::Right = event.button {
                 match *self.mode.read() {
+                    Some(Mode::Prepare(PrepareMode::Objects)) if self.is_painting_seams() => {
+                        self.paint_seam(&event.ray);
+                    }
                     Some(Mode::Prepare(PrepareMode::Objects)) => {
                         if let Some(model) =
                             self.object_server.read().check_hit(&event.ray, 0, false)
@@ -409,6 +674,9 @@ impl Viewer {
                             self.mask_selector.write().select(interact_model);
                         }
                     }
+                    Some(Mode::Preview) if self.is_measuring() => {
+                        self.pick_measured_point(&event.ray);
+                    }
                     Some(Mode::Preview) => {
                         if let Some(model) =
                             self.sliced_object_server.read().check_hit(&event.ray, 2)
@@ -432,7 +700,6 @@ impl Viewer {
     pub fn keyboard_input(&self, event: KeyEvent) {
         if event.state.is_pressed() {
             if let PhysicalKey::Code(key) = event.physical_key {
-                #[allow(clippy::single_match)]
                 match key {
                     KeyCode::Delete => match *self.mode.read() {
                         Some(Mode::Prepare(PrepareMode::Objects)) => {
@@ -443,6 +710,11 @@ impl Viewer {
                         }
                         _ => (),
                     },
+                    KeyCode::Escape => {
+                        if let Some(Mode::Preview) = *self.mode.read() {
+                            self.clear_measurement();
+                        }
+                    }
                     _ => (),
                 }
             } else {
@@ -482,6 +754,7 @@ impl Viewer {
 
                     render_pass.set_pipeline(&pipelines.no_cull);
                     env_server_read.render(&mut render_pass);
+                    env_server_read.render_coverage(&mut render_pass);
 
                     render_pass.set_pipeline(&pipelines.line);
                     env_server_read.render_line(&mut render_pass);
@@ -510,6 +783,7 @@ impl Viewer {
         let trace_selector_read = self.trace_selector.read();
         let object_selector_read = self.object_selector.read();
         let mask_selector_read = self.mask_selector.read();
+        let measurement_read = self.measurement.read();
 
         if let Some((pipelines, mut render_pass)) = render_descriptor.pass() {
             match mode {
@@ -522,6 +796,9 @@ impl Viewer {
                         render_pass.set_pipeline(&pipelines.no_cull);
                         trace_selector_read.render(&mut render_pass);
                     }
+
+                    render_pass.set_pipeline(&pipelines.line);
+                    measurement_read.render_lines(&mut render_pass);
                 }
                 Mode::Prepare(PrepareMode::Objects) => {
                     render_pass.set_pipeline(&pipelines.line);
@@ -166,7 +166,8 @@ impl MaskServer {
         self.queue.push((rx, handle));
     }
 
-    fn load(name: String, mesh: ObjectMesh) -> Result<LoadResult, Error> {
+    fn load(name: String, mut mesh: ObjectMesh) -> Result<LoadResult, Error> {
+        let warning = mesh.repair();
         let (min, max) = mesh.min_max();
 
         let global_state = GLOBAL_STATE.read();
@@ -287,6 +288,7 @@ Clustering models"
             model: root,
             mesh,
             name,
+            warning,
         })
     }
 
@@ -354,6 +356,12 @@ Clustering models"
                     }
                 };
 
+                if let Some(warning) = &model.warning {
+                    global_state
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowWarning(format!("{}", warning)));
+                }
+
                 self.insert(model)?;
 
                 global_state
@@ -40,6 +40,9 @@ pub struct LoadResult {
 
     process: Arc<Process>,
     name: String,
+
+    ///Set when the mesh had to be repaired while loading
+    warning: Option<LoadError>,
 }
 
 type CADObjectResult = Result<LoadResult, Error>;
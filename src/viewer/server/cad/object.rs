@@ -3,7 +3,7 @@ use std::{collections::HashMap, path::Path, sync::Arc};
 
 use glam::{vec3, Mat4, Quat, Vec3, Vec3Swizzles};
 use shared::{
-    loader::{BytesLoader, FileLoader},
+    loader::{BytesLoader, FileLoader, LoadError, ThreeMFLoader},
     object::ObjectMesh,
 };
 
@@ -38,8 +38,9 @@ pub struct ObjectHandle {
 
 #[derive(Debug)]
 pub struct ObjectServer {
+    //A file can hold several objects
     queue: Vec<(
-        tokio::sync::oneshot::Receiver<CADObjectResult>,
+        tokio::sync::oneshot::Receiver<Vec<CADObjectResult>>,
         JoinHandle<()>,
     )>,
 
@@ -123,23 +124,40 @@ impl ObjectServer {
             None => path.as_ref().to_string_lossy().to_string(),
         };
 
+        let is_package = path
+            .as_ref()
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("3mf"));
+
         let path = path.as_ref().to_str().unwrap_or("").to_string();
 
         let (tx, rx) = tokio::sync::oneshot::channel();
 
         let handle = tokio::spawn(async move {
+            if is_package {
+                let results = match std::fs::read(&path) {
+                    Ok(bytes) => Self::load_package(file_name, &bytes),
+                    Err(_) => vec![Err(Error::LoadError(LoadError::FileNotFound))],
+                };
+
+                tx.send(results).unwrap();
+
+                return;
+            }
+
             let mesh = match (shared::loader::STLLoader {}).load(&path) {
                 Ok(model) => model,
                 Err(e) => {
-                    tx.send(Err(Error::LoadError(e))).unwrap();
+                    tx.send(vec![Err(Error::LoadError(e))]).unwrap();
 
                     return;
                 }
             };
 
-            let result = Self::load(file_name, mesh);
+            let bounds = mesh.min_max();
+            let result = Self::load(file_name, mesh, bounds);
 
-            tx.send(result).unwrap()
+            tx.send(vec![result]).unwrap()
         });
 
         self.queue.push((rx, handle));
@@ -151,24 +169,58 @@ impl ObjectServer {
         let bytes = bytes.to_vec();
 
         let handle = tokio::spawn(async move {
+            if ThreeMFLoader::is_package(&bytes) {
+                tx.send(Self::load_package(name, &bytes)).unwrap();
+
+                return;
+            }
+
             let mesh = match (shared::loader::STLLoader {}).load_from_bytes(&bytes) {
                 Ok(model) => model,
                 Err(e) => {
-                    tx.send(Err(Error::LoadError(e))).unwrap();
+                    tx.send(vec![Err(Error::LoadError(e))]).unwrap();
 
                     return;
                 }
             };
 
-            let result = Self::load(name, mesh);
+            let bounds = mesh.min_max();
+            let result = Self::load(name, mesh, bounds);
 
-            tx.send(result).unwrap()
+            tx.send(vec![result]).unwrap()
         });
 
         self.queue.push((rx, handle));
     }
 
-    fn load(name: String, mesh: ObjectMesh) -> CADObjectResult {
+    ///Every build item of a 3MF package becomes its own object, named like the object in the
+    ///package. The items keep their places relative to each other, the build as a whole is
+    ///centered on the bed.
+    fn load_package(name: String, bytes: &[u8]) -> Vec<CADObjectResult> {
+        let items = match ThreeMFLoader.load_items(bytes) {
+            Ok(items) if !items.is_empty() => items,
+            Ok(_) => return vec![Err(Error::LoadError(LoadError::BrokenFile))],
+            Err(e) => return vec![Err(Error::LoadError(e))],
+        };
+
+        let bounds = items.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), item| {
+                let (item_min, item_max) = item.mesh.min_max();
+                (min.min(item_min), max.max(item_max))
+            },
+        );
+
+        items
+            .into_iter()
+            .map(|item| Self::load(item.name.unwrap_or_else(|| name.clone()), item.mesh, bounds))
+            .collect()
+    }
+
+    ///Load the mesh as an object, placed so that the center of `bounds` is in the middle of the
+    ///bed and its bottom rests on it
+    fn load(name: String, mut mesh: ObjectMesh, bounds: (Vec3, Vec3)) -> CADObjectResult {
+        let warning = mesh.repair();
         let (min, max) = mesh.min_max();
 
         let global_state = GLOBAL_STATE.read();
@@ -280,7 +332,8 @@ Clustering models"
 
         root.awaken(&triangle_vertices);
 
-        let center = (min + max) / 2.0;
+        let (bounds_min, bounds_max) = bounds;
+        let center = (bounds_min + bounds_max) / 2.0;
 
         {
             let global_state_read = GLOBAL_STATE.read();
@@ -290,7 +343,7 @@ Clustering models"
 
             root.transform(Mat4::from_translation(vec3(
                 (x / 2.0) - center.x,
-                -min.xzy().y,
+                -bounds_min.xzy().y,
                 (y / 2.0) - center.xzy().z,
             )));
         }
@@ -302,6 +355,7 @@ Clustering models"
             model: root,
             mesh,
             name: name.to_string(),
+            warning,
         })
     }
 
@@ -350,7 +404,7 @@ Clustering models"
 
             self.queue.retain_mut(|(rx, ..)| match rx.try_recv() {
                 Ok(result) => {
-                    results.push(result);
+                    results.extend(result);
 
                     false
                 }
@@ -372,6 +426,12 @@ Clustering models"
 
                 model.process.finish();
 
+                if let Some(warning) = &model.warning {
+                    global_state
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowWarning(format!("{}", warning)));
+                }
+
                 self.insert(model)?;
 
                 global_state
@@ -415,6 +475,19 @@ Clustering models"
             .collect()
     }
 
+    ///The extruder printing the object loaded under `name`
+    pub fn extruder(&self, name: &str) -> Option<usize> {
+        self.models
+            .get(name)
+            .map(|handle| handle.mesh.extruder_index())
+    }
+
+    pub fn set_extruder(&mut self, name: &str, extruder: usize) {
+        if let Some(handle) = self.models.get_mut(name) {
+            handle.mesh.set_extruder_index(extruder);
+        }
+    }
+
     pub fn set_transparency(&mut self, transparency: f32) {
         let queue_read = QUEUE.read();
         let queue = queue_read.as_ref().unwrap();
@@ -0,0 +1,110 @@
+use geo::{MultiPolygon, TriangulateEarcut};
+use glam::{vec3, vec4, Vec3};
+use log::warn;
+use shared::object::ObjectMesh;
+use slicer::Settings;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+
+use crate::{
+    geometry::mesh::vec3s_into_vertices,
+    render::{model::Model, Renderable, Vertex},
+};
+
+//Between the build plate and the bottom of the objects so neither z-fights the overlay
+const COVERAGE_HEIGHT: f32 = -0.2;
+
+#[derive(Debug)]
+pub struct Coverage {
+    polygons: MultiPolygon<f32>,
+
+    revision: Option<u64>,
+    pending: Option<(u64, oneshot::Receiver<MultiPolygon<f32>>)>,
+
+    overlay: Model<Vertex>,
+}
+
+impl Coverage {
+    pub fn instance() -> Self {
+        Self {
+            polygons: MultiPolygon(vec![]),
+            revision: None,
+            pending: None,
+            overlay: Model::create(),
+        }
+    }
+
+    pub fn polygons(&self) -> &MultiPolygon<f32> {
+        &self.polygons
+    }
+
+    ///Recompute the coverage in the background if the revision of the objects and settings
+    ///changed since the last computation.
+    pub fn update(
+        &mut self,
+        revision: u64,
+        objects: impl FnOnce() -> Vec<ObjectMesh>,
+        settings: &Settings,
+    ) {
+        if let Some((pending_revision, rx)) = &mut self.pending {
+            match rx.try_recv() {
+                Ok(polygons) => {
+                    self.revision = Some(*pending_revision);
+                    self.awaken(polygons, settings);
+                    self.pending = None;
+                }
+                //Failed, don't retry until something changes
+                Err(TryRecvError::Closed) => {
+                    self.revision = Some(*pending_revision);
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => return,
+            }
+        }
+
+        if self.revision == Some(revision) {
+            return;
+        }
+
+        let objects = objects();
+        let settings = settings.clone();
+        let (tx, rx) = oneshot::channel();
+
+        //Slicing the first layer is CPU bound and would block an async worker
+        tokio::task::spawn_blocking(move || {
+            match slicer::first_layer_coverage(&objects, &settings) {
+                Ok(polygons) => {
+                    let _ = tx.send(polygons);
+                }
+                Err(e) => warn!("Failed to compute first layer coverage: {:?}", e),
+            }
+        });
+
+        self.pending = Some((revision, rx));
+    }
+
+    fn awaken(&mut self, polygons: MultiPolygon<f32>, settings: &Settings) {
+        let color = vec4(0.2, 0.6, 1.0, 0.6);
+
+        let triangles: Vec<Vec3> = polygons
+            .iter()
+            .flat_map(|polygon| polygon.earcut_triangles())
+            .flat_map(|triangle| triangle.to_array())
+            .map(|coord| {
+                vec3(
+                    coord.x - settings.print_x / 2.0,
+                    COVERAGE_HEIGHT,
+                    coord.y - settings.print_y / 2.0,
+                )
+            })
+            .collect();
+
+        self.overlay.set_enabled(!triangles.is_empty());
+        self.overlay.awaken(&vec3s_into_vertices(triangles, color));
+
+        self.polygons = polygons;
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.overlay.render(render_pass);
+    }
+}
@@ -0,0 +1,241 @@
+use glam::{vec2, vec3, vec4, Vec2, Vec4};
+
+use crate::render::{model::Model, Renderable, Vertex};
+
+//Just above the build plate so the lines don't z-fight it
+const GRID_HEIGHT: f32 = -0.24;
+
+//Every this many squares a line is drawn darker and labelled with its distance from the origin
+const MAJOR_EVERY: usize = 5;
+
+const TICK_LENGTH: f32 = 3.0;
+pub(super) const LABEL_HEIGHT: f32 = 4.0;
+const LABEL_GAP: f32 = 1.5;
+
+//Segments of a seven segment display in a glyph 1 wide and 2 high, starting at the top
+const SEGMENTS: [(Vec2, Vec2); 7] = [
+    (vec2(0.0, 2.0), vec2(1.0, 2.0)),
+    (vec2(1.0, 2.0), vec2(1.0, 1.0)),
+    (vec2(1.0, 1.0), vec2(1.0, 0.0)),
+    (vec2(0.0, 0.0), vec2(1.0, 0.0)),
+    (vec2(0.0, 0.0), vec2(0.0, 1.0)),
+    (vec2(0.0, 1.0), vec2(0.0, 2.0)),
+    (vec2(0.0, 1.0), vec2(1.0, 1.0)),
+];
+
+//The lit segments of each digit, bit 0 is the top segment
+const DIGITS: [u8; 10] = [0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F];
+
+///A measurement grid over the build plate with labelled ticks along the front and left edges
+#[derive(Debug)]
+pub struct Grid {
+    spacing: Option<f32>,
+    size: Vec2,
+
+    lines: Model<Vertex>,
+}
+
+impl Grid {
+    pub fn instance() -> Self {
+        Self {
+            spacing: None,
+            size: Vec2::ZERO,
+            lines: Model::create(),
+        }
+    }
+
+    pub fn awaken(&mut self, x: f32, y: f32) {
+        self.size = vec2(x.abs(), y.abs());
+        self.rebuild();
+    }
+
+    ///Squares of `spacing` mm, or no grid at all
+    pub fn set_spacing(&mut self, spacing: Option<f32>) {
+        self.spacing = spacing;
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        let vertices = match self.spacing {
+            Some(spacing) => grid_vertices(self.size.x, self.size.y, spacing),
+            None => vec![],
+        };
+
+        self.lines.set_enabled(!vertices.is_empty());
+        self.lines.awaken(&vertices);
+    }
+
+    pub fn render_lines<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.lines.render(render_pass);
+    }
+}
+
+///Line list vertices of the grid on a bed of `x` by `y` mm. Bed coordinates map to the x and z
+///axis of the viewer like the rest of the build plate.
+fn grid_vertices(x: f32, y: f32, spacing: f32) -> Vec<Vertex> {
+    if spacing <= 0.0 || x <= 0.0 || y <= 0.0 {
+        return vec![];
+    }
+
+    let minor_color = vec4(0.55, 0.55, 0.55, 1.0);
+    let major_color = vec4(0.3, 0.3, 0.3, 1.0);
+
+    let mut segments: Vec<(Vec2, Vec2, Vec4)> = vec![];
+
+    for index in 0..=(x / spacing).floor() as usize {
+        let at = index as f32 * spacing;
+        let major = index % MAJOR_EVERY == 0;
+        let color = if major { major_color } else { minor_color };
+
+        segments.push((vec2(at, 0.0), vec2(at, y), color));
+
+        if major {
+            segments.push((vec2(at, 0.0), vec2(at, -TICK_LENGTH), color));
+
+            let text = label(at);
+            let origin = vec2(
+                at - text_width(&text) / 2.0,
+                -TICK_LENGTH - LABEL_GAP - LABEL_HEIGHT,
+            );
+            segments.extend(text_segments(&text, origin, color));
+        }
+    }
+
+    for index in 0..=(y / spacing).floor() as usize {
+        let at = index as f32 * spacing;
+        let major = index % MAJOR_EVERY == 0;
+        let color = if major { major_color } else { minor_color };
+
+        segments.push((vec2(0.0, at), vec2(x, at), color));
+
+        if major {
+            segments.push((vec2(0.0, at), vec2(-TICK_LENGTH, at), color));
+
+            let text = label(at);
+            let origin = vec2(
+                -TICK_LENGTH - LABEL_GAP - text_width(&text),
+                at - LABEL_HEIGHT / 2.0,
+            );
+            segments.extend(text_segments(&text, origin, color));
+        }
+    }
+
+    //Axis names past the end of the labelled edges
+    segments.extend(text_segments(
+        "X",
+        vec2(x + LABEL_GAP, -TICK_LENGTH - LABEL_GAP - LABEL_HEIGHT),
+        major_color,
+    ));
+    segments.extend(text_segments(
+        "Y",
+        vec2(-TICK_LENGTH - LABEL_GAP - text_width("Y"), y + LABEL_GAP),
+        major_color,
+    ));
+
+    line_vertices(segments, GRID_HEIGHT)
+}
+
+///Line list vertices of segments on the bed, `height` above the viewer's origin
+pub(super) fn line_vertices(segments: Vec<(Vec2, Vec2, Vec4)>, height: f32) -> Vec<Vertex> {
+    segments
+        .into_iter()
+        .flat_map(|(start, end, color)| [(start, color), (end, color)])
+        .map(|(point, color)| Vertex {
+            position: vec3(point.x, height, point.y).to_array(),
+            normal: [0.0, 0.0, 0.0],
+            color: color.to_array(),
+        })
+        .collect()
+}
+
+fn label(distance: f32) -> String {
+    format!("{}", (distance * 10.0).round() / 10.0)
+}
+
+fn glyph_advance() -> f32 {
+    LABEL_HEIGHT * 0.75
+}
+
+fn text_width(text: &str) -> f32 {
+    let glyphs = text.chars().count() as f32;
+    glyphs * glyph_advance() - (glyph_advance() - LABEL_HEIGHT / 2.0)
+}
+
+///Segments of `text` drawn with its bottom left corner at `origin`. Only digits, the decimal
+///point and the axis names are drawn
+pub(super) fn text_segments(text: &str, origin: Vec2, color: Vec4) -> Vec<(Vec2, Vec2, Vec4)> {
+    let scale = LABEL_HEIGHT / 2.0;
+
+    text.chars()
+        .enumerate()
+        .flat_map(|(index, c)| {
+            let unit: Vec<(Vec2, Vec2)> = match c {
+                '0'..='9' => {
+                    let lit = DIGITS[c as usize - '0' as usize];
+                    SEGMENTS
+                        .iter()
+                        .enumerate()
+                        .filter(|(segment, _)| lit & (1 << segment) != 0)
+                        .map(|(_, segment)| *segment)
+                        .collect()
+                }
+                '.' => vec![(vec2(0.4, 0.0), vec2(0.6, 0.0))],
+                'X' => vec![
+                    (vec2(0.0, 0.0), vec2(1.0, 2.0)),
+                    (vec2(0.0, 2.0), vec2(1.0, 0.0)),
+                ],
+                'Y' => vec![
+                    (vec2(0.0, 2.0), vec2(0.5, 1.0)),
+                    (vec2(1.0, 2.0), vec2(0.5, 1.0)),
+                    (vec2(0.5, 1.0), vec2(0.5, 0.0)),
+                ],
+                _ => vec![],
+            };
+
+            let offset = origin + vec2(index as f32 * glyph_advance(), 0.0);
+
+            unit.into_iter()
+                .map(move |(start, end)| (offset + start * scale, offset + end * scale, color))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_lines_span_the_bed() {
+        let vertices = grid_vertices(100.0, 50.0, 10.0);
+        assert_eq!(vertices.len() % 2, 0);
+
+        let lines: Vec<(Vec2, Vec2)> = vertices
+            .chunks(2)
+            .map(|pair| {
+                (
+                    vec2(pair[0].position[0], pair[0].position[2]),
+                    vec2(pair[1].position[0], pair[1].position[2]),
+                )
+            })
+            .collect();
+
+        let across = lines
+            .iter()
+            .filter(|(start, end)| start.x == end.x && start.y == 0.0 && end.y == 50.0)
+            .count();
+        let along = lines
+            .iter()
+            .filter(|(start, end)| start.y == end.y && start.x == 0.0 && end.x == 100.0)
+            .count();
+        assert_eq!((across, along), (11, 6));
+
+        //Ticks and labels stay in front of and left of the bed
+        assert!(lines.iter().all(|(start, end)| {
+            [start, end].iter().all(|point| {
+                (point.x <= 100.0 || point.y < 0.0) && (point.y <= 50.0 || point.x < 0.0)
+            })
+        }));
+
+        assert!(grid_vertices(100.0, 50.0, 0.0).is_empty());
+    }
+}
@@ -1,14 +1,23 @@
 use std::fmt::Debug;
 
+use coverage::Coverage;
+use geo::MultiPolygon;
 use glam::{vec3, vec4, Vec3};
+use grid::Grid;
+use origin::OriginMarker;
+use shared::object::ObjectMesh;
+use slicer::Settings;
 use volume::Volume;
 use wgpu::BindGroup;
 
+mod coverage;
+mod grid;
+mod origin;
 mod volume;
 
 use crate::{
     geometry::{mesh::construct_triangle_vertices, BoundingBox},
-    prelude::WgpuContext,
+    prelude::{OriginMode, WgpuContext},
     render::{
         model::{Model, TransformMut},
         PipelineBuilder, Renderable, Texture, TextureVertex, Vertex,
@@ -19,6 +28,9 @@ use crate::{
 #[derive(Debug)]
 pub struct EnvironmentServer {
     volume: Volume,
+    coverage: Coverage,
+    grid: Grid,
+    origin: OriginMarker,
 
     texture_pipeline: wgpu::RenderPipeline,
 
@@ -79,6 +91,9 @@ impl RenderServer for EnvironmentServer {
 
         Self {
             volume: Volume::instance(),
+            coverage: Coverage::instance(),
+            grid: Grid::instance(),
+            origin: OriginMarker::instance(),
 
             texture_pipeline,
 
@@ -107,6 +122,8 @@ impl EnvironmentServer {
 
     pub fn update_printer_dimension(&mut self, x: f32, y: f32, z: f32) {
         self.volume.awaken(x, y, z);
+        self.grid.awaken(x, y);
+        self.origin.awaken(x, y);
 
         self.reflect
             .awaken(&build_plate_reflection(x * 1.1, y * 1.1, z));
@@ -119,8 +136,37 @@ impl EnvironmentServer {
         )));
     }
 
+    ///Shows a grid of `spacing` mm squares over the build plate, or hides it with `None`
+    pub fn set_grid(&mut self, spacing: Option<f32>) {
+        self.grid.set_spacing(spacing);
+    }
+
+    ///Moves the origin marker to where the displayed coordinates start
+    pub fn set_origin_display(&mut self, mode: OriginMode) {
+        self.origin.set_mode(mode);
+    }
+
+    pub fn first_layer_coverage(&self) -> &MultiPolygon<f32> {
+        self.coverage.polygons()
+    }
+
+    pub fn update_coverage(
+        &mut self,
+        revision: u64,
+        objects: impl FnOnce() -> Vec<ObjectMesh>,
+        settings: &Settings,
+    ) {
+        self.coverage.update(revision, objects, settings);
+    }
+
+    pub fn render_coverage<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.coverage.render(render_pass);
+    }
+
     pub fn render_line<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
         self.volume.render_lines(render_pass);
+        self.grid.render_lines(render_pass);
+        self.origin.render_lines(render_pass);
     }
 }
 
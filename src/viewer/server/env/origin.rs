@@ -0,0 +1,86 @@
+use glam::{vec2, vec4, Vec2};
+
+use super::grid::{line_vertices, text_segments, LABEL_HEIGHT};
+use crate::{
+    prelude::OriginMode,
+    render::{model::Model, Renderable, Vertex},
+};
+
+//Above the grid so the marker isn't hidden by it
+const MARKER_HEIGHT: f32 = -0.23;
+
+///Arrows along +X and +Y from the origin of the displayed coordinates
+#[derive(Debug)]
+pub struct OriginMarker {
+    mode: OriginMode,
+    size: Vec2,
+
+    lines: Model<Vertex>,
+}
+
+impl OriginMarker {
+    pub fn instance() -> Self {
+        Self {
+            mode: OriginMode::default(),
+            size: Vec2::ZERO,
+            lines: Model::create(),
+        }
+    }
+
+    pub fn awaken(&mut self, x: f32, y: f32) {
+        self.size = vec2(x.abs(), y.abs());
+        self.rebuild();
+    }
+
+    pub fn set_mode(&mut self, mode: OriginMode) {
+        self.mode = mode;
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        let origin = self.mode.origin(self.size.x, self.size.y);
+        let vertices = marker_vertices(origin, self.size.min_element() * 0.1);
+
+        self.lines.set_enabled(!vertices.is_empty());
+        self.lines.awaken(&vertices);
+    }
+
+    pub fn render_lines<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.lines.render(render_pass);
+    }
+}
+
+fn marker_vertices(origin: Vec2, length: f32) -> Vec<Vertex> {
+    if length <= 0.0 {
+        return vec![];
+    }
+
+    let x_color = vec4(0.9, 0.2, 0.2, 1.0);
+    let y_color = vec4(0.2, 0.75, 0.2, 1.0);
+
+    let head = length * 0.2;
+    let x_tip = origin + vec2(length, 0.0);
+    let y_tip = origin + vec2(0.0, length);
+
+    let mut segments = vec![
+        (origin, x_tip, x_color),
+        (x_tip, x_tip + vec2(-head, head / 2.0), x_color),
+        (x_tip, x_tip + vec2(-head, -head / 2.0), x_color),
+        (origin, y_tip, y_color),
+        (y_tip, y_tip + vec2(head / 2.0, -head), y_color),
+        (y_tip, y_tip + vec2(-head / 2.0, -head), y_color),
+    ];
+
+    segments.extend(text_segments(
+        "X",
+        x_tip + vec2(head / 2.0, -LABEL_HEIGHT / 2.0),
+        x_color,
+    ));
+    segments.extend(text_segments(
+        "Y",
+        y_tip + vec2(-LABEL_HEIGHT / 4.0, head / 2.0),
+        y_color,
+    ));
+
+    line_vertices(segments, MARKER_HEIGHT)
+}
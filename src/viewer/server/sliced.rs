@@ -5,16 +5,16 @@ use std::sync::Arc;
 use native_dialog::FileDialog;
 use shared::process::Process;
 use slicer::gcode::mem::GCodeMemoryWriter;
-use slicer::gcode::GCodeFileWriter;
+use slicer::gcode::{binary::GCodeBinaryWriter, GCodeFileWriter, GCodeFormat};
 use slicer::{gcode::write_gcode, SliceResult, SlicedGCode};
-use tokio::sync::oneshot::Receiver;
+use tokio::sync::oneshot::{error::TryRecvError, Receiver};
 use tokio::task::JoinHandle;
 use wgpu::util::DeviceExt;
 
 use crate::input::hitbox::HitboxRoot;
 use crate::render::{ColorBinding, PipelineBuilder, Renderable};
 use crate::viewer::trace::vertex::{TraceContext, TraceVertex};
-use crate::viewer::trace::SlicedObject;
+use crate::viewer::trace::{ColorMode, FiberHighlight, SlicedObject};
 use crate::viewer::RenderServer;
 use crate::QUEUE;
 use crate::{prelude::WgpuContext, GlobalState, RootEvent};
@@ -26,9 +26,12 @@ pub type QueuedSlicedObject = (
     JoinHandle<()>,
 );
 
+pub type QueuedRebuild = (Receiver<Result<SlicedObject, ()>>, JoinHandle<()>);
+
 #[derive(Debug)]
 pub struct SlicedObjectServer {
     queued: Option<QueuedSlicedObject>,
+    rebuilding: Option<QueuedRebuild>,
 
     pipeline: wgpu::RenderPipeline,
 
@@ -39,6 +42,8 @@ pub struct SlicedObjectServer {
 
     travel_visible: bool,
     fiber_visible: bool,
+    fiber_highlight: FiberHighlight,
+    color_mode: ColorMode,
 
     toolpath_context_buffer: wgpu::Buffer,
     toolpath_context: TraceContext,
@@ -120,6 +125,7 @@ impl RenderServer for SlicedObjectServer {
 
         Self {
             queued: None,
+            rebuilding: None,
             sliced_object: None,
             sliced_gcode: None,
 
@@ -128,6 +134,8 @@ impl RenderServer for SlicedObjectServer {
 
             travel_visible: false,
             fiber_visible: true,
+            fiber_highlight: FiberHighlight::default(),
+            color_mode: ColorMode::default(),
 
             toolpath_context,
             toolpath_context_bind_group,
@@ -168,14 +176,21 @@ impl SlicedObjectServer {
 
     pub fn load_from_slice_result(&mut self, slice_result: SliceResult, process: Arc<Process>) {
         let (tx, rx) = tokio::sync::oneshot::channel();
+        let fiber_highlight = self.fiber_highlight;
+        let color_mode = self.color_mode;
 
         let handle = tokio::spawn(async move {
             process.set_task("Loading toolpath".to_string());
             process.set_progress(0.8);
 
-            let obj =
-                SlicedObject::from_commands(&slice_result.moves, &slice_result.settings, &process)
-                    .expect("Failed to load toolpath");
+            let obj = SlicedObject::from_commands(
+                &slice_result.moves,
+                &slice_result.settings,
+                &fiber_highlight,
+                color_mode,
+                &process,
+            )
+            .expect("Failed to load toolpath");
 
             process.set_task("Build GCode".to_string());
             process.set_progress(0.9);
@@ -194,13 +209,13 @@ impl SlicedObjectServer {
         self.queued = Some((rx, handle));
     }
 
-    pub fn export(&self) {
+    pub fn export(&self, format: GCodeFormat) {
         if let Some(toolpath) = self.sliced_object.as_ref() {
             let path = FileDialog::new()
                 .set_location("~")
-                .set_filename("model.gcode")
+                .set_filename(&format!("model.{}", format.extension()))
                 .set_title("Export GCode")
-                .add_filter("GCode", &["gcode"])
+                .add_filter("GCode", &[format.extension()])
                 .show_save_single_file()
                 .unwrap();
 
@@ -214,9 +229,21 @@ impl SlicedObjectServer {
                 };
 
                 let mut writer = BufWriter::new(file);
-                let mut writer = GCodeFileWriter::new(&mut writer);
 
-                match write_gcode(&toolpath.moves, &toolpath.settings, &mut writer) {
+                let result = match format {
+                    GCodeFormat::Text => write_gcode(
+                        &toolpath.moves,
+                        &toolpath.settings,
+                        &mut GCodeFileWriter::new(&mut writer),
+                    ),
+                    GCodeFormat::Binary => GCodeBinaryWriter::new(&mut writer, &toolpath.settings)
+                        .map_err(Into::into)
+                        .and_then(|mut writer| {
+                            write_gcode(&toolpath.moves, &toolpath.settings, &mut writer)
+                        }),
+                };
+
+                match result {
                     Ok(_) => {
                         println!("Gcode saved");
                     }
@@ -242,6 +269,35 @@ impl SlicedObjectServer {
 
                 self.sliced_object = Some(toolpath);
                 self.sliced_gcode = Some(gcode);
+
+                //A rebuild started while loading would replace the new toolpath with the old one
+                if let Some((_, handle)) = self.rebuilding.take() {
+                    handle.abort();
+                    self.rebuild_sliced();
+                }
+            }
+        }
+
+        if let Some((rx, _)) = &mut self.rebuilding {
+            match rx.try_recv() {
+                Ok(Ok(toolpath)) => {
+                    self.hitbox.clear();
+                    self.hitbox.add_node(toolpath.model.clone());
+
+                    self.sliced_object = Some(toolpath);
+                    self.rebuilding = None;
+                }
+                Ok(Err(())) => {
+                    global_state
+                        .ui_event_writer
+                        .send(crate::ui::UiEvent::ShowError(
+                            "Failed to rebuild the toolpath".to_string(),
+                        ));
+
+                    self.rebuilding = None;
+                }
+                Err(TryRecvError::Closed) => self.rebuilding = None,
+                Err(TryRecvError::Empty) => {}
             }
         }
 
@@ -260,6 +316,59 @@ impl SlicedObjectServer {
         self.fiber_visible = visible;
     }
 
+    pub fn set_fiber_highlight(&mut self, fiber_highlight: FiberHighlight) {
+        if self.fiber_highlight == fiber_highlight {
+            return;
+        }
+
+        self.fiber_highlight = fiber_highlight;
+
+        //The fiber color and size are baked into the mesh, so a loaded toolpath is rebuilt
+        self.rebuild_sliced();
+    }
+
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        if self.color_mode == color_mode {
+            return;
+        }
+
+        self.color_mode = color_mode;
+
+        //The trace colors are baked into the mesh as well
+        self.rebuild_sliced();
+    }
+
+    ///Rebuilds the mesh of the loaded toolpath in the background, the current one is shown
+    ///until it's done. A newer rebuild replaces one that is still running.
+    fn rebuild_sliced(&mut self) {
+        let Some(toolpath) = self.sliced_object.as_ref() else {
+            return;
+        };
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let moves = toolpath.moves.clone();
+        let settings = toolpath.settings.clone();
+        let fiber_highlight = self.fiber_highlight;
+        let color_mode = self.color_mode;
+
+        let handle = tokio::spawn(async move {
+            let toolpath = SlicedObject::from_commands(
+                &moves,
+                &settings,
+                &fiber_highlight,
+                color_mode,
+                &Process::new(),
+            );
+
+            //The receiver is gone once a newer rebuild or slice replaced this one
+            let _ = tx.send(toolpath);
+        });
+
+        if let Some((_, handle)) = self.rebuilding.replace((rx, handle)) {
+            handle.abort();
+        }
+    }
+
     pub fn update_visibility(&mut self, value: u32) {
         self.toolpath_context.visibility = value;
 
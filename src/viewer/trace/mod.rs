@@ -4,11 +4,11 @@ use egui::ahash::{HashMap, HashMapExt};
 use glam::{Vec3, Vec4};
 use mesh::{TraceMesher, TRACE_MESH_VERTICES};
 use shared::process::Process;
-use slicer::{Command, TraceType};
+use slicer::{Command, MoveId, MoveType, TraceType};
 use tree::TraceTree;
 use wgpu::BufferAddress;
 
-use crate::render::Vertex;
+use crate::{geometry::BoundingBox, render::Vertex};
 
 pub mod mesh;
 pub mod tree;
@@ -41,11 +41,116 @@ pub const TRAVEL_COLOR: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
 
 pub const FIBER_COLOR: Vec4 = Vec4::new(0.0, 0.0, 0.0, 1.0);
 
+///How fiber traces are drawn in the preview, only affects the viewer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FiberHighlight {
+    pub color: Vec4,
+    ///Factor applied to the width and thickness of fiber traces
+    pub scale: f32,
+}
+
+impl Default for FiberHighlight {
+    fn default() -> Self {
+        Self {
+            color: FIBER_COLOR,
+            scale: 1.0,
+        }
+    }
+}
+
+impl FiberHighlight {
+    pub fn mesher(&self) -> TraceMesher {
+        let mut mesher = TraceMesher::new();
+        mesher.set_color(self.color);
+        mesher
+    }
+}
+
+///What the color of the traces shows in the preview. Fiber traces keep their highlight color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    TraceType,
+    ///A gradient from blue for the slowest to red for the fastest extrusion
+    Speed,
+}
+
+///Slowest and fastest speed in mm/s the traces of a toolpath are extruded at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl SpeedRange {
+    ///The range of the movement speeds set when a trace is extruded, travel speeds are left out
+    pub fn of_commands(commands: &[Command]) -> Option<Self> {
+        let mut speed = None;
+        let mut range: Option<Self> = None;
+
+        for command in commands {
+            match command {
+                Command::SetState { new_state } => {
+                    speed = new_state.movement_speed.or(speed);
+                }
+                Command::MoveAndExtrude { .. } => {
+                    if let Some(speed) = speed {
+                        range = Some(match range {
+                            Some(range) => Self {
+                                min: range.min.min(speed),
+                                max: range.max.max(speed),
+                            },
+                            None => Self {
+                                min: speed,
+                                max: speed,
+                            },
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        range
+    }
+
+    ///Position of the speed in the range, 0 for the slowest and 1 for the fastest
+    pub fn fraction(&self, speed: f32) -> f32 {
+        if self.max - self.min <= f32::EPSILON {
+            return 0.5;
+        }
+
+        ((speed - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+    }
+
+    pub fn color(&self, speed: f32) -> Vec4 {
+        speed_gradient(self.fraction(speed))
+    }
+}
+
+///Color of the speed gradient, blue at 0 through green to red at 1
+pub fn speed_gradient(fraction: f32) -> Vec4 {
+    //The hue runs from 240 to 0 degrees at full saturation and brightness
+    let hue = (1.0 - fraction.clamp(0.0, 1.0)) * 4.0;
+    let rising = hue.fract();
+
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, rising, 0.0),
+        1 => (1.0 - rising, 1.0, 0.0),
+        2 => (0.0, 1.0, rising),
+        3 => (0.0, 1.0 - rising, 1.0),
+        _ => (0.0, 0.0, 1.0),
+    };
+
+    Vec4::new(r, g, b, 1.0)
+}
+
 #[derive(Debug)]
 pub struct SlicedObject {
     pub model: Arc<TraceTree>,
     pub count_map: HashMap<TraceType, usize>,
     pub max_layer: usize,
+    pub speed_range: Option<SpeedRange>,
     pub moves: Vec<Command>,
     pub settings: slicer::Settings,
 }
@@ -57,10 +162,14 @@ impl SlicedObject {
     pub fn from_commands(
         commands: &[slicer::Command],
         settings: &slicer::Settings,
+        fiber_highlight: &FiberHighlight,
+        color_mode: ColorMode,
         _process: &Process,
     ) -> Result<Self, ()> {
         // let mut current_state = StateChange::default();
         let mut current_type = None;
+        let mut current_speed = None;
+        let speed_range = SpeedRange::of_commands(commands);
         let mut current_layer = 0;
         let mut current_height_z = 0.0;
 
@@ -72,8 +181,7 @@ impl SlicedObject {
 
         let mut mesher = TraceMesher::new();
 
-        let mut fiber_mesher = TraceMesher::new();
-        fiber_mesher.set_color(FIBER_COLOR);
+        let mut fiber_mesher = fiber_highlight.mesher();
 
         // let mut fiber_wire_mesher = LineMesher::new();
         // fiber_wire_mesher.set_color(FIBER_COLOR);
@@ -85,7 +193,10 @@ impl SlicedObject {
                 mesher.set_type(ty);
             }
             mesher.set_current_layer(current_layer);
-            mesher.set_color(current_type.unwrap_or(TraceType::Infill).into_color_vec4());
+            mesher.set_color(match (color_mode, speed_range.zip(current_speed)) {
+                (ColorMode::Speed, Some((range, speed))) => range.color(speed),
+                _ => current_type.unwrap_or(TraceType::Infill).into_color_vec4(),
+            });
 
             if let Some(ty) = current_type {
                 fiber_mesher.set_type(ty);
@@ -148,6 +259,7 @@ impl SlicedObject {
                         hitbox,
                         id.expect("Id's not evaluted yet!"),
                         current_type.unwrap_or(TraceType::Infill),
+                        current_layer as u32,
                         offset as u64,
                         TRACE_MESH_VERTICES as BufferAddress,
                     );
@@ -161,6 +273,48 @@ impl SlicedObject {
 
                     last_position = end;
                 }
+                slicer::Command::Arc {
+                    id,
+                    start,
+                    end,
+                    center,
+                    clockwise,
+                    thickness,
+                    width,
+                } => {
+                    if let Some(ty) = current_type {
+                        count_map.entry(ty).and_modify(|e| *e += 1).or_insert(1);
+                    }
+
+                    //The arc is previewed as the segments it is printed along
+                    for (start, end) in slicer::split_arc(*start, *end, *center, *clockwise) {
+                        let start = Vec3::new(
+                            start.x - settings.print_x / 2.0,
+                            current_height_z - thickness / 2.0,
+                            start.y - settings.print_y / 2.0,
+                        );
+                        let end = Vec3::new(
+                            end.x - settings.print_x / 2.0,
+                            current_height_z - thickness / 2.0,
+                            end.y - settings.print_y / 2.0,
+                        );
+
+                        let (offset, hitbox) = mesher.next(start, end, *thickness, *width, true);
+
+                        let tree_move = TraceTree::create_move(
+                            hitbox,
+                            id.expect("Id's not evaluted yet!"),
+                            current_type.unwrap_or(TraceType::Infill),
+                            current_layer as u32,
+                            offset as u64,
+                            TRACE_MESH_VERTICES as BufferAddress,
+                        );
+
+                        root.push(tree_move);
+
+                        last_position = end;
+                    }
+                }
                 slicer::Command::MoveAndExtrudeFiberAndCut {
                     id,
                     start,
@@ -194,12 +348,19 @@ impl SlicedObject {
                         count_map.entry(ty).and_modify(|e| *e += 1).or_insert(1);
                     }
 
-                    let (offset, hitbox) = fiber_mesher.next(start, end, *thickness, *width, false);
+                    let (offset, hitbox) = fiber_mesher.next(
+                        start,
+                        end,
+                        *thickness * fiber_highlight.scale,
+                        *width * fiber_highlight.scale,
+                        false,
+                    );
 
                     let tree_move = TraceTree::create_fiber(
                         hitbox,
                         id.expect("Id's not evaluated yet!"),
                         current_type.unwrap_or(TraceType::Infill),
+                        current_layer as u32,
                         offset as u64,
                         TRACE_MESH_VERTICES as BufferAddress,
                     );
@@ -220,7 +381,9 @@ impl SlicedObject {
                     current_layer = *index;
                     current_height_z = *z;
                 }
-                slicer::Command::SetState { .. } => {}
+                slicer::Command::SetState { new_state } => {
+                    current_speed = new_state.movement_speed.or(current_speed);
+                }
                 slicer::Command::ChangeType { print_type } => current_type = Some(*print_type),
                 _ => {}
             }
@@ -242,14 +405,112 @@ impl SlicedObject {
             model: Arc::new(root),
             count_map,
             max_layer: current_layer,
+            speed_range,
             moves: commands.to_vec(),
             settings: settings.clone(),
         })
     }
 
+    /// Returns the ids of all traces inside the given region which pass the filter.
+    pub fn query_region(
+        &self,
+        region: &BoundingBox,
+        filter: impl Fn(&MoveType, u32) -> bool,
+    ) -> Vec<MoveId> {
+        let mut ids = Vec::new();
+        self.model.query_region(region, &filter, &mut ids);
+
+        ids
+    }
+
     #[allow(dead_code)]
     #[allow(unused_variables)]
     pub fn from_file(path: &str, settings: &slicer::Settings) -> Result<Self, ()> {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fiber_vertices(highlight: &FiberHighlight) -> Vec<vertex::TraceVertex> {
+        let mut mesher = highlight.mesher();
+        mesher.set_type(TraceType::Infill);
+        mesher.next(Vec3::ZERO, Vec3::new(10.0, 0.0, 0.0), 0.2, 0.4, false);
+        mesher.finish_chain();
+        mesher.finish()
+    }
+
+    #[test]
+    fn fiber_highlight_color() {
+        let highlight = FiberHighlight {
+            color: Vec4::new(1.0, 0.5, 0.0, 1.0),
+            scale: 2.0,
+        };
+
+        let vertices = fiber_vertices(&highlight);
+        assert!(!vertices.is_empty());
+        assert!(vertices
+            .iter()
+            .all(|v| v.color == highlight.color.to_array()));
+
+        let default = fiber_vertices(&FiberHighlight::default());
+        assert!(default.iter().all(|v| v.color == FIBER_COLOR.to_array()));
+    }
+
+    #[test]
+    fn speed_range_of_extrusions() {
+        let speed = |speed: f32| Command::SetState {
+            new_state: slicer::StateChange {
+                movement_speed: Some(speed),
+                ..Default::default()
+            },
+        };
+        let extrude = Command::MoveAndExtrude {
+            id: None,
+            start: geo::Coord { x: 0.0, y: 0.0 },
+            end: geo::Coord { x: 10.0, y: 0.0 },
+            thickness: 0.2,
+            width: 0.4,
+
+            #[cfg(debug_assertions)]
+            debug: String::new(),
+        };
+
+        let commands = vec![
+            speed(150.0),
+            Command::MoveTo {
+                end: geo::Coord { x: 0.0, y: 0.0 },
+            },
+            speed(20.0),
+            extrude.clone(),
+            speed(60.0),
+            extrude.clone(),
+            //The fan doesn't change the speed of the following trace
+            Command::SetState {
+                new_state: slicer::StateChange {
+                    fan_speed: Some(100.0),
+                    ..Default::default()
+                },
+            },
+            extrude,
+        ];
+
+        let range = SpeedRange::of_commands(&commands).unwrap();
+        assert_eq!(
+            range,
+            SpeedRange {
+                min: 20.0,
+                max: 60.0
+            }
+        );
+
+        assert_eq!(range.color(20.0), Vec4::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(range.color(60.0), Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(range.color(40.0), Vec4::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(range.color(200.0), range.color(60.0));
+
+        assert!(SpeedRange::of_commands(&commands[..3]).is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use glam::Vec3;
 use parking_lot::RwLock;
@@ -38,6 +38,7 @@ pub enum TraceTree {
     Trace {
         id: MoveId,
         move_type: MoveType,
+        layer: u32,
         offset: BufferAddress,
         size: BufferAddress,
         r#box: RwLock<Box<TraceHitbox>>,
@@ -71,6 +72,7 @@ impl TraceTree {
         path_box: TraceHitbox,
         id: MoveId,
         trace_type: TraceType,
+        layer: u32,
         offset: BufferAddress,
         size: BufferAddress,
     ) -> Self {
@@ -78,6 +80,7 @@ impl TraceTree {
             offset,
             id,
             move_type: MoveType::WithoutFiber(trace_type),
+            layer,
             size,
             r#box: RwLock::new(Box::new(path_box)),
         }
@@ -87,6 +90,7 @@ impl TraceTree {
         path_box: TraceHitbox,
         id: MoveId,
         trace_type: TraceType,
+        layer: u32,
         offset: BufferAddress,
         size: BufferAddress,
     ) -> Self {
@@ -94,6 +98,7 @@ impl TraceTree {
             offset,
             id,
             move_type: MoveType::WithFiber(trace_type),
+            layer,
             size,
             r#box: RwLock::new(Box::new(path_box)),
         }
@@ -154,6 +159,81 @@ impl TraceTree {
         }
     }
 
+    /// Collects the ids of all traces whose bounds lie within `region` and which pass `filter`.
+    /// The filter receives the move type and layer of the trace, so the layer window and
+    /// the type visibility can be applied. Subtrees not intersecting the region are skipped.
+    pub fn query_region(
+        &self,
+        region: &BoundingBox,
+        filter: &impl Fn(&MoveType, u32) -> bool,
+        ids: &mut Vec<MoveId>,
+    ) {
+        match self {
+            Self::Root {
+                bounding_box,
+                children,
+                ..
+            } => Self::query_children(&bounding_box.read(), children, region, filter, ids),
+            Self::Trace {
+                id,
+                move_type,
+                layer,
+                ..
+            } => {
+                let contained = region.contains(self.get_min()) && region.contains(self.get_max());
+                if contained && filter(move_type, *layer) {
+                    ids.push(*id);
+                }
+            }
+            Self::Travel { .. } => {}
+        }
+    }
+
+    /// Queries the children like `query_region`, all of them are skipped at once if their
+    /// `bounds` don't intersect the region
+    fn query_children(
+        bounds: &BoundingBox,
+        children: &[Arc<Self>],
+        region: &BoundingBox,
+        filter: &impl Fn(&MoveType, u32) -> bool,
+        ids: &mut Vec<MoveId>,
+    ) {
+        if !region.intersects(bounds.get_min(), bounds.get_max()) {
+            return;
+        }
+
+        for child in children {
+            child.query_region(region, filter, ids);
+        }
+    }
+
+    /// The traces with any of the given ids among the children of the root
+    pub fn find_traces(&self, ids: &[MoveId]) -> Vec<Arc<Self>> {
+        let ids: HashSet<&MoveId> = ids.iter().collect();
+
+        match self {
+            Self::Root { children, .. } => children
+                .iter()
+                .filter(|child| matches!(&***child, Self::Trace { id, .. } if ids.contains(id)))
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The trace with the given id among the children of the root
+    pub fn find_trace(&self, id: &MoveId) -> Option<Arc<Self>> {
+        match self {
+            Self::Root { children, .. } => children
+                .iter()
+                .find(
+                    |child| matches!(***child, Self::Trace { id: trace_id, .. } if trace_id == *id),
+                )
+                .cloned(),
+            _ => None,
+        }
+    }
+
     pub fn awaken(&mut self, data: &[TraceVertex], travel: &[Vertex], fiber: &[TraceVertex]) {
         match self {
             Self::Root {
@@ -355,3 +435,130 @@ impl InteractiveModel for TraceTree {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+    use crate::viewer::trace::mesh::TraceMesher;
+
+    fn cube_traces() -> Vec<TraceTree> {
+        let mut mesher = TraceMesher::new();
+        let mut traces = Vec::new();
+
+        for layer in 0..10 {
+            for line in 0..10 {
+                let start = vec3(0.0, layer as f32 + 0.5, line as f32 + 0.5);
+                let end = vec3(10.0, layer as f32 + 0.5, line as f32 + 0.5);
+
+                let (offset, hitbox) = mesher.next(start, end, 0.4, 0.2, false);
+
+                traces.push(TraceTree::create_move(
+                    hitbox,
+                    MoveId::new(traces.len()),
+                    TraceType::Infill,
+                    layer,
+                    offset as BufferAddress,
+                    0,
+                ));
+            }
+        }
+
+        traces
+    }
+
+    #[test]
+    fn query_sub_region_of_cube() {
+        let traces = cube_traces();
+        let region = BoundingBox::new(vec3(-1.0, 2.0, 2.0), vec3(11.0, 4.0, 4.0));
+
+        let mut ids = Vec::new();
+        for trace in traces.iter() {
+            trace.query_region(&region, &|_, _| true, &mut ids);
+        }
+
+        // layers 2..=3 and lines 2..=3
+        assert_eq!(ids.len(), 4);
+
+        let mut ids = Vec::new();
+        for trace in traces.iter() {
+            trace.query_region(&region, &|_, layer| layer <= 2, &mut ids);
+        }
+
+        assert_eq!(ids.len(), 2);
+    }
+
+    #[test]
+    fn query_skips_traces_outside_the_region() {
+        let traces = cube_traces();
+        let region = BoundingBox::new(vec3(-1.0, 2.0, 2.0), vec3(11.0, 4.0, 4.0));
+
+        //The filter is only asked about the traces the region reaches
+        let checked = std::cell::Cell::new(0);
+        let mut ids = Vec::new();
+        for trace in traces.iter() {
+            trace.query_region(
+                &region,
+                &|_, _| {
+                    checked.set(checked.get() + 1);
+                    true
+                },
+                &mut ids,
+            );
+        }
+        assert_eq!(checked.get(), ids.len());
+
+        //Nothing is visited beside the cube
+        let beside = BoundingBox::new(vec3(20.0, 0.0, 0.0), vec3(30.0, 10.0, 10.0));
+        for trace in traces.iter() {
+            let outside = |_: &MoveType, _: u32| -> bool { panic!("Trace outside the region") };
+            trace.query_region(&beside, &outside, &mut ids);
+        }
+
+        //Layers 2..=3 and lines 2..=3
+        assert_eq!(ids, [22, 23, 32, 33].map(MoveId::new));
+    }
+
+    #[test]
+    fn query_prunes_subtrees_and_skips_partial_traces() {
+        //The lower and upper half of the cube as the subtrees of a root
+        let mut traces = cube_traces();
+        let upper: Vec<Arc<TraceTree>> = traces.split_off(50).into_iter().map(Arc::new).collect();
+        let lower: Vec<Arc<TraceTree>> = traces.into_iter().map(Arc::new).collect();
+        let bounds = |children: &[Arc<TraceTree>]| {
+            let mut bounds = BoundingBox::default();
+            for child in children {
+                bounds.expand_point(child.get_min());
+                bounds.expand_point(child.get_max());
+            }
+            bounds
+        };
+
+        let query = |region: &BoundingBox| {
+            let mut ids = Vec::new();
+            for children in [&lower, &upper] {
+                TraceTree::query_children(
+                    &bounds(children),
+                    children,
+                    region,
+                    &|_, layer| {
+                        //Only the subtree the region reaches is visited
+                        assert!(layer >= 5);
+                        true
+                    },
+                    &mut ids,
+                );
+            }
+            ids
+        };
+
+        //Layers 6..=7 and lines 2..=3
+        let region = BoundingBox::new(vec3(-1.0, 6.0, 2.0), vec3(11.0, 8.0, 4.0));
+        assert_eq!(query(&region), [62, 63, 72, 73].map(MoveId::new));
+
+        //Traces that only reach into the region are left out
+        let half = BoundingBox::new(vec3(5.0, 6.0, 2.0), vec3(11.0, 8.0, 4.0));
+        assert!(query(&half).is_empty());
+    }
+}